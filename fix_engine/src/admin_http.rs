@@ -0,0 +1,528 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use log::{error, info};
+use serde_json::{json, Value};
+
+use crate::buffer_pool::BufferPool;
+use crate::execution_store::EXECUTION_STORE;
+use crate::fixml::fix_to_fixml;
+use crate::http_server::{self, HttpRequest, HttpResponse};
+use crate::kill_switch::{engage_kill_switch, KillSwitchActions, KILL_SWITCH};
+use crate::message_handling::initiate_logout;
+use crate::orderstore::{OrdStatus, OrderQuery, OrderStore};
+use crate::risk::{ACCOUNT_RISK, ACCOUNT_THROTTLE};
+use crate::sequence::{SequenceNumberStore, SessionId};
+use crate::session::SESSION_STATES;
+use crate::trading_session::{
+    publish_trading_session_status, TradSesStatus, TRADING_SESSION, TRADING_SESSION_ID,
+};
+use crate::transport::Transport;
+use crate::watchdog::SessionActivity;
+use crate::{MessageMap, IS_INITIATOR, RECONNECT_REQUESTED};
+
+lazy_static! {
+    /// Sessions currently running, as seen by the admin API.
+    pub static ref ADMIN_SESSIONS: AdminSessions = AdminSessions::default();
+}
+
+/// What the admin API needs to report on and control one running session.
+pub struct AdminSession {
+    pub session_id: SessionId,
+    pub seq_store: Arc<SequenceNumberStore>,
+    pub stream: Arc<Mutex<Box<dyn Transport>>>,
+    pub activity: Arc<SessionActivity>,
+    pub message_maps: Arc<MessageMap>,
+    pub order_store: Arc<OrderStore>,
+    pub buffers: Arc<BufferPool>,
+}
+
+impl AdminSession {
+    /// Logon exchanged and no Logout sent since.
+    pub fn is_logged_on(&self) -> bool {
+        SESSION_STATES.for_session(&self.session_id).is_logged_on()
+    }
+
+    fn status(&self) -> Value {
+        json!({
+            "session": self.session_id.file_stem(),
+            "session_id": self.session_id.to_string(),
+            "logged_on": self.is_logged_on(),
+            "next_incoming_seq_num": self.seq_store.get_incoming(),
+            "next_outgoing_seq_num": self.seq_store.get_outgoing(),
+            "last_sent_time": SESSION_STATES
+                .for_session(&self.session_id)
+                .last_sent_time()
+                .to_rfc3339(),
+            "last_received_time": self.activity.last_received_time().to_rfc3339(),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct AdminSessions {
+    sessions: Mutex<Vec<(u64, Arc<AdminSession>)>>,
+    next_token: AtomicU64,
+}
+
+/// Keeps a session listed in the admin API until dropped.
+pub struct AdminRegistration {
+    token: u64,
+}
+
+impl Drop for AdminRegistration {
+    fn drop(&mut self) {
+        ADMIN_SESSIONS
+            .sessions
+            .lock()
+            .unwrap()
+            .retain(|(token, _)| *token != self.token);
+    }
+}
+
+impl AdminSessions {
+    pub fn register(&self, session: AdminSession) -> AdminRegistration {
+        let token = self.next_token.fetch_add(1, Ordering::SeqCst);
+        self.sessions
+            .lock()
+            .unwrap()
+            .push((token, Arc::new(session)));
+        AdminRegistration { token }
+    }
+
+    fn find(&self, key: &str) -> Option<Arc<AdminSession>> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, session)| session.session_id.file_stem() == key)
+            .map(|(_, session)| Arc::clone(session))
+    }
+
+    /// The running session with the id, if any.
+    pub fn get(&self, session_id: &SessionId) -> Option<Arc<AdminSession>> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, session)| session.session_id == *session_id)
+            .map(|(_, session)| Arc::clone(session))
+    }
+
+    pub fn all(&self) -> Vec<Arc<AdminSession>> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .iter()
+            .map(|(_, session)| Arc::clone(session))
+            .collect()
+    }
+}
+
+/// Serves the admin API on its own thread. Sessions are addressed by
+/// `<BeginString>_<SenderCompID>_<TargetCompID>`:
+///
+/// - `GET /sessions` and `GET /sessions/<session>`: logon state, next sequence numbers and
+///   last send/receive times
+/// - `POST /sessions/<session>/reset`: resets both sequence numbers to 1
+/// - `POST /sessions/<session>/logout`: sends a Logout and closes the connection once confirmed
+/// - `POST /sessions/<session>/reconnect`: drops the connection; an initiator connects again,
+///   an acceptor waits for the counterparty to
+/// - `GET /sessions/<session>/orders`: the orders of the session, optionally only those of
+///   `?symbol=<symbol>`, the open ones of `?account=<account>` or those in `?status=<OrdStatus>`
+/// - `GET /executions`: the stored ExecutionReports, or those stored after `?after=<ExecID>`
+///   to replay them downstream; `GET /executions/<ExecID>`: one of them;
+///   `GET /executions/<ExecID>/fixml`: the report as a FIXML document
+/// - `GET /positions`: the net positions by account and symbol, or only those of
+///   `?account=<account>`
+/// - `GET /kill_switch`: whether new orders are refused; `POST /kill_switch`: refuses them,
+///   also canceling the open orders with `?cancel=Y` and logging out with `?logout=Y`;
+///   `POST /kill_switch/release`: accepts them again
+/// - `GET /accounts/<account>/breach`: whether the account is blocked by a risk breach, whose
+///   open orders were canceled; `POST /accounts/<account>/breach/clear`: unblocks it
+/// - `GET /trading_session`: the status of the trading session;
+///   `POST /trading_session/<TradSesStatus>`: acceptor only, publishes it to every session,
+///   e.g. `POST /trading_session/halted`
+/// - `GET /metrics`: engine-wide counters, the orders accepted and throttled per account and,
+///   for each session, the seconds since it last received a message and its buffer pool
+pub fn start_admin_server(address: SocketAddr) -> io::Result<()> {
+    http_server::serve("Admin HTTP API", address, handle_request).map(|_| ())
+}
+
+fn handle_request(request: &HttpRequest) -> HttpResponse {
+    match fixml_route(&request.method, &request.path) {
+        Some((status, body)) => HttpResponse {
+            status,
+            content_type: "application/xml",
+            body,
+        },
+        None => {
+            let (status, body) = route(&request.method, &request.path);
+            HttpResponse::json(status, body)
+        }
+    }
+}
+
+/// The resources served as FIXML rather than JSON; None for the others.
+fn fixml_route(method: &str, path: &str) -> Option<(&'static str, String)> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let ("GET", ["executions", exec_id, "fixml"]) = (method, segments.as_slice()) else {
+        return None;
+    };
+    let record = EXECUTION_STORE
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|store| store.get(exec_id));
+    Some(match record.map(|record| fix_to_fixml(&record.message)) {
+        Some(Ok(document)) => ("200 OK", document),
+        Some(Err(e)) => ("500 Internal Server Error", format!("<!-- {:?} -->", e)),
+        None => ("404 Not Found", "<!-- Unknown ExecID -->".to_string()),
+    })
+}
+
+fn route(method: &str, path: &str) -> (&'static str, Value) {
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (method, segments.as_slice()) {
+        ("GET", ["sessions"]) => {
+            let sessions: Vec<Value> = ADMIN_SESSIONS
+                .all()
+                .iter()
+                .map(|session| session.status())
+                .collect();
+            ("200 OK", Value::Array(sessions))
+        }
+        ("GET", ["executions", rest @ ..]) => {
+            let store = EXECUTION_STORE.read().unwrap();
+            let Some(store) = store.as_ref() else {
+                return ("404 Not Found", json!({ "error": "No execution_store configured" }));
+            };
+            match rest {
+                [] => {
+                    let after = query.strip_prefix("after=");
+                    ("200 OK", json!(store.replay(after)))
+                }
+                [exec_id] => match store.get(exec_id) {
+                    Some(execution) => ("200 OK", json!(execution)),
+                    None => ("404 Not Found", json!({ "error": "Unknown ExecID" })),
+                },
+                _ => ("404 Not Found", json!({ "error": "Not found" })),
+            }
+        }
+        ("GET", ["positions"]) => match EXECUTION_STORE.read().unwrap().as_ref() {
+            Some(store) => {
+                let account = query.strip_prefix("account=");
+                ("200 OK", json!(store.positions(account)))
+            }
+            None => ("404 Not Found", json!({ "error": "No execution_store configured" })),
+        },
+        ("GET", ["metrics"]) => {
+            let sessions: Vec<Value> = ADMIN_SESSIONS
+                .all()
+                .iter()
+                .map(|session| {
+                    json!({
+                        "session": session.session_id.file_stem(),
+                        "seconds_since_last_received": session.activity.reader_idle().as_secs(),
+                        "buffer_pool": session.buffers.stats(),
+                    })
+                })
+                .collect();
+            (
+                "200 OK",
+                json!({
+                    "account_throttle": ACCOUNT_THROTTLE.counters(),
+                    "sessions": sessions,
+                }),
+            )
+        }
+        ("GET", ["kill_switch"]) => ("200 OK", kill_switch_status()),
+        ("POST", ["kill_switch"]) => {
+            let flag = |name: &str| {
+                query
+                    .split('&')
+                    .any(|parameter| parameter == format!("{}=Y", name))
+            };
+            let actions = KillSwitchActions {
+                cancel_orders: flag("cancel"),
+                logout: flag("logout"),
+            };
+            let canceled = engage_kill_switch("engaged through the admin API", actions);
+            let mut status = kill_switch_status();
+            status["canceled_orders"] = json!(canceled);
+            ("200 OK", status)
+        }
+        ("POST", ["kill_switch", "release"]) => {
+            KILL_SWITCH.release();
+            ("200 OK", kill_switch_status())
+        }
+        ("GET", ["accounts", account, "breach"]) => ("200 OK", breach_status(account)),
+        ("POST", ["accounts", account, "breach", "clear"]) => {
+            if !ACCOUNT_RISK.clear(account) {
+                return ("404 Not Found", json!({ "error": "Account is not blocked" }));
+            }
+            ("200 OK", breach_status(account))
+        }
+        ("GET", ["trading_session"]) => ("200 OK", trading_session_status()),
+        ("POST", ["trading_session", status]) => {
+            if IS_INITIATOR.load(Ordering::SeqCst) {
+                return (
+                    "409 Conflict",
+                    json!({ "error": "Only the acceptor publishes the trading session status" }),
+                );
+            }
+            let Some(status) = TradSesStatus::parse(status) else {
+                return ("400 Bad Request", json!({ "error": "Unknown TradSesStatus" }));
+            };
+            let sent = publish_trading_session_status(status, Some("Set through the admin API"));
+            let mut body = trading_session_status();
+            body["sent_to_sessions"] = json!(sent);
+            ("200 OK", body)
+        }
+        (_, ["sessions", key, rest @ ..]) => {
+            let session = match ADMIN_SESSIONS.find(key) {
+                Some(session) => session,
+                None => return ("404 Not Found", json!({ "error": "Unknown session" })),
+            };
+            match (method, rest) {
+                ("GET", []) => ("200 OK", session.status()),
+                ("POST", ["reset"]) => {
+                    info!(
+                        "Admin: resetting the sequence numbers of {}",
+                        session.session_id
+                    );
+                    session.seq_store.reset();
+                    ("200 OK", session.status())
+                }
+                ("POST", ["logout"]) => {
+                    info!("Admin: logging out {}", session.session_id);
+                    // Waits for the counterparty's confirmation, up to the logout timeout
+                    thread::spawn(move || {
+                        if let Err(e) = initiate_logout(
+                            &session.stream,
+                            &session.session_id,
+                            &session.message_maps,
+                            &session.seq_store,
+                            Some("Logout requested by the operator"),
+                        ) {
+                            error!("Failed to log out: {}", e);
+                        }
+                    });
+                    ("202 Accepted", json!({ "result": "logout sent" }))
+                }
+                ("POST", ["reconnect"]) => {
+                    info!("Admin: reconnecting {}", session.session_id);
+                    if IS_INITIATOR.load(Ordering::SeqCst) {
+                        RECONNECT_REQUESTED.store(true, Ordering::SeqCst);
+                    }
+                    if let Err(e) = session.stream.lock().unwrap().close() {
+                        error!("Failed to close the connection: {}", e);
+                    }
+                    ("202 Accepted", json!({ "result": "connection closed" }))
+                }
+                ("GET", ["orders"]) => match order_query(query) {
+                    Ok(query) => ("200 OK", json!(session.order_store.query(&query))),
+                    Err(e) => ("400 Bad Request", json!({ "error": e })),
+                },
+                ("GET", _) | ("POST", _) => ("404 Not Found", json!({ "error": "Not found" })),
+                _ => (
+                    "405 Method Not Allowed",
+                    json!({ "error": "Method not allowed" }),
+                ),
+            }
+        }
+        _ => ("404 Not Found", json!({ "error": "Not found" })),
+    }
+}
+
+fn kill_switch_status() -> Value {
+    let reason = KILL_SWITCH.engaged();
+    json!({ "engaged": reason.is_some(), "reason": reason })
+}
+
+fn breach_status(account: &str) -> Value {
+    let reason = ACCOUNT_RISK.blocked_reason(account);
+    json!({ "account": account, "blocked": reason.is_some(), "reason": reason })
+}
+
+fn trading_session_status() -> Value {
+    let (status, text) = TRADING_SESSION.status();
+    json!({ "trading_session_id": TRADING_SESSION_ID, "status": status, "text": text })
+}
+
+/// The orders asked for by the query string of `GET /sessions/<session>/orders`, also that of
+/// the order gateway's `GET /orders`.
+pub fn order_query(query: &str) -> Result<OrderQuery, String> {
+    let parameters: Vec<(&str, &str)> = query
+        .split('&')
+        .filter(|parameter| !parameter.is_empty())
+        .map(|parameter| parameter.split_once('=').unwrap_or((parameter, "")))
+        .collect();
+    match parameters.as_slice() {
+        [] => Ok(OrderQuery::All),
+        [("symbol", symbol)] => Ok(OrderQuery::Symbol(symbol.to_string())),
+        [("account", account)] => Ok(OrderQuery::OpenForAccount(account.to_string())),
+        [("status", status)] => OrdStatus::parse(status)
+            .map(OrderQuery::Status)
+            .ok_or_else(|| format!("Unknown OrdStatus {}", status)),
+        _ => Err("Expected one of symbol, account or status".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderstore::{Order, OrderKey};
+    use crate::test_support::message_maps;
+    use crate::transport::MemoryTransport;
+    use rust_decimal::Decimal;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_admin_api() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let seq_store =
+            Arc::new(SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap());
+        seq_store.set_incoming(42);
+        seq_store.set_outgoing(7);
+        let order_file = NamedTempFile::new().unwrap();
+        let order_store =
+            Arc::new(OrderStore::new(order_file.path().to_str().unwrap(), 4096).unwrap());
+        let (local, mut remote) = MemoryTransport::pair();
+        let registration = ADMIN_SESSIONS.register(AdminSession {
+            session_id: SessionId {
+                begin_string: "FIX.4.2".to_string(),
+                sender_comp_id: "ADMIN".to_string(),
+                target_comp_id: "TEST".to_string(),
+            },
+            seq_store: Arc::clone(&seq_store),
+            stream: Arc::new(Mutex::new(Box::new(local))),
+            activity: Arc::new(SessionActivity::new()),
+            message_maps: message_maps(),
+            order_store: Arc::clone(&order_store),
+            buffers: Arc::default(),
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            http_server::handle_connection("Admin HTTP API", stream, handle_request).unwrap();
+        });
+        let mut client = TcpStream::connect(address).unwrap();
+        client
+            .write_all(b"GET /sessions/FIX.4.2_ADMIN_TEST HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        server.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let status: Value =
+            serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(status["session_id"], "FIX.4.2:ADMIN->TEST");
+        assert_eq!(status["next_incoming_seq_num"], 42);
+        assert_eq!(status["next_outgoing_seq_num"], 7);
+
+        let (status, body) = route("POST", "/sessions/FIX.4.2_ADMIN_TEST/reset");
+        assert_eq!(status, "200 OK");
+        assert_eq!(body["next_incoming_seq_num"], 1);
+        assert_eq!(seq_store.get_outgoing(), 1);
+
+        let (status, body) = route("GET", "/sessions/FIX.4.2_ADMIN_TEST/orders?symbol=IBM");
+        assert_eq!(status, "200 OK");
+        assert_eq!(body, json!([]));
+        assert_eq!(
+            route("GET", "/sessions/FIX.4.2_ADMIN_TEST/orders?status=open").0,
+            "400 Bad Request"
+        );
+
+        assert_eq!(
+            route("POST", "/sessions/FIX.4.2_ADMIN_TEST/reconnect").0,
+            "202 Accepted"
+        );
+        let mut buffer = [0u8; 1];
+        assert_eq!(remote.read(&mut buffer).unwrap(), 0);
+
+        assert_eq!(
+            route("DELETE", "/sessions/FIX.4.2_ADMIN_TEST").0,
+            "405 Method Not Allowed"
+        );
+        drop(registration);
+        assert_eq!(
+            route("GET", "/sessions/FIX.4.2_ADMIN_TEST").0,
+            "404 Not Found"
+        );
+    }
+
+    #[test]
+    fn test_breach_cancels_on_every_session_until_cleared() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let seq_store =
+            Arc::new(SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap());
+        let order_file = NamedTempFile::new().unwrap();
+        let order_store =
+            Arc::new(OrderStore::new(order_file.path().to_str().unwrap(), 4096).unwrap());
+        let mut order = Order {
+            id: "1".to_string(),
+            sender_comp_id: String::new(),
+            order_id: String::new(),
+            account: "BREACHED".to_string(),
+            symbol: "IBM".to_string(),
+            side: "1".to_string(),
+            quantity: Decimal::from(100),
+            price: Some(Decimal::from(10)),
+            ordtype: "2".to_string(),
+            transacttime: "20240101-00:00:00".to_string(),
+            ordstatus: "New".to_string(),
+            timeinforce: "DAY".to_string(),
+            cum_qty: Decimal::ZERO,
+            avg_px: Decimal::ZERO,
+            list_id: None,
+            list_seq_no: 0,
+            history: Vec::new(),
+        };
+        order_store.add_order(order.clone(), None).unwrap();
+        order.id = "2".to_string();
+        order.account = "OTHER".to_string();
+        order_store.add_order(order, None).unwrap();
+        let (local, _remote) = MemoryTransport::pair();
+        let _registration = ADMIN_SESSIONS.register(AdminSession {
+            session_id: SessionId {
+                begin_string: "FIX.4.2".to_string(),
+                sender_comp_id: "ADMIN".to_string(),
+                target_comp_id: "BREACH".to_string(),
+            },
+            seq_store,
+            stream: Arc::new(Mutex::new(Box::new(local))),
+            activity: Arc::new(SessionActivity::new()),
+            message_maps: message_maps(),
+            order_store: Arc::clone(&order_store),
+            buffers: Arc::default(),
+        });
+
+        // Breached on another session, whose order store holds none of the account's orders
+        let breaching_file = NamedTempFile::new().unwrap();
+        let breaching_store =
+            OrderStore::new(breaching_file.path().to_str().unwrap(), 4096).unwrap();
+        ACCOUNT_RISK.breach("BREACHED", "limit", &breaching_store);
+        assert_eq!(order_store.get_order(&OrderKey::own("1")).unwrap().ordstatus, "Canceled");
+        assert_eq!(order_store.get_order(&OrderKey::own("2")).unwrap().ordstatus, "New");
+
+        let (status, body) = route("GET", "/accounts/BREACHED/breach");
+        assert_eq!(status, "200 OK");
+        assert_eq!(body["blocked"], true);
+        assert_eq!(body["reason"], "limit");
+        let (status, body) = route("POST", "/accounts/BREACHED/breach/clear");
+        assert_eq!(status, "200 OK");
+        assert_eq!(body["blocked"], false);
+        assert_eq!(
+            route("POST", "/accounts/BREACHED/breach/clear").0,
+            "404 Not Found"
+        );
+    }
+}
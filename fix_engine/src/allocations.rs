@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use rust_decimal::Decimal;
+
+use crate::message_converter::repeating_group;
+use crate::orderstore::{OrderKey, OrderStore};
+
+/// Fields of a NoAllocs entry besides AllocAccount(79), which starts it.
+const ALLOC_MEMBER_TAGS: [u32; 11] = [80, 366, 81, 76, 109, 12, 13, 161, 153, 154, 467];
+/// Fields of a NoOrders entry besides ClOrdID(11), which starts it.
+const ORDER_MEMBER_TAGS: [u32; 4] = [37, 198, 66, 105];
+
+/// AllocRejCode(88) of an AllocationInstruction refused.
+pub const ALLOC_REJ_CODE_UNKNOWN_ACCOUNT: &str = "0";
+pub const ALLOC_REJ_CODE_INCORRECT_QUANTITY: &str = "1";
+pub const ALLOC_REJ_CODE_UNKNOWN_ORDER_ID: &str = "5";
+pub const ALLOC_REJ_CODE_OTHER: &str = "7";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Allocation {
+    pub account: String,
+    pub quantity: Decimal,
+}
+
+/// An AllocationInstruction (35=J) splitting the quantity executed for orders across accounts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllocationInstruction {
+    pub alloc_id: String,
+    pub side: String,
+    pub symbol: String,
+    /// Shares(53), Quantity in FIX 4.4: what is allocated.
+    pub quantity: Decimal,
+    pub avg_px: String,
+    pub trade_date: String,
+    /// ClOrdIDs of the NoOrders entries.
+    pub orders: Vec<String>,
+    pub allocations: Vec<Allocation>,
+}
+
+/// Why an AllocationInstruction is refused, with its AllocRejCode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AllocationReject {
+    pub code: &'static str,
+    pub text: String,
+}
+
+/// TradeDate of an instruction without one.
+pub fn today() -> String {
+    Utc::now().format("%Y%m%d").to_string()
+}
+
+fn transact_time() -> String {
+    Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string()
+}
+
+impl AllocationReject {
+    fn new(code: &'static str, text: String) -> Self {
+        AllocationReject { code, text }
+    }
+}
+
+impl AllocationInstruction {
+    /// Reads the instruction from the raw message, its repeating groups included.
+    pub fn parse(message: &str) -> Result<Self, AllocationReject> {
+        let other = |text: String| AllocationReject::new(ALLOC_REJ_CODE_OTHER, text);
+        let fields: HashMap<&str, &str> = message
+            .split(['\x01', '|'])
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        let field = |tag: &str| fields.get(tag).map(|value| value.to_string());
+        let quantity = field("53")
+            .and_then(|quantity| quantity.parse().ok())
+            .ok_or_else(|| {
+                AllocationReject::new(
+                    ALLOC_REJ_CODE_INCORRECT_QUANTITY,
+                    "Missing or invalid quantity".to_string(),
+                )
+            })?;
+        let orders = repeating_group(message, 73, 11, &ORDER_MEMBER_TAGS)
+            .map_err(other)?
+            .into_iter()
+            .filter_map(|mut entry| entry.remove(&11))
+            .collect();
+        let mut allocations = Vec::new();
+        for mut entry in repeating_group(message, 78, 79, &ALLOC_MEMBER_TAGS).map_err(other)? {
+            let account = entry.remove(&79).unwrap_or_default();
+            if account.is_empty() {
+                return Err(AllocationReject::new(
+                    ALLOC_REJ_CODE_UNKNOWN_ACCOUNT,
+                    "Allocation without an account".to_string(),
+                ));
+            }
+            let quantity = entry
+                .get(&80)
+                .and_then(|quantity| quantity.parse::<Decimal>().ok())
+                .filter(|quantity| *quantity > Decimal::ZERO)
+                .ok_or_else(|| {
+                    AllocationReject::new(
+                        ALLOC_REJ_CODE_INCORRECT_QUANTITY,
+                        format!("Invalid quantity allocated to {}", account),
+                    )
+                })?;
+            allocations.push(Allocation { account, quantity });
+        }
+        Ok(AllocationInstruction {
+            alloc_id: field("70").ok_or_else(|| other("Missing AllocID".to_string()))?,
+            side: field("54").unwrap_or_default(),
+            symbol: field("55").unwrap_or_default(),
+            quantity,
+            avg_px: field("6").unwrap_or_default(),
+            trade_date: field("75").unwrap_or_else(today),
+            orders,
+            allocations,
+        })
+    }
+
+    /// Checks that the allocations add up to the quantity, and that it was filled on the
+    /// orders referenced, those `sender_comp_id` placed.
+    pub fn validate(
+        &self,
+        order_store: &OrderStore,
+        sender_comp_id: &str,
+    ) -> Result<(), AllocationReject> {
+        let incorrect_quantity =
+            |text: String| AllocationReject::new(ALLOC_REJ_CODE_INCORRECT_QUANTITY, text);
+        if self.allocations.is_empty() {
+            return Err(AllocationReject::new(
+                ALLOC_REJ_CODE_UNKNOWN_ACCOUNT,
+                "No allocations".to_string(),
+            ));
+        }
+        let allocated: Decimal = self.allocations.iter().map(|alloc| alloc.quantity).sum();
+        if allocated != self.quantity {
+            return Err(incorrect_quantity(format!(
+                "Allocations of {} do not add up to {}",
+                allocated, self.quantity
+            )));
+        }
+        if self.orders.is_empty() {
+            return Ok(());
+        }
+        let mut filled = Decimal::ZERO;
+        for id in &self.orders {
+            let key = OrderKey::new(sender_comp_id, id);
+            let order = order_store.get_order(&key).ok_or_else(|| {
+                AllocationReject::new(
+                    ALLOC_REJ_CODE_UNKNOWN_ORDER_ID,
+                    format!("Unknown order {}", id),
+                )
+            })?;
+            filled += order.cum_qty;
+        }
+        if self.quantity > filled {
+            return Err(incorrect_quantity(format!(
+                "{} allocated but {} filled",
+                self.quantity, filled
+            )));
+        }
+        Ok(())
+    }
+
+    /// Override fields of the AllocationReport template confirming the instruction. Its
+    /// repeating groups are added with `report_groups`.
+    pub fn report_fields(&self, alloc_report_id: &str) -> HashMap<String, String> {
+        let mut fields = HashMap::from([
+            ("AllocReportID".to_string(), alloc_report_id.to_string()),
+            ("AllocID".to_string(), self.alloc_id.clone()),
+            ("Side".to_string(), self.side.clone()),
+            ("Symbol".to_string(), self.symbol.clone()),
+            ("Quantity".to_string(), self.quantity.to_string()),
+            ("AvgPx".to_string(), self.avg_px.clone()),
+            ("TradeDate".to_string(), self.trade_date.clone()),
+            ("TransactTime".to_string(), transact_time()),
+        ]);
+        if self.orders.is_empty() {
+            fields.insert("AllocNoOrdersType".to_string(), "NOT_SPECIFIED".to_string());
+        }
+        fields
+    }
+
+    /// The NoOrders and NoAllocs repeating groups of the AllocationReport, as tag and value
+    /// pairs.
+    pub fn report_groups(&self) -> Vec<(u32, String)> {
+        let mut groups = Vec::new();
+        if !self.orders.is_empty() {
+            groups.push((73, self.orders.len().to_string()));
+            groups.extend(self.orders.iter().map(|cl_ord_id| (11, cl_ord_id.clone())));
+        }
+        groups.push((78, self.allocations.len().to_string()));
+        for allocation in &self.allocations {
+            groups.push((79, allocation.account.clone()));
+            groups.push((80, allocation.quantity.to_string()));
+        }
+        groups
+    }
+}
+
+/// Override fields of the AllocationInstructionAck template answering an instruction,
+/// accepting it or refusing it with the AllocRejCode and the reason in Text.
+pub fn ack_fields(
+    alloc_id: &str,
+    trade_date: &str,
+    result: &Result<(), AllocationReject>,
+) -> HashMap<String, String> {
+    let mut fields = HashMap::from([
+        ("AllocID".to_string(), alloc_id.to_string()),
+        ("TradeDate".to_string(), trade_date.to_string()),
+        ("TransactTime".to_string(), transact_time()),
+    ]);
+    if let Err(reject) = result {
+        fields.insert("AllocStatus".to_string(), "BLOCK_LEVEL_REJECT".to_string());
+        fields.insert("AllocRejCode".to_string(), reject.code.to_string());
+        fields.insert("Text".to_string(), reject.text.clone());
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderstore::Order;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_allocations_are_validated_against_fills() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store = OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+        order_store
+            .add_order(
+                Order {
+                    id: "1".to_string(),
+                    sender_comp_id: String::new(),
+                    order_id: "O00000001".to_string(),
+                    account: "BLOCK".to_string(),
+                    symbol: "IBM".to_string(),
+                    side: "1".to_string(),
+                    quantity: Decimal::from(500),
+                    price: Some(Decimal::from(100)),
+                    ordtype: "2".to_string(),
+                    transacttime: "20240101-00:00:00".to_string(),
+                    ordstatus: "New".to_string(),
+                    timeinforce: "DAY".to_string(),
+                    cum_qty: Decimal::ZERO,
+                    avg_px: Decimal::ZERO,
+                    list_id: None,
+                    list_seq_no: 0,
+                    history: Vec::new(),
+                },
+                None,
+            )
+            .unwrap();
+        order_store
+            .fill_order(&OrderKey::own("1"), Decimal::from(300), Decimal::from(100))
+            .unwrap();
+
+        let instruction = |orders: &str, quantity: &str, allocs: &str| {
+            AllocationInstruction::parse(&format!(
+                "8=FIX.4.2|9=0|35=J|70=A1|71=0|{}54=1|55=IBM|53={}|6=100|75=20240101|{}10=000|",
+                orders, quantity, allocs
+            ))
+        };
+        let allocs = "78=2|79=ACC1|80=100|79=ACC2|80=200|";
+        let accepted = instruction("73=1|11=1|", "300", allocs).unwrap();
+        assert_eq!(accepted.allocations[1].account, "ACC2");
+        assert_eq!(accepted.validate(&order_store, ""), Ok(()));
+        assert_eq!(accepted.report_groups().len(), 7);
+        assert_eq!(
+            instruction("", "300", "78=1|79=ACC1|80=200|")
+                .unwrap()
+                .validate(&order_store, "")
+                .unwrap_err()
+                .code,
+            ALLOC_REJ_CODE_INCORRECT_QUANTITY
+        );
+        let overfilled = "78=2|79=ACC1|80=100|79=ACC2|80=300|";
+        let rejected = instruction("73=1|11=1|", "400", overfilled).unwrap();
+        assert_eq!(
+            rejected.validate(&order_store, "").unwrap_err().text,
+            "400 allocated but 300 filled"
+        );
+        let unknown = instruction("73=1|11=9|", "300", allocs).unwrap();
+        assert_eq!(
+            unknown.validate(&order_store, "").unwrap_err().code,
+            ALLOC_REJ_CODE_UNKNOWN_ORDER_ID
+        );
+        assert!(instruction("73=1|11=1|", "300", "78=2|79=ACC1|80=100|").is_err());
+    }
+}
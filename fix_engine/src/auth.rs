@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use indexmap::IndexMap;
+
+use crate::tag_value;
+
+lazy_static! {
+    pub static ref LOGON_AUTH: RwLock<LogonAuth> = RwLock::new(LogonAuth::default());
+}
+
+/// Credentials sent in our own outgoing Logon.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogonCredentials {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub raw_data: Option<String>,
+}
+
+impl LogonCredentials {
+    /// Adds Username(553)/Password(554) and, for legacy venues, RawDataLength(95)/RawData(96)
+    /// to an outgoing Logon template. The length tag is always placed before the data tag.
+    pub fn apply(&self, logon: &mut IndexMap<String, String>) {
+        if let Some(username) = &self.username {
+            logon.insert("Username".to_string(), username.clone());
+        }
+        if let Some(password) = &self.password {
+            logon.insert("Password".to_string(), password.clone());
+        }
+        if let Some(raw_data) = &self.raw_data {
+            logon.shift_remove("RawData");
+            logon.insert("RawDataLength".to_string(), raw_data.len().to_string());
+            logon.insert("RawData".to_string(), raw_data.clone());
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LogonAuth {
+    pub credentials: LogonCredentials,
+    /// Counterparties allowed to log on to the acceptor (username -> password).
+    /// When empty, incoming Logons are not authenticated.
+    pub users: HashMap<String, String>,
+}
+
+impl LogonAuth {
+    /// Checks the credentials carried by an incoming Logon.
+    /// Returns the reason to put into the Logout Text when the Logon must be rejected.
+    pub fn authenticate(&self, msg_map: &IndexMap<String, String>) -> Result<(), String> {
+        if self.users.is_empty() {
+            return Ok(());
+        }
+
+        let (username, secret) = match (msg_map.get("Username"), msg_map.get("Password")) {
+            (Some(username), Some(password)) => (username, password),
+            // Legacy venues identify themselves by SenderCompID and send the password in RawData
+            _ => match (msg_map.get("SenderCompID"), msg_map.get("RawData")) {
+                (Some(sender), Some(raw_data)) => (sender, raw_data),
+                _ => return Err("Logon rejected: missing credentials".to_string()),
+            },
+        };
+
+        // Config keys are case-insensitive, so user names are matched in lower case
+        match self.users.get(&username.to_lowercase()) {
+            Some(expected) if expected == secret => Ok(()),
+            _ => Err(format!("Logon rejected: invalid credentials for {}", username)),
+        }
+    }
+
+    /// Checks the credentials of a Logon still in its raw form, e.g. as peeked by the acceptor
+    /// before the session is set up.
+    pub fn authenticate_raw(&self, logon: &[u8]) -> Result<(), String> {
+        let msg_map = tag_value::fields(logon)
+            .map_while(Result::ok)
+            .filter_map(|(tag, value)| {
+                let name = match tag {
+                    49 => "SenderCompID",
+                    96 => "RawData",
+                    553 => "Username",
+                    554 => "Password",
+                    _ => return None,
+                };
+                Some((name.to_string(), String::from_utf8_lossy(value).into_owned()))
+            })
+            .collect();
+        self.authenticate(&msg_map)
+    }
+}
+
+/// Returns a copy of the admin message templates whose Logon carries the configured credentials.
+pub fn admin_msg_with_credentials(
+    admin_msg: &HashMap<String, IndexMap<String, String>>,
+) -> HashMap<String, IndexMap<String, String>> {
+    let mut admin_msg = admin_msg.clone();
+    if let Some(logon) = admin_msg.get_mut("Logon") {
+        LOGON_AUTH.read().unwrap().credentials.apply(logon);
+    }
+    admin_msg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logon_map(fields: &[(&str, &str)]) -> IndexMap<String, String> {
+        fields
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn auth_with_user(username: &str, password: &str) -> LogonAuth {
+        LogonAuth {
+            credentials: LogonCredentials::default(),
+            users: HashMap::from([(username.to_string(), password.to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_apply_username_password() {
+        let credentials = LogonCredentials {
+            username: Some("trader1".to_string()),
+            password: Some("secret".to_string()),
+            raw_data: None,
+        };
+        let mut logon = logon_map(&[("MsgType", "Logon"), ("HeartBtInt", "10")]);
+        credentials.apply(&mut logon);
+
+        assert_eq!(logon.get("Username").unwrap(), "trader1");
+        assert_eq!(logon.get("Password").unwrap(), "secret");
+        assert!(logon.get("RawData").is_none());
+    }
+
+    #[test]
+    fn test_apply_raw_data_sets_length_first() {
+        let credentials = LogonCredentials {
+            username: None,
+            password: None,
+            raw_data: Some("token42".to_string()),
+        };
+        let mut logon = logon_map(&[("MsgType", "Logon"), ("RawData", "old")]);
+        credentials.apply(&mut logon);
+
+        let keys: Vec<&String> = logon.keys().collect();
+        assert_eq!(keys, vec!["MsgType", "RawDataLength", "RawData"]);
+        assert_eq!(logon.get("RawDataLength").unwrap(), "7");
+        assert_eq!(logon.get("RawData").unwrap(), "token42");
+    }
+
+    #[test]
+    fn test_authenticate_without_user_list() {
+        let auth = LogonAuth::default();
+        assert!(auth.authenticate(&logon_map(&[("MsgType", "LOGON")])).is_ok());
+    }
+
+    #[test]
+    fn test_authenticate_username_password() {
+        let auth = auth_with_user("trader1", "secret");
+        let ok = logon_map(&[("Username", "TRADER1"), ("Password", "secret")]);
+        let bad = logon_map(&[("Username", "trader1"), ("Password", "wrong")]);
+
+        assert!(auth.authenticate(&ok).is_ok());
+        assert_eq!(
+            auth.authenticate(&bad).unwrap_err(),
+            "Logon rejected: invalid credentials for trader1"
+        );
+    }
+
+    #[test]
+    fn test_authenticate_legacy_raw_data() {
+        let auth = auth_with_user("xyzexchange", "token42");
+        let logon = logon_map(&[("SenderCompID", "XYZExchange"), ("RawData", "token42")]);
+        assert!(auth.authenticate(&logon).is_ok());
+    }
+
+    #[test]
+    fn test_authenticate_missing_credentials() {
+        let auth = auth_with_user("trader1", "secret");
+        let logon = logon_map(&[("SenderCompID", "XYZExchange")]);
+        assert_eq!(
+            auth.authenticate(&logon).unwrap_err(),
+            "Logon rejected: missing credentials"
+        );
+    }
+
+    #[test]
+    fn test_authenticate_raw() {
+        let auth = auth_with_user("trader1", "secret");
+        let ok = b"8=FIX.4.2\x019=40\x0135=A\x01553=trader1\x01554=secret\x0110=000\x01";
+        let bad = b"8=FIX.4.2\x019=40\x0135=A\x01553=trader1\x01554=wrong\x0110=000\x01";
+
+        assert!(auth.authenticate_raw(ok).is_ok());
+        assert!(auth.authenticate_raw(bad).is_err());
+        assert!(auth.authenticate_raw(b"8=FIX.4.2\x019=5\x0135=A").is_err());
+    }
+}
@@ -0,0 +1,575 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+use indexmap::IndexMap;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::admin_http::ADMIN_SESSIONS;
+use crate::connection::{establish_connection, handle_stream, send_logon_message};
+use crate::message_handlers::{Handled, MESSAGE_HANDLERS};
+use crate::orderstore::OrderStore;
+use crate::sequence::{SequenceNumberStore, SequenceStores, SessionId};
+use crate::session::{Session, SESSION_STATES};
+use crate::templates::Templates;
+use crate::wire_log::with_wire_log;
+use crate::{MessageMap, RECONNECT_INTERVAL};
+
+lazy_static! {
+    /// The venue sessions the acceptor forwards its clients' orders to by TargetCompID, none
+    /// unless in bridge mode.
+    pub static ref BRIDGES: RwLock<HashMap<String, Arc<Bridge>>> = RwLock::new(HashMap::new());
+}
+
+/// Header and trailer fields, set by the session a message is sent on.
+const SESSION_FIELDS: [&str; 14] = [
+    "BeginString",
+    "BodyLength",
+    "MsgSeqNum",
+    "SenderCompID",
+    "TargetCompID",
+    "SenderSubID",
+    "TargetSubID",
+    "OnBehalfOfCompID",
+    "DeliverToCompID",
+    "SendingTime",
+    "OrigSendingTime",
+    "PossDupFlag",
+    "PossResend",
+    "CheckSum",
+];
+
+/// A `[bridge]` or `[bridge.<name>]` section: a venue the acceptor connects to as an
+/// initiator, and the CompIDs of that session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BridgeConfig {
+    pub host: String,
+    pub port: u16,
+    pub sender_comp_id: String,
+    pub target_comp_id: String,
+    /// Starts the ClOrdIDs of the orders sent to the venue.
+    pub cl_ord_id_prefix: String,
+    /// Where the routes of the orders forwarded are kept, a file per venue session named
+    /// after it like the sequence number stores.
+    pub route_store: String,
+}
+
+/// The client session and ClOrdID of an order forwarded to the venue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Route {
+    pub client: SessionId,
+    pub cl_ord_id: String,
+}
+
+/// A line of the route store: the ClOrdID of an order at the venue and its route.
+#[derive(Serialize, Deserialize)]
+struct RouteRecord {
+    venue_id: String,
+    #[serde(flatten)]
+    route: Route,
+}
+
+/// Why an order request of a client was not forwarded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForwardError {
+    VenueNotLoggedOn,
+    /// The OrigClOrdID, or the ClOrdID of a status request, was never forwarded.
+    UnknownOrder(String),
+    /// The route of the order could not be stored, its reports could not be routed back.
+    RouteStore(String),
+    Send(String),
+}
+
+impl fmt::Display for ForwardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForwardError::VenueNotLoggedOn => write!(f, "Venue session not logged on"),
+            ForwardError::UnknownOrder(cl_ord_id) => write!(f, "Unknown order {}", cl_ord_id),
+            ForwardError::RouteStore(reason) => write!(f, "Failed to store the route: {}", reason),
+            ForwardError::Send(reason) => write!(f, "Failed to forward to the venue: {}", reason),
+        }
+    }
+}
+
+/// Order requests of the clients handed to the venue, ExecutionReports and OrderCancelRejects
+/// of the venue handed back to the client of the order.
+pub fn is_bridged(msg_type: &str) -> bool {
+    matches!(
+        msg_type,
+        "NEW_ORDER_SINGLE"
+            | "ORDER_CANCEL_REQUEST"
+            | "ORDER_CANCEL_REPLACE_REQUEST"
+            | "ORDER_STATUS_REQUEST"
+    )
+}
+
+fn session_fields_removed(msg_map: &IndexMap<String, String>) -> IndexMap<String, String> {
+    msg_map
+        .iter()
+        .filter(|(field, _)| !SESSION_FIELDS.contains(&field.as_str()))
+        .map(|(field, value)| (field.clone(), value.clone()))
+        .collect()
+}
+
+/// The ClOrdIDs of the orders forwarded to the venue mapped to those of the clients, both
+/// ways. Every route is appended to the route store, if any, and loaded again on startup so
+/// the reports of orders forwarded before a restart still find their client.
+pub struct RouteTable {
+    prefix: String,
+    next_id: u64,
+    by_venue_id: HashMap<String, Route>,
+    by_client_id: HashMap<(SessionId, String), String>,
+    file: Option<File>,
+}
+
+impl RouteTable {
+    /// A table held in memory only.
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            next_id: 0,
+            by_venue_id: HashMap::new(),
+            by_client_id: HashMap::new(),
+            file: None,
+        }
+    }
+
+    /// Opens (or creates) the route store and loads its routes. A last line cut short by a
+    /// crash is skipped.
+    pub fn open(file_path: &str, prefix: &str) -> io::Result<Self> {
+        let mut routes = Self::new(prefix);
+        let content = fs::read_to_string(file_path).unwrap_or_default();
+        for (number, line) in content.lines().enumerate() {
+            match serde_json::from_str::<RouteRecord>(line) {
+                Ok(record) => routes.insert(record.venue_id, record.route),
+                Err(e) => warn!("Ignoring line {} of {}: {}", number + 1, file_path, e),
+            }
+        }
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(file_path)?;
+        // The next route starts on a line of its own
+        if !content.is_empty() && !content.ends_with('\n') {
+            writeln!(file)?;
+        }
+        routes.file = Some(file);
+        Ok(routes)
+    }
+
+    fn insert(&mut self, venue_id: String, route: Route) {
+        // Our ClOrdIDs carry on after those of the routes loaded
+        if let Some(id) = venue_id
+            .strip_prefix(&self.prefix)
+            .and_then(|id| id.parse::<u64>().ok())
+        {
+            self.next_id = self.next_id.max(id);
+        }
+        self.by_client_id.insert(
+            (route.client.clone(), route.cl_ord_id.clone()),
+            venue_id.clone(),
+        );
+        self.by_venue_id.insert(venue_id, route);
+    }
+
+    /// Stores the route before the order goes out, so its reports are routed back even
+    /// after a restart.
+    fn record(&mut self, venue_id: &str, route: &Route) -> io::Result<()> {
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        let record = RouteRecord {
+            venue_id: venue_id.to_string(),
+            route: route.clone(),
+        };
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+        file.flush()
+    }
+
+    /// Whether the order of the client was forwarded to the venue.
+    pub fn contains(&self, client: &SessionId, cl_ord_id: &str) -> bool {
+        self.by_client_id
+            .contains_key(&(client.clone(), cl_ord_id.to_string()))
+    }
+
+    fn venue_id(&self, client: &SessionId, cl_ord_id: &str) -> Result<String, ForwardError> {
+        self.by_client_id
+            .get(&(client.clone(), cl_ord_id.to_string()))
+            .cloned()
+            .ok_or_else(|| ForwardError::UnknownOrder(cl_ord_id.to_string()))
+    }
+
+    /// The order request of a client as sent to the venue: without its session fields, under
+    /// a ClOrdID of ours and with the OrigClOrdID of the order at the venue.
+    pub fn outbound(
+        &mut self,
+        client: &SessionId,
+        msg_map: &IndexMap<String, String>,
+    ) -> Result<IndexMap<String, String>, ForwardError> {
+        let mut venue_msg = session_fields_removed(msg_map);
+        if let Some(orig_cl_ord_id) = msg_map.get("OrigClOrdID") {
+            venue_msg.insert(
+                "OrigClOrdID".to_string(),
+                self.venue_id(client, orig_cl_ord_id)?,
+            );
+        }
+        let Some(cl_ord_id) = msg_map.get("ClOrdID") else {
+            return Ok(venue_msg);
+        };
+        // A status request names the order, it does not start a new one
+        let venue_id = if msg_map.get("MsgType").map(String::as_str) == Some("ORDER_STATUS_REQUEST")
+        {
+            self.venue_id(client, cl_ord_id)?
+        } else {
+            let venue_id = format!("{}{:08}", self.prefix, self.next_id + 1);
+            let route = Route {
+                client: client.clone(),
+                cl_ord_id: cl_ord_id.clone(),
+            };
+            self.record(&venue_id, &route)
+                .map_err(|e| ForwardError::RouteStore(e.to_string()))?;
+            self.insert(venue_id.clone(), route);
+            venue_id
+        };
+        venue_msg.insert("ClOrdID".to_string(), venue_id);
+        Ok(venue_msg)
+    }
+
+    /// A report of the venue as sent back to the client of the order: without its session
+    /// fields, with the client's ClOrdID and OrigClOrdID. None for an order never forwarded.
+    pub fn inbound(
+        &self,
+        msg_map: &IndexMap<String, String>,
+    ) -> Option<(SessionId, IndexMap<String, String>)> {
+        let route = self.by_venue_id.get(msg_map.get("ClOrdID")?)?;
+        let mut client_msg = session_fields_removed(msg_map);
+        client_msg.insert("ClOrdID".to_string(), route.cl_ord_id.clone());
+        if let Some(orig) = msg_map
+            .get("OrigClOrdID")
+            .and_then(|id| self.by_venue_id.get(id))
+        {
+            client_msg.insert("OrigClOrdID".to_string(), orig.cl_ord_id.clone());
+        }
+        Some((route.client.clone(), client_msg))
+    }
+}
+
+/// The message maps of the acceptor with the CompIDs of the venue session, in the header
+/// and in every template.
+fn venue_message_maps(message_maps: &MessageMap, config: &BridgeConfig) -> MessageMap {
+    let mut message_maps = message_maps.clone();
+    let comp_ids = vec![
+        ("SenderCompID".to_string(), config.sender_comp_id.clone()),
+        ("TargetCompID".to_string(), config.target_comp_id.clone()),
+    ];
+    for (field, value) in &comp_ids {
+        message_maps.fix_header.insert(field.clone(), value.clone());
+    }
+    for templates in [&mut message_maps.admin_msg, &mut message_maps.app_msg] {
+        *templates = Templates::new((*templates.current()).clone(), comp_ids.clone());
+    }
+    message_maps
+}
+
+/// Bridge mode: the acceptor forwards the order requests of its clients to a venue over an
+/// initiator session of its own, and routes the venue's reports back to them.
+pub struct Bridge {
+    config: BridgeConfig,
+    /// The venue session, run like any other session of the engine.
+    session_id: SessionId,
+    routes: Mutex<RouteTable>,
+}
+
+impl Bridge {
+    pub fn new(config: BridgeConfig, session_id: SessionId, routes: RouteTable) -> Self {
+        Self {
+            config,
+            session_id,
+            routes: Mutex::new(routes),
+        }
+    }
+
+    /// The TargetCompID of the venue, which routing rules name it by.
+    pub fn name(&self) -> &str {
+        &self.config.target_comp_id
+    }
+
+    /// Whether the order of the client was forwarded to this venue.
+    pub fn holds(&self, client: &SessionId, cl_ord_id: &str) -> bool {
+        self.routes.lock().unwrap().contains(client, cl_ord_id)
+    }
+
+    /// Whether the message came in on the venue session.
+    fn is_from_venue(&self, msg_map: &IndexMap<String, String>) -> bool {
+        SessionId::from_received(msg_map) == self.session_id
+    }
+
+    /// Sends the message of the client to the venue, under a ClOrdID of ours if it has one.
+    pub fn forward(
+        &self,
+        client: &SessionId,
+        msg_map: &IndexMap<String, String>,
+    ) -> Result<(), ForwardError> {
+        let venue = ADMIN_SESSIONS
+            .get(&self.session_id)
+            .filter(|venue| venue.is_logged_on())
+            .ok_or(ForwardError::VenueNotLoggedOn)?;
+        let venue_msg = self.routes.lock().unwrap().outbound(client, msg_map)?;
+        info!(
+            "Forwarding {:?} {:?} of {} to {} as {:?}",
+            msg_map.get("MsgType"),
+            msg_map.get("ClOrdID"),
+            client,
+            self.name(),
+            venue_msg.get("ClOrdID")
+        );
+        Session::new(
+            venue.session_id.clone(),
+            Arc::clone(&venue.stream),
+            Arc::clone(&venue.message_maps),
+            Arc::clone(&venue.seq_store),
+        )
+        .send_batch(vec![venue_msg])
+        .map_err(|e| ForwardError::Send(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Hands a report of the venue to the client session of its order.
+    fn route_back(&self, msg_map: &IndexMap<String, String>) {
+        let Some((client, client_msg)) = self.routes.lock().unwrap().inbound(msg_map) else {
+            error!(
+                "Report {:?} of the venue for unknown order {:?}",
+                msg_map.get("ExecID"),
+                msg_map.get("ClOrdID")
+            );
+            return;
+        };
+        let Some(session) = ADMIN_SESSIONS.get(&client) else {
+            error!(
+                "Client {} of order {:?} is not connected",
+                client,
+                client_msg.get("ClOrdID")
+            );
+            return;
+        };
+        let session = Session::new(
+            session.session_id.clone(),
+            Arc::clone(&session.stream),
+            Arc::clone(&session.message_maps),
+            Arc::clone(&session.seq_store),
+        );
+        if let Err(e) = session.send_batch(vec![client_msg]) {
+            error!("Failed to route a report back to {}: {}", client, e);
+        }
+    }
+
+    /// Connects to the venue, logs on and runs the session until the connection closes. Its
+    /// messages go through the same checks, sequencing and recovery as those of any session,
+    /// its reports are routed back by the handlers `start_bridge` registers.
+    fn run_venue_session(
+        &self,
+        message_maps: &Arc<MessageMap>,
+        seq_store: &Arc<SequenceNumberStore>,
+        order_store: &Arc<OrderStore>,
+    ) -> io::Result<()> {
+        let connection = establish_connection(&self.config.host, self.config.port)?;
+        let mut stream = with_wire_log(Box::new(connection), &self.session_id);
+        SESSION_STATES.for_session(&self.session_id).reset();
+        send_logon_message(
+            stream.as_mut(),
+            &self.session_id,
+            message_maps,
+            Arc::clone(seq_store),
+        )?;
+        handle_stream(
+            stream,
+            &self.session_id,
+            message_maps,
+            Arc::clone(seq_store),
+            Arc::clone(order_store),
+        )
+    }
+}
+
+/// The venue the order of the client was forwarded to, if any.
+pub fn venue_holding(client: &SessionId, cl_ord_id: &str) -> Option<Arc<Bridge>> {
+    BRIDGES
+        .read()
+        .unwrap()
+        .values()
+        .find(|bridge| bridge.holds(client, cl_ord_id))
+        .cloned()
+}
+
+/// Sends the message of the client to the venue with the TargetCompID.
+pub fn forward_to(
+    venue: &str,
+    client: &SessionId,
+    msg_map: &IndexMap<String, String>,
+) -> Result<(), ForwardError> {
+    let bridge = BRIDGES.read().unwrap().get(venue).cloned();
+    bridge
+        .ok_or(ForwardError::VenueNotLoggedOn)?
+        .forward(client, msg_map)
+}
+
+/// The session of the acceptor a message of the client came in on.
+pub fn client_of(msg_map: &IndexMap<String, String>) -> SessionId {
+    let get = |key: &str| msg_map.get(key).cloned().unwrap_or_default();
+    SessionId {
+        begin_string: get("BeginString"),
+        sender_comp_id: get("TargetCompID"),
+        target_comp_id: get("SenderCompID"),
+    }
+}
+
+/// Starts the venue session of bridge mode on its own thread, connecting again after
+/// `reconnect_interval` whenever it closes. Its sequence numbers are kept like those of any
+/// other session.
+pub fn start_bridge(
+    config: BridgeConfig,
+    message_maps: &MessageMap,
+    seq_stores: &SequenceStores,
+    order_store: &Arc<OrderStore>,
+) -> io::Result<()> {
+    let message_maps = Arc::new(venue_message_maps(message_maps, &config));
+    let session_id = SessionId::from_header(&message_maps.fix_header, None);
+    let seq_store = seq_stores.for_session(&session_id)?;
+    let routes = RouteTable::open(
+        &session_id.file_path(&config.route_store),
+        &config.cl_ord_id_prefix,
+    )?;
+    let bridge = Arc::new(Bridge::new(config, session_id, routes));
+    BRIDGES
+        .write()
+        .unwrap()
+        .insert(bridge.config.target_comp_id.clone(), Arc::clone(&bridge));
+    for msgtype in ["EXECUTION_REPORT", "ORDER_CANCEL_REJECT"] {
+        let bridge = Arc::clone(&bridge);
+        MESSAGE_HANDLERS.on(msgtype, move |msg_map, _| {
+            if !bridge.is_from_venue(msg_map) {
+                return Handled::Continue;
+            }
+            bridge.route_back(msg_map);
+            Handled::Done
+        });
+    }
+    let order_store = Arc::clone(order_store);
+    thread::spawn(move || loop {
+        if let Err(e) = bridge.run_venue_session(&message_maps, &seq_store, &order_store) {
+            error!("Venue session {} failed: {}", bridge.session_id, e);
+        }
+        sleep(Duration::from_secs(
+            RECONNECT_INTERVAL.load(Ordering::SeqCst),
+        ));
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg_map;
+
+    #[test]
+    fn test_orders_are_routed_to_the_venue_and_back() {
+        let client = SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "HUB".to_string(),
+            target_comp_id: "CLIENT1".to_string(),
+        };
+        let mut routes = RouteTable::new("B");
+        let order = routes
+            .outbound(
+                &client,
+                &msg_map(&[
+                    ("MsgType", "NEW_ORDER_SINGLE"),
+                    ("SenderCompID", "CLIENT1"),
+                    ("MsgSeqNum", "7"),
+                    ("ClOrdID", "1"),
+                    ("Symbol", "IBM"),
+                ]),
+            )
+            .unwrap();
+        assert_eq!(
+            order,
+            msg_map(&[
+                ("MsgType", "NEW_ORDER_SINGLE"),
+                ("ClOrdID", "B00000001"),
+                ("Symbol", "IBM")
+            ])
+        );
+        let cancel = routes
+            .outbound(
+                &client,
+                &msg_map(&[
+                    ("MsgType", "ORDER_CANCEL_REQUEST"),
+                    ("OrigClOrdID", "1"),
+                    ("ClOrdID", "2"),
+                ]),
+            )
+            .unwrap();
+        assert_eq!(cancel["OrigClOrdID"], "B00000001");
+        assert_eq!(cancel["ClOrdID"], "B00000002");
+        assert_eq!(
+            routes.outbound(
+                &client,
+                &msg_map(&[("MsgType", "ORDER_STATUS_REQUEST"), ("ClOrdID", "9")])
+            ),
+            Err(ForwardError::UnknownOrder("9".to_string()))
+        );
+
+        let (routed_to, report) = routes
+            .inbound(&msg_map(&[
+                ("MsgType", "EXECUTION_REPORT"),
+                ("SenderCompID", "VENUE"),
+                ("ClOrdID", "B00000002"),
+                ("OrigClOrdID", "B00000001"),
+                ("ExecType", "CANCELED"),
+            ]))
+            .unwrap();
+        assert_eq!(routed_to, client);
+        assert_eq!(report["ClOrdID"], "2");
+        assert_eq!(report["OrigClOrdID"], "1");
+        assert!(!report.contains_key("SenderCompID"));
+        assert!(routes
+            .inbound(&msg_map(&[("ClOrdID", "B00000003")]))
+            .is_none());
+    }
+
+    #[test]
+    fn test_routes_survive_a_restart() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let file_path = file.path().to_str().unwrap();
+        let client = SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "HUB".to_string(),
+            target_comp_id: "CLIENT1".to_string(),
+        };
+        let order =
+            |cl_ord_id: &str| msg_map(&[("MsgType", "NEW_ORDER_SINGLE"), ("ClOrdID", cl_ord_id)]);
+        let mut routes = RouteTable::open(file_path, "B").unwrap();
+        routes.outbound(&client, &order("1")).unwrap();
+        routes.outbound(&client, &order("2")).unwrap();
+        drop(routes);
+
+        let mut routes = RouteTable::open(file_path, "B").unwrap();
+        assert!(routes.contains(&client, "1"));
+        let (routed_to, report) = routes
+            .inbound(&msg_map(&[("ClOrdID", "B00000002")]))
+            .unwrap();
+        assert_eq!(routed_to, client);
+        assert_eq!(report["ClOrdID"], "2");
+        // ClOrdIDs at the venue are not used twice
+        assert_eq!(
+            routes.outbound(&client, &order("3")).unwrap()["ClOrdID"],
+            "B00000003"
+        );
+    }
+}
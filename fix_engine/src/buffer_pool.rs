@@ -0,0 +1,111 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+/// The room of a buffer, enough for most messages.
+pub const BUFFER_SIZE: usize = 1024;
+/// Buffers kept for reuse, the others freed when given back.
+const MAX_IDLE_BUFFERS: usize = 64;
+
+/// Buffers of a session reused by its read loop and its outbound queue, so reading and
+/// queueing stop allocating once the session has warmed up. Encoding a message still builds
+/// it as a String, which the queue copies into one of these buffers.
+pub struct BufferPool {
+    idle: Mutex<Vec<Vec<u8>>>,
+    taken: AtomicU64,
+    allocated: AtomicU64,
+    freed: AtomicU64,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    /// Buffers handed out.
+    pub taken: u64,
+    /// Buffers handed out which the pool had to allocate.
+    pub allocated: u64,
+    /// Buffers given back and freed, the pool being full or the buffer grown too large.
+    pub freed: u64,
+    /// Buffers waiting in the pool.
+    pub idle: usize,
+}
+
+impl BufferPool {
+    pub fn new() -> Self {
+        Self {
+            idle: Mutex::new(Vec::new()),
+            taken: AtomicU64::new(0),
+            allocated: AtomicU64::new(0),
+            freed: AtomicU64::new(0),
+        }
+    }
+
+    /// An empty buffer with room for at least BUFFER_SIZE bytes.
+    pub fn take(&self) -> Vec<u8> {
+        self.taken.fetch_add(1, Ordering::SeqCst);
+        if let Some(buffer) = self.idle.lock().unwrap().pop() {
+            return buffer;
+        }
+        self.allocated.fetch_add(1, Ordering::SeqCst);
+        Vec::with_capacity(BUFFER_SIZE)
+    }
+
+    /// Keeps the buffer for the next `take`. One grown for an unusually large message is
+    /// freed instead, not to hold its memory for the life of the session.
+    pub fn give_back(&self, mut buffer: Vec<u8>) {
+        buffer.clear();
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < MAX_IDLE_BUFFERS && buffer.capacity() <= 4 * BUFFER_SIZE {
+            idle.push(buffer);
+        } else {
+            self.freed.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            taken: self.taken.load(Ordering::SeqCst),
+            allocated: self.allocated.load(Ordering::SeqCst),
+            freed: self.freed.load(Ordering::SeqCst),
+            idle: self.idle.lock().unwrap().len(),
+        }
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffers_are_reused() {
+        let pool = BufferPool::new();
+        for _ in 0..3 {
+            let mut buffer = pool.take();
+            buffer.extend_from_slice(b"8=FIX.4.2\x0135=0\x01");
+            pool.give_back(buffer);
+        }
+        assert!(pool.take().is_empty());
+        let stats = pool.stats();
+        assert_eq!((stats.taken, stats.allocated, stats.idle), (4, 1, 0));
+
+        // A buffer grown past four times its size is not kept
+        let mut buffer = pool.take();
+        buffer.resize(5 * BUFFER_SIZE, 0);
+        pool.give_back(buffer);
+        assert_eq!(
+            pool.stats(),
+            BufferPoolStats {
+                taken: 5,
+                allocated: 2,
+                freed: 1,
+                idle: 0,
+            }
+        );
+    }
+}
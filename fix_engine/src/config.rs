@@ -1,20 +1,65 @@
-use log::info;
+use chrono::{NaiveTime, Weekday};
+use chrono_tz::Tz;
+use log::{info, warn};
+use rust_decimal::Decimal;
 use std::collections::HashMap;
+use std::env;
 use std::fs;
 use std::io::{self, Error, ErrorKind};
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
+use crate::auth::{LogonCredentials, LOGON_AUTH};
+use crate::bridge::BridgeConfig;
+use crate::connection::{DuplicateLogonPolicy, DUPLICATE_LOGON_POLICY};
+use crate::counterparty::{SessionBehaviour, SESSION_BEHAVIOURS};
+use crate::execution_store::{ExecutionStore, EXECUTION_STORE};
+#[cfg(feature = "kafka")]
+use crate::kafka::KafkaConfig;
+use crate::order_events::{
+    BlotterSink, OrderEventSink, WebhookSink, ORDER_EVENTS, WEBHOOK_QUEUE_SIZE,
+};
+use crate::session_events::{SessionHooks, SessionLogHooks};
+use crate::order_journal::OrderJournal;
+#[cfg(feature = "sqlite")]
+use crate::order_sqlite::SqliteOrderBackend;
 use crate::orderstore::OrderStore;
-use crate::sequence::SequenceNumberStore;
-use crate::{HEART_BT_INT, IS_INITIATOR, RECONNECT_INTERVAL};
+use crate::positions::PositionSink;
+use crate::proxy::{ProxyConfig, ProxyKind, PROXY};
+use crate::quotes::{QuoteConfig, QUOTE_CONFIG};
+use crate::risk::{PreTradeLimits, ACCOUNT_THROTTLE, PRE_TRADE_LIMITS};
+use crate::routing::{RouteTarget, RoutingRules, ROUTING_RULES};
+use crate::sequence::{FlushPolicy, SequenceStores};
+use crate::console::BATCH_FILE;
+use crate::enrichment::{parse_enrichment, ENRICHMENT};
+use crate::interceptors::{InterceptorChain, INTERCEPTORS};
+use crate::masking::{DEFAULT_MASKED_TAGS, MASKED_TAGS};
+use crate::message_handlers::MESSAGE_HANDLERS;
+use crate::message_journal::{journal_note, MESSAGE_JOURNALS};
+use crate::sbe::{SbeSchema, SBE_SCHEMA};
+use crate::schedule::{DailyReset, SessionSchedule, SESSION_SCHEDULE};
+use crate::sim_clock::{TimestampPrecision, SIM_CLOCK_SKEW_MS, TIMESTAMP_PRECISION};
+use crate::sim_rng::{SimRng, SIM_RNG};
+use crate::simulator::SimulatorConfig;
+use crate::socket_options::{SocketOptions, SOCKET_OPTIONS};
+use crate::symbol_master::{SymbolMaster, SYMBOL_MASTER};
+use crate::throttle::{ThrottleAction, ThrottleConfig, THROTTLE};
+use crate::wire_log::WIRE_LOG_DIR;
+use crate::{
+    BATCH_INTERVAL_MS, DONT_KNOW_TRADE, HEART_BT_INT, IS_INITIATOR, LOGOUT_TIMEOUT,
+    MAX_ACCOUNT_OPEN_QTY, MAX_CONNECTIONS, ORDER_ACK_STATUS_REQUEST, ORDER_ACK_TIMEOUT_MS,
+    OUTBOUND_QUEUE_SIZE, QOS_LOG_INTERVAL, RECONNECT_INTERVAL, SECURITY_LIST_PAGE_SIZE,
+    WATCHDOG_TIMEOUT,
+};
 
 /// Check if the configuration file exists in the specified directory.
 /// Returns the path to the configuration file if it exists, otherwise returns an error.
-pub fn check_config_file_existence(cwd: &PathBuf) -> io::Result<PathBuf> {
+pub fn check_config_file_existence(cwd: &Path) -> io::Result<PathBuf> {
     let config_file_path = cwd.join("config").join("setting.conf");
-    if !fs::metadata(&config_file_path).is_ok() {
+    if fs::metadata(&config_file_path).is_err() {
         return Err(Error::new(
             ErrorKind::NotFound,
             "config/setting.conf file not found.",
@@ -26,7 +71,7 @@ pub fn check_config_file_existence(cwd: &PathBuf) -> io::Result<PathBuf> {
 /// Load the configuration from the specified file path into a nested HashMap.
 /// The outer HashMap's keys are section names, and the inner HashMap's keys are property names.
 pub fn load_config(
-    config_file_path: &PathBuf,
+    config_file_path: &Path,
 ) -> Result<HashMap<String, HashMap<String, String>>, Error> {
     // Check if the configuration file exists
     if !config_file_path.exists() {
@@ -65,52 +110,1084 @@ fn parse_and_update_interval(
     default_value: u64,
     interval: &AtomicU64,
 ) -> io::Result<()> {
-    let interval_str = config_map
-        .get("session")
-        .and_then(|session| session.get(key));
+    let interval_value = parse_interval(config_map, key, default_value)?;
+    interval.store(interval_value, Ordering::SeqCst);
+    info!(">>>>>> Updated {}: {}", key, interval_value);
+    Ok(())
+}
 
-    let interval_value: u64 = match interval_str {
+/// Parse a specified interval from the configuration map, the default value if it is not found.
+fn parse_interval(
+    config_map: &HashMap<String, HashMap<String, String>>,
+    key: &str,
+    default_value: u64,
+) -> io::Result<u64> {
+    match config_map.get("session").and_then(|session| session.get(key)) {
         Some(value) => value.parse().map_err(|e| {
             Error::new(
                 ErrorKind::InvalidData,
                 format!("Failed to parse {}: {}", key, e),
             )
+        }),
+        None => Ok(default_value),
+    }
+}
+
+/// Update the reconnect interval from the configuration map.
+pub fn update_reconnect_interval(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    parse_and_update_interval(config_map, "reconnect_interval", 30, &RECONNECT_INTERVAL)
+}
+
+/// Update the heartbeat interval from the configuration map.
+pub fn update_heart_bt_int(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    parse_and_update_interval(config_map, "heart_bt_int", 15, &HEART_BT_INT)
+}
+
+/// Update the QoS statistics log interval from the configuration map. 0 disables the line.
+pub fn update_qos_log_interval(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    parse_and_update_interval(config_map, "qos_log_interval", 60, &QOS_LOG_INTERVAL)
+}
+
+/// Update the watchdog timeout from the configuration map. 0 disables the watchdog.
+pub fn update_watchdog_timeout(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    parse_and_update_interval(config_map, "watchdog_timeout", 0, &WATCHDOG_TIMEOUT)
+}
+
+/// Update the number of simultaneous connections the acceptor serves. 0 is unlimited.
+pub fn update_max_connections(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    parse_and_update_interval(config_map, "max_connections", 0, &MAX_CONNECTIONS)
+}
+
+/// Update the number of application messages a session queues for its writer thread before
+/// senders have to wait.
+pub fn update_outbound_queue_size(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    parse_and_update_interval(config_map, "outbound_queue_size", 1000, &OUTBOUND_QUEUE_SIZE)
+}
+
+/// Update the open order quantity allowed per account. A breach cancels the account's open
+/// orders and blocks it until cleared. 0 disables the check.
+pub fn update_max_account_open_qty(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    parse_and_update_interval(config_map, "max_account_open_qty", 0, &MAX_ACCOUNT_OPEN_QTY)
+}
+
+/// Journal the messages of each session to a file named after `message_journal` in the
+/// `[session]` section and the session id. Messages are not journaled when the key is absent.
+pub fn update_message_journal(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    if let Some(journal_file) = config_map
+        .get("session")
+        .and_then(|session| session.get("message_journal"))
+    {
+        MESSAGE_JOURNALS.configure(journal_file);
+        info!(">>>>>> Journaling messages to {}", journal_file);
+    }
+    Ok(())
+}
+
+/// Open the execution store configured by `execution_store` in the `[session]` section.
+/// ExecutionReports are not stored when the key is absent.
+pub fn update_execution_store(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    if let Some(store_file) = config_map
+        .get("session")
+        .and_then(|session| session.get("execution_store"))
+    {
+        let store = ExecutionStore::open(store_file)?;
+        ORDER_EVENTS.register(Box::new(PositionSink(store.position_book())));
+        *EXECUTION_STORE.write().unwrap() = Some(Arc::new(store));
+        info!(">>>>>> Storing execution reports in {}", store_file);
+    }
+    Ok(())
+}
+
+/// Load the symbol master configured by `symbol_master` in the `[session]` section. New
+/// orders are not validated against reference data when the key is absent. The symbols
+/// listed in one SecurityDefinition or SecurityList are read from `security_list_page_size`.
+pub fn update_symbol_master(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    if let Some(master_file) = config_map
+        .get("session")
+        .and_then(|session| session.get("symbol_master"))
+    {
+        let master = SymbolMaster::load(master_file)?;
+        if master.is_empty() {
+            warn!("Symbol master {} has no symbols, every order will be rejected", master_file);
+        }
+        info!(">>>>>> Loaded {} symbols from {}", master.len(), master_file);
+        *SYMBOL_MASTER.write().unwrap() = Some(Arc::new(master));
+    }
+    parse_and_update_interval(config_map, "security_list_page_size", 100, &SECURITY_LIST_PAGE_SIZE)
+}
+
+/// Set the directory of the per-session wire logs from `wire_log_dir` in the `[session]`
+/// section. No wire log is written when the key is absent.
+pub fn update_wire_log(config_map: &HashMap<String, HashMap<String, String>>) -> io::Result<()> {
+    let directory = config_map
+        .get("session")
+        .and_then(|session| session.get("wire_log_dir"))
+        .map(PathBuf::from);
+    if let Some(directory) = &directory {
+        info!(">>>>>> Writing wire logs to {}", directory.display());
+    }
+    *WIRE_LOG_DIR.write().unwrap() = directory;
+    Ok(())
+}
+
+/// Set the encoding of the messages on the wire from `encoding` in the `[session]` section:
+/// `fix` (tag=value, default) or `sbe`, with the templates of the SBE XML schema `sbe_schema`.
+pub fn update_encoding(config_map: &HashMap<String, HashMap<String, String>>) -> io::Result<()> {
+    let session = config_map.get("session");
+    let encoding = session
+        .and_then(|session| session.get("encoding"))
+        .map_or("fix", String::as_str);
+    let schema = match encoding {
+        "fix" => None,
+        "sbe" => {
+            let schema_file = session
+                .and_then(|session| session.get("sbe_schema"))
+                .ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "encoding=sbe needs an sbe_schema")
+                })?;
+            let schema = SbeSchema::load(schema_file)?;
+            info!(
+                ">>>>>> Encoding messages with the {} templates of SBE schema {}",
+                schema.template_count(),
+                schema_file
+            );
+            Some(Arc::new(schema))
+        }
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid encoding {}, expected fix or sbe", encoding),
+            ))
+        }
+    };
+    *SBE_SCHEMA.write().unwrap() = schema;
+    Ok(())
+}
+
+/// Update the batch file sent once the session is logged on (`batch_file`) and the pause
+/// between its messages (`batch_interval_ms`, also used by the `batch` command).
+pub fn update_batch(config_map: &HashMap<String, HashMap<String, String>>) -> io::Result<()> {
+    let batch_file = config_map
+        .get("session")
+        .and_then(|session| session.get("batch_file"))
+        .map(PathBuf::from);
+    if let Some(batch_file) = &batch_file {
+        info!(">>>>>> Sending batch file {} after logon", batch_file.display());
+    }
+    *BATCH_FILE.write().unwrap() = batch_file;
+    parse_and_update_interval(config_map, "batch_interval_ms", 0, &BATCH_INTERVAL_MS)
+}
+
+/// Update how long the initiator waits for the answer to an order request
+/// (`order_ack_timeout_ms`, 0 to not wait) and whether it then sends an OrderStatusRequest
+/// (`order_ack_status_request=Y`).
+pub fn update_order_ack_timeout(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    let status_request = config_map
+        .get("session")
+        .and_then(|session| session.get("order_ack_status_request"))
+        .is_some_and(|flag| flag == "Y");
+    ORDER_ACK_STATUS_REQUEST.store(status_request, Ordering::SeqCst);
+    parse_and_update_interval(config_map, "order_ack_timeout_ms", 0, &ORDER_ACK_TIMEOUT_MS)
+}
+
+/// Update whether the initiator answers ExecutionReports for orders it never sent with a
+/// DontKnowTrade: `dont_know_trade=Y`.
+pub fn update_dont_know_trade(config_map: &HashMap<String, HashMap<String, String>>) {
+    let dont_know_trade = config_map
+        .get("session")
+        .and_then(|session| session.get("dont_know_trade"))
+        .is_some_and(|flag| flag == "Y");
+    DONT_KNOW_TRADE.store(dont_know_trade, Ordering::SeqCst);
+}
+
+/// Read `masked_tags` from the `[session]` section, comma-separated tags masked in the logs
+/// besides Password(554) and RawData(96).
+pub fn get_masked_tags(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Vec<u32>> {
+    let mut masked_tags = DEFAULT_MASKED_TAGS.to_vec();
+    if let Some(tags) = config_map
+        .get("session")
+        .and_then(|session| session.get("masked_tags"))
+    {
+        for tag in tags.split(',').map(str::trim).filter(|tag| !tag.is_empty()) {
+            let tag = tag.parse::<u32>().map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("masked_tags: {}: {}", tag, e),
+                )
+            })?;
+            if !masked_tags.contains(&tag) {
+                masked_tags.push(tag);
+            }
+        }
+    }
+    Ok(masked_tags)
+}
+
+/// Update the tags masked in the logs from the configuration map.
+pub fn update_masked_tags(config_map: &HashMap<String, HashMap<String, String>>) -> io::Result<()> {
+    let masked_tags = get_masked_tags(config_map)?;
+    info!(">>>>>> Updated masked_tags: {:?}", masked_tags);
+    *MASKED_TAGS.write().unwrap() = masked_tags;
+    Ok(())
+}
+
+/// Read the address a service of the engine listens on from `key` in the `[session]` section:
+/// `admin_http_address`, `websocket_address`, `order_gateway_address` (the REST order gateway
+/// of the initiator) or `grpc_address`. The service is not served when the key is absent.
+pub fn get_listen_address(
+    config_map: &HashMap<String, HashMap<String, String>>,
+    key: &str,
+) -> io::Result<Option<SocketAddr>> {
+    config_map
+        .get("session")
+        .and_then(|session| session.get(key))
+        .map(|address| {
+            address.parse::<SocketAddr>().map_err(|e| {
+                Error::new(ErrorKind::InvalidData, format!("{}: {}: {}", key, address, e))
+            })
+        })
+        .transpose()
+}
+
+/// Fails when `grpc_address` is set but the engine was built without gRPC.
+#[cfg(not(feature = "grpc"))]
+pub fn check_grpc_address(config_map: &HashMap<String, HashMap<String, String>>) -> io::Result<()> {
+    if config_map
+        .get("session")
+        .is_some_and(|session| session.contains_key("grpc_address"))
+    {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "grpc_address needs the engine built with the grpc feature",
+        ));
+    }
+    Ok(())
+}
+
+/// Read the session schedule from `start_time`/`end_time` (HH:MM:SS), the optional
+/// `start_day`/`end_day` for weekly sessions and `timezone` (IANA name, UTC by default).
+/// Returns None when no start/end time is configured, i.e. the session never closes.
+pub fn get_session_schedule(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Option<SessionSchedule>> {
+    let session = match config_map.get("session") {
+        Some(session) => session,
+        None => return Ok(None),
+    };
+    let invalid = |key: &str, value: &str| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid {} in configuration: {}", key, value),
+        )
+    };
+    let parse_time = |key: &str, value: &String| {
+        NaiveTime::parse_from_str(value, "%H:%M:%S").map_err(|_| invalid(key, value))
+    };
+    let parse_day = |key: &str, value: &String| {
+        value.parse::<Weekday>().map_err(|_| invalid(key, value))
+    };
+
+    let (start_time, end_time) = match (session.get("start_time"), session.get("end_time")) {
+        (None, None) => return Ok(None),
+        (Some(start), Some(end)) => (parse_time("start_time", start)?, parse_time("end_time", end)?),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "start_time and end_time must be configured together.",
+            ))
+        }
+    };
+    let (start_day, end_day) = match (session.get("start_day"), session.get("end_day")) {
+        (None, None) => (None, None),
+        (Some(start), Some(end)) => (
+            Some(parse_day("start_day", start)?),
+            Some(parse_day("end_day", end)?),
+        ),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "start_day and end_day must be configured together.",
+            ))
+        }
+    };
+    let timezone = get_timezone(session)?;
+
+    Ok(Some(SessionSchedule {
+        start_time,
+        end_time,
+        start_day,
+        end_day,
+        timezone,
+    }))
+}
+
+/// Read the daily sequence number reset time `reset_time` (HH:MM:SS, in the session `timezone`).
+/// Returns None when no reset time is configured.
+pub fn get_daily_reset(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Option<DailyReset>> {
+    let session = match config_map.get("session") {
+        Some(session) => session,
+        None => return Ok(None),
+    };
+    let reset_time = match session.get("reset_time") {
+        Some(value) => NaiveTime::parse_from_str(value, "%H:%M:%S").map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid reset_time in configuration: {}", value),
+            )
         })?,
-        None => default_value,
+        None => return Ok(None),
     };
+    let timezone = get_timezone(session)?;
+    Ok(Some(DailyReset {
+        reset_time,
+        timezone,
+    }))
+}
+
+/// Read the IANA `timezone` of the session times, UTC by default.
+fn get_timezone(session: &HashMap<String, String>) -> io::Result<Tz> {
+    match session.get("timezone") {
+        Some(value) => value.parse::<Tz>().map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid timezone in configuration: {}", value),
+            )
+        }),
+        None => Ok(Tz::UTC),
+    }
+}
+
+/// Update the session schedule from the configuration map.
+pub fn update_session_schedule(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    let schedule = get_session_schedule(config_map)?;
+    if let Some(schedule) = &schedule {
+        info!(">>>>>> Updated session schedule: {:?}", schedule);
+    }
+    *SESSION_SCHEDULE.write().unwrap() = schedule;
+    Ok(())
+}
+
+/// Update what the acceptor does with a second connection for a live session:
+/// `duplicate_logon=reject` (default) closes the new connection, `kick` the live one.
+pub fn update_duplicate_logon_policy(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    let policy = match config_map
+        .get("session")
+        .and_then(|session| session.get("duplicate_logon"))
+        .map(|value| value.as_str())
+    {
+        None | Some("reject") => DuplicateLogonPolicy::Reject,
+        Some("kick") => DuplicateLogonPolicy::KickExisting,
+        Some(other) => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unknown duplicate_logon policy: {}", other),
+            ))
+        }
+    };
+    *DUPLICATE_LOGON_POLICY.write().unwrap() = policy;
+    info!(">>>>>> Updated duplicate_logon: {:?}", policy);
+    Ok(())
+}
+
+/// Update how long to wait for the counterparty's Logout confirmation from the configuration map.
+pub fn update_logout_timeout(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    parse_and_update_interval(config_map, "logout_timeout", 10, &LOGOUT_TIMEOUT)
+}
+
+/// Read the credentials sent in our own Logon from the `[session]` section.
+/// The environment variables FIX_USERNAME, FIX_PASSWORD and FIX_RAW_DATA take precedence,
+/// so secrets do not have to be stored in the config file.
+fn get_logon_credentials(config_map: &HashMap<String, HashMap<String, String>>) -> LogonCredentials {
+    let lookup = |env_key: &str, config_key: &str| {
+        env::var(env_key).ok().or_else(|| {
+            config_map
+                .get("session")
+                .and_then(|session| session.get(config_key))
+                .cloned()
+        })
+    };
+    LogonCredentials {
+        username: lookup("FIX_USERNAME", "username"),
+        password: lookup("FIX_PASSWORD", "password"),
+        raw_data: lookup("FIX_RAW_DATA", "raw_data"),
+    }
+}
+
+/// Read the users allowed to log on to the acceptor from the `[logon_users]` section (`name=password`).
+fn get_logon_users(config_map: &HashMap<String, HashMap<String, String>>) -> HashMap<String, String> {
+    config_map
+        .get("logon_users")
+        .map(|users| {
+            users
+                .iter()
+                .map(|(name, password)| (name.to_lowercase(), password.clone()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Update the Logon credentials and the accepted user list from the configuration map.
+pub fn update_logon_auth(config_map: &HashMap<String, HashMap<String, String>>) {
+    let mut logon_auth = LOGON_AUTH.write().unwrap();
+    logon_auth.credentials = get_logon_credentials(config_map);
+    logon_auth.users = get_logon_users(config_map);
+    info!(
+        ">>>>>> Updated logon auth: username={:?}, {} accepted user(s)",
+        logon_auth.credentials.username,
+        logon_auth.users.len()
+    );
+}
+
+/// Seed the simulator randomness from `sim_seed`, or from the clock when it is absent.
+/// The seed is logged and journaled so the run can be reproduced.
+pub fn update_sim_rng(config_map: &HashMap<String, HashMap<String, String>>) -> io::Result<()> {
+    let sim_rng = match config_map
+        .get("session")
+        .and_then(|session| session.get("sim_seed"))
+    {
+        Some(value) => SimRng::new(value.parse().map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Failed to parse sim_seed: {}", e),
+            )
+        })?),
+        None => SimRng::from_clock(),
+    };
+
+    info!(">>>>>> Updated sim_seed: {}", sim_rng.seed());
+    journal_note(&format!("sim_seed={}", sim_rng.seed()));
+    *SIM_RNG.write().unwrap() = Arc::new(sim_rng);
+    Ok(())
+}
+
+/// Shift the SendingTime/TransactTime of outgoing messages by `sim_clock_skew_ms`
+/// milliseconds (negative runs behind), to let clients test their clock-drift checks.
+pub fn update_sim_clock_skew(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    let skew_ms: i64 = match config_map
+        .get("session")
+        .and_then(|session| session.get("sim_clock_skew_ms"))
+    {
+        Some(value) => value.parse().map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Failed to parse sim_clock_skew_ms: {}", e),
+            )
+        })?,
+        None => 0,
+    };
+
+    SIM_CLOCK_SKEW_MS.store(skew_ms, Ordering::SeqCst);
+    info!(">>>>>> Updated sim_clock_skew_ms: {}", skew_ms);
+    Ok(())
+}
+
+/// Set the precision of the SendingTime/TransactTime of outgoing messages from
+/// `timestamp_precision` (seconds, millis, micros or nanos). Milliseconds by default, other
+/// TransactTimes then going out as given.
+pub fn update_timestamp_precision(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    let precision = match config_map
+        .get("session")
+        .and_then(|session| session.get("timestamp_precision"))
+    {
+        Some(value) => Some(
+            TimestampPrecision::parse(value).map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+        ),
+        None => None,
+    };
+    info!(">>>>>> Updated timestamp_precision: {:?}", precision);
+    *TIMESTAMP_PRECISION.write().unwrap() = precision;
+    Ok(())
+}
+
+/// Read the socket options (`tcp_nodelay`, `so_keepalive` as Y/N, `recv_buffer_size` and
+/// `send_buffer_size` in bytes) and the initiator's source address (`socket_local_address`,
+/// `socket_local_port`) from the `[session]` section. Absent options keep the OS defaults.
+pub fn get_socket_options(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<SocketOptions> {
+    let session = config_map.get("session");
+    let get = |key: &str| session.and_then(|session| session.get(key));
+    let buffer_size = |key: &str| -> io::Result<Option<usize>> {
+        get(key)
+            .map(|value| {
+                value.parse().map_err(|e| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Failed to parse {}: {}", key, e),
+                    )
+                })
+            })
+            .transpose()
+    };
+    let local_address = match get("socket_local_address") {
+        Some(address) => {
+            let port = get("socket_local_port").map_or("0", |port| port.as_str());
+            let address = format!("{}:{}", address, port).parse().or_else(|_| {
+                // Literal IPv6 addresses need brackets once a port is appended
+                format!("[{}]:{}", address, port).parse()
+            });
+            Some(address.map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Failed to parse socket_local_address: {}", e),
+                )
+            })?)
+        }
+        None => None,
+    };
+    Ok(SocketOptions {
+        tcp_nodelay: get("tcp_nodelay").map(|value| value.as_str()) == Some("Y"),
+        so_keepalive: get("so_keepalive").map(|value| value.as_str()) == Some("Y"),
+        recv_buffer_size: buffer_size("recv_buffer_size")?,
+        send_buffer_size: buffer_size("send_buffer_size")?,
+        local_address,
+    })
+}
+
+/// Update the options applied to connected and accepted sockets from the configuration map.
+pub fn update_socket_options(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    let socket_options = get_socket_options(config_map)?;
+    info!(">>>>>> Updated socket options: {:?}", socket_options);
+    *SOCKET_OPTIONS.write().unwrap() = socket_options;
+    Ok(())
+}
+
+/// Read the proxy the initiator connects through from the `[proxy]` section (`type` socks5 or
+/// http, `host`, `port` and optional `username`/`password`). Returns None when it is absent.
+pub fn get_proxy_config(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Option<ProxyConfig>> {
+    let section = match config_map.get("proxy") {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let get = |key: &str| {
+        section.get(key).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{} not found in the [proxy] section.", key),
+            )
+        })
+    };
+    let kind = match get("type")?.as_str() {
+        "socks5" => ProxyKind::Socks5,
+        "http" => ProxyKind::HttpConnect,
+        other => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unknown proxy type: {}", other),
+            ))
+        }
+    };
+    Ok(Some(ProxyConfig {
+        kind,
+        host: get("host")?.clone(),
+        port: get("port")?
+            .parse()
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+        username: section.get("username").cloned(),
+        password: section.get("password").cloned(),
+    }))
+}
+
+/// Update the proxy of the initiator from the configuration map.
+pub fn update_proxy(config_map: &HashMap<String, HashMap<String, String>>) -> io::Result<()> {
+    let proxy = get_proxy_config(config_map)?;
+    if let Some(proxy) = &proxy {
+        info!(
+            ">>>>>> Updated proxy: {:?} {}:{}",
+            proxy.kind, proxy.host, proxy.port
+        );
+    }
+    *PROXY.write().unwrap() = proxy;
+    Ok(())
+}
+
+/// Read the venues of bridge mode from the `[bridge]` and `[bridge.<name>]` sections (`host`,
+/// `port`, the `sender_comp_id` and `target_comp_id` of the venue session and an optional
+/// `cl_ord_id_prefix`), the `[bridge]` one first. Returns none when they are absent.
+pub fn get_bridge_configs(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Vec<BridgeConfig>> {
+    let mut sections: Vec<(&String, &HashMap<String, String>)> = config_map
+        .iter()
+        .filter(|(name, _)| *name == "bridge" || name.starts_with("bridge."))
+        .collect();
+    sections.sort_by_key(|(name, _)| (*name != "bridge", *name));
+    let mut bridge_configs = Vec::new();
+    for (name, section) in sections {
+        let get = |key: &str| {
+            section.get(key).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} not found in the [{}] section.", key, name),
+                )
+            })
+        };
+        bridge_configs.push(BridgeConfig {
+            host: get("host")?.clone(),
+            port: get("port")?
+                .parse()
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+            sender_comp_id: get("sender_comp_id")?.clone(),
+            target_comp_id: get("target_comp_id")?.clone(),
+            cl_ord_id_prefix: section
+                .get("cl_ord_id_prefix")
+                .cloned()
+                .unwrap_or_else(|| "B".to_string()),
+            route_store: section
+                .get("route_store")
+                .cloned()
+                .unwrap_or_else(|| "data/routes.json".to_string()),
+        });
+    }
+    Ok(bridge_configs)
+}
+
+/// Read the routing rules of the acceptor from the `[routing]` section (`rule.<n>` tried in
+/// the order of n, and the `default` target). Order requests no rule matches go to the
+/// `[bridge]` venue unless another default is set. Returns None with neither section.
+pub fn get_routing_rules(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Option<RoutingRules>> {
+    let bridge_configs = get_bridge_configs(config_map)?;
+    let default_target = match config_map.get("bridge") {
+        Some(_) => RouteTarget::Venue(bridge_configs[0].target_comp_id.clone()),
+        None => RouteTarget::Internal,
+    };
+    let rules = match config_map.get("routing") {
+        Some(section) => RoutingRules::parse(section, default_target)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+        None if bridge_configs.is_empty() => return Ok(None),
+        None => RoutingRules::new(Vec::new(), default_target),
+    };
+    for venue in rules.venues() {
+        if !bridge_configs.iter().any(|bridge| bridge.target_comp_id == venue) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Routing to {} without a [bridge] section for it.", venue),
+            ));
+        }
+    }
+    Ok(Some(rules))
+}
+
+/// Update the routing rules of the acceptor from the configuration map.
+pub fn update_routing_rules(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    let rules = get_routing_rules(config_map)?.map(Arc::new);
+    info!(">>>>>> Updated routing rules: {:?}", rules);
+    *ROUTING_RULES.write().unwrap() = rules;
+    Ok(())
+}
+
+/// Read the Kafka integration from the `[kafka]` section (`brokers`, `inbound_topic`,
+/// `outbound_topic`, optional `order_topic` and `group_id`). Returns None when it is absent.
+#[cfg(feature = "kafka")]
+pub fn get_kafka_config(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Option<KafkaConfig>> {
+    config_map
+        .get("kafka")
+        .map(KafkaConfig::from_section)
+        .transpose()
+}
+
+/// Fails when the `[kafka]` section is set but the engine was built without Kafka.
+#[cfg(not(feature = "kafka"))]
+pub fn check_kafka_config(config_map: &HashMap<String, HashMap<String, String>>) -> io::Result<()> {
+    if config_map.contains_key("kafka") {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "[kafka] needs the engine built with the kafka feature",
+        ));
+    }
+    Ok(())
+}
+
+/// Read the outbound throttle from the `session` section: `throttle_rate` application messages
+/// per second, `throttle_burst` (defaults to the rate) and `throttle_action` delay (default)
+/// or reject. Returns None when no rate is set.
+pub fn get_throttle_config(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Option<ThrottleConfig>> {
+    let session = match config_map.get("session") {
+        Some(session) => session,
+        None => return Ok(None),
+    };
+    let parse = |key: &str| {
+        session
+            .get(key)
+            .map(|value| {
+                value
+                    .parse::<u32>()
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", key, e)))
+            })
+            .transpose()
+    };
+    let messages_per_second = match parse("throttle_rate")? {
+        Some(rate) if rate > 0 => rate,
+        _ => return Ok(None),
+    };
+    let action = match session.get("throttle_action").map(|value| value.as_str()) {
+        None | Some("delay") => ThrottleAction::Delay,
+        Some("reject") => ThrottleAction::Reject,
+        Some(other) => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unknown throttle_action: {}", other),
+            ))
+        }
+    };
+    Ok(Some(ThrottleConfig {
+        messages_per_second,
+        burst: parse("throttle_burst")?.unwrap_or(messages_per_second),
+        action,
+    }))
+}
+
+/// Update the outbound throttle from the configuration map.
+pub fn update_throttle(config_map: &HashMap<String, HashMap<String, String>>) -> io::Result<()> {
+    let throttle = get_throttle_config(config_map)?;
+    if let Some(throttle) = &throttle {
+        info!(
+            ">>>>>> Updated throttle: {} messages/s, burst {}, {:?}",
+            throttle.messages_per_second, throttle.burst, throttle.action
+        );
+    }
+    *THROTTLE.write().unwrap() = throttle;
+    Ok(())
+}
+
+/// Read the `[simulator]` section, which makes the acceptor fill the orders it acknowledges:
+/// `fill_ratio` of the quantity (default 1.0) in `fills` executions (default 1), each after
+/// `latency_ms` (`<min>-<max>` or a fixed value, default 0), and reject orders with
+/// `reject_probability` (default 0.0). Returns None without the section.
+pub fn get_simulator_config(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Option<SimulatorConfig>> {
+    let simulator = match config_map.get("simulator") {
+        Some(simulator) => simulator,
+        None => return Ok(None),
+    };
+    let invalid = |key: &str, value: &str| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid simulator {}: {}", key, value),
+        )
+    };
+    let probability = |key: &str, default: f64| match simulator.get(key) {
+        Some(value) => value
+            .parse::<f64>()
+            .ok()
+            .filter(|probability| (0.0..=1.0).contains(probability))
+            .ok_or_else(|| invalid(key, value)),
+        None => Ok(default),
+    };
+    let fills = match simulator.get("fills") {
+        Some(value) => value
+            .parse::<u32>()
+            .ok()
+            .filter(|fills| *fills > 0)
+            .ok_or_else(|| invalid("fills", value))?,
+        None => 1,
+    };
+    let latency_ms = match simulator.get("latency_ms") {
+        Some(value) => {
+            let (min, max) = value.split_once('-').unwrap_or((value, value));
+            match (min.trim().parse::<u64>(), max.trim().parse::<u64>()) {
+                (Ok(min), Ok(max)) if min <= max => (min, max),
+                _ => return Err(invalid("latency_ms", value)),
+            }
+        }
+        None => (0, 0),
+    };
+    Ok(Some(SimulatorConfig {
+        fill_ratio: probability("fill_ratio", 1.0)?,
+        fills,
+        latency_ms,
+        reject_probability: probability("reject_probability", 0.0)?,
+    }))
+}
+
+/// Read how the messages of a counterparty are validated and its orders simulated:
+/// - `reject_undefined_tags=Y` rejects the tags missing from the dictionary, custom tags
+///   included, which pass through by default;
+/// - `max_latency_seconds` is how far from our clock the SendingTime of an inbound message may
+///   be (default 0, unchecked) and `max_latency_violations` after how many such messages in a
+///   row the session is logged out (default 3);
+/// - `max_consecutive_rejects` session Rejects in a row halt the order flow (default 0, never);
+/// - the `[simulator]` section.
+pub fn get_session_behaviour(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<SessionBehaviour> {
+    Ok(SessionBehaviour {
+        reject_undefined_tags: config_map
+            .get("session")
+            .and_then(|session| session.get("reject_undefined_tags"))
+            .is_some_and(|flag| flag == "Y"),
+        max_latency_seconds: parse_interval(config_map, "max_latency_seconds", 0)?,
+        max_latency_violations: parse_interval(config_map, "max_latency_violations", 3)?,
+        max_consecutive_rejects: parse_interval(config_map, "max_consecutive_rejects", 0)?,
+        simulator: get_simulator_config(config_map)?,
+    })
+}
+
+/// Update the behaviour of the sessions with the counterparty `comp_id` from its profile, or
+/// with every other counterparty from the `[session]` settings for "".
+pub fn update_session_behaviour(
+    comp_id: &str,
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    let behaviour = get_session_behaviour(config_map)?;
+    info!(">>>>>> Updated session behaviour of {:?}: {:?}", comp_id, behaviour);
+    SESSION_BEHAVIOURS
+        .write()
+        .unwrap()
+        .insert(comp_id.to_lowercase(), Arc::new(behaviour));
+    Ok(())
+}
+
+/// Read the `[risk]` section of pre-trade limits: `max_order_qty`, `max_notional`,
+/// `max_open_orders` per account and `price_band_pct` around the `reference_prices` of the
+/// symbols (`<symbol>:<price>,...`). An absent key disables its check, None without the section.
+pub fn get_pre_trade_limits(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Option<PreTradeLimits>> {
+    let risk = match config_map.get("risk") {
+        Some(risk) => risk,
+        None => return Ok(None),
+    };
+    let invalid = |key: &str, value: &str| {
+        Error::new(ErrorKind::InvalidData, format!("Invalid risk {}: {}", key, value))
+    };
+    let limit = |key: &str| {
+        risk.get(key)
+            .map(|value| {
+                value
+                    .parse::<Decimal>()
+                    .ok()
+                    .filter(|limit| !limit.is_sign_negative())
+                    .ok_or_else(|| invalid(key, value))
+            })
+            .transpose()
+    };
+    let mut reference_prices = HashMap::new();
+    for entry in risk.get("reference_prices").iter().flat_map(|value| value.split(',')) {
+        let (symbol, price) = entry
+            .split_once(':')
+            .and_then(|(symbol, price)| Some((symbol, price.trim().parse::<Decimal>().ok()?)))
+            .ok_or_else(|| invalid("reference_prices", entry))?;
+        reference_prices.insert(symbol.trim().to_string(), price);
+    }
+    let max_open_orders = match risk.get("max_open_orders") {
+        Some(value) => Some(
+            value
+                .parse::<usize>()
+                .map_err(|_| invalid("max_open_orders", value))?,
+        ),
+        None => None,
+    };
+    Ok(Some(PreTradeLimits {
+        max_order_qty: limit("max_order_qty")?,
+        max_notional: limit("max_notional")?,
+        price_band_pct: limit("price_band_pct")?,
+        reference_prices,
+        max_open_orders,
+    }))
+}
 
-    interval.store(interval_value, Ordering::SeqCst);
-    info!(">>>>>> Updated {}: {}", key, interval_value);
+/// Update the pre-trade limits from the configuration map.
+pub fn update_pre_trade_limits(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    let limits = get_pre_trade_limits(config_map)?;
+    if let Some(limits) = &limits {
+        info!(">>>>>> Updated pre-trade limits: {:?}", limits);
+    }
+    *PRE_TRADE_LIMITS.write().unwrap() = limits;
     Ok(())
 }
 
-/// Update the reconnect interval from the configuration map.
-pub fn update_reconnect_interval(
+/// Read the orders a second each account may send, `max_orders_per_second` in `[risk]`, and
+/// how many at once after a quiet period, `orders_burst` (default the rate). None when absent.
+pub fn get_account_throttle_config(
     config_map: &HashMap<String, HashMap<String, String>>,
-) -> io::Result<()> {
-    parse_and_update_interval(config_map, "reconnect_interval", 30, &RECONNECT_INTERVAL)
+) -> io::Result<Option<ThrottleConfig>> {
+    let risk = match config_map.get("risk") {
+        Some(risk) => risk,
+        None => return Ok(None),
+    };
+    let parse = |key: &str| {
+        risk.get(key)
+            .map(|value| {
+                value
+                    .parse::<u32>()
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", key, e)))
+            })
+            .transpose()
+    };
+    let messages_per_second = match parse("max_orders_per_second")? {
+        Some(rate) if rate > 0 => rate,
+        _ => return Ok(None),
+    };
+    Ok(Some(ThrottleConfig {
+        messages_per_second,
+        burst: parse("orders_burst")?.unwrap_or(messages_per_second),
+        action: ThrottleAction::Reject,
+    }))
 }
 
-/// Update the heartbeat interval from the configuration map.
-pub fn update_heart_bt_int(
+/// Update the per-account order throttle from the configuration map.
+pub fn update_account_throttle(
     config_map: &HashMap<String, HashMap<String, String>>,
 ) -> io::Result<()> {
-    parse_and_update_interval(config_map, "heart_bt_int", 15, &HEART_BT_INT)
+    let throttle = get_account_throttle_config(config_map)?;
+    if let Some(throttle) = &throttle {
+        info!(
+            ">>>>>> Updated account throttle: {} orders/s, burst {}",
+            throttle.messages_per_second, throttle.burst
+        );
+    }
+    ACCOUNT_THROTTLE.configure(throttle);
+    Ok(())
+}
+
+/// Read how the acceptor answers QuoteRequests from the `[quotes]` section: `spread_pct`
+/// between bid and offer (default 0.1) and `validity_secs` until a quote expires (default 30).
+/// None without the section, QuoteRequests are then rejected.
+pub fn get_quote_config(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Option<QuoteConfig>> {
+    let quotes = match config_map.get("quotes") {
+        Some(quotes) => quotes,
+        None => return Ok(None),
+    };
+    let invalid = |key: &str, value: &str| {
+        Error::new(ErrorKind::InvalidData, format!("Invalid quotes {}: {}", key, value))
+    };
+    let spread_pct = match quotes.get("spread_pct") {
+        Some(value) => value
+            .parse::<Decimal>()
+            .ok()
+            .filter(|spread| !spread.is_sign_negative())
+            .ok_or_else(|| invalid("spread_pct", value))?,
+        None => Decimal::new(1, 1),
+    };
+    let validity_secs = match quotes.get("validity_secs") {
+        Some(value) => value
+            .parse::<u64>()
+            .ok()
+            .filter(|secs| *secs > 0)
+            .ok_or_else(|| invalid("validity_secs", value))?,
+        None => 30,
+    };
+    Ok(Some(QuoteConfig {
+        spread_pct,
+        validity: Duration::from_secs(validity_secs),
+    }))
 }
 
+/// Update the quoting of QuoteRequests from the configuration map.
+pub fn update_quotes(config_map: &HashMap<String, HashMap<String, String>>) -> io::Result<()> {
+    let config = get_quote_config(config_map)?;
+    if let Some(config) = &config {
+        info!(
+            ">>>>>> Updated quotes: spread {}%, valid for {}s",
+            config.spread_pct,
+            config.validity.as_secs()
+        );
+    }
+    *QUOTE_CONFIG.write().unwrap() = config;
+    Ok(())
+}
+
+/// Create the per-session sequence number stores. `sequence_store` names the base file,
+/// each session persists to its own file derived from it.
 pub fn get_sequence_store(
     config_map: &HashMap<String, HashMap<String, String>>,
-) -> Arc<SequenceNumberStore> {
+) -> Arc<SequenceStores> {
     let sequence_file = config_map
         .get("session")
         .and_then(|session| session.get("sequence_store"))
-        .ok_or_else(|| {
-            Error::new(
-                ErrorKind::Other,
-                "sequence_store not found in configuration.",
-            )
-        });
-    Arc::new(SequenceNumberStore::new(sequence_file.unwrap()))
+        .ok_or_else(|| Error::other("sequence_store not found in configuration."));
+    Arc::new(SequenceStores::new(sequence_file.unwrap()))
+}
+
+/// Read when sequence number increments are written: `sequence_flush_every` increments
+/// (default 1, write-through), every `sequence_flush_interval` milliseconds (default 0, off)
+/// and whether to fsync (`sequence_fsync`, default Y). Turning both off is an error.
+pub fn get_sequence_flush_policy(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<FlushPolicy> {
+    let session = config_map.get("session");
+    let parse = |key: &str, default_value: u64| -> io::Result<u64> {
+        match session.and_then(|session| session.get(key)) {
+            Some(value) => value.parse().map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Failed to parse {}: {}", key, e),
+                )
+            }),
+            None => Ok(default_value),
+        }
+    };
+
+    let every_n = parse("sequence_flush_every", 1)?;
+    let interval = Duration::from_millis(parse("sequence_flush_interval", 0)?);
+    if every_n == 0 && interval.is_zero() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "sequence_flush_every=0 needs a sequence_flush_interval, the store would never flush",
+        ));
+    }
+    Ok(FlushPolicy {
+        every_n,
+        interval,
+        fsync: session
+            .and_then(|session| session.get("sequence_fsync"))
+            .map(|flag| flag != "N")
+            .unwrap_or(true),
+    })
 }
 
 pub fn get_order_store(
@@ -119,12 +1196,213 @@ pub fn get_order_store(
     let order_store_file = config_map
         .get("session")
         .and_then(|session| session.get("order_store"))
-        .ok_or_else(|| Error::new(ErrorKind::Other, "order_store not found in configuration."))?;
+        .ok_or_else(|| Error::other("order_store not found in configuration."))?;
 
-    let order_store = OrderStore::new(order_store_file, 1024)?;
+    let session = config_map.get("session");
+    let parse = |key: &str, default_value: usize| -> Result<usize, Error> {
+        match session.and_then(|session| session.get(key)) {
+            Some(value) => value.parse().map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Failed to parse {}: {}", key, e),
+                )
+            }),
+            None => Ok(default_value),
+        }
+    };
+    let initial_size = parse("order_store_initial_size", 1 << 20)?;
+    let max_size = match parse("order_store_max_size", 0)? {
+        0 => usize::MAX,
+        max_size => max_size,
+    };
+
+    let backend = session
+        .and_then(|session| session.get("order_store_backend"))
+        .map(String::as_str)
+        .unwrap_or("mmap");
+    let order_store = match backend {
+        "mmap" => {
+            let (mut journal, orders) = OrderJournal::open(order_store_file, initial_size)?;
+            journal.set_max_size(max_size);
+            OrderStore::with_backend(Box::new(journal), orders)
+        }
+        #[cfg(feature = "sqlite")]
+        "sqlite" => {
+            let (backend, orders) = SqliteOrderBackend::open(order_store_file)?;
+            OrderStore::with_backend(Box::new(backend), orders)
+        }
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "order_store_backend sqlite needs the engine built with the sqlite feature",
+            ))
+        }
+        other => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown order_store_backend {}", other),
+            ))
+        }
+    };
     Ok(Arc::new(order_store))
 }
 
+/// Build the configuration of every `[counterparty.X]` section for the multi-tenant acceptor.
+/// Each profile is a copy of the whole configuration whose `[session]` keys are overridden by the
+/// counterparty section, and whose `[simulator]` is replaced by `[simulator.X]` if present, keyed
+/// by the (lower-cased) SenderCompID X.
+pub fn get_counterparty_configs(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> HashMap<String, HashMap<String, HashMap<String, String>>> {
+    let mut profiles = HashMap::new();
+    for (section, overrides) in config_map {
+        let sender_comp_id = match section.strip_prefix("counterparty.") {
+            Some(sender_comp_id) if !sender_comp_id.is_empty() => sender_comp_id,
+            _ => continue,
+        };
+
+        let mut profile_config = config_map.clone();
+        let session = profile_config.entry("session".to_string()).or_default();
+        for (key, value) in overrides {
+            session.insert(key.clone(), value.clone());
+        }
+        if let Some(simulator) = config_map.get(&format!("simulator.{}", sender_comp_id)) {
+            profile_config.insert("simulator".to_string(), simulator.clone());
+        }
+        info!(">>>>>> Loaded counterparty profile: {}", sender_comp_id);
+        profiles.insert(sender_comp_id.to_string(), profile_config);
+    }
+    profiles
+}
+
+/// Create the order event sinks listed in the `[order_events]` section (e.g. `sinks=blotter,webhook`).
+/// Returns an empty list when the section is absent.
+pub fn get_order_event_sinks(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Vec<Box<dyn OrderEventSink>>> {
+    let section = match config_map.get("order_events") {
+        Some(section) => section,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut sinks: Vec<Box<dyn OrderEventSink>> = Vec::new();
+    for sink_name in section
+        .get("sinks")
+        .map(|s| s.as_str())
+        .unwrap_or("")
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+    {
+        match sink_name {
+            "blotter" => sinks.push(Box::new(BlotterSink)),
+            "webhook" => {
+                let url = section.get("webhook_url").ok_or_else(|| {
+                    Error::other("webhook_url not found in configuration.")
+                })?;
+                let queue_size = match section.get("webhook_queue_size") {
+                    Some(value) => value.parse::<usize>().map_err(|_| {
+                        Error::new(
+                            ErrorKind::InvalidData,
+                            format!("Invalid webhook_queue_size: {}", value),
+                        )
+                    })?,
+                    None => WEBHOOK_QUEUE_SIZE,
+                };
+                sinks.push(Box::new(WebhookSink::new(url, queue_size)?));
+            }
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Unknown order event sink: {}", sink_name),
+                ))
+            }
+        }
+    }
+    Ok(sinks)
+}
+
+/// Create the session hooks listed in the `[session_hooks]` section (e.g. `hooks=log`).
+/// Returns an empty list when the section is absent.
+pub fn get_session_hooks(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<Vec<Box<dyn SessionHooks>>> {
+    let mut hooks: Vec<Box<dyn SessionHooks>> = Vec::new();
+    for hooks_name in config_map
+        .get("session_hooks")
+        .and_then(|section| section.get("hooks"))
+        .map(|s| s.as_str())
+        .unwrap_or("")
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+    {
+        match hooks_name {
+            "log" => hooks.push(Box::new(SessionLogHooks)),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Unknown session hooks: {}", hooks_name),
+                ))
+            }
+        }
+    }
+    Ok(hooks)
+}
+
+/// Register the handlers of the `[message_handlers]` section, one per message name (e.g.
+/// `NEWS=ignore`), run before the built-in handling of the message.
+pub fn update_message_handlers(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    for (msgtype, action) in config_map.get("message_handlers").into_iter().flatten() {
+        MESSAGE_HANDLERS
+            .register_action(msgtype, action)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    }
+    Ok(())
+}
+
+/// Set the fields always set on the messages sent from the `[enrichment]` section, one entry
+/// per message name (e.g. `New_Order_Single=HandlInst=1,SenderSubID=DESK1`).
+pub fn update_enrichment(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    let mut enrichment = HashMap::new();
+    for (msgtype, fields) in config_map.get("enrichment").into_iter().flatten() {
+        let fields = parse_enrichment(fields)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("[enrichment]: {}", e)))?;
+        info!(">>>>>> Enriching {} with {:?}", msgtype, fields);
+        enrichment.insert(msgtype.to_lowercase(), fields);
+    }
+    *ENRICHMENT.write().unwrap() = enrichment;
+    Ok(())
+}
+
+/// Set the interceptors of the sessions from the `[interceptors]` section, for every session,
+/// and the `[interceptors.<CompID>]` sections, replacing it for the sessions with CompID.
+pub fn update_interceptors(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> io::Result<()> {
+    let mut interceptors = HashMap::new();
+    for (section, rules) in config_map {
+        let comp_id = match section.as_str() {
+            "interceptors" => "",
+            section => match section.strip_prefix("interceptors.") {
+                Some(comp_id) if !comp_id.is_empty() => comp_id,
+                _ => continue,
+            },
+        };
+        let chain = InterceptorChain::parse(rules)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("[{}]: {}", section, e)))?;
+        info!(">>>>>> Loaded the interceptors of [{}]", section);
+        interceptors.insert(comp_id.to_lowercase(), Arc::new(chain));
+    }
+    *INTERCEPTORS.write().unwrap() = interceptors;
+    Ok(())
+}
+
 /// Get connection details (host and port) from the configuration map.
 /// Determines the connection type (initiator or acceptor) and retrieves the corresponding host and port.
 pub fn get_connection_details(
@@ -134,35 +1412,35 @@ pub fn get_connection_details(
         let host_str = config_map
             .get("session")
             .and_then(|session| session.get("socket_connect_host"))
-            .ok_or_else(|| Error::new(ErrorKind::Other, "Host not found in configuration."))?;
+            .ok_or_else(|| Error::other("Host not found in configuration."))?;
 
         let port_str = config_map
             .get("session")
             .and_then(|session| session.get("socket_connect_port"))
-            .ok_or_else(|| Error::new(ErrorKind::Other, "Port not found in configuration."))?;
+            .ok_or_else(|| Error::other("Port not found in configuration."))?;
 
         (
             host_str,
             port_str
                 .parse()
-                .map_err(|e| Error::new(ErrorKind::Other, e))?,
+                .map_err(Error::other)?,
         )
     } else {
         let host_str = config_map
             .get("session")
             .and_then(|session| session.get("socket_accept_address"))
-            .ok_or_else(|| Error::new(ErrorKind::Other, "Host not found in configuration."))?;
+            .ok_or_else(|| Error::other("Host not found in configuration."))?;
 
         let port_str = config_map
             .get("session")
             .and_then(|session| session.get("socket_accept_port"))
-            .ok_or_else(|| Error::new(ErrorKind::Other, "Port not found in configuration."))?;
+            .ok_or_else(|| Error::other("Port not found in configuration."))?;
 
         (
             host_str,
             port_str
                 .parse()
-                .map_err(|e| Error::new(ErrorKind::Other, e))?,
+                .map_err(Error::other)?,
         )
     };
     Ok((host, port))
@@ -187,6 +1465,69 @@ pub fn enable_cmd_line(config_map: &HashMap<String, HashMap<String, String>>) ->
         .unwrap_or(false)
 }
 
+/// Determine if sequence numbers are reset on Logon (`reset_on_logon=Y` in the `[session]` section).
+pub fn reset_on_logon(config_map: &HashMap<String, HashMap<String, String>>) -> bool {
+    config_map
+        .get("session")
+        .and_then(|session| session.get("reset_on_logon"))
+        .map(|flag| flag == "Y")
+        .unwrap_or(false)
+}
+
+/// The `[session]` keys of the session identity, with the header field each sets.
+const SESSION_IDENTITY: [(&str, &str); 6] = [
+    ("sender_comp_id", "SenderCompID"),
+    ("target_comp_id", "TargetCompID"),
+    ("sender_sub_id", "SenderSubID"),
+    ("target_sub_id", "TargetSubID"),
+    ("on_behalf_of_comp_id", "OnBehalfOfCompID"),
+    ("on_behalf_of_sub_id", "OnBehalfOfSubID"),
+];
+
+/// Get the CompIDs, SubIDs and on-behalf-of IDs configured in the `[session]` section, as
+/// header fields set on every message sent whatever the predefined messages say.
+pub fn get_session_identity(
+    config_map: &HashMap<String, HashMap<String, String>>,
+) -> Vec<(String, String)> {
+    let session = config_map.get("session");
+    SESSION_IDENTITY
+        .iter()
+        .filter_map(|(key, field)| {
+            let value = session?.get(*key)?;
+            Some((field.to_string(), value.clone()))
+        })
+        .collect()
+}
+
+/// Get the path of the predefined messages (`predefined_msg` in the `[session]` section).
+pub fn get_predefined_msg_path(config_map: &HashMap<String, HashMap<String, String>>) -> &str {
+    config_map
+        .get("session")
+        .and_then(|session| session.get("predefined_msg"))
+        .map(|path| path.as_str())
+        .unwrap_or("reference/predefined_msg.json")
+}
+
+/// Determine if the predefined messages and the `[enrichment]` section are reloaded when their
+/// file changes (`hot_reload=Y` in the `[session]` section).
+pub fn hot_reload(config_map: &HashMap<String, HashMap<String, String>>) -> bool {
+    config_map
+        .get("session")
+        .and_then(|session| session.get("hot_reload"))
+        .map(|flag| flag == "Y")
+        .unwrap_or(false)
+}
+
+/// Determine if the acceptor matches its clients' orders against each other
+/// (`matching_engine=Y` in the `[session]` section).
+pub fn matching_engine(config_map: &HashMap<String, HashMap<String, String>>) -> bool {
+    config_map
+        .get("session")
+        .and_then(|session| session.get("matching_engine"))
+        .map(|flag| flag == "Y")
+        .unwrap_or(false)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -264,6 +1605,35 @@ mod tests {
         assert_eq!(interval.load(Ordering::SeqCst), 30);
     }
 
+    #[test]
+    fn test_get_logon_credentials() {
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([
+                (String::from("username"), String::from("trader1")),
+                (String::from("raw_data"), String::from("token42")),
+            ]),
+        )]);
+        let credentials = get_logon_credentials(&config);
+        if env::var("FIX_USERNAME").is_err() {
+            assert_eq!(credentials.username.as_deref(), Some("trader1"));
+        }
+        if env::var("FIX_RAW_DATA").is_err() {
+            assert_eq!(credentials.raw_data.as_deref(), Some("token42"));
+        }
+    }
+
+    #[test]
+    fn test_get_logon_users() {
+        let config = HashMap::from([(
+            String::from("logon_users"),
+            HashMap::from([(String::from("Trader1"), String::from("Secret"))]),
+        )]);
+        let users = get_logon_users(&config);
+        assert_eq!(users.get("trader1").unwrap(), "Secret");
+        assert!(get_logon_users(&HashMap::new()).is_empty());
+    }
+
     #[test]
     fn test_get_sequence_store() {
         let config = HashMap::from([(
@@ -277,6 +1647,203 @@ mod tests {
         assert!(Arc::strong_count(&store) > 0);
     }
 
+    #[test]
+    fn test_get_sequence_flush_policy() {
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([
+                (String::from("sequence_flush_every"), String::from("100")),
+                (String::from("sequence_flush_interval"), String::from("250")),
+                (String::from("sequence_fsync"), String::from("N")),
+            ]),
+        )]);
+        let policy = get_sequence_flush_policy(&config).unwrap();
+        assert_eq!(policy.every_n, 100);
+        assert_eq!(policy.interval, Duration::from_millis(250));
+        assert!(!policy.fsync);
+
+        assert_eq!(
+            get_sequence_flush_policy(&HashMap::new()).unwrap(),
+            FlushPolicy::default()
+        );
+
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([(String::from("sequence_flush_every"), String::from("0"))]),
+        )]);
+        assert!(get_sequence_flush_policy(&config).is_err());
+    }
+
+    #[test]
+    fn test_get_socket_options() {
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([
+                (String::from("tcp_nodelay"), String::from("Y")),
+                (String::from("recv_buffer_size"), String::from("65536")),
+            ]),
+        )]);
+        let options = get_socket_options(&config).unwrap();
+        assert!(options.tcp_nodelay);
+        assert!(!options.so_keepalive);
+        assert_eq!(options.recv_buffer_size, Some(65536));
+        assert_eq!(options.send_buffer_size, None);
+        assert_eq!(options.local_address, None);
+
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([
+                (String::from("socket_local_address"), String::from("::1")),
+                (String::from("socket_local_port"), String::from("40000")),
+            ]),
+        )]);
+        let options = get_socket_options(&config).unwrap();
+        assert_eq!(options.local_address, Some("[::1]:40000".parse().unwrap()));
+
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([(String::from("send_buffer_size"), String::from("big"))]),
+        )]);
+        assert!(get_socket_options(&config).is_err());
+    }
+
+    #[test]
+    fn test_get_bridge_configs_and_routing_rules() {
+        assert!(get_bridge_configs(&HashMap::new()).unwrap().is_empty());
+        assert_eq!(get_routing_rules(&HashMap::new()).unwrap(), None);
+        let venue = |target_comp_id: &str| {
+            HashMap::from([
+                ("host".to_string(), "venue.example.com".to_string()),
+                ("port".to_string(), "9880".to_string()),
+                ("sender_comp_id".to_string(), "HUB".to_string()),
+                ("target_comp_id".to_string(), target_comp_id.to_string()),
+            ])
+        };
+        let mut config = HashMap::from([
+            ("bridge.dark".to_string(), venue("DARK")),
+            ("bridge".to_string(), venue("LIT")),
+        ]);
+        let bridge_configs = get_bridge_configs(&config).unwrap();
+        assert_eq!(bridge_configs[0].target_comp_id, "LIT");
+        assert_eq!(bridge_configs[1].port, 9880);
+        assert_eq!(bridge_configs[1].cl_ord_id_prefix, "B");
+        assert_eq!(bridge_configs[1].route_store, "data/routes.json");
+        assert_eq!(
+            get_routing_rules(&config).unwrap(),
+            Some(RoutingRules::new(
+                Vec::new(),
+                RouteTarget::Venue("LIT".to_string())
+            ))
+        );
+
+        config.insert(
+            "routing".to_string(),
+            HashMap::from([("rule.1".to_string(), "Symbol=IBM -> OTHER".to_string())]),
+        );
+        assert!(get_routing_rules(&config).is_err());
+        config.get_mut("bridge").unwrap().remove("target_comp_id");
+        assert!(get_bridge_configs(&config).is_err());
+    }
+
+    #[test]
+    fn test_get_proxy_config() {
+        assert_eq!(get_proxy_config(&HashMap::new()).unwrap(), None);
+
+        let mut proxy = HashMap::from([
+            (String::from("type"), String::from("socks5")),
+            (String::from("host"), String::from("proxy.local")),
+            (String::from("port"), String::from("1080")),
+            (String::from("username"), String::from("user")),
+        ]);
+        let config = HashMap::from([(String::from("proxy"), proxy.clone())]);
+        let proxy_config = get_proxy_config(&config).unwrap().unwrap();
+        assert_eq!(proxy_config.kind, ProxyKind::Socks5);
+        assert_eq!(proxy_config.port, 1080);
+        assert_eq!(proxy_config.username.as_deref(), Some("user"));
+        assert_eq!(proxy_config.password, None);
+
+        proxy.insert(String::from("type"), String::from("ftp"));
+        let config = HashMap::from([(String::from("proxy"), proxy)]);
+        assert!(get_proxy_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_get_throttle_config() {
+        assert_eq!(get_throttle_config(&HashMap::new()).unwrap(), None);
+
+        let mut session = HashMap::from([(String::from("throttle_rate"), String::from("50"))]);
+        let config = HashMap::from([(String::from("session"), session.clone())]);
+        let throttle = get_throttle_config(&config).unwrap().unwrap();
+        assert_eq!(throttle.burst, 50);
+        assert_eq!(throttle.action, ThrottleAction::Delay);
+
+        session.insert(String::from("throttle_action"), String::from("reject"));
+        session.insert(String::from("throttle_burst"), String::from("5"));
+        let config = HashMap::from([(String::from("session"), session.clone())]);
+        let throttle = get_throttle_config(&config).unwrap().unwrap();
+        assert_eq!((throttle.burst, throttle.action), (5, ThrottleAction::Reject));
+
+        session.insert(String::from("throttle_action"), String::from("drop"));
+        let config = HashMap::from([(String::from("session"), session)]);
+        assert!(get_throttle_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_get_simulator_config() {
+        assert_eq!(get_simulator_config(&HashMap::new()).unwrap(), None);
+
+        let mut simulator = HashMap::from([
+            (String::from("fill_ratio"), String::from("0.5")),
+            (String::from("fills"), String::from("3")),
+            (String::from("latency_ms"), String::from("10-250")),
+        ]);
+        let config = HashMap::from([(String::from("simulator"), simulator.clone())]);
+        assert_eq!(
+            get_simulator_config(&config).unwrap(),
+            Some(SimulatorConfig {
+                fill_ratio: 0.5,
+                fills: 3,
+                latency_ms: (10, 250),
+                reject_probability: 0.0,
+            })
+        );
+
+        simulator.insert(String::from("reject_probability"), String::from("1.5"));
+        let config = HashMap::from([(String::from("simulator"), simulator)]);
+        assert!(get_simulator_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_get_masked_tags() {
+        assert_eq!(get_masked_tags(&HashMap::new()).unwrap(), vec![554, 96]);
+
+        let mut session =
+            HashMap::from([(String::from("masked_tags"), String::from("553, 96,1"))]);
+        let config = HashMap::from([(String::from("session"), session.clone())]);
+        assert_eq!(get_masked_tags(&config).unwrap(), vec![554, 96, 553, 1]);
+
+        session.insert(String::from("masked_tags"), String::from("Password"));
+        let config = HashMap::from([(String::from("session"), session)]);
+        assert!(get_masked_tags(&config).is_err());
+    }
+
+    #[test]
+    fn test_get_listen_address() {
+        let mut session =
+            HashMap::from([(String::from("websocket_address"), String::from("127.0.0.1:9001"))]);
+        let config = HashMap::from([(String::from("session"), session.clone())]);
+        assert_eq!(
+            get_listen_address(&config, "websocket_address").unwrap(),
+            Some("127.0.0.1:9001".parse().unwrap())
+        );
+        assert_eq!(get_listen_address(&config, "admin_http_address").unwrap(), None);
+
+        session.insert(String::from("admin_http_address"), String::from("localhost"));
+        let config = HashMap::from([(String::from("session"), session)]);
+        let e = get_listen_address(&config, "admin_http_address").unwrap_err();
+        assert!(e.to_string().starts_with("admin_http_address: localhost"));
+    }
+
     #[test]
     fn test_get_order_store() {
         let config = HashMap::from([(
@@ -290,6 +1857,29 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_get_order_event_sinks() {
+        let config = HashMap::from([(
+            String::from("order_events"),
+            HashMap::from([
+                (String::from("sinks"), String::from("blotter, webhook")),
+                (String::from("webhook_url"), String::from("http://127.0.0.1:8080/orders")),
+            ]),
+        )]);
+        let sinks = get_order_event_sinks(&config).unwrap();
+        assert_eq!(sinks.len(), 2);
+        assert_eq!(sinks[0].name(), "blotter");
+    }
+
+    #[test]
+    fn test_get_order_event_sinks_unknown_sink() {
+        let config = HashMap::from([(
+            String::from("order_events"),
+            HashMap::from([(String::from("sinks"), String::from("blotter,carrier_pigeon"))]),
+        )]);
+        assert!(get_order_event_sinks(&config).is_err());
+    }
+
     #[test]
     fn test_get_connection_details_initiator() {
         IS_INITIATOR.store(true, Ordering::SeqCst);
@@ -369,4 +1959,146 @@ mod tests {
         let result = enable_cmd_line(&config);
         assert!(!result);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_reset_on_logon() {
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([(String::from("reset_on_logon"), String::from("Y"))]),
+        )]);
+        assert!(reset_on_logon(&config));
+        assert!(!reset_on_logon(&HashMap::new()));
+    }
+
+    #[test]
+    fn test_get_counterparty_configs() {
+        let config = HashMap::from([
+            (
+                String::from("session"),
+                HashMap::from([
+                    (String::from("sequence_store"), String::from("data/sequence.json")),
+                    (String::from("heart_bt_int"), String::from("30")),
+                ]),
+            ),
+            (
+                String::from("counterparty.xyzexchange"),
+                HashMap::from([(
+                    String::from("sequence_store"),
+                    String::from("data/xyz_sequence.json"),
+                )]),
+            ),
+        ]);
+
+        let profiles = get_counterparty_configs(&config);
+        assert_eq!(profiles.len(), 1);
+        let session = profiles["xyzexchange"].get("session").unwrap();
+        assert_eq!(session.get("sequence_store").unwrap(), "data/xyz_sequence.json");
+        assert_eq!(session.get("heart_bt_int").unwrap(), "30");
+        // The base configuration is left untouched
+        assert_eq!(
+            config["session"].get("sequence_store").unwrap(),
+            "data/sequence.json"
+        );
+    }
+
+    #[test]
+    fn test_get_session_behaviour_per_counterparty() {
+        let section = |entries: &[(&str, &str)]| -> HashMap<String, String> {
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        let config = HashMap::from([
+            (String::from("session"), section(&[("max_latency_seconds", "5")])),
+            (String::from("simulator"), section(&[("fills", "2")])),
+            (
+                String::from("counterparty.xyzexchange"),
+                section(&[("max_latency_seconds", "0"), ("reject_undefined_tags", "Y")]),
+            ),
+            (String::from("simulator.xyzexchange"), section(&[("reject_probability", "1.0")])),
+        ]);
+
+        let behaviour = get_session_behaviour(&config).unwrap();
+        assert_eq!(behaviour.max_latency_seconds, 5);
+        assert!(!behaviour.reject_undefined_tags);
+        assert_eq!(behaviour.simulator.unwrap().fills, 2);
+
+        let profiles = get_counterparty_configs(&config);
+        let behaviour = get_session_behaviour(&profiles["xyzexchange"]).unwrap();
+        assert_eq!(behaviour.max_latency_seconds, 0);
+        assert_eq!(behaviour.max_latency_violations, 3);
+        assert!(behaviour.reject_undefined_tags);
+        let simulator = behaviour.simulator.unwrap();
+        assert_eq!((simulator.fills, simulator.reject_probability), (1, 1.0));
+    }
+
+    #[test]
+    fn test_get_counterparty_configs_without_profiles() {
+        let config = HashMap::from([(String::from("session"), HashMap::new())]);
+        assert!(get_counterparty_configs(&config).is_empty());
+    }
+
+    #[test]
+    fn test_get_session_schedule() {
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([
+                (String::from("start_time"), String::from("17:00:00")),
+                (String::from("end_time"), String::from("17:00:00")),
+                (String::from("start_day"), String::from("sunday")),
+                (String::from("end_day"), String::from("Friday")),
+                (String::from("timezone"), String::from("America/New_York")),
+            ]),
+        )]);
+
+        let schedule = get_session_schedule(&config).unwrap().unwrap();
+        assert_eq!(schedule.start_day, Some(Weekday::Sun));
+        assert_eq!(schedule.end_day, Some(Weekday::Fri));
+        assert_eq!(schedule.timezone, chrono_tz::America::New_York);
+    }
+
+    #[test]
+    fn test_get_session_schedule_invalid() {
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([
+                (String::from("start_time"), String::from("9am")),
+                (String::from("end_time"), String::from("17:00:00")),
+            ]),
+        )]);
+        assert!(get_session_schedule(&config).is_err());
+
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([(String::from("start_time"), String::from("09:00:00"))]),
+        )]);
+        assert!(get_session_schedule(&config).is_err());
+
+        let config = HashMap::from([(String::from("session"), HashMap::new())]);
+        assert!(get_session_schedule(&config).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_get_daily_reset() {
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([
+                (String::from("reset_time"), String::from("17:00:00")),
+                (String::from("timezone"), String::from("America/New_York")),
+            ]),
+        )]);
+        let daily_reset = get_daily_reset(&config).unwrap().unwrap();
+        assert_eq!(
+            daily_reset.reset_time,
+            NaiveTime::from_hms_opt(17, 0, 0).unwrap()
+        );
+        assert_eq!(daily_reset.timezone, chrono_tz::America::New_York);
+
+        let config = HashMap::from([(
+            String::from("session"),
+            HashMap::from([(String::from("reset_time"), String::from("midnight"))]),
+        )]);
+        assert!(get_daily_reset(&config).is_err());
+    }
+}
@@ -1,48 +1,378 @@
-use std::io::Write;
-use std::net::{TcpListener, TcpStream};
-use std::sync::atomic::Ordering;
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, Once, RwLock};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::{io, process, thread};
 
-use chrono::Utc;
+use indexmap::IndexMap;
 use log::{error, info};
 
 use crate::{
-    message_converter::{fixmap2fixmsg, fixmsg2msgtype, msgtype2fixmsg},
+    admin_http::{AdminSession, ADMIN_SESSIONS},
+    auth::{admin_msg_with_credentials, LOGON_AUTH},
+    buffer_pool::BufferPool,
+    console::{
+        order_message, parse_command, quote_request_message, read_batch_file, same_message_name,
+        Command, SeqDirection, BATCH_FILE, HELP,
+    },
+    counterparty::{find_sender_comp_id, peek_logon, CounterpartyProfiles},
+    execution_store::EXECUTION_STORE,
+    fixml::fixml_to_fix,
+    interceptors::with_interceptors,
+    kill_switch::{engage_kill_switch, KILL_SWITCH},
+    message_converter::{msgtype2fixmsg, restamp_seq_num},
     message_handling::{
-        client_session_thread, read_and_route_messages, send_message, venue_session_thread,
+        client_session_thread, initiate_logout, read_and_route_messages, send_sequenced,
+        venue_session_thread, ReaderShared,
     },
-    orderstore::OrderStore,
+    logging::{session_span, Direction},
+    masking::mask_fields,
+    message_journal::journal_sent,
+    message_validator::{describe_errors, FixMessage},
+    news::broadcast_news,
+    orderstore::{orders_table, OrderKey, OrderStore},
+    outbound::{OutboundQueue, QueuedTransport},
     parse_xml::print_fix_message,
-    sequence::SequenceNumberStore,
-    MessageMap, ENABLE_CMD_LINE, HEART_BT_INT, LAST_SENT_TIME, RECEIVED_LOGON, SENT_LOGON,
+    pending_orders::start_ack_timer,
+    positions::positions_table,
+    proxy::{ProxyConfig, PROXY},
+    quotes::{quotes_table, QUOTES},
+    risk::ACCOUNT_RISK,
+    sbe::with_sbe_codec,
+    schedule::{is_session_closed, SESSION_SCHEDULE},
+    sequence::{SequenceNumberStore, SessionId},
+    session::{Session, SessionState, SESSION_STATES},
+    session_stats::SessionStats,
+    transport::Transport,
+    socket_options::{apply_socket_options, connect_from, SOCKET_OPTIONS},
+    throttle::THROTTLE,
+    timer::TIMERS,
+    trading_session::publish_trading_session_status,
+    watchdog::{start_watchdog, SessionActivity},
+    wire_log::with_wire_log,
+    MessageMap, BATCH_INTERVAL_MS, ENABLE_CMD_LINE, HEART_BT_INT, IS_INITIATOR,
+    MAX_CONNECTIONS, OUTBOUND_QUEUE_SIZE, QOS_LOG_INTERVAL, RESET_ON_LOGON,
+    WATCHDOG_TIMEOUT,
 };
 
-type TcpStreamArcMutex = Arc<Mutex<TcpStream>>;
+type TransportArcMutex = Arc<Mutex<Box<dyn Transport>>>;
+
+lazy_static! {
+    pub static ref LIVE_SESSIONS: SessionRegistry = SessionRegistry::default();
+    pub static ref CONNECTIONS: ConnectionLimiter = ConnectionLimiter::default();
+    pub static ref DUPLICATE_LOGON_POLICY: RwLock<DuplicateLogonPolicy> =
+        RwLock::new(DuplicateLogonPolicy::Reject);
+    /// The session the commands typed on the console go to, the one connected last.
+    static ref CONSOLE_SESSION: RwLock<Option<Arc<ConsoleSession>>> = RwLock::new(None);
+}
+
+/// Starts the console thread, once per process whatever the number of connections.
+static CONSOLE: Once = Once::new();
+
+/// A session as the console sees it, with the orders it queries.
+struct ConsoleSession {
+    session: Session,
+    order_store: Arc<OrderStore>,
+}
+
+/// Counts the connections served by the acceptor against MAX_CONNECTIONS.
+#[derive(Default)]
+pub struct ConnectionLimiter {
+    active: AtomicU64,
+    /// Connections closed because the limit was reached, since startup.
+    refused: AtomicU64,
+}
+
+/// A connection counted by the limiter, released when dropped.
+pub struct ConnectionSlot<'a> {
+    limiter: &'a ConnectionLimiter,
+}
+
+impl ConnectionLimiter {
+    /// Takes a slot, unless `max_connections` (0 = unlimited) are already active.
+    pub fn try_acquire(&self, max_connections: u64) -> Option<ConnectionSlot<'_>> {
+        let acquired = self
+            .active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+                (max_connections == 0 || active < max_connections).then_some(active + 1)
+            })
+            .is_ok();
+        if acquired {
+            Some(ConnectionSlot { limiter: self })
+        } else {
+            self.refused.fetch_add(1, Ordering::SeqCst);
+            None
+        }
+    }
+
+    pub fn refused(&self) -> u64 {
+        self.refused.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for ConnectionSlot<'_> {
+    fn drop(&mut self) {
+        self.limiter.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// What the acceptor does when a second connection arrives for a session which is still live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateLogonPolicy {
+    /// Close the new connection, the live session carries on.
+    Reject,
+    /// Close the live connection and continue with the new one, once its Logon authenticates.
+    KickExisting,
+}
+
+/// Live acceptor sessions by SessionID, so two connections never share a session and its
+/// sequence numbers.
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: Mutex<HashMap<SessionId, (u64, TcpStream)>>,
+    next_token: AtomicU64,
+}
+
+/// Registration of a live session, removed from the registry when dropped.
+pub struct LiveSession<'a> {
+    registry: &'a SessionRegistry,
+    session_id: SessionId,
+    token: u64,
+}
+
+impl SessionRegistry {
+    /// Registers the connection as the session. Returns None when the connection was closed
+    /// because the session is already live and the policy rejects duplicates.
+    pub fn register(
+        &self,
+        session_id: &SessionId,
+        stream: &TcpStream,
+        policy: DuplicateLogonPolicy,
+    ) -> io::Result<Option<LiveSession<'_>>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some((_, live_stream)) = sessions.get(session_id) {
+            match policy {
+                DuplicateLogonPolicy::Reject => {
+                    error!(
+                        "Session {} is already live, closing the new connection",
+                        session_id
+                    );
+                    stream.shutdown(Shutdown::Both)?;
+                    return Ok(None);
+                }
+                DuplicateLogonPolicy::KickExisting => {
+                    info!(
+                        "Session {} reconnected, closing the stale connection",
+                        session_id
+                    );
+                    if let Err(e) = live_stream.shutdown(Shutdown::Both) {
+                        error!("Failed to close the stale connection: {}", e);
+                    }
+                }
+            }
+        }
+
+        let token = self.next_token.fetch_add(1, Ordering::SeqCst);
+        sessions.insert(session_id.clone(), (token, stream.try_clone()?));
+        Ok(Some(LiveSession {
+            registry: self,
+            session_id: session_id.clone(),
+            token,
+        }))
+    }
+}
+
+impl Drop for LiveSession<'_> {
+    fn drop(&mut self) {
+        let mut sessions = self.registry.sessions.lock().unwrap();
+        // A kicked connection must not remove the session which replaced it
+        if sessions.get(&self.session_id).map(|(token, _)| *token) == Some(self.token) {
+            sessions.remove(&self.session_id);
+        }
+    }
+}
+
+/// Delay before the next address is raced when the host resolves to several addresses.
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+/// How long a proxy may stay silent while it opens the tunnel.
+const PROXY_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 
-/// Establishes a connection to the target IP and port.
+/// Establishes a connection to the target host and port. A host with several addresses
+/// (A and AAAA records) is connected happy-eyeballs style: the addresses are tried in order,
+/// a new attempt starting every CONNECTION_ATTEMPT_DELAY, and the first to connect wins.
+/// When a proxy is configured the connection is made to the proxy, which opens the tunnel to
+/// the target before the Logon is sent.
 pub fn establish_connection(target_ip: &str, port: u16) -> Result<TcpStream, io::Error> {
-    let stream = TcpStream::connect((target_ip, port)).map_err(|e| {
-        error!("Failed to connect to server: {}", e);
-        e
-    })?;
-    let address = format!("{}:{}", target_ip, port);
-    info!("Connected to {}", address);
+    let local_address = SOCKET_OPTIONS.read().unwrap().local_address;
+    let proxy = PROXY.read().unwrap().clone();
+    let (connect_host, connect_port) = match &proxy {
+        Some(proxy) => (proxy.host.as_str(), proxy.port),
+        None => (target_ip, port),
+    };
+    let mut stream = resolve_addresses(connect_host, connect_port)
+        .and_then(|addresses| {
+            // A bound socket can only reach addresses of its own family
+            let addresses = addresses
+                .into_iter()
+                .filter(|remote| {
+                    !matches!(local_address, Some(local) if local.is_ipv4() != remote.is_ipv4())
+                })
+                .collect();
+            connect_first(addresses, local_address)
+        })
+        .map_err(|e| {
+            error!("Failed to connect to server {}: {}", connect_host, e);
+            e
+        })?;
+    if let Some(proxy) = &proxy {
+        open_tunnel(proxy, &mut stream, target_ip, port, PROXY_HANDSHAKE_TIMEOUT).map_err(|e| {
+            error!("Failed to tunnel to {}:{} through the proxy: {}", target_ip, port, e);
+            e
+        })?;
+        info!("Tunneled to {}:{} through {:?} proxy", target_ip, port, proxy.kind);
+    }
+    info!(
+        "Connected to {} from {}",
+        stream.peer_addr()?,
+        stream.local_addr()?
+    );
+    apply_socket_options(&stream);
     Ok(stream)
 }
 
+/// Opens the tunnel through the proxy, failing once the proxy leaves a read or write of the
+/// handshake waiting longer than `timeout`. The session itself reads without a timeout.
+fn open_tunnel(
+    proxy: &ProxyConfig,
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let result = proxy.open_tunnel(stream, host, port);
+    stream.set_read_timeout(None)?;
+    stream.set_write_timeout(None)?;
+    result
+}
+
+/// Resolves a host name or literal address (IPv6 optionally in brackets), alternating the
+/// address families starting with the family of the first result.
+fn resolve_addresses(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<SocketAddr> = (host, port).to_socket_addrs()?.collect();
+    Ok(interleave_families(addresses))
+}
+
+fn interleave_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(first) = addresses.first() else {
+        return addresses;
+    };
+    let first_is_ipv4 = first.is_ipv4();
+    let (preferred, other): (Vec<_>, Vec<_>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv4() == first_is_ipv4);
+    let mut preferred = preferred.into_iter();
+    let mut other = other.into_iter();
+    let mut interleaved = Vec::new();
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return interleaved,
+            (first, second) => interleaved.extend(first.into_iter().chain(second)),
+        }
+    }
+}
+
+/// Races connection attempts to the addresses, staggered by CONNECTION_ATTEMPT_DELAY.
+fn connect_first(
+    addresses: Vec<SocketAddr>,
+    local_address: Option<SocketAddr>,
+) -> io::Result<TcpStream> {
+    let connect = move |remote: SocketAddr| match local_address {
+        Some(local_address) => connect_from(local_address, remote),
+        None => TcpStream::connect(remote),
+    };
+    if addresses.len() == 1 {
+        return connect(addresses[0]);
+    }
+
+    let connected = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    for (index, remote) in addresses.into_iter().enumerate() {
+        let sender = sender.clone();
+        let connected = Arc::clone(&connected);
+        thread::spawn(move || {
+            sleep(CONNECTION_ATTEMPT_DELAY * index as u32);
+            // An earlier address already connected, no need to open another connection
+            if connected.load(Ordering::SeqCst) {
+                return;
+            }
+            let _ = sender.send((remote, connect(remote)));
+        });
+    }
+    drop(sender);
+
+    let mut last_error =
+        io::Error::new(io::ErrorKind::AddrNotAvailable, "no address to connect to");
+    for (remote, result) in receiver {
+        match result {
+            Ok(stream) => {
+                connected.store(true, Ordering::SeqCst);
+                return Ok(stream);
+            }
+            Err(e) => {
+                info!("Failed to connect to {}: {}", remote, e);
+                last_error = e;
+            }
+        }
+    }
+    Err(last_error)
+}
+
 pub fn handle_stream(
-    mut stream: TcpStream,
-    all_msg_map_collection: &MessageMap,
+    stream: Box<dyn Transport>,
+    session_id: &SessionId,
+    all_msg_map_collection: &Arc<MessageMap>,
     seq_store: Arc<SequenceNumberStore>,
     order_store: Arc<OrderStore>,
 ) -> io::Result<()> {
-    let client_session_stream = stream.try_clone()?;
-    let venue_session_stream = stream.try_clone()?;
-    let input_stream = Arc::new(Mutex::new(stream.try_clone()?));
-    let tick_stream = Arc::new(Mutex::new(stream.try_clone()?));
+    // Every write of the session goes through the outbound queue and its writer thread
+    let buffers = Arc::new(BufferPool::new());
+    let (queued_stream, outbound_queue, writer_handle) =
+        session_span(session_id, Direction::Outbound).in_scope(|| {
+            QueuedTransport::start(
+                stream,
+                OUTBOUND_QUEUE_SIZE.load(Ordering::SeqCst) as usize,
+                *THROTTLE.read().unwrap(),
+                Arc::clone(&buffers),
+            )
+        })?;
+    let mut stream: Box<dyn Transport> = Box::new(queued_stream);
+    let client_session_stream = stream.try_clone_transport()?;
+    let venue_session_stream = stream.try_clone_transport()?;
+    let input_stream = Arc::new(Mutex::new(stream.try_clone_transport()?));
+    let batch_stream = Arc::new(Mutex::new(stream.try_clone_transport()?));
+    let tick_stream = Arc::new(Mutex::new(stream.try_clone_transport()?));
+    let ack_stream = Arc::new(Mutex::new(stream.try_clone_transport()?));
+    let stats = Arc::new(SessionStats::new(&stream.peer(), &seq_store));
+    let activity = Arc::new(SessionActivity::new());
+    let _admin_registration = ADMIN_SESSIONS.register(AdminSession {
+        session_id: session_id.clone(),
+        seq_store: Arc::clone(&seq_store),
+        stream: Arc::new(Mutex::new(stream.try_clone_transport()?)),
+        activity: Arc::clone(&activity),
+        message_maps: Arc::clone(all_msg_map_collection),
+        order_store: Arc::clone(&order_store),
+        buffers: Arc::clone(&buffers),
+    });
+    start_watchdog(
+        stream.try_clone_transport()?,
+        Arc::clone(&activity),
+        Arc::clone(&seq_store),
+    );
 
     let client_session_handle = thread::spawn(move || {
         client_session_thread(client_session_stream);
@@ -52,127 +382,390 @@ pub fn handle_stream(
         venue_session_thread(venue_session_stream);
     });
 
-    let all_msg_map_collection_clone = all_msg_map_collection.clone();
+    let all_msg_map_collection_clone = Arc::clone(all_msg_map_collection);
     let seq_store_clone = Arc::clone(&seq_store);
     let order_store_clone = Arc::clone(&order_store);
+    let reader_shared = ReaderShared {
+        stats: Arc::clone(&stats),
+        activity: Arc::clone(&activity),
+        buffers,
+    };
+    let session_id_clone = session_id.clone();
+    let inbound_span = session_span(session_id, Direction::Inbound);
     let read_and_route_handle = thread::spawn(move || {
+        let _entered = inbound_span.entered();
         let _ = read_and_route_messages(
-            &mut stream,
+            stream.as_mut(),
+            &session_id_clone,
             &all_msg_map_collection_clone,
             seq_store_clone,
             order_store_clone,
+            &reader_shared,
         );
+        reader_shared.activity.close();
     });
 
-    let all_msg_map_collection_clone2 = all_msg_map_collection.clone();
-    let seq_store_clone = Arc::clone(&seq_store);
-    let tick_handle = thread::spawn(move || {
-        run_periodic_task(tick_stream, all_msg_map_collection_clone2, seq_store_clone);
-    });
-
-    if ENABLE_CMD_LINE.load(Ordering::SeqCst) {
-        handle_cmd_line(input_stream, all_msg_map_collection, seq_store)?;
+    if IS_INITIATOR.load(Ordering::SeqCst) {
+        start_ack_timer(
+            Session::new(
+                session_id.clone(),
+                ack_stream,
+                Arc::clone(all_msg_map_collection),
+                Arc::clone(&seq_store),
+            ),
+            Arc::clone(&activity),
+        );
     }
 
-    tick_handle.join().unwrap();
+    schedule_session_timer(
+        tick_stream,
+        SESSION_STATES.for_session(session_id),
+        Arc::clone(all_msg_map_collection),
+        Arc::clone(&seq_store),
+        stats,
+        activity,
+        Arc::clone(&outbound_queue),
+    );
+
+    start_configured_batch(Session::new(
+        session_id.clone(),
+        batch_stream,
+        Arc::clone(all_msg_map_collection),
+        Arc::clone(&seq_store),
+    ));
+
+    // The console outlives the connection, so a scheduled session reopens while it waits for
+    // input; it is handed over to the next connection
+    let console_session = ENABLE_CMD_LINE.load(Ordering::SeqCst).then(|| {
+        let console_session = Arc::new(ConsoleSession {
+            session: Session::new(
+                session_id.clone(),
+                input_stream,
+                Arc::clone(all_msg_map_collection),
+                Arc::clone(&seq_store),
+            ),
+            order_store: Arc::clone(&order_store),
+        });
+        *CONSOLE_SESSION.write().unwrap() = Some(Arc::clone(&console_session));
+        CONSOLE.call_once(|| {
+            thread::spawn(run_console);
+        });
+        console_session
+    });
+
     read_and_route_handle.join().unwrap();
+    if let Some(console_session) = console_session {
+        let mut current = CONSOLE_SESSION.write().unwrap();
+        if current
+            .as_ref()
+            .is_some_and(|current| Arc::ptr_eq(current, &console_session))
+        {
+            *current = None;
+        }
+    }
     client_session_handle.join().unwrap();
     venue_session_handle.join().unwrap();
+    outbound_queue.close();
+    writer_handle.join().unwrap();
+    seq_store.flush();
 
     Ok(())
 }
 
-fn run_periodic_task(
-    stream: TcpStreamArcMutex,
-    all_msg_map_collection: MessageMap,
+/// Schedules the heartbeat and QoS report of the session on the shared timer, which wakes
+/// up at their deadlines rather than polling every second.
+fn schedule_session_timer(
+    stream: TransportArcMutex,
+    session_state: Arc<SessionState>,
+    all_msg_map_collection: Arc<MessageMap>,
     seq_store: Arc<SequenceNumberStore>,
+    stats: Arc<SessionStats>,
+    activity: Arc<SessionActivity>,
+    outbound_queue: Arc<OutboundQueue>,
 ) {
-    let interval = Duration::from_secs(1);
-    loop {
-        sleep(interval);
-        if let Err(e) = check_interval(stream.clone(), &all_msg_map_collection, &seq_store) {
+    let mut last_qos_report = Instant::now();
+    let first_deadline = next_timer_deadline(
+        last_qos_report,
+        &session_state,
+        &activity,
+        heart_bt_int(),
+    );
+    TIMERS.schedule(first_deadline, move || {
+        activity.touch_heartbeat();
+        // No more heartbeats once the session is logging out or gone
+        if session_state.is_logout_sent() || activity.is_closed() {
+            return None;
+        }
+        let qos_log_interval = QOS_LOG_INTERVAL.load(Ordering::SeqCst);
+        if qos_log_interval > 0
+            && last_qos_report.elapsed() >= Duration::from_secs(qos_log_interval)
+        {
+            stats.record_queue_depth(outbound_queue.depth());
+            info!("{}", stats.take_report(&seq_store));
+            last_qos_report = Instant::now();
+        }
+        match check_received(
+            &stream,
+            &session_state,
+            &all_msg_map_collection,
+            &seq_store,
+            &activity,
+        ) {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(e) => error!("Failed to send a TestRequest: {}", e),
+        }
+        if let Err(e) = check_interval(
+            stream.clone(),
+            &session_state,
+            &all_msg_map_collection,
+            &seq_store,
+        ) {
             error!("Failed to perform periodic task: {}", e);
-            process::exit(1);
+            if IS_INITIATOR.load(Ordering::SeqCst) {
+                seq_store.flush();
+                process::exit(1);
+            }
+            // The acceptor keeps serving other clients, only this connection is gone
+            return None;
+        }
+        Some(next_timer_deadline(
+            last_qos_report,
+            &session_state,
+            &activity,
+            heart_bt_int(),
+        ))
+    });
+}
+
+fn heart_bt_int() -> Duration {
+    Duration::from_secs(HEART_BT_INT.load(Ordering::SeqCst))
+}
+
+/// How often the session timer wakes up without heartbeats, to notice the session ended.
+const IDLE_TIMER_PERIOD: Duration = Duration::from_secs(1);
+
+/// How long nothing may be received before a TestRequest, and again before disconnecting:
+/// HeartBtInt with a fifth more for the transmission. None without heartbeats.
+fn test_request_delay(heart_bt_int: Duration) -> Option<Duration> {
+    (!heart_bt_int.is_zero()).then(|| heart_bt_int + heart_bt_int / 5)
+}
+
+/// Sends a TestRequest once the logged on counterparty is silent for the test request delay,
+/// and disconnects once it stays silent as long again. Returns false once disconnected.
+fn check_received(
+    stream: &TransportArcMutex,
+    session_state: &SessionState,
+    all_msg_map_collection: &MessageMap,
+    seq_store: &Arc<SequenceNumberStore>,
+    activity: &SessionActivity,
+) -> Result<bool, io::Error> {
+    let Some(delay) = test_request_delay(heart_bt_int()) else {
+        return Ok(true);
+    };
+    if !session_state.is_logon_received() {
+        return Ok(true);
+    }
+    let idle = activity.reader_idle();
+    if activity.is_test_request_pending() {
+        if idle >= delay * 2 {
+            error!(
+                "Nothing received for {} seconds, TestRequest unanswered, disconnecting",
+                idle.as_secs()
+            );
+            stream.lock().unwrap().close()?;
+            return Ok(false);
         }
+    } else if idle >= delay {
+        let mut override_map: HashMap<String, String> = HashMap::new();
+        override_map.insert(
+            "TestReqID".to_string(),
+            format!("TEST{}", seq_store.get_outgoing()),
+        );
+        let fix_msg = msgtype2fixmsg(
+            "Test_Request".to_string(),
+            &all_msg_map_collection.admin_msg.current(),
+            &all_msg_map_collection.fix_tag_name_map,
+            Some(&override_map),
+            seq_store.get_outgoing(),
+        );
+        send_sequenced(stream, seq_store, fix_msg.replace("|", "\x01"))?;
+        activity.test_request_sent();
+        info!(
+            "Nothing received for {} seconds, TestRequest sent",
+            idle.as_secs()
+        );
+    }
+    Ok(true)
+}
+
+/// The earliest of the next heartbeat, the next TestRequest or disconnect for want of
+/// messages, the next QoS report and, with a watchdog, half its timeout so the heartbeat
+/// component keeps showing progress. Without heartbeats (HeartBtInt=0) IDLE_TIMER_PERIOD
+/// at the latest.
+fn next_timer_deadline(
+    last_qos_report: Instant,
+    session_state: &SessionState,
+    activity: &SessionActivity,
+    heart_bt_int: Duration,
+) -> Instant {
+    let now = Instant::now();
+    let mut deadline = if heart_bt_int.is_zero() {
+        now + IDLE_TIMER_PERIOD
+    } else {
+        now + heart_bt_int.saturating_sub(session_state.sender_idle())
+    };
+
+    if let Some(delay) = test_request_delay(heart_bt_int) {
+        let silence = if activity.is_test_request_pending() {
+            delay * 2
+        } else {
+            delay
+        };
+        deadline = deadline.min(now + silence.saturating_sub(activity.reader_idle()));
+    }
+
+    let qos_log_interval = QOS_LOG_INTERVAL.load(Ordering::SeqCst);
+    if qos_log_interval > 0 {
+        deadline = deadline.min(last_qos_report + Duration::from_secs(qos_log_interval));
     }
+    let watchdog_timeout = WATCHDOG_TIMEOUT.load(Ordering::SeqCst);
+    if watchdog_timeout > 0 {
+        deadline = deadline.min(now + Duration::from_millis(watchdog_timeout * 500));
+    }
+    deadline
 }
 
 fn check_interval(
-    stream: TcpStreamArcMutex,
+    stream: TransportArcMutex,
+    session_state: &SessionState,
     all_msg_map_collection: &MessageMap,
     seq_store: &Arc<SequenceNumberStore>,
 ) -> Result<(), io::Error> {
-    let now = Utc::now();
-    let elapsed = now
-        .signed_duration_since(LAST_SENT_TIME.load(Ordering::SeqCst))
-        .num_seconds();
-    let heart_bt_int = HEART_BT_INT.load(Ordering::SeqCst) as i64;
-
-    if elapsed >= heart_bt_int {
-        perform_task(stream.clone(), all_msg_map_collection.clone(), seq_store)?;
+    let heart_bt_int = heart_bt_int();
+    if !heart_bt_int.is_zero() && session_state.sender_idle() >= heart_bt_int {
+        perform_task(stream.clone(), session_state, all_msg_map_collection, seq_store)?;
     }
 
     Ok(())
 }
 
 fn perform_task(
-    stream: TcpStreamArcMutex,
-    all_msg_map_collection: MessageMap,
+    stream: TransportArcMutex,
+    session_state: &SessionState,
+    all_msg_map_collection: &MessageMap,
     seq_store: &Arc<SequenceNumberStore>,
 ) -> Result<(), io::Error> {
-    let msgtype = if !RECEIVED_LOGON.load(Ordering::SeqCst) {
+    let msgtype = if !session_state.is_logon_received() {
         "Logon"
     } else {
         "Heartbeat"
     };
 
-    let response = msgtype2fixmsg(
-        msgtype.to_string(),
-        &all_msg_map_collection.admin_msg,
-        &all_msg_map_collection.fix_tag_name_map,
-        None,
-        seq_store.get_outgoing(),
-    );
-
-    let modified_response = response.replace("|", "\x01");
-    send_message(&stream, modified_response)?;
-    seq_store.increment_outgoing();
-
-    LAST_SENT_TIME.store(Utc::now(), Ordering::SeqCst);
-    info!("{} message sent, updated last sent time", msgtype);
+    let modified_response = if msgtype == "Logon" {
+        build_logon_message(all_msg_map_collection, seq_store.clone(), false)
+    } else {
+        msgtype2fixmsg(
+            msgtype.to_string(),
+            &all_msg_map_collection.admin_msg.current(),
+            &all_msg_map_collection.fix_tag_name_map,
+            None,
+            seq_store.get_outgoing(),
+        )
+        .replace("|", "\x01")
+    };
+    send_sequenced(&stream, seq_store, modified_response)?;
+    info!("{} message sent", msgtype);
 
     Ok(())
 }
 
+/// The configured duplicate Logon policy, except that a live session is only kicked for a
+/// Logon which authenticates: any other connection is closed instead.
+fn duplicate_logon_policy(logon: &[u8]) -> DuplicateLogonPolicy {
+    match *DUPLICATE_LOGON_POLICY.read().unwrap() {
+        DuplicateLogonPolicy::KickExisting => {
+            match LOGON_AUTH.read().unwrap().authenticate_raw(logon) {
+                Ok(()) => DuplicateLogonPolicy::KickExisting,
+                Err(reason) => {
+                    info!("{}, the live session is kept", reason);
+                    DuplicateLogonPolicy::Reject
+                }
+            }
+        }
+        policy => policy,
+    }
+}
+
 /// Starts the TCP listener on the specified host and port, accepting incoming connections.
-pub fn start_listener(
-    host: &str,
-    port: u16,
-    all_msg_map_collection: Arc<MessageMap>,
-    seq_store: Arc<SequenceNumberStore>,
-    order_store: Arc<OrderStore>,
-) -> io::Result<()> {
-    let address = format!("{}:{}", host, port);
-    let listener = TcpListener::bind(&address).map_err(|e| {
-        eprintln!("Failed to start listener at {address}: {e}");
-        e
-    })?;
-    info!("Listening on {}", address);
+pub fn start_listener(host: &str, port: u16, profiles: Arc<CounterpartyProfiles>) -> io::Result<()> {
+    let listener = resolve_addresses(host, port)
+        .and_then(|addresses| TcpListener::bind(&addresses[..]))
+        .map_err(|e| {
+            eprintln!("Failed to start listener at {host}:{port}: {e}");
+            e
+        })?;
+    info!("Listening on {}", listener.local_addr()?);
 
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
+                let max_connections = MAX_CONNECTIONS.load(Ordering::SeqCst);
+                let Some(connection_slot) = CONNECTIONS.try_acquire(max_connections) else {
+                    error!(
+                        "Refusing connection from {:?}: limit of {} connections reached, {} refused so far",
+                        stream.peer_addr(),
+                        max_connections,
+                        CONNECTIONS.refused()
+                    );
+                    if let Err(e) = stream.shutdown(Shutdown::Both) {
+                        error!("Failed to close the refused connection: {}", e);
+                    }
+                    continue;
+                };
                 info!("New connection: {}", stream.peer_addr()?);
-                let all_msg_map_collection_clone = Arc::clone(&all_msg_map_collection);
-                let seq_store_clone = Arc::clone(&seq_store);
-                let order_store_clone = Arc::clone(&order_store);
+                apply_socket_options(&stream);
+                let profiles_clone = Arc::clone(&profiles);
                 thread::spawn(move || {
-                    if let Err(e) = handle_stream(
-                        stream,
-                        &all_msg_map_collection_clone,
-                        seq_store_clone,
-                        order_store_clone,
-                    ) {
+                    let _connection_slot = connection_slot;
+                    // The counterparty is only known once its Logon arrives
+                    let logon = peek_logon(&stream);
+                    let sender_comp_id = find_sender_comp_id(&logon);
+                    let profile = profiles_clone.select(sender_comp_id.as_deref());
+                    let session_id = SessionId::from_header(
+                        &profile.message_maps.fix_header,
+                        sender_comp_id.as_deref(),
+                    );
+                    let policy = duplicate_logon_policy(&logon);
+                    let _live_session = match LIVE_SESSIONS.register(&session_id, &stream, policy)
+                    {
+                        Ok(Some(live_session)) => live_session,
+                        Ok(None) => return,
+                        Err(e) => {
+                            error!("Failed to register session {}: {}", session_id, e);
+                            return;
+                        }
+                    };
+                    SESSION_STATES.for_session(&session_id).reset();
+                    let result = profile
+                        .seq_stores
+                        .for_session(&session_id)
+                        .and_then(|seq_store| {
+                            handle_stream(
+                                with_interceptors(
+                                    with_wire_log(
+                                        with_sbe_codec(Box::new(stream), &session_id),
+                                        &session_id,
+                                    ),
+                                    &session_id,
+                                ),
+                                &session_id,
+                                &profile.message_maps,
+                                seq_store,
+                                Arc::clone(&profile.order_store),
+                            )
+                        });
+                    if let Err(e) = result {
                         error!("Error handling client: {}", e);
                     }
                 });
@@ -186,108 +779,549 @@ pub fn start_listener(
     Ok(())
 }
 
+/// Starts a thread which logs out once the scheduled session closes.
+/// Does nothing when no session schedule is configured.
+pub fn logout_at_session_close(
+    stream: &dyn Transport,
+    session_id: &SessionId,
+    all_msg_map_collection: &Arc<MessageMap>,
+    seq_store: &Arc<SequenceNumberStore>,
+) -> io::Result<()> {
+    if SESSION_SCHEDULE.read().unwrap().is_none() {
+        return Ok(());
+    }
+
+    let stream = Arc::new(Mutex::new(stream.try_clone_transport()?));
+    let all_msg_map_collection = Arc::clone(all_msg_map_collection);
+    let seq_store = Arc::clone(seq_store);
+    let session_id = session_id.clone();
+    let session_state = SESSION_STATES.for_session(&session_id);
+    thread::spawn(move || {
+        while !is_session_closed() {
+            // The session already ended some other way
+            if session_state.is_logout_sent() {
+                return;
+            }
+            sleep(Duration::from_secs(1));
+        }
+        info!("Session closed, logging out");
+        if let Err(e) = initiate_logout(
+            &stream,
+            &session_id,
+            &all_msg_map_collection,
+            &seq_store,
+            Some("Session closed"),
+        ) {
+            error!("Failed to log out at session close: {}", e);
+        }
+    });
+    Ok(())
+}
+
 pub fn send_logon_message(
-    stream: &mut TcpStream,
+    stream: &mut dyn Transport,
+    session_id: &SessionId,
     all_msg_map_collection: &Arc<MessageMap>,
     seq_store: Arc<SequenceNumberStore>,
 ) -> io::Result<()> {
-    let logon_message = build_logon_message(all_msg_map_collection, seq_store.clone());
-    stream.write_all(logon_message.as_bytes())?;
-    stream.flush()?;
+    let reset_seq_num = RESET_ON_LOGON.load(Ordering::SeqCst);
+    if reset_seq_num {
+        info!("reset_on_logon=Y, resetting sequence numbers before sending Logon");
+        seq_store.reset();
+    }
+    let logon_message =
+        build_logon_message(all_msg_map_collection, seq_store.clone(), reset_seq_num);
+    let Some(logon_message) = stream.intercept_outbound(&logon_message) else {
+        info!("Logon message dropped by an interceptor");
+        return Ok(());
+    };
+    seq_store.send_outgoing(1, |msg_seq_num| {
+        let logon_message = restamp_seq_num(&logon_message, msg_seq_num);
+        stream.write_all(logon_message.as_bytes())?;
+        stream.flush()?;
+        SESSION_STATES.for_session(session_id).message_sent();
+        journal_sent(&logon_message);
+        Ok(())
+    })?;
     info!("Logon message sent");
-    seq_store.increment_outgoing();
 
-    SENT_LOGON.store(true, Ordering::SeqCst);
+    SESSION_STATES.for_session(session_id).logon_sent();
     Ok(())
 }
 
-/// Builds the logon message.
+/// Builds the logon message, carrying ResetSeqNumFlag=Y when `reset_seq_num` is set.
+/// The sequence numbers are reset by the caller, once per logon attempt; a resent Logon
+/// continues from them.
 fn build_logon_message(
-    all_msg_map_collection: &Arc<MessageMap>,
+    all_msg_map_collection: &MessageMap,
     seq_store: Arc<SequenceNumberStore>,
+    reset_seq_num: bool,
 ) -> String {
+    let mut override_map: HashMap<String, String> = HashMap::new();
+    if reset_seq_num {
+        override_map.insert("ResetSeqNumFlag".to_string(), "Y".to_string());
+    }
+
     let fix_msg = msgtype2fixmsg(
         "Logon".to_string(),
-        &all_msg_map_collection.admin_msg,
+        &admin_msg_with_credentials(&all_msg_map_collection.admin_msg.current()),
         &all_msg_map_collection.fix_tag_name_map,
-        None,
+        Some(&override_map),
         seq_store.get_outgoing(),
     );
     fix_msg.replace("|", "\x01")
 }
 
-fn handle_cmd_line(
-    input_stream: TcpStreamArcMutex,
-    all_msg_map_collection: &MessageMap,
-    seq_store: Arc<SequenceNumberStore>,
-) -> io::Result<()> {
+/// Reads the commands typed on stdin until it is closed or `exit` is typed, each run against
+/// the session connected at the time.
+fn run_console() {
     let mut input = String::new();
     loop {
-        io::stdin().read_line(&mut input)?;
-        if input.trim() == "exit" {
+        input.clear();
+        match io::stdin().read_line(&mut input) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to read the console: {}", e);
+                break;
+            }
+        }
+        if input.trim().is_empty() {
+            continue;
+        }
+        let command = match parse_command(&input) {
+            Ok(command) => command,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        if command == Command::Exit {
             break;
-        } else {
-            handle_input_message(
-                input.trim(),
-                input_stream.clone(),
-                all_msg_map_collection,
-                seq_store.clone(),
+        }
+        let console_session = CONSOLE_SESSION.read().unwrap().clone();
+        let Some(console_session) = console_session else {
+            println!("No session connected");
+            continue;
+        };
+        if let Err(e) = handle_command(
+            &console_session.session,
+            &console_session.order_store,
+            command,
+        ) {
+            error!("Command failed: {}", e);
+        }
+    }
+    info!("Console closed");
+}
+
+fn handle_command(session: &Session, order_store: &OrderStore, command: Command) -> io::Result<()> {
+    match command {
+        Command::Help => println!("{}", HELP),
+        Command::Exit => {}
+        Command::Logout => {
+            initiate_logout(
+                &session.stream,
+                &session.session_id,
+                &session.all_msg_map_collection,
+                &session.seq_store,
+                None,
             )?;
         }
-        input.clear();
+        Command::Status => print_session_status(session),
+        Command::Orders(query) => println!("{}", orders_table(&order_store.query(&query))),
+        Command::History(key) => print_order_history(order_store, &key),
+        Command::Executions(order_id) => print_executions(&order_id),
+        Command::Positions(account) => print_positions(account.as_deref()),
+        Command::Quotes => print_quotes(),
+        Command::KillSwitch(actions) => {
+            let canceled = engage_kill_switch("engaged from the command line", actions);
+            println!("Kill switch engaged, {} open orders canceled", canceled);
+        }
+        Command::ReleaseKillSwitch => {
+            if !KILL_SWITCH.release() {
+                println!("Kill switch is not engaged");
+            }
+        }
+        Command::TradingSession(status, text) => {
+            if IS_INITIATOR.load(Ordering::SeqCst) {
+                println!("Only the acceptor publishes the trading session status");
+                return Ok(());
+            }
+            let sent = publish_trading_session_status(status, text.as_deref());
+            println!(
+                "Trading session {:?}, TradingSessionStatus sent to {} sessions",
+                status, sent
+            );
+        }
+        Command::News(news) => {
+            println!("News sent to {} sessions", broadcast_news(&news));
+        }
+        Command::ClearBreach(account) => {
+            if !ACCOUNT_RISK.clear(&account) {
+                println!("Account {} is not blocked", account);
+            }
+        }
+        Command::SetSeq(direction, seq_num) => {
+            match direction {
+                SeqDirection::In => session.seq_store.set_incoming(seq_num),
+                SeqDirection::Out => session.seq_store.set_outgoing(seq_num),
+            }
+            info!("Operator set the next {:?} sequence number to {}", direction, seq_num);
+            print_session_status(session);
+        }
+        Command::Resend(begin_seq_no, end_seq_no) => {
+            let msg_map = IndexMap::from([
+                ("MsgType".to_string(), "2".to_string()),
+                ("BeginSeqNo".to_string(), begin_seq_no.to_string()),
+                ("EndSeqNo".to_string(), end_seq_no.to_string()),
+            ]);
+            session.send_batch(vec![msg_map])?;
+        }
+        Command::Batch(path, interval) => match read_batch_file(&path) {
+            Ok(commands) => run_batch(
+                session,
+                commands,
+                interval.unwrap_or_else(|| BATCH_INTERVAL_MS.load(Ordering::SeqCst)),
+            )?,
+            Err(e) => println!("{}", e),
+        },
+        command => send_command(session, command)?,
     }
+    Ok(())
+}
+
+/// Sends the message of a raw FIX, order or `send` command.
+fn send_command(session: &Session, command: Command) -> io::Result<()> {
+    match command {
+        Command::Send(message_type, fields) => {
+            send_command_message(session, &message_type, fields)
+        }
+        Command::Order(spec) => {
+            let all_msg_map_collection = &session.all_msg_map_collection;
+            let template = all_msg_map_collection
+                .app_msg
+                .current()
+                .get("New_Order_Single")
+                .cloned()
+                .unwrap_or_default();
+            let msg_map = order_message(&spec, &template);
+            if admit_message("NEW_ORDER_SINGLE", &msg_map, session) {
+                session.send_batch(vec![msg_map.clone()])?;
+                println!("Sent order {}", msg_map["ClOrdID"]);
+            }
+            Ok(())
+        }
+        Command::QuoteRequest(symbol, quantity) => {
+            let all_msg_map_collection = &session.all_msg_map_collection;
+            let template = all_msg_map_collection
+                .app_msg
+                .current()
+                .get("Quote_Request")
+                .cloned()
+                .unwrap_or_default();
+            let msg_map = quote_request_message(&symbol, quantity, &template);
+            if admit_message("QUOTE_REQUEST", &msg_map, session) {
+                session.send_batch(vec![msg_map.clone()])?;
+                println!("Sent quote request {}", msg_map["QuoteReqID"]);
+            }
+            Ok(())
+        }
+        Command::Raw(messages) => handle_input_message(&messages, session),
+        Command::Json(json) => {
+            let fix_tag_name_map = &session.all_msg_map_collection.fix_tag_name_map;
+            match FixMessage::from_json(&json, fix_tag_name_map) {
+                Ok(fix_message) => handle_input_message(&fix_message.to_fix(), session),
+                Err(e) => {
+                    println!("Invalid JSON message: {:?}", e);
+                    Ok(())
+                }
+            }
+        }
+        Command::Fixml(document) => match fixml_to_fix(&document) {
+            Ok(message) => handle_input_message(&message, session),
+            Err(e) => {
+                println!("Invalid FIXML message: {:?}", e);
+                Ok(())
+            }
+        },
+        _ => Ok(()),
+    }
+}
 
+/// Sends the commands of a batch file one by one, `interval_ms` apart, stopping early when
+/// the session logs out.
+fn run_batch(session: &Session, commands: Vec<Command>, interval_ms: u64) -> io::Result<()> {
+    let count = commands.len();
+    info!("Sending a batch of {} messages, {} ms apart", count, interval_ms);
+    let session_state = SESSION_STATES.for_session(&session.session_id);
+    for (index, command) in commands.into_iter().enumerate() {
+        if session_state.is_logout_sent() {
+            error!("Session logged out, batch stopped after {} of {} messages", index, count);
+            return Ok(());
+        }
+        if index > 0 && interval_ms > 0 {
+            sleep(Duration::from_millis(interval_ms));
+        }
+        send_command(session, command)?;
+    }
+    info!("Batch of {} messages sent", count);
     Ok(())
 }
 
-fn handle_input_message(
-    input: &str,
-    input_stream: TcpStreamArcMutex,
-    all_msg_map_collection: &MessageMap,
-    seq_store: Arc<SequenceNumberStore>,
+/// Sends the configured batch file once the session is logged on. The file is sent once per
+/// run of the engine, not again after a reconnect.
+fn start_configured_batch(session: Session) {
+    let path = match BATCH_FILE.write().unwrap().take() {
+        Some(path) => path,
+        None => return,
+    };
+    thread::spawn(move || {
+        let commands = match read_batch_file(&path) {
+            Ok(commands) => commands,
+            Err(e) => {
+                error!("Batch not sent: {}", e);
+                return;
+            }
+        };
+        let session_state = SESSION_STATES.for_session(&session.session_id);
+        while !session_state.is_logon_received() {
+            if session_state.is_logout_sent() {
+                return;
+            }
+            sleep(Duration::from_millis(100));
+        }
+        if let Err(e) = run_batch(&session, commands, BATCH_INTERVAL_MS.load(Ordering::SeqCst)) {
+            error!("Batch failed: {}", e);
+        }
+    });
+}
+
+fn print_session_status(session: &Session) {
+    println!(
+        "logged on: {}, next incoming MsgSeqNum: {}, next outgoing MsgSeqNum: {}, last sent: {}",
+        SESSION_STATES
+            .for_session(&session.session_id)
+            .is_logged_on(),
+        session.seq_store.get_incoming(),
+        session.seq_store.get_outgoing(),
+        SESSION_STATES
+            .for_session(&session.session_id)
+            .last_sent_time()
+            .to_rfc3339()
+    );
+}
+
+/// Sends the message of a `send` command. The message type is a MsgType value or a message
+/// name of the dictionary, the fields are keyed by tag name.
+fn send_command_message(
+    session: &Session,
+    message_type: &str,
+    fields: IndexMap<String, String>,
 ) -> io::Result<()> {
-    if input.starts_with("8=FIX") {
+    let all_msg_map_collection = &session.all_msg_map_collection;
+    let message = all_msg_map_collection
+        .msgnumber_fields_map
+        .get_key_value(message_type)
+        .or_else(|| {
+            all_msg_map_collection
+                .msgnumber_fields_map
+                .iter()
+                .find(|(_, fix_msg_tag)| same_message_name(message_type, &fix_msg_tag.msgname))
+        })
+        .filter(|(_, fix_msg_tag)| !["header", "trailer"].contains(&fix_msg_tag.msgcat.as_str()));
+    let (msgtype, fix_msg_tag) = match message {
+        Some(message) => message,
+        None => {
+            println!("Unknown message type {}", message_type);
+            return Ok(());
+        }
+    };
+    if let Some(name) = fields
+        .keys()
+        .find(|name| !all_msg_map_collection.fix_tag_name_map.contains_key(*name))
+    {
+        println!("Unknown field {}", name);
+        return Ok(());
+    }
+
+    let mut msg_map = IndexMap::from([("MsgType".to_string(), msgtype.clone())]);
+    msg_map.extend(fields);
+    if admit_message(&fix_msg_tag.msgname, &msg_map, session) {
+        session.send_batch(vec![msg_map])?;
+    }
+    Ok(())
+}
+
+/// Whether a message typed by the operator may go out: order flow of the session must not be
+/// halted, no new order while the kill switch is engaged and the account must not be blocked.
+fn admit_message(msgtype: &str, msg_map: &IndexMap<String, String>, session: &Session) -> bool {
+    if msgtype == "NEW_ORDER_SINGLE" {
+        if let Some(reason) = KILL_SWITCH.engaged() {
+            error!("Kill switch engaged ({}), order not sent", reason);
+            return false;
+        }
+    }
+    if SESSION_STATES
+        .for_session(&session.session_id)
+        .is_order_flow_halted()
+        && !session
+            .all_msg_map_collection
+            .admin_msg_list
+            .iter()
+            .any(|admin| admin == msgtype)
+    {
+        error!("Order flow is halted after consecutive session Rejects, message not sent");
+        return false;
+    }
+
+    if let Some(reason) = msg_map
+        .get("Account")
+        .and_then(|account| ACCOUNT_RISK.blocked_reason(account))
+    {
+        error!("Account is blocked after a risk breach ({}), message not sent", reason);
+        return false;
+    }
+    true
+}
+
+/// Prints the fields each cancel/replace changed on the order.
+fn print_order_history(order_store: &OrderStore, key: &OrderKey) {
+    match order_store.order_history(key) {
+        Some(history) => {
+            for entry in history {
+                let changes: Vec<String> = entry
+                    .changes
+                    .iter()
+                    .map(|change| {
+                        format!("{}: {} -> {}", change.field, change.old_value, change.new_value)
+                    })
+                    .collect();
+                let msg_seq_num = entry
+                    .msg_seq_num
+                    .map_or("-".to_string(), |msg_seq_num| msg_seq_num.to_string());
+                println!(
+                    "{} seq {} {}: {}",
+                    entry.timestamp,
+                    msg_seq_num,
+                    entry.cl_ord_id,
+                    changes.join(", ")
+                );
+            }
+        }
+        None => println!("Order {} not found", key.cl_ord_id),
+    }
+}
+
+fn print_executions(order_id: &str) {
+    let store = EXECUTION_STORE.read().unwrap();
+    let Some(store) = store.as_ref() else {
+        println!("No execution_store configured");
+        return;
+    };
+    let executions = store.for_order(order_id);
+    if executions.is_empty() {
+        println!("No executions of {}", order_id);
+    }
+    for execution in executions {
+        println!(
+            "{} {:?} {} ExecType={} OrdStatus={} LastQty={} LastPx={}",
+            execution.recorded_at,
+            execution.direction,
+            execution.exec_id,
+            execution.exec_type,
+            execution.ord_status,
+            execution.last_qty.as_deref().unwrap_or("-"),
+            execution.last_px.as_deref().unwrap_or("-")
+        );
+    }
+}
+
+fn print_positions(account: Option<&str>) {
+    let store = EXECUTION_STORE.read().unwrap();
+    let Some(store) = store.as_ref() else {
+        println!("No execution_store configured");
+        return;
+    };
+    let positions = store.positions(account);
+    if positions.is_empty() {
+        println!("No positions");
+        return;
+    }
+    println!("{}", positions_table(&positions));
+}
+
+fn print_quotes() {
+    let quotes = QUOTES.active();
+    if quotes.is_empty() {
+        println!("No quotes");
+        return;
+    }
+    println!("{}", quotes_table(&quotes));
+}
+
+/// Sends the FIX messages typed on one line. Several messages on the same line
+/// (e.g. an order wave) are sent as one batch.
+fn handle_input_message(input: &str, session: &Session) -> io::Result<()> {
+    let all_msg_map_collection = &session.all_msg_map_collection;
+    let mut batch = Vec::new();
+    for message in split_fix_messages(input) {
         if let Ok(fix_details) =
-            print_fix_message(input, &all_msg_map_collection.fix_tag_number_map)
+            print_fix_message(message, &all_msg_map_collection.fix_tag_number_map)
         {
             println!("{}", fix_details);
         }
 
-        if let Ok(fix_message) = crate::message_validator::FixMessage::parse(input) {
-            if fix_message.validate(
+        if let Ok(fix_message) = FixMessage::parse(message) {
+            if let Err(errors) = fix_message.validate(
                 &all_msg_map_collection.required_fields,
                 &all_msg_map_collection.valid_msg_types,
-                &all_msg_map_collection.msgnumber_fields_map.clone(),
+                &all_msg_map_collection.msgnumber_fields_map,
+                &all_msg_map_collection.fix_tag_number_map,
             ) {
-                let (msgtype, msg_map) =
-                    fixmsg2msgtype(input, &all_msg_map_collection.fix_tag_number_map).unwrap();
-                info!("Parsed message type: {}, map: {:?}", msgtype, msg_map);
-
-                let mut merged_msg_map = all_msg_map_collection.fix_header.clone();
-                merged_msg_map.extend(msg_map);
-                info!("Merged message map: {:?}", merged_msg_map);
-
-                let mut msg = fixmap2fixmsg(
-                    &merged_msg_map,
-                    &all_msg_map_collection.fix_tag_name_map,
-                    seq_store.get_outgoing(),
-                );
-                msg = msg.replace("|", "\x01");
-
-                send_message(&input_stream, msg.clone())?;
+                error!("Message validation failed: {}", describe_errors(&errors));
+                continue;
+            }
+            let (msgtype, msg_map) =
+                fix_message.to_names(&all_msg_map_collection.fix_tag_number_map);
+            info!(
+                "Parsed message type: {}, map: {:?}",
+                msgtype,
+                mask_fields(&msg_map, &all_msg_map_collection.fix_tag_number_map)
+            );
 
-                seq_store.increment_outgoing();
-                LAST_SENT_TIME.store(Utc::now(), Ordering::SeqCst);
-                info!("Message sent, updated last sent time");
-            } else {
-                error!("Message validation failed");
+            if admit_message(&msgtype, &msg_map, session) {
+                batch.push(msg_map);
             }
         }
     }
 
+    if !batch.is_empty() {
+        session.send_batch(batch)?;
+        info!("Message sent, updated last sent time");
+    }
+
     Ok(())
 }
 
+/// Splits a line into the FIX messages it contains, each starting with "8=FIX".
+fn split_fix_messages(input: &str) -> Vec<&str> {
+    let starts: Vec<usize> = input
+        .match_indices("8=FIX")
+        .map(|(index, _)| index)
+        .filter(|&index| index == 0 || input[..index].ends_with(['|', '\x01', ' ']))
+        .collect();
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = starts.get(i + 1).copied().unwrap_or(input.len());
+            input[start..end].trim()
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -297,7 +1331,6 @@ mod tests {
     use std::thread;
 
     use crate::sequence::SequenceNumberStore;
-    use crate::orderstore::OrderStore;
     use crate::MessageMap;
 
     fn setup_dummy_msg_map() -> Arc<MessageMap> {
@@ -311,17 +1344,12 @@ mod tests {
             required_fields: Default::default(),
             valid_msg_types: Default::default(),
             msgnumber_fields_map: Default::default(),
-            msgname_fields_map: Default::default(),
             fix_header: Default::default(),
         })
     }
 
     fn setup_dummy_sequence_store() -> Arc<SequenceNumberStore> {
-        Arc::new(SequenceNumberStore::new("dummy_sequence.txt"))
-    }
-
-    fn setup_dummy_order_store() -> Arc<OrderStore> {
-        Arc::new(OrderStore::new("dummy_order.txt", 1024).unwrap())
+        Arc::new(SequenceNumberStore::new("dummy_sequence.txt").unwrap())
     }
 
     #[test]
@@ -332,11 +1360,7 @@ mod tests {
 
         // Spawn a thread to accept connections
         thread::spawn(move || {
-            for stream in listener.incoming() {
-                if let Ok(_) = stream {
-                    break;
-                }
-            }
+            listener.incoming().find(|stream| stream.is_ok());
         });
 
         // Attempt to establish connection
@@ -372,7 +1396,167 @@ mod tests {
         let seq_store = setup_dummy_sequence_store();
 
         // Send the logon message
-        let result = send_logon_message(&mut stream, &all_msg_map_collection, seq_store);
+        let session_id = SessionId::from_header(&all_msg_map_collection.fix_header, None);
+        let result = send_logon_message(&mut stream, &session_id, &all_msg_map_collection, seq_store);
         assert!(result.is_ok());
+        assert!(SESSION_STATES.for_session(&session_id).is_logon_sent());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_resent_logon_keeps_sequence_numbers() {
+        let dir = tempfile::tempdir().unwrap();
+        let seq_file = dir.path().join("sequence.txt");
+        let seq_store = Arc::new(SequenceNumberStore::new(seq_file.to_str().unwrap()).unwrap());
+        seq_store.set_outgoing(5);
+        seq_store.set_incoming(7);
+
+        let logon = build_logon_message(&setup_dummy_msg_map(), seq_store.clone(), false);
+        assert!(!logon.contains("141=Y"));
+        assert_eq!(seq_store.get_outgoing(), 5);
+        assert_eq!(seq_store.get_incoming(), 7);
+    }
+
+    #[test]
+    fn test_silent_proxy_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let proxy = ProxyConfig {
+            kind: crate::proxy::ProxyKind::Socks5,
+            host: address.ip().to_string(),
+            port: address.port(),
+            username: None,
+            password: None,
+        };
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        // Accepted, never answered
+        let (_silent, _) = listener.accept().unwrap();
+        let started = Instant::now();
+        let timeout = Duration::from_millis(100);
+        assert!(open_tunnel(&proxy, &mut stream, "venue.com", 9999, timeout).is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(stream.read_timeout().unwrap(), None);
+        assert_eq!(stream.write_timeout().unwrap(), None);
+    }
+
+    #[test]
+    fn test_no_heartbeat_without_heart_bt_int() {
+        let activity = SessionActivity::new();
+        let now = Instant::now();
+        let session_state = SessionState::default();
+        let deadline = next_timer_deadline(now, &session_state, &activity, Duration::ZERO);
+        assert!(deadline >= now + IDLE_TIMER_PERIOD);
+        assert_eq!(test_request_delay(Duration::ZERO), None);
+        assert_eq!(
+            test_request_delay(Duration::from_secs(30)),
+            Some(Duration::from_secs(36))
+        );
+    }
+
+    #[test]
+    fn test_split_fix_messages() {
+        let input = "8=FIX.4.2|35=D|58=8=FIX|10=001| 8=FIX.4.2|35=D|10=002|";
+        assert_eq!(
+            split_fix_messages(input),
+            vec!["8=FIX.4.2|35=D|58=8=FIX|10=001|", "8=FIX.4.2|35=D|10=002|"]
+        );
+        assert!(split_fix_messages("status").is_empty());
+    }
+
+    #[test]
+    fn test_session_registry_duplicate_logon() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let connect = || {
+            let client = TcpStream::connect(address).unwrap();
+            let (server, _) = listener.accept().unwrap();
+            (client, server)
+        };
+        let session_id = SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "FIX_Engine".to_string(),
+            target_comp_id: "XYZExchange".to_string(),
+        };
+        let registry = SessionRegistry::default();
+
+        let (mut first_client, first) = connect();
+        let first_session = registry
+            .register(&session_id, &first, DuplicateLogonPolicy::Reject)
+            .unwrap();
+        assert!(first_session.is_some());
+
+        // A duplicate is closed while the live session carries on
+        let (mut second_client, second) = connect();
+        let rejected = registry.register(&session_id, &second, DuplicateLogonPolicy::Reject);
+        assert!(rejected.unwrap().is_none());
+        assert_eq!(second_client.read(&mut [0; 16]).unwrap(), 0);
+
+        // Kicking closes the live session and the new connection takes over
+        let (_third_client, third) = connect();
+        let third_session = registry
+            .register(&session_id, &third, DuplicateLogonPolicy::KickExisting)
+            .unwrap();
+        assert!(third_session.is_some());
+        assert_eq!(first_client.read(&mut [0; 16]).unwrap(), 0);
+        drop(first_session);
+        let (_fourth_client, fourth) = connect();
+        let rejected = registry.register(&session_id, &fourth, DuplicateLogonPolicy::Reject);
+        assert!(rejected.unwrap().is_none());
+
+        // Once the session ends its SessionID is free again
+        drop(third_session);
+        let (_fifth_client, fifth) = connect();
+        let registered = registry.register(&session_id, &fifth, DuplicateLogonPolicy::Reject);
+        assert!(registered.unwrap().is_some());
+    }
+
+    #[test]
+    fn test_connection_limit() {
+        let limiter = ConnectionLimiter::default();
+        let first = limiter.try_acquire(2);
+        let second = limiter.try_acquire(2);
+        assert!(first.is_some() && second.is_some());
+        assert!(limiter.try_acquire(2).is_none());
+        assert_eq!(limiter.refused(), 1);
+
+        // A closed connection frees its slot, 0 never limits
+        drop(first);
+        assert!(limiter.try_acquire(2).is_some());
+        assert!(limiter.try_acquire(0).is_some());
+    }
+
+    #[test]
+    fn test_interleave_families() {
+        let address = |s: &str| s.parse::<SocketAddr>().unwrap();
+        let addresses = vec![
+            address("[::1]:9999"),
+            address("[::2]:9999"),
+            address("127.0.0.1:9999"),
+            address("[::3]:9999"),
+        ];
+        assert_eq!(
+            interleave_families(addresses),
+            vec![
+                address("[::1]:9999"),
+                address("127.0.0.1:9999"),
+                address("[::2]:9999"),
+                address("[::3]:9999"),
+            ]
+        );
+        assert_eq!(
+            resolve_addresses("[::1]", 9999).unwrap(),
+            vec![address("[::1]:9999")]
+        );
+    }
+
+    #[test]
+    fn test_connect_first_skips_unreachable_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let unreachable: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        let addresses = vec![unreachable, listener.local_addr().unwrap()];
+
+        let stream = connect_first(addresses, None).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), listener.local_addr().unwrap());
+        assert!(connect_first(vec![unreachable], None).is_err());
+    }
+}
@@ -0,0 +1,621 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use chrono::Utc;
+use indexmap::IndexMap;
+
+use crate::kill_switch::KillSwitchActions;
+use crate::news::News;
+use crate::orderstore::{OrdStatus, OrderKey, OrderQuery};
+use crate::trading_session::TradSesStatus;
+
+lazy_static! {
+    /// Batch file sent once the session is logged on, from `batch_file` or `--batch`.
+    pub static ref BATCH_FILE: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Commands of the command line mode, first words only so a line editor can complete them.
+pub const COMMANDS: [&str; 22] = [
+    "batch",
+    "buy",
+    "clear_breach",
+    "executions",
+    "exit",
+    "help",
+    "history",
+    "kill_switch",
+    "logout",
+    "news",
+    "orders",
+    "positions",
+    "quote",
+    "quotes",
+    "resend",
+    "send",
+    "sell",
+    "seq",
+    "short",
+    "status",
+    "trading_session",
+    "8=FIX",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqDirection {
+    In,
+    Out,
+}
+
+/// An order typed as `buy 100 AAPL @ 187.5 limit day`. Side, OrdType and TimeInForce hold
+/// the dictionary's enum descriptions, encoded to their values when the message is sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderSpec {
+    pub side: &'static str,
+    pub quantity: u64,
+    pub symbol: String,
+    pub price: Option<String>,
+    pub ord_type: &'static str,
+    pub time_in_force: &'static str,
+    pub account: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Help,
+    Exit,
+    Logout,
+    Status,
+    /// `orders [symbol <symbol> | account <account> | status <OrdStatus>]`
+    Orders(OrderQuery),
+    /// `history [<SenderCompID>] <ClOrdID>`: the audit trail of an order, of the engine's own
+    /// unless the counterparty which placed it is given.
+    History(OrderKey),
+    /// `executions <ClOrdID or OrderID>`: the stored ExecutionReports of an order.
+    Executions(String),
+    /// `positions [<account>]`: net positions from the stored fills.
+    Positions(Option<String>),
+    /// `quote <symbol> [<qty>]`: a QuoteRequest built from the template.
+    QuoteRequest(String, Option<u64>),
+    /// `quotes`: the live quotes sent and received.
+    Quotes,
+    ClearBreach(String),
+    /// `kill_switch [cancel] [logout]`: stops new orders, optionally canceling the open ones
+    /// and logging out.
+    KillSwitch(KillSwitchActions),
+    /// `kill_switch off`
+    ReleaseKillSwitch,
+    /// `trading_session <TradSesStatus> [<text>]`: publishes a TradingSessionStatus, e.g.
+    /// halted or open.
+    TradingSession(TradSesStatus, Option<String>),
+    /// `news <headline> [| <line> | <line> ...]`: broadcasts a News to every session.
+    News(News),
+    /// `seq set in|out <n>`: the next expected incoming or outgoing MsgSeqNum.
+    SetSeq(SeqDirection, u64),
+    /// `resend <begin> [<end>]`: ResendRequest, an absent or 0 end meaning up to the latest.
+    Resend(u64, u64),
+    /// `send <MsgType or message name> <Field>=<value> ...`
+    Send(String, IndexMap<String, String>),
+    /// `buy|sell|short <qty> <symbol> [@ <price>] [market|limit|stop] [day|gtc|ioc|fok|opg]
+    /// [account=<account>]`: a NewOrderSingle built from the template.
+    Order(OrderSpec),
+    /// One or more raw FIX messages, `|` or SOH delimited.
+    Raw(String),
+    /// A FIX message as a JSON object of its fields by tag name, e.g.
+    /// `{"BeginString": "FIX.4.2", "MsgType": "HEARTBEAT", ...}`.
+    Json(String),
+    /// A FIX message as a FIXML document, e.g. `<FIXML v="4.4"><Order ...>...</FIXML>`.
+    Fixml(String),
+    /// `batch <file> [<interval_ms>]`: sends the messages of a file one after the other.
+    Batch(PathBuf, Option<u64>),
+}
+
+pub const HELP: &str = "\
+status                          session state and sequence numbers
+orders                          all the orders
+orders symbol <symbol>          orders of a symbol
+orders account <account>        open orders of an account
+orders status <OrdStatus>       orders in a status, e.g. orders status partially_filled
+history <ClOrdID>               audit trail of an order
+history <CompID> <ClOrdID>      audit trail of an order the counterparty placed
+executions <ClOrdID|OrderID>    execution reports of an order
+positions [<account>]           net positions by account and symbol
+quote <symbol> [<qty>]          send a QuoteRequest, e.g. quote IBM 100
+quotes                          live quotes sent and received
+seq set in|out <n>              set the next incoming/outgoing MsgSeqNum
+resend <begin> [<end>]          send a ResendRequest
+send <MsgType> <Field>=<value>  send a message, e.g. send NewOrderSingle ClOrdID=1 Symbol=IBM
+buy|sell|short <qty> <symbol> [@ <price>] [market|limit|stop] [day|gtc|ioc|fok|opg]
+    [account=<account>]         send a NewOrderSingle, e.g. buy 100 AAPL @ 187.5 limit day
+batch <file> [<interval_ms>]    send the FIX messages, orders and send commands of a file
+clear_breach <account>          unblock an account after a risk breach
+kill_switch [cancel] [logout]   stop new orders, cancel the open ones and/or log out
+kill_switch off                 accept new orders again
+trading_session <status> [<text>]
+                                publish the trading session open, halted, closed, pre_open
+                                or pre_close, e.g. trading_session halted News pending
+news <headline> [| <line> ...]  send a News to every session, e.g. news Open | Market open
+logout                          log out and leave the command line
+exit                            leave the command line
+8=FIX...                        send raw FIX messages
+{\"BeginString\": ...}            send a FIX message given as JSON, fields by tag name
+<FIXML ...>...</FIXML>          send a FIX message given as FIXML, its Hdr included";
+
+/// Commands starting with the given prefix, for completion and suggestions.
+pub fn completions(prefix: &str) -> Vec<&'static str> {
+    COMMANDS
+        .iter()
+        .copied()
+        .filter(|command| command.starts_with(prefix))
+        .collect()
+}
+
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    if line.starts_with("8=FIX") {
+        return Ok(Command::Raw(line.to_string()));
+    }
+    if line.starts_with('{') {
+        return Ok(Command::Json(line.to_string()));
+    }
+    if line.starts_with("<FIXML") {
+        return Ok(Command::Fixml(line.to_string()));
+    }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let seq_num = |word: &str| {
+        word.parse::<u64>()
+            .map_err(|_| format!("Not a sequence number: {}", word))
+    };
+    match words.as_slice() {
+        ["help"] => Ok(Command::Help),
+        ["exit"] => Ok(Command::Exit),
+        ["logout"] => Ok(Command::Logout),
+        ["status"] => Ok(Command::Status),
+        ["orders"] => Ok(Command::Orders(OrderQuery::All)),
+        ["orders", "symbol", symbol] => Ok(Command::Orders(OrderQuery::Symbol(symbol.to_string()))),
+        ["orders", "account", account] => Ok(Command::Orders(OrderQuery::OpenForAccount(
+            account.to_string(),
+        ))),
+        ["orders", "status", status] => match OrdStatus::parse(status) {
+            Some(status) => Ok(Command::Orders(OrderQuery::Status(status))),
+            None => Err(format!("Unknown OrdStatus {}", status)),
+        },
+        ["history", cl_ord_id] => Ok(Command::History(OrderKey::own(cl_ord_id))),
+        ["history", sender_comp_id, cl_ord_id] => {
+            Ok(Command::History(OrderKey::new(sender_comp_id, cl_ord_id)))
+        }
+        ["executions", order_id] => Ok(Command::Executions(order_id.to_string())),
+        ["positions"] => Ok(Command::Positions(None)),
+        ["positions", account] => Ok(Command::Positions(Some(account.to_string()))),
+        ["quote", symbol] => Ok(Command::QuoteRequest(symbol.to_string(), None)),
+        ["quote", symbol, quantity] => match quantity.parse::<u64>() {
+            Ok(quantity) if quantity > 0 => {
+                Ok(Command::QuoteRequest(symbol.to_string(), Some(quantity)))
+            }
+            _ => Err(format!("Not a quantity: {}", quantity)),
+        },
+        ["quotes"] => Ok(Command::Quotes),
+        ["clear_breach", account] => Ok(Command::ClearBreach(account.to_string())),
+        ["kill_switch", "off"] => Ok(Command::ReleaseKillSwitch),
+        ["kill_switch", options @ ..] => {
+            let mut actions = KillSwitchActions::default();
+            for option in options {
+                match *option {
+                    "cancel" => actions.cancel_orders = true,
+                    "logout" => actions.logout = true,
+                    other => return Err(format!("Expected cancel or logout, got {}", other)),
+                }
+            }
+            Ok(Command::KillSwitch(actions))
+        }
+        ["trading_session", status, text @ ..] => match TradSesStatus::parse(status) {
+            Some(status) => Ok(Command::TradingSession(
+                status,
+                Some(text.join(" ")).filter(|text| !text.is_empty()),
+            )),
+            None => Err(format!("Unknown TradSesStatus {}", status)),
+        },
+        ["news", _, ..] => {
+            let text = words[1..].join(" ");
+            let mut parts = text
+                .split('|')
+                .map(str::trim)
+                .filter(|part| !part.is_empty());
+            match parts.next() {
+                Some(headline) => Ok(Command::News(News::new(
+                    headline,
+                    parts.map(str::to_string).collect(),
+                ))),
+                None => Err("Missing headline".to_string()),
+            }
+        }
+        ["seq", "set", direction, value] => {
+            let direction = match *direction {
+                "in" => SeqDirection::In,
+                "out" => SeqDirection::Out,
+                other => return Err(format!("Expected in or out, got {}", other)),
+            };
+            Ok(Command::SetSeq(direction, seq_num(value)?))
+        }
+        ["resend", begin] => Ok(Command::Resend(seq_num(begin)?, 0)),
+        ["resend", begin, end] => Ok(Command::Resend(seq_num(begin)?, seq_num(end)?)),
+        ["send", message_type, fields @ ..] => {
+            let mut msg_map = IndexMap::new();
+            for field in fields {
+                match field.split_once('=') {
+                    Some((name, value)) if !name.is_empty() => {
+                        msg_map.insert(name.to_string(), value.to_string());
+                    }
+                    _ => return Err(format!("Expected <Field>=<value>, got {}", field)),
+                }
+            }
+            Ok(Command::Send(message_type.to_string(), msg_map))
+        }
+        [side @ ("buy" | "sell" | "short"), rest @ ..] => {
+            parse_order(side, rest).map(Command::Order)
+        }
+        ["batch", path] => Ok(Command::Batch(PathBuf::from(path), None)),
+        ["batch", path, interval] => {
+            let interval = interval
+                .parse::<u64>()
+                .map_err(|_| format!("Not an interval in milliseconds: {}", interval))?;
+            Ok(Command::Batch(PathBuf::from(path), Some(interval)))
+        }
+        [] => Err("Empty command".to_string()),
+        [command, ..] => match completions(command).as_slice() {
+            [] => Err(format!("Unknown command {}, type help", command)),
+            candidates => Err(format!(
+                "Unknown command {}, did you mean {}?",
+                command,
+                candidates.join(" or ")
+            )),
+        },
+    }
+}
+
+pub fn parse_order(side: &str, words: &[&str]) -> Result<OrderSpec, String> {
+    let usage = "Expected <qty> <symbol> [@ <price>] [market|limit|stop] [day|gtc|ioc|fok|opg]";
+    let (quantity, symbol, mut rest) = match words {
+        [quantity, symbol, rest @ ..] => (quantity, symbol, rest),
+        _ => return Err(usage.to_string()),
+    };
+    let quantity = quantity
+        .parse::<u64>()
+        .ok()
+        .filter(|quantity| *quantity > 0)
+        .ok_or_else(|| format!("Not a quantity: {}", quantity))?;
+
+    let mut price = None;
+    if let Some((first, after)) = rest.split_first() {
+        // Accepts both "@ 187.5" and "@187.5"
+        let value = match first.strip_prefix('@') {
+            Some("") => match after.split_first() {
+                Some((value, after)) => {
+                    rest = after;
+                    Some(*value)
+                }
+                None => return Err(usage.to_string()),
+            },
+            Some(value) => {
+                rest = after;
+                Some(value)
+            }
+            None => None,
+        };
+        if let Some(value) = value {
+            value
+                .parse::<f64>()
+                .map_err(|_| format!("Not a price: {}", value))?;
+            price = Some(value.to_string());
+        }
+    }
+
+    let mut ord_type = None;
+    let mut time_in_force = "DAY";
+    let mut account = None;
+    for word in rest {
+        match word.to_ascii_lowercase().as_str() {
+            "market" | "mkt" => ord_type = Some("MARKET"),
+            "limit" | "lmt" => ord_type = Some("LIMIT"),
+            "stop" | "stp" => ord_type = Some("STOP"),
+            "day" => time_in_force = "DAY",
+            "gtc" => time_in_force = "GOOD_TILL_CANCEL",
+            "ioc" => time_in_force = "IMMEDIATE_OR_CANCEL",
+            "fok" => time_in_force = "FILL_OR_KILL",
+            "opg" => time_in_force = "AT_THE_OPENING",
+            _ => match word.split_once('=') {
+                Some((name, value)) if name.eq_ignore_ascii_case("account") => {
+                    account = Some(value.to_string())
+                }
+                _ => return Err(format!("Unexpected {}. {}", word, usage)),
+            },
+        }
+    }
+    let ord_type = ord_type.unwrap_or(if price.is_some() { "LIMIT" } else { "MARKET" });
+    if ord_type != "MARKET" && price.is_none() {
+        return Err(format!("A {} order needs a price", ord_type.to_lowercase()));
+    }
+
+    Ok(OrderSpec {
+        side: match side {
+            "buy" => "BUY",
+            "sell" => "SELL",
+            _ => "SELL_SHORT",
+        },
+        quantity,
+        symbol: symbol.to_string(),
+        price,
+        ord_type,
+        time_in_force,
+        account,
+    })
+}
+
+/// Reads a batch file: one raw, JSON or FIXML FIX message, order (`buy 100 AAPL @ 187.5`) or `send`
+/// command per line, blank lines and lines starting with `#` ignored. The whole file is checked
+/// before anything is sent.
+pub fn read_batch_file(path: &Path) -> io::Result<Vec<Command>> {
+    let content = fs::read_to_string(path)?;
+    let mut commands = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{} line {}: {}", path.display(), index + 1, message),
+            )
+        };
+        match parse_command(line).map_err(error)? {
+            command @ (Command::Raw(_)
+            | Command::Json(_)
+            | Command::Fixml(_)
+            | Command::Order(_)
+            | Command::Send(..)) => commands.push(command),
+            _ => {
+                return Err(error(
+                    "only messages, orders and send commands can be batched".into(),
+                ))
+            }
+        }
+    }
+    Ok(commands)
+}
+
+/// Unique ClOrdIDs for the orders typed in, numeric like the ones the order store keeps:
+/// milliseconds since the epoch, bumped when several are needed within the same millisecond.
+pub fn next_cl_ord_id() -> String {
+    static LAST_CL_ORD_ID: AtomicU64 = AtomicU64::new(0);
+    let now = Utc::now().timestamp_millis() as u64;
+    let previous = LAST_CL_ORD_ID
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap();
+    now.max(previous + 1).to_string()
+}
+
+/// The NewOrderSingle for an order spec: the template's fields with the spec applied and a
+/// new ClOrdID and TransactTime.
+pub fn order_message(
+    spec: &OrderSpec,
+    template: &IndexMap<String, String>,
+) -> IndexMap<String, String> {
+    let mut msg_map = IndexMap::from([("MsgType".to_string(), "D".to_string())]);
+    msg_map.extend(template.clone());
+    msg_map.insert("ClOrdID".to_string(), next_cl_ord_id());
+    msg_map.insert(
+        "TransactTime".to_string(),
+        Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string(),
+    );
+    msg_map.insert("Side".to_string(), spec.side.to_string());
+    msg_map.insert("OrderQty".to_string(), spec.quantity.to_string());
+    msg_map.insert("Symbol".to_string(), spec.symbol.clone());
+    msg_map.shift_remove("SecurityID");
+    msg_map.insert("OrdType".to_string(), spec.ord_type.to_string());
+    msg_map.insert("TimeInForce".to_string(), spec.time_in_force.to_string());
+    msg_map.shift_remove("Price");
+    if let Some(price) = &spec.price {
+        let field = if spec.ord_type == "STOP" {
+            "StopPx"
+        } else {
+            "Price"
+        };
+        msg_map.insert(field.to_string(), price.clone());
+    }
+    if let Some(account) = &spec.account {
+        msg_map.insert("Account".to_string(), account.clone());
+    }
+    msg_map
+}
+
+/// QuoteRequest for the symbol built from the `Quote_Request` template, with a new QuoteReqID.
+pub fn quote_request_message(
+    symbol: &str,
+    quantity: Option<u64>,
+    template: &IndexMap<String, String>,
+) -> IndexMap<String, String> {
+    let mut msg_map = IndexMap::from([("MsgType".to_string(), "R".to_string())]);
+    msg_map.extend(template.clone());
+    msg_map.insert("QuoteReqID".to_string(), next_cl_ord_id());
+    msg_map.insert("Symbol".to_string(), symbol.to_string());
+    msg_map.shift_remove("OrderQty");
+    if let Some(quantity) = quantity {
+        msg_map.insert("OrderQty".to_string(), quantity.to_string());
+    }
+    msg_map
+}
+
+/// Compares message names ignoring case and underscores, so `NewOrderSingle` matches the
+/// dictionary's `NEW_ORDER_SINGLE`.
+pub fn same_message_name(name: &str, other: &str) -> bool {
+    let normalize = |name: &str| -> String {
+        name.chars()
+            .filter(|c| *c != '_')
+            .map(|c| c.to_ascii_uppercase())
+            .collect()
+    };
+    normalize(name) == normalize(other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(
+            parse_command("seq set in 42"),
+            Ok(Command::SetSeq(SeqDirection::In, 42))
+        );
+        assert_eq!(parse_command("resend 10 20"), Ok(Command::Resend(10, 20)));
+        assert_eq!(parse_command(" resend 10 "), Ok(Command::Resend(10, 0)));
+        assert_eq!(
+            parse_command("send NewOrderSingle ClOrdID=1 Symbol=IBM"),
+            Ok(Command::Send(
+                "NewOrderSingle".to_string(),
+                IndexMap::from([
+                    ("ClOrdID".to_string(), "1".to_string()),
+                    ("Symbol".to_string(), "IBM".to_string()),
+                ])
+            ))
+        );
+        assert!(matches!(
+            parse_command("8=FIX.4.2|35=0|"),
+            Ok(Command::Raw(_))
+        ));
+        assert!(matches!(
+            parse_command(r#"{"MsgType": "HEARTBEAT"}"#),
+            Ok(Command::Json(_))
+        ));
+        assert!(matches!(
+            parse_command(r#"<FIXML v="4.4"><Order ID="1"/></FIXML>"#),
+            Ok(Command::Fixml(_))
+        ));
+        assert_eq!(
+            parse_command("orders status partially_filled"),
+            Ok(Command::Orders(OrderQuery::Status(
+                OrdStatus::PartiallyFilled
+            )))
+        );
+        assert!(parse_command("orders status open").is_err());
+        assert_eq!(
+            parse_command("kill_switch logout cancel"),
+            Ok(Command::KillSwitch(KillSwitchActions {
+                cancel_orders: true,
+                logout: true,
+            }))
+        );
+        assert_eq!(
+            parse_command("kill_switch off"),
+            Ok(Command::ReleaseKillSwitch)
+        );
+        assert!(parse_command("kill_switch now").is_err());
+        assert_eq!(
+            parse_command("trading_session halted News pending"),
+            Ok(Command::TradingSession(
+                TradSesStatus::Halted,
+                Some("News pending".to_string())
+            ))
+        );
+        assert!(parse_command("trading_session paused").is_err());
+        assert_eq!(
+            parse_command("news Market open | Trading starts | Good luck"),
+            Ok(Command::News(News::new(
+                "Market open",
+                vec!["Trading starts".to_string(), "Good luck".to_string()]
+            )))
+        );
+        assert!(parse_command("news |").is_err());
+        assert_eq!(
+            parse_command("positions ACC1"),
+            Ok(Command::Positions(Some("ACC1".to_string())))
+        );
+        assert_eq!(
+            parse_command("quote IBM 100"),
+            Ok(Command::QuoteRequest("IBM".to_string(), Some(100)))
+        );
+        assert!(parse_command("quote IBM lots").is_err());
+        assert!(parse_command("seq set both 1").is_err());
+        assert!(parse_command("send D Symbol").is_err());
+        assert_eq!(
+            parse_command("st"),
+            Err("Unknown command st, did you mean status?".to_string())
+        );
+        assert!(same_message_name("NewOrderSingle", "NEW_ORDER_SINGLE"));
+    }
+
+    #[test]
+    fn test_read_batch_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("orders.txt");
+        fs::write(
+            &path,
+            "# certification run\n\nbuy 100 AAPL @ 187.5\n8=FIX.4.2|35=0|\nsend D Symbol=IBM\n",
+        )
+        .unwrap();
+        let commands = read_batch_file(&path).unwrap();
+        assert_eq!(commands.len(), 3);
+        assert!(matches!(commands[0], Command::Order(_)));
+        assert!(matches!(commands[1], Command::Raw(_)));
+
+        fs::write(&path, "buy 100 AAPL\nlogout\n").unwrap();
+        let error = read_batch_file(&path).unwrap_err();
+        assert!(error.to_string().contains("line 2"));
+        assert_eq!(
+            parse_command("batch orders.txt 250"),
+            Ok(Command::Batch(PathBuf::from("orders.txt"), Some(250)))
+        );
+    }
+
+    #[test]
+    fn test_order_entry() {
+        let spec = match parse_command("buy 100 AAPL @ 187.5 limit day") {
+            Ok(Command::Order(spec)) => spec,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(
+            spec,
+            OrderSpec {
+                side: "BUY",
+                quantity: 100,
+                symbol: "AAPL".to_string(),
+                price: Some("187.5".to_string()),
+                ord_type: "LIMIT",
+                time_in_force: "DAY",
+                account: None,
+            }
+        );
+        assert_eq!(
+            parse_command("short 5 IBM @101 gtc account=ACC1"),
+            parse_command("short 5 IBM @ 101 limit gtc account=ACC1")
+        );
+        assert!(parse_command("sell 0 IBM").is_err());
+        assert!(parse_command("sell 10 IBM limit").is_err());
+
+        let template = IndexMap::from([
+            ("ClOrdID".to_string(), "0".to_string()),
+            ("Account".to_string(), "XYZ".to_string()),
+            ("Symbol".to_string(), "IBM".to_string()),
+            ("SecurityID".to_string(), "IBM".to_string()),
+            ("TransactTime".to_string(), "0".to_string()),
+            ("Price".to_string(), "0".to_string()),
+        ]);
+        let msg_map = order_message(&spec, &template);
+        assert_eq!(msg_map["MsgType"], "D");
+        assert_eq!(msg_map["Account"], "XYZ");
+        assert_eq!(msg_map["Symbol"], "AAPL");
+        assert_eq!(msg_map["Price"], "187.5");
+        assert!(!msg_map.contains_key("SecurityID"));
+        assert_ne!(msg_map["ClOrdID"], "0");
+        assert_ne!(msg_map["TransactTime"], "0");
+        assert_ne!(
+            order_message(&spec, &template)["ClOrdID"],
+            msg_map["ClOrdID"]
+        );
+    }
+}
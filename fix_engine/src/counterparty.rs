@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::net::TcpStream;
+use std::sync::{Arc, RwLock};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::orderstore::OrderStore;
+use crate::sequence::SequenceStores;
+use crate::simulator::SimulatorConfig;
+use crate::MessageMap;
+
+/// How long the acceptor waits for the first Logon before falling back to the default profile.
+const LOGON_PEEK_TIMEOUT: Duration = Duration::from_secs(5);
+
+lazy_static! {
+    /// The behaviour of the sessions by counterparty CompID, lower-cased, "" for the `[session]`
+    /// settings serving every other counterparty.
+    pub static ref SESSION_BEHAVIOURS: RwLock<HashMap<String, Arc<SessionBehaviour>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// How the messages of a counterparty are validated and its orders simulated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionBehaviour {
+    /// Reject the tags missing from the dictionary.
+    pub reject_undefined_tags: bool,
+    /// Largest difference between SendingTime and our clock, 0 not checking it.
+    pub max_latency_seconds: u64,
+    /// Inaccurate SendingTimes in a row before logging out.
+    pub max_latency_violations: u64,
+    /// Session Rejects in a row before halting the order flow, 0 never halting it.
+    pub max_consecutive_rejects: u64,
+    /// Toy exchange behaviour of the acceptor, None when orders are only acknowledged.
+    pub simulator: Option<SimulatorConfig>,
+}
+
+impl Default for SessionBehaviour {
+    fn default() -> Self {
+        Self {
+            reject_undefined_tags: false,
+            max_latency_seconds: 0,
+            max_latency_violations: 3,
+            max_consecutive_rejects: 0,
+            simulator: None,
+        }
+    }
+}
+
+/// The behaviour of the sessions with the counterparty, that of `[session]` for the others.
+pub fn behaviour_of(counterparty: Option<&str>) -> Arc<SessionBehaviour> {
+    let behaviours = SESSION_BEHAVIOURS.read().unwrap();
+    counterparty
+        .and_then(|comp_id| behaviours.get(&comp_id.to_lowercase()))
+        .or_else(|| behaviours.get(""))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Dictionaries, templates and stores used to serve one counterparty.
+pub struct SessionProfile {
+    pub message_maps: Arc<MessageMap>,
+    pub seq_stores: Arc<SequenceStores>,
+    pub order_store: Arc<OrderStore>,
+}
+
+/// Session profiles of the acceptor keyed by the counterparty SenderCompID.
+pub struct CounterpartyProfiles {
+    default: SessionProfile,
+    profiles: HashMap<String, SessionProfile>,
+}
+
+impl CounterpartyProfiles {
+    pub fn new(default: SessionProfile) -> Self {
+        Self {
+            default,
+            profiles: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, sender_comp_id: &str, profile: SessionProfile) {
+        self.profiles.insert(sender_comp_id.to_lowercase(), profile);
+    }
+
+    /// Returns the sequence number stores of the default and every counterparty profile.
+    pub fn seq_stores(&self) -> Vec<Arc<SequenceStores>> {
+        std::iter::once(&self.default)
+            .chain(self.profiles.values())
+            .map(|profile| Arc::clone(&profile.seq_stores))
+            .collect()
+    }
+
+    /// Returns the profile configured for the SenderCompID, or the `[session]` profile.
+    pub fn select(&self, sender_comp_id: Option<&str>) -> &SessionProfile {
+        match sender_comp_id.and_then(|id| self.profiles.get(&id.to_lowercase())) {
+            Some(profile) => {
+                info!("Using counterparty profile for {}", sender_comp_id.unwrap());
+                profile
+            }
+            None => &self.default,
+        }
+    }
+}
+
+/// Peeks at the first message of a new connection, without consuming it, until it is complete
+/// so the Logon can be identified and authenticated. Returns what arrived of it in time.
+pub fn peek_logon(stream: &TcpStream) -> Vec<u8> {
+    let deadline = Instant::now() + LOGON_PEEK_TIMEOUT;
+    let mut buf = [0; 1024];
+    let _ = stream.set_read_timeout(Some(LOGON_PEEK_TIMEOUT));
+
+    let mut bytes_read = 0;
+    while Instant::now() < deadline {
+        match stream.peek(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => {
+                bytes_read = read;
+                if is_complete(&buf[..bytes_read]) || bytes_read == buf.len() {
+                    break;
+                }
+                // Only part of the message has arrived so far
+                sleep(Duration::from_millis(10));
+            }
+        }
+    }
+
+    let _ = stream.set_read_timeout(None);
+    buf[..bytes_read].to_vec()
+}
+
+/// Whether the CheckSum field, which ends every message, has arrived.
+fn is_complete(buf: &[u8]) -> bool {
+    buf.windows(4)
+        .position(|window| window == b"\x0110=")
+        .is_some_and(|start| buf[start + 4..].contains(&b'\x01'))
+}
+
+/// Extracts the value of tag 49 from a raw FIX message.
+pub fn find_sender_comp_id(buf: &[u8]) -> Option<String> {
+    let message = String::from_utf8_lossy(buf);
+    let start = message.find("\x0149=")? + 4;
+    let end = message[start..].find('\x01')?;
+    Some(message[start..start + end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_sender_comp_id() {
+        let message = b"8=FIX.4.2\x019=65\x0135=A\x0149=XYZExchange\x0156=FIX_Engine\x0134=1\x01";
+        assert_eq!(
+            find_sender_comp_id(message),
+            Some("XYZExchange".to_string())
+        );
+    }
+
+    #[test]
+    fn test_find_sender_comp_id_incomplete() {
+        assert_eq!(
+            find_sender_comp_id(b"8=FIX.4.2\x019=65\x0135=A\x0149=XYZ"),
+            None
+        );
+        assert_eq!(find_sender_comp_id(b"8=FIX.4.2\x019=65\x0135=A\x01"), None);
+    }
+
+    #[test]
+    fn test_behaviour_of() {
+        let behaviour = SessionBehaviour {
+            reject_undefined_tags: true,
+            ..SessionBehaviour::default()
+        };
+        SESSION_BEHAVIOURS
+            .write()
+            .unwrap()
+            .insert("behaviour_venue".to_string(), Arc::new(behaviour));
+
+        assert!(behaviour_of(Some("BEHAVIOUR_VENUE")).reject_undefined_tags);
+        // The others fall back to the [session] behaviour
+        assert!(!behaviour_of(Some("OTHER_BEHAVIOUR_VENUE")).reject_undefined_tags);
+        assert!(!behaviour_of(None).reject_undefined_tags);
+    }
+
+    #[test]
+    fn test_is_complete() {
+        assert!(is_complete(b"8=FIX.4.2\x019=5\x0135=A\x0110=123\x01"));
+        assert!(!is_complete(b"8=FIX.4.2\x019=5\x0135=A\x0110=12"));
+        assert!(!is_complete(b"8=FIX.4.2\x019=5\x0135=A\x01"));
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use indexmap::IndexMap;
+
+use crate::execution_store::{ExecutionStore, EXECUTION_STORE};
+
+lazy_static! {
+    /// ClOrdIDs of the order requests the initiator sent since it started.
+    pub static ref SENT_ORDERS: SentOrders = SentOrders::default();
+}
+
+/// DKReason(127) of an ExecutionReport for none of our orders.
+pub const DK_REASON_NO_MATCHING_ORDER: &str = "D";
+
+/// MsgType of a request placing, replacing, canceling or asking the status of an order, as a
+/// value or the dictionary description.
+fn is_order_request(msg_type: &str) -> bool {
+    matches!(
+        msg_type,
+        "D" | "F"
+            | "G"
+            | "H"
+            | "NEW_ORDER_SINGLE"
+            | "ORDER_CANCEL_REQUEST"
+            | "ORDER_CANCEL_REPLACE_REQUEST"
+            | "ORDER_STATUS_REQUEST"
+    )
+}
+
+/// The ClOrdIDs of our orders, so ExecutionReports for orders we never sent are told apart,
+/// and the OrderIDs and ClOrdIDs of the unknown orders reported so far.
+#[derive(Default)]
+pub struct SentOrders {
+    cl_ord_ids: Mutex<HashSet<String>>,
+    unknown: Mutex<HashSet<String>>,
+}
+
+impl SentOrders {
+    /// Remembers the ClOrdID of a message sent if it is an order request.
+    pub fn record(&self, msg_map: &IndexMap<String, String>) {
+        let (Some(msg_type), Some(cl_ord_id)) = (msg_map.get("MsgType"), msg_map.get("ClOrdID"))
+        else {
+            return;
+        };
+        if is_order_request(msg_type) {
+            self.cl_ord_ids.lock().unwrap().insert(cl_ord_id.clone());
+        }
+    }
+
+    pub fn contains(&self, cl_ord_id: &str) -> bool {
+        self.cl_ord_ids.lock().unwrap().contains(cl_ord_id)
+    }
+
+    /// Remembers the order of an ExecutionReport found unknown, so its later reports, stored
+    /// in the execution store too, are not taken for those of a known order.
+    pub fn mark_unknown(&self, msg_map: &IndexMap<String, String>) {
+        let mut unknown = self.unknown.lock().unwrap();
+        for name in ["OrderID", "ClOrdID"] {
+            if let Some(id) = msg_map.get(name).filter(|id| !id.is_empty()) {
+                unknown.insert(id.clone());
+            }
+        }
+    }
+
+    fn is_unknown(&self, id: &str) -> bool {
+        self.unknown.lock().unwrap().contains(id)
+    }
+}
+
+/// Whether the ExecutionReport is for one of our orders: sent since the start, or reported
+/// before in the execution store, which keeps the orders of earlier runs.
+pub fn is_known_execution(
+    msg_map: &IndexMap<String, String>,
+    sent_orders: &SentOrders,
+    execution_store: Option<&ExecutionStore>,
+) -> bool {
+    let field = |name: &str| msg_map.get(name).map_or("", String::as_str);
+    if [field("ClOrdID"), field("OrigClOrdID")]
+        .iter()
+        .any(|id| !id.is_empty() && sent_orders.contains(id))
+    {
+        return true;
+    }
+    let ids = [field("ClOrdID"), field("OrderID")];
+    let ids = ids.iter().filter(|id| !id.is_empty());
+    let Some(store) = execution_store else {
+        return false;
+    };
+    if ids.clone().any(|id| sent_orders.is_unknown(id)) {
+        return false;
+    }
+    // The report itself was stored on receipt
+    ids.flat_map(|id| store.for_order(id))
+        .any(|record| record.exec_id != field("ExecID"))
+}
+
+/// Whether the ExecutionReport is for one of the orders sent or stored.
+pub fn is_known(msg_map: &IndexMap<String, String>) -> bool {
+    let store = EXECUTION_STORE.read().unwrap().clone();
+    is_known_execution(msg_map, &SENT_ORDERS, store.as_deref())
+}
+
+/// Override fields of the DontKnowTrade template refusing the ExecutionReport of an unknown
+/// order. LastShares is FIX 4.2, LastQty FIX 4.4; only those of the report are copied.
+pub fn dont_know_trade_fields(msg_map: &IndexMap<String, String>) -> HashMap<String, String> {
+    let mut fields: HashMap<String, String> = [
+        "OrderID",
+        "ExecID",
+        "Symbol",
+        "Side",
+        "OrderQty",
+        "LastShares",
+        "LastQty",
+        "LastPx",
+    ]
+    .iter()
+    .filter_map(|name| Some((name.to_string(), msg_map.get(*name)?.clone())))
+    .collect();
+    fields.insert(
+        "DKReason".to_string(),
+        DK_REASON_NO_MATCHING_ORDER.to_string(),
+    );
+    let cl_ord_id = msg_map.get("ClOrdID").map_or("", String::as_str);
+    fields.insert("Text".to_string(), format!("Unknown order {}", cl_ord_id));
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg_map;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_execution_reports_of_unknown_orders() {
+        let sent_orders = SentOrders::default();
+        sent_orders.record(&msg_map(&[
+            ("MsgType", "NEW_ORDER_SINGLE"),
+            ("ClOrdID", "1"),
+        ]));
+        sent_orders.record(&msg_map(&[
+            ("MsgType", "EXECUTION_REPORT"),
+            ("ClOrdID", "2"),
+        ]));
+
+        let report = |cl_ord_id: &str, exec_id: &str| {
+            msg_map(&[
+                ("OrderID", "O1"),
+                ("ClOrdID", cl_ord_id),
+                ("ExecID", exec_id),
+                ("Symbol", "IBM"),
+                ("Side", "BUY"),
+                ("LastShares", "100"),
+            ])
+        };
+        assert!(is_known_execution(&report("1", "E1"), &sent_orders, None));
+        assert!(!is_known_execution(&report("2", "E2"), &sent_orders, None));
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = ExecutionStore::open(temp_file.path().to_str().unwrap()).unwrap();
+        store
+            .record(
+                crate::execution_store::ExecDirection::Received,
+                "8=FIX.4.2|35=8|37=O1|11=3|17=E3|150=0|39=0|10=000|",
+            )
+            .unwrap();
+        assert!(is_known_execution(
+            &report("3", "E4"),
+            &sent_orders,
+            Some(&store)
+        ));
+        assert!(!is_known_execution(
+            &report("3", "E3"),
+            &SentOrders::default(),
+            Some(&store)
+        ));
+        sent_orders.mark_unknown(&report("3", "E3"));
+        assert!(!is_known_execution(
+            &report("3", "E4"),
+            &sent_orders,
+            Some(&store)
+        ));
+
+        let fields = dont_know_trade_fields(&report("9", "E9"));
+        assert_eq!(fields["DKReason"], DK_REASON_NO_MATCHING_ORDER);
+        assert_eq!(fields["LastShares"], "100");
+        assert!(!fields.contains_key("LastQty"));
+        assert_eq!(fields["Text"], "Unknown order 9");
+    }
+}
@@ -0,0 +1,221 @@
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Mutex, Once};
+
+use log::{error, warn};
+
+use crate::execution_store::ExecDirection;
+use crate::message_validator::FixMessage;
+use crate::order_events::{OrderEvent, OrderEventSink, ORDER_EVENTS};
+use crate::sequence::SessionId;
+use crate::session_events::{SessionHooks, SESSION_HOOKS};
+
+lazy_static! {
+    pub static ref ENGINE_EVENTS: EngineEvents = EngineEvents::new();
+}
+
+/// Events a subscriber may fall behind by before those published next are dropped for it.
+const SUBSCRIBER_CAPACITY: usize = 10_000;
+
+/// A change of the session's state.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionState {
+    LoggedOn,
+    LoggedOut { text: Option<String> },
+    Disconnected,
+}
+
+/// What the engine does, for consumers which would rather read a channel than implement
+/// `SessionHooks` and `OrderEventSink`.
+#[derive(Debug, Clone)]
+pub enum EngineEvent {
+    MessageReceived(FixMessage),
+    MessageSent(FixMessage),
+    OrderUpdated(Box<OrderEvent>),
+    SessionStateChanged(SessionId, SessionState),
+}
+
+/// Hands every engine event to each subscribed channel. Nothing is parsed or copied while
+/// nobody subscribes. The channels are bounded: the session never waits on a subscriber, one
+/// which falls behind misses the events published while its channel is full.
+pub struct EngineEvents {
+    subscribers: Mutex<Vec<Subscriber>>,
+    capacity: usize,
+    hooked: Once,
+}
+
+struct Subscriber {
+    sender: SyncSender<EngineEvent>,
+    /// Events dropped since the last one the subscriber took.
+    dropped: u64,
+}
+
+impl EngineEvents {
+    pub fn new() -> Self {
+        Self::with_capacity(SUBSCRIBER_CAPACITY)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+            capacity,
+            hooked: Once::new(),
+        }
+    }
+
+    /// The events from now on, until the receiver is dropped. The first subscription hooks the
+    /// channels into the order events and session hooks.
+    pub fn subscribe(&'static self) -> Receiver<EngineEvent> {
+        self.hooked.call_once(|| {
+            ORDER_EVENTS.register(Box::new(ChannelSink(self)));
+            SESSION_HOOKS.register(Box::new(ChannelHooks(self)));
+        });
+        let (sender, receiver) = mpsc::sync_channel(self.capacity);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .push(Subscriber { sender, dropped: 0 });
+        receiver
+    }
+
+    pub fn is_watched(&self) -> bool {
+        !self.subscribers.lock().unwrap().is_empty()
+    }
+
+    /// Sends the event to every subscriber with room for it, forgetting those whose receiver
+    /// is dropped.
+    pub fn publish(&self, event: EngineEvent) {
+        self.subscribers.lock().unwrap().retain_mut(|subscriber| {
+            match subscriber.sender.try_send(event.clone()) {
+                Ok(()) => {
+                    if subscriber.dropped > 0 {
+                        warn!(
+                            "Engine event subscriber fell behind, {} events dropped",
+                            subscriber.dropped
+                        );
+                        subscriber.dropped = 0;
+                    }
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    subscriber.dropped += 1;
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+
+    /// Publishes the raw message sent or received, parsed, if anybody subscribes.
+    pub fn message(&self, direction: ExecDirection, message: &str) {
+        if !self.is_watched() {
+            return;
+        }
+        match FixMessage::parse(message) {
+            Ok(message) => self.publish(match direction {
+                ExecDirection::Sent => EngineEvent::MessageSent(message),
+                ExecDirection::Received => EngineEvent::MessageReceived(message),
+            }),
+            Err(e) => error!("Message not published as an engine event: {}", e),
+        }
+    }
+}
+
+struct ChannelSink(&'static EngineEvents);
+
+impl OrderEventSink for ChannelSink {
+    fn name(&self) -> &str {
+        "engine events"
+    }
+
+    fn on_event(&self, event: &OrderEvent) -> Result<(), Box<dyn std::error::Error>> {
+        self.0
+            .publish(EngineEvent::OrderUpdated(Box::new(event.clone())));
+        Ok(())
+    }
+}
+
+struct ChannelHooks(&'static EngineEvents);
+
+impl SessionHooks for ChannelHooks {
+    fn name(&self) -> &str {
+        "engine events"
+    }
+
+    fn on_logon(&self, session_id: &SessionId) {
+        self.0.publish(EngineEvent::SessionStateChanged(
+            session_id.clone(),
+            SessionState::LoggedOn,
+        ));
+    }
+
+    fn on_logout(&self, session_id: &SessionId, text: Option<&str>) {
+        let text = text.map(str::to_string);
+        self.0.publish(EngineEvent::SessionStateChanged(
+            session_id.clone(),
+            SessionState::LoggedOut { text },
+        ));
+    }
+
+    fn on_disconnect(&self, session_id: &SessionId) {
+        self.0.publish(EngineEvent::SessionStateChanged(
+            session_id.clone(),
+            SessionState::Disconnected,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order_events::OrderEventKind;
+    use indexmap::IndexMap;
+
+    #[test]
+    fn test_events_are_sent_to_every_subscriber() {
+        let events: &'static EngineEvents = Box::leak(Box::new(EngineEvents::new()));
+        // Kept out of the global registries, which other tests publish to
+        events.hooked.call_once(|| {});
+        events.message(ExecDirection::Sent, "8=FIX.4.2\x0135=0\x01");
+        let first = events.subscribe();
+        drop(events.subscribe());
+
+        events.message(ExecDirection::Received, "8=FIX.4.2\x0135=0\x01");
+        let msg_map: IndexMap<String, String> = [("ClOrdID".to_string(), "7".to_string())].into();
+        let order = OrderEvent::from_order_message(OrderEventKind::Accepted, &msg_map);
+        ChannelSink(events).on_event(&order).unwrap();
+        let session_id = SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "BUYSIDE".to_string(),
+            target_comp_id: "SELLSIDE".to_string(),
+        };
+        ChannelHooks(events).on_logout(&session_id, Some("Bye"));
+
+        let received = first.try_iter().collect::<Vec<_>>();
+        assert_eq!(received.len(), 3);
+        assert!(matches!(&received[0], EngineEvent::MessageReceived(message)
+            if message.to_fix() == "8=FIX.4.2|35=0|"));
+        assert!(matches!(&received[1], EngineEvent::OrderUpdated(event) if **event == order));
+        assert!(matches!(
+            &received[2],
+            EngineEvent::SessionStateChanged(id, SessionState::LoggedOut { text })
+                if *id == session_id && text.as_deref() == Some("Bye")
+        ));
+        assert_eq!(events.subscribers.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_events_are_dropped_for_a_subscriber_falling_behind() {
+        let events: &'static EngineEvents = Box::leak(Box::new(EngineEvents::with_capacity(2)));
+        events.hooked.call_once(|| {});
+        let receiver = events.subscribe();
+        for _ in 0..5 {
+            events.message(ExecDirection::Sent, "8=FIX.4.2\x0135=0\x01");
+        }
+        assert_eq!(events.subscribers.lock().unwrap()[0].dropped, 3);
+        assert_eq!(receiver.try_iter().count(), 2);
+
+        events.message(ExecDirection::Sent, "8=FIX.4.2\x0135=0\x01");
+        assert_eq!(events.subscribers.lock().unwrap()[0].dropped, 0);
+        assert_eq!(receiver.try_iter().count(), 1);
+    }
+}
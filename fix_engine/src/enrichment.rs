@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use indexmap::IndexMap;
+
+lazy_static! {
+    /// The fields always set on the messages sent, by lower-cased message name (e.g.
+    /// `new_order_single`).
+    pub static ref ENRICHMENT: RwLock<HashMap<String, Vec<(String, String)>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// The fields of the standard header, where the enrichment adds them rather than at the end of
+/// the message.
+pub(crate) const HEADER_FIELDS: [&str; 26] = [
+    "BeginString",
+    "BodyLength",
+    "MsgType",
+    "SenderCompID",
+    "TargetCompID",
+    "OnBehalfOfCompID",
+    "DeliverToCompID",
+    "SecureDataLen",
+    "SecureData",
+    "MsgSeqNum",
+    "SenderSubID",
+    "SenderLocationID",
+    "TargetSubID",
+    "TargetLocationID",
+    "OnBehalfOfSubID",
+    "OnBehalfOfLocationID",
+    "DeliverToSubID",
+    "DeliverToLocationID",
+    "PossDupFlag",
+    "PossResend",
+    "SendingTime",
+    "OrigSendingTime",
+    "XmlDataLen",
+    "XmlData",
+    "MessageEncoding",
+    "LastMsgSeqNumProcessed",
+];
+
+/// Parses the fields of a message in the `[enrichment]` section, e.g.
+/// `HandlInst=1,SenderSubID=DESK1`.
+pub fn parse_enrichment(fields: &str) -> Result<Vec<(String, String)>, String> {
+    fields
+        .split(',')
+        .filter(|field| !field.trim().is_empty())
+        .map(|field| {
+            field
+                .split_once('=')
+                .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| format!("Invalid enrichment field {}", field.trim()))
+        })
+        .collect()
+}
+
+/// Sets the field of the message. A field the message lacks is added at the end of the header
+/// if it is a header field, of the message otherwise.
+pub fn set_field(message: &mut IndexMap<String, String>, name: &str, value: &str) {
+    if let Some(held) = message.get_mut(name) {
+        *held = value.to_string();
+    } else if HEADER_FIELDS.contains(&name) {
+        let end_of_header = message
+            .keys()
+            .rposition(|key| HEADER_FIELDS.contains(&key.as_str()))
+            .map_or(0, |index| index + 1);
+        message.shift_insert(end_of_header, name.to_string(), value.to_string());
+    } else {
+        message.insert(name.to_string(), value.to_string());
+    }
+}
+
+/// Sets the fields configured for the message named `msgtype`, overriding those given.
+pub fn enrich(msgtype: &str, message: &mut IndexMap<String, String>) {
+    let enrichment = ENRICHMENT.read().unwrap();
+    let Some(fields) = enrichment.get(&msgtype.to_lowercase()) else {
+        return;
+    };
+    for (name, value) in fields {
+        set_field(message, name, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enrich() {
+        let fields = parse_enrichment("HandlInst=1, SenderSubID=DESK1").unwrap();
+        assert!(parse_enrichment("HandlInst").is_err());
+        ENRICHMENT
+            .write()
+            .unwrap()
+            .insert("test_enrichment_order".to_string(), fields);
+
+        let mut order: IndexMap<String, String> = [
+            ("MsgType", "New_Order_Single"),
+            ("SendingTime", "0"),
+            ("ClOrdID", "1"),
+            ("HandlInst", "3"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        enrich("Test_Enrichment_Order", &mut order);
+        let fields: Vec<(&str, &str)> = order
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("MsgType", "New_Order_Single"),
+                ("SendingTime", "0"),
+                ("SenderSubID", "DESK1"),
+                ("ClOrdID", "1"),
+                ("HandlInst", "1"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, RwLock};
+
+use chrono::Utc;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::positions::{Position, PositionBook};
+
+lazy_static! {
+    pub static ref EXECUTION_STORE: RwLock<Option<Arc<ExecutionStore>>> = RwLock::new(None);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecDirection {
+    Sent,
+    Received,
+}
+
+/// One ExecutionReport sent or received, with the fields that link it to its order.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ExecutionRecord {
+    pub exec_id: String,
+    pub direction: ExecDirection,
+    pub order_id: String,
+    pub cl_ord_id: String,
+    pub orig_cl_ord_id: Option<String>,
+    #[serde(default)]
+    pub account: String,
+    #[serde(default)]
+    pub symbol: String,
+    #[serde(default)]
+    pub side: String,
+    pub exec_type: String,
+    pub ord_status: String,
+    pub last_qty: Option<String>,
+    pub last_px: Option<String>,
+    pub msg_seq_num: Option<u64>,
+    /// When the report was stored, UTC.
+    pub recorded_at: String,
+    /// The report as it went over the wire, SOH delimited, to replay it downstream.
+    pub message: String,
+}
+
+impl ExecutionRecord {
+    /// Reads the record of a raw message; None unless it is an ExecutionReport with an ExecID.
+    pub fn from_message(direction: ExecDirection, message: &str) -> Option<Self> {
+        let fields: HashMap<&str, &str> = message
+            .split(['\x01', '|'])
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        if fields.get("35") != Some(&"8") {
+            return None;
+        }
+        let get = |tag: &str| fields.get(tag).map(|value| value.to_string());
+        Some(ExecutionRecord {
+            exec_id: get("17")?,
+            direction,
+            order_id: get("37").unwrap_or_default(),
+            cl_ord_id: get("11").unwrap_or_default(),
+            orig_cl_ord_id: get("41"),
+            account: get("1").unwrap_or_default(),
+            symbol: get("55").unwrap_or_default(),
+            side: get("54").unwrap_or_default(),
+            exec_type: get("150").unwrap_or_default(),
+            ord_status: get("39").unwrap_or_default(),
+            last_qty: get("32"),
+            last_px: get("31"),
+            msg_seq_num: fields.get("34").and_then(|value| value.parse().ok()),
+            recorded_at: Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string(),
+            message: message.to_string(),
+        })
+    }
+}
+
+#[derive(Default)]
+struct Executions {
+    records: Vec<ExecutionRecord>,
+    by_exec_id: HashMap<String, usize>,
+    /// Records by OrderID and by ClOrdID, so an order's executions are found by either.
+    by_order: HashMap<String, Vec<usize>>,
+}
+
+impl Executions {
+    /// Adds the record unless its ExecID is already known, as for a resent report.
+    fn insert(&mut self, record: ExecutionRecord) -> bool {
+        if self.by_exec_id.contains_key(&record.exec_id) {
+            return false;
+        }
+        let index = self.records.len();
+        self.by_exec_id.insert(record.exec_id.clone(), index);
+        for key in [&record.order_id, &record.cl_ord_id] {
+            if !key.is_empty() && key != "NONE" {
+                let indexes = self.by_order.entry(key.clone()).or_default();
+                if indexes.last() != Some(&index) {
+                    indexes.push(index);
+                }
+            }
+        }
+        self.records.push(record);
+        true
+    }
+}
+
+/// Append-only store of the ExecutionReports sent and received, one JSON record per line,
+/// loaded again on startup so executions survive restarts. The positions are seeded from the
+/// fills stored and kept up to date by the fill events published from then on.
+pub struct ExecutionStore {
+    file: Mutex<File>,
+    executions: RwLock<Executions>,
+    positions: Arc<PositionBook>,
+}
+
+impl ExecutionStore {
+    /// Opens (or creates) the store file and loads its records. A last line cut short by a
+    /// crash is skipped.
+    pub fn open(file_path: &str) -> io::Result<Self> {
+        let mut executions = Executions::default();
+        let positions = Arc::new(PositionBook::default());
+        let content = fs::read_to_string(file_path).unwrap_or_default();
+        for (number, line) in content.lines().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(record) => {
+                    positions.apply(&record);
+                    executions.insert(record);
+                }
+                Err(e) => warn!("Ignoring line {} of {}: {}", number + 1, file_path, e),
+            }
+        }
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(file_path)?;
+        // The next record starts on a line of its own
+        if !content.is_empty() && !content.ends_with('\n') {
+            writeln!(file)?;
+        }
+        Ok(Self {
+            file: Mutex::new(file),
+            executions: RwLock::new(executions),
+            positions,
+        })
+    }
+
+    /// Stores the message if it is an ExecutionReport not stored yet. Returns whether it was.
+    pub fn record(&self, direction: ExecDirection, message: &str) -> io::Result<bool> {
+        let record = match ExecutionRecord::from_message(direction, message) {
+            Some(record) => record,
+            None => return Ok(false),
+        };
+        let line = serde_json::to_string(&record)?;
+        let mut executions = self.executions.write().unwrap();
+        if executions.by_exec_id.contains_key(&record.exec_id) {
+            return Ok(false);
+        }
+        executions.insert(record);
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", line)?;
+        file.flush()?;
+        Ok(true)
+    }
+
+    pub fn get(&self, exec_id: &str) -> Option<ExecutionRecord> {
+        let executions = self.executions.read().unwrap();
+        let index = *executions.by_exec_id.get(exec_id)?;
+        Some(executions.records[index].clone())
+    }
+
+    /// The executions of an order, by its OrderID or any of its ClOrdIDs, in the order stored.
+    pub fn for_order(&self, order_id: &str) -> Vec<ExecutionRecord> {
+        let executions = self.executions.read().unwrap();
+        executions
+            .by_order
+            .get(order_id)
+            .into_iter()
+            .flatten()
+            .map(|index| executions.records[*index].clone())
+            .collect()
+    }
+
+    /// The executions stored after the one with `after_exec_id`, or all of them, in the order
+    /// stored, to replay them to a downstream system from where it left off.
+    pub fn replay(&self, after_exec_id: Option<&str>) -> Vec<ExecutionRecord> {
+        let executions = self.executions.read().unwrap();
+        let start = after_exec_id
+            .and_then(|exec_id| executions.by_exec_id.get(exec_id))
+            .map_or(0, |index| index + 1);
+        executions.records[start..].to_vec()
+    }
+
+    /// The positions netted from the fills, to register as an order event sink.
+    pub fn position_book(&self) -> Arc<PositionBook> {
+        Arc::clone(&self.positions)
+    }
+
+    /// The positions of the account, or of every account, from the fills stored.
+    pub fn positions(&self, account: Option<&str>) -> Vec<Position> {
+        self.positions.positions(account)
+    }
+}
+
+/// Stores the message in the configured execution store if it is an ExecutionReport.
+pub fn store_execution_report(direction: ExecDirection, message: &str) {
+    if let Some(store) = EXECUTION_STORE.read().unwrap().as_ref() {
+        if let Err(e) = store.record(direction, message) {
+            error!("Failed to store execution report: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_execution_reports_are_stored_and_replayed() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let store = ExecutionStore::open(path).unwrap();
+        let ack =
+            "8=FIX.4.2|35=8|34=2|37=O00000001|11=1|17=E00000001|150=0|39=0|".replace('|', "\x01");
+        let ack = ack.as_str();
+        let fill = "8=FIX.4.2|35=8|34=3|37=O00000001|11=2|41=1|17=E00000002|150=2|39=2|\
+                    1=ACC1|55=IBM|54=1|32=100|31=50|";
+        assert!(store.record(ExecDirection::Sent, ack).unwrap());
+        assert!(!store.record(ExecDirection::Sent, ack).unwrap());
+        assert!(store.record(ExecDirection::Received, fill).unwrap());
+        assert!(!store
+            .record(ExecDirection::Sent, "8=FIX.4.2|35=D|11=3|")
+            .unwrap());
+        drop(store);
+
+        // A line torn by a crash is skipped
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        write!(file, "{{\"exec_id\":").unwrap();
+        let store = ExecutionStore::open(path).unwrap();
+        let cancel = "8=FIX.4.2|35=8|34=4|37=O00000001|11=3|41=2|17=E00000003|150=4|39=4|";
+        assert!(store.record(ExecDirection::Received, cancel).unwrap());
+        drop(store);
+        let store = ExecutionStore::open(path).unwrap();
+        let fill = store.get("E00000002").unwrap();
+        assert_eq!(fill.direction, ExecDirection::Received);
+        assert_eq!(fill.last_qty.as_deref(), Some("100"));
+        assert_eq!(fill.orig_cl_ord_id.as_deref(), Some("1"));
+        let exec_ids = |records: Vec<ExecutionRecord>| -> Vec<String> {
+            records.into_iter().map(|record| record.exec_id).collect()
+        };
+        assert_eq!(exec_ids(store.for_order("O00000001")).len(), 3);
+        assert_eq!(exec_ids(store.for_order("2")), vec!["E00000002"]);
+        assert_eq!(
+            exec_ids(store.replay(Some("E00000001"))),
+            vec!["E00000002", "E00000003"]
+        );
+        assert_eq!(store.replay(None).len(), 3);
+
+        // Positions are rebuilt from the fills stored
+        let positions = store.positions(Some("ACC1"));
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].symbol, "IBM");
+        assert_eq!(positions[0].quantity.to_string(), "100");
+        assert!(store.positions(Some("ACC2")).is_empty());
+    }
+}
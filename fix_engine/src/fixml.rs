@@ -0,0 +1,314 @@
+use std::io::Cursor;
+
+use log::debug;
+use quick_xml::events::{BytesEnd, BytesStart, Event};
+use quick_xml::{Reader, Writer};
+
+use crate::message_converter::append_fields;
+use crate::parse_xml::FixError;
+
+/// FIXML element of each MsgType converted.
+const MESSAGES: [(&str, &str); 17] = [
+    ("D", "Order"),
+    ("F", "OrdCxlReq"),
+    ("G", "OrdCxlRplcReq"),
+    ("H", "OrdStatReq"),
+    ("8", "ExecRpt"),
+    ("9", "OrdCxlRej"),
+    ("j", "BizMsgRej"),
+    ("J", "AllocInstrctn"),
+    ("P", "AllocInstrctnAck"),
+    ("AE", "TrdCaptRpt"),
+    ("AR", "TrdCaptRptAck"),
+    ("AS", "AllocRpt"),
+    ("AT", "AllocRptAck"),
+    ("R", "QuotReq"),
+    ("S", "Quote"),
+    ("B", "News"),
+    ("h", "TrdgSesStat"),
+];
+
+/// The component holding a field, None for the message element itself.
+type Component = Option<&'static str>;
+
+/// FIXML attribute of each tag converted, and its component.
+const FIELDS: [(u32, Component, &str); 53] = [
+    (49, Some("Hdr"), "SID"),
+    (56, Some("Hdr"), "TID"),
+    (34, Some("Hdr"), "SeqNum"),
+    (52, Some("Hdr"), "Snt"),
+    (50, Some("Hdr"), "SSub"),
+    (57, Some("Hdr"), "TSub"),
+    (115, Some("Hdr"), "OBID"),
+    (128, Some("Hdr"), "D2ID"),
+    (43, Some("Hdr"), "PosDup"),
+    (97, Some("Hdr"), "PosRsnd"),
+    (122, Some("Hdr"), "OrigSnt"),
+    (55, Some("Instrmt"), "Sym"),
+    (65, Some("Instrmt"), "Sfx"),
+    (48, Some("Instrmt"), "ID"),
+    (22, Some("Instrmt"), "Src"),
+    (167, Some("Instrmt"), "SecTyp"),
+    (200, Some("Instrmt"), "MMY"),
+    (207, Some("Instrmt"), "Exch"),
+    (38, Some("OrdQty"), "Qty"),
+    (152, Some("OrdQty"), "Cash"),
+    (1, None, "Acct"),
+    (11, None, "ID"),
+    (41, None, "OrigID"),
+    (37, None, "OrdID"),
+    (17, None, "ExecID"),
+    (19, None, "ExecRefID"),
+    (20, None, "TransTyp"),
+    (150, None, "ExecTyp"),
+    (39, None, "Stat"),
+    (54, None, "Side"),
+    (40, None, "Typ"),
+    (44, None, "Px"),
+    (99, None, "StopPx"),
+    (59, None, "TmInForce"),
+    (126, None, "ExpireTm"),
+    (432, None, "ExpireDt"),
+    (21, None, "HandlInst"),
+    (18, None, "ExecInst"),
+    (110, None, "MinQty"),
+    (60, None, "TxnTm"),
+    (75, None, "TrdDt"),
+    (63, None, "SettlTyp"),
+    (64, None, "SettlDt"),
+    (15, None, "Ccy"),
+    (32, None, "LastQty"),
+    (31, None, "LastPx"),
+    (151, None, "LeavesQty"),
+    (14, None, "CumQty"),
+    (6, None, "AvgPx"),
+    (103, None, "RejRsn"),
+    (102, None, "CxlRejRsn"),
+    (434, None, "CxlRejRspTo"),
+    (58, None, "Txt"),
+];
+
+/// Components in the order they are written, the header first.
+const COMPONENTS: [&str; 3] = ["Hdr", "Instrmt", "OrdQty"];
+
+fn xml_error(e: impl std::fmt::Display) -> FixError {
+    FixError::Parse(format!("Invalid FIXML: {}", e))
+}
+
+/// The raw FIX message, `|` or SOH delimited, as a FIXML document for post-trade systems,
+/// e.g. `<FIXML v="4.4"><ExecRpt ExecID="E1" ...><Hdr SID="EXCH" .../><Instrmt Sym="IBM"/>
+/// </ExecRpt></FIXML>`. Values stay as on the wire. Fields FIXML has no attribute for here
+/// (repeating groups, custom tags, ...) are left out.
+pub fn fix_to_fixml(message: &str) -> Result<String, FixError> {
+    let fields: Vec<(&str, &str)> = message
+        .split(['\x01', '|'])
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            field
+                .split_once('=')
+                .ok_or_else(|| FixError::Parse(format!("Invalid field {}", field)))
+        })
+        .collect::<Result<_, _>>()?;
+    let value = |tag: &str| {
+        fields
+            .iter()
+            .find(|(number, _)| *number == tag)
+            .map(|(_, value)| *value)
+    };
+    let msg_type =
+        value("35").ok_or_else(|| FixError::Parse("Missing MsgType".to_string()))?;
+    let element = MESSAGES
+        .iter()
+        .find(|(value, _)| *value == msg_type)
+        .map(|(_, element)| *element)
+        .ok_or_else(|| FixError::Parse(format!("No FIXML for MsgType {}", msg_type)))?;
+    let version = value("8").map_or("4.4", |begin_string| {
+        begin_string.strip_prefix("FIX.").unwrap_or(begin_string)
+    });
+
+    // Attributes of the message element (None) and of each component
+    let mut attributes: Vec<(Component, Vec<(&str, &str)>)> = Vec::new();
+    for (tag, value) in &fields {
+        if matches!(*tag, "8" | "9" | "10" | "35") {
+            continue;
+        }
+        let Some((_, component, name)) = FIELDS
+            .iter()
+            .find(|(number, _, _)| tag.parse::<u32>() == Ok(*number))
+        else {
+            debug!("Tag {} left out of FIXML {}", tag, element);
+            continue;
+        };
+        match attributes
+            .iter_mut()
+            .find(|(held_by, _)| held_by == component)
+        {
+            Some((_, held)) => held.push((name, value)),
+            None => attributes.push((*component, vec![(name, value)])),
+        }
+    }
+    let attributes_of = |component: Component| {
+        attributes
+            .iter()
+            .find(|(held_by, _)| *held_by == component)
+            .map_or(Vec::new(), |(_, held)| held.clone())
+    };
+
+    let mut writer = Writer::new(Cursor::new(Vec::new()));
+    let root = BytesStart::new("FIXML").with_attributes([("v", version)]);
+    writer.write_event(Event::Start(root)).map_err(xml_error)?;
+    let message_element = BytesStart::new(element).with_attributes(attributes_of(None));
+    writer
+        .write_event(Event::Start(message_element))
+        .map_err(xml_error)?;
+    for component in COMPONENTS {
+        let held = attributes_of(Some(component));
+        if !held.is_empty() {
+            let component = BytesStart::new(component).with_attributes(held);
+            writer
+                .write_event(Event::Empty(component))
+                .map_err(xml_error)?;
+        }
+    }
+    writer
+        .write_event(Event::End(BytesEnd::new(element)))
+        .map_err(xml_error)?;
+    writer
+        .write_event(Event::End(BytesEnd::new("FIXML")))
+        .map_err(xml_error)?;
+    String::from_utf8(writer.into_inner().into_inner()).map_err(xml_error)
+}
+
+/// The FIX message of a FIXML document, `|` delimited, with its BodyLength and CheckSum. The
+/// header comes from the `Hdr` component, BeginString from the FIXML version.
+pub fn fixml_to_fix(document: &str) -> Result<String, FixError> {
+    let mut reader = Reader::from_str(document);
+    reader.trim_text(true);
+    let mut begin_string = None;
+    let mut msg_type = None;
+    let mut header = Vec::new();
+    let mut body = Vec::new();
+    loop {
+        let element = match reader.read_event().map_err(xml_error)? {
+            Event::Start(element) | Event::Empty(element) => element,
+            Event::Eof => break,
+            _ => continue,
+        };
+        let name = String::from_utf8_lossy(element.name().as_ref()).to_string();
+        let mut attributes = Vec::new();
+        for attribute in element.attributes() {
+            let attribute = attribute.map_err(xml_error)?;
+            let key = String::from_utf8_lossy(attribute.key.as_ref()).to_string();
+            let value = attribute.unescape_value().map_err(xml_error)?.to_string();
+            attributes.push((key, value));
+        }
+        if name == "FIXML" {
+            let version = attributes.iter().find(|(key, _)| key == "v");
+            begin_string = version.map(|(_, version)| format!("FIX.{}", version));
+            continue;
+        }
+        let component = match msg_type {
+            None => {
+                let value = MESSAGES
+                    .iter()
+                    .find(|(_, message)| *message == name)
+                    .map(|(value, _)| *value)
+                    .ok_or_else(|| {
+                        FixError::Parse(format!("Unknown FIXML message {}", name))
+                    })?;
+                msg_type = Some(value);
+                None
+            }
+            Some(_) => match COMPONENTS.iter().find(|component| **component == name) {
+                Some(component) => Some(*component),
+                None => {
+                    debug!("FIXML component {} left out", name);
+                    continue;
+                }
+            },
+        };
+        for (key, value) in attributes {
+            let Some((tag, _, _)) = FIELDS
+                .iter()
+                .find(|(_, held_by, attribute)| *held_by == component && *attribute == key)
+            else {
+                debug!("FIXML attribute {} of {} left out", key, name);
+                continue;
+            };
+            match component {
+                Some("Hdr") => header.push((*tag, value)),
+                _ => body.push((*tag, value)),
+            }
+        }
+    }
+    let msg_type = msg_type.ok_or_else(|| FixError::Parse("No FIXML message".to_string()))?;
+    let begin_string = begin_string.unwrap_or_else(|| "FIX.4.4".to_string());
+    header.extend(body);
+    Ok(append_fields(
+        &format!("8={}|35={}|", begin_string, msg_type),
+        &header,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixml_round_trip() {
+        let report = append_fields(
+            "8=FIX.4.4|35=8|",
+            &[
+                (49, "EXCH".to_string()),
+                (56, "CLIENT".to_string()),
+                (34, "2".to_string()),
+                (37, "O1".to_string()),
+                (11, "C&1".to_string()),
+                (17, "E1".to_string()),
+                (150, "F".to_string()),
+                (39, "2".to_string()),
+                (55, "IBM".to_string()),
+                (54, "1".to_string()),
+                (38, "100".to_string()),
+                (32, "100".to_string()),
+                (31, "187.5".to_string()),
+                (5001, "X".to_string()),
+            ],
+        );
+        let document = fix_to_fixml(&report).unwrap();
+        assert_eq!(
+            document,
+            "<FIXML v=\"4.4\"><ExecRpt OrdID=\"O1\" ID=\"C&amp;1\" ExecID=\"E1\" ExecTyp=\"F\" \
+             Stat=\"2\" Side=\"1\" LastQty=\"100\" LastPx=\"187.5\"><Hdr SID=\"EXCH\" \
+             TID=\"CLIENT\" SeqNum=\"2\"/><Instrmt Sym=\"IBM\"/><OrdQty Qty=\"100\"/>\
+             </ExecRpt></FIXML>"
+        );
+
+        // The custom tag is left out
+        let message = fixml_to_fix(&document).unwrap();
+        assert_eq!(
+            message,
+            append_fields(
+                "8=FIX.4.4|35=8|",
+                &[
+                    (49, "EXCH".to_string()),
+                    (56, "CLIENT".to_string()),
+                    (34, "2".to_string()),
+                    (37, "O1".to_string()),
+                    (11, "C&1".to_string()),
+                    (17, "E1".to_string()),
+                    (150, "F".to_string()),
+                    (39, "2".to_string()),
+                    (54, "1".to_string()),
+                    (32, "100".to_string()),
+                    (31, "187.5".to_string()),
+                    (55, "IBM".to_string()),
+                    (38, "100".to_string()),
+                ],
+            )
+        );
+
+        assert!(fix_to_fixml("8=FIX.4.4|35=0|").is_err());
+        assert!(fixml_to_fix("<FIXML v=\"4.4\"><Unknown/></FIXML>").is_err());
+    }
+}
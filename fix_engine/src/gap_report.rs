@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+use chrono::Utc;
+use log::{error, info};
+
+use crate::message_journal::{MessageJournal, MESSAGE_JOURNALS};
+use crate::sequence::SessionId;
+
+/// MsgSeqNums seen, as disjoint inclusive ranges keyed by their start, so a GapFill over
+/// any number of messages takes one entry.
+#[derive(Default)]
+struct SeqRanges(BTreeMap<u64, u64>);
+
+impl SeqRanges {
+    /// Adds `start..=end`, merged with the ranges it overlaps or touches.
+    fn insert(&mut self, mut start: u64, mut end: u64) {
+        if let Some((&before_start, &before_end)) = self.0.range(..=start).next_back() {
+            if before_end.saturating_add(1) >= start {
+                start = before_start;
+                end = end.max(before_end);
+            }
+        }
+        let merged: Vec<u64> = self
+            .0
+            .range(start..=end.saturating_add(1))
+            .map(|(&start, _)| start)
+            .collect();
+        for merged_start in merged {
+            end = end.max(self.0.remove(&merged_start).unwrap());
+        }
+        self.0.insert(start, end);
+    }
+
+    fn last(&self) -> Option<u64> {
+        self.0.last_key_value().map(|(_, &end)| end)
+    }
+}
+
+/// Sequence numbers and recovery activity seen in one direction of the session.
+#[derive(Default)]
+struct DirectionSummary {
+    /// MsgSeqNums per sequence run; a new run starts whenever the numbers reset to 1.
+    runs: Vec<SeqRanges>,
+    poss_dups: usize,
+    resend_requests: usize,
+    gap_fills: usize,
+    /// MsgSeqNums of the session Rejects (35=3).
+    rejects: Vec<u64>,
+}
+
+impl DirectionSummary {
+    fn record(&mut self, message: &str) {
+        let Some(seq_num) = field(message, "34").and_then(|value| value.parse::<u64>().ok()) else {
+            return;
+        };
+        let poss_dup = field(message, "43") == Some("Y");
+        if poss_dup {
+            self.poss_dups += 1;
+        }
+        // A new run starts whenever the numbers are reset to 1
+        if self.runs.is_empty() || (!poss_dup && seq_num == 1) {
+            self.runs.push(SeqRanges::default());
+        }
+        let run = self.runs.last_mut().unwrap();
+        run.insert(seq_num, seq_num);
+
+        match field(message, "35") {
+            Some("2") => self.resend_requests += 1,
+            Some("3") => self.rejects.push(seq_num),
+            Some("4") if field(message, "123") == Some("Y") => {
+                // A GapFill stands in for every message up to NewSeqNo
+                self.gap_fills += 1;
+                if let Some(new_seq_no) = field(message, "36").and_then(|v| v.parse::<u64>().ok()) {
+                    if new_seq_no > seq_num {
+                        run.insert(seq_num, new_seq_no - 1);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&self, direction: &str) -> String {
+        let mut report = String::new();
+        for (index, run) in self.runs.iter().enumerate() {
+            let last = run.last().unwrap_or(0);
+            report.push_str(&format!(
+                "{} run {}: MsgSeqNum 1-{}, missing: {}\n",
+                direction,
+                index + 1,
+                last,
+                format_ranges(&missing_ranges(run))
+            ));
+        }
+        if self.runs.is_empty() {
+            report.push_str(&format!("{}: no messages\n", direction));
+        }
+        report.push_str(&format!(
+            "{} resends: {} PossDup messages, {} ResendRequests, {} GapFills\n",
+            direction, self.poss_dups, self.resend_requests, self.gap_fills
+        ));
+        report.push_str(&format!(
+            "{} session Rejects: {}",
+            direction,
+            self.rejects.len()
+        ));
+        if !self.rejects.is_empty() {
+            let rejects: Vec<String> = self.rejects.iter().map(|seq| seq.to_string()).collect();
+            report.push_str(&format!(" (MsgSeqNum {})", rejects.join(", ")));
+        }
+        report.push('\n');
+        report
+    }
+}
+
+/// Compares the journaled messages against their sequence ranges: numbers never sent or
+/// received, resends performed and Rejects issued or received.
+pub fn gap_report(journal_content: &str) -> String {
+    let mut sent = DirectionSummary::default();
+    let mut received = DirectionSummary::default();
+    let mut rejected_by_counterparty = Vec::new();
+
+    for line in journal_content.lines() {
+        let mut parts = line.splitn(3, ' ');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("S"), Some(_), Some(message)) => sent.record(message),
+            (Some("R"), Some(_), Some(message)) => received.record(message),
+            (Some("X"), Some(_), Some(rejection)) => rejected_by_counterparty.push(rejection),
+            _ => {}
+        }
+    }
+
+    let mut report = sent.render("Sent");
+    report.push_str(&received.render("Received"));
+    report.push_str(&format!(
+        "Sent messages rejected by the counterparty: {}\n",
+        rejected_by_counterparty.len()
+    ));
+    for rejection in rejected_by_counterparty {
+        report.push_str(&format!("  MsgSeqNum {}\n", rejection));
+    }
+    report
+}
+
+/// Writes the gap report of the journal to `<journal>.<date>.gaps` and returns its path.
+pub fn write_gap_report(journal: &MessageJournal, date: &str) -> io::Result<String> {
+    let content = fs::read_to_string(journal.file_path())?;
+    let report_path = format!("{}.{}.gaps", journal.file_path(), date);
+    let report = format!(
+        "Gap report of {} generated {}\n{}",
+        journal.file_path(),
+        Utc::now().format("%Y%m%d-%H:%M:%S"),
+        gap_report(&content)
+    );
+    fs::write(&report_path, report)?;
+    Ok(report_path)
+}
+
+/// Writes the gap report of the session's journal, if any, dated today.
+pub fn journal_gap_report(session_id: &SessionId) {
+    if let Some(journal) = MESSAGE_JOURNALS.for_session(session_id) {
+        match write_gap_report(&journal, &Utc::now().format("%Y%m%d").to_string()) {
+            Ok(report_path) => info!("Wrote gap report to {}", report_path),
+            Err(e) => error!("Failed to write gap report: {}", e),
+        }
+    }
+}
+
+/// Ranges of sequence numbers between 1 and the highest seen which are absent.
+fn missing_ranges(seq_nums: &SeqRanges) -> Vec<(u64, u64)> {
+    let mut ranges = Vec::new();
+    let mut expected = 1;
+    for (&start, &end) in &seq_nums.0 {
+        if start > expected {
+            ranges.push((expected, start - 1));
+        }
+        expected = end.saturating_add(1);
+    }
+    ranges
+}
+
+fn format_ranges(ranges: &[(u64, u64)]) -> String {
+    if ranges.is_empty() {
+        return "none".to_string();
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Value of a tag in a raw message delimited by SOH or '|'.
+fn field<'a>(message: &'a str, tag: &str) -> Option<&'a str> {
+    message
+        .split(['\x01', '|'])
+        .find_map(|pair| pair.strip_prefix(tag)?.strip_prefix('='))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gap_report() {
+        let journal = "\
+S 20240101-00:00:00.000 8=FIX.4.2|35=A|34=1|
+S 20240101-00:00:01.000 8=FIX.4.2|35=D|34=2|
+S 20240101-00:00:02.000 8=FIX.4.2|35=D|34=5|
+S 20240101-00:00:03.000 8=FIX.4.2|35=4|34=6|123=Y|36=10|
+S 20240101-00:00:04.000 8=FIX.4.2|35=D|34=10|
+R 20240101-00:00:00.500 8=FIX.4.2|35=A|34=1|
+R 20240101-00:00:01.500 8=FIX.4.2|35=3|34=2|45=2|
+R 20240101-00:00:02.500 8=FIX.4.2|35=2|34=3|7=3|16=0|
+R 20240101-00:00:03.500 8=FIX.4.2|35=D|34=2|43=Y|
+X 20240101-00:00:01.600 2 Invalid tag
+R 20240102-00:00:00.500 8=FIX.4.2|35=A|34=1|
+R 20240102-00:00:01.500 8=FIX.4.2|35=0|34=3|
+";
+        let report = gap_report(journal);
+        assert!(report.contains("Sent run 1: MsgSeqNum 1-10, missing: 3-4\n"));
+        assert!(report.contains("Sent resends: 0 PossDup messages, 0 ResendRequests, 1 GapFills"));
+        assert!(report.contains("Received run 1: MsgSeqNum 1-3, missing: none\n"));
+        assert!(report.contains("Received run 2: MsgSeqNum 1-3, missing: 2\n"));
+        assert!(report.contains("Received resends: 1 PossDup messages, 1 ResendRequests"));
+        assert!(report.contains("Received session Rejects: 1 (MsgSeqNum 2)"));
+        assert!(report.contains("rejected by the counterparty: 1\n  MsgSeqNum 2 Invalid tag\n"));
+    }
+
+    #[test]
+    fn test_gap_fill_of_huge_range() {
+        let journal = "\
+S 20240101-00:00:00.000 8=FIX.4.2|35=A|34=1|
+S 20240101-00:00:01.000 8=FIX.4.2|35=4|34=3|123=Y|36=18446744073709551615|
+S 20240101-00:00:02.000 8=FIX.4.2|35=D|34=7|
+";
+        let report = gap_report(journal);
+        assert!(report.contains("Sent run 1: MsgSeqNum 1-18446744073709551614, missing: 2\n"));
+    }
+
+    #[test]
+    fn test_seq_ranges_merge() {
+        let mut ranges = SeqRanges::default();
+        ranges.insert(5, 5);
+        ranges.insert(1, 2);
+        ranges.insert(8, 10);
+        ranges.insert(3, 4);
+        assert_eq!(missing_ranges(&ranges), vec![(6, 7)]);
+        ranges.insert(6, 12);
+        assert_eq!(ranges.0.len(), 1);
+        assert_eq!(ranges.last(), Some(12));
+    }
+}
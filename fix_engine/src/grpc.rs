@@ -0,0 +1,323 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use log::{error, info};
+use serde_json::{json, Value};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio_stream::wrappers::{TcpListenerStream, UnboundedReceiverStream};
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use crate::admin_http::{order_query, AdminSession, ADMIN_SESSIONS};
+use crate::order_events::{self, OrderEventSink, ORDER_EVENTS};
+use crate::order_gateway::{order_cancel, order_spec, send_cancel, send_order};
+use crate::orderstore;
+
+mod proto {
+    tonic::include_proto!("fix_engine");
+}
+
+use proto::fix_engine_server::{FixEngine, FixEngineServer};
+use proto::{
+    CancelOrderRequest, GetOrderRequest, ListOrdersReply, ListOrdersRequest, ListSessionsReply,
+    ListSessionsRequest, Order, OrderEvent, OrderReply, Session, StreamExecutionsRequest,
+    SubmitOrderRequest,
+};
+
+impl From<&orderstore::Order> for Order {
+    fn from(order: &orderstore::Order) -> Self {
+        Order {
+            cl_ord_id: order.id.clone(),
+            order_id: order.order_id.clone(),
+            account: order.account.clone(),
+            symbol: order.symbol.clone(),
+            side: order.side.clone(),
+            quantity: order.quantity.to_string(),
+            price: order.price_text(),
+            ord_type: order.ordtype.clone(),
+            time_in_force: order.timeinforce.clone(),
+            ord_status: order.ordstatus.clone(),
+            cum_qty: order.cum_qty.to_string(),
+            leaves_qty: order.leaves_qty().to_string(),
+            avg_px: order.avg_px.to_string(),
+            transact_time: order.transacttime.clone(),
+        }
+    }
+}
+
+impl From<&order_events::OrderEvent> for OrderEvent {
+    fn from(event: &order_events::OrderEvent) -> Self {
+        let field = |value: &Option<String>| value.clone().unwrap_or_default();
+        OrderEvent {
+            kind: format!("{:?}", event.kind),
+            cl_ord_id: event.cl_ord_id.clone(),
+            orig_cl_ord_id: field(&event.orig_cl_ord_id),
+            order_id: field(&event.order_id),
+            account: event.account.clone(),
+            symbol: event.symbol.clone(),
+            side: event.side.clone(),
+            order_qty: field(&event.order_qty),
+            price: field(&event.price),
+            last_qty: field(&event.last_qty),
+            last_px: field(&event.last_px),
+            cum_qty: field(&event.cum_qty),
+            leaves_qty: field(&event.leaves_qty),
+            avg_px: field(&event.avg_px),
+            text: field(&event.text),
+            transact_time: field(&event.transact_time),
+        }
+    }
+}
+
+/// The `StreamExecutions` calls open, each fed the order events from its start.
+#[derive(Default)]
+pub struct ExecutionStreams {
+    subscribers: Mutex<Vec<UnboundedSender<OrderEvent>>>,
+}
+
+impl ExecutionStreams {
+    fn subscribe(&self) -> UnboundedReceiverStream<OrderEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.subscribers.lock().unwrap().push(sender);
+        UnboundedReceiverStream::new(receiver)
+    }
+
+    fn publish(&self, event: &order_events::OrderEvent) {
+        let event = OrderEvent::from(event);
+        // Calls ended by their client are dropped
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+/// Delivers the order events to the `StreamExecutions` calls.
+struct GrpcSink(Arc<ExecutionStreams>);
+
+impl OrderEventSink for GrpcSink {
+    fn name(&self) -> &str {
+        "grpc"
+    }
+
+    fn on_event(&self, event: &order_events::OrderEvent) -> Result<(), Box<dyn std::error::Error>> {
+        self.0.publish(event);
+        Ok(())
+    }
+}
+
+/// The request of `POST /orders` of the REST order gateway with the fields set.
+fn order_request(request: &SubmitOrderRequest) -> Value {
+    let fields = [
+        ("side", &request.side),
+        ("quantity", &request.quantity),
+        ("symbol", &request.symbol),
+        ("price", &request.price),
+        ("ord_type", &request.ord_type),
+        ("time_in_force", &request.time_in_force),
+        ("account", &request.account),
+    ];
+    let mut order = json!({});
+    for (name, value) in fields.into_iter().filter(|(_, value)| !value.is_empty()) {
+        order[name] = json!(value);
+    }
+    order
+}
+
+/// The query string of `GET /orders` of the admin API for the filter set.
+fn order_filter(request: &ListOrdersRequest) -> String {
+    [
+        ("symbol", &request.symbol),
+        ("account", &request.account),
+        ("status", &request.status),
+    ]
+    .iter()
+    .filter(|(_, value)| !value.is_empty())
+    .map(|(name, value)| format!("{}={}", name, value))
+    .collect::<Vec<_>>()
+    .join("&")
+}
+
+/// The initiator runs one session at a time.
+fn running_session() -> Option<Arc<AdminSession>> {
+    ADMIN_SESSIONS.all().into_iter().next()
+}
+
+fn no_session() -> Status {
+    Status::unavailable("No session running")
+}
+
+pub struct FixEngineService {
+    executions: Arc<ExecutionStreams>,
+}
+
+#[tonic::async_trait]
+impl FixEngine for FixEngineService {
+    async fn submit_order(
+        &self,
+        request: Request<SubmitOrderRequest>,
+    ) -> Result<Response<OrderReply>, Status> {
+        let spec =
+            order_spec(&order_request(request.get_ref())).map_err(Status::invalid_argument)?;
+        let session = running_session().ok_or_else(no_session)?;
+        let cl_ord_id =
+            send_order(&session, &spec).map_err(|e| Status::unavailable(e.to_string()))?;
+        Ok(Response::new(OrderReply { cl_ord_id }))
+    }
+
+    async fn cancel_order(
+        &self,
+        request: Request<CancelOrderRequest>,
+    ) -> Result<Response<OrderReply>, Status> {
+        let session = running_session().ok_or_else(no_session)?;
+        let order = session
+            .order_store
+            .get_order(&request.get_ref().cl_ord_id)
+            .ok_or_else(|| Status::not_found("Unknown order"))?;
+        if !order.is_open() {
+            return Err(Status::failed_precondition("Order is no longer open"));
+        }
+        let cl_ord_id = send_cancel(&session, &order_cancel(&order))
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        Ok(Response::new(OrderReply { cl_ord_id }))
+    }
+
+    async fn get_order(
+        &self,
+        request: Request<GetOrderRequest>,
+    ) -> Result<Response<Order>, Status> {
+        let session = running_session().ok_or_else(no_session)?;
+        match session.order_store.get_order(&request.get_ref().cl_ord_id) {
+            Some(order) => Ok(Response::new(Order::from(&order))),
+            None => Err(Status::not_found("Unknown order")),
+        }
+    }
+
+    async fn list_orders(
+        &self,
+        request: Request<ListOrdersRequest>,
+    ) -> Result<Response<ListOrdersReply>, Status> {
+        let query =
+            order_query(&order_filter(request.get_ref())).map_err(Status::invalid_argument)?;
+        let session = running_session().ok_or_else(no_session)?;
+        let orders = session
+            .order_store
+            .query(&query)
+            .iter()
+            .map(Order::from)
+            .collect();
+        Ok(Response::new(ListOrdersReply { orders }))
+    }
+
+    async fn list_sessions(
+        &self,
+        _request: Request<ListSessionsRequest>,
+    ) -> Result<Response<ListSessionsReply>, Status> {
+        let sessions = ADMIN_SESSIONS
+            .all()
+            .iter()
+            .map(|session| Session {
+                session: session.session_id.file_stem(),
+                logged_on: session.is_logged_on(),
+                next_incoming_seq_num: session.seq_store.get_incoming(),
+                next_outgoing_seq_num: session.seq_store.get_outgoing(),
+            })
+            .collect();
+        Ok(Response::new(ListSessionsReply { sessions }))
+    }
+
+    type StreamExecutionsStream = Pin<Box<dyn Stream<Item = Result<OrderEvent, Status>> + Send>>;
+
+    async fn stream_executions(
+        &self,
+        request: Request<StreamExecutionsRequest>,
+    ) -> Result<Response<Self::StreamExecutionsStream>, Status> {
+        let cl_ord_id = request.into_inner().cl_ord_id;
+        let events = self.executions.subscribe().filter_map(move |event| {
+            let wanted = cl_ord_id.is_empty()
+                || [&event.cl_ord_id, &event.orig_cl_ord_id].contains(&&cl_ord_id);
+            wanted.then_some(Ok(event))
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// Serves the gRPC service of `proto/fix_engine.proto` on its own thread and runtime, sending
+/// orders over the running initiator session and streaming the order events.
+pub fn start_grpc_server(address: SocketAddr) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+    info!("gRPC service listening on {}", listener.local_addr()?);
+    let executions = Arc::new(ExecutionStreams::default());
+    ORDER_EVENTS.register(Box::new(GrpcSink(Arc::clone(&executions))));
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    let listener = {
+        let _guard = runtime.enter();
+        tokio::net::TcpListener::from_std(listener)?
+    };
+    thread::spawn(move || {
+        let service = FixEngineServer::new(FixEngineService { executions });
+        let server = Server::builder()
+            .add_service(service)
+            .serve_with_incoming(TcpListenerStream::new(listener));
+        if let Err(e) = runtime.block_on(server) {
+            error!("gRPC service failed: {}", e);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order_events::OrderEventKind;
+    use indexmap::IndexMap;
+
+    #[test]
+    fn test_requests_and_execution_streams() {
+        let request = SubmitOrderRequest {
+            side: "buy".to_string(),
+            quantity: "100".to_string(),
+            symbol: "IBM".to_string(),
+            price: "187.5".to_string(),
+            ..Default::default()
+        };
+        let spec = order_spec(&order_request(&request)).unwrap();
+        assert_eq!(spec.ord_type, "LIMIT");
+        assert_eq!(spec.price.as_deref(), Some("187.5"));
+        assert!(order_spec(&order_request(&SubmitOrderRequest::default())).is_err());
+
+        assert_eq!(order_filter(&ListOrdersRequest::default()), "");
+        let filter = ListOrdersRequest {
+            status: "FILLED".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(order_filter(&filter), "status=FILLED");
+
+        let executions = ExecutionStreams::default();
+        let mut stream = executions.subscribe();
+        drop(executions.subscribe());
+        let msg_map: IndexMap<String, String> = [("ClOrdID", "7"), ("Symbol", "IBM")]
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let event =
+            order_events::OrderEvent::from_order_message(OrderEventKind::Accepted, &msg_map);
+        executions.publish(&event);
+        assert_eq!(executions.subscribers.lock().unwrap().len(), 1);
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let received = runtime.block_on(stream.next()).unwrap();
+        assert_eq!(received.kind, "Accepted");
+        assert_eq!(received.cl_ord_id, "7");
+        assert_eq!(received.price, "");
+    }
+}
@@ -0,0 +1,198 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use serde_json::Value;
+
+/// How long a client may take to send its request or read the response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Requests served at once, each by its own worker thread.
+const WORKERS: usize = 8;
+/// Connections waiting for a worker, those accepted beyond it are closed at once.
+const BACKLOG: usize = 32;
+/// Largest request read, request line and headers included. A request is far smaller.
+const MAX_REQUEST_LEN: u64 = 64 * 1024;
+
+/// A request to one of the engine's HTTP APIs. Parameters come in the query string of the
+/// path, the body is only read when the request gives its Content-Length.
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub body: String,
+}
+
+pub struct HttpResponse {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn json(status: &'static str, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: body.to_string(),
+        }
+    }
+}
+
+/// Serves the API on its own thread, answering each request with the handler on one of a
+/// fixed number of workers. Returns the address listened on.
+pub fn serve(
+    name: &'static str,
+    address: SocketAddr,
+    handler: fn(&HttpRequest) -> HttpResponse,
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let local_address = listener.local_addr()?;
+    info!("{} listening on {}", name, local_address);
+    let (sender, receiver) = mpsc::sync_channel::<TcpStream>(BACKLOG);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..WORKERS {
+        let receiver = Arc::clone(&receiver);
+        thread::spawn(move || serve_connections(name, &receiver, handler));
+    }
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream.map(|stream| sender.try_send(stream)) {
+                Ok(Ok(())) => {}
+                Ok(Err(TrySendError::Full(_))) => {
+                    warn!("{} busy, connection closed", name);
+                }
+                Ok(Err(TrySendError::Disconnected(_))) => break,
+                Err(e) => error!("{} connection failed: {}", name, e),
+            }
+        }
+    });
+    Ok(local_address)
+}
+
+fn serve_connections(
+    name: &str,
+    receiver: &Mutex<Receiver<TcpStream>>,
+    handler: fn(&HttpRequest) -> HttpResponse,
+) {
+    loop {
+        // The lock is held only while waiting, not while serving
+        let stream = match receiver.lock().unwrap().recv() {
+            Ok(stream) => stream,
+            Err(_) => return,
+        };
+        if let Err(e) = handle_connection(name, stream, handler) {
+            error!("{} request failed: {}", name, e);
+        }
+    }
+}
+
+/// Reads one request, answers it with the handler and closes the connection.
+pub fn handle_connection(
+    name: &str,
+    mut stream: TcpStream,
+    handler: fn(&HttpRequest) -> HttpResponse,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let request = read_request(stream.try_clone()?)?;
+    let response = handler(&request);
+    info!(
+        "{} {} {} -> {}",
+        name, request.method, request.path, response.status
+    );
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+fn read_request(stream: impl Read) -> io::Result<HttpRequest> {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_LEN));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+        header.clear();
+    }
+    let mut body = Vec::new();
+    reader.take(content_length).read_to_end(&mut body)?;
+
+    let mut parts = request_line.split_whitespace();
+    Ok(HttpRequest {
+        method: parts.next().unwrap_or("").to_string(),
+        path: parts.next().unwrap_or("").to_string(),
+        body: String::from_utf8_lossy(&body).into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn echo(request: &HttpRequest) -> HttpResponse {
+        HttpResponse::json(
+            "200 OK",
+            json!({ "method": request.method, "path": request.path, "body": request.body }),
+        )
+    }
+
+    #[test]
+    fn test_requests_are_served_by_the_workers() {
+        let address = serve("Test API", "127.0.0.1:0".parse().unwrap(), echo).unwrap();
+        let clients: Vec<_> = (0..WORKERS + 2)
+            .map(|n| {
+                thread::spawn(move || {
+                    let mut client = TcpStream::connect(address).unwrap();
+                    let body = format!("{{\"n\": {}}}", n);
+                    write!(
+                        client,
+                        "POST /orders?n={} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                        n,
+                        body.len(),
+                        body
+                    )
+                    .unwrap();
+                    let mut response = String::new();
+                    client.read_to_string(&mut response).unwrap();
+                    (n, body, response)
+                })
+            })
+            .collect();
+        for client in clients {
+            let (n, body, response) = client.join().unwrap();
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+            let echoed: Value =
+                serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+            assert_eq!(echoed["method"], "POST");
+            assert_eq!(echoed["path"], format!("/orders?n={}", n));
+            assert_eq!(echoed["body"], body);
+        }
+    }
+
+    #[test]
+    fn test_request_is_bounded() {
+        let request = format!(
+            "GET / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            MAX_REQUEST_LEN * 2,
+            "x".repeat(MAX_REQUEST_LEN as usize * 2)
+        );
+        let request = read_request(request.as_bytes()).unwrap();
+        assert_eq!(request.method, "GET");
+        assert!(request.body.len() < MAX_REQUEST_LEN as usize);
+    }
+}
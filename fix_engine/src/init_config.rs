@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, Error, ErrorKind, Write};
+use std::path::Path;
+
+// The dictionaries are embedded so `init` works from any directory.
+const FIX42_DICTIONARY: &str = include_str!("../reference/FIX4_2.xml");
+const FIX42_PAYLOAD_DICTIONARY: &str = include_str!("../reference/FIX4_2_Payload.xml");
+const FIX44_DICTIONARY: &str = include_str!("../reference/FIX4_4.xml");
+const FIX44_PAYLOAD_DICTIONARY: &str = include_str!("../reference/FIX4_4_Payload.xml");
+const PREDEFINED_MSG: &str = include_str!("../reference/predefined_msg.json");
+
+/// Settings collected from the command line flags or the interactive prompts.
+#[derive(Debug, Clone, PartialEq)]
+struct InitOptions {
+    role: String,
+    host: String,
+    port: u16,
+    sender_comp_id: String,
+    target_comp_id: String,
+    fix_version: String,
+    heart_bt_int: u64,
+}
+
+/// Entry point of `fix_engine init [--role initiator|acceptor] [--host H] [--port P]
+/// [--sender-comp-id ID] [--target-comp-id ID] [--fix-version 4.2|4.4] [--heart-bt-int N]
+/// [--dir PATH] [--defaults] [--force]`.
+/// Settings not given as flags are asked for interactively unless `--defaults` is passed.
+pub fn run_init(cwd: &Path, args: &[String]) -> io::Result<()> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    run_init_with_input(cwd, args, &mut input)
+}
+
+fn run_init_with_input<R: BufRead>(cwd: &Path, args: &[String], input: &mut R) -> io::Result<()> {
+    let flags = parse_args(args)?;
+    let target_dir = match flags.get("dir") {
+        Some(dir) => cwd.join(dir),
+        None => cwd.to_path_buf(),
+    };
+
+    let config_file_path = target_dir.join("config").join("setting.conf");
+    if config_file_path.exists() && !flags.contains_key("force") {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!(
+                "{} already exists, use --force to overwrite it.",
+                config_file_path.display()
+            ),
+        ));
+    }
+
+    let options = collect_options(&flags, input)?;
+    write_files(&target_dir, &options)?;
+
+    println!("Wrote {}", config_file_path.display());
+    println!(
+        "Copied FIX {} dictionaries to {}",
+        options.fix_version,
+        target_dir.join("reference").display()
+    );
+    Ok(())
+}
+
+/// Parses `--key value`, `--key=value` and the bare `--defaults` / `--force` switches.
+fn parse_args(args: &[String]) -> io::Result<HashMap<String, String>> {
+    let mut flags = HashMap::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let name = arg.strip_prefix("--").ok_or_else(|| {
+            Error::new(ErrorKind::InvalidInput, format!("Unexpected argument: {}", arg))
+        })?;
+        if name == "defaults" || name == "force" {
+            flags.insert(name.to_string(), "true".to_string());
+        } else if let Some((key, value)) = name.split_once('=') {
+            flags.insert(key.to_string(), value.to_string());
+        } else {
+            let value = iter.next().ok_or_else(|| {
+                Error::new(ErrorKind::InvalidInput, format!("Missing value for --{}", name))
+            })?;
+            flags.insert(name.to_string(), value.to_string());
+        }
+    }
+    Ok(flags)
+}
+
+fn collect_options<R: BufRead>(
+    flags: &HashMap<String, String>,
+    input: &mut R,
+) -> io::Result<InitOptions> {
+    let interactive = !flags.contains_key("defaults");
+    let mut ask = |key: &str, label: &str, default: &str| -> io::Result<String> {
+        match flags.get(key) {
+            Some(value) => Ok(value.clone()),
+            None if interactive => prompt(input, label, default),
+            None => Ok(default.to_string()),
+        }
+    };
+
+    let role = ask("role", "Role (initiator/acceptor)", "initiator")?;
+    if role != "initiator" && role != "acceptor" {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Role must be initiator or acceptor: {}", role),
+        ));
+    }
+    let is_initiator = role == "initiator";
+
+    let host = ask(
+        "host",
+        if is_initiator {
+            "Counterparty host"
+        } else {
+            "Listen address"
+        },
+        "127.0.0.1",
+    )?;
+    let port = ask("port", "Port", "9999")?
+        .parse()
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("Invalid port: {}", e)))?;
+    let (default_sender, default_target) = if is_initiator {
+        ("FIX_Engine", "XYZExchange")
+    } else {
+        ("XYZExchange", "FIX_Engine")
+    };
+    let sender_comp_id = ask("sender-comp-id", "SenderCompID (this engine)", default_sender)?;
+    let target_comp_id = ask("target-comp-id", "TargetCompID (counterparty)", default_target)?;
+    let fix_version = ask("fix-version", "FIX version (4.2/4.4)", "4.2")?;
+    if fix_version != "4.2" && fix_version != "4.4" {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Unsupported FIX version: {}", fix_version),
+        ));
+    }
+    let heart_bt_int = ask("heart-bt-int", "Heartbeat interval in seconds", "30")?
+        .parse()
+        .map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid heartbeat interval: {}", e),
+            )
+        })?;
+
+    Ok(InitOptions {
+        role,
+        host,
+        port,
+        sender_comp_id,
+        target_comp_id,
+        fix_version,
+        heart_bt_int,
+    })
+}
+
+/// Asks for a value on stdout, returning the default when the answer is empty.
+fn prompt<R: BufRead>(input: &mut R, label: &str, default: &str) -> io::Result<String> {
+    print!("{} [{}]: ", label, default);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    let answer = answer.trim();
+    if answer.is_empty() {
+        Ok(default.to_string())
+    } else {
+        Ok(answer.to_string())
+    }
+}
+
+fn dictionary_file_names(fix_version: &str) -> (String, String) {
+    let version = fix_version.replace('.', "_");
+    (
+        format!("FIX{}.xml", version),
+        format!("FIX{}_Payload.xml", version),
+    )
+}
+
+fn render_config(options: &InitOptions) -> String {
+    let is_initiator = options.role == "initiator";
+    let (dictionary, payload_dictionary) = dictionary_file_names(&options.fix_version);
+    let socket_settings = if is_initiator {
+        format!(
+            "socket_connect_port={}\nsocket_connect_host={}",
+            options.port, options.host
+        )
+    } else {
+        format!(
+            "socket_accept_port={}\nsocket_accept_address={}",
+            options.port, options.host
+        )
+    };
+
+    format!(
+        "# default settings for sessions
+[default]
+connection_type={}
+enable_cmd_line={}
+
+# session definition
+[session]
+reconnect_interval=30
+heart_bt_int={}
+{}
+
+use_data_dictionary=Y
+data_dictionary=reference/{}
+data_payload_dictionary=reference/{}
+admin_messages=logon,logout,heartbeat,test_request,resend_request,sequence_reset
+
+sequence_store=data/sequence.json
+order_store=data/order_store.dat
+",
+        options.role, is_initiator, options.heart_bt_int, socket_settings, dictionary, payload_dictionary
+    )
+}
+
+/// Fills the BeginString, CompIDs and HeartBtInt of the predefined message templates.
+fn render_predefined_msg(options: &InitOptions) -> io::Result<String> {
+    let mut templates = json::parse(PREDEFINED_MSG)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+    templates["header"]["BeginString"] = format!("FIX.{}", options.fix_version).into();
+    templates["header"]["SenderCompID"] = options.sender_comp_id.clone().into();
+    templates["header"]["TargetCompID"] = options.target_comp_id.clone().into();
+    templates["admin"]["Logon"]["HeartBtInt"] = options.heart_bt_int.to_string().into();
+    Ok(templates.pretty(2))
+}
+
+fn write_files(target_dir: &Path, options: &InitOptions) -> io::Result<()> {
+    let config_dir = target_dir.join("config");
+    let reference_dir = target_dir.join("reference");
+    fs::create_dir_all(&config_dir)?;
+    fs::create_dir_all(&reference_dir)?;
+    fs::create_dir_all(target_dir.join("data"))?;
+    fs::create_dir_all(target_dir.join("logs"))?;
+
+    fs::write(config_dir.join("setting.conf"), render_config(options))?;
+
+    let (dictionary, payload_dictionary) = dictionary_file_names(&options.fix_version);
+    let (dictionary_content, payload_content) = if options.fix_version == "4.4" {
+        (FIX44_DICTIONARY, FIX44_PAYLOAD_DICTIONARY)
+    } else {
+        (FIX42_DICTIONARY, FIX42_PAYLOAD_DICTIONARY)
+    };
+    fs::write(reference_dir.join(dictionary), dictionary_content)?;
+    fs::write(reference_dir.join(payload_dictionary), payload_content)?;
+    fs::write(
+        reference_dir.join("predefined_msg.json"),
+        render_predefined_msg(options)?,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::load_config;
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let flags = parse_args(&args(&["--role", "acceptor", "--port=9000", "--force"])).unwrap();
+        assert_eq!(flags.get("role").unwrap(), "acceptor");
+        assert_eq!(flags.get("port").unwrap(), "9000");
+        assert_eq!(flags.get("force").unwrap(), "true");
+
+        assert!(parse_args(&args(&["--role"])).is_err());
+        assert!(parse_args(&args(&["role"])).is_err());
+    }
+
+    #[test]
+    fn test_collect_options_interactive() {
+        let flags = parse_args(&args(&["--role", "acceptor"])).unwrap();
+        let mut input = Cursor::new("0.0.0.0\n\nEXCH\n\n4.4\n\n");
+        let options = collect_options(&flags, &mut input).unwrap();
+
+        assert_eq!(options.role, "acceptor");
+        assert_eq!(options.host, "0.0.0.0");
+        assert_eq!(options.port, 9999);
+        assert_eq!(options.sender_comp_id, "EXCH");
+        assert_eq!(options.target_comp_id, "FIX_Engine");
+        assert_eq!(options.fix_version, "4.4");
+        assert_eq!(options.heart_bt_int, 30);
+    }
+
+    #[test]
+    fn test_collect_options_invalid_role() {
+        let flags = parse_args(&args(&["--role", "broker", "--defaults"])).unwrap();
+        assert!(collect_options(&flags, &mut Cursor::new("")).is_err());
+    }
+
+    #[test]
+    fn test_run_init_writes_loadable_config() {
+        let dir = tempdir().unwrap();
+        let result = run_init_with_input(
+            dir.path(),
+            &args(&["--role", "initiator", "--port", "9100", "--defaults"]),
+            &mut Cursor::new(""),
+        );
+        assert!(result.is_ok());
+
+        let config = load_config(&dir.path().join("config").join("setting.conf")).unwrap();
+        let session = config.get("session").unwrap();
+        assert_eq!(session.get("socket_connect_port").unwrap(), "9100");
+        assert_eq!(session.get("data_dictionary").unwrap(), "reference/FIX4_2.xml");
+        assert!(dir.path().join("reference").join("FIX4_2.xml").exists());
+        assert!(dir.path().join("reference").join("FIX4_2_Payload.xml").exists());
+        assert!(dir.path().join("data").is_dir());
+
+        let templates = fs::read_to_string(dir.path().join("reference").join("predefined_msg.json"))
+            .unwrap();
+        let templates = json::parse(&templates).unwrap();
+        assert_eq!(templates["header"]["SenderCompID"], "FIX_Engine");
+        assert_eq!(templates["admin"]["Logon"]["HeartBtInt"], "30");
+    }
+
+    #[test]
+    fn test_run_init_refuses_to_overwrite() {
+        let dir = tempdir().unwrap();
+        let init_args = args(&["--defaults"]);
+        run_init_with_input(dir.path(), &init_args, &mut Cursor::new("")).unwrap();
+
+        assert!(run_init_with_input(dir.path(), &init_args, &mut Cursor::new("")).is_err());
+        assert!(run_init_with_input(
+            dir.path(),
+            &args(&["--defaults", "--force"]),
+            &mut Cursor::new("")
+        )
+        .is_ok());
+    }
+}
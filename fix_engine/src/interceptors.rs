@@ -0,0 +1,498 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, RwLock};
+
+use log::info;
+
+use crate::masking::mask_message;
+use crate::scripting::{ScriptFailure, ScriptInterceptor};
+use crate::sequence::SessionId;
+use crate::tag_value::{checksum, length_tag, parse_tag, DATA_FIELDS};
+use crate::transport::{message_length, Transport, MAX_MESSAGE_LEN};
+
+const SOH: u8 = 0x01;
+
+lazy_static! {
+    /// The interceptors of the sessions with each counterparty (lower-cased CompID), and under
+    /// "" those of every other session.
+    pub static ref INTERCEPTORS: RwLock<HashMap<String, Arc<InterceptorChain>>> =
+        RwLock::new(HashMap::new());
+}
+
+/// Whether an intercepted message goes on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Pass,
+    Drop,
+}
+
+/// The fields of a message passing the interceptors, the Length of its DATA fields, BodyLength
+/// and CheckSum computed again once they are done.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterceptedMessage {
+    fields: Vec<(u32, Vec<u8>)>,
+}
+
+impl InterceptedMessage {
+    /// Splits the message at each SOH, the value of a DATA field being as long as its Length
+    /// field says, whatever bytes it holds.
+    pub fn parse(message: &[u8]) -> Result<Self, String> {
+        let mut fields = Vec::new();
+        let mut position = 0;
+        // The DATA field announced by the last Length field, and its length
+        let mut data_length: Option<(u32, usize)> = None;
+        while position < message.len() {
+            let rest = &message[position..];
+            if rest[0] == SOH {
+                position += 1;
+                continue;
+            }
+            let invalid = || format!("Invalid field at {}", position);
+            let equals = rest
+                .iter()
+                .take_while(|&&byte| byte != SOH)
+                .position(|&byte| byte == b'=')
+                .ok_or_else(invalid)?;
+            let tag = parse_tag(&rest[..equals]).ok_or_else(invalid)?;
+            let value_start = equals + 1;
+            let value_end = match data_length.take() {
+                Some((data_tag, length)) if data_tag == tag => value_start + length,
+                _ => rest[value_start..]
+                    .iter()
+                    .position(|&byte| byte == SOH)
+                    .map_or(rest.len(), |end| value_start + end),
+            };
+            if value_end > rest.len() || rest.get(value_end).is_some_and(|&byte| byte != SOH) {
+                return Err(format!("Tag {} is not as long as its Length field", tag));
+            }
+            let value = &rest[value_start..value_end];
+            if let Some((data_tag, _)) = DATA_FIELDS.iter().find(|(_, length)| *length == tag) {
+                let length = std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|length| length.parse().ok())
+                    .ok_or_else(invalid)?;
+                data_length = Some((*data_tag, length));
+            }
+            fields.push((tag, value.to_vec()));
+            position += value_end + 1;
+        }
+        Ok(Self { fields })
+    }
+
+    /// The value of the tag, None when absent or not text, e.g. binary DATA.
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(number, _)| *number == tag)
+            .and_then(|(_, value)| std::str::from_utf8(value).ok())
+    }
+
+    /// Replaces the value of the tag, or adds the tag at the end of the message.
+    pub fn set(&mut self, tag: u32, value: &str) {
+        match self.fields.iter_mut().find(|(number, _)| *number == tag) {
+            Some((_, held)) => *held = value.as_bytes().to_vec(),
+            None => self.fields.push((tag, value.as_bytes().to_vec())),
+        }
+    }
+
+    pub fn remove(&mut self, tag: u32) {
+        self.fields.retain(|(number, _)| *number != tag);
+    }
+
+    /// The SOH delimited message, each DATA field preceded by its Length.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (tag, value) in &self.fields {
+            let is_length = DATA_FIELDS.iter().any(|(_, length)| length == tag);
+            if matches!(tag, 8..=10) || is_length {
+                continue;
+            }
+            if let Some(length_tag) = length_tag(*tag) {
+                body.extend_from_slice(format!("{}={}\x01", length_tag, value.len()).as_bytes());
+            }
+            body.extend_from_slice(format!("{}=", tag).as_bytes());
+            body.extend_from_slice(value);
+            body.push(SOH);
+        }
+        let begin_string = self.get(8).unwrap_or_default();
+        let mut message = format!("8={}\x019={}\x01", begin_string, body.len()).into_bytes();
+        message.extend_from_slice(&body);
+        let checksum = checksum(&message);
+        message.extend_from_slice(format!("10={:03}\x01", checksum).as_bytes());
+        message
+    }
+
+    /// A SequenceReset-GapFill with the MsgSeqNum of the message, standing in for it so the
+    /// session moves past it. None when the message has no MsgSeqNum.
+    fn gap_fill(&self) -> Option<Self> {
+        let msg_seq_num: u64 = self.get(34)?.parse().ok()?;
+        let mut gap_fill = Self { fields: Vec::new() };
+        // BeginString, SenderCompID, TargetCompID, MsgSeqNum, PossDupFlag, SendingTime and
+        // OrigSendingTime
+        for tag in [8, 49, 56, 34, 43, 52, 122] {
+            if let Some((_, value)) = self.fields.iter().find(|(number, _)| *number == tag) {
+                gap_fill.fields.push((tag, value.clone()));
+            }
+        }
+        gap_fill.fields.insert(1, (35, b"4".to_vec()));
+        gap_fill.set(123, "Y");
+        gap_fill.set(36, &(msg_seq_num + 1).to_string());
+        Some(gap_fill)
+    }
+}
+
+/// Inspects, changes or drops the messages of a session, e.g. to enrich them with tags, map
+/// symbols or tag them for compliance.
+pub trait Interceptor: Send + Sync {
+    fn intercept(&self, message: &mut InterceptedMessage) -> Verdict;
+}
+
+/// An interceptor of the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterceptorRule {
+    /// `set <tag>=<value>`
+    Set(u32, String),
+    /// `remove <tag>`
+    Remove(u32),
+    /// `map <tag> <from>=<to>`
+    Map(u32, String, String),
+    /// `drop <tag>=<value>`
+    Drop(u32, String),
+}
+
+impl InterceptorRule {
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid interceptor {}", rule);
+        let tag = |tag: &str| tag.trim().parse::<u32>().map_err(|_| invalid());
+        let assignment = |assignment: &str| {
+            assignment
+                .split_once('=')
+                .map(|(left, right)| (left.trim().to_string(), right.trim().to_string()))
+                .ok_or_else(invalid)
+        };
+        let (kind, arguments) = rule.trim().split_once(' ').ok_or_else(invalid)?;
+        match kind {
+            "set" => {
+                let (field, value) = assignment(arguments)?;
+                Ok(InterceptorRule::Set(tag(&field)?, value))
+            }
+            "remove" => Ok(InterceptorRule::Remove(tag(arguments)?)),
+            "map" => {
+                let (field, mapping) = arguments.trim().split_once(' ').ok_or_else(invalid)?;
+                let (from, to) = assignment(mapping)?;
+                Ok(InterceptorRule::Map(tag(field)?, from, to))
+            }
+            "drop" => {
+                let (field, value) = assignment(arguments)?;
+                Ok(InterceptorRule::Drop(tag(&field)?, value))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Interceptor for InterceptorRule {
+    fn intercept(&self, message: &mut InterceptedMessage) -> Verdict {
+        match self {
+            InterceptorRule::Set(tag, value) => message.set(*tag, value),
+            InterceptorRule::Remove(tag) => message.remove(*tag),
+            InterceptorRule::Map(tag, from, to) => {
+                if message.get(*tag) == Some(from.as_str()) {
+                    message.set(*tag, to);
+                }
+            }
+            InterceptorRule::Drop(tag, value) => {
+                if message.get(*tag) == Some(value.as_str()) {
+                    return Verdict::Drop;
+                }
+            }
+        }
+        Verdict::Pass
+    }
+}
+
+/// The interceptors of a session, each direction run in order until one drops the message.
+#[derive(Default)]
+pub struct InterceptorChain {
+    pub inbound: Vec<Box<dyn Interceptor>>,
+    pub outbound: Vec<Box<dyn Interceptor>>,
+}
+
+impl InterceptorChain {
+    /// Parses the `inbound.<n>` and `outbound.<n>` entries of the section in the order of n,
+    /// each a rule or `script <file> [fail-open|fail-closed]` running a Rhai script.
+    pub fn parse(section: &HashMap<String, String>) -> Result<Self, String> {
+        let mut inbound = Vec::new();
+        let mut outbound = Vec::new();
+        for (key, rule) in section {
+            let (numbered, number) = match key.split_once('.') {
+                Some(("inbound", number)) => (&mut inbound, number),
+                Some(("outbound", number)) => (&mut outbound, number),
+                _ => return Err(format!("Unknown interceptor key {}", key)),
+            };
+            let number: u32 = number
+                .parse()
+                .map_err(|_| format!("Invalid interceptor number {}", key))?;
+            let interceptor: Box<dyn Interceptor> = match rule.trim().strip_prefix("script ") {
+                Some(script) => {
+                    let mut words = script.split_whitespace();
+                    let path = words.next().unwrap_or_default();
+                    let on_failure = match words.next() {
+                        Some(option) => ScriptFailure::parse(option)?,
+                        None => ScriptFailure::default(),
+                    };
+                    Box::new(ScriptInterceptor::load(path, on_failure)?)
+                }
+                None => Box::new(InterceptorRule::parse(rule)?),
+            };
+            numbered.push((number, interceptor));
+        }
+        let ordered = |mut numbered: Vec<(u32, Box<dyn Interceptor>)>| {
+            numbered.sort_by_key(|(number, _)| *number);
+            numbered
+                .into_iter()
+                .map(|(_, interceptor)| interceptor)
+                .collect()
+        };
+        Ok(Self {
+            inbound: ordered(inbound),
+            outbound: ordered(outbound),
+        })
+    }
+
+    /// The message once intercepted, None when dropped. A message which does not parse is
+    /// left for the session to reject.
+    fn run(
+        interceptors: &[Box<dyn Interceptor>],
+        direction: &str,
+        message: &[u8],
+    ) -> Option<Vec<u8>> {
+        let Ok(mut intercepted) = InterceptedMessage::parse(message) else {
+            return Some(message.to_vec());
+        };
+        for interceptor in interceptors {
+            if interceptor.intercept(&mut intercepted) == Verdict::Drop {
+                let message = String::from_utf8_lossy(message);
+                info!(
+                    "{} message dropped by an interceptor: {}",
+                    direction,
+                    mask_message(&message)
+                );
+                return None;
+            }
+        }
+        Some(intercepted.to_bytes())
+    }
+}
+
+/// A transport passing the messages through the interceptors of its session, once read and,
+/// through `intercept_outbound`, before they take their MsgSeqNum. An inbound message dropped
+/// is read as a SequenceReset-GapFill in its place, so the session expects the next one
+/// instead of asking for it again.
+struct InterceptedTransport {
+    inner: Box<dyn Transport>,
+    chain: Arc<InterceptorChain>,
+    /// What has been read of the messages not intercepted yet.
+    received: Vec<u8>,
+    /// What is left of the last message intercepted, for reads into a smaller buffer.
+    pending: Vec<u8>,
+}
+
+impl Read for InterceptedTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pending.is_empty() {
+            let length = match message_length(&self.received) {
+                Some(length) => length,
+                // Bytes which never become a message are left for the session to give up on
+                None if self.received.len() > MAX_MESSAGE_LEN => {
+                    self.pending = std::mem::take(&mut self.received);
+                    break;
+                }
+                None => {
+                    let length = self.inner.read(buf)?;
+                    if length == 0 {
+                        // What is left of the connection is passed on as it is
+                        self.pending = std::mem::take(&mut self.received);
+                        if self.pending.is_empty() {
+                            return Ok(0);
+                        }
+                        break;
+                    }
+                    self.received.extend_from_slice(&buf[..length]);
+                    continue;
+                }
+            };
+            let message: Vec<u8> = self.received.drain(..length).collect();
+            self.pending = InterceptorChain::run(&self.chain.inbound, "Inbound", &message)
+                .or_else(|| {
+                    let gap_fill = InterceptedMessage::parse(&message).ok()?.gap_fill()?;
+                    Some(gap_fill.to_bytes())
+                })
+                .unwrap_or_default();
+        }
+        let length = buf.len().min(self.pending.len());
+        buf[..length].copy_from_slice(&self.pending[..length]);
+        self.pending.drain(..length);
+        Ok(length)
+    }
+}
+
+impl Write for InterceptedTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Transport for InterceptedTransport {
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(Self {
+            inner: self.inner.try_clone_transport()?,
+            chain: Arc::clone(&self.chain),
+            received: Vec::new(),
+            pending: Vec::new(),
+        }))
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.inner.close()
+    }
+
+    fn peer(&self) -> String {
+        self.inner.peer()
+    }
+
+    fn intercept_outbound<'a>(&self, message: &'a str) -> Option<Cow<'a, str>> {
+        if self.chain.outbound.is_empty() {
+            return Some(Cow::Borrowed(message));
+        }
+        InterceptorChain::run(&self.chain.outbound, "Outbound", message.as_bytes())
+            .map(|message| Cow::Owned(String::from_utf8_lossy(&message).into_owned()))
+    }
+}
+
+/// Wraps the connection of a session so its messages pass the interceptors configured for its
+/// counterparty, or for every session, if any.
+pub fn with_interceptors(stream: Box<dyn Transport>, session_id: &SessionId) -> Box<dyn Transport> {
+    let interceptors = INTERCEPTORS.read().unwrap();
+    let chain = interceptors
+        .get(&session_id.target_comp_id.to_lowercase())
+        .or_else(|| interceptors.get(""));
+    match chain {
+        Some(chain) => Box::new(InterceptedTransport {
+            inner: stream,
+            chain: Arc::clone(chain),
+            received: Vec::new(),
+            pending: Vec::new(),
+        }),
+        None => stream,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_converter::append_fields;
+    use crate::transport::MemoryTransport;
+
+    #[test]
+    fn test_intercepted_transport() {
+        let section: HashMap<String, String> = [
+            ("outbound.2", "set 1=ACC1"),
+            ("outbound.1", "drop 35=B"),
+            ("inbound.1", "map 55 IBM.N=IBM"),
+            ("inbound.2", "remove 5001"),
+            ("inbound.3", "drop 35=B"),
+        ]
+        .iter()
+        .map(|(key, rule)| (key.to_string(), rule.to_string()))
+        .collect();
+        let chain = Arc::new(InterceptorChain::parse(&section).unwrap());
+        assert!(
+            InterceptorChain::parse(&[("rule.1".to_string(), "set 1=A".to_string())].into())
+                .is_err()
+        );
+        assert!(InterceptorRule::parse("map 55 IBM.N").is_err());
+
+        let (local, mut remote) = MemoryTransport::pair();
+        let mut transport = InterceptedTransport {
+            inner: Box::new(local),
+            chain,
+            received: Vec::new(),
+            pending: Vec::new(),
+        };
+        let message = |fields: &[(u32, &str)]| {
+            let fields: Vec<(u32, String)> = fields
+                .iter()
+                .map(|(tag, value)| (*tag, value.to_string()))
+                .collect();
+            append_fields("8=FIX.4.2|", &fields).replace('|', "\x01")
+        };
+
+        // The News is dropped, the order enriched
+        let news = message(&[(35, "B"), (148, "Closing")]);
+        let order = message(&[(35, "D"), (11, "1"), (55, "IBM.N")]);
+        assert_eq!(transport.intercept_outbound(&news), None);
+        assert_eq!(
+            transport.intercept_outbound(&order).unwrap(),
+            message(&[(35, "D"), (11, "1"), (55, "IBM.N"), (1, "ACC1")])
+        );
+        let mut buf = [0; 1024];
+
+        // The symbol is mapped back
+        remote
+            .write_all(message(&[(35, "8"), (55, "IBM.N"), (5001, "X")]).as_bytes())
+            .unwrap();
+        let length = transport.read(&mut buf).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&buf[..length]),
+            message(&[(35, "8"), (55, "IBM")])
+        );
+
+        // Two messages in one read, and one split across reads, are each intercepted
+        let report = message(&[(35, "8"), (55, "IBM.N"), (5001, "X")]);
+        let (start, end) = report.split_at(20);
+        remote
+            .write_all(format!("{}{}{}", report, report, start).as_bytes())
+            .unwrap();
+        let mut read = |transport: &mut InterceptedTransport| {
+            let length = transport.read(&mut buf).unwrap();
+            String::from_utf8_lossy(&buf[..length]).into_owned()
+        };
+        assert_eq!(read(&mut transport), message(&[(35, "8"), (55, "IBM")]));
+        assert_eq!(read(&mut transport), message(&[(35, "8"), (55, "IBM")]));
+        remote.write_all(end.as_bytes()).unwrap();
+        assert_eq!(read(&mut transport), message(&[(35, "8"), (55, "IBM")]));
+
+        // A News dropped is read as a GapFill to the MsgSeqNum after it
+        let news = message(&[(35, "B"), (49, "VENUE"), (56, "ENGINE"), (34, "7"), (148, "Up")]);
+        remote.write_all(news.as_bytes()).unwrap();
+        assert_eq!(
+            read(&mut transport),
+            message(&[(35, "4"), (49, "VENUE"), (56, "ENGINE"), (34, "7"), (123, "Y"), (36, "8")])
+        );
+    }
+
+    #[test]
+    fn test_data_fields_hold_any_byte() {
+        let mut message =
+            InterceptedMessage::parse(b"8=FIX.4.2\x0135=A\x0195=3\x0196=a\x01|\x01553=U|1\x01")
+                .unwrap();
+        assert_eq!(message.get(96), Some("a\x01|"));
+        assert_eq!(message.get(553), Some("U|1"));
+        message.set(96, "token");
+        let bytes = message.to_bytes();
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.starts_with("8=FIX.4.2\x019=27\x0135=A\x0195=5\x0196=token\x01553=U|1\x01"));
+        let checksum = checksum(&bytes[..bytes.len() - 7]);
+        assert!(text.ends_with(&format!("\x0110={:03}\x01", checksum)));
+        let parsed = InterceptedMessage::parse(&bytes).unwrap();
+        assert_eq!((parsed.get(96), parsed.get(553)), (Some("token"), Some("U|1")));
+        // A DATA field shorter than its Length does not parse
+        assert!(InterceptedMessage::parse(b"8=FIX.4.2\x0195=9\x0196=a\x01").is_err());
+    }
+}
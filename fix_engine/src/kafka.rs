@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::io::{self, Error, ErrorKind};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use indexmap::IndexMap;
+use json::JsonValue;
+use log::{error, info};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::message::Message;
+use rdkafka::producer::{BaseRecord, DefaultProducerContext, ThreadedProducer};
+
+use crate::admin_http::ADMIN_SESSIONS;
+use crate::execution_store::ExecDirection;
+use crate::message_converter::fixmsg2msgtype;
+use crate::order_events::{OrderEvent, OrderEventSink, ORDER_EVENTS};
+use crate::session::Session;
+use crate::MessageMap;
+
+lazy_static! {
+    /// Where the application messages are published, None unless the `[kafka]` section is set.
+    pub static ref KAFKA: RwLock<Option<Arc<KafkaBridge>>> = RwLock::new(None);
+}
+
+/// MsgTypes of the session level messages, which are not published.
+const ADMIN_MSG_TYPES: [&str; 7] = ["0", "1", "2", "3", "4", "5", "A"];
+
+/// The `[kafka]` section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KafkaConfig {
+    /// `bootstrap.servers`, comma separated host:port.
+    pub brokers: String,
+    /// Topic of the application messages received.
+    pub inbound_topic: String,
+    /// Topic of the application messages sent.
+    pub outbound_topic: String,
+    /// Topic of the order instructions to send as FIX, none consumed without it.
+    pub order_topic: Option<String>,
+    /// Topic of the normalized order events, none published without it.
+    pub event_topic: Option<String>,
+    pub group_id: String,
+}
+
+impl KafkaConfig {
+    pub fn from_section(section: &HashMap<String, String>) -> io::Result<Self> {
+        let get = |key: &str| {
+            section.get(key).cloned().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("{} not found in the [kafka] section.", key),
+                )
+            })
+        };
+        Ok(KafkaConfig {
+            brokers: get("brokers")?,
+            inbound_topic: get("inbound_topic")?,
+            outbound_topic: get("outbound_topic")?,
+            order_topic: section.get("order_topic").cloned(),
+            event_topic: section.get("event_topic").cloned(),
+            group_id: section
+                .get("group_id")
+                .cloned()
+                .unwrap_or_else(|| "fix_engine".to_string()),
+        })
+    }
+}
+
+/// The application message as JSON: its direction, MsgType and fields by tag name, in the
+/// order of the message.
+pub fn message_json(
+    direction: ExecDirection,
+    msg_type: &str,
+    msg_map: &IndexMap<String, String>,
+) -> String {
+    let mut fields = JsonValue::new_object();
+    for (name, value) in msg_map {
+        fields[name.as_str()] = value.as_str().into();
+    }
+    let direction = match direction {
+        ExecDirection::Sent => "sent",
+        ExecDirection::Received => "received",
+    };
+    json::object! {
+        "direction" => direction,
+        "msg_type" => msg_type,
+        "fields" => fields,
+    }
+    .dump()
+}
+
+/// An order instruction: `{"session": "<BeginString>_<SenderCompID>_<TargetCompID>",
+/// "fields": {"MsgType": "NEW_ORDER_SINGLE", ...}}`, the session optional while only one
+/// runs. Returns the session and the fields, in order.
+pub fn parse_instruction(
+    payload: &str,
+) -> Result<(Option<String>, IndexMap<String, String>), String> {
+    let instruction = json::parse(payload).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let session = instruction["session"].as_str().map(str::to_string);
+    let fields = &instruction["fields"];
+    if !fields.is_object() {
+        return Err("Missing fields".to_string());
+    }
+    let mut msg_map = IndexMap::new();
+    for (name, value) in fields.entries() {
+        let value = match value {
+            JsonValue::Number(_) | JsonValue::Boolean(_) => value.dump(),
+            _ => value
+                .as_str()
+                .ok_or_else(|| format!("Invalid value of {}", name))?
+                .to_string(),
+        };
+        msg_map.insert(name.to_string(), value);
+    }
+    if !msg_map.contains_key("MsgType") {
+        return Err("Missing MsgType".to_string());
+    }
+    Ok((session, msg_map))
+}
+
+/// Publishes the application messages of the sessions to Kafka and sends the order
+/// instructions it consumes as FIX.
+pub struct KafkaBridge {
+    config: KafkaConfig,
+    producer: ThreadedProducer<DefaultProducerContext>,
+    /// Names the fields of the messages published.
+    message_maps: Arc<MessageMap>,
+}
+
+impl KafkaBridge {
+    pub fn connect(config: KafkaConfig, message_maps: Arc<MessageMap>) -> io::Result<Self> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", &config.brokers)
+            .create()
+            .map_err(Error::other)?;
+        Ok(KafkaBridge {
+            config,
+            producer,
+            message_maps,
+        })
+    }
+
+    /// Publishes the raw message to the topic of its direction unless it is session level.
+    /// Messages of an order share its ClOrdID as key, so they stay in order.
+    pub fn publish(&self, direction: ExecDirection, message: &str) {
+        let raw_msg_type = message
+            .split(['\x01', '|'])
+            .find_map(|field| field.strip_prefix("35="))
+            .unwrap_or("");
+        if ADMIN_MSG_TYPES.contains(&raw_msg_type) {
+            return;
+        }
+        let (msg_type, msg_map) =
+            match fixmsg2msgtype(message, &self.message_maps.fix_tag_number_map) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    error!("Message not published to Kafka: {:?}", e);
+                    return;
+                }
+            };
+        let topic = match direction {
+            ExecDirection::Sent => &self.config.outbound_topic,
+            ExecDirection::Received => &self.config.inbound_topic,
+        };
+        let payload = message_json(direction, &msg_type, &msg_map);
+        let key = msg_map.get("ClOrdID").map_or("", String::as_str);
+        let record = BaseRecord::to(topic).payload(&payload).key(key);
+        if let Err((e, _)) = self.producer.send(record) {
+            error!(
+                "Failed to publish {} to Kafka topic {}: {}",
+                msg_type, topic, e
+            );
+        }
+    }
+
+    /// Publishes the order event as JSON to the event topic, keyed by its ClOrdID.
+    fn publish_event(&self, topic: &str, event: &OrderEvent) -> Result<(), String> {
+        let payload = serde_json::to_string(event).map_err(|e| e.to_string())?;
+        let record = BaseRecord::to(topic)
+            .payload(&payload)
+            .key(&event.cl_ord_id);
+        self.producer
+            .send(record)
+            .map_err(|(e, _)| format!("Failed to publish to Kafka topic {}: {}", topic, e))
+    }
+
+    /// Consumes the order topic on its own thread, sending each instruction on its session.
+    fn start_consumer(&self, order_topic: &str) -> io::Result<()> {
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &self.config.brokers)
+            .set("group.id", &self.config.group_id)
+            .create()
+            .map_err(Error::other)?;
+        consumer.subscribe(&[order_topic]).map_err(Error::other)?;
+        thread::spawn(move || loop {
+            let Some(message) = consumer.poll(Duration::from_secs(1)) else {
+                continue;
+            };
+            let payload = match message {
+                Ok(ref message) => match message.payload_view::<str>() {
+                    Some(Ok(payload)) => payload.to_string(),
+                    _ => {
+                        error!("Order instruction without UTF-8 payload ignored");
+                        continue;
+                    }
+                },
+                Err(e) => {
+                    error!("Failed to consume order instructions: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = send_instruction(&payload) {
+                error!("Order instruction {} not sent: {}", payload, e);
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Publishes the order events to the event topic.
+struct KafkaSink {
+    bridge: Arc<KafkaBridge>,
+    topic: String,
+}
+
+impl OrderEventSink for KafkaSink {
+    fn name(&self) -> &str {
+        "kafka"
+    }
+
+    fn on_event(&self, event: &OrderEvent) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(self.bridge.publish_event(&self.topic, event)?)
+    }
+}
+
+/// Sends the order instruction as FIX on its session.
+fn send_instruction(payload: &str) -> Result<(), String> {
+    let (session_key, msg_map) = parse_instruction(payload)?;
+    let sessions = ADMIN_SESSIONS.all();
+    let session = match &session_key {
+        Some(key) => sessions
+            .iter()
+            .find(|session| session.session_id.file_stem() == *key),
+        None if sessions.len() == 1 => sessions.first(),
+        None => return Err(format!("No session given of {} running", sessions.len())),
+    }
+    .ok_or_else(|| format!("Unknown session {:?}", session_key))?;
+    info!(
+        "Sending {:?} {:?} from Kafka on {}",
+        msg_map.get("MsgType"),
+        msg_map.get("ClOrdID"),
+        session.session_id
+    );
+    Session::new(
+        session.session_id.clone(),
+        Arc::clone(&session.stream),
+        Arc::clone(&session.message_maps),
+        Arc::clone(&session.seq_store),
+    )
+    .send_batch(vec![msg_map])
+    .map(|_| ())
+    .map_err(|e| e.to_string())
+}
+
+/// Connects to Kafka, publishing the application messages of every session from now on, the
+/// order events if the event topic is set and consuming the order topic if set.
+pub fn start_kafka(config: KafkaConfig, message_maps: Arc<MessageMap>) -> io::Result<()> {
+    let order_topic = config.order_topic.clone();
+    let event_topic = config.event_topic.clone();
+    let bridge = KafkaBridge::connect(config, message_maps)?;
+    if let Some(order_topic) = order_topic {
+        bridge.start_consumer(&order_topic)?;
+    }
+    info!(
+        ">>>>>> Publishing application messages to Kafka {}",
+        bridge.config.brokers
+    );
+    let bridge = Arc::new(bridge);
+    if let Some(topic) = event_topic {
+        ORDER_EVENTS.register(Box::new(KafkaSink {
+            bridge: Arc::clone(&bridge),
+            topic,
+        }));
+    }
+    *KAFKA.write().unwrap() = Some(bridge);
+    Ok(())
+}
+
+/// Publishes the raw message to Kafka, if configured.
+pub fn publish_message(direction: ExecDirection, message: &str) {
+    if let Some(kafka) = KAFKA.read().unwrap().as_ref() {
+        kafka.publish(direction, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_and_order_instructions_as_json() {
+        let msg_map: IndexMap<String, String> = [("ClOrdID", "1"), ("Side", "BUY")]
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        assert_eq!(
+            message_json(ExecDirection::Received, "NEW_ORDER_SINGLE", &msg_map),
+            r#"{"direction":"received","msg_type":"NEW_ORDER_SINGLE","fields":{"ClOrdID":"1","Side":"BUY"}}"#
+        );
+
+        let (session, fields) = parse_instruction(
+            r#"{"session": "FIX.4.2_CLIENT_EXCH",
+                "fields": {"MsgType": "NEW_ORDER_SINGLE", "ClOrdID": "7", "OrderQty": 100}}"#,
+        )
+        .unwrap();
+        assert_eq!(session.as_deref(), Some("FIX.4.2_CLIENT_EXCH"));
+        assert_eq!(
+            fields.keys().collect::<Vec<_>>(),
+            vec!["MsgType", "ClOrdID", "OrderQty"]
+        );
+        assert_eq!(fields["OrderQty"], "100");
+        assert!(parse_instruction(r#"{"fields": {"ClOrdID": "7"}}"#).is_err());
+        assert!(parse_instruction("not json").is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use log::{error, info, warn};
+use rust_decimal::Decimal;
+
+use crate::admin_http::{AdminSession, ADMIN_SESSIONS};
+use crate::matching::MATCHING_ENGINE;
+use crate::message_handling::{initiate_logout, order_execution_report, send_execution_report};
+use crate::order_events::{OrderEvent, OrderEventKind, ORDER_EVENTS};
+use crate::orderstore::OrderStore;
+use crate::IS_INITIATOR;
+
+lazy_static! {
+    pub static ref KILL_SWITCH: KillSwitch = KillSwitch::default();
+}
+
+/// Engine-wide stop of new orders: while engaged the acceptor rejects every NewOrderSingle
+/// and the command line sends none. Stays engaged until an operator releases it.
+#[derive(Default)]
+pub struct KillSwitch {
+    reason: RwLock<Option<String>>,
+}
+
+impl KillSwitch {
+    /// Why new orders are refused, None when the switch is released.
+    pub fn engaged(&self) -> Option<String> {
+        self.reason.read().unwrap().clone()
+    }
+
+    fn engage(&self, reason: &str) {
+        error!("Kill switch engaged: {}", reason);
+        *self.reason.write().unwrap() = Some(reason.to_string());
+    }
+
+    /// Accepts new orders again. Returns false when the switch was not engaged.
+    pub fn release(&self) -> bool {
+        let released = self.reason.write().unwrap().take().is_some();
+        if released {
+            info!("Kill switch released");
+        }
+        released
+    }
+}
+
+/// What the kill switch does besides refusing new orders.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KillSwitchActions {
+    /// Acceptor only: cancel every open order and report it to the counterparty.
+    pub cancel_orders: bool,
+    /// Log out every session.
+    pub logout: bool,
+}
+
+/// Engages the kill switch and takes its actions on every running session. Returns the
+/// number of open orders canceled.
+pub fn engage_kill_switch(reason: &str, actions: KillSwitchActions) -> usize {
+    KILL_SWITCH.engage(reason);
+    let sessions = ADMIN_SESSIONS.all();
+    let mut canceled = 0;
+    if actions.cancel_orders && !IS_INITIATOR.load(Ordering::SeqCst) {
+        let text = format!("Kill switch: {}", reason);
+        // Resting orders of the matching engine are reported to the session they came from
+        canceled += MATCHING_ENGINE.cancel_all(&text);
+        canceled += mass_cancel(&sessions, None, None, &text);
+        info!("Kill switch canceled {} open orders", canceled);
+    }
+    if actions.logout {
+        for session in sessions {
+            thread::spawn(move || {
+                if let Err(e) = initiate_logout(
+                    &session.stream,
+                    &session.session_id,
+                    &session.message_maps,
+                    &session.seq_store,
+                    Some("Kill switch engaged"),
+                ) {
+                    error!("Failed to log out {}: {}", session.session_id, e);
+                }
+            });
+        }
+    }
+    canceled
+}
+
+/// Cancels the open orders of the account in the matching engine and in the order store of
+/// every running session but `reported_by`, whose session reports its own cancels, as a risk
+/// breach does. Returns the number of orders canceled.
+pub fn cancel_account_orders(account: &str, text: &str, reported_by: &OrderStore) -> usize {
+    let canceled = MATCHING_ENGINE.cancel_account(account, text)
+        + mass_cancel(
+            &ADMIN_SESSIONS.all(),
+            Some(account),
+            Some(reported_by),
+            text,
+        );
+    if canceled > 0 {
+        info!(
+            "Canceled {} open orders of account {} across the sessions",
+            canceled, account
+        );
+    }
+    canceled
+}
+
+/// Cancels the open orders, only those of `account` when given, of every order store of the
+/// sessions but `skip`. Returns the number of orders canceled.
+fn mass_cancel(
+    sessions: &[Arc<AdminSession>],
+    account: Option<&str>,
+    skip: Option<&OrderStore>,
+    text: &str,
+) -> usize {
+    let mut canceled = 0;
+    let mut order_stores: Vec<&Arc<OrderStore>> = Vec::new();
+    for session in sessions {
+        if skip.is_some_and(|skip| std::ptr::eq(Arc::as_ptr(&session.order_store), skip))
+            || order_stores
+                .iter()
+                .any(|order_store| Arc::ptr_eq(order_store, &session.order_store))
+        {
+            continue;
+        }
+        order_stores.push(&session.order_store);
+        let sharing = sessions
+            .iter()
+            .filter(|other| Arc::ptr_eq(&other.order_store, &session.order_store))
+            .count();
+        canceled += cancel_open_orders(session, account, sharing, text);
+    }
+    canceled
+}
+
+/// Cancels the open orders of the session's order store, only those of `account` when given.
+/// They are reported over the session unless other sessions share the store, as an order does
+/// not record the session it came from.
+fn cancel_open_orders(
+    session: &AdminSession,
+    account: Option<&str>,
+    sharing: usize,
+    text: &str,
+) -> usize {
+    let orders = match account {
+        Some(account) => session.order_store.cancel_account_orders(account),
+        None => session.order_store.cancel_all_orders(),
+    };
+    let orders = match orders {
+        Ok(orders) => orders,
+        Err(e) => {
+            error!("Failed to cancel the open orders: {}", e);
+            return 0;
+        }
+    };
+    if sharing > 1 && !orders.is_empty() {
+        warn!(
+            "{} orders canceled without ExecutionReports, {} sessions share their order store",
+            orders.len(),
+            sharing
+        );
+    }
+    for order in &orders {
+        let mut event = OrderEvent::from_order(OrderEventKind::Canceled, order);
+        event.text = Some(text.to_string());
+        ORDER_EVENTS.publish(&event);
+        if sharing > 1 {
+            continue;
+        }
+        let exec_id = session.seq_store.next_exec_id();
+        let mut override_map =
+            order_execution_report(order, &exec_id, "4", Decimal::ZERO, Decimal::ZERO);
+        override_map.insert("Text".to_string(), text.to_string());
+        if let Err(e) = send_execution_report(
+            &session.stream,
+            &session.message_maps.app_msg.current(),
+            &session.message_maps.fix_tag_name_map,
+            &session.seq_store,
+            &override_map,
+        ) {
+            error!("Failed to report the cancel of order {}: {}", order.id, e);
+        }
+    }
+    orders.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kill_switch_stays_engaged_until_released() {
+        let kill_switch = KillSwitch::default();
+        assert_eq!(kill_switch.engaged(), None);
+        kill_switch.engage("runaway algo");
+        assert_eq!(kill_switch.engaged().as_deref(), Some("runaway algo"));
+        assert!(kill_switch.release());
+        assert!(!kill_switch.release());
+        assert_eq!(kill_switch.engaged(), None);
+    }
+}
@@ -0,0 +1,146 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Error};
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::Local;
+use tracing::{info_span, Span};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::masking::mask_message;
+use crate::sequence::SessionId;
+
+/// Direction of the messages handled inside a session span.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Inbound => "inbound",
+            Direction::Outbound => "outbound",
+        }
+    }
+}
+
+/// Installs the tracing subscriber: readable lines on stdout and JSON lines, one object per
+/// event with its span fields, in `logs/`. Records of the `log` macros used across the engine
+/// are forwarded to it, so they carry the fields of the session span they are logged in.
+/// The level is read from LOG_LEVEL, info by default.
+pub fn init_logging() -> io::Result<()> {
+    let level = env::var("LOG_LEVEL")
+        .ok()
+        .and_then(|level| level.parse::<LevelFilter>().ok())
+        .unwrap_or(LevelFilter::INFO);
+    let directory = Path::new("logs");
+    fs::create_dir_all(directory)?;
+    let file = File::create(directory.join(format!(
+        "fix_engine_{}.log",
+        Local::now().format("%Y-%m-%d_%H-%M-%S")
+    )))?;
+
+    tracing_subscriber::registry()
+        .with(level)
+        .with(fmt::layer().with_thread_ids(true).with_target(false))
+        .with(
+            fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_thread_ids(true)
+                .with_writer(Mutex::new(file)),
+        )
+        .try_init()
+        .map_err(Error::other)
+}
+
+/// Span of one direction of a session. Threads enter it for their lifetime.
+pub fn session_span(session_id: &SessionId, direction: Direction) -> Span {
+    info_span!(
+        "session",
+        session_id = %session_id,
+        direction = direction.as_str()
+    )
+}
+
+/// Logs a raw FIX message, masked, with its MsgType, MsgSeqNum and ClOrdID as fields.
+pub fn log_message(description: &str, message: &str) {
+    let message = mask_message(message);
+    let field = |prefix: &str| {
+        message
+            .split('\x01')
+            .find_map(|field| field.strip_prefix(prefix))
+    };
+    tracing::info!(
+        msg_type = field("35="),
+        seq_num = field("34="),
+        cl_ord_id = field("11="),
+        "{}: {}",
+        description,
+        message
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_message_logged_with_session_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_writer(move || writer.clone()),
+        );
+        let session_id = SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "FIX_Engine".to_string(),
+            target_comp_id: "XYZExchange".to_string(),
+        };
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _entered = session_span(&session_id, Direction::Inbound).entered();
+            log_message(
+                "Received message",
+                "8=FIX.4.2\x0135=D\x0134=7\x0111=ORD1\x01",
+            );
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let event: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(event["fields"]["msg_type"], "D");
+        assert_eq!(event["fields"]["seq_num"], "7");
+        assert_eq!(event["fields"]["cl_ord_id"], "ORD1");
+        assert_eq!(
+            event["span"]["session_id"],
+            "FIX.4.2:FIX_Engine->XYZExchange"
+        );
+        assert_eq!(event["span"]["direction"], "inbound");
+    }
+}
@@ -1,26 +1,46 @@
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Utc};
 use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
+/// A time kept as nanoseconds since the unix epoch.
 pub struct AtomicDateTime {
     inner: AtomicU64,
 }
 
 impl AtomicDateTime {
     pub fn new(time: DateTime<Utc>) -> Self {
-        let timestamp = time.timestamp();
         Self {
-            inner: AtomicU64::new(timestamp as u64),
+            inner: AtomicU64::new(Self::nanos(time)),
         }
     }
 
+    fn nanos(time: DateTime<Utc>) -> u64 {
+        time.timestamp_nanos_opt().unwrap_or_default() as u64
+    }
+
     pub fn load(&self, order: Ordering) -> DateTime<Utc> {
-        let timestamp = self.inner.load(order) as i64;
-        Utc.timestamp_opt(timestamp, 0).unwrap()
+        DateTime::from_timestamp_nanos(self.inner.load(order) as i64)
     }
 
     pub fn store(&self, time: DateTime<Utc>, order: Ordering) {
-        let timestamp = time.timestamp() as u64;
-        self.inner.store(timestamp, order);
+        self.inner.store(Self::nanos(time), order);
+    }
+
+    /// The time from the stored time to `now`, zero if `now` is earlier.
+    pub fn elapsed_since(&self, now: DateTime<Utc>) -> Duration {
+        Duration::from_nanos(Self::nanos(now).saturating_sub(self.inner.load(Ordering::SeqCst)))
+    }
+
+    /// The time since the stored time.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed_since(Utc::now())
+    }
+}
+
+impl Default for AtomicDateTime {
+    /// Now.
+    fn default() -> Self {
+        Self::new(Utc::now())
     }
 }
 
@@ -70,3 +90,26 @@ macro_rules! initialize_atomic_datetime {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_date_time_keeps_sub_second_resolution() {
+        let sent = DateTime::parse_from_rfc3339("2024-01-01T00:00:00.250Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let last_sent_time = AtomicDateTime::new(sent);
+        assert_eq!(last_sent_time.load(Ordering::SeqCst), sent);
+
+        let now = sent + chrono::Duration::milliseconds(1500);
+        assert_eq!(
+            last_sent_time.elapsed_since(now),
+            Duration::from_millis(1500)
+        );
+        last_sent_time.store(now, Ordering::SeqCst);
+        assert_eq!(last_sent_time.elapsed_since(sent), Duration::ZERO);
+        assert!(last_sent_time.elapsed() > Duration::ZERO);
+    }
+}
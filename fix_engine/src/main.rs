@@ -6,70 +6,189 @@ use std::sync::atomic::Ordering;
 use std::{
     collections::HashMap,
     env,
-    io::{self, Error, ErrorKind},
-    path::PathBuf,
+    io::{self, Error},
+    path::{Path, PathBuf},
     sync::atomic::{AtomicBool, AtomicU64},
     sync::Arc,
 };
 
-use chrono::Utc;
-use flexi_logger::{Duplicate, FileSpec, Logger};
 use indexmap::IndexMap;
 use log::{error, info};
 
 pub use macros::*;
 
+#[cfg(not(feature = "grpc"))]
+use crate::config::check_grpc_address;
+#[cfg(not(feature = "kafka"))]
+use crate::config::check_kafka_config;
+#[cfg(feature = "grpc")]
+use crate::grpc::start_grpc_server;
+#[cfg(feature = "kafka")]
+use crate::{config::get_kafka_config, kafka::start_kafka};
 use crate::orderstore::OrderStore;
 use crate::{
+    admin_http::start_admin_server,
+    bridge::start_bridge,
     config::{
-        check_config_file_existence, enable_cmd_line, get_connection_details, get_order_store,
-        get_sequence_store, is_initiator, load_config, update_heart_bt_int,
-        update_reconnect_interval,
+        check_config_file_existence, enable_cmd_line, get_bridge_configs, get_connection_details,
+        get_counterparty_configs, get_daily_reset, get_listen_address, get_order_event_sinks,
+        get_order_store, get_predefined_msg_path, get_sequence_flush_policy, get_sequence_store,
+        get_session_hooks, get_session_identity, hot_reload, is_initiator, load_config,
+        matching_engine, reset_on_logon, update_account_throttle, update_batch,
+        update_dont_know_trade, update_duplicate_logon_policy, update_encoding, update_enrichment,
+        update_execution_store, update_heart_bt_int, update_interceptors, update_logon_auth,
+        update_logout_timeout, update_masked_tags, update_max_account_open_qty,
+        update_max_connections, update_message_handlers, update_message_journal,
+        update_order_ack_timeout, update_outbound_queue_size, update_pre_trade_limits, update_proxy,
+        update_qos_log_interval, update_quotes, update_reconnect_interval, update_routing_rules,
+        update_session_behaviour, update_session_schedule, update_sim_clock_skew, update_sim_rng,
+        update_socket_options, update_symbol_master, update_throttle, update_timestamp_precision,
+        update_watchdog_timeout, update_wire_log,
     },
-    connection::{establish_connection, handle_stream, send_logon_message, start_listener},
+    connection::{
+        establish_connection, handle_stream, logout_at_session_close, send_logon_message,
+        start_listener,
+    },
+    console::BATCH_FILE,
+    counterparty::{CounterpartyProfiles, SessionProfile},
+    enrichment::set_field,
+    init_config::run_init,
+    interceptors::with_interceptors,
+    logging::init_logging,
     message_converter::read_json_file,
+    order_events::ORDER_EVENTS,
+    order_gateway::start_order_gateway,
     parse_payload_xml::{parse_fix_payload_xml, FixMsgTag},
     parse_xml::{parse_fix_xml, FixTag},
-    sequence::SequenceNumberStore,
+    sbe::with_sbe_codec,
+    schedule::{start_daily_reset, wait_for_session_open, SESSION_SCHEDULE},
+    sequence::{SequenceStores, SessionId},
+    session::SESSION_STATES,
+    session_events::SESSION_HOOKS,
+    templates::{start_hot_reload, Templates},
+    trading_session::start_trading_session_publisher,
+    websocket::start_websocket_server,
+    wire_log::with_wire_log,
 };
 
+mod admin_http;
+mod allocations;
+mod auth;
+mod bridge;
+mod buffer_pool;
 mod config;
 mod connection;
+mod console;
+mod counterparty;
+mod dont_know_trade;
+mod engine_events;
+mod enrichment;
+mod execution_store;
+mod fixml;
+mod gap_report;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod http_server;
+mod init_config;
+mod interceptors;
+#[cfg(feature = "kafka")]
+mod kafka;
+mod kill_switch;
+mod logging;
 mod macros;
+mod masking;
+mod matching;
 mod message_converter;
+mod message_handlers;
 mod message_handling;
+mod message_journal;
 mod message_validator;
+mod news;
+mod order_events;
+mod order_gateway;
+mod order_journal;
+mod order_lists;
+#[cfg(feature = "sqlite")]
+mod order_sqlite;
 mod orderstore;
+mod outbound;
 mod parse_payload_xml;
 mod parse_xml;
+mod pending_orders;
+mod positions;
+mod proxy;
+mod quotes;
+mod risk;
+mod routing;
+mod sbe;
+mod schedule;
+mod scripting;
+mod security_list;
 mod sequence;
+mod session;
+mod session_events;
+mod session_stats;
+mod sim_clock;
+mod sim_rng;
+mod simulator;
+mod socket_options;
+mod symbol_master;
+mod tag_value;
+mod templates;
+#[cfg(test)]
+mod test_support;
+mod throttle;
+mod timer;
+mod trading_session;
+mod transport;
+mod watchdog;
+mod websocket;
+mod wire_log;
 
 // Define global variables wrapped in Arc<Mutex<>> using custom macros
 initialize_flag!(ENABLE_CMD_LINE, false);
-initialize_flag!(SENT_LOGON, false);
-initialize_flag!(RECEIVED_LOGON, false);
 initialize_flag!(IS_LOGGED_ON, false);
 initialize_flag!(IS_INITIATOR, false);
-initialize_atomic_datetime!(LAST_SENT_TIME);
+initialize_flag!(RESET_ON_LOGON, false);
+initialize_flag!(RECONNECT_REQUESTED, false);
+initialize_flag!(MATCHING_ENGINE_ENABLED, false);
+initialize_flag!(ORDER_ACK_STATUS_REQUEST, false);
+initialize_flag!(DONT_KNOW_TRADE, false);
 initialize_value!(HEART_BT_INT, 15);
 initialize_value!(RECONNECT_INTERVAL, 30);
+initialize_value!(QOS_LOG_INTERVAL, 60);
+initialize_value!(LOGOUT_TIMEOUT, 10);
+initialize_value!(WATCHDOG_TIMEOUT, 0);
+initialize_value!(MAX_ACCOUNT_OPEN_QTY, 0);
+initialize_value!(MAX_CONNECTIONS, 0);
+initialize_value!(OUTBOUND_QUEUE_SIZE, 1000);
+initialize_value!(BATCH_INTERVAL_MS, 0);
+initialize_value!(ORDER_ACK_TIMEOUT_MS, 0);
+initialize_value!(SECURITY_LIST_PAGE_SIZE, 100);
 
 #[derive(Clone)]
 pub struct MessageMap {
     fix_header: IndexMap<String, String>,
     fix_tag_number_map: HashMap<u32, FixTag>,
     admin_msg_list: Vec<String>,
-    admin_msg: HashMap<String, IndexMap<String, String>>,
-    app_msg: HashMap<String, IndexMap<String, String>>,
+    admin_msg: Templates,
+    app_msg: Templates,
     fix_tag_name_map: HashMap<String, FixTag>,
-    msgname_fields_map: HashMap<String, FixMsgTag>,
     msgnumber_fields_map: HashMap<String, FixMsgTag>,
     valid_msg_types: Vec<String>,
     required_fields: Vec<String>,
 }
 
 fn main() -> io::Result<()> {
-    let _ = configure_logger();
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(|arg| arg.as_str()) == Some("init") {
+        return run_init(&env::current_dir()?, &args[2..]);
+    }
+
+    if let Err(e) = init_logging() {
+        eprintln!("Failed to initialize logging: {}", e);
+    }
+    info!("Logger initialized.");
 
     let cwd = env::current_dir()?;
     info!("Current working directory: {}", cwd.display());
@@ -82,68 +201,200 @@ fn main() -> io::Result<()> {
     // Update the ENABLE_CMD_LINE flag
     ENABLE_CMD_LINE.store(enable_cmd_line(&config_map), Ordering::SeqCst);
     IS_INITIATOR.store(is_initiator(&config_map), Ordering::SeqCst);
+    RESET_ON_LOGON.store(reset_on_logon(&config_map), Ordering::SeqCst);
+    MATCHING_ENGINE_ENABLED.store(matching_engine(&config_map), Ordering::SeqCst);
     update_reconnect_interval(&config_map)?;
     update_heart_bt_int(&config_map)?;
+    update_qos_log_interval(&config_map)?;
+    update_logout_timeout(&config_map)?;
+    update_max_account_open_qty(&config_map)?;
+    update_pre_trade_limits(&config_map)?;
+    update_account_throttle(&config_map)?;
+    update_symbol_master(&config_map)?;
+    update_quotes(&config_map)?;
+    update_routing_rules(&config_map)?;
+    update_max_connections(&config_map)?;
+    update_outbound_queue_size(&config_map)?;
+    update_message_journal(&config_map)?;
+    update_execution_store(&config_map)?;
+    update_wire_log(&config_map)?;
+    update_encoding(&config_map)?;
+    update_interceptors(&config_map)?;
+    update_enrichment(&config_map)?;
+    update_masked_tags(&config_map)?;
+    update_sim_rng(&config_map)?;
+    update_sim_clock_skew(&config_map)?;
+    update_timestamp_precision(&config_map)?;
+    update_session_behaviour("", &config_map)?;
+    update_session_schedule(&config_map)?;
+    update_watchdog_timeout(&config_map)?;
+    update_duplicate_logon_policy(&config_map)?;
+    update_socket_options(&config_map)?;
+    update_proxy(&config_map)?;
+    update_throttle(&config_map)?;
+    update_batch(&config_map)?;
+    update_order_ack_timeout(&config_map)?;
+    update_dont_know_trade(&config_map);
+    update_logon_auth(&config_map);
+    // `--batch <file>` takes precedence over batch_file
+    if let Some(path) = args.iter().skip_while(|arg| *arg != "--batch").nth(1) {
+        *BATCH_FILE.write().unwrap() = Some(PathBuf::from(path));
+    }
 
-    let sequence_store: Arc<SequenceNumberStore> = get_sequence_store(&config_map);
+    let sequence_stores: Arc<SequenceStores> = get_sequence_store(&config_map);
+    sequence_stores.apply_flush_policy(get_sequence_flush_policy(&config_map)?);
 
     let order_store: Arc<OrderStore> = get_order_store(&config_map)?;
 
+    for sink in get_order_event_sinks(&config_map)? {
+        ORDER_EVENTS.register(sink);
+    }
+    for hooks in get_session_hooks(&config_map)? {
+        SESSION_HOOKS.register(hooks);
+    }
+    update_message_handlers(&config_map)?;
+
+    let daily_reset = get_daily_reset(&config_map)?;
+
     let (host, port) = get_connection_details(&config_map)?;
     let all_msg_map_collection = initialize_message_maps(&cwd, &config_map)?;
+    let mut reloaded_templates = vec![(
+        PathBuf::from(get_predefined_msg_path(&config_map)),
+        Arc::clone(&all_msg_map_collection),
+    )];
+    #[cfg(feature = "kafka")]
+    if let Some(kafka_config) = get_kafka_config(&config_map)? {
+        start_kafka(kafka_config, Arc::clone(&all_msg_map_collection))?;
+    }
+    #[cfg(not(feature = "kafka"))]
+    check_kafka_config(&config_map)?;
+
+    if let Some(address) = get_listen_address(&config_map, "admin_http_address")? {
+        start_admin_server(address)?;
+    }
+    if let Some(address) = get_listen_address(&config_map, "websocket_address")? {
+        start_websocket_server(address, Arc::clone(&all_msg_map_collection))?;
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(address) = get_listen_address(&config_map, "grpc_address")? {
+        start_grpc_server(address)?;
+    }
+    #[cfg(not(feature = "grpc"))]
+    check_grpc_address(&config_map)?;
 
     info!("Application started successfully");
 
     if IS_INITIATOR.load(Ordering::SeqCst) {
-        let mut stream = establish_connection(&host, port)?;
+        let session_id = SessionId::from_header(&all_msg_map_collection.fix_header, None);
+        let sequence_store = sequence_stores.for_session(&session_id)?;
 
-        let seq_store_clone = Arc::clone(&sequence_store);
-        send_logon_message(&mut stream, &all_msg_map_collection, seq_store_clone)?;
+        if let Some(daily_reset) = daily_reset {
+            start_daily_reset(daily_reset, vec![Arc::clone(&sequence_stores)]);
+        }
+        if let Some(address) = get_listen_address(&config_map, "order_gateway_address")? {
+            start_order_gateway(address)?;
+        }
+        if hot_reload(&config_map) {
+            start_hot_reload(config_file_path, reloaded_templates);
+        }
 
-        let order_store_clone = Arc::clone(&order_store);
+        loop {
+            if wait_for_session_open() {
+                info!("Session opened, resetting sequence numbers");
+                sequence_store.reset();
+            }
+            SESSION_STATES.for_session(&session_id).reset();
+
+            let connection = establish_connection(host, port)?;
+            let stream = with_sbe_codec(Box::new(connection), &session_id);
+            let stream = with_wire_log(stream, &session_id);
+            let mut stream = with_interceptors(stream, &session_id);
+
+            let seq_store_clone = Arc::clone(&sequence_store);
+            send_logon_message(
+                stream.as_mut(),
+                &session_id,
+                &all_msg_map_collection,
+                seq_store_clone,
+            )?;
+            logout_at_session_close(
+                stream.as_ref(),
+                &session_id,
+                &all_msg_map_collection,
+                &sequence_store,
+            )?;
+
+            let order_store_clone = Arc::clone(&order_store);
+
+            let seq_store_clone = Arc::clone(&sequence_store);
+            if let Err(e) = handle_stream(
+                stream,
+                &session_id,
+                &all_msg_map_collection,
+                seq_store_clone,
+                order_store_clone,
+            ) {
+                error!("Error handling client: {}", e);
+            }
 
-        let seq_store_clone = Arc::clone(&sequence_store);
-        if let Err(e) = handle_stream(
-            stream,
-            &all_msg_map_collection,
-            seq_store_clone,
-            order_store_clone,
-        ) {
-            error!("Error handling client: {}", e);
+            if RECONNECT_REQUESTED.swap(false, Ordering::SeqCst) {
+                info!("Reconnecting as requested");
+                continue;
+            }
+            // Without a schedule the session is not reopened
+            if SESSION_SCHEDULE.read().unwrap().is_none() {
+                break;
+            }
         }
+        sequence_store.flush();
     } else {
-        start_listener(
-            host,
-            port,
-            all_msg_map_collection,
-            sequence_store,
+        for bridge_config in get_bridge_configs(&config_map)? {
+            start_bridge(
+                bridge_config,
+                &all_msg_map_collection,
+                &sequence_stores,
+                &order_store,
+            )?;
+        }
+        let mut profiles = CounterpartyProfiles::new(SessionProfile {
+            message_maps: all_msg_map_collection,
+            seq_stores: sequence_stores,
             order_store,
-        )?;
-    }
-    Ok(())
-}
+        });
+        for (sender_comp_id, profile_config) in get_counterparty_configs(&config_map) {
+            let seq_stores = get_sequence_store(&profile_config);
+            seq_stores.apply_flush_policy(get_sequence_flush_policy(&profile_config)?);
+            let message_maps = initialize_message_maps(&cwd, &profile_config)?;
+            reloaded_templates.push((
+                PathBuf::from(get_predefined_msg_path(&profile_config)),
+                Arc::clone(&message_maps),
+            ));
+            update_session_behaviour(&sender_comp_id, &profile_config)?;
+            profiles.insert(
+                &sender_comp_id,
+                SessionProfile {
+                    message_maps,
+                    seq_stores,
+                    order_store: get_order_store(&profile_config)?,
+                },
+            );
+        }
 
-fn configure_logger() -> Result<(), flexi_logger::FlexiLoggerError> {
-    Logger::try_with_str("info")?
-        .format(|write, now, record| {
-            writeln!(
-                write,
-                "[{}] [{}] [{:?}] {}",
-                now.now().format("%Y-%m-%d %H:%M:%S"),
-                record.level(),
-                std::thread::current().id(),
-                record.args()
-            )
-        })
-        .duplicate_to_stdout(Duplicate::All)
-        .log_to_file(FileSpec::default().directory("logs"))
-        .start()?;
-    info!("Logger initialized.");
+        if let Some(daily_reset) = daily_reset {
+            start_daily_reset(daily_reset, profiles.seq_stores());
+        }
+        if hot_reload(&config_map) {
+            start_hot_reload(config_file_path, reloaded_templates);
+        }
+
+        start_trading_session_publisher();
+        start_listener(host, port, Arc::new(profiles))?;
+    }
     Ok(())
 }
 
 fn initialize_message_maps(
-    cwd: &PathBuf,
+    cwd: &Path,
     config_map: &HashMap<String, HashMap<String, String>>,
 ) -> io::Result<Arc<MessageMap>> {
     let mut payload_xml_path = cwd.join("reference").join("FIX4_2_Payload.xml");
@@ -152,12 +403,7 @@ fn initialize_message_maps(
     let use_data_dictionary = config_map
         .get("session")
         .and_then(|session| session.get("use_data_dictionary"))
-        .ok_or_else(|| {
-            Error::new(
-                ErrorKind::Other,
-                "use_data_dictionary not found in configuration.",
-            )
-        })?;
+        .ok_or_else(|| Error::other("use_data_dictionary not found in configuration."))?;
 
     info!(
         "config_map:session:use_data_dictionary - [{}]",
@@ -168,12 +414,7 @@ fn initialize_message_maps(
         let use_data_dictionary_path = config_map
             .get("session")
             .and_then(|session| session.get("data_dictionary"))
-            .ok_or_else(|| {
-                Error::new(
-                    ErrorKind::Other,
-                    "data_dictionary not found in configuration.",
-                )
-            })?;
+            .ok_or_else(|| Error::other("data_dictionary not found in configuration."))?;
 
         fix_tag_xml_path = cwd.join(use_data_dictionary_path);
         info!(
@@ -184,12 +425,7 @@ fn initialize_message_maps(
         let data_payload_dictionary_path = config_map
             .get("session")
             .and_then(|session| session.get("data_payload_dictionary"))
-            .ok_or_else(|| {
-                Error::new(
-                    ErrorKind::Other,
-                    "data_payload_dictionary not found in configuration.",
-                )
-            })?;
+            .ok_or_else(|| Error::other("data_payload_dictionary not found in configuration."))?;
 
         payload_xml_path = cwd.join(data_payload_dictionary_path);
         info!(
@@ -201,12 +437,7 @@ fn initialize_message_maps(
     let admin_messages_list = config_map
         .get("session")
         .and_then(|session| session.get("admin_messages"))
-        .ok_or_else(|| {
-            Error::new(
-                ErrorKind::Other,
-                "admin_messages not found in configuration.",
-            )
-        })?;
+        .ok_or_else(|| Error::other("admin_messages not found in configuration."))?;
 
     info!(
         "config_map:session:admin_messages - [{}]",
@@ -220,7 +451,7 @@ fn initialize_message_maps(
 
     let (fix_tagname_number_map, fix_number_tagname_map, msgtype_name_map, _msgname_type_map) =
         parse_fix_xml(fix_tag_xml_path.to_str().unwrap()).unwrap();
-    let (msgname_fields_map, msgnumber_fields_map) = parse_fix_payload_xml(
+    let (_msgname_fields_map, msgnumber_fields_map) = parse_fix_payload_xml(
         payload_xml_path.to_str().unwrap(),
         &msgtype_name_map,
         &fix_number_tagname_map,
@@ -228,16 +459,23 @@ fn initialize_message_maps(
     .unwrap();
 
     // Read predefined messages from JSON file
-    let (fix_header, admin_msg, app_msg) = match read_json_file("reference/predefined_msg.json") {
+    let predefined_msg_path = get_predefined_msg_path(config_map);
+
+    let (mut fix_header, admin_msg, app_msg) = match read_json_file(predefined_msg_path) {
         Ok(result) => result,
-        Err(e) => return Err(Error::new(ErrorKind::Other, e.to_string())),
+        Err(e) => return Err(Error::other(e.to_string())),
     };
+    let session_identity = get_session_identity(config_map);
+    for (field, value) in &session_identity {
+        info!("config_map:session:{} - [{}]", field, value);
+        set_field(&mut fix_header, field, value);
+    }
 
     // Predefined valid message types for validation
     let valid_msg_types: Vec<String> = msgtype_name_map.keys().cloned().collect();
 
     // Extract the header field information safely
-    let required_fields: Vec<String> = match msgnumber_fields_map.get(&"<".to_string()) {
+    let required_fields: Vec<String> = match msgnumber_fields_map.get("<") {
         Some(header_fld_info) => match &header_fld_info.field {
             Some(field_map) => field_map.keys().cloned().collect(),
             None => {
@@ -255,10 +493,9 @@ fn initialize_message_maps(
         fix_header,
         fix_tag_number_map: fix_tagname_number_map,
         admin_msg_list,
-        admin_msg,
-        app_msg,
+        admin_msg: Templates::new(admin_msg, session_identity.clone()),
+        app_msg: Templates::new(app_msg, session_identity),
         fix_tag_name_map: fix_number_tagname_map,
-        msgname_fields_map,
         msgnumber_fields_map,
         valid_msg_types,
         required_fields,
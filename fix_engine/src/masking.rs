@@ -0,0 +1,148 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use indexmap::IndexMap;
+
+use crate::parse_xml::FixTag;
+use crate::tag_value;
+
+/// Password(554) and RawData(96), masked whatever the configuration.
+pub const DEFAULT_MASKED_TAGS: [u32; 2] = [554, 96];
+
+const MASK: &str = "****";
+
+lazy_static! {
+    /// Tags whose values never appear in plaintext in the logs.
+    pub static ref MASKED_TAGS: RwLock<Vec<u32>> = RwLock::new(DEFAULT_MASKED_TAGS.to_vec());
+}
+
+fn is_masked_tag(tag: &[u8], masked_tags: &[u32]) -> bool {
+    std::str::from_utf8(tag)
+        .ok()
+        .and_then(|tag| tag.parse::<u32>().ok())
+        .is_some_and(|tag| masked_tags.contains(&tag))
+}
+
+/// Replaces the values of the masked tags in a raw message delimited by SOH or '|'. The value
+/// of a DATA field, e.g. RawData(96), is as long as its Length field says, SOH included.
+pub fn mask_bytes(message: &[u8]) -> Cow<'_, [u8]> {
+    let masked_tags = MASKED_TAGS.read().unwrap();
+    let mut masked = Vec::with_capacity(message.len());
+    let mut changed = false;
+    let mut copied = 0;
+    for field in tag_value::fields(message) {
+        let Ok((tag, value)) = field else {
+            break;
+        };
+        if masked_tags.contains(&tag) {
+            // The value is borrowed from the message, its offset is where it starts
+            let value_start = value.as_ptr() as usize - message.as_ptr() as usize;
+            masked.extend_from_slice(&message[copied..value_start]);
+            masked.extend_from_slice(MASK.as_bytes());
+            copied = value_start + value.len();
+            changed = true;
+        }
+    }
+    // What does not parse is masked field by field up to the next delimiter
+    changed |= mask_delimited(&message[copied..], &masked_tags, &mut masked);
+    if changed {
+        Cow::Owned(masked)
+    } else {
+        Cow::Borrowed(message)
+    }
+}
+
+/// Appends the fields to `masked`, the values of the masked tags replaced, each field ending
+/// at the next delimiter. Returns whether a value was masked.
+fn mask_delimited(fields: &[u8], masked_tags: &[u32], masked: &mut Vec<u8>) -> bool {
+    let mut changed = false;
+    for field in fields.split_inclusive(|&byte| byte == b'\x01' || byte == b'|') {
+        let value_end = match field.last() {
+            Some(b'\x01' | b'|') => field.len() - 1,
+            _ => field.len(),
+        };
+        match field[..value_end].iter().position(|&byte| byte == b'=') {
+            Some(equals) if is_masked_tag(&field[..equals], masked_tags) => {
+                masked.extend_from_slice(&field[..=equals]);
+                masked.extend_from_slice(MASK.as_bytes());
+                masked.extend_from_slice(&field[value_end..]);
+                changed = true;
+            }
+            _ => masked.extend_from_slice(field),
+        }
+    }
+    changed
+}
+
+/// `mask_bytes` for a message held as text, to be used before logging it.
+pub fn mask_message(message: &str) -> Cow<'_, str> {
+    match mask_bytes(message.as_bytes()) {
+        Cow::Borrowed(_) => Cow::Borrowed(message),
+        Cow::Owned(masked) => Cow::Owned(String::from_utf8_lossy(&masked).into_owned()),
+    }
+}
+
+/// Whether the value of the tag must be masked.
+pub fn is_masked(tag: u32) -> bool {
+    MASKED_TAGS.read().unwrap().contains(&tag)
+}
+
+/// Copy of a parsed message, keyed by field name, with the values of the masked tags replaced.
+pub fn mask_fields(
+    msg_map: &IndexMap<String, String>,
+    fix_tag_number_map: &HashMap<u32, FixTag>,
+) -> IndexMap<String, String> {
+    let masked_names: Vec<&str> = MASKED_TAGS
+        .read()
+        .unwrap()
+        .iter()
+        .filter_map(|tag| fix_tag_number_map.get(tag))
+        .map(|fix_tag| fix_tag.name.as_str())
+        .collect();
+    msg_map
+        .iter()
+        .map(|(name, value)| {
+            let value = if masked_names.contains(&name.as_str()) {
+                MASK.to_string()
+            } else {
+                value.clone()
+            };
+            (name.clone(), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_message() {
+        assert_eq!(
+            mask_message("8=FIX.4.2\x0135=A\x01554=secret\x0196=token\x0110=000\x01"),
+            "8=FIX.4.2\x0135=A\x01554=****\x0196=****\x0110=000\x01"
+        );
+        assert_eq!(
+            mask_message("35=A|553=user|554=secret"),
+            "35=A|553=user|554=****"
+        );
+        assert!(matches!(
+            mask_message("8=FIX.4.2|35=0|"),
+            Cow::Borrowed("8=FIX.4.2|35=0|")
+        ));
+    }
+
+    #[test]
+    fn test_mask_raw_data_holding_soh() {
+        assert_eq!(
+            mask_bytes(b"8=FIX.4.2\x0135=A\x0195=9\x0196=ab\x01554=cd\x0198=0\x0110=000\x01"),
+            &b"8=FIX.4.2\x0135=A\x0195=9\x0196=****\x0198=0\x0110=000\x01"[..]
+        );
+        // A field which does not parse still has the values after it masked
+        assert_eq!(
+            mask_message("35=A|95=x|96=token|554=secret|"),
+            "35=A|95=x|96=****|554=****|"
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use indexmap::IndexMap;
+use log::{error, info};
+use rust_decimal::Decimal;
+
+use crate::message_handling::{order_execution_report, send_execution_report};
+use crate::order_events::{OrderEvent, ORDER_EVENTS};
+use crate::orderstore::{OrdStatus, Order, OrderStore};
+use crate::parse_xml::FixTag;
+use crate::sequence::SequenceNumberStore;
+use crate::transport::Transport;
+
+lazy_static! {
+    /// Order books shared by all the sessions of the acceptor.
+    pub static ref MATCHING_ENGINE: MatchingEngine = MatchingEngine::default();
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    /// Side of an order as stored, the dictionary description or the raw value.
+    pub fn parse(side: &str) -> Option<Side> {
+        match side.to_uppercase().as_str() {
+            "BUY" | "1" => Some(Side::Buy),
+            "SELL" | "2" | "SELL_SHORT" | "5" | "SELL_SHORT_EXEMPT" | "6" => Some(Side::Sell),
+            _ => None,
+        }
+    }
+}
+
+/// One execution between an incoming order and a resting one, at the resting order's price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trade {
+    pub resting_id: u64,
+    pub quantity: Decimal,
+    pub price: Decimal,
+}
+
+/// Resting orders of one symbol, by price level and, within a level, by arrival.
+#[derive(Default)]
+pub struct OrderBook {
+    bids: BTreeMap<Decimal, VecDeque<(u64, Decimal)>>,
+    asks: BTreeMap<Decimal, VecDeque<(u64, Decimal)>>,
+}
+
+impl OrderBook {
+    /// Matches an incoming order against the other side of the book, best price first, down
+    /// to its limit (None for a market order). Returns the trades and the quantity left.
+    pub fn execute(
+        &mut self,
+        side: Side,
+        limit: Option<Decimal>,
+        mut quantity: Decimal,
+    ) -> (Vec<Trade>, Decimal) {
+        let mut trades = Vec::new();
+        while quantity > Decimal::ZERO {
+            let best = match side {
+                Side::Buy => self.asks.keys().next().copied(),
+                Side::Sell => self.bids.keys().next_back().copied(),
+            };
+            let price = match best {
+                Some(price)
+                    if limit.is_none_or(|limit| match side {
+                        Side::Buy => price <= limit,
+                        Side::Sell => price >= limit,
+                    }) =>
+                {
+                    price
+                }
+                _ => break,
+            };
+            let levels = match side {
+                Side::Buy => &mut self.asks,
+                Side::Sell => &mut self.bids,
+            };
+            let level = levels.get_mut(&price).unwrap();
+            let (resting_id, remaining) = level.front_mut().unwrap();
+            let traded = quantity.min(*remaining);
+            trades.push(Trade {
+                resting_id: *resting_id,
+                quantity: traded,
+                price,
+            });
+            quantity -= traded;
+            *remaining -= traded;
+            if remaining.is_zero() {
+                level.pop_front();
+                if level.is_empty() {
+                    levels.remove(&price);
+                }
+            }
+        }
+        (trades, quantity)
+    }
+
+    /// Adds the unmatched quantity of a limit order behind the orders at the same price.
+    pub fn rest(&mut self, order_id: u64, side: Side, price: Decimal, quantity: Decimal) {
+        let levels = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        levels
+            .entry(price)
+            .or_default()
+            .push_back((order_id, quantity));
+    }
+
+    /// Takes a resting order out of the book. Returns false when it is not resting.
+    pub fn remove(&mut self, order_id: u64) -> bool {
+        for levels in [&mut self.bids, &mut self.asks] {
+            let found = levels.iter_mut().find_map(|(price, level)| {
+                let index = level.iter().position(|(id, _)| *id == order_id)?;
+                level.remove(index);
+                Some((*price, level.is_empty()))
+            });
+            if let Some((price, empty)) = found {
+                if empty {
+                    levels.remove(&price);
+                }
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// The session an order came from, to which its executions are reported.
+pub struct OrderOwner {
+    /// SenderCompID of the session, ClOrdIDs are only unique within it.
+    pub comp_id: String,
+    pub stream: Arc<Mutex<Box<dyn Transport>>>,
+    pub app_msg: HashMap<String, IndexMap<String, String>>,
+    pub fix_tag_name_map: HashMap<String, FixTag>,
+    pub seq_store: Arc<SequenceNumberStore>,
+    pub order_store: Arc<OrderStore>,
+}
+
+struct LiveOrder {
+    /// The order as last updated in its owner's OrderStore.
+    order: Order,
+    owner: Arc<OrderOwner>,
+}
+
+#[derive(Default)]
+struct EngineState {
+    books: HashMap<String, OrderBook>,
+    /// Orders in the books keyed by an engine-wide id, as ClOrdIDs repeat across sessions.
+    orders: HashMap<u64, LiveOrder>,
+    ids: HashMap<(String, String), u64>,
+    next_id: u64,
+}
+
+/// Price-time priority matching between the orders of all the acceptor's sessions. Every
+/// execution is reported to both sides.
+#[derive(Default)]
+pub struct MatchingEngine {
+    state: Mutex<EngineState>,
+}
+
+impl MatchingEngine {
+    /// Matches an acknowledged order; what is not filled rests in the book, or is canceled
+    /// for a market order.
+    pub fn submit(&self, order: Order, owner: Arc<OrderOwner>) {
+        let side = match Side::parse(&order.side) {
+            Some(side) => side,
+            None => {
+                error!(
+                    "Order {} not matched, unknown side {}",
+                    order.id, order.side
+                );
+                return;
+            }
+        };
+        let limit = match order.ordtype.to_uppercase().as_str() {
+            "MARKET" | "1" => None,
+            _ => order.price,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let engine_id = state.next_id;
+        state.next_id += 1;
+        let mut incoming = LiveOrder { order, owner };
+        let book = state
+            .books
+            .entry(incoming.order.symbol.clone())
+            .or_default();
+        let (trades, remaining) = book.execute(side, limit, incoming.order.leaves_qty());
+
+        for trade in trades {
+            let resting = state.orders.get_mut(&trade.resting_id).unwrap();
+            resting.fill(trade.quantity, trade.price);
+            incoming.fill(trade.quantity, trade.price);
+            info!(
+                "Matched {} {} at {}: order {} of {} against order {} of {}",
+                trade.quantity,
+                incoming.order.symbol,
+                trade.price,
+                incoming.order.id,
+                incoming.owner.comp_id,
+                resting.order.id,
+                resting.owner.comp_id
+            );
+            if resting.order.leaves_qty().is_zero() {
+                let resting = state.orders.remove(&trade.resting_id).unwrap();
+                state
+                    .ids
+                    .remove(&(resting.owner.comp_id.clone(), resting.order.id.clone()));
+            }
+        }
+
+        if remaining.is_zero() {
+            return;
+        }
+        match limit {
+            Some(price) => {
+                book.rest(engine_id, side, price, remaining);
+                state.ids.insert(
+                    (incoming.owner.comp_id.clone(), incoming.order.id.clone()),
+                    engine_id,
+                );
+                state.orders.insert(engine_id, incoming);
+            }
+            None => incoming.cancel_remaining("No liquidity for the market order"),
+        }
+    }
+
+    /// Takes an order out of its book once canceled or replaced by its owner.
+    pub fn remove(&self, comp_id: &str, order_id: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let engine_id = match state
+            .ids
+            .remove(&(comp_id.to_string(), order_id.to_string()))
+        {
+            Some(engine_id) => engine_id,
+            None => return false,
+        };
+        let live_order = state.orders.remove(&engine_id).unwrap();
+        if let Some(book) = state.books.get_mut(&live_order.order.symbol) {
+            book.remove(engine_id);
+        }
+        true
+    }
+
+    /// Empties the books, canceling every resting order and reporting it to its owner.
+    /// Returns the number of orders canceled.
+    pub fn cancel_all(&self, text: &str) -> usize {
+        let mut state = self.state.lock().unwrap();
+        state.books.clear();
+        state.ids.clear();
+        let canceled = state.orders.len();
+        for (_, mut live_order) in state.orders.drain() {
+            live_order.cancel_remaining(text);
+        }
+        canceled
+    }
+
+    /// Takes the resting orders of the account out of the books, canceling them and reporting
+    /// them to their owners. Returns the number of orders canceled.
+    pub fn cancel_account(&self, account: &str, text: &str) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let engine_ids: Vec<u64> = state
+            .orders
+            .iter()
+            .filter(|(_, live_order)| live_order.order.account == account)
+            .map(|(engine_id, _)| *engine_id)
+            .collect();
+        for engine_id in &engine_ids {
+            let mut live_order = state.orders.remove(engine_id).unwrap();
+            if let Some(book) = state.books.get_mut(&live_order.order.symbol) {
+                book.remove(*engine_id);
+            }
+            state.ids.remove(&(
+                live_order.owner.comp_id.clone(),
+                live_order.order.id.clone(),
+            ));
+            live_order.cancel_remaining(text);
+        }
+        engine_ids.len()
+    }
+}
+
+impl LiveOrder {
+    /// Records an execution in the owner's OrderStore and reports it to the owner.
+    fn fill(&mut self, quantity: Decimal, price: Decimal) {
+        match self
+            .owner
+            .order_store
+            .fill_order(&self.order.key(), quantity, price)
+        {
+            Ok(order) => self.order = order,
+            Err(e) => {
+                error!("Failed to fill order {}: {}", self.order.id, e);
+                let _ = self.order.record_fill(quantity, price);
+            }
+        }
+        let exec_type = if self.order.leaves_qty().is_zero() {
+            "2"
+        } else {
+            "1"
+        };
+        let exec_id = self.owner.seq_store.next_exec_id();
+        ORDER_EVENTS.publish(&OrderEvent::from_fill(&self.order, &exec_id, quantity, price));
+        let override_map =
+            order_execution_report(&self.order, &exec_id, exec_type, quantity, price);
+        self.report(&override_map);
+    }
+
+    fn cancel_remaining(&mut self, text: &str) {
+        match self
+            .owner
+            .order_store
+            .set_status(&self.order.key(), OrdStatus::Canceled, None)
+        {
+            Ok(order) => self.order = order,
+            Err(e) => {
+                error!("Failed to cancel order {}: {}", self.order.id, e);
+                let _ = self.order.transition(OrdStatus::Canceled);
+            }
+        }
+        let exec_id = self.owner.seq_store.next_exec_id();
+        let mut override_map =
+            order_execution_report(&self.order, &exec_id, "4", Decimal::ZERO, Decimal::ZERO);
+        override_map.insert("Text".to_string(), text.to_string());
+        self.report(&override_map);
+    }
+
+    fn report(&self, override_map: &HashMap<String, String>) {
+        let owner = &self.owner;
+        if let Err(e) = send_execution_report(
+            &owner.stream,
+            &owner.app_msg,
+            &owner.fix_tag_name_map,
+            &owner.seq_store,
+            override_map,
+        ) {
+            error!(
+                "Failed to report the execution of order {} to {}: {}",
+                self.order.id, owner.comp_id, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(value: u64) -> Decimal {
+        Decimal::from(value)
+    }
+
+    #[test]
+    fn test_price_time_priority() {
+        let mut book = OrderBook::default();
+        book.rest(1, Side::Sell, dec(101), dec(100));
+        book.rest(2, Side::Sell, dec(100), dec(50));
+        book.rest(3, Side::Sell, dec(100), dec(50));
+        book.rest(4, Side::Buy, dec(99), dec(10));
+
+        // Not crossing
+        assert_eq!(
+            book.execute(Side::Buy, Some(dec(99)), dec(10)),
+            (vec![], dec(10))
+        );
+
+        let (trades, remaining) = book.execute(Side::Buy, Some(dec(101)), dec(120));
+        assert_eq!(
+            trades,
+            vec![
+                Trade {
+                    resting_id: 2,
+                    quantity: dec(50),
+                    price: dec(100)
+                },
+                Trade {
+                    resting_id: 3,
+                    quantity: dec(50),
+                    price: dec(100)
+                },
+                Trade {
+                    resting_id: 1,
+                    quantity: dec(20),
+                    price: dec(101)
+                },
+            ]
+        );
+        assert_eq!(remaining, dec(0));
+
+        assert!(book.remove(1));
+        assert!(!book.remove(1));
+        let (trades, remaining) = book.execute(Side::Sell, None, dec(30));
+        assert_eq!(
+            trades,
+            vec![Trade {
+                resting_id: 4,
+                quantity: dec(10),
+                price: dec(99)
+            }]
+        );
+        assert_eq!(remaining, dec(20));
+    }
+}
@@ -1,25 +1,28 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader, Read};
 
-use chrono::Utc;
 use indexmap::IndexMap;
 use json::JsonValue;
-use log::{error, info};
+use log::{debug, error, info};
 
+use crate::enrichment::enrich;
+use crate::masking::mask_message;
 use crate::parse_xml::{FixError, FixTag};
+use crate::sim_clock::{skew_timestamp, venue_timestamp};
+use crate::tag_value::{checksum, encode_field, fields, is_length_tag};
+use crate::templates::expand_placeholders;
+
+/// The header, admin and app message templates of a message file.
+type FixSections = (
+    IndexMap<String, String>,
+    HashMap<String, IndexMap<String, String>>,
+    HashMap<String, IndexMap<String, String>>,
+);
 
 /// Reads and parses a JSON file containing FIX message definitions.
-pub fn read_json_file(
-    file_path: &str,
-) -> Result<
-    (
-        IndexMap<String, String>,
-        HashMap<String, IndexMap<String, String>>,
-        HashMap<String, IndexMap<String, String>>,
-    ),
-    Box<dyn std::error::Error>,
-> {
+pub fn read_json_file(file_path: &str) -> Result<FixSections, Box<dyn std::error::Error>> {
     // Open the JSON file
     let file = File::open(file_path)?;
     let mut reader = BufReader::new(file);
@@ -38,16 +41,7 @@ pub fn read_json_file(
 }
 
 /// Extracts FIX message sections (header, admin, app) from JSON value.
-fn extract_fix_sections(
-    json_value: &JsonValue,
-) -> Result<
-    (
-        IndexMap<String, String>,
-        HashMap<String, IndexMap<String, String>>,
-        HashMap<String, IndexMap<String, String>>,
-    ),
-    Box<dyn std::error::Error>,
-> {
+fn extract_fix_sections(json_value: &JsonValue) -> Result<FixSections, Box<dyn std::error::Error>> {
     let fix_header = extract_section(json_value, "header")?;
     let admin_msg = extract_msg_map(json_value, "admin", &fix_header)?;
     let app_msg = extract_msg_map(json_value, "app", &fix_header)?;
@@ -63,11 +57,9 @@ fn extract_section(
     let mut section_map = IndexMap::new();
 
     if let JsonValue::Object(obj) = json_value {
-        if let Some(section) = obj.get(section_name) {
-            if let JsonValue::Object(section_obj) = section {
-                for (key, value) in section_obj.iter() {
-                    section_map.insert(key.to_string(), value.as_str().unwrap_or("").to_string());
-                }
+        if let Some(JsonValue::Object(section_obj)) = obj.get(section_name) {
+            for (key, value) in section_obj.iter() {
+                section_map.insert(key.to_string(), value.as_str().unwrap_or("").to_string());
             }
         }
     }
@@ -84,27 +76,25 @@ fn extract_msg_map(
     let mut msg_map = HashMap::new();
 
     if let JsonValue::Object(obj) = json_value {
-        if let Some(msg_section) = obj.get(msg_type) {
-            if let JsonValue::Object(msg_obj) = msg_section {
-                for (key, value) in msg_obj.iter() {
-                    let mut msg_tags = IndexMap::new();
-
-                    // Populate with fix_header tags
-                    for (f_k, f_v) in fix_header.iter() {
-                        if f_k == "MsgType" {
-                            msg_tags.insert(f_k.clone(), key.to_string().clone());
-                        } else {
-                            msg_tags.insert(f_k.clone(), f_v.clone());
-                        }
-                    }
-
-                    // Populate with current msg_obj tags
-                    for (k, v) in value.entries() {
-                        msg_tags.insert(k.to_string(), v.as_str().unwrap_or("").to_string());
+        if let Some(JsonValue::Object(msg_obj)) = obj.get(msg_type) {
+            for (key, value) in msg_obj.iter() {
+                let mut msg_tags = IndexMap::new();
+
+                // Populate with fix_header tags
+                for (f_k, f_v) in fix_header.iter() {
+                    if f_k == "MsgType" {
+                        msg_tags.insert(f_k.clone(), key.to_string().clone());
+                    } else {
+                        msg_tags.insert(f_k.clone(), f_v.clone());
                     }
+                }
 
-                    msg_map.insert(key.to_string(), msg_tags);
+                // Populate with current msg_obj tags
+                for (k, v) in value.entries() {
+                    msg_tags.insert(k.to_string(), v.as_str().unwrap_or("").to_string());
                 }
+
+                msg_map.insert(key.to_string(), msg_tags);
             }
         }
     }
@@ -116,51 +106,49 @@ pub fn fixmsg2msgtype(
     fixmsg: &str,
     fix_tag_number_map: &HashMap<u32, FixTag>,
 ) -> Result<(String, IndexMap<String, String>), FixError> {
-    let modified_message = fixmsg.replace('\x01', "|");
-    info!("{}", modified_message);
+    info!("{}", mask_message(fixmsg).replace('\x01', "|"));
 
-    let fields: Vec<&str> = modified_message.split('|').collect();
     let mut msgtype = String::new();
     let mut msg_map = IndexMap::new();
 
-    for field in fields {
-        let parts: Vec<&str> = field.split('=').collect();
-        if parts.len() == 2 {
-            if let Ok(tag) = parts[0].parse::<u32>() {
-                if let Some(tag_definition) = fix_tag_number_map.get(&tag) {
-                    let tag_value = parts[1];
-                    if let Some(enum_values) = &tag_definition.enum_values {
-                        let enum_description = match enum_values.get(tag_value) {
-                            Some(desc) => desc.clone(),
-                            None => {
-                                println!(
-                                    "{} - Enum value not found for tag {}: {}",
-                                    tag_definition.name, tag, tag_value
-                                );
-                                // "".to_string()
-                                // You can return an empty string or handle this case as needed
-                                tag_value.to_string()
-                            }
-                        };
-                        if tag_definition.name == "MsgType" {
-                            msgtype = enum_description.clone();
-                        }
-                        msg_map
-                            .entry(tag_definition.name.clone())
-                            .or_insert_with(|| enum_description.clone());
-                    } else {
-                        msg_map
-                            .entry(tag_definition.name.clone())
-                            .or_insert_with(|| tag_value.to_string());
+    for field in fields(fixmsg.as_bytes()) {
+        let Ok((tag, value)) = field else {
+            msgtype = "InvalidTagNumber".to_string();
+            break;
+        };
+        // Only a DATA field may not be UTF-8, and is then left out
+        let Ok(tag_value) = std::str::from_utf8(value) else {
+            continue;
+        };
+        if let Some(tag_definition) = fix_tag_number_map.get(&tag) {
+            if let Some(enum_values) = &tag_definition.enum_values {
+                let enum_description = match enum_values.get(tag_value) {
+                    Some(desc) => desc.as_str(),
+                    None => {
+                        debug!(
+                            "{} - Enum value not found for tag {}: {}",
+                            tag_definition.name, tag, tag_value
+                        );
+                        tag_value
                     }
-                } else {
-                    msgtype = "UnknownTag".to_string();
-                    msg_map.insert("Unknown tag".to_string(), parts[1].to_string());
+                };
+                if tag_definition.name == "MsgType" {
+                    msgtype = enum_description.to_string();
                 }
+                msg_map
+                    .entry(tag_definition.name.clone())
+                    .or_insert_with(|| enum_description.to_string());
             } else {
-                msgtype = "InvalidTagNumber".to_string();
-                msg_map.insert("Invalid tag number".to_string(), parts[1].to_string());
+                msg_map
+                    .entry(tag_definition.name.clone())
+                    .or_insert_with(|| tag_value.to_string());
             }
+        } else {
+            // A tag missing from the dictionary, e.g. a custom tag of the venue, is
+            // kept by number so it survives re-encoding
+            msg_map
+                .entry(tag.to_string())
+                .or_insert_with(|| tag_value.to_string());
         }
     }
     Ok((msgtype, msg_map))
@@ -180,13 +168,6 @@ pub fn msgtype2fixmsg(
 ) -> String {
     let mut fix_msg = String::new();
     let mut body_length: u32 = 0;
-    let mut checksum: u32 = 0;
-
-    // Formats the current timestamp for the FIX message.
-    fn format_timestamp() -> String {
-        let now = Utc::now();
-        now.format("%Y%m%d-%H:%M:%S%.3f").to_string()
-    }
 
     // Retrieve and modify the predefined message based on msgtype
     if let Some(mut predefined_msg) = msg_map.get(&msgtype).cloned() {
@@ -196,11 +177,15 @@ pub fn msgtype2fixmsg(
                 predefined_msg.insert(key.clone(), value.clone());
             }
         }
+        enrich(&msgtype, &mut predefined_msg);
         // Construct FIX message
         for (key, value) in predefined_msg.iter() {
+            let value = &*expand_placeholders(value, msg_seq_num);
             let new_tag = if let Some(tags_info) = fix_tagname_number_map.get(key) {
                 let tag_value = match &tags_info.enum_values {
-                    Some(enum_values) => enum_values.get(&value.to_uppercase()).unwrap_or(value),
+                    Some(enum_values) => enum_values
+                        .get(&value.to_uppercase())
+                        .map_or(value, String::as_str),
                     None => {
                         if key == "BodyLength" {
                             "#"
@@ -211,11 +196,16 @@ pub fn msgtype2fixmsg(
                 };
 
                 match key.as_str() {
-                    "SendingTime" => format!("{}={}", tags_info.number, format_timestamp()),
-                    "MsgSeqNum" => format!("{}={}", tags_info.number, msg_seq_num.to_string()),
+                    "SendingTime" => format!("{}={}", tags_info.number, venue_timestamp()),
+                    "TransactTime" => format!("{}={}", tags_info.number, skew_timestamp(tag_value)),
+                    "MsgSeqNum" => format!("{}={}", tags_info.number, msg_seq_num),
                     "CheckSum" => continue, // CheckSum is handled separately
-                    _ => format!("{}={}", tags_info.number, tag_value),
+                    // Computed for the DATA field it precedes
+                    _ if is_length_tag(&tags_info.number) => continue,
+                    _ => encode_field(&tags_info.number, tag_value),
                 }
+            } else if key.parse::<u32>().is_ok() {
+                format!("{}={}", key, value)
             } else {
                 error!("Field {}={} is not in FIX definition.", key, value);
                 continue;
@@ -242,10 +232,7 @@ pub fn msgtype2fixmsg(
 
     // Calculate checksum
     let chksum_fix_msg = fix_msg.replace("|", "\x01");
-    for &byte in chksum_fix_msg.as_bytes() {
-        checksum = checksum.wrapping_add(byte as u32);
-    }
-    let checksum_value = ((checksum + 1) % 256) as u8;
+    let checksum_value = checksum(chksum_fix_msg.as_bytes()).wrapping_add(1);
 
     // Append the checksum to the message
     fix_msg.push_str(&format!("|10={:03}|", checksum_value));
@@ -260,13 +247,6 @@ pub fn fixmap2fixmsg(
 ) -> String {
     let mut fix_msg = String::new();
     let mut body_length: u32 = 0;
-    let mut checksum: u32 = 0;
-
-    /// Formats the current timestamp for the FIX message.
-    fn format_timestamp() -> String {
-        let now = Utc::now();
-        now.format("%Y%m%d-%H:%M:%S%.3f").to_string()
-    }
 
     for (key, value) in msg_map.iter() {
         let new_tag = if let Some(tags_info) = fix_tag_name_map.get(key) {
@@ -280,13 +260,15 @@ pub fn fixmap2fixmsg(
                 }
             };
             if key == "SendingTime" {
-                format!("{}={}", tags_info.number, format_timestamp())
+                format!("{}={}", tags_info.number, venue_timestamp())
+            } else if key == "TransactTime" {
+                format!("{}={}", tags_info.number, skew_timestamp(tag_value))
             } else if key == "MsgSeqNum" {
-                format!("{}={}", tags_info.number, msg_seq_num.to_string())
-            } else if key == "CheckSum" {
+                format!("{}={}", tags_info.number, msg_seq_num)
+            } else if key == "CheckSum" || is_length_tag(&tags_info.number) {
                 continue;
             } else {
-                format!("{}={}", tags_info.number, tag_value)
+                encode_field(&tags_info.number, tag_value)
             }
         } else {
             format!("{}={}", key, value)
@@ -310,14 +292,108 @@ pub fn fixmap2fixmsg(
 
     // Calculate checksum over tag value bytes
     let chksum_fix_msg = fix_msg.replace("|", "\x01");
-    let bytes = chksum_fix_msg.as_bytes();
-    for &byte in bytes {
-        checksum = checksum.wrapping_add(byte as u32);
+    let checksum_value = checksum(chksum_fix_msg.as_bytes()).wrapping_add(1);
+    fix_msg = format!("{}|10={:03}|", fix_msg, checksum_value);
+    fix_msg
+}
+
+/// Reads the entries of a repeating group from a raw message: `count_tag` gives their
+/// number, each starts with `delimiter_tag` followed by any of `member_tags`.
+pub fn repeating_group(
+    message: &str,
+    count_tag: u32,
+    delimiter_tag: u32,
+    member_tags: &[u32],
+) -> Result<Vec<HashMap<u32, String>>, String> {
+    let mut fields = message
+        .split(['\x01', '|'])
+        .filter_map(|field| field.split_once('='))
+        .filter_map(|(tag, value)| Some((tag.parse::<u32>().ok()?, value)))
+        .skip_while(|(tag, _)| *tag != count_tag)
+        .peekable();
+    let count = match fields.next() {
+        Some((_, count)) => count
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid {} {}", count_tag, count))?,
+        None => return Ok(Vec::new()),
+    };
+    let mut entries = Vec::with_capacity(count);
+    while entries.len() < count {
+        match fields.next() {
+            Some((tag, value)) if tag == delimiter_tag => {
+                let mut entry = HashMap::from([(tag, value.to_string())]);
+                while let Some((tag, value)) = fields
+                    .next_if(|(tag, _)| *tag != delimiter_tag && member_tags.contains(tag))
+                {
+                    entry.insert(tag, value.to_string());
+                }
+                entries.push(entry);
+            }
+            _ => {
+                return Err(format!(
+                    "{} {} entries expected, {} found",
+                    count_tag,
+                    count,
+                    entries.len()
+                ))
+            }
+        }
     }
+    Ok(entries)
+}
 
-    // Take the modulo 256 to get the 8-bit checksum
-    fix_msg = format!("{}|10={:03}|", fix_msg, (checksum % 256) as u8 + 1);
-    fix_msg
+/// Appends fields to a message built by `msgtype2fixmsg`, e.g. the entries of a repeating
+/// group its field maps cannot hold, with its BodyLength and CheckSum computed again.
+pub fn append_fields(fix_msg: &str, fields: &[(u32, String)]) -> String {
+    let mut head = Vec::new();
+    let mut body = Vec::new();
+    for field in fix_msg.split('|').filter(|field| !field.is_empty()) {
+        match field.split_once('=').map(|(tag, _)| tag) {
+            Some("8") => head.push(field.to_string()),
+            Some("9") | Some("10") => continue,
+            _ => body.push(field.to_string()),
+        }
+    }
+    body.extend(fields.iter().map(|(tag, value)| format!("{}={}", tag, value)));
+    let body = body.join("|") + "|";
+    head.push(format!("9={}", body.len()));
+    let message = head.join("|") + "|" + body.as_str();
+    // Calculate checksum over the message as sent, SOH-delimited
+    let checksum = checksum(message.replace('|', "\x01").as_bytes());
+    format!("{}10={:03}|", message, checksum)
+}
+
+/// The SOH-delimited message with the MsgSeqNum it is sent with, its BodyLength and CheckSum
+/// computed again when it was built with another number, e.g. one a timer took meanwhile.
+pub fn restamp_seq_num(message: &str, msg_seq_num: u64) -> Cow<'_, str> {
+    let msg_seq_num = msg_seq_num.to_string();
+    let mut begin_string = None;
+    let mut body = String::with_capacity(message.len());
+    let mut restamped = false;
+    for field in fields(message.as_bytes()) {
+        let Ok((tag, value)) = field else {
+            return Cow::Borrowed(message);
+        };
+        let Ok(value) = std::str::from_utf8(value) else {
+            return Cow::Borrowed(message);
+        };
+        match tag {
+            8 => begin_string = Some(value),
+            9 | 10 => continue,
+            34 if value == msg_seq_num => return Cow::Borrowed(message),
+            34 => {
+                restamped = true;
+                body.push_str(&format!("34={}\x01", msg_seq_num));
+            }
+            _ => body.push_str(&format!("{}={}\x01", tag, value)),
+        }
+    }
+    let Some(begin_string) = begin_string.filter(|_| restamped) else {
+        return Cow::Borrowed(message);
+    };
+    let message = format!("8={}\x019={}\x01{}", begin_string, body.len(), body);
+    let checksum = checksum(message.as_bytes());
+    Cow::Owned(format!("{}10={:03}\x01", message, checksum))
 }
 
 #[cfg(test)]
@@ -550,6 +626,54 @@ mod tests {
         assert!(fix_msg.contains("10="));                 // Ensure that checksum exists
     }
 
+    #[test]
+    fn test_repeating_groups() {
+        let fix_msg = "8=FIX.4.2|9=20|35=J|70=A1|53=300|10=000|";
+        let fix_msg = append_fields(
+            fix_msg,
+            &[
+                (78, "2".to_string()),
+                (79, "ACC1".to_string()),
+                (80, "100".to_string()),
+                (79, "ACC2".to_string()),
+                (80, "200".to_string()),
+            ],
+        );
+        let body = "35=J|70=A1|53=300|78=2|79=ACC1|80=100|79=ACC2|80=200|";
+        assert!(fix_msg.starts_with(&format!("8=FIX.4.2|9={}|{}10=", body.len(), body)));
+        let checksum: u32 = fix_msg
+            .rsplit_once("10=")
+            .unwrap()
+            .0
+            .replace('|', "\x01")
+            .bytes()
+            .map(u32::from)
+            .sum();
+        assert!(fix_msg.ends_with(&format!("10={:03}|", checksum % 256)));
+
+        let allocs = repeating_group(&fix_msg, 78, 79, &[80]).unwrap();
+        assert_eq!(allocs.len(), 2);
+        assert_eq!(allocs[1][&79], "ACC2");
+        assert_eq!(allocs[1][&80], "200");
+        assert!(repeating_group(&fix_msg, 73, 11, &[37]).unwrap().is_empty());
+        assert!(repeating_group("78=2|79=ACC1|80=100|10=000|", 78, 79, &[80]).is_err());
+    }
+
+    #[test]
+    fn test_restamp_seq_num() {
+        let message = "8=FIX.4.2\x019=19\x0135=0\x0134=9\x0158=x\x0110=000\x01";
+        assert!(matches!(restamp_seq_num(message, 9), Cow::Borrowed(_)));
+
+        let restamped = restamp_seq_num(message, 10);
+        let body = "35=0\x0134=10\x0158=x\x01";
+        let head = format!("8=FIX.4.2\x019={}\x01{}", body.len(), body);
+        assert_eq!(
+            restamped,
+            format!("{}10={:03}\x01", head, checksum(head.as_bytes()))
+        );
+        assert!(crate::message_validator::garbled_reason(restamped.as_bytes()).is_none());
+    }
+
     #[test]
     fn test_fixmsg2msgtype() {
         let fix_tag_map = setup_fix_tag_map();
@@ -563,6 +687,120 @@ mod tests {
         assert_eq!(msg_map.get("MsgType").unwrap(), "A");
         assert_eq!(msg_map.get("SenderCompID").unwrap(), "SENDER123");
         assert_eq!(msg_map.get("TargetCompID").unwrap(), "TARGET123");
+
+        // A custom tag is kept by number and encoded again
+        let (msgtype, msg_map) = fixmsg2msgtype("35=A|5001=DESK7|49=S", &fix_tag_map).unwrap();
+        assert_eq!(msgtype, "A");
+        assert_eq!(msg_map.get("5001").unwrap(), "DESK7");
+        let mut fix_tag_name_map = HashMap::new();
+        fix_tag_name_map.insert("SenderCompID".to_string(), fix_tag_map[&49].clone());
+        let messages = HashMap::from([("Logon".to_string(), msg_map)]);
+        let fix_msg = msgtype2fixmsg("Logon".to_string(), &messages, &fix_tag_name_map, None, 1);
+        assert!(fix_msg.starts_with("5001=DESK7|49=S|10="));
+
+        // SOH delimited, RawData sliced by its length
+        let (_, msg_map) =
+            fixmsg2msgtype("35=A\x0195=3\x0196=a|b\x0158=x=y\x01", &fix_tag_map).unwrap();
+        assert_eq!(msg_map.get("96").unwrap(), "a|b");
+        assert_eq!(msg_map.get("58").unwrap(), "x=y");
+        let (msgtype, _) = fixmsg2msgtype("35=A|x=1|", &fix_tag_map).unwrap();
+        assert_eq!(msgtype, "InvalidTagNumber");
+    }
+
+    #[test]
+    fn test_custom_tags_survive_a_round_trip() {
+        let (fix_tag_number_map, fix_tag_name_map, _, _) =
+            crate::parse_xml::parse_fix_xml("reference/FIX4_2.xml").unwrap();
+        let inbound = "8=FIX.4.2\x019=0\x0135=D\x0149=CLIENT\x0156=VENUE\x0134=2\x01\
+                       11=ORD1\x015001=DESK7\x0121=1\x0155=IBM\x0154=1\x01\
+                       9100=ALGO=TWAP;PCT=10\x0138=100\x0140=1\x0120000=x\x0110=000\x01";
+        let (msgtype, msg_map) = fixmsg2msgtype(inbound, &fix_tag_number_map).unwrap();
+        assert_eq!(msgtype, "NEW_ORDER_SINGLE");
+        let custom_fields = |msg_map: &IndexMap<String, String>| -> Vec<(String, String)> {
+            msg_map
+                .iter()
+                .filter(|(name, _)| name.parse::<u32>().is_ok_and(|tag| tag >= 5000))
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect()
+        };
+        let sent = custom_fields(&msg_map);
+        assert_eq!(
+            sent,
+            [("5001", "DESK7"), ("9100", "ALGO=TWAP;PCT=10"), ("20000", "x")]
+                .map(|(tag, value)| (tag.to_string(), value.to_string()))
+        );
+
+        let messages = HashMap::from([(msgtype.clone(), msg_map)]);
+        let outbound = msgtype2fixmsg(msgtype, &messages, &fix_tag_name_map, None, 3)
+            .replace('|', "\x01");
+        assert!(crate::message_validator::garbled_reason(outbound.as_bytes()).is_none());
+        assert!(outbound.contains("\x0111=ORD1\x015001=DESK7\x0121=1\x01"));
+        let (_, received) = fixmsg2msgtype(&outbound, &fix_tag_number_map).unwrap();
+        assert_eq!(custom_fields(&received), sent);
+        assert_eq!(received["Side"], "BUY");
+    }
+
+    /// The parser `fixmsg2msgtype` had before reading fields in place: the message copied with
+    /// '|' for SOH, then split into fields and each field at '='.
+    fn split_fixmsg2msgtype(
+        fixmsg: &str,
+        fix_tag_number_map: &HashMap<u32, FixTag>,
+    ) -> (String, IndexMap<String, String>) {
+        let mut msgtype = String::new();
+        let mut msg_map = IndexMap::new();
+        for field in fixmsg.replace('\x01', "|").split('|') {
+            let parts: Vec<&str> = field.split('=').collect();
+            if parts.len() != 2 {
+                continue;
+            }
+            let tag: u32 = parts[0].parse().unwrap();
+            let (name, value) = match fix_tag_number_map.get(&tag) {
+                Some(definition) => {
+                    let value = definition
+                        .enum_values
+                        .as_ref()
+                        .and_then(|enum_values| enum_values.get(parts[1]))
+                        .map_or(parts[1], String::as_str);
+                    if definition.name == "MsgType" {
+                        msgtype = value.to_string();
+                    }
+                    (definition.name.clone(), value)
+                }
+                None => (tag.to_string(), parts[1]),
+            };
+            msg_map.entry(name).or_insert_with(|| value.to_string());
+        }
+        (msgtype, msg_map)
+    }
+
+    #[test]
+    fn test_fields_are_read_as_the_split_parser_did() {
+        let (fix_tag_number_map, _, _, _) =
+            crate::parse_xml::parse_fix_xml("reference/FIX4_2.xml").unwrap();
+        let messages = [
+            "8=FIX.4.2\x019=65\x0135=A\x0149=CLIENT\x0156=VENUE\x0134=1\x01\
+             52=20240101-00:00:00.000\x0198=0\x01108=30\x01141=Y\x0110=123\x01",
+            "8=FIX.4.2|9=120|35=D|49=CLIENT|56=VENUE|34=2|11=ORD-1/A|21=1|55=IBM|54=1|\
+             38=100.5|40=2|44=187.45|59=0|60=20240101-00:00:00|10=042|",
+            // An unknown enum value, a custom tag and a repeated tag, the first kept
+            "35=8\x0137=O1\x0111=C1\x0117=E1\x0120=0\x0139=Z\x01\
+             5001=DESK7\x0154=2\x0154=1\x01",
+            // Empty fields between delimiters
+            "35=0||112=TEST\x01\x0110=000|",
+        ];
+        for message in messages {
+            let (msgtype, msg_map) = fixmsg2msgtype(message, &fix_tag_number_map).unwrap();
+            assert_eq!((msgtype, msg_map), split_fixmsg2msgtype(message, &fix_tag_number_map));
+        }
+
+        // Unlike the split parser, a value holding '=' is kept, and a DATA value holding a
+        // delimiter is read to its length
+        let message = "35=B\x0158=x=y\x0195=3\x0196=a|b\x01";
+        let (_, msg_map) = fixmsg2msgtype(message, &fix_tag_number_map).unwrap();
+        let (_, split_map) = split_fixmsg2msgtype(message, &fix_tag_number_map);
+        assert_eq!((msg_map["Text"].as_str(), msg_map["RawData"].as_str()), ("x=y", "a|b"));
+        assert!(!split_map.contains_key("Text"));
+        assert_eq!(split_map["RawData"], "a");
     }
 
     #[test]
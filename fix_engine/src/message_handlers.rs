@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+
+use indexmap::IndexMap;
+use log::{error, info};
+
+use crate::masking::mask_message;
+use crate::message_converter::msgtype2fixmsg;
+use crate::message_handling::{
+    business_reject_fields, ref_msg_type, send_sequenced,
+    BUSINESS_REJECT_REASON_UNSUPPORTED_MESSAGE_TYPE,
+};
+use crate::parse_xml::FixTag;
+use crate::sequence::SequenceNumberStore;
+use crate::transport::Transport;
+
+lazy_static! {
+    pub static ref MESSAGE_HANDLERS: MessageHandlers = MessageHandlers::new();
+}
+
+/// The session a handled message arrived on, to answer it.
+pub struct HandlerContext<'a> {
+    pub msgtype: &'a str,
+    /// The raw message.
+    pub message: &'a str,
+    pub stream: &'a dyn Transport,
+    pub app_msg: &'a HashMap<String, IndexMap<String, String>>,
+    pub fix_tag_name_map: &'a HashMap<String, FixTag>,
+    pub seq_store: &'a Arc<SequenceNumberStore>,
+}
+
+impl HandlerContext<'_> {
+    /// Sends the message named as in the predefined messages (e.g. `Execution_Report`), the
+    /// fields given overriding its defaults.
+    pub fn reply(&self, message_name: &str, fields: &HashMap<String, String>) -> io::Result<()> {
+        let message = msgtype2fixmsg(
+            message_name.to_string(),
+            self.app_msg,
+            self.fix_tag_name_map,
+            Some(fields),
+            self.seq_store.get_outgoing(),
+        );
+        let stream = Arc::new(Mutex::new(self.stream.try_clone_transport()?));
+        send_sequenced(&stream, self.seq_store, message.replace('|', "\x01"))
+    }
+}
+
+/// What `handle_business_message` does once a handler returns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Handled {
+    /// The next handler and then the built-in logic run, e.g. after recording the message.
+    Continue,
+    /// The message is fully handled, the built-in logic is skipped.
+    Done,
+}
+
+type Handler = Box<dyn Fn(&IndexMap<String, String>, &HandlerContext) -> Handled + Send + Sync>;
+
+/// Handlers of the business messages by message name, run before the built-in logic so they
+/// can augment or replace it.
+pub struct MessageHandlers {
+    handlers: RwLock<HashMap<String, Vec<Handler>>>,
+}
+
+impl MessageHandlers {
+    pub fn new() -> Self {
+        Self {
+            handlers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Registers a handler of the messages named `msgtype` as in the dictionary, e.g.
+    /// `NEW_ORDER_SINGLE`. The handlers of a message run in the order registered.
+    pub fn on<F>(&self, msgtype: &str, handler: F)
+    where
+        F: Fn(&IndexMap<String, String>, &HandlerContext) -> Handled + Send + Sync + 'static,
+    {
+        info!("Registered a handler of {}", msgtype);
+        self.handlers
+            .write()
+            .unwrap()
+            .entry(msgtype.to_string())
+            .or_default()
+            .push(Box::new(handler));
+    }
+
+    /// Runs the handlers of the message until one is `Done`.
+    pub fn dispatch(
+        &self,
+        msg_map: &IndexMap<String, String>,
+        context: &HandlerContext,
+    ) -> Handled {
+        let handlers = self.handlers.read().unwrap();
+        for handler in handlers.get(context.msgtype).into_iter().flatten() {
+            if handler(msg_map, context) == Handled::Done {
+                return Handled::Done;
+            }
+        }
+        Handled::Continue
+    }
+
+    /// Registers the handler configured for a message: `log` logs it before the built-in
+    /// logic, `ignore` drops it and `reject` answers it with a BusinessMessageReject.
+    pub fn register_action(&self, msgtype: &str, action: &str) -> Result<(), String> {
+        match action {
+            "log" => self.on(msgtype, |_, context| {
+                info!(
+                    "{} received: {}",
+                    context.msgtype,
+                    mask_message(context.message)
+                );
+                Handled::Continue
+            }),
+            "ignore" => self.on(msgtype, |_, context| {
+                info!("{} ignored", context.msgtype);
+                Handled::Done
+            }),
+            "reject" => self.on(msgtype, |msg_map, context| {
+                let fields = business_reject_fields(
+                    msg_map,
+                    &ref_msg_type(msg_map, context.fix_tag_name_map),
+                    BUSINESS_REJECT_REASON_UNSUPPORTED_MESSAGE_TYPE,
+                    &format!("{} not accepted", context.msgtype),
+                );
+                if let Err(e) = context.reply("Business_Message_Reject", &fields) {
+                    error!("Failed to reject {}: {}", context.msgtype, e);
+                }
+                Handled::Done
+            }),
+            _ => return Err(format!("Unknown handler {} of {}", action, msgtype)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MemoryTransport;
+    use std::io::Read;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_handlers_run_in_order_until_done() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let seq_store =
+            Arc::new(SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap());
+        let (local, mut remote) = MemoryTransport::pair();
+        let reject = IndexMap::from([("MsgType".to_string(), "j".to_string())]);
+        let app_msg = HashMap::from([("Business_Message_Reject".to_string(), reject)]);
+        let fix_tag_name_map = HashMap::new();
+        let context = |msgtype| HandlerContext {
+            msgtype,
+            message: "",
+            stream: &local,
+            app_msg: &app_msg,
+            fix_tag_name_map: &fix_tag_name_map,
+            seq_store: &seq_store,
+        };
+
+        let handlers = MessageHandlers::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&calls);
+        handlers.on("NEWS", move |_, _| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            Handled::Continue
+        });
+        handlers.register_action("NEWS", "log").unwrap();
+        handlers.register_action("NEWS", "ignore").unwrap();
+        handlers.register_action("NEWS", "reject").unwrap();
+        handlers.register_action("QUOTE_REQUEST", "reject").unwrap();
+        assert!(handlers.register_action("NEWS", "forward").is_err());
+
+        let msg_map = IndexMap::new();
+        assert_eq!(handlers.dispatch(&msg_map, &context("NEWS")), Handled::Done);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            handlers.dispatch(&msg_map, &context("EMAIL")),
+            Handled::Continue
+        );
+
+        // The reject went out with the next outgoing sequence number
+        let outgoing = seq_store.get_outgoing();
+        assert_eq!(
+            handlers.dispatch(&msg_map, &context("QUOTE_REQUEST")),
+            Handled::Done
+        );
+        assert_eq!(seq_store.get_outgoing(), outgoing + 1);
+        let mut buf = [0; 1024];
+        assert!(remote.read(&mut buf).unwrap() > 0);
+    }
+}
@@ -1,62 +1,190 @@
 use chrono::Utc;
 use indexmap::IndexMap;
-use log::{error, info};
+use log::{debug, error, info, log_enabled, Level};
+use rust_decimal::Decimal;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::{self, Read, Write};
-use std::net::TcpStream;
+use std::io::{self, Write};
 use std::process;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
 
-use crate::message_converter::{fixmsg2msgtype, msgtype2fixmsg};
-use crate::orderstore::{add_order_to_store, update_order_in_store, OrderStore};
+use crate::allocations::{ack_fields, today, AllocationInstruction};
+use crate::auth::LOGON_AUTH;
+use crate::bridge::{client_of, forward_to, ForwardError};
+use crate::buffer_pool::{BufferPool, BUFFER_SIZE};
+use crate::counterparty::{behaviour_of, SessionBehaviour};
+use crate::dont_know_trade::{dont_know_trade_fields, is_known, SENT_ORDERS};
+use crate::engine_events::ENGINE_EVENTS;
+use crate::execution_store::{store_execution_report, ExecDirection};
+use crate::gap_report::journal_gap_report;
+#[cfg(feature = "kafka")]
+use crate::kafka::publish_message;
+use crate::logging::log_message;
+use crate::masking::{mask_fields, mask_message};
+use crate::matching::{OrderOwner, MATCHING_ENGINE};
+use crate::message_converter::{
+    append_fields, fixmsg2msgtype, msgtype2fixmsg, repeating_group, restamp_seq_num,
+};
+use crate::message_handlers::{Handled, HandlerContext, MESSAGE_HANDLERS};
+use crate::message_journal::{journal_received, journal_sent, MESSAGE_JOURNALS};
+use crate::message_validator::{
+    describe_errors, garbled_reason, identity_problem, sending_time_problem, ValidationError,
+};
+use crate::news::News;
+use crate::order_events::{OrderEvent, OrderEventKind, ORDER_EVENTS};
+use crate::order_gateway::apply_execution_report;
+use crate::order_lists::{
+    list_order_status, list_status_fields, list_status_group, rejected_list_group, NewOrderList,
+    LIST_ORDER_STATUS_RECEIVED_FOR_EXECUTION, LIST_ORDER_STATUS_REJECT, LIST_STATUS_TYPE_ACK,
+    LIST_STATUS_TYPE_RESPONSE,
+};
+use crate::orderstore::{
+    add_order_to_store, msg_seq_num, replace_order_in_store, IllegalTransition, OrdStatus, Order,
+    OrderError, OrderKey, OrderStore,
+};
+use crate::outbound::application_messages;
 use crate::parse_xml::{print_fix_message, FixTag};
-use crate::sequence::SequenceNumberStore;
-use crate::{MessageMap, IS_INITIATOR, LAST_SENT_TIME, RECEIVED_LOGON, SENT_LOGON};
+use crate::pending_orders::PENDING_ORDERS;
+use crate::quotes::{Quote, QUOTES, QUOTE_CONFIG};
+use crate::risk::{check_pre_trade, RiskBreach, ACCOUNT_RISK, PRE_TRADE_LIMITS};
+use crate::routing::{route, RouteTarget};
+use crate::schedule::{is_session_closed, SESSION_SCHEDULE};
+use crate::security_list::{
+    pages, security_definition_fields, security_definition_page, security_list_page,
+    security_request_rejected_fields, SecurityRequest,
+};
+use crate::sequence::{SequenceNumberStore, SessionId};
+use crate::session::SESSION_STATES;
+use crate::session_events::SESSION_HOOKS;
+use crate::session_stats::SessionStats;
+use crate::simulator::{start_fills, FillContext};
+use crate::symbol_master::SYMBOL_MASTER;
+use crate::tag_value::{self, decode_message};
+use crate::timer::TIMERS;
+use crate::transport::{message_length, Transport, MAX_MESSAGE_LEN};
+use crate::watchdog::SessionActivity;
+use crate::{
+    MessageMap, DONT_KNOW_TRADE, IS_INITIATOR, LOGOUT_TIMEOUT, MATCHING_ENGINE_ENABLED,
+    RECONNECT_REQUESTED, RESET_ON_LOGON, SECURITY_LIST_PAGE_SIZE,
+};
+
+/// What the read loop of a session shares with the session's other threads: the QoS statistics
+/// it records, the activity the watchdog checks and the pool its read buffer comes from.
+pub struct ReaderShared {
+    pub stats: Arc<SessionStats>,
+    pub activity: Arc<SessionActivity>,
+    pub buffers: Arc<BufferPool>,
+}
 
 pub fn read_and_route_messages(
-    stream: &mut TcpStream,
+    stream: &mut dyn Transport,
+    session_id: &SessionId,
     all_msg_map_collection: &MessageMap,
     seq_store: Arc<SequenceNumberStore>,
     order_store: Arc<OrderStore>,
+    shared: &ReaderShared,
 ) -> Result<(), io::Error> {
-    let mut buf = [0; 1024];
+    let ReaderShared {
+        stats,
+        activity,
+        buffers,
+    } = shared;
+    let mut buf = buffers.take();
+    buf.resize(BUFFER_SIZE, 0);
+    // What has been read of the messages not handled yet
+    let mut pending = buffers.take();
     loop {
         match stream.read(&mut buf) {
             Ok(0) => {
-                info!("Got disconnected, exiting!!");
-                process::exit(1);
+                SESSION_HOOKS.disconnect(session_id);
+                if IS_INITIATOR.load(Ordering::SeqCst) {
+                    if RECONNECT_REQUESTED.load(Ordering::SeqCst) {
+                        info!("Disconnected to reconnect");
+                        break;
+                    }
+                    if SESSION_STATES.for_session(session_id).is_logout_sent() {
+                        if SESSION_SCHEDULE.read().unwrap().is_some() {
+                            info!("Logged out, waiting for the next session");
+                            break;
+                        }
+                        info!("Logged out, exiting");
+                        seq_store.flush();
+                        process::exit(0);
+                    }
+                    info!("Got disconnected, exiting!!");
+                    seq_store.flush();
+                    process::exit(1);
+                }
+                info!("Client disconnected, closing the connection");
+                break;
             }
             Ok(bytes_read) => {
-                handle_incoming_message(
-                    &buf[..bytes_read],
-                    stream,
-                    all_msg_map_collection,
-                    Arc::clone(&seq_store),
-                    Arc::clone(&order_store),
-                )?;
+                activity.touch_reader();
+                pending.extend_from_slice(&buf[..bytes_read]);
+                // A read may hold several messages, or part of one
+                loop {
+                    let length = match message_length(&pending) {
+                        Some(length) => length,
+                        // Bytes which never become a message are given up on
+                        None if pending.len() > MAX_MESSAGE_LEN => pending.len(),
+                        None => break,
+                    };
+                    let started = Instant::now();
+                    handle_incoming_message(
+                        &pending[..length],
+                        stream,
+                        all_msg_map_collection,
+                        Arc::clone(&seq_store),
+                        Arc::clone(&order_store),
+                    )?;
+                    stats.record_incoming(started.elapsed());
+                    pending.drain(..length);
+                }
             }
             Err(e) => {
                 error!("Error reading from stream: {}", e);
+                SESSION_HOOKS.disconnect(session_id);
                 break;
             }
         }
-        buf = [0; 1024];
     }
+    buffers.give_back(buf);
+    buffers.give_back(pending);
     Ok(())
 }
 
 fn handle_incoming_message(
     buf: &[u8],
-    stream: &mut TcpStream,
+    stream: &mut dyn Transport,
     all_msg_map_collection: &MessageMap,
     seq_store: Arc<SequenceNumberStore>,
     order_store: Arc<OrderStore>,
 ) -> Result<(), io::Error> {
-    if let Ok(message) = std::str::from_utf8(buf) {
-        info!("Received message: {}", message);
+    // Binary DATA fields are given in hexadecimal, BodyLength and CheckSum are checked on the
+    // bytes received
+    if let Some(decoded) = decode_message(buf) {
+        let message = decoded.as_ref();
+        log_message("Received message", message);
 
         if is_fix_message(message) {
+            // Garbled messages are ignored, the next valid message still has to carry the
+            // MsgSeqNum we expect
+            if let Some(reason) = garbled_reason(buf) {
+                info!(
+                    "Ignoring garbled message ({}): {}",
+                    reason,
+                    mask_message(message).replace('\x01', "|")
+                );
+                return Ok(());
+            }
+            journal_received(message);
+            store_execution_report(ExecDirection::Received, message);
+            #[cfg(feature = "kafka")]
+            publish_message(ExecDirection::Received, message);
+            ENGINE_EVENTS.message(ExecDirection::Received, message);
             process_fix_message(
                 message,
                 stream,
@@ -73,119 +201,236 @@ fn handle_incoming_message(
 
 fn process_fix_message(
     message: &str,
-    stream: &mut TcpStream,
+    stream: &mut dyn Transport,
     all_msg_map_collection: &MessageMap,
     seq_store: Arc<SequenceNumberStore>,
     order_store: Arc<OrderStore>,
 ) -> Result<(), io::Error> {
-    if let Ok(fix_details) = print_fix_message(&message, &all_msg_map_collection.fix_tag_number_map)
-    {
-        println!("{}", fix_details);
+    // The table is only made when it is logged
+    if log_enabled!(Level::Debug) {
+        if let Ok(fix_details) =
+            print_fix_message(message, &all_msg_map_collection.fix_tag_number_map)
+        {
+            debug!("{}", fix_details);
+        }
     }
 
-    let modified_message = message.replace('\x01', "|");
-    if let Ok(fix_message) = crate::message_validator::FixMessage::parse(&modified_message) {
-        if fix_message.validate(
+    if let Ok(fix_message) = crate::message_validator::FixMessage::parse(message) {
+        if let Err(errors) = fix_message.validate(
             &all_msg_map_collection.required_fields,
             &all_msg_map_collection.valid_msg_types,
-            &all_msg_map_collection.msgnumber_fields_map.clone(),
+            &all_msg_map_collection.msgnumber_fields_map,
+            &all_msg_map_collection.fix_tag_number_map,
         ) {
-            if let Ok((msgtype, msg_map)) =
-                fixmsg2msgtype(&message, &all_msg_map_collection.fix_tag_number_map)
+            reject_invalid_message(message, &errors, all_msg_map_collection, &seq_store, stream)?;
+        } else {
+            info!("{}", mask_message(message).replace('\x01', "|"));
+            // The sequencing reads the fields by tag number, only a message handed on to the
+            // handlers has its fields named
+            let fix_tag_number_map = &all_msg_map_collection.fix_tag_number_map;
+            let msgtype = fix_message
+                .described(35, fix_tag_number_map)
+                .unwrap_or_default()
+                .to_string();
+            let named_fields = || {
+                let (_, msg_map) = fix_message.to_names(fix_tag_number_map);
+                info!(
+                    "Parsed message type: {}, map: {:?}",
+                    msgtype,
+                    mask_fields(&msg_map, fix_tag_number_map)
+                );
+                msg_map
+            };
+
+            // The counterparty restarted its outgoing numbers, the Logon itself carries MsgSeqNum=1
+            if msgtype == "LOGON"
+                && matches!(
+                    fix_message.described(141, fix_tag_number_map),
+                    Some("Y") | Some("YES")
+                )
             {
-                info!("Parsed message type: {}, map: {:?}", msgtype, msg_map);
+                info!("Logon with ResetSeqNumFlag=Y, resetting incoming sequence number to 1");
+                seq_store.set_incoming(1);
+            }
 
-                let expected_incoming_seq_num = seq_store.get_incoming();
-                if let Some(incoming_seq_num) =
-                    msg_map.get("MsgSeqNum").and_then(|s| s.parse::<u64>().ok())
-                {
-                    if expected_incoming_seq_num == incoming_seq_num {
-                        println!(
-                            "Expected incoming seq num: {} vs msg.MsgSeqNum: {}",
-                            expected_incoming_seq_num, incoming_seq_num
-                        );
-                        seq_store.increment_incoming();
-
-                        if is_admin_message(&msgtype, all_msg_map_collection.admin_msg_list.clone())
-                        {
-                            handle_admin_message(
-                                stream.try_clone().expect("Failed to clone stream"),
-                                &msgtype,
-                                &msg_map,
-                                &all_msg_map_collection.admin_msg,
-                                &all_msg_map_collection.fix_tag_name_map,
-                                message,
-                                Arc::clone(&seq_store),
-                            );
-                        } else {
-                            handle_business_message(
-                                stream.try_clone().expect("Failed to clone stream"),
-                                &msgtype,
-                                &msg_map,
-                                &all_msg_map_collection.app_msg,
-                                &all_msg_map_collection.fix_tag_name_map,
-                                message,
-                                Arc::clone(&seq_store),
-                                Arc::clone(&order_store),
-                            );
-                        }
-                    } else if expected_incoming_seq_num < incoming_seq_num {
-                        if msgtype == "SEQUENCE_RESET" {
-                            handle_admin_message(
-                                stream.try_clone().expect("Failed to clone stream"),
-                                &msgtype,
-                                &msg_map,
-                                &all_msg_map_collection.admin_msg,
-                                &all_msg_map_collection.fix_tag_name_map,
-                                message,
-                                Arc::clone(&seq_store),
+            let expected_incoming_seq_num = seq_store.get_incoming();
+            if let Some(incoming_seq_num) = fix_message.get(34).and_then(|s| s.parse::<u64>().ok())
+            {
+                if expected_incoming_seq_num == incoming_seq_num {
+                    debug!(
+                        "Expected incoming seq num: {} vs msg.MsgSeqNum: {}",
+                        expected_incoming_seq_num, incoming_seq_num
+                    );
+                    seq_store.increment_incoming();
+                    let msg_map = named_fields();
+
+                    if let Some(problem) =
+                        identity_problem(&msg_map, &all_msg_map_collection.fix_header)
+                    {
+                        send_session_reject(
+                            &msg_map,
+                            "COMP_ID_PROBLEM",
+                            None,
+                            &problem,
+                            all_msg_map_collection,
+                            &seq_store,
+                            stream,
+                        )?;
+                    } else if let Some(problem) = stale_sending_time(&msg_map) {
+                        send_session_reject(
+                            &msg_map,
+                            "SENDING_TIME_ACCURACY_PROBLEM",
+                            None,
+                            &problem,
+                            all_msg_map_collection,
+                            &seq_store,
+                            stream,
+                        )?;
+                        let session_state =
+                            SESSION_STATES.for_session(&SessionId::from_received(&msg_map));
+                        let violations = session_state.latency_violation();
+                        if violations >= counterparty_behaviour(&msg_map).max_latency_violations {
+                            session_state.clear_latency_violations();
+                            let err_text = format!(
+                                "{} messages in a row with an inaccurate SendingTime",
+                                violations
                             );
-                        } else {
-                            println!("Resend Request, MsgSeqNum too high, expecting {} but received {}!!", expected_incoming_seq_num, incoming_seq_num);
-                            handle_resend_request(
-                                expected_incoming_seq_num,
-                                &msgtype,
-                                &all_msg_map_collection,
+                            handle_logout(
+                                &err_text,
+                                all_msg_map_collection,
                                 Arc::clone(&seq_store),
                                 stream,
                             )?;
                         }
+                    } else if msgtype == "REJECT" {
+                        // Session-level Reject is handled regardless of the configured
+                        // admin_messages
+                        handle_session_reject(&msg_map, all_msg_map_collection, &order_store);
+                    } else if is_admin_message(
+                        &msgtype,
+                        all_msg_map_collection.admin_msg_list.clone(),
+                    ) {
+                        handle_admin_message(
+                            stream.try_clone_transport().expect("Failed to clone stream"),
+                            &msgtype,
+                            &msg_map,
+                            &all_msg_map_collection.admin_msg.current(),
+                            &all_msg_map_collection.fix_tag_name_map,
+                            message,
+                            Arc::clone(&seq_store),
+                        );
                     } else {
-                        let err_text: String = format!(
-                            "MsgSeqNum too low, expecting {} but received {}!!",
-                            expected_incoming_seq_num, incoming_seq_num
+                        // The counterparty accepted a business message, the reject streak is over
+                        SESSION_STATES
+                            .for_session(&SessionId::from_received(&msg_map))
+                            .clear_session_rejects();
+                        handle_business_message(
+                            stream.try_clone_transport().expect("Failed to clone stream"),
+                            &msgtype,
+                            &msg_map,
+                            all_msg_map_collection,
+                            message,
+                            Arc::clone(&seq_store),
+                            Arc::clone(&order_store),
                         );
-                        handle_logout(
-                            &err_text,
+                    }
+                } else if expected_incoming_seq_num < incoming_seq_num {
+                    if msgtype == "SEQUENCE_RESET" {
+                        handle_admin_message(
+                            stream.try_clone_transport().expect("Failed to clone stream"),
                             &msgtype,
-                            &all_msg_map_collection,
+                            &named_fields(),
+                            &all_msg_map_collection.admin_msg.current(),
+                            &all_msg_map_collection.fix_tag_name_map,
+                            message,
+                            Arc::clone(&seq_store),
+                        );
+                    } else {
+                        info!(
+                            "MsgSeqNum too high, expecting {} but received {}, sending Resend Request",
+                            expected_incoming_seq_num, incoming_seq_num
+                        );
+                        handle_resend_request(
+                            expected_incoming_seq_num,
+                            all_msg_map_collection,
                             Arc::clone(&seq_store),
                             stream,
                         )?;
-                        process::exit(1);
                     }
+                } else if matches!(
+                    fix_message.described(43, fix_tag_number_map),
+                    Some("Y") | Some("YES")
+                ) {
+                    // A message sent again, already received under its MsgSeqNum
+                    info!(
+                        "Ignoring possible duplicate with MsgSeqNum {}, expecting {}",
+                        incoming_seq_num, expected_incoming_seq_num
+                    );
+                } else {
+                    // Only this session ends, the others of the engine go on
+                    let err_text: String = format!(
+                        "MsgSeqNum too low, expecting {} but received {}",
+                        expected_incoming_seq_num, incoming_seq_num
+                    );
+                    logout_and_disconnect(
+                        stream.try_clone_transport()?,
+                        &err_text,
+                        &all_msg_map_collection.admin_msg.current(),
+                        &all_msg_map_collection.fix_tag_name_map,
+                        &seq_store,
+                    );
+                    seq_store.flush();
                 }
-            } else {
-                error!("fixmsg2msgtype parse error: {}", modified_message);
             }
-        } else {
-            error!(
-                "Dropping the message due to validation failure!!! - {}",
-                modified_message
-            );
         }
     }
     Ok(())
 }
 
+/// Refuses an inbound message failing validation with a Reject (35=3) giving its first
+/// problem, when it carries the MsgSeqNum expected, which the Reject consumes. Any other
+/// message is dropped.
+fn reject_invalid_message(
+    message: &str,
+    errors: &[ValidationError],
+    all_msg_map_collection: &MessageMap,
+    seq_store: &Arc<SequenceNumberStore>,
+    stream: &mut dyn Transport,
+) -> Result<(), io::Error> {
+    let problems = describe_errors(errors);
+    let msg_map = fixmsg2msgtype(message, &all_msg_map_collection.fix_tag_number_map)
+        .map(|(_, msg_map)| msg_map)
+        .ok()
+        .filter(|msg_map| {
+            msg_map.get("MsgSeqNum").and_then(|s| s.parse::<u64>().ok())
+                == Some(seq_store.get_incoming())
+        });
+    let Some(msg_map) = msg_map else {
+        error!(
+            "Dropping the message due to validation failure!!! ({}) - {}",
+            problems,
+            mask_message(message).replace('\x01', "|")
+        );
+        return Ok(());
+    };
+    seq_store.increment_incoming();
+    send_session_reject(
+        &msg_map,
+        errors[0].reject_reason(),
+        Some(errors[0].tag()),
+        &problems,
+        all_msg_map_collection,
+        seq_store,
+        stream,
+    )
+}
+
 fn handle_resend_request(
     expected_incoming_seq_num: u64,
-    msgtype: &str,
     all_msg_map_collection: &MessageMap,
     seq_store: Arc<SequenceNumberStore>,
-    stream: &mut TcpStream,
+    stream: &mut dyn Transport,
 ) -> Result<(), io::Error> {
-    println!("Resend Request!!!");
     let mut override_map: HashMap<String, String> = HashMap::new();
     override_map.insert(
         "BeginSeqNo".to_string(),
@@ -193,51 +438,247 @@ fn handle_resend_request(
     );
     let fix_msg: String = msgtype2fixmsg(
         "Resend_Request".to_string(),
-        &all_msg_map_collection.admin_msg,
+        &all_msg_map_collection.admin_msg.current(),
         &all_msg_map_collection.fix_tag_name_map,
         Some(&override_map),
         seq_store.get_outgoing(),
     );
-    println!("{}", fix_msg);
+    debug!("{}", mask_message(&fix_msg));
     let modified_response = fix_msg.replace("|", "\x01");
-    let new_stream = stream.try_clone()?;
+    let new_stream = stream.try_clone_transport()?;
     let stream = Arc::new(Mutex::new(new_stream));
-    if let Err(err) = send_message(&stream, modified_response) {
+    if let Err(err) = send_sequenced(&stream, &seq_store, modified_response) {
         error!("Failed to send resend request response: {}", err);
     }
-    seq_store.increment_outgoing();
     Ok(())
 }
 
 fn handle_logout(
     err_text: &str,
-    msgtype: &str,
     all_msg_map_collection: &MessageMap,
     seq_store: Arc<SequenceNumberStore>,
-    stream: &mut TcpStream,
+    stream: &mut dyn Transport,
 ) -> Result<(), io::Error> {
     let mut override_map: HashMap<String, String> = HashMap::new();
     override_map.insert("Text".to_string(), err_text.to_string());
     let fix_msg: String = msgtype2fixmsg(
         "Logout".to_string(),
-        &all_msg_map_collection.admin_msg,
+        &all_msg_map_collection.admin_msg.current(),
         &all_msg_map_collection.fix_tag_name_map,
         Some(&override_map),
         seq_store.get_outgoing(),
     );
-    println!("{}", fix_msg);
+    debug!("{}", mask_message(&fix_msg));
     let modified_response = fix_msg.replace("|", "\x01");
-    let new_stream = stream.try_clone()?;
+    let new_stream = stream.try_clone_transport()?;
     let stream = Arc::new(Mutex::new(new_stream));
-    if let Err(err) = send_message(&stream, modified_response) {
+    if let Err(err) = send_sequenced(&stream, &seq_store, modified_response) {
         error!("Failed to send logout response: {}", err);
     }
-    seq_store.increment_outgoing();
     Ok(())
 }
 
+/// The behaviour configured for the counterparty of an inbound message.
+fn counterparty_behaviour(msg_map: &IndexMap<String, String>) -> Arc<SessionBehaviour> {
+    behaviour_of(msg_map.get("SenderCompID").map(String::as_str))
+}
+
+/// The SendingTime problem of an inbound message when `max_latency_seconds` is set, counting
+/// the messages with an accurate one as ending the run of violations.
+fn stale_sending_time(msg_map: &IndexMap<String, String>) -> Option<String> {
+    let max_latency_seconds = counterparty_behaviour(msg_map).max_latency_seconds;
+    if max_latency_seconds == 0 {
+        return None;
+    }
+    let problem = sending_time_problem(msg_map, Utc::now(), max_latency_seconds);
+    if problem.is_none() {
+        SESSION_STATES
+            .for_session(&SessionId::from_received(msg_map))
+            .clear_latency_violations();
+    }
+    problem
+}
+
+/// Refuses an inbound message with a session-level Reject (35=3) giving the reason, as
+/// described in the dictionary (e.g. COMP_ID_PROBLEM), the tag at fault in RefTagID if any,
+/// and the problem in Text.
+fn send_session_reject(
+    msg_map: &IndexMap<String, String>,
+    reason: &str,
+    ref_tag_id: Option<&str>,
+    text: &str,
+    all_msg_map_collection: &MessageMap,
+    seq_store: &Arc<SequenceNumberStore>,
+    stream: &mut dyn Transport,
+) -> Result<(), io::Error> {
+    error!("Rejecting the message: {}", text);
+    let Some(mut reject) = all_msg_map_collection
+        .admin_msg
+        .current()
+        .get("Reject")
+        .cloned()
+    else {
+        error!("No Reject template in the predefined messages");
+        return Ok(());
+    };
+    // FIX 4.2 has no reason past INVALID_MSG_TYPE, such a reason is only given in Text
+    let reasons = all_msg_map_collection
+        .fix_tag_name_map
+        .get("SessionRejectReason")
+        .and_then(|tag| tag.enum_values.as_ref());
+    let enumerated = reasons.is_none_or(|reasons| reasons.contains_key(reason));
+    if !enumerated {
+        reject.shift_remove("SessionRejectReason");
+    }
+    let mut override_map: HashMap<String, String> = HashMap::new();
+    insert_if_some_and_not_empty(
+        &mut override_map,
+        "RefSeqNum",
+        msg_map.get("MsgSeqNum").map(String::as_str),
+    );
+    override_map.insert(
+        "RefMsgType".to_string(),
+        ref_msg_type(msg_map, &all_msg_map_collection.fix_tag_name_map),
+    );
+    insert_if_some_and_not_empty(&mut override_map, "RefTagID", ref_tag_id);
+    if enumerated {
+        override_map.insert("SessionRejectReason".to_string(), reason.to_string());
+    }
+    override_map.insert("Text".to_string(), text.to_string());
+    let fix_msg = msgtype2fixmsg(
+        "Reject".to_string(),
+        &HashMap::from([("Reject".to_string(), reject)]),
+        &all_msg_map_collection.fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    );
+    let stream = Arc::new(Mutex::new(stream.try_clone_transport()?));
+    send_sequenced(&stream, seq_store, fix_msg.replace("|", "\x01"))
+}
+
+/// Handles a session-level Reject (35=3) of one of our messages. The rejected message is
+/// resolved from the journal by RefSeqNum; if it was an order message the order is marked
+/// REJECTED and an order event is published. Order flow of the session is halted once
+/// `max_consecutive_rejects` Rejects arrive in a row.
+fn handle_session_reject(
+    msg_map: &IndexMap<String, String>,
+    all_msg_map_collection: &MessageMap,
+    order_store: &Arc<OrderStore>,
+) {
+    let ref_seq_num = msg_map.get("RefSeqNum").and_then(|s| s.parse::<u64>().ok());
+    let reason = match (msg_map.get("SessionRejectReason"), msg_map.get("Text")) {
+        (Some(reason), Some(text)) => format!("{}: {}", reason, text),
+        (Some(reason), None) => reason.clone(),
+        (None, Some(text)) => text.clone(),
+        (None, None) => "Session reject".to_string(),
+    };
+    error!(
+        "Received session Reject for our MsgSeqNum {:?}: {}",
+        ref_seq_num, reason
+    );
+
+    let journal = MESSAGE_JOURNALS.for_session(&SessionId::from_received(msg_map));
+    let rejected_message = match (journal, ref_seq_num) {
+        (Some(journal), Some(ref_seq_num)) => {
+            if let Err(e) = journal.record_rejected(ref_seq_num, &reason) {
+                error!("Failed to journal the reject: {}", e);
+            }
+            journal.sent_message(ref_seq_num)
+        }
+        _ => None,
+    };
+
+    match rejected_message
+        .and_then(|message| fixmsg2msgtype(&message, &all_msg_map_collection.fix_tag_number_map).ok())
+    {
+        Some((ref_msgtype, ref_msg_map)) if ref_msg_map.contains_key("ClOrdID") => {
+            info!(
+                "Rejected message was {}: {:?}",
+                ref_msgtype,
+                mask_fields(&ref_msg_map, &all_msg_map_collection.fix_tag_number_map)
+            );
+            let cl_ord_id = &ref_msg_map["ClOrdID"];
+            let key = order_key(msg_map, cl_ord_id);
+            if order_store.get_order(&key).is_some() {
+                if let Err(e) =
+                    order_store.set_status(&key, OrdStatus::Rejected, msg_seq_num(msg_map))
+                {
+                    error!("Failed to mark order {} as rejected: {}", cl_ord_id, e);
+                }
+            }
+
+            let mut event = OrderEvent::from_order_message(OrderEventKind::Rejected, &ref_msg_map);
+            event.text = Some(reason);
+            ORDER_EVENTS.publish(&event);
+        }
+        Some((ref_msgtype, _)) => info!("Rejected message was {}", ref_msgtype),
+        None => info!("Rejected message not found in the journal"),
+    }
+
+    let session_state = SESSION_STATES.for_session(&SessionId::from_received(msg_map));
+    let consecutive_rejects = session_state.session_reject();
+    let max_consecutive_rejects = counterparty_behaviour(msg_map).max_consecutive_rejects;
+    if max_consecutive_rejects > 0 && consecutive_rejects >= max_consecutive_rejects {
+        error!(
+            "{} consecutive session Rejects, halting order flow",
+            consecutive_rejects
+        );
+        session_state.halt_order_flow();
+    }
+}
+
+/// Sends a Logout and waits up to LOGOUT_TIMEOUT seconds for the counterparty's Logout
+/// confirmation before closing the socket. Returns whether the confirmation arrived in time.
+pub fn initiate_logout(
+    stream: &Arc<Mutex<Box<dyn Transport>>>,
+    session_id: &SessionId,
+    all_msg_map_collection: &MessageMap,
+    seq_store: &Arc<SequenceNumberStore>,
+    text: Option<&str>,
+) -> Result<bool, io::Error> {
+    let mut override_map: HashMap<String, String> = HashMap::new();
+    if let Some(text) = text {
+        override_map.insert("Text".to_string(), text.to_string());
+    }
+    let fix_msg = msgtype2fixmsg(
+        "Logout".to_string(),
+        &all_msg_map_collection.admin_msg.current(),
+        &all_msg_map_collection.fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    );
+
+    let session_state = SESSION_STATES.for_session(session_id);
+    session_state.logout_sent();
+    send_sequenced(stream, seq_store, fix_msg.replace("|", "\x01"))?;
+
+    // The read loop flags the confirmation when the counterparty's Logout arrives
+    let timeout = Duration::from_secs(LOGOUT_TIMEOUT.load(Ordering::SeqCst));
+    let started = Instant::now();
+    while !session_state.is_logout_received() && started.elapsed() < timeout {
+        sleep(Duration::from_millis(100));
+    }
+
+    let confirmed = session_state.is_logout_received();
+    if confirmed {
+        info!("Logout confirmed by the counterparty");
+    } else {
+        error!(
+            "No Logout confirmation within {} seconds, closing the connection",
+            timeout.as_secs()
+        );
+    }
+    journal_gap_report(session_id);
+
+    let shutdown_result = stream.lock().unwrap().close();
+    if let Err(err) = shutdown_result {
+        error!("Failed to close the connection: {}", err);
+    }
+    Ok(confirmed)
+}
+
 pub fn handle_admin_message(
-    stream: TcpStream,
+    stream: Box<dyn Transport>,
     msgtype: &str,
     msg_map: &IndexMap<String, String>,
     admin_msg: &HashMap<String, IndexMap<String, String>>,
@@ -245,34 +686,63 @@ pub fn handle_admin_message(
     message: &str,
     seq_store: Arc<SequenceNumberStore>,
 ) {
-    info!("Handling admin message {}: {}", msgtype, message);
+    info!("Handling admin message {}: {}", msgtype, mask_message(message));
 
-    if SENT_LOGON.load(Ordering::SeqCst) && msgtype == "LOGON" {
-        if IS_INITIATOR.load(Ordering::SeqCst) {
-            RECEIVED_LOGON.store(true, Ordering::SeqCst);
-            info!(
-                "Initiator received the Logon message: RECEIVED_LOGON - {}",
-                RECEIVED_LOGON.load(Ordering::SeqCst)
-            );
+    let session_id = SessionId::from_received(msg_map);
+    let session_state = SESSION_STATES.for_session(&session_id);
+    if session_state.is_logout_sent() && msgtype == "LOGOUT" {
+        // Confirmation of our own Logout, initiate_logout closes the connection
+        session_state.logout_received();
+        info!("Received Logout confirmation");
+        SESSION_HOOKS.logout(&session_id, msg_map.get("Text").map(|text| text.as_str()));
+        return;
+    }
+
+    if session_state.is_logon_sent() && msgtype == "LOGON" {
+        // The answer to the Logon we initiated
+        if !session_state.is_logon_received() {
+            session_state.logon_received();
+            session_state.clear_logout();
+            session_state.resume_order_flow();
+            info!("Initiator received the Logon message");
+            SESSION_HOOKS.logon(&session_id);
         }
-        info!(
-            "No message sent: SENT_LOGON - {}",
-            SENT_LOGON.load(Ordering::SeqCst)
-        );
+        info!("No message sent, Logon already sent");
         return;
     }
+    let mut disconnect = false;
+    let mut logged_on = false;
     let response = match msgtype {
         "LOGON" => {
-            // Set the RECEIVED_LOGON and SENT_LOGON flags to true
-            RECEIVED_LOGON.store(true, Ordering::SeqCst);
-            SENT_LOGON.store(true, Ordering::SeqCst);
+            if is_session_closed() {
+                let reason = "Logon rejected: outside of session hours";
+                logout_and_disconnect(stream, reason, admin_msg, fix_tag_name_map, &seq_store);
+                return;
+            }
+            if let Err(reason) = LOGON_AUTH.read().unwrap().authenticate(msg_map) {
+                logout_and_disconnect(stream, &reason, admin_msg, fix_tag_name_map, &seq_store);
+                return;
+            }
+
+            session_state.logon_received();
+            session_state.logon_sent();
+            session_state.clear_logout();
+            logged_on = true;
+
+            // Echo ResetSeqNumFlag=Y so the counterparty resets its incoming sequence number too
+            let mut override_map: HashMap<String, String> = HashMap::new();
+            if is_reset_seq_num_requested(msg_map) || RESET_ON_LOGON.load(Ordering::SeqCst) {
+                info!("Resetting outgoing sequence number to 1 for Logon response");
+                seq_store.set_outgoing(1);
+                override_map.insert("ResetSeqNumFlag".to_string(), "Y".to_string());
+            }
 
             // Generate the FIX message for Logon
             msgtype2fixmsg(
                 "Logon".to_string(),      // The type of message
                 admin_msg,                // The admin message
                 fix_tag_name_map,         // The FIX tag name map
-                None,                     // No overrides
+                Some(&override_map),      // ResetSeqNumFlag override, if any
                 seq_store.get_outgoing(), // The current outgoing sequence number
             )
         }
@@ -289,6 +759,12 @@ pub fn handle_admin_message(
         }
 
         "RESEND_REQUEST" => {
+            let seq_no = |key: &str| msg_map.get(key).and_then(|s| s.parse::<u64>().ok());
+            SESSION_HOOKS.resend(
+                &session_id,
+                seq_no("BeginSeqNo").unwrap_or(1),
+                seq_no("EndSeqNo").unwrap_or(0),
+            );
             // Create a new HashMap to hold the override mappings
             let mut override_map: HashMap<String, String> = HashMap::new();
             // Insert the current incoming sequence number into the override map
@@ -303,6 +779,21 @@ pub fn handle_admin_message(
             )
         }
 
+        "LOGOUT" => {
+            // The counterparty initiated the Logout, confirm it and disconnect once sent
+            info!("Counterparty requested Logout, confirming");
+            session_state.logout_sent();
+            SESSION_HOOKS.logout(&session_id, msg_map.get("Text").map(|text| text.as_str()));
+            disconnect = true;
+            msgtype2fixmsg(
+                "Logout".to_string(),
+                admin_msg,
+                fix_tag_name_map,
+                None,
+                seq_store.get_outgoing(),
+            )
+        }
+
         "SEQUENCE_RESET" => {
             // Retrieve the value associated with "NewSeqNo" and attempt to parse it as an u64
             let new_seqno: u64 = msg_map
@@ -311,15 +802,21 @@ pub fn handle_admin_message(
                 .parse::<u64>()
                 .expect("Failed to parse NewSeqNo as u64");
 
-            // Log the reset of the outgoing sequence number
+            // The counterparty's next MsgSeqNum, the one expected of it
             info!(
-                "Resetting Outgoing Sequence number! {} -> {}",
-                seq_store.get_outgoing(),
+                "Resetting Incoming Sequence number! {} -> {}",
+                seq_store.get_incoming(),
                 new_seqno
             );
-
-            // Update the outgoing sequence number
-            seq_store.set_outgoing(new_seqno);
+            seq_store.set_incoming(new_seqno);
+            SESSION_HOOKS.sequence_reset(
+                &session_id,
+                new_seqno,
+                matches!(
+                    msg_map.get("GapFillFlag").map(|flag| flag.as_str()),
+                    Some("Y") | Some("YES")
+                ),
+            );
 
             // Return an empty string
             "".to_string()
@@ -330,32 +827,129 @@ pub fn handle_admin_message(
     if !response.is_empty() {
         let modified_response = response.replace("|", "\x01");
         let stream = Arc::new(Mutex::new(stream));
-        if let Err(err) = send_message(&stream, modified_response) {
+        if let Err(err) = send_sequenced(&stream, &seq_store, modified_response) {
             error!("Failed to send admin response: {}", err);
         }
-        seq_store.increment_outgoing();
 
-        LAST_SENT_TIME.store(Utc::now(), Ordering::SeqCst);
-        info!(
-            "Updated last sent time: {:?}",
-            LAST_SENT_TIME.load(Ordering::SeqCst)
-        );
+        if logged_on {
+            SESSION_HOOKS.logon(&session_id);
+        }
+        if disconnect {
+            let shutdown_result = stream.lock().unwrap().close();
+            if let Err(err) = shutdown_result {
+                error!("Failed to close the connection: {}", err);
+            }
+            journal_gap_report(&session_id);
+        }
     } else {
         info!("Nothing to send out!");
     }
 }
 
+/// Sends a Logout carrying the reason, then disconnects without waiting for the counterparty's:
+/// for a Logon that failed authentication, or a message with a MsgSeqNum lower than expected.
+fn logout_and_disconnect(
+    stream: Box<dyn Transport>,
+    reason: &str,
+    admin_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &Arc<SequenceNumberStore>,
+) {
+    error!("{}", reason);
+    let mut override_map: HashMap<String, String> = HashMap::new();
+    override_map.insert("Text".to_string(), reason.to_string());
+    let fix_msg = msgtype2fixmsg(
+        "Logout".to_string(),
+        admin_msg,
+        fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    );
+    let stream = Arc::new(Mutex::new(stream));
+    if let Err(err) = send_sequenced(&stream, seq_store, fix_msg.replace("|", "\x01")) {
+        error!("Failed to send logout response: {}", err);
+    }
+
+    let shutdown_result = stream.lock().unwrap().close();
+    if let Err(err) = shutdown_result {
+        error!("Failed to close the connection: {}", err);
+    }
+}
+
 pub fn handle_business_message(
-    stream: TcpStream,
+    stream: Box<dyn Transport>,
     msgtype: &str,
     msg_map: &IndexMap<String, String>,
-    app_msg: &HashMap<String, IndexMap<String, String>>,
-    fix_tag_name_map: &HashMap<String, FixTag>,
+    all_msg_map_collection: &MessageMap,
     message: &str,
     seq_store: Arc<SequenceNumberStore>,
     order_store: Arc<OrderStore>,
 ) {
-    info!("Handling business message {}: {}", msgtype, message);
+    info!("Handling business message {}: {}", msgtype, mask_message(message));
+    let app_msg = all_msg_map_collection.app_msg.current();
+    let app_msg = app_msg.as_ref();
+    let fix_tag_name_map = &all_msg_map_collection.fix_tag_name_map;
+
+    let context = HandlerContext {
+        msgtype,
+        message,
+        stream: stream.as_ref(),
+        app_msg,
+        fix_tag_name_map,
+        seq_store: &seq_store,
+    };
+    // Above a rejecting throttle the answers would not go out, the message is refused instead
+    if !IS_INITIATOR.load(Ordering::SeqCst)
+        && stream.outbound_queue().is_some_and(|queue| queue.throttled())
+    {
+        handle_throttled_message(stream, msg_map, app_msg, fix_tag_name_map, &seq_store);
+        return;
+    }
+    if MESSAGE_HANDLERS.dispatch(msg_map, &context) == Handled::Done {
+        return;
+    }
+
+    // Routed orders are the venue's to check and fill, not kept in the order store
+    if !IS_INITIATOR.load(Ordering::SeqCst) {
+        let client = client_of(msg_map);
+        let target = route(&client, msgtype, msg_map, message);
+        if target != RouteTarget::Internal {
+            handle_routed_message(
+                stream,
+                &target,
+                &client,
+                msg_map,
+                app_msg,
+                fix_tag_name_map,
+                &seq_store,
+            );
+            return;
+        }
+    }
+
+    if msgtype == "NEW_ORDER_SINGLE" && !IS_INITIATOR.load(Ordering::SeqCst) {
+        let account = msg_map.get("Account").map(String::as_str).unwrap_or("");
+        let order_qty = msg_map
+            .get("OrderQty")
+            .and_then(|qty| qty.parse().ok())
+            .unwrap_or_default();
+        // Refusing an order that violates a limit comes before blocking its account
+        if let Err(breach) = check_pre_trade(msg_map, &order_store) {
+            handle_risk_breach(stream, msg_map, &breach, app_msg, fix_tag_name_map, &seq_store);
+            return;
+        }
+        if let Err(breach) = ACCOUNT_RISK.check_new_order(account, order_qty, &order_store) {
+            handle_risk_breach(stream, msg_map, &breach, app_msg, fix_tag_name_map, &seq_store);
+            return;
+        }
+        if counterparty_behaviour(msg_map)
+            .simulator
+            .is_some_and(|simulator| simulator.rejects())
+        {
+            handle_simulated_reject(stream, msg_map, app_msg, fix_tag_name_map, &seq_store);
+            return;
+        }
+    }
 
     let response = match msgtype {
         "NEW_ORDER_SINGLE" => handle_new_order_single(
@@ -379,97 +973,1108 @@ pub fn handle_business_message(
             seq_store.clone(),
             order_store.clone(),
         ),
-        "EXECUTION_REPORT" => "".to_string(), // TODO
-        // "BUSINESS_MESSAGE_REJECT" => msgtype2fixmsg("Business_Message_Reject".to_string(), app_msg, fix_tag_name_map, None, seq_store.get_outgoing()),
-        _ => msgtype2fixmsg(
-            "Business_Message_Reject".to_string(),
+        "ORDER_STATUS_REQUEST" => handle_order_status_request(
+            msg_map,
             app_msg,
             fix_tag_name_map,
-            None,
-            seq_store.get_outgoing(),
+            &seq_store,
+            &order_store,
         ),
-    };
-
-    if !response.is_empty() {
-        let modified_response = response.replace("|", "\x01");
-        let stream = Arc::new(Mutex::new(stream));
-        if let Err(err) = send_message(&stream, modified_response) {
-            error!("Failed to send business response: {}", err);
+        "QUOTE_REQUEST" if !IS_INITIATOR.load(Ordering::SeqCst) => handle_quote_request(
+            stream.as_ref(),
+            msg_map,
+            app_msg,
+            fix_tag_name_map,
+            &seq_store,
+        ),
+        "QUOTE" => {
+            handle_quote(msg_map);
+            "".to_string()
         }
-        seq_store.increment_outgoing();
-    } else {
-        info!(" >>>> No message to send out");
-    }
-}
-
-fn is_fix_message(message: &str) -> bool {
-    message.contains("8=FIX")
-}
-
-fn is_admin_message(msgtype: &str, admin_msg_list: Vec<String>) -> bool {
-    admin_msg_list.contains(&msgtype.to_string())
-}
-
-fn handle_new_order_single(
-    msg_map: &IndexMap<String, String>,
-    app_msg: &HashMap<String, IndexMap<String, String>>,
-    fix_tag_name_map: &HashMap<String, FixTag>,
-    seq_store: Arc<SequenceNumberStore>,
-    order_store: Arc<OrderStore>,
-) -> String {
-    // Add an order
-    if let (
-        Some(clordid),
-        Some(symbol),
-        Some(side),
-        Some(orderqty),
-        Some(price),
-        Some(ordtype),
-        Some(transacttime),
-    ) = (
-        msg_map.get("ClOrdID"),
-        msg_map.get("Symbol"),
-        msg_map.get("Side"),
-        msg_map.get("OrderQty"),
-        msg_map.get("Price"),
-        msg_map.get("OrdType"),
-        msg_map.get("TransactTime"),
-    ) {
-        let mut msg_map_clone = msg_map.clone();
-        msg_map_clone.insert("OrdStatus".to_string(), "New".to_string());
-        add_order_to_store(order_store.clone(), &msg_map_clone).expect("Failed to add order");
-
-        match order_store.print_orders() {
-            Ok(fix_details) => println!("{}", fix_details),
-            Err(err) => error!("Failed to print orders: {:?}", err),
+        "QUOTE_CANCEL" => {
+            let comp_id = msg_map.get("SenderCompID").map_or("", String::as_str);
+            for quote in QUOTES.cancel(comp_id, msg_map) {
+                info!("Quote {} of {} canceled", quote.quote_id, comp_id);
+            }
+            "".to_string()
         }
-
-        if IS_INITIATOR.load(Ordering::SeqCst) {
-            info!("Oops, got a new order single message from server!");
-            "".to_string() // if client(initiator) get new order single nessage, it will be ignored!
-        } else {
-            info!("Preparing Execution_Report message for New Order Single Request");
-            let override_map = prepare_execution_report(
-                Some(clordid),                                           // orderid
-                Some("XYZ123"),                                          // execid
-                Some(msg_map.get("Account").unwrap_or(&"".to_string())), // account
-                Some(symbol),                                            // symbol
-                Some(side),                                              // side
-                Some(ordtype),                                           // ordtype
-                Some(transacttime),                                      // transacttime
-                Some(orderqty),                                          // orderqty
-                Some("0"),                                               // lastshares
-                Some(price),                                             // lastpx
-                Some("0"),                                               // leavesqty
-                Some("0"),                                               // cumqty
-                Some("0"),                                               // avgpx
-                Some("0"),                                               // exectranstype
-                Some("0"),                                               // exectype
-                Some("0"),                                               // ordstatus
+        "ALLOCATION_INSTRUCTION" if !IS_INITIATOR.load(Ordering::SeqCst) => {
+            handle_allocation_instruction(
+                stream.as_ref(),
+                msg_map,
+                message,
+                app_msg,
+                fix_tag_name_map,
+                &seq_store,
+                &order_store,
             );
-
-            msgtype2fixmsg(
-                "Execution_Report".to_string(),
+            "".to_string()
+        }
+        "NEW_ORDER_LIST" if !IS_INITIATOR.load(Ordering::SeqCst) => {
+            handle_new_order_list(
+                stream.as_ref(),
+                msg_map,
+                message,
+                app_msg,
+                fix_tag_name_map,
+                &seq_store,
+                &order_store,
+            );
+            "".to_string()
+        }
+        "LIST_STATUS_REQUEST" if !IS_INITIATOR.load(Ordering::SeqCst) => {
+            handle_list_status_request(
+                msg_map,
+                app_msg,
+                fix_tag_name_map,
+                &seq_store,
+                &order_store,
+            )
+        }
+        "LIST_STATUS" => {
+            info!(
+                "List {:?} status {:?}: {:?}",
+                msg_map.get("ListID"),
+                msg_map.get("ListOrderStatus"),
+                msg_map.get("ListStatusText")
+            );
+            "".to_string()
+        }
+        "SECURITY_DEFINITION_REQUEST" | "SECURITY_LIST_REQUEST"
+            if !IS_INITIATOR.load(Ordering::SeqCst) =>
+        {
+            handle_security_request(
+                stream.as_ref(),
+                msgtype,
+                msg_map,
+                app_msg,
+                fix_tag_name_map,
+                &seq_store,
+            );
+            "".to_string()
+        }
+        "SECURITY_DEFINITION" | "SECURITY_LIST" => {
+            info!(
+                "Securities for request {:?}: {:?} {:?} of {:?} {:?}",
+                msg_map.get("SecurityReqID"),
+                msg_map.get("SecurityResponseType").or(msg_map.get("SecurityRequestResult")),
+                msg_map.get("NoRelatedSym"),
+                msg_map.get("TotalNumSecurities").or(msg_map.get("TotNoRelatedSym")),
+                msg_map.get("Text")
+            );
+            "".to_string()
+        }
+        "NEWS" | "EMAIL" => {
+            match News::parse(message) {
+                Ok(news) => info!("{} {}: {}", msgtype, news.headline, news.lines.join(" / ")),
+                Err(reason) => error!("Malformed {} ignored: {}", msgtype, reason),
+            }
+            "".to_string()
+        }
+        "TRADING_SESSION_STATUS" => {
+            SESSION_HOOKS.trading_session_status(
+                &SessionId::from_received(msg_map),
+                msg_map.get("TradingSessionID").map_or("", String::as_str),
+                msg_map.get("TradSesStatus").map_or("", String::as_str),
+                msg_map.get("Text").map(String::as_str),
+            );
+            "".to_string()
+        }
+        "ALLOCATION_INSTRUCTION_ACK" => {
+            match msg_map.get("AllocStatus").map(String::as_str) {
+                Some("ACCEPTED") => info!("Allocation {:?} accepted", msg_map.get("AllocID")),
+                status => error!(
+                    "Allocation {:?} {:?}: {:?} {:?}",
+                    msg_map.get("AllocID"),
+                    status,
+                    msg_map.get("AllocRejCode"),
+                    msg_map.get("Text")
+                ),
+            }
+            "".to_string()
+        }
+        "ALLOCATION_REPORT" => {
+            info!(
+                "Allocation {:?} reported as {:?}",
+                msg_map.get("AllocID"),
+                msg_map.get("AllocStatus")
+            );
+            "".to_string()
+        }
+        "EXECUTION_REPORT" => {
+            if IS_INITIATOR.load(Ordering::SeqCst) {
+                apply_execution_report(&order_store, msg_map);
+            }
+            handle_execution_report(msg_map, app_msg, fix_tag_name_map, &seq_store)
+        }
+        "ORDER_CANCEL_REJECT" => {
+            acknowledge_order_request(msg_map);
+            info!(
+                "Cancel request {:?} rejected: {:?}",
+                msg_map.get("ClOrdID"),
+                msg_map.get("Text")
+            );
+            "".to_string()
+        }
+        "BUSINESS_MESSAGE_REJECT" => {
+            // Never answered, two engines would otherwise reject each other's rejects
+            if let Some(ref_id) = msg_map.get("BusinessRejectRefID") {
+                PENDING_ORDERS.acknowledge(ref_id);
+            }
+            error!(
+                "Message {:?} rejected: {:?}",
+                msg_map.get("BusinessRejectRefID"),
+                msg_map.get("Text")
+            );
+            "".to_string()
+        }
+        _ => {
+            let override_map = business_reject_fields(
+                msg_map,
+                &ref_msg_type(msg_map, fix_tag_name_map),
+                BUSINESS_REJECT_REASON_UNSUPPORTED_MESSAGE_TYPE,
+                &format!("Unsupported message type {}", msgtype),
+            );
+            msgtype2fixmsg(
+                "Business_Message_Reject".to_string(),
+                app_msg,
+                fix_tag_name_map,
+                Some(&override_map),
+                seq_store.get_outgoing(),
+            )
+        }
+    };
+
+    if !response.is_empty() {
+        let modified_response = response.replace("|", "\x01");
+        let stream = Arc::new(Mutex::new(stream));
+        // A refused message never went out, so its sequence number is reused
+        if let Err(err) = send_sequenced(&stream, &seq_store, modified_response) {
+            error!("Failed to send business response: {}", err);
+        }
+        // An order refused, e.g. for a duplicate ClOrdID, is neither matched nor filled
+        let accepted = !rejects_order(&response);
+        if accepted
+            && MATCHING_ENGINE_ENABLED.load(Ordering::SeqCst)
+            && !IS_INITIATOR.load(Ordering::SeqCst)
+        {
+            match_order(
+                &stream,
+                msgtype,
+                msg_map,
+                app_msg,
+                fix_tag_name_map,
+                &seq_store,
+                &order_store,
+            );
+        } else if accepted && msgtype == "NEW_ORDER_SINGLE" && !IS_INITIATOR.load(Ordering::SeqCst)
+        {
+            start_simulated_fills(
+                &stream,
+                msg_map,
+                app_msg,
+                fix_tag_name_map,
+                seq_store,
+                order_store,
+            );
+        }
+    } else {
+        info!(" >>>> No message to send out");
+    }
+}
+
+/// OrdRejReason(103) OTHER of an order refused by a risk check.
+const ORD_REJ_REASON_OTHER: &str = "99";
+
+/// Reports the orders canceled by a risk breach with unsolicited ExecutionReports,
+/// then rejects the order which was refused, the reason in its Text.
+fn handle_risk_breach(
+    stream: Box<dyn Transport>,
+    msg_map: &IndexMap<String, String>,
+    breach: &RiskBreach,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &Arc<SequenceNumberStore>,
+) {
+    let mut responses = Vec::new();
+    for order in &breach.canceled_orders {
+        let mut event = OrderEvent::from_order(OrderEventKind::Canceled, order);
+        event.text = Some(breach.reason.clone());
+        ORDER_EVENTS.publish(&event);
+
+        let exec_id = seq_store.next_exec_id();
+        let mut override_map =
+            order_execution_report(order, &exec_id, "4", Decimal::ZERO, Decimal::ZERO);
+        override_map.insert("Text".to_string(), breach.reason.clone());
+        responses.push(override_map);
+    }
+
+    error!(
+        "Rejecting order {:?}: {}",
+        msg_map.get("ClOrdID"),
+        breach.reason
+    );
+    let mut event = OrderEvent::from_order_message(OrderEventKind::Rejected, msg_map);
+    event.text = Some(breach.reason.clone());
+    ORDER_EVENTS.publish(&event);
+
+    let get = |key: &str| msg_map.get(key).map(String::as_str);
+    let mut override_map = prepare_execution_report(&ExecutionReportFields {
+        order_id: Some("NONE"),
+        exec_id: Some(&seq_store.next_exec_id()),
+        account: get("Account"),
+        symbol: get("Symbol"),
+        side: get("Side"),
+        ord_type: get("OrdType"),
+        transact_time: get("TransactTime"),
+        order_qty: get("OrderQty"),
+        last_shares: Some("0"),
+        last_px: Some("0"),
+        leaves_qty: Some("0"),
+        cum_qty: Some("0"),
+        avg_px: Some("0"),
+        exec_trans_type: Some("0"),
+        exec_type: Some("8"),
+        ord_status: Some("8"),
+    });
+    insert_if_some_and_not_empty(&mut override_map, "ClOrdID", get("ClOrdID"));
+    override_map.insert("OrdRejReason".to_string(), ORD_REJ_REASON_OTHER.to_string());
+    override_map.insert("Text".to_string(), breach.reason.clone());
+    responses.push(override_map);
+
+    let stream = Arc::new(Mutex::new(stream));
+    for override_map in responses {
+        let response = msgtype2fixmsg(
+            "Execution_Report".to_string(),
+            app_msg,
+            fix_tag_name_map,
+            Some(&override_map),
+            seq_store.get_outgoing(),
+        );
+        if let Err(err) = send_sequenced(&stream, seq_store, response.replace("|", "\x01")) {
+            error!("Failed to send risk ExecutionReport: {}", err);
+            return;
+        }
+    }
+}
+
+/// Rejects a new order as the simulated venue decided to, drawn from `reject_probability`.
+/// Forwards the business message of the client to the venue its route names, answering it
+/// with a BusinessMessageReject when it cannot be or the route refuses it.
+fn handle_routed_message(
+    stream: Box<dyn Transport>,
+    target: &RouteTarget,
+    client: &SessionId,
+    msg_map: &IndexMap<String, String>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &Arc<SequenceNumberStore>,
+) {
+    let (reason, text) = match target {
+        RouteTarget::Internal => return,
+        RouteTarget::Reject => (
+            BUSINESS_REJECT_REASON_OTHER,
+            "Refused by routing rule".to_string(),
+        ),
+        RouteTarget::Venue(venue) => match forward_to(venue, client, msg_map) {
+            Ok(()) => return,
+            Err(e @ ForwardError::UnknownOrder(_)) => {
+                (BUSINESS_REJECT_REASON_UNKNOWN_ID, e.to_string())
+            }
+            Err(e) => (BUSINESS_REJECT_REASON_APPLICATION_NOT_AVAILABLE, e.to_string()),
+        },
+    };
+    error!("{:?} of {} not routed: {}", msg_map.get("MsgType"), client, text);
+    let mut override_map = business_reject_fields(
+        msg_map,
+        &ref_msg_type(msg_map, fix_tag_name_map),
+        reason,
+        &text,
+    );
+    insert_if_some_and_not_empty(
+        &mut override_map,
+        "BusinessRejectRefID",
+        msg_map.get("ClOrdID").map(String::as_str),
+    );
+    let response = msgtype2fixmsg(
+        "Business_Message_Reject".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    );
+    let stream = Arc::new(Mutex::new(stream));
+    if let Err(err) = send_sequenced(&stream, seq_store, response.replace("|", "\x01")) {
+        error!("Failed to send business reject: {}", err);
+    }
+}
+
+/// BusinessMessageReject refusing a business message received above the outbound rate.
+fn handle_throttled_message(
+    stream: Box<dyn Transport>,
+    msg_map: &IndexMap<String, String>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &Arc<SequenceNumberStore>,
+) {
+    let text = "Outbound message rate limit reached";
+    error!("{:?} refused: {}", msg_map.get("MsgType"), text);
+    let override_map = business_reject_fields(
+        msg_map,
+        &ref_msg_type(msg_map, fix_tag_name_map),
+        BUSINESS_REJECT_REASON_OTHER,
+        text,
+    );
+    let response = msgtype2fixmsg(
+        "Business_Message_Reject".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    );
+    let stream = Arc::new(Mutex::new(stream));
+    if let Err(err) = send_sequenced(&stream, seq_store, response.replace("|", "\x01")) {
+        error!("Failed to send business message reject: {}", err);
+    }
+}
+
+fn handle_simulated_reject(
+    stream: Box<dyn Transport>,
+    msg_map: &IndexMap<String, String>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &Arc<SequenceNumberStore>,
+) {
+    let text = "Rejected by the simulator";
+    info!("{} order {:?}", text, msg_map.get("ClOrdID"));
+    let mut event = OrderEvent::from_order_message(OrderEventKind::Rejected, msg_map);
+    event.text = Some(text.to_string());
+    ORDER_EVENTS.publish(&event);
+
+    let get = |key: &str| msg_map.get(key).map(String::as_str);
+    let mut override_map = prepare_execution_report(&ExecutionReportFields {
+        order_id: Some("NONE"),
+        exec_id: Some(&seq_store.next_exec_id()),
+        account: get("Account"),
+        symbol: get("Symbol"),
+        side: get("Side"),
+        ord_type: get("OrdType"),
+        transact_time: get("TransactTime"),
+        order_qty: get("OrderQty"),
+        last_shares: Some("0"),
+        last_px: Some("0"),
+        leaves_qty: Some("0"),
+        cum_qty: Some("0"),
+        avg_px: Some("0"),
+        exec_trans_type: Some("0"),
+        exec_type: Some("8"),
+        ord_status: Some("8"),
+    });
+    insert_if_some_and_not_empty(&mut override_map, "ClOrdID", get("ClOrdID"));
+    override_map.insert("Text".to_string(), text.to_string());
+    let response = msgtype2fixmsg(
+        "Execution_Report".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    );
+    let stream = Arc::new(Mutex::new(stream));
+    if let Err(err) = send_sequenced(&stream, seq_store, response.replace("|", "\x01")) {
+        error!("Failed to send simulated reject: {}", err);
+    }
+}
+
+/// Feeds the matching engine with the order just acknowledged, replaced or canceled.
+fn match_order(
+    stream: &Arc<Mutex<Box<dyn Transport>>>,
+    msgtype: &str,
+    msg_map: &IndexMap<String, String>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &Arc<SequenceNumberStore>,
+    order_store: &Arc<OrderStore>,
+) {
+    let comp_id = msg_map.get("SenderCompID").cloned().unwrap_or_default();
+    if matches!(msgtype, "ORDER_CANCEL_REQUEST" | "ORDER_CANCEL_REPLACE_REQUEST") {
+        if let Some(orig_order_id) = msg_map.get("OrigClOrdID") {
+            MATCHING_ENGINE.remove(&comp_id, orig_order_id);
+        }
+    }
+    if !matches!(msgtype, "NEW_ORDER_SINGLE" | "ORDER_CANCEL_REPLACE_REQUEST") {
+        return;
+    }
+    let order = match msg_map
+        .get("ClOrdID")
+        .and_then(|order_id| order_store.get_order(&order_key(msg_map, order_id)))
+    {
+        Some(order) if order.is_open() => order,
+        _ => return,
+    };
+    let stream = match stream.lock().unwrap().try_clone_transport() {
+        Ok(stream) => stream,
+        Err(err) => {
+            error!("Failed to clone the stream for the matching engine: {}", err);
+            return;
+        }
+    };
+    MATCHING_ENGINE.submit(
+        order,
+        Arc::new(OrderOwner {
+            comp_id,
+            stream: Arc::new(Mutex::new(stream)),
+            app_msg: app_msg.clone(),
+            fix_tag_name_map: fix_tag_name_map.clone(),
+            seq_store: Arc::clone(seq_store),
+            order_store: Arc::clone(order_store),
+        }),
+    );
+}
+
+/// Answers a QuoteRequest with a Quote around the symbol's reference price, withdrawn by a
+/// QuoteCancel at its ValidUntilTime unless the counterparty canceled it first.
+fn handle_quote_request(
+    stream: &dyn Transport,
+    msg_map: &IndexMap<String, String>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &Arc<SequenceNumberStore>,
+) -> String {
+    let symbol = msg_map.get("Symbol").map_or("", String::as_str);
+    let reference_price = PRE_TRADE_LIMITS
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|limits| limits.reference_prices.get(symbol).copied());
+    let (config, reference_price) = match (*QUOTE_CONFIG.read().unwrap(), reference_price) {
+        (Some(config), Some(reference_price)) => (config, reference_price),
+        (config, _) => {
+            let (reason, text) = match config {
+                None => (BUSINESS_REJECT_REASON_APPLICATION_NOT_AVAILABLE, "Quoting is disabled"),
+                Some(_) => (BUSINESS_REJECT_REASON_UNKNOWN_SECURITY, "No price to quote"),
+            };
+            error!("QuoteRequest {:?} rejected: {}", msg_map.get("QuoteReqID"), text);
+            let mut override_map = business_reject_fields(
+                msg_map,
+                &ref_msg_type(msg_map, fix_tag_name_map),
+                reason,
+                &format!("{} {}", text, symbol),
+            );
+            insert_if_some_and_not_empty(
+                &mut override_map,
+                "BusinessRejectRefID",
+                msg_map.get("QuoteReqID").map(String::as_str),
+            );
+            return msgtype2fixmsg(
+                "Business_Message_Reject".to_string(),
+                app_msg,
+                fix_tag_name_map,
+                Some(&override_map),
+                seq_store.get_outgoing(),
+            );
+        }
+    };
+    let tick_size = SYMBOL_MASTER
+        .read()
+        .unwrap()
+        .as_ref()
+        .and_then(|symbol_master| symbol_master.get(symbol).map(|info| info.tick_size));
+    let comp_id = msg_map.get("SenderCompID").map_or("", String::as_str);
+    let quote = Quote::for_request(
+        &config,
+        comp_id,
+        &seq_store.next_quote_id(),
+        msg_map,
+        reference_price,
+        tick_size,
+    );
+    QUOTES.insert(quote.clone());
+
+    match stream.try_clone_transport() {
+        Ok(stream) => {
+            let stream = Arc::new(Mutex::new(stream));
+            let app_msg = app_msg.clone();
+            let fix_tag_name_map = fix_tag_name_map.clone();
+            let seq_store = Arc::clone(seq_store);
+            let (comp_id, quote_id) = (quote.counterparty.clone(), quote.quote_id.clone());
+            TIMERS.schedule(Instant::now() + config.validity, move || {
+                let quote = QUOTES.expire(&comp_id, &quote_id, Utc::now())?;
+                info!("Quote {} of {} expired", quote.quote_id, comp_id);
+                let cancel = msgtype2fixmsg(
+                    "Quote_Cancel".to_string(),
+                    &app_msg,
+                    &fix_tag_name_map,
+                    Some(&quote.cancel_fields()),
+                    seq_store.get_outgoing(),
+                );
+                if let Err(err) = send_sequenced(&stream, &seq_store, cancel.replace("|", "\x01")) {
+                    error!("Failed to cancel expired quote {}: {}", quote_id, err);
+                }
+                None
+            });
+        }
+        Err(err) => error!("Failed to clone the stream for the quote expiry: {}", err),
+    }
+
+    msgtype2fixmsg(
+        "Quote".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&quote.fields()),
+        seq_store.get_outgoing(),
+    )
+}
+
+/// Acknowledges an AllocationInstruction, accepted when its NoAllocs entries add up to the
+/// quantity filled on its orders, and confirms an accepted one with an AllocationReport when
+/// the dictionary has it (FIX 4.4).
+fn handle_allocation_instruction(
+    stream: &dyn Transport,
+    msg_map: &IndexMap<String, String>,
+    message: &str,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &SequenceNumberStore,
+    order_store: &OrderStore,
+) {
+    let stream = match stream.try_clone_transport() {
+        Ok(stream) => Arc::new(Mutex::new(stream)),
+        Err(err) => {
+            error!("Failed to clone the stream for the allocation: {}", err);
+            return;
+        }
+    };
+    let (instruction, result) = match AllocationInstruction::parse(message) {
+        Ok(instruction) => {
+            let result = instruction.validate(order_store, order_owner(msg_map));
+            (Some(instruction), result)
+        }
+        Err(reject) => (None, Err(reject)),
+    };
+    let override_map = match &instruction {
+        Some(instruction) => ack_fields(&instruction.alloc_id, &instruction.trade_date, &result),
+        None => ack_fields(
+            msg_map.get("AllocID").map_or("NONE", String::as_str),
+            &msg_map.get("TradeDate").cloned().unwrap_or_else(today),
+            &result,
+        ),
+    };
+    match &result {
+        Ok(()) => info!("Allocation {:?} accepted", msg_map.get("AllocID")),
+        Err(reject) => error!("Allocation {:?} rejected: {}", msg_map.get("AllocID"), reject.text),
+    }
+    let ack = msgtype2fixmsg(
+        "Allocation_Instruction_Ack".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    );
+    if let Err(err) = send_sequenced(&stream, seq_store, ack.replace("|", "\x01")) {
+        error!("Failed to acknowledge the allocation: {}", err);
+        return;
+    }
+
+    let has_allocation_report = fix_tag_name_map
+        .get("MsgType")
+        .and_then(|tag| tag.enum_values.as_ref())
+        .is_some_and(|values| values.contains_key("ALLOCATION_REPORT"));
+    let Some(instruction) = instruction.filter(|_| result.is_ok() && has_allocation_report) else {
+        return;
+    };
+    let report = msgtype2fixmsg(
+        "Allocation_Report".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&instruction.report_fields(&seq_store.next_alloc_report_id())),
+        seq_store.get_outgoing(),
+    );
+    let report = append_fields(&report, &instruction.report_groups());
+    if let Err(err) = send_sequenced(&stream, seq_store, report.replace("|", "\x01")) {
+        error!("Failed to report the allocation: {}", err);
+    }
+}
+
+/// Places the orders of a NewOrderList, each acknowledged with an ExecutionReport, then
+/// acknowledges the list with a ListStatus. A list with an order that is malformed, known
+/// already or refused by the pre-trade checks is rejected as a whole.
+fn handle_new_order_list(
+    stream: &dyn Transport,
+    msg_map: &IndexMap<String, String>,
+    message: &str,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &SequenceNumberStore,
+    order_store: &OrderStore,
+) {
+    let stream = match stream.try_clone_transport() {
+        Ok(stream) => Arc::new(Mutex::new(stream)),
+        Err(err) => {
+            error!("Failed to clone the stream for the order list: {}", err);
+            return;
+        }
+    };
+    let list_id = msg_map.get("ListID").cloned().unwrap_or_default();
+    let placed = NewOrderList::parse(message, fix_tag_name_map).and_then(|mut list| {
+        for order in &mut list.orders {
+            check_pre_trade(order, order_store).map_err(|breach| breach.reason)?;
+            order.insert("OrderID".to_string(), seq_store.next_order_id());
+        }
+        order_store
+            .add_order_list(
+                order_owner(msg_map),
+                &list.list_id,
+                &list.orders,
+                msg_seq_num(msg_map),
+            )
+            .map_err(|err| err.to_string())
+    });
+    let (override_map, group) = match &placed {
+        Ok(orders) => {
+            info!("List {} of {} orders placed", list_id, orders.len());
+            let fields = list_status_fields(
+                &list_id,
+                LIST_STATUS_TYPE_ACK,
+                LIST_ORDER_STATUS_RECEIVED_FOR_EXECUTION,
+                orders.len(),
+                None,
+            );
+            (fields, list_status_group(orders))
+        }
+        Err(reason) => {
+            error!("List {} rejected: {}", list_id, reason);
+            let cl_ord_ids = repeating_group(message, 73, 11, &[])
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|mut entry| entry.remove(&11))
+                .collect::<Vec<_>>();
+            let fields = list_status_fields(
+                &list_id,
+                LIST_STATUS_TYPE_ACK,
+                LIST_ORDER_STATUS_REJECT,
+                cl_ord_ids.len(),
+                Some(reason),
+            );
+            (fields, rejected_list_group(&cl_ord_ids))
+        }
+    };
+    for order in placed.iter().flatten() {
+        ORDER_EVENTS.publish(&OrderEvent::from_order(OrderEventKind::Accepted, order));
+        let exec_id = seq_store.next_exec_id();
+        let override_map =
+            order_execution_report(order, &exec_id, "0", Decimal::ZERO, Decimal::ZERO);
+        if let Err(err) = send_execution_report(
+            &stream,
+            app_msg,
+            fix_tag_name_map,
+            seq_store,
+            &override_map,
+        ) {
+            error!("Failed to acknowledge order {} of list {}: {}", order.id, list_id, err);
+            return;
+        }
+    }
+    let list_status = msgtype2fixmsg(
+        "List_Status".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    );
+    let list_status = append_fields(&list_status, &group);
+    if let Err(err) = send_sequenced(&stream, seq_store, list_status.replace("|", "\x01")) {
+        error!("Failed to send the ListStatus of list {}: {}", list_id, err);
+    }
+}
+
+/// Answers a ListStatusRequest with the status of the list's orders, or rejects it when the
+/// list is unknown.
+fn handle_list_status_request(
+    msg_map: &IndexMap<String, String>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &SequenceNumberStore,
+    order_store: &OrderStore,
+) -> String {
+    let list_id = msg_map.get("ListID").map_or("", String::as_str);
+    let orders = order_store.list_orders(order_owner(msg_map), list_id);
+    if orders.is_empty() {
+        let mut override_map = business_reject_fields(
+            msg_map,
+            &ref_msg_type(msg_map, fix_tag_name_map),
+            BUSINESS_REJECT_REASON_UNKNOWN_ID,
+            &format!("Unknown list {}", list_id),
+        );
+        override_map.insert("BusinessRejectRefID".to_string(), list_id.to_string());
+        return msgtype2fixmsg(
+            "Business_Message_Reject".to_string(),
+            app_msg,
+            fix_tag_name_map,
+            Some(&override_map),
+            seq_store.get_outgoing(),
+        );
+    }
+    let override_map = list_status_fields(
+        list_id,
+        LIST_STATUS_TYPE_RESPONSE,
+        list_order_status(&orders),
+        orders.len(),
+        None,
+    );
+    let list_status = msgtype2fixmsg(
+        "List_Status".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    );
+    append_fields(&list_status, &list_status_group(&orders))
+}
+
+/// Answers a SecurityDefinitionRequest or SecurityListRequest from the symbol master. Lists
+/// of securities are sent in pages of at most `security_list_page_size` symbols, all under
+/// one SecurityResponseID: SecurityDefinitions in FIX 4.2, SecurityLists in FIX 4.4.
+fn handle_security_request(
+    stream: &dyn Transport,
+    msgtype: &str,
+    msg_map: &IndexMap<String, String>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &SequenceNumberStore,
+) {
+    let stream = match stream.try_clone_transport() {
+        Ok(stream) => Arc::new(Mutex::new(stream)),
+        Err(err) => {
+            error!("Failed to clone the stream for the security request: {}", err);
+            return;
+        }
+    };
+    for (template, mut override_map, group) in
+        security_responses(msgtype, msg_map, fix_tag_name_map, seq_store)
+    {
+        // TotalNumSecurities is FIX 4.2 only, RoundLot FIX 4.4 only
+        override_map.retain(|field, _| fix_tag_name_map.contains_key(field));
+        let response = msgtype2fixmsg(
+            template.to_string(),
+            app_msg,
+            fix_tag_name_map,
+            Some(&override_map),
+            seq_store.get_outgoing(),
+        );
+        let response = append_fields(&response, &group);
+        if let Err(err) = send_sequenced(&stream, seq_store, response.replace("|", "\x01")) {
+            error!(
+                "Failed to answer security request {:?}: {}",
+                msg_map.get("SecurityReqID"),
+                err
+            );
+            return;
+        }
+    }
+}
+
+/// Template, override fields and NoRelatedSym group of a message answering a security request.
+type SecurityResponse = (&'static str, HashMap<String, String>, Vec<(u32, String)>);
+
+/// The messages answering a security request, a BusinessMessageReject without a symbol master.
+fn security_responses(
+    msgtype: &str,
+    msg_map: &IndexMap<String, String>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &SequenceNumberStore,
+) -> Vec<SecurityResponse> {
+    let security_req_id = msg_map.get("SecurityReqID").map_or("", String::as_str);
+    let Some(master) = SYMBOL_MASTER.read().unwrap().clone() else {
+        error!("Security request {} rejected: no symbol master", security_req_id);
+        let mut override_map = business_reject_fields(
+            msg_map,
+            &ref_msg_type(msg_map, fix_tag_name_map),
+            BUSINESS_REJECT_REASON_APPLICATION_NOT_AVAILABLE,
+            "No symbol master",
+        );
+        override_map.insert("BusinessRejectRefID".to_string(), security_req_id.to_string());
+        return vec![("Business_Message_Reject", override_map, Vec::new())];
+    };
+    let security_list = msgtype == "SECURITY_LIST_REQUEST";
+    let template = if security_list { "Security_List" } else { "Security_Definition" };
+    let security_response_id = seq_store.next_security_response_id();
+    let request = if security_list {
+        SecurityRequest::from_list_request(msg_map)
+    } else {
+        SecurityRequest::from_definition_request(msg_map)
+    };
+    // A SecurityDefinition lists securities in FIX 4.2 only
+    let request = request.and_then(|request| match request {
+        SecurityRequest::List(_)
+            if !security_list && !fix_tag_name_map.contains_key("TotalNumSecurities") =>
+        {
+            Err("Securities are listed in answer to a SecurityListRequest".to_string())
+        }
+        request => Ok(request),
+    });
+    let request = match request {
+        Ok(request) => request,
+        Err(reason) => {
+            error!("Security request {} rejected: {}", security_req_id, reason);
+            let override_map = security_request_rejected_fields(
+                security_req_id,
+                &security_response_id,
+                security_list,
+                &reason,
+            );
+            return vec![(template, override_map, Vec::new())];
+        }
+    };
+    let symbols = request.select(&master);
+    if let SecurityRequest::Symbol(symbol) = &request {
+        let override_map = security_definition_fields(
+            security_req_id,
+            &security_response_id,
+            symbol,
+            symbols.first().copied(),
+        );
+        return vec![(template, override_map, Vec::new())];
+    }
+    let pages = pages(&symbols, SECURITY_LIST_PAGE_SIZE.load(Ordering::SeqCst) as usize);
+    info!(
+        "Security request {}: {} securities in {} messages",
+        security_req_id,
+        symbols.len(),
+        pages.len()
+    );
+    pages
+        .iter()
+        .enumerate()
+        .map(|(number, page)| {
+            let (override_map, group) = if security_list {
+                security_list_page(
+                    security_req_id,
+                    &security_response_id,
+                    symbols.len(),
+                    page,
+                    number + 1 == pages.len(),
+                )
+            } else {
+                security_definition_page(
+                    security_req_id,
+                    &security_response_id,
+                    symbols.len(),
+                    page,
+                )
+            };
+            (template, override_map, group)
+        })
+        .collect()
+}
+
+/// Keeps a Quote received in answer to a QuoteRequest until it is canceled or expires.
+fn handle_quote(msg_map: &IndexMap<String, String>) {
+    let comp_id = msg_map.get("SenderCompID").map_or("", String::as_str);
+    let Some(quote) = Quote::from_message(comp_id, msg_map) else {
+        error!("Quote from {} without a QuoteID ignored", comp_id);
+        return;
+    };
+    info!(
+        "Quote {} for {}: {:?} / {:?}",
+        quote.quote_id, quote.symbol, quote.bid_px, quote.offer_px
+    );
+    if let Some(valid_until) = quote.valid_until {
+        let remaining = (valid_until - Utc::now()).to_std().unwrap_or_default();
+        let (comp_id, quote_id) = (quote.counterparty.clone(), quote.quote_id.clone());
+        TIMERS.schedule(Instant::now() + remaining, move || {
+            if QUOTES.expire(&comp_id, &quote_id, Utc::now()).is_some() {
+                info!("Quote {} of {} expired", quote_id, comp_id);
+            }
+            None
+        });
+    }
+    QUOTES.insert(quote);
+}
+
+/// Hands an order just acknowledged to the simulator, which fills it in the background.
+fn start_simulated_fills(
+    stream: &Arc<Mutex<Box<dyn Transport>>>,
+    msg_map: &IndexMap<String, String>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: Arc<SequenceNumberStore>,
+    order_store: Arc<OrderStore>,
+) {
+    let simulator = match counterparty_behaviour(msg_map).simulator {
+        Some(simulator) => simulator,
+        None => return,
+    };
+    let order = msg_map
+        .get("ClOrdID")
+        .and_then(|order_id| order_store.get_order(&order_key(msg_map, order_id)))
+        .filter(|order| order.is_open());
+    let (order, stream) = match (order, stream.lock().unwrap().try_clone_transport()) {
+        (Some(order), Ok(stream)) => (order, stream),
+        (None, _) => return,
+        (_, Err(err)) => {
+            error!("Failed to clone the stream for simulated fills: {}", err);
+            return;
+        }
+    };
+    start_fills(
+        simulator,
+        order,
+        FillContext {
+            stream,
+            app_msg: app_msg.clone(),
+            fix_tag_name_map: fix_tag_name_map.clone(),
+            seq_store,
+            order_store,
+        },
+    );
+}
+
+/// Whether the response is an ExecutionReport with OrdStatus REJECTED.
+fn rejects_order(response: &str) -> bool {
+    tag_value::fields(response.as_bytes()).any(|field| field == Ok((39, &b"8"[..])))
+}
+
+fn is_fix_message(message: &str) -> bool {
+    message.contains("8=FIX")
+}
+
+fn is_admin_message(msgtype: &str, admin_msg_list: Vec<String>) -> bool {
+    admin_msg_list.contains(&msgtype.to_string())
+}
+
+/// ResetSeqNumFlag arrives as the dictionary description (YES) or the raw value (Y).
+fn is_reset_seq_num_requested(msg_map: &IndexMap<String, String>) -> bool {
+    matches!(
+        msg_map.get("ResetSeqNumFlag").map(|flag| flag.as_str()),
+        Some("Y") | Some("YES")
+    )
+}
+
+/// The ExecutionReport or OrderCancelReject answers our pending order request.
+fn acknowledge_order_request(msg_map: &IndexMap<String, String>) {
+    if let Some(cl_ord_id) = msg_map.get("ClOrdID") {
+        PENDING_ORDERS.acknowledge(cl_ord_id);
+    }
+}
+
+/// Publishes the order event of an ExecutionReport. The initiator reports those for orders
+/// it never sent to the session hooks and, with `dont_know_trade`, answers them with a
+/// DontKnowTrade.
+fn handle_execution_report(
+    msg_map: &IndexMap<String, String>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &SequenceNumberStore,
+) -> String {
+    acknowledge_order_request(msg_map);
+    match OrderEvent::from_execution_report(msg_map) {
+        Some(event) => ORDER_EVENTS.publish(&event),
+        None => info!(
+            "ExecutionReport is not an order lifecycle event: {:?}",
+            msg_map.get("ExecType")
+        ),
+    }
+    if !IS_INITIATOR.load(Ordering::SeqCst) || is_known(msg_map) {
+        return "".to_string();
+    }
+    let cl_ord_id = msg_map.get("ClOrdID").map_or("", String::as_str);
+    let exec_id = msg_map.get("ExecID").map_or("", String::as_str);
+    error!("ExecutionReport {} for unknown order {}", exec_id, cl_ord_id);
+    SENT_ORDERS.mark_unknown(msg_map);
+    SESSION_HOOKS.unknown_execution(&SessionId::from_received(msg_map), cl_ord_id, exec_id);
+    if !DONT_KNOW_TRADE.load(Ordering::SeqCst) {
+        return "".to_string();
+    }
+    msgtype2fixmsg(
+        "Dont_Know_Trade".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&dont_know_trade_fields(msg_map)),
+        seq_store.get_outgoing(),
+    )
+}
+
+fn handle_new_order_single(
+    msg_map: &IndexMap<String, String>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: Arc<SequenceNumberStore>,
+    order_store: Arc<OrderStore>,
+) -> String {
+    // Add an order, its Price only required of a limit order (checked by the order store)
+    if let (
+        Some(clordid),
+        Some(symbol),
+        Some(side),
+        Some(orderqty),
+        Some(ordtype),
+        Some(transacttime),
+    ) = (
+        msg_map.get("ClOrdID"),
+        msg_map.get("Symbol"),
+        msg_map.get("Side"),
+        msg_map.get("OrderQty"),
+        msg_map.get("OrdType"),
+        msg_map.get("TransactTime"),
+    ) {
+        let mut msg_map_clone = msg_map.clone();
+        msg_map_clone.insert("OrdStatus".to_string(), "New".to_string());
+        if !IS_INITIATOR.load(Ordering::SeqCst) {
+            msg_map_clone.insert("OrderID".to_string(), seq_store.next_order_id());
+        }
+        if let Err(err) =
+            add_order_to_store(order_store.clone(), order_owner(msg_map), &msg_map_clone)
+        {
+            error!("Order {} refused: {}", clordid, err);
+            if IS_INITIATOR.load(Ordering::SeqCst) {
+                return "".to_string();
+            }
+            return new_order_reject(
+                msg_map,
+                &err.to_string(),
+                err.ord_rej_reason(),
+                app_msg,
+                fix_tag_name_map,
+                &seq_store,
+            );
+        }
+
+        match order_store.print_orders() {
+            Ok(fix_details) => debug!("{}", fix_details),
+            Err(err) => error!("Failed to print orders: {:?}", err),
+        }
+
+        if IS_INITIATOR.load(Ordering::SeqCst) {
+            info!("Oops, got a new order single message from server!");
+            "".to_string() // if client(initiator) get new order single nessage, it will be ignored!
+        } else {
+            info!("Preparing Execution_Report message for New Order Single Request");
+            ORDER_EVENTS.publish(&OrderEvent::from_order_message(
+                OrderEventKind::Accepted,
+                &msg_map_clone,
+            ));
+            let mut override_map = prepare_execution_report(&ExecutionReportFields {
+                order_id: msg_map_clone.get("OrderID").map(String::as_str),
+                exec_id: Some(&seq_store.next_exec_id()),
+                account: Some(msg_map.get("Account").unwrap_or(&"".to_string())),
+                symbol: Some(symbol),
+                side: Some(side),
+                ord_type: Some(ordtype),
+                transact_time: Some(transacttime),
+                order_qty: Some(orderqty),
+                last_shares: Some("0"),
+                last_px: Some(msg_map.get("Price").map_or("0", String::as_str)),
+                leaves_qty: Some(orderqty),
+                cum_qty: Some("0"),
+                avg_px: Some("0"),
+                exec_trans_type: Some("0"),
+                exec_type: Some("0"),
+                ord_status: Some("0"),
+            });
+            override_map.insert("ClOrdID".to_string(), clordid.clone());
+
+            msgtype2fixmsg(
+                "Execution_Report".to_string(),
                 app_msg,
                 fix_tag_name_map,
                 Some(&override_map),
@@ -484,37 +2089,67 @@ fn handle_new_order_single(
             "".to_string() // if client(initiator) get new order single nessage, it will be ignored!
         } else {
             error!("Missing fields in NEW_ORDER_SINGLE message");
-
-            let override_map = prepare_execution_report(
-                Some(msg_map.get("ClOrdID").unwrap_or(&"".to_string())), // orderid
-                Some("XYZ123"),                                          // execid
-                Some(msg_map.get("Account").unwrap_or(&"".to_string())), // account
-                Some(msg_map.get("Symbol").unwrap_or(&"".to_string())),  // symbol
-                Some(msg_map.get("Side").unwrap_or(&"".to_string())),    // side
-                Some(msg_map.get("OrdType").unwrap_or(&"".to_string())), // ordtype
-                Some(msg_map.get("TransactTime").unwrap_or(&"".to_string())), // transacttime
-                Some("0"),                                               // orderqty
-                Some("0"),                                               // lastshares
-                Some(msg_map.get("Price").unwrap_or(&"".to_string())),   // lastpx
-                Some("0"),                                               // leavesqty
-                Some("0"),                                               // cumqty
-                Some("0"),                                               // avgpx
-                Some("0"),                                               // exectranstype
-                Some("8"),                                               // exectype
-                Some("8"),                                               // ordstatus
-            );
-
-            msgtype2fixmsg(
-                "Execution_Report".to_string(),
+            new_order_reject(
+                msg_map,
+                "Missing fields in NEW_ORDER_SINGLE message",
+                None,
                 app_msg,
                 fix_tag_name_map,
-                Some(&override_map),
-                seq_store.get_outgoing(),
+                &seq_store,
             )
         }
     }
 }
 
+/// ExecutionReport rejecting a NewOrderSingle which could not be accepted, with the reason as
+/// Text and, when it has one, as OrdRejReason.
+fn new_order_reject(
+    msg_map: &IndexMap<String, String>,
+    text: &str,
+    ord_rej_reason: Option<&str>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &SequenceNumberStore,
+) -> String {
+    let mut event = OrderEvent::from_order_message(OrderEventKind::Rejected, msg_map);
+    event.text = Some(text.to_string());
+    ORDER_EVENTS.publish(&event);
+
+    let mut override_map = prepare_execution_report(&ExecutionReportFields {
+        order_id: Some("NONE"),
+        exec_id: Some(&seq_store.next_exec_id()),
+        account: Some(msg_map.get("Account").unwrap_or(&"".to_string())),
+        symbol: Some(msg_map.get("Symbol").unwrap_or(&"".to_string())),
+        side: Some(msg_map.get("Side").unwrap_or(&"".to_string())),
+        ord_type: Some(msg_map.get("OrdType").unwrap_or(&"".to_string())),
+        transact_time: Some(msg_map.get("TransactTime").unwrap_or(&"".to_string())),
+        order_qty: Some("0"),
+        last_shares: Some("0"),
+        last_px: Some(msg_map.get("Price").unwrap_or(&"".to_string())),
+        leaves_qty: Some("0"),
+        cum_qty: Some("0"),
+        avg_px: Some("0"),
+        exec_trans_type: Some("0"),
+        exec_type: Some("8"),
+        ord_status: Some("8"),
+    });
+    insert_if_some_and_not_empty(
+        &mut override_map,
+        "ClOrdID",
+        msg_map.get("ClOrdID").map(String::as_str),
+    );
+    insert_if_some_and_not_empty(&mut override_map, "OrdRejReason", ord_rej_reason);
+    override_map.insert("Text".to_string(), text.to_string());
+
+    msgtype2fixmsg(
+        "Execution_Report".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    )
+}
+
 fn handle_order_cancel_replace_request(
     msg_map: &IndexMap<String, String>,
     app_msg: &HashMap<String, IndexMap<String, String>>,
@@ -522,13 +2157,13 @@ fn handle_order_cancel_replace_request(
     seq_store: Arc<SequenceNumberStore>,
     order_store: Arc<OrderStore>,
 ) -> String {
+    // The Price is only required of a limit order, checked by the order store
     if let (
         Some(origclordid),
         Some(clordid),
         Some(symbol),
         Some(side),
         Some(orderqty),
-        Some(price),
         Some(ordtype),
         Some(transacttime),
     ) = (
@@ -537,16 +2172,67 @@ fn handle_order_cancel_replace_request(
         msg_map.get("Symbol"),
         msg_map.get("Side"),
         msg_map.get("OrderQty"),
-        msg_map.get("Price"),
         msg_map.get("OrdType"),
         msg_map.get("TransactTime"),
     ) {
         let mut msg_map_clone = msg_map.clone();
         msg_map_clone.insert("OrdStatus".to_string(), "Replaced".to_string());
-        update_order_in_store(order_store.clone(), &msg_map_clone).expect("Failed to add order");
+        let orig_key = order_key(msg_map, origclordid);
+        if !IS_INITIATOR.load(Ordering::SeqCst) && !is_known_order(&orig_key, &order_store) {
+            error!("Cancel/replace of unknown order {}", origclordid);
+            return order_cancel_reject(
+                msg_map,
+                CXL_REJ_RESPONSE_TO_REPLACE,
+                &CancelRejection::UnknownOrder,
+                app_msg,
+                fix_tag_name_map,
+                &seq_store,
+            );
+        }
+        if let Err(err) =
+            replace_order_in_store(order_store.clone(), order_owner(msg_map), &msg_map_clone)
+        {
+            error!("Cancel/replace of order {} refused: {}", origclordid, err);
+            if IS_INITIATOR.load(Ordering::SeqCst) {
+                return "".to_string();
+            }
+            return match err {
+                OrderError::IllegalTransition(refused) => order_cancel_reject(
+                    msg_map,
+                    CXL_REJ_RESPONSE_TO_REPLACE,
+                    &CancelRejection::TooLate {
+                        order_id: assigned_order_id(&orig_key, &order_store),
+                        refused,
+                    },
+                    app_msg,
+                    fix_tag_name_map,
+                    &seq_store,
+                ),
+                OrderError::DuplicateClOrdID(_) => order_cancel_reject(
+                    msg_map,
+                    CXL_REJ_RESPONSE_TO_REPLACE,
+                    &CancelRejection::DuplicateClOrdID {
+                        order_id: assigned_order_id(&orig_key, &order_store),
+                        ord_status: order_store
+                            .get_order(&orig_key)
+                            .map_or(OrdStatus::Rejected, |order| order.status()),
+                    },
+                    app_msg,
+                    fix_tag_name_map,
+                    &seq_store,
+                ),
+                err => business_message_reject(
+                    msg_map,
+                    &err,
+                    app_msg,
+                    fix_tag_name_map,
+                    &seq_store,
+                ),
+            };
+        }
 
         match order_store.print_orders() {
-            Ok(fix_details) => println!("{}", fix_details),
+            Ok(fix_details) => debug!("{}", fix_details),
             Err(err) => error!("Failed to print orders: {:?}", err),
         };
         if IS_INITIATOR.load(Ordering::SeqCst) {
@@ -554,25 +2240,43 @@ fn handle_order_cancel_replace_request(
             "".to_string() // if client(initiator) get new order single nessage, it will be ignored!
         } else {
             info!("Preparing Execution_Report message for Cancel Replace Request");
+            ORDER_EVENTS.publish(&OrderEvent::from_order_message(
+                OrderEventKind::Replaced,
+                &msg_map_clone,
+            ));
 
-            let override_map = prepare_execution_report(
-                Some(clordid),                                           // orderid
-                Some("XYZ123"),                                          // execid
-                Some(msg_map.get("Account").unwrap_or(&"".to_string())), // account
-                Some(symbol),                                            // symbol
-                Some(side),                                              // side
-                Some(ordtype),                                           // ordtype
-                Some(transacttime),                                      // transacttime
-                Some(orderqty),                                          // orderqty
-                Some("0"),                                               // lastshares
-                Some(price),                                             // lastpx
-                Some("0"),                                               // leavesqty
-                Some("0"),                                               // cumqty
-                Some("0"),                                               // avgpx
-                Some("2"),                                               // exectranstype
-                Some("5"),                                               // exectype
-                Some("5"),                                               // ordstatus
-            );
+            // The replacement carries over the executions of the original order
+            let replaced = order_store.get_order(&order_key(msg_map, clordid));
+            let leaves_qty = replaced
+                .as_ref()
+                .map_or(orderqty.clone(), |order| order.leaves_qty().to_string());
+            let cum_qty = replaced
+                .as_ref()
+                .map_or("0".to_string(), |order| order.cum_qty.to_string());
+            let avg_px = replaced
+                .as_ref()
+                .map_or("0".to_string(), |order| order.avg_px.to_string());
+            let order_id = replaced.as_ref().map(|order| order.order_id.as_str());
+            let mut override_map = prepare_execution_report(&ExecutionReportFields {
+                order_id,
+                exec_id: Some(&seq_store.next_exec_id()),
+                account: Some(msg_map.get("Account").unwrap_or(&"".to_string())),
+                symbol: Some(symbol),
+                side: Some(side),
+                ord_type: Some(ordtype),
+                transact_time: Some(transacttime),
+                order_qty: Some(orderqty),
+                last_shares: Some("0"),
+                last_px: Some(msg_map.get("Price").map_or("0", String::as_str)),
+                leaves_qty: Some(&leaves_qty),
+                cum_qty: Some(&cum_qty),
+                avg_px: Some(&avg_px),
+                exec_trans_type: Some("2"),
+                exec_type: Some("5"),
+                ord_status: Some("5"),
+            });
+            override_map.insert("ClOrdID".to_string(), clordid.clone());
+            override_map.insert("OrigClOrdID".to_string(), origclordid.clone());
 
             msgtype2fixmsg(
                 "Execution_Report".to_string(),
@@ -611,9 +2315,9 @@ fn handle_order_cancel_request(
         Some(clordid),
         Some(symbol),
         Some(side),
-        Some(orderqty),
-        Some(price),
-        Some(ordtype),
+        Some(_orderqty),
+        Some(_price),
+        Some(_ordtype),
         Some(transacttime),
     ) = (
         msg_map.get("OrigClOrdID"),
@@ -627,10 +2331,48 @@ fn handle_order_cancel_request(
     ) {
         let mut msg_map_clone = msg_map.clone();
         msg_map_clone.insert("OrdStatus".to_string(), "Canceled".to_string());
-        update_order_in_store(order_store.clone(), &msg_map_clone).expect("Failed to add order");
+        let orig_key = order_key(msg_map, origclordid);
+        if !IS_INITIATOR.load(Ordering::SeqCst) && !is_known_order(&orig_key, &order_store) {
+            error!("Cancel of unknown order {}", origclordid);
+            return order_cancel_reject(
+                msg_map,
+                CXL_REJ_RESPONSE_TO_CANCEL,
+                &CancelRejection::UnknownOrder,
+                app_msg,
+                fix_tag_name_map,
+                &seq_store,
+            );
+        }
+        // The original order is canceled, keeping what was executed of it
+        let canceled = match order_store.set_status(
+            &orig_key,
+            OrdStatus::Canceled,
+            msg_seq_num(msg_map),
+        ) {
+            Ok(order) => Some(order),
+            Err(err) => {
+                error!("Failed to cancel order {}: {}", origclordid, err);
+                match err.downcast_ref::<IllegalTransition>() {
+                    Some(refused) if !IS_INITIATOR.load(Ordering::SeqCst) => {
+                        return order_cancel_reject(
+                            msg_map,
+                            CXL_REJ_RESPONSE_TO_CANCEL,
+                            &CancelRejection::TooLate {
+                                order_id: assigned_order_id(&orig_key, &order_store),
+                                refused: refused.clone(),
+                            },
+                            app_msg,
+                            fix_tag_name_map,
+                            &seq_store,
+                        );
+                    }
+                    _ => None,
+                }
+            }
+        };
 
         match order_store.print_orders() {
-            Ok(fix_details) => println!("{}", fix_details),
+            Ok(fix_details) => debug!("{}", fix_details),
             Err(err) => error!("Failed to print orders: {:?}", err),
         };
 
@@ -639,25 +2381,34 @@ fn handle_order_cancel_request(
             "".to_string() // if client(initiator) get new order single message, it will be ignored!
         } else {
             info!("Preparing Execution_Report message for Cancel Request");
+            let cum_qty = canceled
+                .as_ref()
+                .map_or("0".to_string(), |order| order.cum_qty.to_string());
+            let avg_px = canceled
+                .as_ref()
+                .map_or("0".to_string(), |order| order.avg_px.to_string());
+            ORDER_EVENTS.publish(&OrderEvent::from_order_message(
+                OrderEventKind::Canceled,
+                &msg_map_clone,
+            ));
 
-            let override_map = prepare_execution_report(
-                Some(clordid),      // orderid
-                Some("XYZ123"),     // execid
-                None,               // account
-                Some(symbol),       // symbol
-                Some(side),         // side
-                None,               // ordtype
-                Some(transacttime), // transacttime
-                None,               // orderqty
-                None,               // lastshares
-                None,               // lastpx
-                None,               // leavesqty
-                None,               // cumqty
-                None,               // avgpx
-                Some("1"),          // exectranstype
-                Some("4"),          // exectype
-                Some("4"),          // ordstatus
-            );
+            let order_id = canceled.as_ref().map(|order| order.order_id.as_str());
+            let mut override_map = prepare_execution_report(&ExecutionReportFields {
+                order_id,
+                exec_id: Some(&seq_store.next_exec_id()),
+                symbol: Some(symbol),
+                side: Some(side),
+                transact_time: Some(transacttime),
+                leaves_qty: Some("0"),
+                cum_qty: Some(&cum_qty),
+                avg_px: Some(&avg_px),
+                exec_trans_type: Some("1"),
+                exec_type: Some("4"),
+                ord_status: Some("4"),
+                ..Default::default()
+            });
+            override_map.insert("ClOrdID".to_string(), clordid.clone());
+            override_map.insert("OrigClOrdID".to_string(), origclordid.clone());
             msgtype2fixmsg(
                 "Execution_Report".to_string(),
                 app_msg,
@@ -683,6 +2434,278 @@ fn handle_order_cancel_request(
     }
 }
 
+/// ExecType(150) ORDER_STATUS of an ExecutionReport answering an OrderStatusRequest.
+const EXEC_TYPE_ORDER_STATUS: &str = "I";
+
+/// ExecutionReport fields answering an OrderStatusRequest for the order, looked up by ClOrdID
+/// or OrderID. An unknown order is reported rejected with OrdRejReason UNKNOWN_ORDER.
+fn order_status_fields(
+    msg_map: &IndexMap<String, String>,
+    exec_id: &str,
+    order_store: &OrderStore,
+) -> HashMap<String, String> {
+    let order = msg_map
+        .get("ClOrdID")
+        .and_then(|cl_ord_id| order_store.get_order(&order_key(msg_map, cl_ord_id)))
+        .or_else(|| {
+            msg_map
+                .get("OrderID")
+                .and_then(|order_id| order_store.find_by_order_id(order_id))
+                .filter(|order| order.sender_comp_id == order_owner(msg_map))
+        });
+    let mut override_map = match order {
+        Some(order) => {
+            let mut override_map = order_execution_report(
+                &order,
+                exec_id,
+                EXEC_TYPE_ORDER_STATUS,
+                Decimal::ZERO,
+                Decimal::ZERO,
+            );
+            override_map.insert("OrdStatus".to_string(), order.status().value().to_string());
+            override_map
+        }
+        None => {
+            let cl_ord_id = msg_map.get("ClOrdID").map_or("", |id| id.as_str());
+            let mut override_map = prepare_execution_report(&ExecutionReportFields {
+                order_id: Some("NONE"),
+                exec_id: Some(exec_id),
+                symbol: msg_map.get("Symbol").map(String::as_str),
+                side: msg_map.get("Side").map(String::as_str),
+                leaves_qty: Some("0"),
+                cum_qty: Some("0"),
+                avg_px: Some("0"),
+                exec_type: Some(EXEC_TYPE_ORDER_STATUS),
+                ord_status: Some(OrdStatus::Rejected.value()),
+                ..Default::default()
+            });
+            insert_if_some_and_not_empty(&mut override_map, "ClOrdID", Some(cl_ord_id));
+            override_map.insert("OrdRejReason".to_string(), "5".to_string());
+            override_map.insert("Text".to_string(), format!("Unknown order {}", cl_ord_id));
+            override_map
+        }
+    };
+    // STATUS, for FIX 4.2 counterparties which know no ORDER_STATUS ExecType
+    override_map.insert("ExecTransType".to_string(), "3".to_string());
+    override_map
+}
+
+fn handle_order_status_request(
+    msg_map: &IndexMap<String, String>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &SequenceNumberStore,
+    order_store: &OrderStore,
+) -> String {
+    if IS_INITIATOR.load(Ordering::SeqCst) {
+        info!("Oops, got a order status request message from server!");
+        return "".to_string();
+    }
+    info!("Preparing Execution_Report message for Order Status Request");
+    let override_map = order_status_fields(msg_map, &seq_store.next_exec_id(), order_store);
+    msgtype2fixmsg(
+        "Execution_Report".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    )
+}
+
+/// CxlRejResponseTo(434) of an OrderCancelReject.
+const CXL_REJ_RESPONSE_TO_CANCEL: &str = "1";
+const CXL_REJ_RESPONSE_TO_REPLACE: &str = "2";
+
+/// Why a cancel or cancel/replace request is refused.
+#[derive(Debug, Clone, PartialEq)]
+enum CancelRejection {
+    /// The lifecycle of the order refuses it, e.g. because it is already filled. The OrderID
+    /// is the one assigned to the order.
+    TooLate {
+        order_id: String,
+        refused: IllegalTransition,
+    },
+    /// No order has the OrigClOrdID.
+    UnknownOrder,
+    /// Another order already has the ClOrdID of the replacement. The OrderID and OrdStatus
+    /// are those of the order to replace, left as it was.
+    DuplicateClOrdID {
+        order_id: String,
+        ord_status: OrdStatus,
+    },
+}
+
+/// SenderCompID the orders named by a received message are kept under: the acceptor keeps the
+/// orders of each counterparty apart, the initiator only has its own.
+fn order_owner(msg_map: &IndexMap<String, String>) -> &str {
+    if IS_INITIATOR.load(Ordering::SeqCst) {
+        return "";
+    }
+    msg_map.get("SenderCompID").map_or("", String::as_str)
+}
+
+/// Key of the order with the ClOrdID, as named by a received message.
+fn order_key(msg_map: &IndexMap<String, String>, cl_ord_id: &str) -> OrderKey {
+    OrderKey::new(order_owner(msg_map), cl_ord_id)
+}
+
+fn is_known_order(key: &OrderKey, order_store: &OrderStore) -> bool {
+    order_store.get_order(key).is_some()
+}
+
+/// OrderID assigned to the order, "NONE" when it has none.
+fn assigned_order_id(key: &OrderKey, order_store: &OrderStore) -> String {
+    order_store
+        .get_order(key)
+        .map(|order| order.order_id)
+        .filter(|order_id| !order_id.is_empty())
+        .unwrap_or_else(|| "NONE".to_string())
+}
+
+/// OrderCancelReject fields answering the request; the order, if any, is left as it was.
+fn cancel_reject_fields(
+    msg_map: &IndexMap<String, String>,
+    response_to: &str,
+    rejection: &CancelRejection,
+) -> HashMap<String, String> {
+    let mut override_map = HashMap::new();
+    for field in ["ClOrdID", "OrigClOrdID"] {
+        insert_if_some_and_not_empty(
+            &mut override_map,
+            field,
+            msg_map.get(field).map(|value| value.as_str()),
+        );
+    }
+    let (reason, order_id, ord_status, text) = match rejection {
+        CancelRejection::TooLate { order_id, refused } => (
+            "0",
+            order_id.clone(),
+            refused.from,
+            refused.to_string(),
+        ),
+        CancelRejection::UnknownOrder => (
+            "1",
+            "NONE".to_string(),
+            OrdStatus::Rejected,
+            format!(
+                "Unknown order {}",
+                msg_map.get("OrigClOrdID").map_or("", |id| id.as_str())
+            ),
+        ),
+        CancelRejection::DuplicateClOrdID {
+            order_id,
+            ord_status,
+        } => (
+            "6",
+            order_id.clone(),
+            *ord_status,
+            format!(
+                "Duplicate ClOrdID {}",
+                msg_map.get("ClOrdID").map_or("", |id| id.as_str())
+            ),
+        ),
+    };
+    override_map.insert("OrderID".to_string(), order_id);
+    override_map.insert("OrdStatus".to_string(), ord_status.value().to_string());
+    override_map.insert("CxlRejResponseTo".to_string(), response_to.to_string());
+    override_map.insert("CxlRejReason".to_string(), reason.to_string());
+    override_map.insert("Text".to_string(), text);
+    override_map
+}
+
+fn order_cancel_reject(
+    msg_map: &IndexMap<String, String>,
+    response_to: &str,
+    rejection: &CancelRejection,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &SequenceNumberStore,
+) -> String {
+    let override_map = cancel_reject_fields(msg_map, response_to, rejection);
+    msgtype2fixmsg(
+        "Order_Cancel_Reject".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    )
+}
+
+/// BusinessRejectReason(380) of a BusinessMessageReject.
+const BUSINESS_REJECT_REASON_OTHER: &str = "0";
+const BUSINESS_REJECT_REASON_UNKNOWN_ID: &str = "1";
+const BUSINESS_REJECT_REASON_UNKNOWN_SECURITY: &str = "2";
+pub const BUSINESS_REJECT_REASON_UNSUPPORTED_MESSAGE_TYPE: &str = "3";
+const BUSINESS_REJECT_REASON_APPLICATION_NOT_AVAILABLE: &str = "4";
+const BUSINESS_REJECT_REASON_FIELD_MISSING: &str = "5";
+
+/// MsgType value of a parsed message, whose MsgType holds the dictionary description.
+pub fn ref_msg_type(
+    msg_map: &IndexMap<String, String>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+) -> String {
+    let msg_type = msg_map.get("MsgType").map_or("", |msg_type| msg_type.as_str());
+    fix_tag_name_map
+        .get("MsgType")
+        .and_then(|tag| tag.enum_values.as_ref())
+        .and_then(|values| values.get(msg_type))
+        .map_or(msg_type, |value| value.as_str())
+        .to_string()
+}
+
+/// BusinessMessageReject fields refusing an application message, referring to it by
+/// MsgSeqNum and ClOrdID.
+pub fn business_reject_fields(
+    msg_map: &IndexMap<String, String>,
+    ref_msg_type: &str,
+    reason: &str,
+    text: &str,
+) -> HashMap<String, String> {
+    let mut override_map = HashMap::from([
+        ("RefMsgType".to_string(), ref_msg_type.to_string()),
+        ("BusinessRejectReason".to_string(), reason.to_string()),
+        ("Text".to_string(), text.to_string()),
+    ]);
+    insert_if_some_and_not_empty(
+        &mut override_map,
+        "RefSeqNum",
+        msg_map.get("MsgSeqNum").map(|value| value.as_str()),
+    );
+    insert_if_some_and_not_empty(
+        &mut override_map,
+        "BusinessRejectRefID",
+        msg_map.get("ClOrdID").map(|value| value.as_str()),
+    );
+    override_map
+}
+
+/// BusinessMessageReject refusing an order message which could not be applied.
+fn business_message_reject(
+    msg_map: &IndexMap<String, String>,
+    err: &OrderError,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &SequenceNumberStore,
+) -> String {
+    let reason = match err {
+        OrderError::MissingField(_) => BUSINESS_REJECT_REASON_FIELD_MISSING,
+        _ => BUSINESS_REJECT_REASON_OTHER,
+    };
+    let override_map = business_reject_fields(
+        msg_map,
+        &ref_msg_type(msg_map, fix_tag_name_map),
+        reason,
+        &err.to_string(),
+    );
+    msgtype2fixmsg(
+        "Business_Message_Reject".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(&override_map),
+        seq_store.get_outgoing(),
+    )
+}
+
 fn insert_if_some_and_not_empty(map: &mut HashMap<String, String>, key: &str, value: Option<&str>) {
     if let Some(value) = value {
         if !value.is_empty() {
@@ -691,60 +2714,465 @@ fn insert_if_some_and_not_empty(map: &mut HashMap<String, String>, key: &str, va
     }
 }
 
-fn prepare_execution_report(
-    orderid: Option<&str>,
-    execid: Option<&str>,
-    account: Option<&str>,
-    symbol: Option<&str>,
-    side: Option<&str>,
-    ordtype: Option<&str>,
-    transactiontime: Option<&str>,
-    orderqty: Option<&str>,
-    lastshares: Option<&str>,
-    lastpx: Option<&str>,
-    leavesqty: Option<&str>,
-    cumqty: Option<&str>,
-    avgpx: Option<&str>,
-    exectranstype: Option<&str>,
-    exectype: Option<&str>,
-    ordstatus: Option<&str>,
-) -> HashMap<String, String> {
+/// The fields of an ExecutionReport set by `prepare_execution_report`, those None or empty
+/// left to the template.
+#[derive(Default)]
+pub struct ExecutionReportFields<'a> {
+    pub order_id: Option<&'a str>,
+    pub exec_id: Option<&'a str>,
+    pub account: Option<&'a str>,
+    pub symbol: Option<&'a str>,
+    pub side: Option<&'a str>,
+    pub ord_type: Option<&'a str>,
+    pub transact_time: Option<&'a str>,
+    pub order_qty: Option<&'a str>,
+    pub last_shares: Option<&'a str>,
+    pub last_px: Option<&'a str>,
+    pub leaves_qty: Option<&'a str>,
+    pub cum_qty: Option<&'a str>,
+    pub avg_px: Option<&'a str>,
+    pub exec_trans_type: Option<&'a str>,
+    pub exec_type: Option<&'a str>,
+    pub ord_status: Option<&'a str>,
+}
+
+pub fn prepare_execution_report(fields: &ExecutionReportFields) -> HashMap<String, String> {
     let mut override_map = HashMap::new();
 
-    insert_if_some_and_not_empty(&mut override_map, "OrderID", orderid);
-    insert_if_some_and_not_empty(&mut override_map, "ExecID", execid);
-    insert_if_some_and_not_empty(&mut override_map, "Account", account);
-    insert_if_some_and_not_empty(&mut override_map, "Symbol", symbol);
-    insert_if_some_and_not_empty(&mut override_map, "Side", side);
-    insert_if_some_and_not_empty(&mut override_map, "OrdType", ordtype);
-    insert_if_some_and_not_empty(&mut override_map, "TransactionTime", transactiontime);
-    insert_if_some_and_not_empty(&mut override_map, "OrderQty", orderqty);
-    insert_if_some_and_not_empty(&mut override_map, "LastShares", lastshares);
-    insert_if_some_and_not_empty(&mut override_map, "LastPx", lastpx);
-    insert_if_some_and_not_empty(&mut override_map, "LeavesQty", leavesqty);
-    insert_if_some_and_not_empty(&mut override_map, "CumQty", cumqty);
-    insert_if_some_and_not_empty(&mut override_map, "AvgPx", avgpx);
-    insert_if_some_and_not_empty(&mut override_map, "ExecTransType", exectranstype);
-    insert_if_some_and_not_empty(&mut override_map, "ExecType", exectype);
-    insert_if_some_and_not_empty(&mut override_map, "OrdStatus", ordstatus);
+    insert_if_some_and_not_empty(&mut override_map, "OrderID", fields.order_id);
+    insert_if_some_and_not_empty(&mut override_map, "ExecID", fields.exec_id);
+    insert_if_some_and_not_empty(&mut override_map, "Account", fields.account);
+    insert_if_some_and_not_empty(&mut override_map, "Symbol", fields.symbol);
+    insert_if_some_and_not_empty(&mut override_map, "Side", fields.side);
+    insert_if_some_and_not_empty(&mut override_map, "OrdType", fields.ord_type);
+    insert_if_some_and_not_empty(&mut override_map, "TransactionTime", fields.transact_time);
+    insert_if_some_and_not_empty(&mut override_map, "OrderQty", fields.order_qty);
+    insert_if_some_and_not_empty(&mut override_map, "LastShares", fields.last_shares);
+    insert_if_some_and_not_empty(&mut override_map, "LastPx", fields.last_px);
+    insert_if_some_and_not_empty(&mut override_map, "LeavesQty", fields.leaves_qty);
+    insert_if_some_and_not_empty(&mut override_map, "CumQty", fields.cum_qty);
+    insert_if_some_and_not_empty(&mut override_map, "AvgPx", fields.avg_px);
+    insert_if_some_and_not_empty(&mut override_map, "ExecTransType", fields.exec_trans_type);
+    insert_if_some_and_not_empty(&mut override_map, "ExecType", fields.exec_type);
+    insert_if_some_and_not_empty(&mut override_map, "OrdStatus", fields.ord_status);
+
+    override_map
+}
 
+/// ExecutionReport fields of a stored order, with its LeavesQty, CumQty and AvgPx.
+pub fn order_execution_report(
+    order: &Order,
+    exec_id: &str,
+    exec_type: &str,
+    last_shares: Decimal,
+    last_px: Decimal,
+) -> HashMap<String, String> {
+    let mut override_map = prepare_execution_report(&ExecutionReportFields {
+        order_id: Some(&order.order_id),
+        exec_id: Some(exec_id),
+        account: Some(&order.account),
+        symbol: Some(&order.symbol),
+        side: Some(&order.side),
+        ord_type: Some(&order.ordtype),
+        transact_time: Some(&order.transacttime),
+        order_qty: Some(&order.quantity.to_string()),
+        last_shares: Some(&last_shares.to_string()),
+        last_px: Some(&last_px.to_string()),
+        leaves_qty: Some(&order.leaves_qty().to_string()),
+        cum_qty: Some(&order.cum_qty.to_string()),
+        avg_px: Some(&order.avg_px.to_string()),
+        exec_trans_type: Some("0"),
+        exec_type: Some(exec_type),
+        ord_status: Some(exec_type),
+    });
+    override_map.insert("ClOrdID".to_string(), order.id.clone());
     override_map
 }
 
-pub fn send_message(stream: &Arc<Mutex<TcpStream>>, message: String) -> Result<(), io::Error> {
+/// Sends an unsolicited ExecutionReport built from the template and the override fields.
+pub fn send_execution_report(
+    stream: &Arc<Mutex<Box<dyn Transport>>>,
+    app_msg: &HashMap<String, IndexMap<String, String>>,
+    fix_tag_name_map: &HashMap<String, FixTag>,
+    seq_store: &SequenceNumberStore,
+    override_map: &HashMap<String, String>,
+) -> Result<(), io::Error> {
+    let report = msgtype2fixmsg(
+        "Execution_Report".to_string(),
+        app_msg,
+        fix_tag_name_map,
+        Some(override_map),
+        seq_store.get_outgoing(),
+    );
+    send_sequenced(stream, seq_store, report.replace("|", "\x01"))
+}
+
+/// Sends the message with the next outgoing MsgSeqNum, restamped into it when another sender
+/// took the one it was built with, and consumes the number. Senders off the session thread
+/// (timers, the simulator, the matching engine) go through it as well as the session. The
+/// outbound interceptors run first, a message they drop takes no number, then the outbound
+/// queue lets it through, or refuses it above a rejecting throttle.
+pub fn send_sequenced(
+    stream: &Arc<Mutex<Box<dyn Transport>>>,
+    seq_store: &SequenceNumberStore,
+    message: String,
+) -> Result<(), io::Error> {
+    let (message, queue) = {
+        let stream = stream.lock().unwrap();
+        let Some(message) = stream.intercept_outbound(&message).map(Cow::into_owned) else {
+            return Ok(());
+        };
+        (message, stream.outbound_queue())
+    };
+    let application_messages = application_messages(message.as_bytes());
+    if queue.is_some_and(|queue| !queue.admit(application_messages)) {
+        error!(
+            "Outbound message rate limit reached, not sending {}",
+            mask_message(&message).replace('\x01', "|")
+        );
+        return Ok(());
+    }
+    seq_store.send_outgoing(1, |msg_seq_num| {
+        send_message(stream, restamp_seq_num(&message, msg_seq_num).into_owned())
+    })
+}
+
+pub fn send_message(stream: &Arc<Mutex<Box<dyn Transport>>>, message: String) -> Result<(), io::Error> {
     let mut stream = stream.lock().unwrap();
     stream.write_all(message.as_bytes())?;
     stream.flush()?;
-    info!("sent out message: {}", message);
+    SESSION_STATES.message_sent(&message);
+    journal_sent(&message);
+    store_execution_report(ExecDirection::Sent, &message);
+    #[cfg(feature = "kafka")]
+    publish_message(ExecDirection::Sent, &message);
+    ENGINE_EVENTS.message(ExecDirection::Sent, &message);
     Ok(())
 }
 
-pub fn client_session_thread(_stream: TcpStream) {
+pub fn client_session_thread(_stream: Box<dyn Transport>) {
     // let ten_millis = time::Duration::from_millis(1000);
     // sleep(ten_millis);
     info!("Client session thread started.");
 }
 
-pub fn venue_session_thread(_stream: TcpStream) {
+pub fn venue_session_thread(_stream: Box<dyn Transport>) {
     info!("Venue session thread started.");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_xml::DataType;
+    use crate::templates::Templates;
+    use crate::transport::MemoryTransport;
+    use std::io::Read;
+
+    #[test]
+    fn test_business_reject_fields() {
+        let msg_map: IndexMap<String, String> = [
+            ("MsgType", "ORDER_CANCEL_REPLACE_REQUEST"),
+            ("MsgSeqNum", "12"),
+            ("ClOrdID", "2"),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        let msg_type = FixTag::new(
+            "35".to_string(),
+            "MsgType".to_string(),
+            DataType::String,
+            Some(HashMap::from([(
+                "ORDER_CANCEL_REPLACE_REQUEST".to_string(),
+                "G".to_string(),
+            )])),
+        );
+        let fix_tag_name_map = HashMap::from([("MsgType".to_string(), msg_type)]);
+        let ref_msg_type = ref_msg_type(&msg_map, &fix_tag_name_map);
+        assert_eq!(ref_msg_type, "G");
+
+        let err = OrderError::InvalidField {
+            field: "Price",
+            value: "abc".to_string(),
+        };
+        let fields = business_reject_fields(
+            &msg_map,
+            &ref_msg_type,
+            BUSINESS_REJECT_REASON_OTHER,
+            &err.to_string(),
+        );
+        assert_eq!(fields["RefMsgType"], "G");
+        assert_eq!(fields["RefSeqNum"], "12");
+        assert_eq!(fields["BusinessRejectRefID"], "2");
+        assert_eq!(fields["BusinessRejectReason"], "0");
+        assert_eq!(fields["Text"], "Invalid Price \"abc\"");
+    }
+
+    #[test]
+    fn test_cancel_reject_fields() {
+        let msg_map: IndexMap<String, String> = [("ClOrdID", "2"), ("OrigClOrdID", "1")]
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let fields = cancel_reject_fields(
+            &msg_map,
+            CXL_REJ_RESPONSE_TO_REPLACE,
+            &CancelRejection::TooLate {
+                order_id: "O00000001".to_string(),
+                refused: IllegalTransition {
+                    order_id: "1".to_string(),
+                    from: OrdStatus::Filled,
+                    to: OrdStatus::Replaced,
+                },
+            },
+        );
+        assert_eq!(fields["OrderID"], "O00000001");
+        assert_eq!(fields["OrigClOrdID"], "1");
+        assert_eq!(fields["ClOrdID"], "2");
+        assert_eq!(fields["OrdStatus"], "2");
+        assert_eq!(fields["CxlRejResponseTo"], "2");
+        assert_eq!(fields["CxlRejReason"], "0");
+
+        let fields = cancel_reject_fields(
+            &msg_map,
+            CXL_REJ_RESPONSE_TO_CANCEL,
+            &CancelRejection::UnknownOrder,
+        );
+        assert_eq!(fields["OrderID"], "NONE");
+        assert_eq!(fields["CxlRejResponseTo"], "1");
+        assert_eq!(fields["CxlRejReason"], "1");
+        assert_eq!(fields["Text"], "Unknown order 1");
+
+        let fields = cancel_reject_fields(
+            &msg_map,
+            CXL_REJ_RESPONSE_TO_REPLACE,
+            &CancelRejection::DuplicateClOrdID {
+                order_id: "O00000001".to_string(),
+                ord_status: OrdStatus::New,
+            },
+        );
+        assert_eq!(fields["OrderID"], "O00000001");
+        assert_eq!(fields["OrdStatus"], "0");
+        assert_eq!(fields["CxlRejReason"], "6");
+        assert_eq!(fields["Text"], "Duplicate ClOrdID 2");
+    }
+
+    #[test]
+    fn test_order_status_fields() {
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let order_store = OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+        let order = Order {
+            id: "7".to_string(),
+            sender_comp_id: String::new(),
+            order_id: "O00000007".to_string(),
+            account: "ACC1".to_string(),
+            symbol: "IBM".to_string(),
+            side: "BUY".to_string(),
+            quantity: Decimal::from(100),
+            price: Some(Decimal::from(50)),
+            ordtype: "LIMIT".to_string(),
+            transacttime: "20240101-00:00:00".to_string(),
+            ordstatus: "New".to_string(),
+            timeinforce: "DAY".to_string(),
+            cum_qty: Decimal::ZERO,
+            avg_px: Decimal::ZERO,
+            list_id: None,
+            list_seq_no: 0,
+            history: Vec::new(),
+        };
+        order_store.add_order(order, None).unwrap();
+        order_store
+            .fill_order(&OrderKey::own("7"), Decimal::from(40), Decimal::from(50))
+            .unwrap();
+        let request = |fields: &[(&str, &str)]| -> IndexMap<String, String> {
+            fields
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+
+        let fields =
+            order_status_fields(&request(&[("OrderID", "O00000007")]), "E1", &order_store);
+        assert_eq!(fields["ExecType"], "I");
+        assert_eq!(fields["ExecID"], "E1");
+        assert_eq!(fields["OrderID"], "O00000007");
+        assert_eq!(fields["ExecTransType"], "3");
+        assert_eq!(fields["OrdStatus"], "1");
+        assert_eq!(fields["ClOrdID"], "7");
+        assert_eq!((fields["CumQty"].as_str(), fields["LeavesQty"].as_str()), ("40", "60"));
+
+        let fields = order_status_fields(
+            &request(&[("ClOrdID", "8"), ("Symbol", "IBM"), ("Side", "BUY")]),
+            "E2",
+            &order_store,
+        );
+        assert_eq!(fields["OrdStatus"], "8");
+        assert_eq!(fields["OrdRejReason"], "5");
+        assert_eq!(fields["OrderID"], "NONE");
+        assert_eq!(fields["ClOrdID"], "8");
+    }
+
+    #[test]
+    fn test_logout_confirms_only_its_own_session() {
+        let session = |client: &str| SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: client.to_string(),
+            target_comp_id: "LOGOUT_VENUE".to_string(),
+        };
+        let (first, second) = (session("LOGOUT_CLIENT1"), session("LOGOUT_CLIENT2"));
+        SESSION_STATES.for_session(&first).logout_sent();
+        SESSION_STATES.for_session(&second).logout_sent();
+
+        let logout: IndexMap<String, String> = [
+            ("BeginString", "FIX.4.2"),
+            ("SenderCompID", "LOGOUT_VENUE"),
+            ("TargetCompID", "LOGOUT_CLIENT1"),
+        ]
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let seq_store =
+            Arc::new(SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap());
+        let (local, _remote) = MemoryTransport::pair();
+        handle_admin_message(
+            Box::new(local),
+            "LOGOUT",
+            &logout,
+            &HashMap::new(),
+            &HashMap::new(),
+            "",
+            seq_store,
+        );
+        assert!(SESSION_STATES.for_session(&first).is_logout_received());
+        assert!(!SESSION_STATES.for_session(&second).is_logout_received());
+
+        // A new connection of one session leaves the other ending
+        SESSION_STATES.for_session(&first).reset();
+        assert!(!SESSION_STATES.for_session(&first).is_logout_sent());
+        assert!(SESSION_STATES.for_session(&second).is_logout_sent());
+    }
+
+    #[test]
+    fn test_binary_raw_data_logon_consumes_its_seq_num() {
+        let fix_tag = |number: u32, name: &str, data_type: DataType| {
+            (number, FixTag::new(number.to_string(), name.to_string(), data_type, None))
+        };
+        let logon = crate::parse_payload_xml::FixMsgTag {
+            msgcat: "admin".to_string(),
+            msgname: "Logon".to_string(),
+            field: Some(HashMap::new()),
+        };
+        let message_maps = MessageMap {
+            fix_header: Default::default(),
+            fix_tag_number_map: HashMap::from([
+                fix_tag(34, "MsgSeqNum", DataType::Int),
+                fix_tag(95, "RawDataLength", DataType::Int),
+                fix_tag(96, "RawData", DataType::String),
+            ]),
+            admin_msg_list: Default::default(),
+            admin_msg: Default::default(),
+            app_msg: Default::default(),
+            fix_tag_name_map: Default::default(),
+            msgnumber_fields_map: HashMap::from([("A".to_string(), logon)]),
+            valid_msg_types: vec!["A".to_string()],
+            required_fields: Default::default(),
+        };
+
+        // RawData holding SOH, '|' and bytes which are not UTF-8
+        let body = b"35=A\x0134=1\x0195=4\x0196=\x00\x01\xff|\x01";
+        let mut message = format!("8=FIX.4.2\x019={}\x01", body.len()).into_bytes();
+        message.extend_from_slice(body);
+        let checksum = tag_value::checksum(&message);
+        message.extend_from_slice(format!("10={:03}\x01", checksum).as_bytes());
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let seq_store =
+            Arc::new(SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap());
+        let order_file = tempfile::NamedTempFile::new().unwrap();
+        let order_store =
+            Arc::new(OrderStore::new(order_file.path().to_str().unwrap(), 4096).unwrap());
+        let (mut local, _remote) = MemoryTransport::pair();
+        handle_incoming_message(
+            &message,
+            &mut local,
+            &message_maps,
+            Arc::clone(&seq_store),
+            order_store,
+        )
+        .unwrap();
+        assert_eq!(seq_store.get_incoming(), 2);
+    }
+    #[test]
+    fn test_msg_seq_num_too_low_ends_only_the_session() {
+        let fix_tag = |number: u32, name: &str, data_type: DataType| {
+            FixTag::new(number.to_string(), name.to_string(), data_type, None)
+        };
+        let heartbeat = crate::parse_payload_xml::FixMsgTag {
+            msgcat: "admin".to_string(),
+            msgname: "Heartbeat".to_string(),
+            field: Some(HashMap::new()),
+        };
+        let logout = [("8", "FIX.4.2"), ("MsgType", "5"), ("MsgSeqNum", "")]
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let message_maps = MessageMap {
+            fix_header: Default::default(),
+            fix_tag_number_map: HashMap::from([
+                (34, fix_tag(34, "MsgSeqNum", DataType::Int)),
+                (43, fix_tag(43, "PossDupFlag", DataType::Bool)),
+            ]),
+            admin_msg_list: Default::default(),
+            admin_msg: Templates::new(
+                HashMap::from([("Logout".to_string(), logout)]),
+                Vec::new(),
+            ),
+            app_msg: Default::default(),
+            fix_tag_name_map: HashMap::from([
+                ("MsgType".to_string(), fix_tag(35, "MsgType", DataType::String)),
+                ("MsgSeqNum".to_string(), fix_tag(34, "MsgSeqNum", DataType::Int)),
+                ("Text".to_string(), fix_tag(58, "Text", DataType::String)),
+            ]),
+            msgnumber_fields_map: HashMap::from([("0".to_string(), heartbeat)]),
+            valid_msg_types: vec!["0".to_string()],
+            required_fields: Default::default(),
+        };
+        let heartbeat = |fields: &str| {
+            let body = format!("35=0\x01{}", fields);
+            let message = format!("8=FIX.4.2\x019={}\x01{}", body.len(), body);
+            let checksum = tag_value::checksum(message.as_bytes());
+            format!("{}10={:03}\x01", message, checksum).into_bytes()
+        };
+
+        let temp_file = tempfile::NamedTempFile::new().unwrap();
+        let seq_store =
+            Arc::new(SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap());
+        seq_store.set_incoming(5);
+        let order_file = tempfile::NamedTempFile::new().unwrap();
+        let order_store =
+            Arc::new(OrderStore::new(order_file.path().to_str().unwrap(), 4096).unwrap());
+        let (mut local, mut remote) = MemoryTransport::pair();
+        let mut receive = |message: &[u8]| {
+            handle_incoming_message(
+                message,
+                &mut local,
+                &message_maps,
+                Arc::clone(&seq_store),
+                Arc::clone(&order_store),
+            )
+            .unwrap()
+        };
+
+        // A message sent again is a duplicate, not a reason to end the session
+        receive(&heartbeat("34=3\x0143=Y\x01"));
+        assert_eq!(seq_store.get_incoming(), 5);
+        receive(&heartbeat("34=3\x01"));
+        let mut sent = Vec::new();
+        remote.read_to_end(&mut sent).unwrap();
+        let sent = String::from_utf8(sent).unwrap();
+        assert!(sent.contains("\x0135=5\x01"));
+        assert!(sent.contains("\x0158=MsgSeqNum too low, expecting 5 but received 3\x01"));
+        assert_eq!(seq_store.get_incoming(), 5);
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, RwLock};
+
+use chrono::Utc;
+use log::{error, info};
+
+use crate::sequence::SessionId;
+
+lazy_static! {
+    pub static ref MESSAGE_JOURNALS: MessageJournals = MessageJournals::new();
+}
+
+const SENT: &str = "S";
+const RECEIVED: &str = "R";
+const REJECTED: &str = "X";
+const NOTE: &str = "N";
+
+/// Append-only log of every message sent and received, one line per message:
+/// `<S|R> <timestamp> <raw message>`. Rejected outgoing messages are recorded as
+/// `X <timestamp> <MsgSeqNum> <reason>`, session facts (e.g. the simulator seed) as
+/// `N <timestamp> <text>`.
+pub struct MessageJournal {
+    file_path: String,
+    file: Mutex<File>,
+    /// Our outgoing messages by MsgSeqNum, used to resolve RefSeqNum of a Reject.
+    sent: RwLock<BTreeMap<u64, String>>,
+}
+
+impl MessageJournal {
+    /// Opens (or creates) the journal file and indexes the messages we already sent.
+    pub fn open(file_path: &str) -> io::Result<Self> {
+        let mut sent = BTreeMap::new();
+        if let Ok(file) = File::open(file_path) {
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                let mut parts = line.splitn(3, ' ');
+                if let (Some(SENT), Some(_), Some(message)) =
+                    (parts.next(), parts.next(), parts.next())
+                {
+                    if let Some(seq_num) = msg_seq_num(message) {
+                        sent.insert(seq_num, message.to_string());
+                    }
+                }
+            }
+        }
+
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(file_path)?;
+        Ok(Self {
+            file_path: file_path.to_string(),
+            file: Mutex::new(file),
+            sent: RwLock::new(sent),
+        })
+    }
+
+    pub fn record_sent(&self, message: &str) -> io::Result<()> {
+        if let Some(seq_num) = msg_seq_num(message) {
+            self.sent
+                .write()
+                .unwrap()
+                .insert(seq_num, message.to_string());
+        }
+        self.append(SENT, message)
+    }
+
+    pub fn record_received(&self, message: &str) -> io::Result<()> {
+        self.append(RECEIVED, message)
+    }
+
+    /// Marks one of our outgoing messages as rejected by the counterparty.
+    pub fn record_rejected(&self, seq_num: u64, reason: &str) -> io::Result<()> {
+        self.append(REJECTED, &format!("{} {}", seq_num, reason))
+    }
+
+    /// Records a fact about the session run which is needed to audit or reproduce it.
+    pub fn record_note(&self, note: &str) -> io::Result<()> {
+        self.append(NOTE, note)
+    }
+
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+
+    /// Returns the raw outgoing message sent with the given MsgSeqNum.
+    pub fn sent_message(&self, seq_num: u64) -> Option<String> {
+        self.sent.read().unwrap().get(&seq_num).cloned()
+    }
+
+    /// Moves the journal to `<file>.<suffix>` (e.g. the trading date) and starts an empty one,
+    /// as done when the sequence numbers are reset.
+    pub fn archive(&self, suffix: &str) -> io::Result<String> {
+        let mut file = self.file.lock().unwrap();
+        let archive_path = format!("{}.{}", self.file_path, suffix);
+        fs::rename(&self.file_path, &archive_path)?;
+        *file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.file_path)?;
+        self.sent.write().unwrap().clear();
+        Ok(archive_path)
+    }
+
+    fn append(&self, kind: &str, entry: &str) -> io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        writeln!(
+            file,
+            "{} {} {}",
+            kind,
+            Utc::now().format("%Y%m%d-%H:%M:%S%.3f"),
+            entry.trim_end_matches('\n')
+        )
+    }
+}
+
+/// The journals of every session, each in its own file named after the configured
+/// `message_journal` path and the session id, like the sequence number stores.
+pub struct MessageJournals {
+    /// None while messages are not journaled.
+    base_path: RwLock<Option<String>>,
+    journals: Mutex<HashMap<SessionId, Arc<MessageJournal>>>,
+    /// Facts about the run, recorded in every journal.
+    notes: Mutex<Vec<String>>,
+}
+
+impl MessageJournals {
+    pub fn new() -> Self {
+        Self {
+            base_path: RwLock::new(None),
+            journals: Mutex::new(HashMap::new()),
+            notes: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Journals the messages of every session from now on.
+    pub fn configure(&self, base_path: &str) {
+        *self.base_path.write().unwrap() = Some(base_path.to_string());
+    }
+
+    /// Returns the journal of the session, opening it on first use. None when messages are
+    /// not journaled or the journal cannot be opened.
+    pub fn for_session(&self, session_id: &SessionId) -> Option<Arc<MessageJournal>> {
+        let base_path = self.base_path.read().unwrap().clone()?;
+        let mut journals = self.journals.lock().unwrap();
+        if let Some(journal) = journals.get(session_id) {
+            return Some(Arc::clone(journal));
+        }
+
+        let file_path = session_id.file_path(&base_path);
+        // Messages journaled by earlier versions in the shared file move to the first session
+        if !Path::new(&file_path).exists() && Path::new(&base_path).exists() {
+            match fs::rename(&base_path, &file_path) {
+                Ok(_) => info!("Moved {} to {}", base_path, file_path),
+                Err(e) => info!("Could not move {} to {}: {}", base_path, file_path, e),
+            }
+        }
+        let journal = match MessageJournal::open(&file_path) {
+            Ok(journal) => Arc::new(journal),
+            Err(e) => {
+                error!("Failed to open the message journal {}: {}", file_path, e);
+                return None;
+            }
+        };
+        info!("Messages of {} are journaled to {}", session_id, file_path);
+        for note in self.notes.lock().unwrap().iter() {
+            if let Err(e) = journal.record_note(note) {
+                error!("Failed to journal note: {}", e);
+            }
+        }
+        journals.insert(session_id.clone(), Arc::clone(&journal));
+        Some(journal)
+    }
+
+    /// Returns the journals of every session seen so far.
+    pub fn all(&self) -> Vec<Arc<MessageJournal>> {
+        self.journals.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Records a fact about the run in the journal of every session, those opened later
+    /// included.
+    pub fn note(&self, note: &str) {
+        self.notes.lock().unwrap().push(note.to_string());
+        for journal in self.all() {
+            if let Err(e) = journal.record_note(note) {
+                error!("Failed to journal note: {}", e);
+            }
+        }
+    }
+
+    /// The journal of the session a raw message was sent (`sent`) or received on, if any.
+    fn of_message(&self, message: &str, sent: bool) -> Option<Arc<MessageJournal>> {
+        if self.base_path.read().unwrap().is_none() {
+            return None;
+        }
+        self.for_session(&SessionId::of_message(message, sent)?)
+    }
+}
+
+impl Default for MessageJournals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Records a note in the journal of every session, if messages are journaled.
+pub fn journal_note(note: &str) {
+    MESSAGE_JOURNALS.note(note);
+}
+
+/// Records an outgoing message in the journal of its session, if any.
+pub fn journal_sent(message: &str) {
+    if let Some(journal) = MESSAGE_JOURNALS.of_message(message, true) {
+        if let Err(e) = journal.record_sent(message) {
+            error!("Failed to journal sent message: {}", e);
+        }
+    }
+}
+
+/// Records an incoming message in the journal of its session, if any.
+pub fn journal_received(message: &str) {
+    if let Some(journal) = MESSAGE_JOURNALS.of_message(message, false) {
+        if let Err(e) = journal.record_received(message) {
+            error!("Failed to journal received message: {}", e);
+        }
+    }
+}
+
+/// Extracts MsgSeqNum(34) from a raw message delimited by SOH or '|'.
+fn msg_seq_num(message: &str) -> Option<u64> {
+    message
+        .split(['\x01', '|'])
+        .find_map(|field| field.strip_prefix("34="))
+        .and_then(|value| value.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_record_and_lookup_sent_message() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let journal = MessageJournal::open(temp_file.path().to_str().unwrap()).unwrap();
+
+        journal
+            .record_sent("8=FIX.4.2\x019=50\x0135=D\x0134=7\x0111=1001\x0110=000\x01")
+            .unwrap();
+        journal
+            .record_received("8=FIX.4.2\x019=50\x0135=3\x0134=3\x0145=7\x0110=000\x01")
+            .unwrap();
+
+        assert!(journal.sent_message(7).unwrap().contains("11=1001"));
+        // Only our own messages are indexed
+        assert!(journal.sent_message(3).is_none());
+    }
+
+    #[test]
+    fn test_reopen_rebuilds_index() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        {
+            let journal = MessageJournal::open(path).unwrap();
+            journal
+                .record_sent("8=FIX.4.2|35=D|34=12|11=1002|")
+                .unwrap();
+            journal.record_rejected(12, "REQUIRED_TAG_MISSING").unwrap();
+        }
+
+        let journal = MessageJournal::open(path).unwrap();
+        assert_eq!(
+            journal.sent_message(12).unwrap(),
+            "8=FIX.4.2|35=D|34=12|11=1002|"
+        );
+        let content = std::fs::read_to_string(path).unwrap();
+        assert!(content.lines().last().unwrap().starts_with("X "));
+    }
+
+    #[test]
+    fn test_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.log");
+        let journal = MessageJournal::open(path.to_str().unwrap()).unwrap();
+        journal.record_sent("8=FIX.4.2|35=0|34=5|").unwrap();
+
+        let archive_path = journal.archive("20240506").unwrap();
+        journal.record_sent("8=FIX.4.2|35=A|34=1|").unwrap();
+
+        assert!(std::fs::read_to_string(&archive_path)
+            .unwrap()
+            .contains("34=5|"));
+        assert!(journal.sent_message(5).is_none());
+        assert!(journal.sent_message(1).is_some());
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn test_msg_seq_num() {
+        assert_eq!(msg_seq_num("8=FIX.4.2\x0135=0\x0134=42\x01"), Some(42));
+        assert_eq!(msg_seq_num("8=FIX.4.2|35=0|"), None);
+    }
+
+    #[test]
+    fn test_journals_per_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().join("journal.log");
+        let journals = MessageJournals::new();
+        let session = |target_comp_id: &str| SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "FIX_Engine".to_string(),
+            target_comp_id: target_comp_id.to_string(),
+        };
+        assert!(journals.for_session(&session("XYZ")).is_none());
+
+        journals.configure(base_path.to_str().unwrap());
+        journals.note("sim_seed=7");
+        let xyz = journals
+            .of_message("8=FIX.4.2|35=D|49=FIX_Engine|56=XYZ|34=3|", true)
+            .unwrap();
+        xyz.record_sent("8=FIX.4.2|35=D|49=FIX_Engine|56=XYZ|34=3|")
+            .unwrap();
+        let abc = journals
+            .of_message("8=FIX.4.2|35=0|49=ABC|56=FIX_Engine|34=3|", false)
+            .unwrap();
+
+        assert!(Arc::ptr_eq(&xyz, &journals.for_session(&session("XYZ")).unwrap()));
+        assert!(abc.sent_message(3).is_none());
+        assert!(xyz.sent_message(3).is_some());
+        let content =
+            std::fs::read_to_string(dir.path().join("journal_FIX.4.2_FIX_Engine_ABC.log")).unwrap();
+        assert!(content.starts_with("N ") && content.contains("sim_seed=7"));
+        assert_eq!(journals.all().len(), 2);
+    }
+}
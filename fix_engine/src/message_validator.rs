@@ -1,105 +1,568 @@
+use crate::counterparty::behaviour_of;
+use crate::enrichment::HEADER_FIELDS;
 use crate::parse_payload_xml::FixMsgTag;
-use log::error;
+use crate::parse_xml::{DataType, FixError, FixTag};
+use crate::tag_value;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use indexmap::IndexMap;
+use json::JsonValue;
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
 
-type FixFieldMap = HashMap<String, String>;
+/// Fields by tag number, in the order of the message.
+type FixFieldMap = IndexMap<u32, String>;
 type StrVec = Vec<String>;
 type MsgTypeMap = HashMap<String, FixMsgTag>;
 
-#[derive(Debug)]
+/// A parsed message keyed by tag number, the names of the dictionary only looked up to show
+/// it or to hand it to the code working with named fields (`to_names`).
+#[derive(Debug, Clone)]
 pub struct FixMessage {
     fields: FixFieldMap,
+    /// The first value of each repeated tag, which the named fields keep as `fixmsg2msgtype`
+    /// does. Empty unless the message has a repeating group.
+    first_values: Vec<(u32, String)>,
 }
 
 impl FixMessage {
+    /// Parses a message delimited by SOH or '|'. A tag repeated, e.g. in a repeating group,
+    /// keeps its last value, at the position of its first one, for the validation.
     pub fn parse(raw_message: &str) -> Result<Self, &'static str> {
         let mut fields = FixFieldMap::new();
-        for part in raw_message.split('|') {
-            if !part.is_empty() {
-                let mut iter = part.splitn(2, '=');
-                if let (Some(key), Some(value)) = (iter.next(), iter.next()) {
-                    fields.insert(key.to_string(), value.to_string());
-                } else {
-                    return Err("Invalid field format");
+        let mut first_values = Vec::new();
+        for field in tag_value::fields(raw_message.as_bytes()) {
+            let (tag, value) = field.map_err(|_| "Invalid field format")?;
+            let value = std::str::from_utf8(value).map_err(|_| "Invalid field format")?;
+            if let Some(previous) = fields.insert(tag, value.to_string()) {
+                if !first_values.iter().any(|(repeated, _)| *repeated == tag) {
+                    first_values.push((tag, previous));
                 }
             }
         }
-        Ok(FixMessage { fields })
+        Ok(FixMessage {
+            fields,
+            first_values,
+        })
     }
 
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields.get(&tag).map(String::as_str)
+    }
+
+    /// The value of the tag, replaced by its description when the dictionary enumerates it,
+    /// e.g. `LOGON` for 35=A, without naming the other fields.
+    pub fn described<'a>(
+        &'a self,
+        tag: u32,
+        fix_tag_number_map: &'a HashMap<u32, FixTag>,
+    ) -> Option<&'a str> {
+        let value = self.fields.get(&tag)?;
+        let description = fix_tag_number_map
+            .get(&tag)
+            .and_then(|definition| definition.enum_values.as_ref())
+            .and_then(|enum_values| enum_values.get(value));
+        Some(description.unwrap_or(value))
+    }
+
+    /// The MsgType and fields by name, as `fixmsg2msgtype` gives them: a value enumerated by
+    /// the dictionary is replaced by its description, a tag missing from it is kept by number
+    /// and a repeated tag keeps its first value.
+    pub fn to_names(
+        &self,
+        fix_tag_number_map: &HashMap<u32, FixTag>,
+    ) -> (String, IndexMap<String, String>) {
+        let mut msgtype = String::new();
+        let mut msg_map = IndexMap::with_capacity(self.fields.len());
+        for (tag, value) in &self.fields {
+            let value = self
+                .first_values
+                .iter()
+                .find(|(repeated, _)| repeated == tag)
+                .map_or(value, |(_, first)| first);
+            let Some(definition) = fix_tag_number_map.get(tag) else {
+                msg_map.insert(tag.to_string(), value.clone());
+                continue;
+            };
+            let value = definition
+                .enum_values
+                .as_ref()
+                .and_then(|enum_values| enum_values.get(value))
+                .unwrap_or(value);
+            if *tag == 35 {
+                msgtype = value.clone();
+            }
+            msg_map.insert(definition.name.clone(), value.clone());
+        }
+        (msgtype, msg_map)
+    }
+
+    /// The message as JSON, as `print_fix_message` shows it: a field by its tag name, with
+    /// the description of its value when the dictionary enumerates it, e.g.
+    /// `{"BeginString": "FIX.4.2", "MsgType": "NEW_ORDER_SINGLE", "Side": "BUY", ...}`.
+    /// Tags missing from the dictionary keep their number.
+    pub fn to_json(&self, fix_tag_number_map: &HashMap<u32, FixTag>) -> String {
+        let mut json = JsonValue::new_object();
+        for (tag, value) in &self.fields {
+            match fix_tag_number_map.get(tag) {
+                Some(definition) => {
+                    let value = definition
+                        .enum_values
+                        .as_ref()
+                        .and_then(|enum_values| enum_values.get(value))
+                        .unwrap_or(value);
+                    json[definition.name.as_str()] = value.as_str().into();
+                }
+                None => json[tag.to_string().as_str()] = value.as_str().into(),
+            }
+        }
+        json.dump()
+    }
+
+    /// The message of a JSON object made by `to_json`. Fields are named as in the dictionary
+    /// or by tag number, their values described or as on the wire.
+    pub fn from_json(
+        json: &str,
+        fix_tag_name_map: &HashMap<String, FixTag>,
+    ) -> Result<Self, FixError> {
+        let object = json::parse(json)
+            .map_err(|e| FixError::Parse(format!("Invalid JSON: {}", e)))?;
+        if !object.is_object() {
+            return Err(FixError::Parse("Expected a JSON object".to_string()));
+        }
+        let mut fields = FixFieldMap::new();
+        for (name, value) in object.entries() {
+            let value = match value {
+                JsonValue::Number(_) | JsonValue::Boolean(_) => value.dump(),
+                _ => value
+                    .as_str()
+                    .ok_or_else(|| FixError::Parse(format!("Invalid value of {}", name)))?
+                    .to_string(),
+            };
+            if let Ok(tag) = name.parse::<u32>() {
+                fields.insert(tag, value);
+                continue;
+            }
+            let definition = fix_tag_name_map
+                .get(name)
+                .ok_or_else(|| FixError::Parse(format!("Unknown field {}", name)))?;
+            let value = definition
+                .enum_values
+                .as_ref()
+                .and_then(|enum_values| enum_values.get(&value.to_uppercase()))
+                .cloned()
+                .unwrap_or(value);
+            let tag = definition
+                .number
+                .parse::<u32>()
+                .map_err(|_| FixError::Parse(format!("Invalid tag number of {}", name)))?;
+            fields.insert(tag, value);
+        }
+        Ok(FixMessage {
+            fields,
+            first_values: Vec::new(),
+        })
+    }
+
+    /// The fields as `tag=value|`, the form `parse` reads.
+    pub fn to_fix(&self) -> String {
+        self.fields
+            .iter()
+            .map(|(tag, value)| format!("{}={}|", tag, value))
+            .collect()
+    }
+
+    /// Checks the message against the dictionary: the required fields and those of its
+    /// MsgType are present, BodyLength and CheckSum are numbers (`garbled_reason` checks them
+    /// against the message), and each field of the dictionary has a value of its type and, when
+    /// enumerated, one of its values. Returns every problem found.
     pub fn validate(
         &self,
         required_fields: &StrVec,
         valid_msg_types: &StrVec,
         msgnumber_fields_map: &MsgTypeMap,
-    ) -> bool {
-        for field in required_fields {
-            match self.fields.get(field) {
-                Some(value) if !value.is_empty() => (),
-                _ => {
-                    error!("Required field is missing or empty: {}", field);
-                    return false;
-                }
+        fix_tag_number_map: &HashMap<u32, FixTag>,
+    ) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        let require = |errors: &mut Vec<ValidationError>, tag: &String| {
+            let value = tag.parse::<u32>().ok().and_then(|tag| self.get(tag));
+            if value.is_none_or(str::is_empty) {
+                errors.push(ValidationError::MissingRequiredField { tag: tag.clone() });
             }
+        };
+        for field in required_fields {
+            require(&mut errors, field);
         }
 
-        // Check BodyLength field
-        if let Some(body_length) = self.fields.get("9") {
-            if body_length.parse::<usize>().is_err() || body_length.is_empty() {
-                error!("Invalid or empty BodyLength field: {}", body_length);
-                return false;
+        if let Some(body_length) = self.fields.get(&9) {
+            if body_length.parse::<usize>().is_err() {
+                errors.push(ValidationError::BadBodyLength(body_length.clone()));
             }
         }
-
-        // Check MsgType field
-        if let Some(msg_type) = self.fields.get("35") {
-            if !valid_msg_types.contains(msg_type) || msg_type.is_empty() {
-                error!("Invalid or empty MsgType field: {}", msg_type);
-                return false;
+        if let Some(checksum) = self.fields.get(&10) {
+            if checksum.len() != 3 || checksum.parse::<u8>().is_err() {
+                errors.push(ValidationError::BadChecksum(checksum.clone()));
             }
+        }
 
-            // Retrieve required fields for this MsgType
-            let msgtype_required_fields: StrVec = match msgnumber_fields_map.get(msg_type) {
-                Some(msgtype_fld_info) => match &msgtype_fld_info.field {
-                    Some(field_map) => field_map.keys().cloned().collect(),
-                    None => {
-                        error!("MsgType field information is empty");
-                        return false;
+        match self.fields.get(&35) {
+            Some(msg_type) if valid_msg_types.contains(msg_type) => {
+                match msgnumber_fields_map
+                    .get(msg_type)
+                    .and_then(|msgtype_fld_info| msgtype_fld_info.field.as_ref())
+                {
+                    Some(field_map) => {
+                        for field in field_map.keys() {
+                            require(&mut errors, field);
+                        }
                     }
-                },
-                None => {
-                    error!(
-                        "MsgType field information not found for MsgType: {}",
-                        msg_type
-                    );
-                    return false;
+                    None => errors.push(ValidationError::InvalidMsgType(msg_type.clone())),
                 }
-            };
+            }
+            Some(msg_type) => errors.push(ValidationError::InvalidMsgType(msg_type.clone())),
+            None if !required_fields.iter().any(|field| field == "35") => {
+                errors.push(ValidationError::MissingRequiredField {
+                    tag: "35".to_string(),
+                })
+            }
+            None => (),
+        }
 
-            for field in msgtype_required_fields {
-                match self.fields.get(&field) {
-                    Some(value) if !value.is_empty() => (),
-                    _ => {
-                        error!(
-                            "MsgType {} required field is missing or empty: {}",
-                            msg_type, field
-                        );
-                        return false;
-                    }
+        errors.extend(self.ordering_errors(fix_tag_number_map));
+
+        // The session fields checked above, and the empty values of the others, are not
+        // checked again
+        for (tag, value) in &self.fields {
+            if matches!(tag, 9 | 10 | 35) || value.is_empty() {
+                continue;
+            }
+            // Tags missing from the dictionary are checked by `undefined_tags`
+            let Some(definition) = fix_tag_number_map.get(tag) else {
+                continue;
+            };
+            if !is_of_type(value, definition.data_type()) {
+                errors.push(ValidationError::WrongDataType {
+                    tag: tag.to_string(),
+                });
+            } else if let Some(enum_values) = &definition.enum_values {
+                // A MultipleValueString holds several values apart by spaces
+                if !value
+                    .split(' ')
+                    .all(|value| enum_values.contains_key(value))
+                {
+                    errors.push(ValidationError::InvalidEnum {
+                        tag: tag.to_string(),
+                        value: value.clone(),
+                    });
                 }
             }
-        } else {
-            error!("Missing MsgType field");
-            return false;
         }
 
-        true
+        if behaviour_of(self.get(49)).reject_undefined_tags {
+            errors.extend(self.undefined_tags(fix_tag_number_map));
+        }
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    /// The fields out of the order of the standard header, body and trailer: BeginString,
+    /// BodyLength and MsgType lead the message in that order, the other header fields come
+    /// before the body and CheckSum ends the message.
+    fn ordering_errors(&self, fix_tag_number_map: &HashMap<u32, FixTag>) -> Vec<ValidationError> {
+        let out_of_order = |tag: u32| ValidationError::OutOfOrder {
+            tag: tag.to_string(),
+        };
+        let mut errors: Vec<ValidationError> = [8, 9, 35]
+            .into_iter()
+            .filter(|tag| self.fields.contains_key(tag))
+            .enumerate()
+            .filter(|(position, tag)| self.fields.get_index_of(tag) != Some(*position))
+            .map(|(_, tag)| out_of_order(tag))
+            .collect();
+
+        let is_header = |tag: &u32| {
+            fix_tag_number_map
+                .get(tag)
+                .is_some_and(|definition| HEADER_FIELDS.contains(&definition.name.as_str()))
+        };
+        let mut in_body = false;
+        for tag in self.fields.keys() {
+            match tag {
+                8 | 9 | 35 | 10 => (),
+                tag if !is_header(tag) => in_body = true,
+                tag if in_body => errors.push(out_of_order(*tag)),
+                _ => (),
+            }
+        }
+
+        if self
+            .fields
+            .get_index_of(&10)
+            .is_some_and(|index| index + 1 != self.fields.len())
+        {
+            errors.push(out_of_order(10));
+        }
+        errors
+    }
+
+    /// The fields missing from the dictionary, e.g. custom tags (5000 and up) of the venue.
+    /// They pass through the engine unless `reject_undefined_tags=Y`.
+    pub fn undefined_tags(
+        &self,
+        fix_tag_number_map: &HashMap<u32, FixTag>,
+    ) -> Vec<ValidationError> {
+        self.fields
+            .keys()
+            .filter(|tag| !fix_tag_number_map.contains_key(tag))
+            .map(|tag| ValidationError::UndefinedTag {
+                tag: tag.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Why `FixMessage::validate` refused a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    MissingRequiredField { tag: String },
+    InvalidMsgType(String),
+    BadBodyLength(String),
+    BadChecksum(String),
+    InvalidEnum { tag: String, value: String },
+    WrongDataType { tag: String },
+    UndefinedTag { tag: String },
+    OutOfOrder { tag: String },
+}
+
+impl ValidationError {
+    /// The SessionRejectReason of a Reject refusing the message, as described in the
+    /// dictionary.
+    pub fn reject_reason(&self) -> &'static str {
+        match self {
+            ValidationError::MissingRequiredField { .. } => "REQUIRED_TAG_MISSING",
+            ValidationError::InvalidMsgType(_) => "INVALID_MSG_TYPE",
+            ValidationError::InvalidEnum { .. } => "VALUE_IS_INCORRECT",
+            ValidationError::UndefinedTag { .. } => "UNDEFINED_TAG",
+            ValidationError::OutOfOrder { .. } => "TAG_SPECIFIED_OUT_OF_REQUIRED_ORDER",
+            ValidationError::BadBodyLength(_)
+            | ValidationError::BadChecksum(_)
+            | ValidationError::WrongDataType { .. } => "INCORRECT_DATA_FORMAT_FOR_VALUE",
+        }
+    }
+
+    /// The RefTagID of a Reject refusing the message.
+    pub fn tag(&self) -> &str {
+        match self {
+            ValidationError::MissingRequiredField { tag }
+            | ValidationError::InvalidEnum { tag, .. }
+            | ValidationError::WrongDataType { tag }
+            | ValidationError::UndefinedTag { tag }
+            | ValidationError::OutOfOrder { tag } => tag,
+            ValidationError::InvalidMsgType(_) => "35",
+            ValidationError::BadBodyLength(_) => "9",
+            ValidationError::BadChecksum(_) => "10",
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::MissingRequiredField { tag } => {
+                write!(f, "Required field {} is missing or empty", tag)
+            }
+            ValidationError::InvalidMsgType(msg_type) => write!(f, "Invalid MsgType {}", msg_type),
+            ValidationError::BadBodyLength(value) => write!(f, "Invalid BodyLength {}", value),
+            ValidationError::BadChecksum(value) => write!(f, "Invalid CheckSum {}", value),
+            ValidationError::InvalidEnum { tag, value } => {
+                write!(f, "Value {} of field {} is not enumerated", value, tag)
+            }
+            ValidationError::WrongDataType { tag } => {
+                write!(f, "Value of field {} is not of its type", tag)
+            }
+            ValidationError::UndefinedTag { tag } => write!(f, "Tag {} is not defined", tag),
+            ValidationError::OutOfOrder { tag } => {
+                write!(f, "Tag {} is out of the required order", tag)
+            }
+        }
+    }
+}
+
+impl Error for ValidationError {}
+
+/// The problems of a message on one line, for the logs and the Text of a Reject.
+pub fn describe_errors(errors: &[ValidationError]) -> String {
+    errors
+        .iter()
+        .map(ValidationError::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Whether the value reads as the data type, an Int or a Float possibly negative.
+fn is_of_type(value: &str, data_type: &DataType) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    match data_type {
+        DataType::String => true,
+        DataType::Int => !digits.is_empty() && digits.bytes().all(|byte| byte.is_ascii_digit()),
+        DataType::Float => {
+            digits.bytes().any(|byte| byte.is_ascii_digit())
+                && digits
+                    .bytes()
+                    .all(|byte| byte.is_ascii_digit() || byte == b'.')
+                && digits.matches('.').count() <= 1
+        }
+        DataType::Char => value.chars().count() == 1,
+        DataType::Bool => value == "Y" || value == "N",
+    }
+}
+
+const SOH: u8 = 0x01;
+
+/// The header fields of an inbound message with the field of the session's own header they
+/// answer: the counterparty sends to our SenderSubID in its TargetSubID and so on.
+const ANSWERED_IDENTITY: [(&str, &str); 4] = [
+    ("SenderSubID", "TargetSubID"),
+    ("TargetSubID", "SenderSubID"),
+    ("DeliverToCompID", "OnBehalfOfCompID"),
+    ("DeliverToSubID", "OnBehalfOfSubID"),
+];
+
+/// Checks the SubIDs and on-behalf-of IDs of an inbound message against those the session
+/// sends, e.g. an ExecutionReport for another desk behind the same CompIDs. A field the session
+/// does not send, or the message lacks, is not checked. Returns the problem, or None.
+pub fn identity_problem(
+    msg_map: &IndexMap<String, String>,
+    header: &IndexMap<String, String>,
+) -> Option<String> {
+    ANSWERED_IDENTITY.iter().find_map(|(field, answered)| {
+        let value = msg_map.get(*field)?;
+        let expected = header.get(*answered)?;
+        (value != expected).then(|| format!("{} {} is not {}", field, value, expected))
+    })
+}
+
+/// Classifies a raw SOH-delimited message before any session processing. A message is garbled
+/// when BeginString (8), BodyLength (9) and MsgType (35) are not its first three fields, when
+/// BodyLength does not end on the CheckSum (10) field or when the CheckSum is wrong. Garbled
+/// messages are ignored without consuming an incoming MsgSeqNum. Returns the reason, or None
+/// for a well-formed message.
+pub fn garbled_reason(bytes: &[u8]) -> Option<String> {
+    // End (past the SOH) of the field starting at `start`
+    let field_end = |start: usize| {
+        bytes[start..]
+            .iter()
+            .position(|&byte| byte == SOH)
+            .map(|position| start + position + 1)
+    };
+
+    if !bytes.starts_with(b"8=") {
+        return Some("BeginString (8) is not the first field".to_string());
+    }
+    let Some(body_length_start) = field_end(0) else {
+        return Some("BeginString (8) is not terminated".to_string());
+    };
+    if !bytes[body_length_start..].starts_with(b"9=") {
+        return Some("BodyLength (9) is not the second field".to_string());
+    }
+    let Some(body_start) = field_end(body_length_start) else {
+        return Some("BodyLength (9) is not terminated".to_string());
+    };
+    let Some(body_length) = std::str::from_utf8(&bytes[body_length_start + 2..body_start - 1])
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+    else {
+        return Some("BodyLength (9) is not a number".to_string());
+    };
+    if !bytes[body_start..].starts_with(b"35=") {
+        return Some("MsgType (35) is not the third field".to_string());
+    }
+
+    // A BodyLength near usize::MAX must not wrap around to a small offset
+    let Some(checksum_start) = body_start
+        .checked_add(body_length)
+        .filter(|&checksum_start| checksum_start <= bytes.len())
+    else {
+        return Some(format!(
+            "BodyLength {} does not end at the CheckSum (10) field",
+            body_length
+        ));
+    };
+    if bytes[checksum_start - 1] != SOH
+        || !bytes[checksum_start..].starts_with(b"10=")
+    {
+        return Some(format!(
+            "BodyLength {} does not end at the CheckSum (10) field",
+            body_length
+        ));
+    }
+    let checksum = bytes
+        .get(checksum_start + 3..checksum_start + 7)
+        .filter(|field| field[3] == SOH)
+        .and_then(|field| std::str::from_utf8(&field[..3]).ok())
+        .and_then(|value| value.parse::<u32>().ok());
+    let expected = tag_value::checksum(&bytes[..checksum_start]) as u32;
+    match checksum {
+        Some(checksum) if checksum == expected => None,
+        Some(checksum) => Some(format!(
+            "CheckSum {:03} does not match the calculated {:03}",
+            checksum, expected
+        )),
+        None => Some("CheckSum (10) is not three digits".to_string()),
+    }
+}
+
+/// Checks the SendingTime of an inbound message is within `max_latency_seconds` of `now`,
+/// either way. A possible duplicate (PossDupFlag=Y) was sent some time ago, so its
+/// OrigSendingTime must instead be no later than its SendingTime. Returns the problem, or None.
+pub fn sending_time_problem(
+    msg_map: &IndexMap<String, String>,
+    now: DateTime<Utc>,
+    max_latency_seconds: u64,
+) -> Option<String> {
+    let sending_time = msg_map.get("SendingTime")?;
+    let sent = match parse_utc_timestamp("SendingTime", sending_time) {
+        Ok(sent) => sent,
+        Err(problem) => return Some(problem),
+    };
+
+    if matches!(msg_map.get("PossDupFlag").map(String::as_str), Some("Y" | "YES")) {
+        let Some(orig_sending_time) = msg_map.get("OrigSendingTime") else {
+            return Some("PossDupFlag=Y without OrigSendingTime".to_string());
+        };
+        return match parse_utc_timestamp("OrigSendingTime", orig_sending_time) {
+            Ok(orig_sent) if orig_sent > sent => Some(format!(
+                "OrigSendingTime {} is later than SendingTime {}",
+                orig_sending_time, sending_time
+            )),
+            Ok(_) => None,
+            Err(problem) => Some(problem),
+        };
     }
+
+    let latency = (now.naive_utc() - sent).num_milliseconds().unsigned_abs();
+    (latency > max_latency_seconds * 1000).then(|| {
+        format!(
+            "SendingTime {} is {} ms from our clock, more than {} s",
+            sending_time, latency, max_latency_seconds
+        )
+    })
+}
+
+fn parse_utc_timestamp(name: &str, value: &str) -> Result<NaiveDateTime, String> {
+    NaiveDateTime::parse_from_str(value, "%Y%m%d-%H:%M:%S%.f")
+        .map_err(|_| format!("{} {} is not a UTCTimestamp", name, value))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::message_converter::fixmsg2msgtype;
+    use crate::parse_xml::DataType;
+    use chrono::TimeZone;
     use std::collections::HashMap;
 
     fn create_test_msgtype_map() -> MsgTypeMap {
@@ -129,12 +592,23 @@ mod tests {
         let message = parsed.unwrap();
 
         // Validate fields in message
-        assert_eq!(message.fields.get("8").unwrap(), "FIX.4.4");
-        assert_eq!(message.fields.get("9").unwrap(), "65");
-        assert_eq!(message.fields.get("35").unwrap(), "D");
-        assert_eq!(message.fields.get("11").unwrap(), "12345");
-        assert_eq!(message.fields.get("55").unwrap(), "ABC");
-        assert_eq!(message.fields.get("10").unwrap(), "123");
+        assert_eq!(message.get(8).unwrap(), "FIX.4.4");
+        assert_eq!(message.get(9).unwrap(), "65");
+        assert_eq!(message.get(35).unwrap(), "D");
+        assert_eq!(message.get(11).unwrap(), "12345");
+        assert_eq!(message.get(55).unwrap(), "ABC");
+        assert_eq!(message.get(10).unwrap(), "123");
+    }
+
+    #[test]
+    fn test_parse_repeated_tag_keeps_last_value() {
+        let message = FixMessage::parse("8=FIX.4.4|35=D|448=A|447=D|448=B|447=P|").unwrap();
+        assert_eq!(message.get(448), Some("B"));
+        assert_eq!(message.get(447), Some("P"));
+        assert_eq!(message.to_fix(), "8=FIX.4.4|35=D|448=B|447=P|");
+        let (_, msg_map) = message.to_names(&HashMap::new());
+        assert_eq!(msg_map.get("448").map(String::as_str), Some("A"));
+        assert_eq!(msg_map.get("447").map(String::as_str), Some("D"));
     }
 
     #[test]
@@ -166,9 +640,10 @@ mod tests {
         let required_fields = vec!["8".to_string(), "9".to_string(), "35".to_string()];
         let valid_msg_types = vec!["D".to_string()];
         let msgtype_map = create_test_msgtype_map();
+        let tags = HashMap::new();
 
-        let is_valid = message.validate(&required_fields, &valid_msg_types, &msgtype_map);
-        assert!(is_valid);
+        let result = message.validate(&required_fields, &valid_msg_types, &msgtype_map, &tags);
+        assert_eq!(result, Ok(()));
     }
 
     #[test]
@@ -179,10 +654,16 @@ mod tests {
         // Define required and valid MsgTypes
         let required_fields = vec!["8".to_string(), "9".to_string(), "35".to_string()];
         let msgtype_map = create_test_msgtype_map();
+        let tags = HashMap::new();
         let valid_msg_types = vec!["D".to_string()];
 
-        let is_valid = message.validate(&required_fields, &valid_msg_types, &msgtype_map);
-        assert!(!is_valid);
+        let result = message.validate(&required_fields, &valid_msg_types, &msgtype_map, &tags);
+        assert_eq!(
+            result,
+            Err(vec![ValidationError::MissingRequiredField {
+                tag: "11".to_string()
+            }])
+        );
     }
 
     #[test]
@@ -193,10 +674,14 @@ mod tests {
         // Define required and valid MsgTypes
         let required_fields = vec!["8".to_string(), "9".to_string(), "35".to_string()];
         let msgtype_map = create_test_msgtype_map();
+        let tags = HashMap::new();
         let valid_msg_types = vec!["D".to_string()];
 
-        let is_valid = message.validate(&required_fields, &valid_msg_types, &msgtype_map);
-        assert!(!is_valid);
+        let result = message.validate(&required_fields, &valid_msg_types, &msgtype_map, &tags);
+        assert_eq!(
+            result,
+            Err(vec![ValidationError::InvalidMsgType("Z".to_string())])
+        );
     }
 
     #[test]
@@ -207,10 +692,14 @@ mod tests {
         // Define required and valid MsgTypes
         let required_fields = vec!["8".to_string(), "9".to_string(), "35".to_string()];
         let msgtype_map = create_test_msgtype_map();
+        let tags = HashMap::new();
         let valid_msg_types = vec!["C".to_string()];
 
-        let is_valid = message.validate(&required_fields, &valid_msg_types, &msgtype_map);
-        assert!(!is_valid);
+        let result = message.validate(&required_fields, &valid_msg_types, &msgtype_map, &tags);
+        assert_eq!(
+            result,
+            Err(vec![ValidationError::InvalidMsgType("C".to_string())])
+        );
     }
 
     #[test]
@@ -221,10 +710,14 @@ mod tests {
         // Define required and valid MsgTypes
         let required_fields = vec!["8".to_string(), "9".to_string(), "35".to_string()];
         let msgtype_map = create_test_msgtype_map();
+        let tags = HashMap::new();
         let valid_msg_types = vec!["D".to_string()];
 
-        let is_valid = message.validate(&required_fields, &valid_msg_types, &msgtype_map);
-        assert!(!is_valid);
+        let result = message.validate(&required_fields, &valid_msg_types, &msgtype_map, &tags);
+        assert_eq!(
+            result,
+            Err(vec![ValidationError::BadBodyLength("abc".to_string())])
+        );
     }
 
     #[test]
@@ -235,9 +728,284 @@ mod tests {
         // Define required fields and valid MsgTypes
         let required_fields = vec!["8".to_string(), "9".to_string(), "35".to_string()];
         let msgtype_map = create_test_msgtype_map();
+        let tags = HashMap::new();
         let valid_msg_types = vec!["D".to_string()];
 
-        let is_valid = message.validate(&required_fields, &valid_msg_types, &msgtype_map);
-        assert!(!is_valid);
+        let result = message.validate(&required_fields, &valid_msg_types, &msgtype_map, &tags);
+        let errors = result.unwrap_err();
+        assert_eq!(
+            errors,
+            vec![ValidationError::MissingRequiredField {
+                tag: "35".to_string()
+            }]
+        );
+        assert_eq!(errors[0].reject_reason(), "REQUIRED_TAG_MISSING");
+        assert_eq!(errors[0].tag(), "35");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_validate_field_values() {
+        let side = HashMap::from([("1".to_string(), "BUY".to_string())]);
+        let exec_inst = HashMap::from([
+            ("1".to_string(), "NOT_HELD".to_string()),
+            ("G".to_string(), "ALL_OR_NONE".to_string()),
+        ]);
+        let tags: HashMap<u32, FixTag> = [
+            (18, "ExecInst", DataType::String, Some(exec_inst)),
+            (38, "OrderQty", DataType::Float, None),
+            (44, "Price", DataType::Float, None),
+            (54, "Side", DataType::Char, Some(side)),
+            (59, "TimeInForce", DataType::Char, None),
+            (68, "TotNoOrders", DataType::Int, None),
+        ]
+        .into_iter()
+        .map(|(number, name, data_type, enum_values)| {
+            let tag = FixTag::new(number.to_string(), name.to_string(), data_type, enum_values);
+            (number, tag)
+        })
+        .collect();
+        let required_fields = vec!["35".to_string()];
+        let valid_msg_types = vec!["D".to_string()];
+        let msgtype_map = create_test_msgtype_map();
+        let validate = |raw_message: &str| {
+            FixMessage::parse(raw_message).unwrap().validate(
+                &required_fields,
+                &valid_msg_types,
+                &msgtype_map,
+                &tags,
+            )
+        };
+
+        let order = "35=D|11=1|55=IBM|54=1|38=100|44=-1.5|18=1 G|68=3|10=007|";
+        assert_eq!(validate(order), Ok(()));
+        let errors =
+            validate("35=D|11=1|55=IBM|54=7|38=1e5|44=1.2.3|59=00|68=x|10=7|").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::BadChecksum("7".to_string()),
+                ValidationError::InvalidEnum {
+                    tag: "54".to_string(),
+                    value: "7".to_string()
+                },
+                ValidationError::WrongDataType {
+                    tag: "38".to_string()
+                },
+                ValidationError::WrongDataType {
+                    tag: "44".to_string()
+                },
+                ValidationError::WrongDataType {
+                    tag: "59".to_string()
+                },
+                ValidationError::WrongDataType {
+                    tag: "68".to_string()
+                },
+            ]
+        );
+        assert_eq!(errors[1].reject_reason(), "VALUE_IS_INCORRECT");
+        assert_eq!(
+            FixMessage::parse("35=D|54=1|5001=DESK7|")
+                .unwrap()
+                .undefined_tags(&tags),
+            vec![
+                ValidationError::UndefinedTag {
+                    tag: "35".to_string()
+                },
+                ValidationError::UndefinedTag {
+                    tag: "5001".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            describe_errors(&errors[..2]),
+            "Invalid CheckSum 7; Value 7 of field 54 is not enumerated"
+        );
+    }
+
+    #[test]
+    fn test_validate_field_order() {
+        let tags: HashMap<u32, FixTag> = [(34, "MsgSeqNum"), (49, "SenderCompID"), (55, "Symbol")]
+            .into_iter()
+            .map(|(number, name)| {
+                let tag = FixTag::new(number.to_string(), name.to_string(), DataType::String, None);
+                (number, tag)
+            })
+            .collect();
+        let required_fields = vec!["35".to_string()];
+        let valid_msg_types = vec!["D".to_string()];
+        let msgtype_map = create_test_msgtype_map();
+        let validate = |raw_message: &str| {
+            FixMessage::parse(raw_message).unwrap().validate(
+                &required_fields,
+                &valid_msg_types,
+                &msgtype_map,
+                &tags,
+            )
+        };
+        let out_of_order = |tags: &[&str]| {
+            Err(tags
+                .iter()
+                .map(|tag| ValidationError::OutOfOrder {
+                    tag: tag.to_string(),
+                })
+                .collect())
+        };
+
+        assert_eq!(validate("8=FIX.4.2|9=5|35=D|49=S|34=2|11=1|55=IBM|10=000|"), Ok(()));
+        assert_eq!(
+            validate("8=FIX.4.2|35=D|9=5|49=S|11=1|55=IBM|10=000|"),
+            out_of_order(&["9", "35"])
+        );
+        assert_eq!(
+            validate("35=D|49=S|11=1|34=2|55=IBM|10=000|5001=X|"),
+            out_of_order(&["34", "10"])
+        );
+        let errors = validate("35=D|11=1|55=IBM|49=S|").unwrap_err();
+        assert_eq!(errors[0].reject_reason(), "TAG_SPECIFIED_OUT_OF_REQUIRED_ORDER");
+        assert_eq!(errors[0].tag(), "49");
+    }
+
+    #[test]
+    fn test_garbled_reason() {
+        let body = "35=0\x0149=FIX_Engine\x0156=XYZExchange\x0134=2\x01";
+        let head = format!("8=FIX.4.2\x019={}\x01", body.len());
+        let checksum = format!("{}{}", head, body).bytes().map(u32::from).sum::<u32>() % 256;
+        let message = format!("{}{}10={:03}\x01", head, body, checksum);
+        assert_eq!(garbled_reason(message.as_bytes()), None);
+        // Data after the CheckSum field (e.g. the next message) does not make it garbled
+        assert_eq!(garbled_reason(format!("{}8=FIX.4.2\x01", message).as_bytes()), None);
+
+        let bad_checksum = format!("{}{}10={:03}\x01", head, body, (checksum + 1) % 256);
+        assert!(garbled_reason(bad_checksum.as_bytes()).unwrap().starts_with("CheckSum"));
+        let bad_length = message.replacen(&format!("9={}", body.len()), "9=99", 1);
+        assert!(garbled_reason(bad_length.as_bytes()).unwrap().contains("does not end"));
+        let no_msgtype = message.replacen("35=0", "36=0", 1);
+        assert!(garbled_reason(no_msgtype.as_bytes()).unwrap().starts_with("MsgType"));
+        assert!(garbled_reason(b"9=5\x0135=0\x01").is_some());
+        let huge_length = format!("8=FIX.4.2\x019={}\x0135=0\x0110=000\x01", usize::MAX);
+        assert!(garbled_reason(huge_length.as_bytes()).unwrap().contains("does not end"));
+        assert!(garbled_reason(&message.as_bytes()[..message.len() - 8]).is_some());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let tag = |number: &str, name: &str, enums: &[(&str, &str)], by_value: bool| {
+            let enum_values = (!enums.is_empty()).then(|| {
+                enums
+                    .iter()
+                    .map(|(value, description)| match by_value {
+                        true => (value.to_string(), description.to_string()),
+                        false => (description.to_string(), value.to_string()),
+                    })
+                    .collect()
+            });
+            FixTag::new(number.to_string(), name.to_string(), DataType::String, enum_values)
+        };
+        let tags = |by_value: bool| {
+            vec![
+                tag("8", "BeginString", &[], by_value),
+                tag("35", "MsgType", &[("D", "NEW_ORDER_SINGLE")], by_value),
+                tag("54", "Side", &[("1", "BUY"), ("2", "SELL")], by_value),
+                tag("38", "OrderQty", &[], by_value),
+            ]
+        };
+        let number_map: HashMap<u32, FixTag> = tags(true)
+            .into_iter()
+            .map(|tag| (tag.number.parse().unwrap(), tag))
+            .collect();
+        let name_map: HashMap<String, FixTag> =
+            tags(false).into_iter().map(|tag| (tag.name.clone(), tag)).collect();
+
+        let message = FixMessage::parse("8=FIX.4.2|35=D|54=1|38=100|5001=X|").unwrap();
+        let json = message.to_json(&number_map);
+        assert_eq!(
+            json,
+            r#"{"BeginString":"FIX.4.2","MsgType":"NEW_ORDER_SINGLE","Side":"BUY","OrderQty":"100","5001":"X"}"#
+        );
+        let parsed = FixMessage::from_json(&json, &name_map).unwrap();
+        assert_eq!(parsed.to_fix(), "8=FIX.4.2|35=D|54=1|38=100|5001=X|");
+
+        // Validated with the last value of a repeated tag, named with its first as
+        // fixmsg2msgtype names it
+        let message = FixMessage::parse("8=FIX.4.2\x0135=D\x0154=1\x0154=2\x015001=X\x01").unwrap();
+        assert_eq!(message.get(54), Some("2"));
+        assert_eq!(message.described(54, &number_map), Some("SELL"));
+        assert_eq!(message.described(35, &number_map), Some("NEW_ORDER_SINGLE"));
+        assert_eq!(message.described(5001, &number_map), Some("X"));
+        assert_eq!(message.described(11, &number_map), None);
+        let (msgtype, msg_map) = message.to_names(&number_map);
+        assert_eq!(msgtype, "NEW_ORDER_SINGLE");
+        assert_eq!(
+            (msgtype, msg_map),
+            fixmsg2msgtype("8=FIX.4.2|35=D|54=1|54=2|5001=X|", &number_map).unwrap()
+        );
+
+        let parsed =
+            FixMessage::from_json(r#"{"Side": "sell", "OrderQty": 50}"#, &name_map).unwrap();
+        assert_eq!(parsed.to_fix(), "54=2|38=50|");
+        assert!(FixMessage::from_json(r#"{"Unknown": "1"}"#, &name_map).is_err());
+        assert!(FixMessage::from_json("[]", &name_map).is_err());
+    }
+
+    #[test]
+    fn test_identity_problem() {
+        let fields = |fields: &[(&str, &str)]| -> IndexMap<String, String> {
+            fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        let header = fields(&[("SenderSubID", "DESK1"), ("OnBehalfOfCompID", "FUND")]);
+
+        let report = fields(&[("TargetSubID", "DESK1"), ("DeliverToCompID", "FUND")]);
+        assert_eq!(identity_problem(&report, &header), None);
+        assert_eq!(identity_problem(&fields(&[("SenderSubID", "X")]), &header), None);
+        assert_eq!(
+            identity_problem(&fields(&[("TargetSubID", "DESK2")]), &header),
+            Some("TargetSubID DESK2 is not DESK1".to_string())
+        );
+        assert!(identity_problem(&fields(&[("DeliverToCompID", "OTHER")]), &header).is_some());
+    }
+
+    #[test]
+    fn test_sending_time_problem() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let sent = |sending_time: &str| -> IndexMap<String, String> {
+            [("SendingTime".to_string(), sending_time.to_string())].into()
+        };
+        assert_eq!(sending_time_problem(&sent("20240501-11:59:58.500"), now, 2), None);
+        assert_eq!(sending_time_problem(&sent("20240501-12:00:02"), now, 2), None);
+        assert!(sending_time_problem(&sent("20240501-11:59:57.999"), now, 2).is_some());
+        assert!(sending_time_problem(&sent("20240501-12:00:30"), now, 2).is_some());
+        assert!(sending_time_problem(&sent("yesterday"), now, 2).is_some());
+        assert_eq!(sending_time_problem(&IndexMap::new(), now, 2), None);
+    }
+
+    #[test]
+    fn test_sending_time_problem_of_possible_duplicate() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let resent = |orig_sending_time: Option<&str>| -> IndexMap<String, String> {
+            let mut msg_map: IndexMap<String, String> = [
+                ("SendingTime".to_string(), "20240501-11:00:00".to_string()),
+                ("PossDupFlag".to_string(), "Y".to_string()),
+            ]
+            .into();
+            if let Some(orig_sending_time) = orig_sending_time {
+                msg_map.insert("OrigSendingTime".to_string(), orig_sending_time.to_string());
+            }
+            msg_map
+        };
+        // An hour old, but resent: only the order of the two timestamps matters
+        assert_eq!(
+            sending_time_problem(&resent(Some("20240501-10:30:00")), now, 2),
+            None
+        );
+        assert_eq!(
+            sending_time_problem(&resent(Some("20240501-11:00:00")), now, 2),
+            None
+        );
+        assert!(sending_time_problem(&resent(Some("20240501-11:00:01")), now, 2).is_some());
+        assert!(sending_time_problem(&resent(Some("earlier")), now, 2).is_some());
+        assert!(sending_time_problem(&resent(None), now, 2).is_some());
+    }
+}
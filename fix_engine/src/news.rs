@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::io;
+
+use chrono::Utc;
+use log::{error, info};
+
+use crate::admin_http::{AdminSession, ADMIN_SESSIONS};
+use crate::message_converter::{append_fields, msgtype2fixmsg, repeating_group};
+use crate::message_handling::send_sequenced;
+
+/// Fields of a LinesOfText entry besides Text(58), which starts it.
+const LINE_MEMBER_TAGS: [u32; 2] = [354, 355];
+
+/// A News (35=B), or an Email (35=C) with its Subject as the headline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct News {
+    pub headline: String,
+    /// The LinesOfText, at least one.
+    pub lines: Vec<String>,
+}
+
+impl News {
+    /// News with the lines of text, the headline alone when there are none.
+    pub fn new(headline: &str, lines: Vec<String>) -> Self {
+        let lines = if lines.is_empty() {
+            vec![headline.to_string()]
+        } else {
+            lines
+        };
+        News {
+            headline: headline.to_string(),
+            lines,
+        }
+    }
+
+    /// Reads the Headline, or the Subject of an Email, and the LinesOfText from the raw
+    /// message.
+    pub fn parse(message: &str) -> Result<Self, String> {
+        let fields: HashMap<&str, &str> = message
+            .split(['\x01', '|'])
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        let headline = fields
+            .get("148")
+            .or_else(|| fields.get("147"))
+            .ok_or_else(|| "Missing Headline".to_string())?
+            .to_string();
+        let lines = repeating_group(message, 33, 58, &LINE_MEMBER_TAGS)?
+            .into_iter()
+            .filter_map(|mut entry| entry.remove(&58))
+            .collect();
+        Ok(News { headline, lines })
+    }
+
+    /// Override fields of the News template. Its LinesOfText group is added with
+    /// `lines_of_text_group`.
+    pub fn fields(&self) -> HashMap<String, String> {
+        HashMap::from([
+            ("Headline".to_string(), self.headline.clone()),
+            (
+                "OrigTime".to_string(),
+                Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string(),
+            ),
+        ])
+    }
+
+    /// The LinesOfText group, as tag and value pairs.
+    pub fn lines_of_text_group(&self) -> Vec<(u32, String)> {
+        let mut group = vec![(33, self.lines.len().to_string())];
+        group.extend(self.lines.iter().map(|line| (58, line.clone())));
+        group
+    }
+}
+
+fn send_news(session: &AdminSession, news: &News) -> io::Result<()> {
+    let message = msgtype2fixmsg(
+        "News".to_string(),
+        &session.message_maps.app_msg.current(),
+        &session.message_maps.fix_tag_name_map,
+        Some(&news.fields()),
+        session.seq_store.get_outgoing(),
+    );
+    let message = append_fields(&message, &news.lines_of_text_group());
+    send_sequenced(&session.stream, &session.seq_store, message.replace("|", "\x01"))
+}
+
+/// Sends the News to every running session. Returns the number of sessions it was sent to.
+pub fn broadcast_news(news: &News) -> usize {
+    info!("News {}: {}", news.headline, news.lines.join(" / "));
+    let mut sent = 0;
+    for session in ADMIN_SESSIONS.all() {
+        match send_news(&session, news) {
+            Ok(()) => sent += 1,
+            Err(e) => error!("Failed to send the News to {}: {}", session.session_id, e),
+        }
+    }
+    sent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_news_lines_of_text() {
+        let news =
+            News::parse("8=FIX.4.2|9=0|35=B|148=Open|33=2|58=Market open|58=Good luck|10=000|")
+                .unwrap();
+        assert_eq!(
+            news,
+            News::new(
+                "Open",
+                vec!["Market open".to_string(), "Good luck".to_string()]
+            )
+        );
+        assert_eq!(
+            news.lines_of_text_group(),
+            vec![
+                (33, "2".to_string()),
+                (58, "Market open".to_string()),
+                (58, "Good luck".to_string())
+            ]
+        );
+        let email =
+            News::parse("8=FIX.4.2|9=0|35=C|164=T1|94=0|147=Fills|33=1|58=Done|10=000|").unwrap();
+        assert_eq!(email.headline, "Fills");
+        assert_eq!(
+            News::new("Halt", Vec::new()).lines,
+            vec!["Halt".to_string()]
+        );
+        assert!(News::parse("8=FIX.4.2|9=0|35=B|148=Open|33=2|58=Market open|10=000|").is_err());
+    }
+}
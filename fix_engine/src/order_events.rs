@@ -0,0 +1,430 @@
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use indexmap::IndexMap;
+use log::{error, info};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::orderstore::Order;
+
+lazy_static! {
+    pub static ref ORDER_EVENTS: OrderEventDispatcher = OrderEventDispatcher::new();
+}
+
+/// Normalized kind of an order lifecycle transition.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderEventKind {
+    Accepted,
+    Filled,
+    Canceled,
+    Replaced,
+    Rejected,
+}
+
+/// Order lifecycle event shared by every integration, so none of them has to
+/// re-parse ExecutionReports on its own.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct OrderEvent {
+    pub kind: OrderEventKind,
+    pub cl_ord_id: String,
+    pub orig_cl_ord_id: Option<String>,
+    pub order_id: Option<String>,
+    pub exec_id: Option<String>,
+    pub account: String,
+    pub symbol: String,
+    pub side: String,
+    pub order_qty: Option<String>,
+    pub price: Option<String>,
+    pub last_qty: Option<String>,
+    pub last_px: Option<String>,
+    pub cum_qty: Option<String>,
+    pub leaves_qty: Option<String>,
+    pub avg_px: Option<String>,
+    pub text: Option<String>,
+    pub transact_time: Option<String>,
+}
+
+impl OrderEvent {
+    /// Builds an event from a parsed order message (NewOrderSingle, cancel or replace request).
+    pub fn from_order_message(kind: OrderEventKind, msg_map: &IndexMap<String, String>) -> Self {
+        let get = |key: &str| msg_map.get(key).cloned();
+        OrderEvent {
+            kind,
+            cl_ord_id: get("ClOrdID").unwrap_or_default(),
+            orig_cl_ord_id: get("OrigClOrdID"),
+            order_id: get("OrderID"),
+            exec_id: get("ExecID"),
+            account: get("Account").unwrap_or_default(),
+            symbol: get("Symbol").unwrap_or_default(),
+            side: get("Side").unwrap_or_default(),
+            order_qty: get("OrderQty"),
+            price: get("Price"),
+            last_qty: None,
+            last_px: None,
+            cum_qty: None,
+            leaves_qty: None,
+            avg_px: None,
+            text: get("Text"),
+            transact_time: get("TransactTime"),
+        }
+    }
+
+    /// Builds an event from an order held in the order store.
+    pub fn from_order(kind: OrderEventKind, order: &Order) -> Self {
+        let msg_map: IndexMap<String, String> = [
+            ("ClOrdID", order.id.clone()),
+            ("Account", order.account.clone()),
+            ("Symbol", order.symbol.clone()),
+            ("Side", order.side.clone()),
+            ("OrderQty", order.quantity.to_string()),
+            ("Price", order.price_text()),
+            ("TransactTime", order.transacttime.clone()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value))
+        .collect();
+        OrderEvent::from_order_message(kind, &msg_map)
+    }
+
+    /// The fill of an order held in the order store, as reported to its owner.
+    pub fn from_fill(order: &Order, exec_id: &str, last_qty: Decimal, last_px: Decimal) -> Self {
+        let mut event = OrderEvent::from_order(OrderEventKind::Filled, order);
+        event.order_id = Some(order.order_id.clone()).filter(|order_id| !order_id.is_empty());
+        event.exec_id = Some(exec_id.to_string());
+        event.last_qty = Some(last_qty.to_string());
+        event.last_px = Some(last_px.to_string());
+        event.cum_qty = Some(order.cum_qty.to_string());
+        event.leaves_qty = Some(order.leaves_qty().to_string());
+        event.avg_px = Some(order.avg_px.to_string());
+        event
+    }
+
+    /// Normalizes a parsed ExecutionReport. Returns None for ExecTypes which are
+    /// not order lifecycle transitions (pending states, restatements, ...).
+    pub fn from_execution_report(msg_map: &IndexMap<String, String>) -> Option<Self> {
+        let exec_type = msg_map
+            .get("ExecType")
+            .or_else(|| msg_map.get("OrdStatus"))?;
+        let kind = match exec_type.as_str() {
+            "0" | "NEW" => OrderEventKind::Accepted,
+            "1" | "2" | "F" | "PARTIAL_FILL" | "FILL" | "PARTIALLY_FILLED" | "FILLED"
+            | "TRADE" => OrderEventKind::Filled,
+            "4" | "CANCELED" => OrderEventKind::Canceled,
+            "5" | "REPLACED" => OrderEventKind::Replaced,
+            "8" | "REJECTED" => OrderEventKind::Rejected,
+            _ => return None,
+        };
+
+        let mut event = OrderEvent::from_order_message(kind, msg_map);
+        let get = |key: &str| msg_map.get(key).cloned();
+        // FIX 4.2 names the fill quantity LastShares, FIX 4.4 renamed it LastQty
+        event.last_qty = get("LastQty").or_else(|| get("LastShares"));
+        event.last_px = get("LastPx");
+        event.cum_qty = get("CumQty");
+        event.leaves_qty = get("LeavesQty");
+        event.avg_px = get("AvgPx");
+        Some(event)
+    }
+}
+
+/// A consumer of normalized order events (blotter, webhook, position tracker, ...).
+pub trait OrderEventSink: Send + Sync {
+    fn name(&self) -> &str;
+    fn on_event(&self, event: &OrderEvent) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+/// Fans every published event out to all registered sinks.
+pub struct OrderEventDispatcher {
+    sinks: RwLock<Vec<Box<dyn OrderEventSink>>>,
+}
+
+impl OrderEventDispatcher {
+    pub fn new() -> Self {
+        Self {
+            sinks: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn register(&self, sink: Box<dyn OrderEventSink>) {
+        info!("Registered order event sink: {}", sink.name());
+        self.sinks.write().unwrap().push(sink);
+    }
+
+    /// Delivers the event to every sink. A failing sink is logged and does not
+    /// prevent delivery to the others.
+    pub fn publish(&self, event: &OrderEvent) {
+        let sinks = self.sinks.read().unwrap();
+        for sink in sinks.iter() {
+            if let Err(e) = sink.on_event(event) {
+                error!("Order event sink {} failed: {}", sink.name(), e);
+            }
+        }
+    }
+}
+
+/// Prints a one-line summary of every event to the application log.
+pub struct BlotterSink;
+
+impl OrderEventSink for BlotterSink {
+    fn name(&self) -> &str {
+        "blotter"
+    }
+
+    fn on_event(&self, event: &OrderEvent) -> Result<(), Box<dyn std::error::Error>> {
+        info!(
+            "[BLOTTER] {:?} ClOrdID={} Account={} Symbol={} Side={} Qty={} Price={} LastQty={} LastPx={} CumQty={} LeavesQty={}",
+            event.kind,
+            event.cl_ord_id,
+            event.account,
+            event.symbol,
+            event.side,
+            event.order_qty.as_deref().unwrap_or("-"),
+            event.price.as_deref().unwrap_or("-"),
+            event.last_qty.as_deref().unwrap_or("-"),
+            event.last_px.as_deref().unwrap_or("-"),
+            event.cum_qty.as_deref().unwrap_or("-"),
+            event.leaves_qty.as_deref().unwrap_or("-"),
+        );
+        Ok(())
+    }
+}
+
+/// Events the webhook may fall behind by before those published next are dropped.
+pub const WEBHOOK_QUEUE_SIZE: usize = 1024;
+
+/// How long connecting to the webhook, sending an event or waiting for its answer may take.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// POSTs every event as JSON to an HTTP endpoint. Requests are sent from a
+/// background thread so a slow endpoint never blocks message handling; once
+/// `queue_size` events wait for it the next ones are dropped and counted.
+pub struct WebhookSink {
+    url: String,
+    sender: Mutex<SyncSender<String>>,
+    /// Events dropped so far because the queue was full.
+    dropped: AtomicU64,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str, queue_size: usize) -> io::Result<Self> {
+        let (host, port, path) = parse_http_url(url)?;
+        let (sender, receiver) = mpsc::sync_channel::<String>(queue_size);
+        thread::spawn(move || {
+            for body in receiver {
+                if let Err(e) = http_post(&host, port, &path, &body) {
+                    error!("Webhook POST to {}:{}{} failed: {}", host, port, path, e);
+                }
+            }
+        });
+        Ok(Self {
+            url: url.to_string(),
+            sender: Mutex::new(sender),
+            dropped: AtomicU64::new(0),
+        })
+    }
+}
+
+impl OrderEventSink for WebhookSink {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    fn on_event(&self, event: &OrderEvent) -> Result<(), Box<dyn std::error::Error>> {
+        let body = serde_json::to_string(event)?;
+        match self.sender.lock().unwrap().try_send(body) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                Err(format!("Webhook queue full, {} events dropped so far", dropped).into())
+            }
+            Err(TrySendError::Disconnected(_)) => Err("Webhook thread stopped".into()),
+        }
+    }
+}
+
+/// Splits a plain `http://host[:port][/path]` URL into its parts.
+fn parse_http_url(url: &str) -> io::Result<(String, u16, String)> {
+    let rest = url.strip_prefix("http://").ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Only http:// webhook URLs are supported: {}", url),
+        )
+    })?;
+    let (authority, path) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], rest[idx..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host.to_string(),
+            port.parse()
+                .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?,
+        ),
+        None => (authority.to_string(), 80),
+    };
+    Ok((host, port, path))
+}
+
+fn http_post(host: &str, port: u16, path: &str, body: &str) -> io::Result<()> {
+    let address = (host, port).to_socket_addrs()?.next().ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            format!("No address for {}:{}", host, port),
+        )
+    })?;
+    let mut stream = TcpStream::connect_timeout(&address, WEBHOOK_TIMEOUT)?;
+    stream.set_read_timeout(Some(WEBHOOK_TIMEOUT))?;
+    stream.set_write_timeout(Some(WEBHOOK_TIMEOUT))?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(Error::other(format!(
+            "Webhook answered {:?}",
+            status_line.trim_end()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    struct CollectingSink {
+        events: Arc<Mutex<Vec<OrderEvent>>>,
+    }
+
+    impl OrderEventSink for CollectingSink {
+        fn name(&self) -> &str {
+            "collector"
+        }
+
+        fn on_event(&self, event: &OrderEvent) -> Result<(), Box<dyn std::error::Error>> {
+            self.events.lock().unwrap().push(event.clone());
+            Ok(())
+        }
+    }
+
+    struct FailingSink;
+
+    impl OrderEventSink for FailingSink {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        fn on_event(&self, _event: &OrderEvent) -> Result<(), Box<dyn std::error::Error>> {
+            Err("sink unavailable".into())
+        }
+    }
+
+    fn execution_report(exec_type: &str) -> IndexMap<String, String> {
+        let mut msg_map = IndexMap::new();
+        msg_map.insert("MsgType".to_string(), "EXECUTION_REPORT".to_string());
+        msg_map.insert("OrderID".to_string(), "ORD1".to_string());
+        msg_map.insert("ClOrdID".to_string(), "1001".to_string());
+        msg_map.insert("ExecType".to_string(), exec_type.to_string());
+        msg_map.insert("Symbol".to_string(), "IBM".to_string());
+        msg_map.insert("Side".to_string(), "BUY".to_string());
+        msg_map.insert("LastShares".to_string(), "50".to_string());
+        msg_map.insert("LastPx".to_string(), "101".to_string());
+        msg_map.insert("CumQty".to_string(), "50".to_string());
+        msg_map.insert("LeavesQty".to_string(), "50".to_string());
+        msg_map
+    }
+
+    #[test]
+    fn test_from_execution_report_partial_fill() {
+        let event = OrderEvent::from_execution_report(&execution_report("PARTIAL_FILL")).unwrap();
+        assert_eq!(event.kind, OrderEventKind::Filled);
+        assert_eq!(event.cl_ord_id, "1001");
+        assert_eq!(event.order_id.as_deref(), Some("ORD1"));
+        assert_eq!(event.last_qty.as_deref(), Some("50"));
+        assert_eq!(event.leaves_qty.as_deref(), Some("50"));
+    }
+
+    #[test]
+    fn test_from_execution_report_raw_codes() {
+        assert_eq!(
+            OrderEvent::from_execution_report(&execution_report("4")).unwrap().kind,
+            OrderEventKind::Canceled
+        );
+        assert_eq!(
+            OrderEvent::from_execution_report(&execution_report("8")).unwrap().kind,
+            OrderEventKind::Rejected
+        );
+    }
+
+    #[test]
+    fn test_from_execution_report_ignores_pending_states() {
+        assert!(OrderEvent::from_execution_report(&execution_report("PENDING_NEW")).is_none());
+    }
+
+    #[test]
+    fn test_dispatcher_delivers_to_all_sinks() {
+        let dispatcher = OrderEventDispatcher::new();
+        let first = Arc::new(Mutex::new(Vec::new()));
+        let second = Arc::new(Mutex::new(Vec::new()));
+        dispatcher.register(Box::new(CollectingSink {
+            events: Arc::clone(&first),
+        }));
+        dispatcher.register(Box::new(FailingSink));
+        dispatcher.register(Box::new(CollectingSink {
+            events: Arc::clone(&second),
+        }));
+
+        let event = OrderEvent::from_execution_report(&execution_report("FILL")).unwrap();
+        dispatcher.publish(&event);
+
+        assert_eq!(first.lock().unwrap().len(), 1);
+        assert_eq!(first.lock().unwrap()[0], event);
+        assert_eq!(second.lock().unwrap().len(), 1);
+        assert_eq!(second.lock().unwrap()[0], event);
+    }
+
+    #[test]
+    fn test_webhook_drops_events_once_its_queue_is_full() {
+        // Accepts the POSTs without ever answering them
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/orders", listener.local_addr().unwrap());
+        let sink = WebhookSink::new(&url, 1).unwrap();
+        let event = OrderEvent::from_execution_report(&execution_report("FILL")).unwrap();
+
+        // One event in flight at most, one queued, the rest dropped
+        let results: Vec<_> = (0..5).map(|_| sink.on_event(&event).is_ok()).collect();
+        assert!(!results[4]);
+        assert!(sink.dropped.load(Ordering::Relaxed) >= 3);
+        drop(listener);
+    }
+
+    #[test]
+    fn test_parse_http_url() {
+        let (host, port, path) = parse_http_url("http://127.0.0.1:8080/orders").unwrap();
+        assert_eq!(host, "127.0.0.1");
+        assert_eq!(port, 8080);
+        assert_eq!(path, "/orders");
+
+        let (host, port, path) = parse_http_url("http://example.com").unwrap();
+        assert_eq!(host, "example.com");
+        assert_eq!(port, 80);
+        assert_eq!(path, "/");
+
+        assert!(parse_http_url("https://example.com").is_err());
+    }
+}
@@ -0,0 +1,276 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use chrono::Utc;
+use indexmap::IndexMap;
+use log::{error, info};
+use rust_decimal::Decimal;
+use serde_json::{json, Value};
+
+use crate::admin_http::{order_query, AdminSession, ADMIN_SESSIONS};
+use crate::console::{next_cl_ord_id, order_message, parse_order, OrderSpec};
+use crate::http_server::{self, HttpRequest, HttpResponse};
+use crate::orderstore::{add_order_to_store, OrdStatus, Order, OrderKey, OrderStore};
+use crate::session::Session;
+
+/// Serves the REST order gateway on its own thread, sending orders over the running initiator
+/// session:
+///
+/// - `POST /orders` with `{"side": "buy", "quantity": 100, "symbol": "IBM", "price": "187.5",
+///   "ord_type": "limit", "time_in_force": "day", "account": "ACC1"}`, the fields of the
+///   console's `buy 100 IBM @ 187.5 limit day account=ACC1`: sends a NewOrderSingle and
+///   answers its ClOrdID
+/// - `DELETE /orders/<ClOrdID>`: sends an OrderCancelRequest for the open order
+/// - `GET /orders`, optionally `?symbol=`, `?account=` or `?status=` as in the admin API, and
+///   `GET /orders/<ClOrdID>`: the orders sent, updated by their ExecutionReports
+pub fn start_order_gateway(address: SocketAddr) -> io::Result<()> {
+    http_server::serve("REST order gateway", address, handle_request).map(|_| ())
+}
+
+fn handle_request(request: &HttpRequest) -> HttpResponse {
+    let (status, body) = route(&request.method, &request.path, &request.body);
+    HttpResponse::json(status, body)
+}
+
+fn route(method: &str, path: &str, body: &str) -> (&'static str, Value) {
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    // The initiator runs one session at a time
+    let Some(session) = ADMIN_SESSIONS.all().into_iter().next() else {
+        return (
+            "503 Service Unavailable",
+            json!({ "error": "No session running" }),
+        );
+    };
+    match (method, segments.as_slice()) {
+        ("GET", ["orders"]) => match order_query(query) {
+            Ok(query) => ("200 OK", json!(session.order_store.query(&query))),
+            Err(e) => ("400 Bad Request", json!({ "error": e })),
+        },
+        ("GET", ["orders", cl_ord_id]) => match session
+            .order_store
+            .get_order(&OrderKey::own(cl_ord_id))
+        {
+            Some(order) => ("200 OK", json!(order)),
+            None => ("404 Not Found", json!({ "error": "Unknown order" })),
+        },
+        ("POST", ["orders"]) => {
+            let spec = serde_json::from_str(body)
+                .map_err(|e| e.to_string())
+                .and_then(|request| order_spec(&request));
+            match spec {
+                Ok(spec) => match send_order(&session, &spec) {
+                    Ok(cl_ord_id) => ("201 Created", json!({ "cl_ord_id": cl_ord_id })),
+                    Err(e) => ("502 Bad Gateway", json!({ "error": e.to_string() })),
+                },
+                Err(e) => ("400 Bad Request", json!({ "error": e })),
+            }
+        }
+        ("DELETE", ["orders", cl_ord_id]) => match session
+            .order_store
+            .get_order(&OrderKey::own(cl_ord_id))
+        {
+            Some(order) if order.is_open() => match send_cancel(&session, &order_cancel(&order)) {
+                Ok(cl_ord_id) => ("202 Accepted", json!({ "cl_ord_id": cl_ord_id })),
+                Err(e) => ("502 Bad Gateway", json!({ "error": e.to_string() })),
+            },
+            Some(_) => (
+                "409 Conflict",
+                json!({ "error": "Order is no longer open" }),
+            ),
+            None => ("404 Not Found", json!({ "error": "Unknown order" })),
+        },
+        (_, ["orders", ..]) => (
+            "405 Method Not Allowed",
+            json!({ "error": "Method not allowed" }),
+        ),
+        _ => ("404 Not Found", json!({ "error": "Not found" })),
+    }
+}
+
+/// The order of a `POST /orders` request, checked like the console's order commands.
+pub fn order_spec(request: &Value) -> Result<OrderSpec, String> {
+    let field = |name: &str| match request.get(name) {
+        Some(Value::String(value)) => Some(value.clone()),
+        Some(Value::Number(value)) => Some(value.to_string()),
+        _ => None,
+    };
+    let side = field("side")
+        .map(|side| side.to_lowercase())
+        .filter(|side| matches!(side.as_str(), "buy" | "sell" | "short"))
+        .ok_or("Expected side buy, sell or short")?;
+    let mut words = vec![
+        field("quantity").ok_or("Missing quantity")?,
+        field("symbol").ok_or("Missing symbol")?,
+    ];
+    if let Some(price) = field("price") {
+        words.push(format!("@{}", price));
+    }
+    words.extend(field("ord_type"));
+    words.extend(field("time_in_force"));
+    words.extend(field("account").map(|account| format!("account={}", account)));
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    parse_order(&side, &words)
+}
+
+fn session_of(admin_session: &AdminSession) -> Session {
+    Session::new(
+        admin_session.session_id.clone(),
+        Arc::clone(&admin_session.stream),
+        Arc::clone(&admin_session.message_maps),
+        Arc::clone(&admin_session.seq_store),
+    )
+}
+
+/// Sends the NewOrderSingle and keeps the order in the store. Returns its ClOrdID.
+pub fn send_order(admin_session: &AdminSession, spec: &OrderSpec) -> io::Result<String> {
+    let session = session_of(admin_session);
+    let template = session
+        .all_msg_map_collection
+        .app_msg
+        .current()
+        .get("New_Order_Single")
+        .cloned()
+        .unwrap_or_default();
+    let msg_map = order_message(spec, &template);
+    session.send_batch(vec![msg_map.clone()])?;
+    let cl_ord_id = msg_map["ClOrdID"].clone();
+    info!("Gateway sent order {}", cl_ord_id);
+    let mut stored = msg_map;
+    stored.insert("OrdStatus".to_string(), OrdStatus::New.as_str().to_string());
+    if let Err(e) = add_order_to_store(Arc::clone(&admin_session.order_store), "", &stored) {
+        error!("Order {} sent but not stored: {}", cl_ord_id, e);
+    }
+    Ok(cl_ord_id)
+}
+
+/// The OrderCancelRequest fields of an order, under a new ClOrdID.
+pub fn order_cancel(order: &Order) -> IndexMap<String, String> {
+    IndexMap::from([
+        ("MsgType".to_string(), "F".to_string()),
+        ("OrigClOrdID".to_string(), order.id.clone()),
+        ("ClOrdID".to_string(), next_cl_ord_id()),
+        ("Symbol".to_string(), order.symbol.clone()),
+        ("Side".to_string(), order.side.clone()),
+        ("OrderQty".to_string(), order.quantity.to_string()),
+        (
+            "TransactTime".to_string(),
+            Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string(),
+        ),
+    ])
+}
+
+/// Sends the OrderCancelRequest. Returns its ClOrdID.
+pub fn send_cancel(
+    admin_session: &AdminSession,
+    msg_map: &IndexMap<String, String>,
+) -> io::Result<String> {
+    session_of(admin_session).send_batch(vec![msg_map.clone()])?;
+    info!(
+        "Gateway sent cancel {} of order {}",
+        msg_map["ClOrdID"], msg_map["OrigClOrdID"]
+    );
+    Ok(msg_map["ClOrdID"].clone())
+}
+
+/// Updates the order an ExecutionReport received by the initiator is for, if the gateway sent
+/// it: fills, cancels and rejects. Reports of other orders are ignored.
+pub fn apply_execution_report(order_store: &OrderStore, msg_map: &IndexMap<String, String>) {
+    let get = |name: &str| msg_map.get(name).map_or("", String::as_str);
+    // A cancel is reported under the ClOrdID of the cancel request
+    let Some(order) = [get("ClOrdID"), get("OrigClOrdID")]
+        .into_iter()
+        .find_map(|id| order_store.get_order(&OrderKey::own(id)))
+    else {
+        return;
+    };
+    let decimal = |name: &str| get(name).parse::<Decimal>().ok();
+    let result = match get("ExecType") {
+        "1" | "2" | "F" | "PARTIAL_FILL" | "FILL" | "TRADE" => {
+            let last_qty = decimal("LastQty").or_else(|| decimal("LastShares"));
+            match (last_qty, decimal("LastPx")) {
+                (Some(last_qty), Some(last_px)) if last_qty > Decimal::ZERO => order_store
+                    .fill_order(&order.key(), last_qty, last_px)
+                    .map(|_| ()),
+                _ => Ok(()),
+            }
+        }
+        "4" | "CANCELED" => order_store
+            .set_status(&order.key(), OrdStatus::Canceled, None)
+            .map(|_| ()),
+        "8" | "REJECTED" => order_store
+            .set_status(&order.key(), OrdStatus::Rejected, None)
+            .map(|_| ()),
+        _ => Ok(()),
+    };
+    if let Err(e) = result {
+        error!(
+            "Order {} not updated from its ExecutionReport: {}",
+            order.id, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_gateway_orders_follow_their_execution_reports() {
+        let spec = order_spec(&json!({
+            "side": "Buy", "quantity": 100, "symbol": "IBM", "price": 187.5, "account": "ACC1"
+        }))
+        .unwrap();
+        assert_eq!(spec.side, "BUY");
+        assert_eq!(spec.ord_type, "LIMIT");
+        assert_eq!(spec.price.as_deref(), Some("187.5"));
+        assert_eq!(spec.account.as_deref(), Some("ACC1"));
+        assert!(order_spec(&json!({ "side": "buy", "symbol": "IBM" })).is_err());
+        assert!(
+            order_spec(&json!({ "side": "buy", "quantity": 1, "symbol": "IBM",
+                                     "ord_type": "limit" }))
+            .is_err()
+        );
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store =
+            Arc::new(OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap());
+        let mut stored = order_message(&spec, &IndexMap::new());
+        stored.insert("OrdStatus".to_string(), "New".to_string());
+        let cl_ord_id = stored["ClOrdID"].clone();
+        add_order_to_store(Arc::clone(&order_store), "", &stored).unwrap();
+
+        let report = |fields: &[(&str, &str)]| -> IndexMap<String, String> {
+            fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        apply_execution_report(
+            &order_store,
+            &report(&[
+                ("ClOrdID", &cl_ord_id),
+                ("ExecType", "PARTIAL_FILL"),
+                ("LastShares", "40"),
+                ("LastPx", "187.5"),
+            ]),
+        );
+        let order = order_store.get_order(&OrderKey::own(&cl_ord_id)).unwrap();
+        assert_eq!(order.status(), OrdStatus::PartiallyFilled);
+        assert_eq!(order.leaves_qty(), Decimal::from(60));
+
+        let cancel = order_cancel(&order);
+        assert_eq!(cancel["OrigClOrdID"], cl_ord_id);
+        apply_execution_report(
+            &order_store,
+            &report(&[
+                ("ClOrdID", &cancel["ClOrdID"]),
+                ("OrigClOrdID", &cl_ord_id),
+                ("ExecType", "CANCELED"),
+            ]),
+        );
+        assert!(!order_store.get_order(&OrderKey::own(&cl_ord_id)).unwrap().is_open());
+    }
+}
@@ -0,0 +1,657 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io;
+
+use log::{info, warn};
+use memmap2::{MmapMut, MmapOptions};
+use serde::{Deserialize, Serialize};
+
+use rust_decimal::Decimal;
+
+use crate::orderstore::{Order, OrderAuditEntry, OrderBackend, OrderKey};
+
+/// First bytes of an order store file, followed by the format version, the generation of the
+/// snapshot, its length and its CRC-32. The records appended since follow the snapshot.
+const FILE_MAGIC: &[u8; 8] = b"FIXORDER";
+const FILE_VERSION: u32 = 6;
+/// Version of the files written before orders kept their ListID and ListSeqNo, read and
+/// written again in the current version when opened.
+const LEGACY_VERSION: u32 = 5;
+const HEADER_LEN: usize = 28;
+/// Length, generation and CRC-32 of the body of a record.
+const RECORD_HEADER_LEN: usize = 12;
+/// Records appended before the orders are written as a new snapshot.
+const COMPACT_EVERY: usize = 1000;
+
+/// A change to the orders, as recorded in the journal.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum OrderChange {
+    Put(Box<Order>),
+    Remove(OrderKey),
+    /// The order formerly under `from` continues as `order`, its history left out: the
+    /// history it had is kept and `entry` added to it, so a change costs its own size only.
+    Update {
+        from: OrderKey,
+        order: Box<Order>,
+        entry: OrderAuditEntry,
+    },
+}
+
+/// An order as written in `LEGACY_VERSION`, its ListID and ListSeqNo only in its history.
+#[derive(Serialize, Deserialize)]
+struct LegacyOrder {
+    id: String,
+    sender_comp_id: String,
+    order_id: String,
+    account: String,
+    symbol: String,
+    side: String,
+    quantity: Decimal,
+    price: Option<Decimal>,
+    ordtype: String,
+    transacttime: String,
+    ordstatus: String,
+    timeinforce: String,
+    cum_qty: Decimal,
+    avg_px: Decimal,
+    history: Vec<OrderAuditEntry>,
+}
+
+impl From<LegacyOrder> for Order {
+    /// The ListID and ListSeqNo are those recorded with the fields set when it was added.
+    fn from(legacy: LegacyOrder) -> Self {
+        let placed = |field: &str| {
+            legacy.history.first().and_then(|placed| {
+                placed
+                    .changes
+                    .iter()
+                    .find(|change| change.field == field)
+                    .map(|change| change.new_value.clone())
+            })
+        };
+        let list_id = placed("ListID");
+        let list_seq_no = placed("ListSeqNo")
+            .and_then(|list_seq_no| list_seq_no.parse().ok())
+            .unwrap_or_default();
+        Order {
+            id: legacy.id,
+            sender_comp_id: legacy.sender_comp_id,
+            order_id: legacy.order_id,
+            account: legacy.account,
+            symbol: legacy.symbol,
+            side: legacy.side,
+            quantity: legacy.quantity,
+            price: legacy.price,
+            ordtype: legacy.ordtype,
+            transacttime: legacy.transacttime,
+            ordstatus: legacy.ordstatus,
+            timeinforce: legacy.timeinforce,
+            cum_qty: legacy.cum_qty,
+            avg_px: legacy.avg_px,
+            list_id,
+            list_seq_no,
+            history: legacy.history,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+enum LegacyOrderChange {
+    Put(Box<LegacyOrder>),
+    Remove(OrderKey),
+}
+
+impl From<LegacyOrderChange> for OrderChange {
+    fn from(legacy: LegacyOrderChange) -> Self {
+        match legacy {
+            LegacyOrderChange::Put(order) => OrderChange::Put(Box::new((*order).into())),
+            LegacyOrderChange::Remove(key) => OrderChange::Remove(key),
+        }
+    }
+}
+
+impl OrderChange {
+    /// The order formerly under `from` as changed, the last entry of its history being the
+    /// change. An order without history is put whole.
+    pub fn update(from: &OrderKey, mut order: Order) -> Self {
+        let mut history = std::mem::take(&mut order.history);
+        match history.pop() {
+            Some(entry) => OrderChange::Update {
+                from: from.clone(),
+                order: Box::new(order),
+                entry,
+            },
+            None => OrderChange::Put(Box::new(order)),
+        }
+    }
+
+    pub fn apply(self, orders: &mut HashMap<OrderKey, Order>) {
+        match self {
+            OrderChange::Put(order) => {
+                orders.insert(order.key(), *order);
+            }
+            OrderChange::Remove(key) => {
+                orders.remove(&key);
+            }
+            OrderChange::Update {
+                from,
+                mut order,
+                entry,
+            } => {
+                order.history = orders
+                    .remove(&from)
+                    .map(|previous| previous.history)
+                    .unwrap_or_default();
+                order.history.push(entry);
+                orders.insert(order.key(), *order);
+            }
+        }
+    }
+}
+
+/// The orders once the changes are applied, for a snapshot taken as they are appended.
+fn applied(orders: &HashMap<OrderKey, Order>, changes: &[OrderChange]) -> HashMap<OrderKey, Order> {
+    let mut orders = orders.clone();
+    for change in changes {
+        change.clone().apply(&mut orders);
+    }
+    orders
+}
+
+/// The mapped order store file: a snapshot of the orders followed by the changes appended
+/// since, one checksummed record per update. Every `COMPACT_EVERY` records the orders are
+/// written as the snapshot of a new generation instead. The file doubles in size when the
+/// next record or snapshot does not fit, up to `max_size`.
+pub struct OrderJournal {
+    file_path: String,
+    mmap: MmapMut,
+    max_size: usize,
+    /// Incremented by every snapshot; records left over from an older one are not replayed.
+    generation: u32,
+    /// Offset of the next record.
+    end: usize,
+    /// Records appended since the snapshot.
+    records: usize,
+}
+
+impl OrderJournal {
+    /// Maps the file, at least `size` bytes, and returns the orders of its snapshot with the
+    /// records replayed in order, up to the first torn one. A file never written gets an empty
+    /// snapshot, one of the legacy version is written again as a snapshot of the current one;
+    /// a snapshot which fails its checksum is an error.
+    pub fn open(file_path: &str, size: usize) -> io::Result<(Self, HashMap<OrderKey, Order>)> {
+        let invalid = |e: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Corrupt order store {}: {}", file_path, e),
+            )
+        };
+        let mmap = map_file(file_path, size)?;
+        let snapshot = decode_snapshot(&mmap).map_err(invalid)?;
+        let mut journal = OrderJournal {
+            file_path: file_path.to_string(),
+            mmap,
+            max_size: usize::MAX,
+            generation: 0,
+            end: HEADER_LEN,
+            records: 0,
+        };
+        let orders = match snapshot {
+            Some(mut snapshot) => {
+                journal.generation = snapshot.generation;
+                journal
+                    .replay(snapshot.end, snapshot.version, &mut snapshot.orders)
+                    .map_err(invalid)?;
+                if snapshot.version != FILE_VERSION {
+                    journal
+                        .compact(&snapshot.orders)
+                        .map_err(|e| io::Error::other(e.to_string()))?;
+                    info!(
+                        "Order store {} upgraded to format version {}",
+                        file_path, FILE_VERSION
+                    );
+                }
+                snapshot.orders
+            }
+            None => {
+                let orders = HashMap::new();
+                journal
+                    .compact(&orders)
+                    .map_err(|e| io::Error::other(e.to_string()))?;
+                orders
+            }
+        };
+        Ok((journal, orders))
+    }
+
+    /// Limits the size the file may grow to. A file already larger is not shrunk.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+    }
+
+    /// Writes the orders as the snapshot of a new generation to a temporary file renamed over
+    /// the store, so a crash leaves either the previous or the new file.
+    fn compact(&mut self, orders: &HashMap<OrderKey, Order>) -> Result<(), Box<dyn Error>> {
+        let generation = self.generation.wrapping_add(1);
+        let snapshot = encode_snapshot(generation, orders)?;
+        let size = if snapshot.len() > self.mmap.len() {
+            self.grown_size(snapshot.len()).ok_or_else(|| {
+                format!(
+                    "Orders need {} bytes, more than the order store maximum of {}",
+                    snapshot.len(),
+                    self.max_size
+                )
+            })?
+        } else {
+            self.mmap.len()
+        };
+
+        let temp_path = format!("{}.tmp", self.file_path);
+        let _ = fs::remove_file(&temp_path);
+        let mut mmap = map_file(&temp_path, size)?;
+        mmap[..snapshot.len()].copy_from_slice(&snapshot);
+        mmap.flush()?;
+        fs::rename(&temp_path, &self.file_path)?;
+
+        self.mmap = mmap;
+        self.generation = generation;
+        self.end = snapshot.len();
+        self.records = 0;
+        Ok(())
+    }
+
+    /// The size the file doubles to for `needed` bytes, capped at `max_size`. None when they
+    /// cannot fit.
+    fn grown_size(&self, needed: usize) -> Option<usize> {
+        if needed > self.max_size {
+            return None;
+        }
+        let mut size = self.mmap.len().max(HEADER_LEN);
+        while size < needed {
+            size = size.saturating_mul(2);
+        }
+        Some(size.min(self.max_size))
+    }
+
+    /// Extends the file to `size` bytes and maps it again.
+    fn grow(&mut self, size: usize) -> io::Result<()> {
+        self.mmap.flush()?;
+        self.mmap = map_file(&self.file_path, size)?;
+        info!("Order store {} grown to {} bytes", self.file_path, size);
+        Ok(())
+    }
+
+    /// Applies the records of the current generation from `offset` on. A record cut short or
+    /// failing its checksum ends the journal: it was being written when the engine stopped.
+    fn replay(
+        &mut self,
+        mut offset: usize,
+        version: u32,
+        orders: &mut HashMap<OrderKey, Order>,
+    ) -> Result<(), String> {
+        while let Some(header) = self.mmap.get(offset..offset + RECORD_HEADER_LEN) {
+            let len = read_u32(header, 0) as usize;
+            if len == 0 || read_u32(header, 4) != self.generation {
+                break;
+            }
+            let start = offset + RECORD_HEADER_LEN;
+            let body = match self.mmap.get(start..start + len) {
+                Some(body) if crc32(body) == read_u32(header, 8) => body,
+                _ => {
+                    warn!(
+                        "Torn record at offset {} of {} ignored",
+                        offset, self.file_path
+                    );
+                    break;
+                }
+            };
+            let changes: Vec<OrderChange> = if version == LEGACY_VERSION {
+                let changes: Vec<LegacyOrderChange> =
+                    bincode::deserialize(body).map_err(|e| e.to_string())?;
+                changes.into_iter().map(OrderChange::from).collect()
+            } else {
+                bincode::deserialize(body).map_err(|e| e.to_string())?
+            };
+            for change in changes {
+                change.apply(orders);
+            }
+            offset = start + len;
+            self.records += 1;
+        }
+        self.end = offset;
+        Ok(())
+    }
+}
+
+impl OrderBackend for OrderJournal {
+    /// Appends the changes as one record, so they are replayed together or not at all. The
+    /// orders are written as a snapshot instead when it is time to compact, or when the file
+    /// cannot grow to fit the record.
+    fn append(
+        &mut self,
+        changes: &[OrderChange],
+        orders: &HashMap<OrderKey, Order>,
+    ) -> Result<(), Box<dyn Error>> {
+        let record = encode_record(self.generation, changes)?;
+        if self.records >= COMPACT_EVERY {
+            return self.compact(&applied(orders, changes));
+        }
+        let needed = self.end + record.len();
+        if needed > self.mmap.len() {
+            match self.grown_size(needed) {
+                Some(size) => self.grow(size)?,
+                None => return self.compact(&applied(orders, changes)),
+            }
+        }
+        self.mmap[self.end..self.end + record.len()].copy_from_slice(&record);
+        self.mmap.flush_range(self.end, record.len())?;
+        self.end += record.len();
+        self.records += 1;
+        Ok(())
+    }
+}
+
+fn map_file(file_path: &str, size: usize) -> io::Result<MmapMut> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(file_path)?;
+    if file.metadata()?.len() < size as u64 {
+        file.set_len(size as u64)?;
+    }
+    unsafe { MmapOptions::new().map_mut(&file) }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// CRC-32 (IEEE) of a snapshot or record body, to detect a torn or overwritten store.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+fn encode_snapshot(
+    generation: u32,
+    orders: &HashMap<OrderKey, Order>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let body = bincode::serialize(orders, bincode::Infinite)?;
+    let mut encoded = Vec::with_capacity(HEADER_LEN + body.len());
+    encoded.extend_from_slice(FILE_MAGIC);
+    encoded.extend_from_slice(&FILE_VERSION.to_le_bytes());
+    encoded.extend_from_slice(&generation.to_le_bytes());
+    encoded.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    encoded.extend_from_slice(&crc32(&body).to_le_bytes());
+    encoded.extend_from_slice(&body);
+    Ok(encoded)
+}
+
+struct Snapshot {
+    version: u32,
+    generation: u32,
+    orders: HashMap<OrderKey, Order>,
+    /// Offset of the first record.
+    end: usize,
+}
+
+/// Decodes the snapshot of the mapped file. A file which was never written is all zeroes and
+/// has no snapshot yet.
+fn decode_snapshot(bytes: &[u8]) -> Result<Option<Snapshot>, String> {
+    if bytes.len() < HEADER_LEN || bytes[..HEADER_LEN].iter().all(|byte| *byte == 0) {
+        return Ok(None);
+    }
+    if &bytes[..8] != FILE_MAGIC {
+        return Err("not an order store".to_string());
+    }
+    let version = read_u32(bytes, 8);
+    if version != FILE_VERSION && version != LEGACY_VERSION {
+        return Err(format!("unsupported format version {}", version));
+    }
+    let generation = read_u32(bytes, 12);
+    let len = u64::from_le_bytes(bytes[16..24].try_into().unwrap()) as usize;
+    let body = bytes
+        .get(HEADER_LEN..HEADER_LEN.saturating_add(len))
+        .ok_or("truncated snapshot")?;
+    if read_u32(bytes, 24) != crc32(body) {
+        return Err("checksum mismatch".to_string());
+    }
+    let orders = if version == LEGACY_VERSION {
+        let orders: HashMap<OrderKey, LegacyOrder> =
+            bincode::deserialize(body).map_err(|e| e.to_string())?;
+        orders
+            .into_iter()
+            .map(|(key, order)| (key, order.into()))
+            .collect()
+    } else {
+        bincode::deserialize(body).map_err(|e| e.to_string())?
+    };
+    Ok(Some(Snapshot {
+        version,
+        generation,
+        orders,
+        end: HEADER_LEN + len,
+    }))
+}
+
+fn encode_record(generation: u32, changes: &[OrderChange]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let body = bincode::serialize(changes, bincode::Infinite)?;
+    let mut encoded = Vec::with_capacity(RECORD_HEADER_LEN + body.len());
+    encoded.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    encoded.extend_from_slice(&generation.to_le_bytes());
+    encoded.extend_from_slice(&crc32(&body).to_le_bytes());
+    encoded.extend_from_slice(&body);
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+    use tempfile::NamedTempFile;
+
+    fn order(id: &str, quantity: u64) -> Order {
+        Order {
+            id: id.to_string(),
+            sender_comp_id: String::new(),
+            order_id: String::new(),
+            account: "ACC1".to_string(),
+            symbol: "IBM".to_string(),
+            side: "BUY".to_string(),
+            quantity: Decimal::from(quantity),
+            price: Some(Decimal::from(50)),
+            ordtype: "LIMIT".to_string(),
+            transacttime: "20240101-00:00:00".to_string(),
+            ordstatus: "New".to_string(),
+            timeinforce: "DAY".to_string(),
+            cum_qty: Decimal::ZERO,
+            avg_px: Decimal::ZERO,
+            list_id: None,
+            list_seq_no: 0,
+            history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_journal_replays_records_and_compacts() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+
+        let (mut journal, mut orders) = OrderJournal::open(path, 1 << 20).unwrap();
+        assert!(orders.is_empty());
+        for quantity in 1..=COMPACT_EVERY as u64 + 10 {
+            let changes = vec![OrderChange::Put(Box::new(order("1", quantity)))];
+            journal.append(&changes, &orders).unwrap();
+            changes[0].clone().apply(&mut orders);
+        }
+        assert_eq!(journal.generation, 2);
+        assert_eq!(journal.records, 9);
+        let changes = vec![
+            OrderChange::Remove(OrderKey::own("1")),
+            OrderChange::Put(Box::new(order("2", 7))),
+        ];
+        journal.append(&changes, &orders).unwrap();
+        for change in changes {
+            change.apply(&mut orders);
+        }
+        let torn_at = journal.end;
+        journal
+            .append(&[OrderChange::Put(Box::new(order("3", 1)))], &orders)
+            .unwrap();
+        drop(journal);
+
+        let (journal, reloaded) = OrderJournal::open(path, 1 << 20).unwrap();
+        assert_eq!(journal.records, 11);
+        assert_eq!(reloaded.len(), 2);
+        assert_eq!(reloaded[&OrderKey::own("2")].quantity, Decimal::from(7));
+
+        // The last record was cut short by a crash
+        drop(journal);
+        let mut bytes = fs::read(path).unwrap();
+        bytes[torn_at + RECORD_HEADER_LEN] ^= 0xFF;
+        fs::write(path, &bytes).unwrap();
+        let (journal, reloaded) = OrderJournal::open(path, 1 << 20).unwrap();
+        assert_eq!(journal.end, torn_at);
+        assert!(!reloaded.contains_key(&OrderKey::own("3")));
+        assert!(reloaded.contains_key(&OrderKey::own("2")));
+
+        drop(journal);
+        bytes[HEADER_LEN] ^= 0xFF;
+        fs::write(path, &bytes).unwrap();
+        assert!(OrderJournal::open(path, 1 << 20).is_err());
+    }
+
+    #[test]
+    fn test_updates_are_journaled_without_the_history() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let (mut journal, mut orders) = OrderJournal::open(path, 1 << 20).unwrap();
+        let mut order = order("1", 100);
+        let mut record_lens = Vec::new();
+        for fill in 11..=30 {
+            order.cum_qty = Decimal::from(fill);
+            order.history.push(OrderAuditEntry {
+                timestamp: "20240101-00:00:00.000".to_string(),
+                msg_seq_num: None,
+                cl_ord_id: "1".to_string(),
+                changes: Vec::new(),
+            });
+            let changes = vec![OrderChange::update(&OrderKey::own("1"), order.clone())];
+            record_lens.push(encode_record(journal.generation, &changes).unwrap().len());
+            journal.append(&changes, &orders).unwrap();
+            for change in changes {
+                change.apply(&mut orders);
+            }
+        }
+        // Each record is as long as the first, whatever the history of the order
+        assert!(record_lens.iter().all(|len| *len == record_lens[0]));
+        assert_eq!(orders[&OrderKey::own("1")].history.len(), 20);
+        drop(journal);
+
+        let (_, reloaded) = OrderJournal::open(path, 1 << 20).unwrap();
+        let reloaded = &reloaded[&OrderKey::own("1")];
+        assert_eq!(reloaded.history.len(), 20);
+        assert_eq!(reloaded.cum_qty, Decimal::from(30));
+    }
+
+    #[test]
+    fn test_legacy_journal_is_upgraded() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let legacy = |id: &str, list_seq_no: &str| LegacyOrder {
+            id: id.to_string(),
+            sender_comp_id: "CLIENT1".to_string(),
+            order_id: String::new(),
+            account: "ACC1".to_string(),
+            symbol: "IBM".to_string(),
+            side: "BUY".to_string(),
+            quantity: Decimal::from(100),
+            price: None,
+            ordtype: "MARKET".to_string(),
+            transacttime: "20240101-00:00:00".to_string(),
+            ordstatus: "New".to_string(),
+            timeinforce: "DAY".to_string(),
+            cum_qty: Decimal::ZERO,
+            avg_px: Decimal::ZERO,
+            history: vec![OrderAuditEntry {
+                timestamp: "20240101-00:00:00.000".to_string(),
+                msg_seq_num: Some(2),
+                cl_ord_id: id.to_string(),
+                changes: [("ListID", "L1"), ("ListSeqNo", list_seq_no)]
+                    .into_iter()
+                    .map(|(field, new_value)| crate::orderstore::FieldChange {
+                        field: field.to_string(),
+                        old_value: String::new(),
+                        new_value: new_value.to_string(),
+                    })
+                    .collect(),
+            }],
+        };
+        let orders = HashMap::from([(OrderKey::new("CLIENT1", "A"), legacy("A", "1"))]);
+        let body = bincode::serialize(&orders, bincode::Infinite).unwrap();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(FILE_MAGIC);
+        bytes.extend_from_slice(&LEGACY_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&crc32(&body).to_le_bytes());
+        bytes.extend_from_slice(&body);
+        let changes = vec![LegacyOrderChange::Put(Box::new(legacy("B", "2")))];
+        let record = bincode::serialize(&changes, bincode::Infinite).unwrap();
+        bytes.extend_from_slice(&(record.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&crc32(&record).to_le_bytes());
+        bytes.extend_from_slice(&record);
+        fs::write(path, &bytes).unwrap();
+
+        for _ in 0..2 {
+            let (journal, orders) = OrderJournal::open(path, 4096).unwrap();
+            let b = &orders[&OrderKey::new("CLIENT1", "B")];
+            assert_eq!((b.list_id.as_deref(), b.list_seq_no), (Some("L1"), 2));
+            assert_eq!(orders[&OrderKey::new("CLIENT1", "A")].list_seq_no, 1);
+            assert_eq!(read_u32(&journal.mmap, 8), FILE_VERSION);
+        }
+    }
+
+    #[test]
+    fn test_journal_grows_up_to_max_size() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+
+        let (mut journal, mut orders) = OrderJournal::open(path, 64).unwrap();
+        for index in 0..20 {
+            let changes = vec![OrderChange::Put(Box::new(order(&index.to_string(), 1)))];
+            journal.append(&changes, &orders).unwrap();
+            changes[0].clone().apply(&mut orders);
+        }
+        assert!(journal.mmap.len() > 64);
+        assert_eq!(journal.generation, 1);
+        drop(journal);
+
+        // Once at its maximum, the file is compacted to make room until the orders no longer fit
+        let (mut journal, mut orders) = OrderJournal::open(path, 64).unwrap();
+        assert_eq!(orders.len(), 20);
+        let size = journal.mmap.len();
+        journal.set_max_size(size);
+        let refused = (20..1000).find(|index| {
+            let changes = vec![OrderChange::Put(Box::new(order(&index.to_string(), 1)))];
+            let refused = journal.append(&changes, &orders).is_err();
+            changes[0].clone().apply(&mut orders);
+            refused
+        });
+        assert!(refused.is_some());
+        assert!(journal.generation > 1);
+        assert_eq!(journal.mmap.len(), size);
+    }
+}
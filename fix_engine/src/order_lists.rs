@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+
+use crate::message_converter::repeating_group;
+use crate::orderstore::{OrdStatus, Order};
+use crate::parse_xml::FixTag;
+
+/// Fields of a NoOrders entry of a NewOrderList besides ClOrdID(11), which starts it.
+const ORDER_MEMBER_TAGS: [u32; 17] = [
+    67, 1, 21, 18, 100, 55, 48, 22, 54, 38, 40, 44, 99, 59, 60, 15, 58,
+];
+
+/// ListStatusType(429) of a ListStatus.
+pub const LIST_STATUS_TYPE_ACK: &str = "1";
+pub const LIST_STATUS_TYPE_RESPONSE: &str = "2";
+
+/// ListOrderStatus(431) of a ListStatus.
+pub const LIST_ORDER_STATUS_RECEIVED_FOR_EXECUTION: &str = "2";
+pub const LIST_ORDER_STATUS_EXECUTING: &str = "3";
+pub const LIST_ORDER_STATUS_ALL_DONE: &str = "6";
+pub const LIST_ORDER_STATUS_REJECT: &str = "7";
+
+/// A NewOrderList (35=E) placing a basket of orders under one ListID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewOrderList {
+    pub list_id: String,
+    /// The NoOrders entries as parsed NewOrderSingles: fields by name, enums by description,
+    /// OrdStatus New.
+    pub orders: Vec<IndexMap<String, String>>,
+}
+
+impl NewOrderList {
+    /// Reads the list from the raw message, its NoOrders entries included. Lists sent in
+    /// several messages, with a TotNoOrders above the entries, are refused.
+    pub fn parse(
+        message: &str,
+        fix_tag_name_map: &HashMap<String, FixTag>,
+    ) -> Result<Self, String> {
+        let fields: HashMap<&str, &str> = message
+            .split(['\x01', '|'])
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        let list_id = fields
+            .get("66")
+            .ok_or_else(|| "Missing ListID".to_string())?
+            .to_string();
+        let tags: HashMap<&str, &FixTag> = fix_tag_name_map
+            .values()
+            .map(|tag| (tag.number.as_str(), tag))
+            .collect();
+        let transact_time = fields.get("60").map_or_else(
+            || Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string(),
+            |value| value.to_string(),
+        );
+        let mut orders = Vec::new();
+        for entry in repeating_group(message, 73, 11, &ORDER_MEMBER_TAGS)? {
+            let mut order = IndexMap::new();
+            let mut entry: Vec<(u32, String)> = entry.into_iter().collect();
+            entry.sort_by_key(|(tag, _)| ORDER_MEMBER_TAGS.iter().position(|member| member == tag));
+            for (tag, value) in entry {
+                let Some(tag) = tags.get(tag.to_string().as_str()) else {
+                    continue;
+                };
+                // Enums are held by description, as in a parsed message
+                let value = tag
+                    .enum_values
+                    .as_ref()
+                    .and_then(|values| values.iter().find(|(_, raw)| **raw == value))
+                    .map_or(value, |(description, _)| description.clone());
+                order.insert(tag.name.clone(), value);
+            }
+            order
+                .entry("TransactTime".to_string())
+                .or_insert_with(|| transact_time.clone());
+            order.insert("OrdStatus".to_string(), "New".to_string());
+            orders.push(order);
+        }
+        if orders.is_empty() {
+            return Err(format!("List {} without orders", list_id));
+        }
+        let tot_no_orders = fields
+            .get("68")
+            .map_or(orders.len().to_string(), |value| value.to_string());
+        if tot_no_orders != orders.len().to_string() {
+            return Err(format!(
+                "TotNoOrders {} but {} orders in list {}",
+                tot_no_orders,
+                orders.len(),
+                list_id
+            ));
+        }
+        Ok(NewOrderList { list_id, orders })
+    }
+}
+
+/// ListOrderStatus of a list whose orders were received: all done once every order is final.
+pub fn list_order_status(orders: &[Order]) -> &'static str {
+    if orders.iter().all(|order| order.status().is_final()) {
+        LIST_ORDER_STATUS_ALL_DONE
+    } else {
+        LIST_ORDER_STATUS_EXECUTING
+    }
+}
+
+/// Override fields of the ListStatus template. Its NoOrders group is added with
+/// `list_status_group`.
+pub fn list_status_fields(
+    list_id: &str,
+    list_status_type: &str,
+    list_order_status: &str,
+    tot_no_orders: usize,
+    text: Option<&str>,
+) -> HashMap<String, String> {
+    let mut fields = HashMap::from([
+        ("ListID".to_string(), list_id.to_string()),
+        ("ListStatusType".to_string(), list_status_type.to_string()),
+        ("ListOrderStatus".to_string(), list_order_status.to_string()),
+        ("TotNoOrders".to_string(), tot_no_orders.to_string()),
+        (
+            "TransactTime".to_string(),
+            Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string(),
+        ),
+    ]);
+    if let Some(text) = text {
+        fields.insert("ListStatusText".to_string(), text.to_string());
+    }
+    fields
+}
+
+/// The NoOrders group of a ListStatus reporting the orders, as tag and value pairs.
+pub fn list_status_group(orders: &[Order]) -> Vec<(u32, String)> {
+    let mut group = vec![(73, orders.len().to_string())];
+    for order in orders {
+        let cxl_qty = match order.status() {
+            OrdStatus::Canceled => (order.quantity - order.cum_qty).max(Decimal::ZERO),
+            _ => Decimal::ZERO,
+        };
+        group.extend([
+            (11, order.id.clone()),
+            (14, order.cum_qty.to_string()),
+            (39, order.status().value().to_string()),
+            (151, order.leaves_qty().to_string()),
+            (84, cxl_qty.to_string()),
+            (6, order.avg_px.to_string()),
+        ]);
+    }
+    group
+}
+
+/// The NoOrders group of a ListStatus refusing a list, every order rejected.
+pub fn rejected_list_group(cl_ord_ids: &[String]) -> Vec<(u32, String)> {
+    let mut group = vec![(73, cl_ord_ids.len().to_string())];
+    for cl_ord_id in cl_ord_ids {
+        group.extend([
+            (11, cl_ord_id.clone()),
+            (14, "0".to_string()),
+            (39, OrdStatus::Rejected.value().to_string()),
+            (151, "0".to_string()),
+            (84, "0".to_string()),
+            (6, "0".to_string()),
+        ]);
+    }
+    group
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_xml::parse_fix_xml;
+
+    #[test]
+    fn test_new_order_list_entries_are_read_as_orders() {
+        let fix_tag_name_map = parse_fix_xml("reference/FIX4_2.xml").unwrap().1;
+        let list = |tot_no_orders: &str| {
+            NewOrderList::parse(
+                &format!(
+                    "8=FIX.4.2|9=0|35=E|66=L1|394=3|68={}|73=2|\
+                     11=A|67=1|55=IBM|54=1|38=100|40=2|44=150|\
+                     11=B|67=2|1=ACC1|55=AAPL|54=2|38=50|40=2|44=187.5|60=20240101-00:00:00|\
+                     10=000|",
+                    tot_no_orders
+                ),
+                &fix_tag_name_map,
+            )
+        };
+        let parsed = list("2").unwrap();
+        assert_eq!(parsed.list_id, "L1");
+        let first = &parsed.orders[0];
+        assert_eq!(first.get("ListSeqNo").map(String::as_str), Some("1"));
+        assert_eq!(first.get("Side").map(String::as_str), Some("BUY"));
+        assert_eq!(first.get("OrdType").map(String::as_str), Some("LIMIT"));
+        assert!(first.contains_key("TransactTime"));
+        assert_eq!(
+            parsed.orders[1].get("Account").map(String::as_str),
+            Some("ACC1")
+        );
+        assert_eq!(
+            list("3").unwrap_err(),
+            "TotNoOrders 3 but 2 orders in list L1"
+        );
+
+        let group = rejected_list_group(&["A".to_string(), "B".to_string()]);
+        assert_eq!(group.len(), 13);
+        assert_eq!(group[3], (39, "8".to_string()));
+    }
+}
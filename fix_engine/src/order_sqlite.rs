@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+
+use rusqlite::{params, Connection, Transaction};
+
+use crate::order_journal::OrderChange;
+use crate::orderstore::{Order, OrderBackend, OrderKey};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS orders (
+        sender_comp_id TEXT NOT NULL,
+        cl_ord_id TEXT NOT NULL,
+        order_id TEXT NOT NULL,
+        account TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        side TEXT NOT NULL,
+        ordstatus TEXT NOT NULL,
+        quantity TEXT NOT NULL,
+        price TEXT NOT NULL,
+        cum_qty TEXT NOT NULL,
+        avg_px TEXT NOT NULL,
+        transacttime TEXT NOT NULL,
+        body TEXT NOT NULL,
+        PRIMARY KEY (sender_comp_id, cl_ord_id)
+    );
+    CREATE INDEX IF NOT EXISTS orders_symbol ON orders (symbol);
+    CREATE INDEX IF NOT EXISTS orders_account ON orders (account);
+    CREATE INDEX IF NOT EXISTS orders_ordstatus ON orders (ordstatus);
+";
+
+/// Moves the orders of a database written before orders were keyed by SenderCompID and
+/// ClOrdID to the current table, under an empty SenderCompID.
+const MIGRATE_UNKEYED: &str = "
+    DROP INDEX IF EXISTS orders_symbol;
+    DROP INDEX IF EXISTS orders_account;
+    DROP INDEX IF EXISTS orders_ordstatus;
+    ALTER TABLE orders RENAME TO orders_unkeyed;
+";
+
+const COLUMNS: &str = "cl_ord_id, order_id, account, symbol, side, ordstatus, quantity, price, \
+                       cum_qty, avg_px, transacttime, body";
+
+/// Orders kept in an SQLite database, one row per order. The key fields are columns of their
+/// own so the store can be queried from any SQLite client; `body` holds the whole order as JSON
+/// and is what is loaded back. Each append is one transaction.
+pub struct SqliteOrderBackend {
+    connection: Connection,
+}
+
+impl SqliteOrderBackend {
+    /// Opens the database, creating the table on first use, and returns the orders it holds.
+    pub fn open(file_path: &str) -> io::Result<(Self, HashMap<OrderKey, Order>)> {
+        let mut connection = Connection::open(file_path).map_err(io::Error::other)?;
+        connection
+            .execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = FULL;")
+            .map_err(io::Error::other)?;
+        migrate(&mut connection).map_err(io::Error::other)?;
+
+        let mut orders = HashMap::new();
+        let mut statement = connection
+            .prepare("SELECT body FROM orders")
+            .map_err(io::Error::other)?;
+        let bodies = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(io::Error::other)?;
+        for body in bodies {
+            let order: Order =
+                serde_json::from_str(&body.map_err(io::Error::other)?).map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Corrupt order in {}: {}", file_path, e),
+                    )
+                })?;
+            orders.insert(order.key(), order);
+        }
+        drop(statement);
+        Ok((SqliteOrderBackend { connection }, orders))
+    }
+}
+
+impl OrderBackend for SqliteOrderBackend {
+    /// Writes the changes in one transaction. An update is written as the whole order, its
+    /// history that of `orders` with the new entry added.
+    fn append(
+        &mut self,
+        changes: &[OrderChange],
+        orders: &HashMap<OrderKey, Order>,
+    ) -> Result<(), Box<dyn Error>> {
+        let transaction = self.connection.transaction()?;
+        for change in changes {
+            match change {
+                OrderChange::Put(order) => put(&transaction, order)?,
+                OrderChange::Remove(key) => remove(&transaction, key)?,
+                OrderChange::Update { from, order, entry } => {
+                    let mut order = Order::clone(order);
+                    order.history = orders
+                        .get(from)
+                        .map(|previous| previous.history.clone())
+                        .unwrap_or_default();
+                    order.history.push(entry.clone());
+                    if *from != order.key() {
+                        remove(&transaction, from)?;
+                    }
+                    put(&transaction, &order)?;
+                }
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+fn put(transaction: &Transaction, order: &Order) -> Result<(), Box<dyn Error>> {
+    transaction.execute(
+        &format!(
+            "INSERT OR REPLACE INTO orders (sender_comp_id, {}) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            COLUMNS
+        ),
+        params![
+            order.sender_comp_id,
+            order.id,
+            order.order_id,
+            order.account,
+            order.symbol,
+            order.side,
+            order.ordstatus,
+            order.quantity.to_string(),
+            order.price_text(),
+            order.cum_qty.to_string(),
+            order.avg_px.to_string(),
+            order.transacttime,
+            serde_json::to_string(order)?,
+        ],
+    )?;
+    Ok(())
+}
+
+fn remove(transaction: &Transaction, key: &OrderKey) -> rusqlite::Result<()> {
+    transaction.execute(
+        "DELETE FROM orders WHERE sender_comp_id = ?1 AND cl_ord_id = ?2",
+        [&key.sender_comp_id, &key.cl_ord_id],
+    )?;
+    Ok(())
+}
+
+/// Creates the table on first use, moving the orders of an older table over in the same
+/// transaction.
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    let unkeyed: bool = transaction.query_row(
+        "SELECT COUNT(*) = 1 FROM pragma_table_info('orders') WHERE name = 'cl_ord_id' \
+         AND pk = 1",
+        [],
+        |row| row.get(0),
+    )?;
+    if unkeyed {
+        transaction.execute_batch(MIGRATE_UNKEYED)?;
+    }
+    transaction.execute_batch(SCHEMA)?;
+    if unkeyed {
+        transaction.execute_batch(&format!(
+            "INSERT INTO orders (sender_comp_id, {columns}) \
+             SELECT '', {columns} FROM orders_unkeyed; \
+             DROP TABLE orders_unkeyed;",
+            columns = COLUMNS
+        ))?;
+    }
+    transaction.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderstore::OrderStore;
+    use rust_decimal::Decimal;
+    use tempfile::NamedTempFile;
+
+    fn order(id: &str) -> Order {
+        Order {
+            id: id.to_string(),
+            sender_comp_id: String::new(),
+            order_id: String::new(),
+            account: "ACC1".to_string(),
+            symbol: "IBM".to_string(),
+            side: "BUY".to_string(),
+            quantity: Decimal::from(100),
+            price: Some(Decimal::new(5025, 2)),
+            ordtype: "LIMIT".to_string(),
+            transacttime: "20240101-00:00:00".to_string(),
+            ordstatus: "New".to_string(),
+            timeinforce: "DAY".to_string(),
+            cum_qty: Decimal::ZERO,
+            avg_px: Decimal::ZERO,
+            list_id: None,
+            list_seq_no: 0,
+            history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_sqlite_backend_persists_orders() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+
+        let (backend, orders) = SqliteOrderBackend::open(path).unwrap();
+        let order_store = OrderStore::with_backend(Box::new(backend), orders);
+        order_store.add_order(order("1"), None).unwrap();
+        order_store
+            .fill_order(&OrderKey::own("1"), Decimal::from(40), Decimal::new(5025, 2))
+            .unwrap();
+        order_store.add_order(order("2"), None).unwrap();
+        order_store.remove_order(&OrderKey::own("2")).unwrap();
+        // Another counterparty may use the same ClOrdID
+        let mut other = order("1");
+        other.sender_comp_id = "XYZ".to_string();
+        order_store.add_order(other, None).unwrap();
+        drop(order_store);
+
+        let (backend, orders) = SqliteOrderBackend::open(path).unwrap();
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[&OrderKey::own("1")].avg_px, Decimal::new(5025, 2));
+        // The fill was appended to the history the order was added with
+        assert_eq!(orders[&OrderKey::own("1")].history.len(), 2);
+        assert_eq!(orders[&OrderKey::new("XYZ", "1")].avg_px, Decimal::ZERO);
+        let cum_qty: String = backend
+            .connection
+            .query_row(
+                "SELECT cum_qty FROM orders WHERE sender_comp_id = '' AND symbol = 'IBM'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(cum_qty, "40");
+    }
+    #[test]
+    fn test_orders_of_an_unkeyed_table_are_migrated() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let mut body = serde_json::to_value(order("1")).unwrap();
+        body.as_object_mut().unwrap().remove("sender_comp_id");
+        let connection = Connection::open(path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE orders (cl_ord_id TEXT PRIMARY KEY, order_id TEXT NOT NULL, \
+                 account TEXT NOT NULL, symbol TEXT NOT NULL, side TEXT NOT NULL, \
+                 ordstatus TEXT NOT NULL, quantity TEXT NOT NULL, price TEXT NOT NULL, \
+                 cum_qty TEXT NOT NULL, avg_px TEXT NOT NULL, transacttime TEXT NOT NULL, \
+                 body TEXT NOT NULL); \
+                 CREATE INDEX orders_symbol ON orders (symbol);",
+            )
+            .unwrap();
+        connection
+            .execute(
+                "INSERT INTO orders VALUES ('1', '', 'ACC1', 'IBM', 'BUY', 'New', '100', \
+                 '50.25', '0', '0', '20240101-00:00:00', ?1)",
+                [body.to_string()],
+            )
+            .unwrap();
+        drop(connection);
+
+        let (backend, orders) = SqliteOrderBackend::open(path).unwrap();
+        assert_eq!(orders[&OrderKey::own("1")].quantity, Decimal::from(100));
+        let order_store = OrderStore::with_backend(Box::new(backend), orders);
+        let mut other = order("1");
+        other.sender_comp_id = "XYZ".to_string();
+        order_store.add_order(other, None).unwrap();
+        drop(order_store);
+        let (_, orders) = SqliteOrderBackend::open(path).unwrap();
+        assert_eq!(orders.len(), 2);
+    }
+}
@@ -1,253 +1,1621 @@
-use bincode;
-use memmap2::{MmapMut, MmapOptions};
+use chrono::Utc;
 use prettytable::{row, Cell, Row, Table};
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs::OpenOptions;
-use std::sync::RwLock;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::{Mutex, RwLock};
 
 use indexmap::IndexMap;
-use log::{error, info};
+use log::{error, info, warn};
 use std::error::Error;
 use std::sync::Arc;
 
+use crate::order_journal::OrderChange;
 use crate::parse_xml::FixError;
 
+/// Lifecycle of an order: New, then PartiallyFilled, then Filled, Canceled, Replaced or
+/// Rejected. Filled, Canceled and Rejected are final; a replaced order keeps working under its
+/// new ClOrdID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OrdStatus {
+    New,
+    PartiallyFilled,
+    Filled,
+    Canceled,
+    Replaced,
+    Rejected,
+}
+
+impl OrdStatus {
+    /// OrdStatus as stored, the dictionary description or the raw value.
+    pub fn parse(ordstatus: &str) -> Option<OrdStatus> {
+        match ordstatus.to_uppercase().as_str() {
+            "NEW" | "0" => Some(OrdStatus::New),
+            "PARTIALLY FILLED" | "PARTIALLY_FILLED" | "1" => Some(OrdStatus::PartiallyFilled),
+            "FILLED" | "2" => Some(OrdStatus::Filled),
+            "CANCELED" | "4" => Some(OrdStatus::Canceled),
+            "REPLACED" | "5" => Some(OrdStatus::Replaced),
+            "REJECTED" | "8" => Some(OrdStatus::Rejected),
+            _ => None,
+        }
+    }
+
+    /// The status as stored in `Order::ordstatus`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrdStatus::New => "New",
+            OrdStatus::PartiallyFilled => "Partially filled",
+            OrdStatus::Filled => "Filled",
+            OrdStatus::Canceled => "Canceled",
+            OrdStatus::Replaced => "Replaced",
+            OrdStatus::Rejected => "Rejected",
+        }
+    }
+
+    /// The value of OrdStatus(39).
+    pub fn value(self) -> &'static str {
+        match self {
+            OrdStatus::New => "0",
+            OrdStatus::PartiallyFilled => "1",
+            OrdStatus::Filled => "2",
+            OrdStatus::Canceled => "4",
+            OrdStatus::Replaced => "5",
+            OrdStatus::Rejected => "8",
+        }
+    }
+
+    pub fn is_final(self) -> bool {
+        matches!(
+            self,
+            OrdStatus::Filled | OrdStatus::Canceled | OrdStatus::Rejected
+        )
+    }
+
+    /// Whether an order in this status may move to the next one. Only a new order can be
+    /// rejected, and nothing happens to an order once final.
+    pub fn can_become(self, next: OrdStatus) -> bool {
+        match (self, next) {
+            _ if self.is_final() => false,
+            (OrdStatus::New, OrdStatus::Rejected) => true,
+            (_, OrdStatus::New | OrdStatus::Rejected) => false,
+            _ => true,
+        }
+    }
+}
+
+/// A status change refused by the order lifecycle, e.g. canceling a filled order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IllegalTransition {
+    pub order_id: String,
+    pub from: OrdStatus,
+    pub to: OrdStatus,
+}
+
+impl fmt::Display for IllegalTransition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Order {} is {}, it cannot be {}",
+            self.order_id,
+            self.from.as_str().to_lowercase(),
+            self.to.as_str().to_lowercase()
+        )
+    }
+}
+
+impl Error for IllegalTransition {}
+
+/// Why an order message could not be applied to the OrderStore.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderError {
+    MissingField(&'static str),
+    InvalidField { field: &'static str, value: String },
+    IllegalTransition(IllegalTransition),
+    /// A new order with the ClOrdID of a known order.
+    DuplicateClOrdID(String),
+}
+
+impl OrderError {
+    /// The OrdRejReason(103) of an ExecutionReport rejecting the order, when it has one.
+    pub fn ord_rej_reason(&self) -> Option<&'static str> {
+        match self {
+            OrderError::DuplicateClOrdID(_) => Some("6"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderError::MissingField(field) => write!(f, "Missing {}", field),
+            OrderError::InvalidField { field, value } => {
+                write!(f, "Invalid {} {:?}", field, value)
+            }
+            OrderError::IllegalTransition(refused) => refused.fmt(f),
+            OrderError::DuplicateClOrdID(id) => write!(f, "Duplicate ClOrdID {}", id),
+        }
+    }
+}
+
+impl Error for OrderError {}
+
+impl From<IllegalTransition> for OrderError {
+    fn from(refused: IllegalTransition) -> Self {
+        OrderError::IllegalTransition(refused)
+    }
+}
+
+/// Decimal places AvgPx is rounded to.
+const AVG_PX_DECIMALS: u32 = 8;
+
+/// Identifies an order. ClOrdIDs are only unique per counterparty, so the orders the acceptor
+/// takes are kept under the SenderCompID of the counterparty which placed them; the engine's
+/// own orders under an empty one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OrderKey {
+    pub sender_comp_id: String,
+    pub cl_ord_id: String,
+}
+
+impl OrderKey {
+    pub fn new(sender_comp_id: &str, cl_ord_id: &str) -> Self {
+        OrderKey {
+            sender_comp_id: sender_comp_id.to_string(),
+            cl_ord_id: cl_ord_id.to_string(),
+        }
+    }
+
+    /// Key of an order the engine placed itself.
+    pub fn own(cl_ord_id: &str) -> Self {
+        OrderKey::new("", cl_ord_id)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Order {
-    pub id: u64,
+    /// ClOrdID of the order, or of its last replacement.
+    pub id: String,
+    /// SenderCompID of the counterparty which placed the order, empty for the engine's own.
+    #[serde(default)]
+    pub sender_comp_id: String,
+    /// OrderID assigned by the acceptor, kept across replacements. Empty until assigned.
+    pub order_id: String,
     pub account: String,
     pub symbol: String,
     pub side: String,
-    pub quantity: u64,
-    pub price: u64,
+    pub quantity: Decimal,
+    /// Limit price, None for a market order.
+    pub price: Option<Decimal>,
     pub ordtype: String,
     pub transacttime: String,
     pub ordstatus: String,
+    pub timeinforce: String,
+    /// Quantity executed so far; LeavesQty is what remains of `quantity` while the order is open.
+    pub cum_qty: Decimal,
+    /// Volume-weighted price of the executions, 0 before the first one.
+    pub avg_px: Decimal,
+    /// ListID of the NewOrderList which placed the order, kept across replacements.
+    #[serde(default)]
+    pub list_id: Option<String>,
+    /// ListSeqNo of the order in its list, 0 outside of one.
+    #[serde(default)]
+    pub list_seq_no: u32,
+    /// Every change made to the order since it was added, oldest first.
+    pub history: Vec<OrderAuditEntry>,
+}
+
+/// One field of an order and the value it changed from and to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+/// One step in the life of an order, so what happened to it can be reconstructed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OrderAuditEntry {
+    /// When the store recorded the change, UTC.
+    pub timestamp: String,
+    /// MsgSeqNum of the message which caused the change; None for the changes the engine makes
+    /// on its own, such as executions and risk cancels.
+    pub msg_seq_num: Option<u64>,
+    /// ClOrdID of the order once changed.
+    pub cl_ord_id: String,
+    pub changes: Vec<FieldChange>,
+}
+
+impl Order {
+    /// The key the order is stored under.
+    pub fn key(&self) -> OrderKey {
+        OrderKey::new(&self.sender_comp_id, &self.id)
+    }
+
+    /// The Price as sent, empty for a market order.
+    pub fn price_text(&self) -> String {
+        self.price.map(|price| price.to_string()).unwrap_or_default()
+    }
+
+    /// Returns the fields which differ from `before`, the same order earlier in its life.
+    pub fn changes_since(&self, before: &Order) -> Vec<FieldChange> {
+        let fields = [
+            ("ClOrdID", before.id.clone(), self.id.clone()),
+            ("Symbol", before.symbol.clone(), self.symbol.clone()),
+            ("Side", before.side.clone(), self.side.clone()),
+            (
+                "OrderQty",
+                before.quantity.to_string(),
+                self.quantity.to_string(),
+            ),
+            ("Price", before.price_text(), self.price_text()),
+            ("OrdType", before.ordtype.clone(), self.ordtype.clone()),
+            (
+                "TimeInForce",
+                before.timeinforce.clone(),
+                self.timeinforce.clone(),
+            ),
+            ("OrdStatus", before.ordstatus.clone(), self.ordstatus.clone()),
+            ("CumQty", before.cum_qty.to_string(), self.cum_qty.to_string()),
+            ("AvgPx", before.avg_px.to_string(), self.avg_px.to_string()),
+        ];
+        fields
+            .into_iter()
+            .filter(|(_, old_value, new_value)| old_value != new_value)
+            .map(|(field, old_value, new_value)| FieldChange {
+                field: field.to_string(),
+                old_value,
+                new_value,
+            })
+            .collect()
+    }
+
+    /// Adds the changes to the history of the order and returns the entry.
+    fn audit(&mut self, msg_seq_num: Option<u64>, changes: Vec<FieldChange>) -> OrderAuditEntry {
+        let entry = OrderAuditEntry {
+            timestamp: Utc::now().format("%Y%m%d-%H:%M:%S%.3f").to_string(),
+            msg_seq_num,
+            cl_ord_id: self.id.clone(),
+            changes,
+        };
+        self.history.push(entry.clone());
+        entry
+    }
+
+    /// Quantity still working: 0 once the order is no longer open.
+    pub fn leaves_qty(&self) -> Decimal {
+        if self.is_open() {
+            (self.quantity - self.cum_qty).max(Decimal::ZERO)
+        } else {
+            Decimal::ZERO
+        }
+    }
+
+    /// Adds an execution to CumQty and AvgPx, the order becoming filled once CumQty reaches
+    /// OrderQty. Orders no longer open cannot be executed.
+    pub fn record_fill(
+        &mut self,
+        quantity: Decimal,
+        price: Decimal,
+    ) -> Result<(), IllegalTransition> {
+        let cum_qty = self.cum_qty + quantity;
+        let next = if cum_qty >= self.quantity {
+            OrdStatus::Filled
+        } else {
+            OrdStatus::PartiallyFilled
+        };
+        self.transition(next)?;
+        if cum_qty > Decimal::ZERO {
+            self.avg_px = ((self.avg_px * self.cum_qty + price * quantity) / cum_qty)
+                .round_dp(AVG_PX_DECIMALS)
+                .normalize();
+        }
+        self.cum_qty = cum_qty;
+        Ok(())
+    }
+
+    /// The lifecycle status of the order; statuses the lifecycle does not know count as New.
+    pub fn status(&self) -> OrdStatus {
+        OrdStatus::parse(&self.ordstatus).unwrap_or(OrdStatus::New)
+    }
+
+    /// Moves the order to the next status if its lifecycle allows it.
+    pub fn transition(&mut self, next: OrdStatus) -> Result<(), IllegalTransition> {
+        let from = self.status();
+        if !from.can_become(next) {
+            return Err(IllegalTransition {
+                order_id: self.id.clone(),
+                from,
+                to: next,
+            });
+        }
+        self.ordstatus = next.as_str().to_string();
+        Ok(())
+    }
+
+    /// Canceled, filled and rejected orders are no longer working.
+    pub fn is_open(&self) -> bool {
+        !self.status().is_final()
+    }
+
+    /// Brings the stored OrdStatus in line with the executions recorded, for orders written
+    /// by an earlier run: the status is normalized and a working order whose executions cover
+    /// its quantity is filled. Returns the previous status when it changed.
+    pub fn reconcile(&mut self) -> Option<String> {
+        let mut status = self.status();
+        if !status.is_final() && status != OrdStatus::Replaced && self.cum_qty > Decimal::ZERO {
+            status = if self.cum_qty >= self.quantity {
+                OrdStatus::Filled
+            } else {
+                OrdStatus::PartiallyFilled
+            };
+        }
+        if self.ordstatus == status.as_str() {
+            return None;
+        }
+        Some(std::mem::replace(
+            &mut self.ordstatus,
+            status.as_str().to_string(),
+        ))
+    }
+}
+
+/// Where an `OrderStore` persists its orders. Each call records the changes made under the
+/// store's write lock, in the order they were made; `orders` are those before the changes,
+/// which the store applies only once the backend has them.
+pub trait OrderBackend: Send {
+    fn append(
+        &mut self,
+        changes: &[OrderChange],
+        orders: &HashMap<OrderKey, Order>,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/// Orders looked up through the secondary indexes of an `OrderStore`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderQuery {
+    All,
+    Symbol(String),
+    /// Orders of the account still working.
+    OpenForAccount(String),
+    Status(OrdStatus),
+}
+
+/// A ListID, like a ClOrdID, is only unique per counterparty: its SenderCompID and the ListID.
+type ListKey = (String, String);
+
+/// What an order is indexed under.
+struct IndexedAs {
+    symbol: String,
+    account: String,
+    status: OrdStatus,
+    list_id: Option<ListKey>,
+    order_id: String,
+}
+
+/// Keys of the orders by symbol, account, OrdStatus, ListID and OrderID, kept in step with
+/// the orders by `OrderStore::journal`.
+#[derive(Default)]
+struct OrderIndexes {
+    keys: HashMap<OrderKey, IndexedAs>,
+    by_symbol: HashMap<String, BTreeSet<OrderKey>>,
+    by_account: HashMap<String, BTreeSet<OrderKey>>,
+    by_status: HashMap<OrdStatus, BTreeSet<OrderKey>>,
+    by_list: HashMap<ListKey, BTreeSet<OrderKey>>,
+    /// Orders by the OrderID the acceptor assigned, those without one left out.
+    by_order_id: HashMap<String, BTreeSet<OrderKey>>,
+}
+
+impl OrderIndexes {
+    fn update(&mut self, change: &OrderChange) {
+        let (removed, put) = match change {
+            OrderChange::Put(order) => (order.key(), Some(order)),
+            OrderChange::Remove(key) => (key.clone(), None),
+            OrderChange::Update { from, order, .. } => (from.clone(), Some(order)),
+        };
+        if let Some(indexed) = self.keys.remove(&removed) {
+            unlink(&mut self.by_symbol, &indexed.symbol, &removed);
+            unlink(&mut self.by_account, &indexed.account, &removed);
+            unlink(&mut self.by_status, &indexed.status, &removed);
+            if let Some(list_id) = indexed.list_id {
+                unlink(&mut self.by_list, &list_id, &removed);
+            }
+            unlink(&mut self.by_order_id, &indexed.order_id, &removed);
+        }
+        if let Some(order) = put {
+            let id = order.key();
+            let status = order.status();
+            for (index, key) in [
+                (&mut self.by_symbol, &order.symbol),
+                (&mut self.by_account, &order.account),
+            ] {
+                index.entry(key.clone()).or_default().insert(id.clone());
+            }
+            if !order.order_id.is_empty() {
+                self.by_order_id
+                    .entry(order.order_id.clone())
+                    .or_default()
+                    .insert(id.clone());
+            }
+            self.by_status.entry(status).or_default().insert(id.clone());
+            let list_id = order
+                .list_id
+                .clone()
+                .map(|list_id| (order.sender_comp_id.clone(), list_id));
+            if let Some(list_id) = &list_id {
+                self.by_list.entry(list_id.clone()).or_default().insert(id.clone());
+            }
+            self.keys.insert(
+                id,
+                IndexedAs {
+                    symbol: order.symbol.clone(),
+                    account: order.account.clone(),
+                    status,
+                    list_id,
+                    order_id: order.order_id.clone(),
+                },
+            );
+        }
+    }
+}
+
+fn unlink<K: Hash + Eq>(index: &mut HashMap<K, BTreeSet<OrderKey>>, key: &K, id: &OrderKey) {
+    if let Some(ids) = index.get_mut(key) {
+        ids.remove(id);
+        if ids.is_empty() {
+            index.remove(key);
+        }
+    }
 }
 
 pub struct OrderStore {
-    orders: RwLock<HashMap<u64, Order>>,
-    mmap: RwLock<MmapMut>,
+    orders: RwLock<HashMap<OrderKey, Order>>,
+    indexes: RwLock<OrderIndexes>,
+    backend: Mutex<Box<dyn OrderBackend>>,
 }
 
 impl OrderStore {
+    /// Opens the store file, at least `size` bytes and grown on demand, and loads the orders
+    /// written by an earlier run, see `OrderJournal::open`. Their OrdStatus is reconciled, see
+    /// `Order::reconcile`. The engine opens its store through the configured backend instead.
+    #[cfg(test)]
     pub fn new(file_path: &str, size: usize) -> std::io::Result<Self> {
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(file_path)?;
-        file.set_len(size as u64)?;
-
-        let mmap = unsafe { MmapOptions::new().map_mut(&file)? };
-
-        Ok(Self {
-            orders: RwLock::new(HashMap::new()),
-            mmap: RwLock::new(mmap),
-        })
-    }
-
-    pub fn add_order(&self, order: Order) -> Result<(), Box<dyn std::error::Error>> {
-        {
-            let mut orders = self.orders.write().unwrap();
-            orders.insert(order.id, order);
-        } // Release the orders lock here before persisting
-        self.persist()?;
-        Ok(())
+        let (journal, orders) = crate::order_journal::OrderJournal::open(file_path, size)?;
+        Ok(Self::with_backend(Box::new(journal), orders))
     }
-    pub fn update_order(&self, order: Order) -> Result<(), Box<dyn std::error::Error>> {
-        {
-            let mut orders = self.orders.write().unwrap();
-            if orders.contains_key(&order.id) {
-                orders.insert(order.id, order);
-            } else {
-                return Err("Order ID not found".into());
+
+    /// Keeps the orders loaded from `backend`, reconciled, and records their changes to it.
+    pub fn with_backend(
+        backend: Box<dyn OrderBackend>,
+        mut orders: HashMap<OrderKey, Order>,
+    ) -> Self {
+        for order in orders.values_mut() {
+            if let Some(previous) = order.reconcile() {
+                warn!(
+                    "Order {} was stored as {}, reconciled to {}",
+                    order.id, previous, order.ordstatus
+                );
             }
         }
-        self.persist()?;
-        Ok(())
+        let open = orders.values().filter(|order| order.is_open()).count();
+        info!("Loaded {} orders, {} still open", orders.len(), open);
+        let mut indexes = OrderIndexes::default();
+        for order in orders.values() {
+            indexes.update(&OrderChange::Put(Box::new(order.clone())));
+        }
+        Self {
+            orders: RwLock::new(orders),
+            indexes: RwLock::new(indexes),
+            backend: Mutex::new(backend),
+        }
+    }
+
+    /// Adds an order, its history starting with the message which placed it. An order with the
+    /// ClOrdID of a known one is refused, see `OrderError::DuplicateClOrdID`.
+    pub fn add_order(
+        &self,
+        mut order: Order,
+        msg_seq_num: Option<u64>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let placed = FieldChange {
+            field: "OrdStatus".to_string(),
+            old_value: String::new(),
+            new_value: order.ordstatus.clone(),
+        };
+        order.audit(msg_seq_num, vec![placed]);
+        let mut orders = self.orders.write().unwrap();
+        if orders.contains_key(&order.key()) {
+            return Err(Box::new(OrderError::DuplicateClOrdID(order.id)));
+        }
+        self.journal(&mut orders, vec![OrderChange::Put(Box::new(order))])
+    }
+
+    /// Adds the orders of a NewOrderList placed by `sender_comp_id`, each entry a
+    /// NewOrderSingle with its ListSeqNo. The ListID and ListSeqNo are kept on each order and
+    /// recorded in its history. Nothing is added when an entry is malformed or the list or one
+    /// of its ClOrdIDs is already known.
+    pub fn add_order_list(
+        &self,
+        sender_comp_id: &str,
+        list_id: &str,
+        entries: &[IndexMap<String, String>],
+        msg_seq_num: Option<u64>,
+    ) -> Result<Vec<Order>, Box<dyn std::error::Error>> {
+        let mut orders = self.orders.write().unwrap();
+        let list_key = (sender_comp_id.to_string(), list_id.to_string());
+        if self.indexes.read().unwrap().by_list.contains_key(&list_key) {
+            return Err(format!("List {} already exists", list_id).into());
+        }
+        let mut added: Vec<Order> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let mut order = order_from_message(entry)?;
+            order.sender_comp_id = sender_comp_id.to_string();
+            order.list_id = Some(list_id.to_string());
+            order.list_seq_no = entry
+                .get("ListSeqNo")
+                .and_then(|list_seq_no| list_seq_no.parse().ok())
+                .unwrap_or_default();
+            if orders.contains_key(&order.key()) || added.iter().any(|other| other.id == order.id)
+            {
+                return Err(format!("Duplicate ClOrdID {}", order.id).into());
+            }
+            let placed = [
+                ("ListID", list_id.to_string()),
+                ("ListSeqNo", entry.get("ListSeqNo").cloned().unwrap_or_default()),
+                ("OrdStatus", order.ordstatus.clone()),
+            ];
+            let changes = placed
+                .into_iter()
+                .map(|(field, new_value)| FieldChange {
+                    field: field.to_string(),
+                    old_value: String::new(),
+                    new_value,
+                })
+                .collect();
+            order.audit(msg_seq_num, changes);
+            added.push(order);
+        }
+        let changes = added
+            .iter()
+            .map(|order| OrderChange::Put(Box::new(order.clone())))
+            .collect();
+        self.journal(&mut orders, changes)?;
+        Ok(added)
+    }
+
+    /// Applies a cancel/replace request to the order with the original key: the changed
+    /// fields are recorded in its history and the order continues under the new ClOrdID, of
+    /// the same counterparty. Fails with an `IllegalTransition` when the order is no longer
+    /// open, and with `OrderError::DuplicateClOrdID` when another order of the counterparty
+    /// already has the new ClOrdID.
+    pub fn replace_order(
+        &self,
+        orig_key: &OrderKey,
+        replacement: Order,
+        msg_seq_num: Option<u64>,
+    ) -> Result<OrderAuditEntry, Box<dyn std::error::Error>> {
+        let mut orders = self.orders.write().unwrap();
+        let order = orders.get(orig_key).ok_or("Order ID not found")?;
+        let from = order.status();
+        if !from.can_become(OrdStatus::Replaced) {
+            return Err(IllegalTransition {
+                order_id: orig_key.cl_ord_id.clone(),
+                from,
+                to: OrdStatus::Replaced,
+            }
+            .into());
+        }
+        let new_key = OrderKey::new(&orig_key.sender_comp_id, &replacement.id);
+        if new_key != *orig_key && orders.contains_key(&new_key) {
+            return Err(Box::new(OrderError::DuplicateClOrdID(replacement.id)));
+        }
+        let mut order = orders[orig_key].clone();
+        let before = order.clone();
+        order.id = replacement.id;
+        order.symbol = replacement.symbol;
+        order.side = replacement.side;
+        order.quantity = replacement.quantity;
+        order.price = replacement.price;
+        order.ordtype = replacement.ordtype;
+        order.timeinforce = replacement.timeinforce;
+        order.transacttime = replacement.transacttime;
+        order.ordstatus = OrdStatus::Replaced.as_str().to_string();
+        let changes = order.changes_since(&before);
+        let entry = order.audit(msg_seq_num, changes);
+        self.journal(&mut orders, vec![OrderChange::update(orig_key, order)])?;
+        Ok(entry)
     }
 
-    pub fn get_order(&self, order_id: u64) -> Option<Order> {
+    /// Returns the audit trail of the order: every change since it was added, with the time
+    /// and the MsgSeqNum of the message which caused it.
+    pub fn order_history(&self, key: &OrderKey) -> Option<Vec<OrderAuditEntry>> {
         let orders = self.orders.read().unwrap();
-        orders.get(&order_id).cloned()
+        orders.get(key).map(|order| order.history.clone())
     }
 
-    pub fn remove_order(&self, order_id: u64) -> Result<(), Box<dyn std::error::Error>> {
-        {
-            let mut orders = self.orders.write().unwrap();
-            orders.remove(&order_id);
-        } // Release the orders lock here before persisting
-        self.persist()?;
-        Ok(())
+    pub fn get_order(&self, key: &OrderKey) -> Option<Order> {
+        let orders = self.orders.read().unwrap();
+        orders.get(key).cloned()
+    }
+
+    /// Looks an order up by the OrderID the acceptor assigned to it.
+    pub fn find_by_order_id(&self, order_id: &str) -> Option<Order> {
+        self.indexed(|indexes| indexes.by_order_id.get(order_id))
+            .into_iter()
+            .next()
     }
 
-    fn persist(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let serialized_orders;
-        {
-            let orders = self.orders.read().unwrap();
-            serialized_orders = bincode::serialize(&*orders, bincode::Infinite)?;
-        } // Release the orders lock after serialization
+    /// Orders are kept with their history once final, so only tests take one out.
+    #[cfg(test)]
+    pub fn remove_order(&self, key: &OrderKey) -> Result<(), Box<dyn std::error::Error>> {
+        let mut orders = self.orders.write().unwrap();
+        self.journal(&mut orders, vec![OrderChange::Remove(key.clone())])
+    }
+
+    /// Records an execution of the order, see `Order::record_fill`. Returns the updated order.
+    pub fn fill_order(
+        &self,
+        key: &OrderKey,
+        quantity: Decimal,
+        price: Decimal,
+    ) -> Result<Order, Box<dyn std::error::Error>> {
+        let mut orders = self.orders.write().unwrap();
+        let mut order = orders.get(key).ok_or("Order ID not found")?.clone();
+        let before = order.clone();
+        order.record_fill(quantity, price)?;
+        let changes = order.changes_since(&before);
+        order.audit(None, changes);
+        self.journal(&mut orders, vec![OrderChange::update(key, order.clone())])?;
+        Ok(order)
+    }
 
-        if serialized_orders.len() > self.mmap.read().unwrap().len() {
-            return Err("Serialized data exceeds mmap size".into());
+    /// Changes the OrdStatus of the order, keeping its executions. Returns the updated order,
+    /// or an `IllegalTransition` when its lifecycle does not allow the change.
+    pub fn set_status(
+        &self,
+        key: &OrderKey,
+        ordstatus: OrdStatus,
+        msg_seq_num: Option<u64>,
+    ) -> Result<Order, Box<dyn std::error::Error>> {
+        let mut orders = self.orders.write().unwrap();
+        let mut order = orders.get(key).ok_or("Order ID not found")?.clone();
+        let before = order.clone();
+        order.transition(ordstatus)?;
+        let changes = order.changes_since(&before);
+        order.audit(msg_seq_num, changes);
+        self.journal(&mut orders, vec![OrderChange::update(key, order.clone())])?;
+        Ok(order)
+    }
+
+    /// Orders of the symbol, by SenderCompID and ClOrdID.
+    pub fn find_by_symbol(&self, symbol: &str) -> Vec<Order> {
+        self.indexed(|indexes| indexes.by_symbol.get(symbol))
+    }
+
+    /// Orders of the account still working, by SenderCompID and ClOrdID.
+    pub fn open_orders_for_account(&self, account: &str) -> Vec<Order> {
+        let mut orders = self.indexed(|indexes| indexes.by_account.get(account));
+        orders.retain(|order| order.is_open());
+        orders
+    }
+
+    /// Orders in the status, by SenderCompID and ClOrdID.
+    pub fn orders_in_status(&self, status: OrdStatus) -> Vec<Order> {
+        self.indexed(|indexes| indexes.by_status.get(&status))
+    }
+
+    /// Orders of the list `sender_comp_id` placed, under their current ClOrdID, by ListSeqNo.
+    pub fn list_orders(&self, sender_comp_id: &str, list_id: &str) -> Vec<Order> {
+        let list_key = (sender_comp_id.to_string(), list_id.to_string());
+        let mut orders = self.indexed(|indexes| indexes.by_list.get(&list_key));
+        orders.sort_by_key(|order| order.list_seq_no);
+        orders
+    }
+
+    pub fn query(&self, query: &OrderQuery) -> Vec<Order> {
+        match query {
+            OrderQuery::All => {
+                let mut orders: Vec<Order> =
+                    self.orders.read().unwrap().values().cloned().collect();
+                orders.sort_by_key(|order| order.key());
+                orders
+            }
+            OrderQuery::Symbol(symbol) => self.find_by_symbol(symbol),
+            OrderQuery::OpenForAccount(account) => self.open_orders_for_account(account),
+            OrderQuery::Status(status) => self.orders_in_status(*status),
         }
+    }
 
-        let mut mmap = self.mmap.write().unwrap();
-        mmap[..serialized_orders.len()].copy_from_slice(&serialized_orders);
-        mmap.flush()?;
-        Ok(())
+    fn indexed<F>(&self, ids: F) -> Vec<Order>
+    where
+        F: FnOnce(&OrderIndexes) -> Option<&BTreeSet<OrderKey>>,
+    {
+        let orders = self.orders.read().unwrap();
+        let indexes = self.indexes.read().unwrap();
+        ids(&indexes)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| orders.get(id).cloned())
+            .collect()
+    }
+
+    /// Total quantity still working on the account's open orders.
+    pub fn open_quantity(&self, account: &str) -> Decimal {
+        self.open_orders_for_account(account)
+            .iter()
+            .map(|order| order.leaves_qty())
+            .sum()
+    }
+
+    /// Marks every open order of the account as canceled and returns the canceled orders.
+    pub fn cancel_account_orders(
+        &self,
+        account: &str,
+    ) -> Result<Vec<Order>, Box<dyn std::error::Error>> {
+        self.cancel_orders_where(|order| order.account == account)
     }
 
-    pub fn load(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let orders;
-        {
-            let mmap = self.mmap.read().unwrap();
-            if mmap.is_empty() {
-                return Ok(());
+    /// Marks every open order as canceled, as the kill switch does, and returns them.
+    pub fn cancel_all_orders(&self) -> Result<Vec<Order>, Box<dyn std::error::Error>> {
+        self.cancel_orders_where(|_| true)
+    }
+
+    fn cancel_orders_where(
+        &self,
+        filter: impl Fn(&Order) -> bool,
+    ) -> Result<Vec<Order>, Box<dyn std::error::Error>> {
+        let mut canceled_orders = Vec::new();
+        let mut orders = self.orders.write().unwrap();
+        for before in orders.values().filter(|order| filter(order)) {
+            let mut order = before.clone();
+            if order.transition(OrdStatus::Canceled).is_ok() {
+                let changes = order.changes_since(before);
+                order.audit(None, changes);
+                canceled_orders.push(order);
             }
-            orders = bincode::deserialize(&mmap[..mmap.len()])?;
         }
+        if !canceled_orders.is_empty() {
+            let changes = canceled_orders
+                .iter()
+                .map(|order| OrderChange::update(&order.key(), order.clone()))
+                .collect();
+            self.journal(&mut orders, changes)?;
+        }
+        Ok(canceled_orders)
+    }
 
-        {
-            let mut orders_lock = self.orders.write().unwrap();
-            *orders_lock = orders;
+    /// Records the changes in the backend and, once it has them, applies them to `orders` and
+    /// the indexes; when the backend fails nothing changes. The orders lock is held meanwhile,
+    /// so the backend has the changes in the order they were applied.
+    fn journal(
+        &self,
+        orders: &mut HashMap<OrderKey, Order>,
+        changes: Vec<OrderChange>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.backend.lock().unwrap().append(&changes, orders)?;
+        let mut indexes = self.indexes.write().unwrap();
+        for change in changes {
+            indexes.update(&change);
+            change.apply(orders);
         }
         Ok(())
     }
 
     pub fn print_orders(&self) -> Result<String, FixError> {
         let orders = self.orders.read().unwrap();
-        let mut table = Table::new();
-        table.add_row(row![
-            "ID",
-            "Account",
-            "Symbol",
-            "Side",
-            "Quantity",
-            "Price",
-            "OrdType",
-            "TransactTime",
-            "OrdStatus"
-        ]);
+        Ok(orders_table(orders.values()))
+    }
+}
 
-        for order in orders.values() {
-            table.add_row(Row::new(vec![
-                Cell::new(&order.id.to_string()),
-                Cell::new(&order.account),
-                Cell::new(&order.symbol),
-                Cell::new(&order.side),
-                Cell::new(&order.quantity.to_string()),
-                Cell::new(&order.price.to_string()),
-                Cell::new(&order.ordtype),
-                Cell::new(&order.transacttime),
-                Cell::new(&order.ordstatus),
-            ]));
-        }
-        // table.printstd();
-        // Convert the table to a string
-        let table_string = format!("{}", table);
-        Ok(table_string)
+/// The orders as a table for the console.
+pub fn orders_table<'a>(orders: impl IntoIterator<Item = &'a Order>) -> String {
+    let mut table = Table::new();
+    table.add_row(row![
+        "ID",
+        "OrderID",
+        "Account",
+        "Symbol",
+        "Side",
+        "Quantity",
+        "Price",
+        "OrdType",
+        "TransactTime",
+        "OrdStatus",
+        "CumQty",
+        "LeavesQty",
+        "AvgPx"
+    ]);
+
+    for order in orders {
+        table.add_row(Row::new(vec![
+            Cell::new(&order.id),
+            Cell::new(&order.order_id),
+            Cell::new(&order.account),
+            Cell::new(&order.symbol),
+            Cell::new(&order.side),
+            Cell::new(&order.quantity.to_string()),
+            Cell::new(&order.price_text()),
+            Cell::new(&order.ordtype),
+            Cell::new(&order.transacttime),
+            Cell::new(&order.ordstatus),
+            Cell::new(&order.cum_qty.to_string()),
+            Cell::new(&order.leaves_qty().to_string()),
+            Cell::new(&order.avg_px.to_string()),
+        ]));
     }
+    table.to_string()
 }
 
-pub fn add_order_to_store(
-    order_store: Arc<OrderStore>,
+fn required_field<'a>(
+    msg_map: &'a IndexMap<String, String>,
+    field: &'static str,
+) -> Result<&'a String, OrderError> {
+    msg_map.get(field).ok_or(OrderError::MissingField(field))
+}
+
+fn decimal_field(
     msg_map: &IndexMap<String, String>,
-) -> Result<(), Box<dyn Error>> {
-    let order = Order {
-        id: msg_map
-            .get("ClOrdID")
-            .unwrap()
-            .to_string()
-            .parse()
-            .expect("Invalid ClOrdID"),
+    field: &'static str,
+) -> Result<Decimal, OrderError> {
+    let value = required_field(msg_map, field)?;
+    value.parse().map_err(|_| OrderError::InvalidField {
+        field,
+        value: value.clone(),
+    })
+}
+
+/// The OrdTypes priced by the Price field, by description and by value.
+const LIMIT_ORD_TYPES: [&str; 12] = [
+    "LIMIT",
+    "2",
+    "STOP_LIMIT",
+    "4",
+    "LIMIT_OR_BETTER",
+    "7",
+    "LIMIT_WITH_OR_WITHOUT",
+    "8",
+    "LIMIT_ON_CLOSE",
+    "B",
+    "FOREX_LIMIT",
+    "F",
+];
+
+/// Whether the OrdType is a limit order, whose Price is required.
+pub fn is_limit_order(ordtype: &str) -> bool {
+    LIMIT_ORD_TYPES.contains(&ordtype.to_uppercase().as_str())
+}
+
+/// Builds an order from a parsed NewOrderSingle or cancel/replace request. Fails on a missing
+/// field, an unparsable OrderQty or Price, an OrderQty which is not positive, or a limit order
+/// without a Price. Other orders keep a Price only when one is given.
+fn order_from_message(msg_map: &IndexMap<String, String>) -> Result<Order, OrderError> {
+    let quantity = decimal_field(msg_map, "OrderQty")?;
+    if quantity <= Decimal::ZERO {
+        return Err(OrderError::InvalidField {
+            field: "OrderQty",
+            value: quantity.to_string(),
+        });
+    }
+    let ordtype = required_field(msg_map, "OrdType")?;
+    let price = match msg_map.get("Price") {
+        Some(_) => Some(decimal_field(msg_map, "Price")?),
+        None if is_limit_order(ordtype) => return Err(OrderError::MissingField("Price")),
+        None => None,
+    };
+    Ok(Order {
+        id: required_field(msg_map, "ClOrdID")?.to_string(),
+        sender_comp_id: String::new(),
+        order_id: msg_map
+            .get("OrderID")
+            .unwrap_or(&"".to_string())
+            .to_string(),
         account: msg_map
             .get("Account")
             .unwrap_or(&"".to_string())
             .to_string(),
-        symbol: msg_map.get("Symbol").unwrap().to_string(),
-        side: msg_map.get("Side").unwrap().to_string(),
-        quantity: msg_map
-            .get("OrderQty")
-            .unwrap()
-            .to_string()
-            .parse()
-            .expect("Invalid OrderQty"),
-        price: msg_map
-            .get("Price")
-            .unwrap()
-            .to_string()
-            .parse()
-            .expect("Invalid Price"),
-        ordtype: msg_map.get("OrdType").unwrap().to_string(),
-        transacttime: msg_map.get("TransactTime").unwrap().to_string(),
-        ordstatus: msg_map.get("OrdStatus").unwrap().to_string(),
-    };
-    // order_store.add_order(order)?;
-    match order_store.add_order(order.clone()) {
-        Ok(_) => info!("Order added successfully: {:?}", order),
-        Err(err) => error!("Failed to add order: {}", err),
-    }
-    Ok(())
+        symbol: required_field(msg_map, "Symbol")?.to_string(),
+        side: required_field(msg_map, "Side")?.to_string(),
+        quantity,
+        price,
+        ordtype: ordtype.to_string(),
+        transacttime: required_field(msg_map, "TransactTime")?.to_string(),
+        ordstatus: required_field(msg_map, "OrdStatus")?.to_string(),
+        timeinforce: msg_map
+            .get("TimeInForce")
+            .unwrap_or(&"".to_string())
+            .to_string(),
+        cum_qty: Decimal::ZERO,
+        avg_px: Decimal::ZERO,
+        list_id: None,
+        list_seq_no: 0,
+        history: Vec::new(),
+    })
+}
+
+/// MsgSeqNum of a parsed message, for the audit trail of the orders it changes.
+pub fn msg_seq_num(msg_map: &IndexMap<String, String>) -> Option<u64> {
+    msg_map.get("MsgSeqNum").and_then(|value| value.parse().ok())
 }
 
-pub fn update_order_in_store(
+/// Adds the order of a NewOrderSingle placed by `sender_comp_id`, empty for the engine's own.
+/// Only a malformed message is an error; a failure to persist the store is logged.
+pub fn add_order_to_store(
     order_store: Arc<OrderStore>,
+    sender_comp_id: &str,
     msg_map: &IndexMap<String, String>,
-) -> Result<(), Box<dyn Error>> {
-    let order = Order {
-        id: msg_map
-            .get("ClOrdID")
-            .unwrap()
-            .to_string()
-            .parse()
-            .expect("Invalid ClOrdID"),
-        account: msg_map
-            .get("Account")
-            .unwrap_or(&"".to_string())
-            .to_string(),
-        symbol: msg_map.get("Symbol").unwrap().to_string(),
-        side: msg_map.get("Side").unwrap().to_string(),
-        quantity: msg_map
-            .get("OrderQty")
-            .unwrap()
-            .to_string()
-            .parse()
-            .expect("Invalid OrderQty"),
-        price: msg_map
-            .get("Price")
-            .unwrap()
-            .to_string()
-            .parse()
-            .expect("Invalid Price"),
-        ordtype: msg_map.get("OrdType").unwrap().to_string(),
-        transacttime: msg_map.get("TransactTime").unwrap().to_string(),
-        ordstatus: msg_map.get("OrdStatus").unwrap().to_string(),
-    };
-    // order_store.update_order(order)?;
-    match order_store.update_order(order.clone()) {
-        Ok(_) => info!("Order updated successfully: {:?}", order),
-        Err(err) => error!("Failed to update order: {}", err),
+) -> Result<(), OrderError> {
+    let mut order = order_from_message(msg_map)?;
+    order.sender_comp_id = sender_comp_id.to_string();
+    match order_store.add_order(order.clone(), msg_seq_num(msg_map)) {
+        Ok(_) => info!("Order added successfully: {:?}", order),
+        Err(err) => match err.downcast::<OrderError>() {
+            Ok(err) => return Err(*err),
+            Err(err) => error!("Failed to add order: {}", err),
+        },
     }
     Ok(())
 }
 
-pub fn remove_order_from_store(
+/// Applies the cancel/replace request of `sender_comp_id` to its order with the OrigClOrdID.
+pub fn replace_order_in_store(
     order_store: Arc<OrderStore>,
+    sender_comp_id: &str,
     msg_map: &IndexMap<String, String>,
-) -> Result<(), Box<dyn Error>> {
-    let order_id = msg_map
-        .get("ClOrdID")
-        .unwrap()
-        .to_string()
-        .parse()
-        .expect("Invalid ClOrdID");
-    // order_store.remove_order(order_id)?;
-    match order_store.remove_order(order_id) {
-        Ok(_) => info!("Order removed successfully: {}", order_id),
-        Err(err) => error!("Failed to remove order: {}", err),
+) -> Result<(), OrderError> {
+    let orig_order_id = required_field(msg_map, "OrigClOrdID")?;
+    let order = order_from_message(msg_map)?;
+    let orig_key = OrderKey::new(sender_comp_id, orig_order_id);
+    match order_store.replace_order(&orig_key, order, msg_seq_num(msg_map)) {
+        Ok(entry) => info!("Order replaced successfully: {:?}", entry),
+        // The counterparty is told, see handle_order_cancel_replace_request
+        Err(err) => match err.downcast::<IllegalTransition>() {
+            Ok(refused) => return Err((*refused).into()),
+            Err(err) => match err.downcast::<OrderError>() {
+                Ok(err) => return Err(*err),
+                Err(err) => error!("Failed to replace order {}: {}", orig_order_id, err),
+            },
+        },
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+    use tempfile::NamedTempFile;
+
+    fn dec(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    fn order_message(fields: &[(&str, &str)]) -> IndexMap<String, String> {
+        fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_order_history_records_every_change() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store = OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+        let new_order = [
+            ("ClOrdID", "ORD-1001/A"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "100"),
+            ("Price", "50"),
+            ("OrdType", "LIMIT"),
+            ("TimeInForce", "DAY"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "New"),
+        ];
+        order_store
+            .add_order(order_from_message(&order_message(&new_order)).unwrap(), Some(2))
+            .unwrap();
+
+        let mut replace = order_message(&new_order);
+        replace.insert("ClOrdID".to_string(), "ORD-1001/B".to_string());
+        replace.insert("OrderQty".to_string(), "200".to_string());
+        replace.insert("TimeInForce".to_string(), "GOOD_TILL_CANCEL".to_string());
+        replace.insert("OrdStatus".to_string(), "Replaced".to_string());
+        let entry = order_store
+            .replace_order(
+                &OrderKey::own("ORD-1001/A"),
+                order_from_message(&replace).unwrap(),
+                Some(5),
+            )
+            .unwrap();
+
+        let change = |field: &str, old_value: &str, new_value: &str| FieldChange {
+            field: field.to_string(),
+            old_value: old_value.to_string(),
+            new_value: new_value.to_string(),
+        };
+        assert_eq!(entry.msg_seq_num, Some(5));
+        assert_eq!(entry.cl_ord_id, "ORD-1001/B");
+        assert_eq!(
+            entry.changes,
+            vec![
+                change("ClOrdID", "ORD-1001/A", "ORD-1001/B"),
+                change("OrderQty", "100", "200"),
+                change("TimeInForce", "DAY", "GOOD_TILL_CANCEL"),
+                change("OrdStatus", "New", "Replaced"),
+            ]
+        );
+        assert!(order_store.get_order(&OrderKey::own("ORD-1001/A")).is_none());
+        let replaced = order_store.get_order(&OrderKey::own("ORD-1001/B")).unwrap();
+        assert_eq!(replaced.quantity, dec("200"));
+
+        // Executions are made by the engine itself, not in response to a message
+        order_store.fill_order(&OrderKey::own("ORD-1001/B"), dec("200"), dec("50")).unwrap();
+        let history = order_store.order_history(&OrderKey::own("ORD-1001/B")).unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].msg_seq_num, Some(2));
+        assert_eq!(history[0].changes, vec![change("OrdStatus", "", "New")]);
+        assert_eq!(history[1], entry);
+        assert_eq!(history[2].msg_seq_num, None);
+        assert_eq!(
+            history[2].changes,
+            vec![
+                change("OrdStatus", "Replaced", "Filled"),
+                change("CumQty", "0", "200"),
+                change("AvgPx", "0", "50"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_order_history_is_kept_through_restarts() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let order_store = Arc::new(OrderStore::new(path, 4096).unwrap());
+        let mut fields = order_message(&[
+            ("ClOrdID", "1"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "100"),
+            ("Price", "50"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "New"),
+        ]);
+        fields.insert("MsgSeqNum".to_string(), "3".to_string());
+        add_order_to_store(Arc::clone(&order_store), "", &fields).unwrap();
+        order_store.fill_order(&OrderKey::own("1"), dec("30"), dec("50")).unwrap();
+        order_store.set_status(&OrderKey::own("1"), OrdStatus::Canceled, Some(9)).unwrap();
+        let history = order_store.order_history(&OrderKey::own("1")).unwrap();
+        drop(order_store);
+
+        let order_store = OrderStore::new(path, 4096).unwrap();
+        assert_eq!(order_store.order_history(&OrderKey::own("1")).unwrap(), history);
+        assert!(order_store.order_history(&OrderKey::own("2")).is_none());
+        let seq_nums: Vec<_> = history.iter().map(|entry| entry.msg_seq_num).collect();
+        assert_eq!(seq_nums, vec![Some(3), None, Some(9)]);
+        let canceled = &history[2];
+        assert_eq!(canceled.cl_ord_id, "1");
+        assert_eq!(canceled.changes[0].old_value, "Partially filled");
+        assert_eq!(canceled.changes[0].new_value, "Canceled");
+        // Recorded in the order they were made, to the millisecond
+        assert!(history.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+        assert!(NaiveDateTime::parse_from_str(&canceled.timestamp, "%Y%m%d-%H:%M:%S%.3f").is_ok());
+    }
+
+    #[test]
+    fn test_cl_ord_id_is_any_string() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let order_store = Arc::new(OrderStore::new(path, 4096).unwrap());
+        let mut fields = order_message(&[
+            ("ClOrdID", "20240101-ABC/7 x"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "100"),
+            ("Price", "50"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "New"),
+        ]);
+        add_order_to_store(Arc::clone(&order_store), "", &fields).unwrap();
+        let order = order_store.get_order(&OrderKey::own("20240101-ABC/7 x")).unwrap();
+        assert_eq!(order.id, "20240101-ABC/7 x");
+
+        fields.insert("OrigClOrdID".to_string(), "20240101-ABC/7 x".to_string());
+        fields.insert("ClOrdID".to_string(), "0042".to_string());
+        fields.insert("OrdStatus".to_string(), "Replaced".to_string());
+        replace_order_in_store(Arc::clone(&order_store), "", &fields).unwrap();
+        drop(order_store);
+
+        // Kept as sent, leading zeros included, through a restart
+        let order_store = OrderStore::new(path, 4096).unwrap();
+        assert!(order_store.get_order(&OrderKey::own("20240101-ABC/7 x")).is_none());
+        assert!(order_store.get_order(&OrderKey::own("42")).is_none());
+        let order = order_store.get_order(&OrderKey::own("0042")).unwrap();
+        assert_eq!(order.history[1].changes[0].old_value, "20240101-ABC/7 x");
+    }
+
+    #[test]
+    fn test_fill_order_tracks_cum_qty_and_avg_px() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store = OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+        order_store
+            .add_order(order_from_message(&order_message(&[
+                ("ClOrdID", "1"),
+                ("Symbol", "IBM"),
+                ("Side", "BUY"),
+                ("OrderQty", "100"),
+                ("Price", "50.25"),
+                ("OrdType", "LIMIT"),
+                ("TransactTime", "20240101-00:00:00"),
+                ("OrdStatus", "New"),
+            ]))
+            .unwrap(), None)
+            .unwrap();
+        assert_eq!(order_store.get_order(&OrderKey::own("1")).unwrap().leaves_qty(), dec("100"));
+
+        let order = order_store.fill_order(&OrderKey::own("1"), dec("40"), dec("50.25")).unwrap();
+        assert_eq!((order.cum_qty, order.leaves_qty()), (dec("40"), dec("60")));
+        assert_eq!(order.ordstatus, "Partially filled");
+
+        // Replacing keeps the executions
+        let mut replace = order_message(&[
+            ("ClOrdID", "2"),
+            ("OrigClOrdID", "1"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "80"),
+            ("Price", "49"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:01"),
+        ]);
+        replace.insert("OrdStatus".to_string(), "Replaced".to_string());
+        order_store
+            .replace_order(&OrderKey::own("1"), order_from_message(&replace).unwrap(), None)
+            .unwrap();
+        let order = order_store.fill_order(&OrderKey::own("2"), dec("40"), dec("49")).unwrap();
+        assert_eq!((order.cum_qty, order.leaves_qty()), (dec("80"), dec("0")));
+        assert_eq!(order.avg_px.to_string(), "49.625");
+        assert_eq!(order.ordstatus, "Filled");
+        assert_eq!(order_store.open_quantity(""), Decimal::ZERO);
+
+        // A filled order can no longer be canceled
+        assert!(order_store.set_status(&OrderKey::own("2"), OrdStatus::Canceled, None).is_err());
+    }
+
+    #[test]
+    fn test_fractional_prices_and_quantities_are_kept() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let order_store = Arc::new(OrderStore::new(path, 4096).unwrap());
+        let fields = order_message(&[
+            ("ClOrdID", "1"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "12.5"),
+            ("Price", "187.45"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "New"),
+        ]);
+        add_order_to_store(Arc::clone(&order_store), "", &fields).unwrap();
+        let order = order_store.fill_order(&OrderKey::own("1"), dec("2.5"), dec("187.45")).unwrap();
+        assert_eq!((order.cum_qty, order.leaves_qty()), (dec("2.5"), dec("10")));
+        let order = order_store.fill_order(&OrderKey::own("1"), dec("10"), dec("187.46")).unwrap();
+        assert_eq!(order.avg_px.to_string(), "187.458");
+        assert_eq!(order.status(), OrdStatus::Filled);
+        drop(order_store);
+
+        let order = OrderStore::new(path, 4096).unwrap().get_order(&OrderKey::own("1")).unwrap();
+        assert_eq!((order.quantity, order.cum_qty), (dec("12.5"), dec("12.5")));
+        assert_eq!(order.price_text(), "187.45");
+        assert_eq!(order.avg_px.to_string(), "187.458");
+    }
+
+    #[test]
+    fn test_illegal_transitions_are_refused() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store = OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+        let new_order = [
+            ("ClOrdID", "1"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "100"),
+            ("Price", "50"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "New"),
+        ];
+        order_store
+            .add_order(order_from_message(&order_message(&new_order)).unwrap(), None)
+            .unwrap();
+        order_store.fill_order(&OrderKey::own("1"), dec("100"), dec("50")).unwrap();
+
+        let err = order_store
+            .set_status(&OrderKey::own("1"), OrdStatus::Canceled, None)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<IllegalTransition>(),
+            Some(&IllegalTransition {
+                order_id: "1".to_string(),
+                from: OrdStatus::Filled,
+                to: OrdStatus::Canceled,
+            })
+        );
+        assert!(order_store.fill_order(&OrderKey::own("1"), dec("10"), dec("50")).is_err());
+        let mut replace = order_message(&new_order);
+        replace.insert("ClOrdID".to_string(), "2".to_string());
+        assert!(order_store
+            .replace_order(&OrderKey::own("1"), order_from_message(&replace).unwrap(), None)
+            .unwrap_err()
+            .is::<IllegalTransition>());
+        let order = order_store.get_order(&OrderKey::own("1")).unwrap();
+        assert_eq!((order.status(), order.cum_qty), (OrdStatus::Filled, dec("100")));
+
+        // Canceling a partially filled order keeps its executions
+        let mut other_order = order_message(&new_order);
+        other_order.insert("ClOrdID".to_string(), "3".to_string());
+        order_store
+            .add_order(order_from_message(&other_order).unwrap(), None)
+            .unwrap();
+        order_store.fill_order(&OrderKey::own("3"), dec("30"), dec("50")).unwrap();
+        let order = order_store.set_status(&OrderKey::own("3"), OrdStatus::Canceled, None).unwrap();
+        assert_eq!((order.cum_qty, order.leaves_qty()), (dec("30"), dec("0")));
+        assert!(order_store.set_status(&OrderKey::own("3"), OrdStatus::Canceled, None).is_err());
+
+        assert!(OrdStatus::New.can_become(OrdStatus::Rejected));
+        assert!(!OrdStatus::PartiallyFilled.can_become(OrdStatus::Rejected));
+        assert!(OrdStatus::Replaced.can_become(OrdStatus::Canceled));
+        assert!(!OrdStatus::Canceled.can_become(OrdStatus::Replaced));
+    }
+
+    #[test]
+    fn test_malformed_order_messages_are_errors() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store =
+            Arc::new(OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap());
+        let mut fields = vec![
+            ("ClOrdID", "1"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "ten"),
+            ("Price", "50"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "New"),
+        ];
+        assert_eq!(
+            add_order_to_store(Arc::clone(&order_store), "", &order_message(&fields)),
+            Err(OrderError::InvalidField {
+                field: "OrderQty",
+                value: "ten".to_string()
+            })
+        );
+        fields[3] = ("OrderQty", "0");
+        assert!(add_order_to_store(Arc::clone(&order_store), "", &order_message(&fields)).is_err());
+        fields[3] = ("OrderQty", "10");
+        fields.retain(|(field, _)| *field != "Symbol");
+        assert_eq!(
+            add_order_to_store(Arc::clone(&order_store), "", &order_message(&fields)),
+            Err(OrderError::MissingField("Symbol"))
+        );
+        assert!(order_store.get_order(&OrderKey::own("1")).is_none());
+        assert_eq!(
+            replace_order_in_store(order_store, "", &order_message(&fields)),
+            Err(OrderError::MissingField("OrigClOrdID"))
+        );
+    }
+
+    #[test]
+    fn test_price_is_only_required_of_limit_orders() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store =
+            Arc::new(OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap());
+        let mut fields = order_message(&[
+            ("ClOrdID", "1"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "100"),
+            ("OrdType", "MARKET"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "New"),
+        ]);
+        add_order_to_store(Arc::clone(&order_store), "", &fields).unwrap();
+        let market = order_store.get_order(&OrderKey::own("1")).unwrap();
+        assert_eq!(market.price, None);
+        assert_eq!(market.price_text(), "");
+
+        fields.insert("ClOrdID".to_string(), "2".to_string());
+        fields.insert("OrdType".to_string(), "2".to_string());
+        assert_eq!(
+            add_order_to_store(Arc::clone(&order_store), "", &fields),
+            Err(OrderError::MissingField("Price"))
+        );
+
+        // A fractional Price is kept to the digit
+        fields.insert("Price".to_string(), "187.0625".to_string());
+        add_order_to_store(Arc::clone(&order_store), "", &fields).unwrap();
+        let limit = order_store.get_order(&OrderKey::own("2")).unwrap();
+        assert_eq!(limit.price, Some(dec("187.0625")));
+        assert_eq!(limit.price_text(), "187.0625");
+        fields.insert("Price".to_string(), "187,5".to_string());
+        assert!(add_order_to_store(order_store, "", &fields).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_cl_ord_id_is_refused() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store =
+            Arc::new(OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap());
+        let mut fields = order_message(&[
+            ("ClOrdID", "1"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "100"),
+            ("Price", "50"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "New"),
+        ]);
+        add_order_to_store(Arc::clone(&order_store), "", &fields).unwrap();
+
+        fields.insert("OrderQty".to_string(), "300".to_string());
+        let refused = add_order_to_store(Arc::clone(&order_store), "", &fields).unwrap_err();
+        assert_eq!(refused, OrderError::DuplicateClOrdID("1".to_string()));
+        assert_eq!(refused.ord_rej_reason(), Some("6"));
+        // The known order is kept as it was
+        let order = order_store.get_order(&OrderKey::own("1")).unwrap();
+        assert_eq!(order.quantity, dec("100"));
+        assert_eq!(order.history.len(), 1);
+    }
+
+    #[test]
+    fn test_cl_ord_ids_are_unique_per_counterparty() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let order_store = Arc::new(OrderStore::new(path, 4096).unwrap());
+        let mut fields = order_message(&[
+            ("ClOrdID", "1"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "100"),
+            ("Price", "50"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "New"),
+        ]);
+        add_order_to_store(Arc::clone(&order_store), "ABC", &fields).unwrap();
+        fields.insert("Side".to_string(), "SELL".to_string());
+        add_order_to_store(Arc::clone(&order_store), "XYZ", &fields).unwrap();
+        assert_eq!(
+            add_order_to_store(Arc::clone(&order_store), "XYZ", &fields),
+            Err(OrderError::DuplicateClOrdID("1".to_string()))
+        );
+
+        // XYZ replaces its own order 1, the order of ABC is left as it was
+        fields.insert("OrigClOrdID".to_string(), "1".to_string());
+        fields.insert("ClOrdID".to_string(), "2".to_string());
+        fields.insert("OrderQty".to_string(), "300".to_string());
+        fields.insert("OrdStatus".to_string(), "Replaced".to_string());
+        replace_order_in_store(Arc::clone(&order_store), "XYZ", &fields).unwrap();
+        drop(order_store);
+
+        let order_store = OrderStore::new(path, 4096).unwrap();
+        let abc = order_store.get_order(&OrderKey::new("ABC", "1")).unwrap();
+        assert_eq!((abc.side.as_str(), abc.quantity), ("BUY", dec("100")));
+        assert!(order_store.get_order(&OrderKey::new("XYZ", "1")).is_none());
+        let xyz = order_store.get_order(&OrderKey::new("XYZ", "2")).unwrap();
+        assert_eq!((xyz.side.as_str(), xyz.quantity), ("SELL", dec("300")));
+        assert_eq!(xyz.sender_comp_id, "XYZ");
+        assert!(order_store.get_order(&OrderKey::own("1")).is_none());
+        assert_eq!(order_store.find_by_symbol("IBM").len(), 2);
+    }
+
+    #[test]
+    fn test_replace_with_known_cl_ord_id_is_refused() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store =
+            Arc::new(OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap());
+        let mut fields = order_message(&[
+            ("ClOrdID", "1"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "100"),
+            ("Price", "50"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "New"),
+        ]);
+        add_order_to_store(Arc::clone(&order_store), "", &fields).unwrap();
+        fields.insert("ClOrdID".to_string(), "2".to_string());
+        fields.insert("OrderQty".to_string(), "200".to_string());
+        add_order_to_store(Arc::clone(&order_store), "", &fields).unwrap();
+
+        // Replacing order 1 under the ClOrdID of the live order 2
+        fields.insert("OrigClOrdID".to_string(), "1".to_string());
+        fields.insert("OrderQty".to_string(), "300".to_string());
+        fields.insert("OrdStatus".to_string(), "Replaced".to_string());
+        assert_eq!(
+            replace_order_in_store(Arc::clone(&order_store), "", &fields),
+            Err(OrderError::DuplicateClOrdID("2".to_string()))
+        );
+        // Both orders are kept as they were
+        let original = order_store.get_order(&OrderKey::own("1")).unwrap();
+        assert_eq!(original.quantity, dec("100"));
+        assert_eq!(original.ordstatus, "New");
+        assert_eq!(original.history.len(), 1);
+        let live = order_store.get_order(&OrderKey::own("2")).unwrap();
+        assert_eq!(live.quantity, dec("200"));
+        assert_eq!(live.history.len(), 1);
+    }
+
+    #[test]
+    fn test_orders_are_loaded_on_restart() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let order_store = OrderStore::new(path, 4096).unwrap();
+        assert!(order_store.get_order(&OrderKey::own("1")).is_none());
+        let mut order = order_from_message(&order_message(&[
+            ("ClOrdID", "1"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "100"),
+            ("Price", "50"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "0"),
+        ]))
+        .unwrap();
+        // Executions recorded under a status which was not updated
+        order.cum_qty = dec("40");
+        order_store.add_order(order, None).unwrap();
+        drop(order_store);
+
+        let order_store = OrderStore::new(path, 4096).unwrap();
+        let order = order_store.get_order(&OrderKey::own("1")).unwrap();
+        assert_eq!(order.status(), OrdStatus::PartiallyFilled);
+        assert_eq!(order.ordstatus, "Partially filled");
+        assert_eq!(order.leaves_qty(), dec("60"));
+    }
+
+    #[test]
+    fn test_orders_are_queried_through_indexes() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store = OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+        let orders = [("1", "ACC1", "IBM"), ("2", "ACC1", "MSFT"), ("3", "ACC2", "IBM")];
+        for (cl_ord_id, account, symbol) in orders {
+            let order_id = format!("O{}", cl_ord_id);
+            let order = order_from_message(&order_message(&[
+                ("ClOrdID", cl_ord_id),
+                ("OrderID", &order_id),
+                ("Account", account),
+                ("Symbol", symbol),
+                ("Side", "BUY"),
+                ("OrderQty", "100"),
+                ("Price", "50"),
+                ("OrdType", "LIMIT"),
+                ("TransactTime", "20240101-00:00:00"),
+                ("OrdStatus", "New"),
+            ]))
+            .unwrap();
+            order_store.add_order(order, None).unwrap();
+        }
+        order_store.fill_order(&OrderKey::own("1"), dec("100"), dec("50")).unwrap();
+        order_store.remove_order(&OrderKey::own("3")).unwrap();
+
+        let ids = |orders: Vec<Order>| -> Vec<String> {
+            orders.into_iter().map(|order| order.id).collect()
+        };
+        assert_eq!(ids(order_store.find_by_symbol("IBM")), vec!["1"]);
+        assert_eq!(ids(order_store.open_orders_for_account("ACC1")), vec!["2"]);
+        assert_eq!(ids(order_store.orders_in_status(OrdStatus::Filled)), vec!["1"]);
+        assert_eq!(ids(order_store.orders_in_status(OrdStatus::New)), vec!["2"]);
+        assert_eq!(ids(order_store.query(&OrderQuery::All)), vec!["1", "2"]);
+        assert_eq!(order_store.open_quantity("ACC1"), dec("100"));
+
+        // The OrderID stays with the order through a replacement
+        let replacement = order_from_message(&order_message(&[
+            ("ClOrdID", "2b"),
+            ("Symbol", "MSFT"),
+            ("Side", "BUY"),
+            ("OrderQty", "200"),
+            ("Price", "50"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:01"),
+            ("OrdStatus", "New"),
+        ]))
+        .unwrap();
+        order_store.replace_order(&OrderKey::own("2"), replacement, None).unwrap();
+        assert_eq!(order_store.find_by_order_id("O2").unwrap().id, "2b");
+        assert_eq!(order_store.find_by_order_id("O1").unwrap().id, "1");
+        assert!(order_store.find_by_order_id("O3").is_none());
+        assert!(order_store.find_by_order_id("").is_none());
+    }
+
+    struct FailingBackend;
+
+    impl OrderBackend for FailingBackend {
+        fn append(
+            &mut self,
+            _changes: &[OrderChange],
+            _orders: &HashMap<OrderKey, Order>,
+        ) -> Result<(), Box<dyn Error>> {
+            Err("disk full".into())
+        }
+    }
+
+    #[test]
+    fn test_orders_are_unchanged_when_the_backend_fails() {
+        let order = order_from_message(&order_message(&[
+            ("ClOrdID", "1"),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+            ("OrderQty", "100"),
+            ("Price", "50"),
+            ("OrdType", "LIMIT"),
+            ("TransactTime", "20240101-00:00:00"),
+            ("OrdStatus", "New"),
+        ]))
+        .unwrap();
+        let orders = HashMap::from([(order.key(), order.clone())]);
+        let order_store = OrderStore::with_backend(Box::new(FailingBackend), orders);
+
+        assert!(order_store.fill_order(&order.key(), dec("40"), dec("50")).is_err());
+        assert!(order_store.set_status(&order.key(), OrdStatus::Canceled, None).is_err());
+        assert!(order_store.cancel_all_orders().is_err());
+        let mut second = order.clone();
+        second.id = "2".to_string();
+        assert!(order_store.add_order(second, None).is_err());
+
+        let stored = order_store.get_order(&order.key()).unwrap();
+        assert_eq!((stored.cum_qty, stored.status()), (Decimal::ZERO, OrdStatus::New));
+        assert!(stored.history.is_empty());
+        assert!(order_store.get_order(&OrderKey::own("2")).is_none());
+        assert_eq!(order_store.orders_in_status(OrdStatus::New).len(), 1);
+    }
+
+    #[test]
+    fn test_order_lists_are_kept_through_restarts() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let order_store = OrderStore::new(path, 4096).unwrap();
+        let entry = |cl_ord_id: &str, list_seq_no: &str| {
+            order_message(&[
+                ("ClOrdID", cl_ord_id),
+                ("ListSeqNo", list_seq_no),
+                ("Symbol", "IBM"),
+                ("Side", "BUY"),
+                ("OrderQty", "100"),
+                ("Price", "50"),
+                ("OrdType", "LIMIT"),
+                ("TransactTime", "20240101-00:00:00"),
+                ("OrdStatus", "New"),
+            ])
+        };
+        let added = order_store
+            .add_order_list("", "L1", &[entry("B", "2"), entry("A", "1")], Some(7))
+            .unwrap();
+        assert_eq!(
+            (added[0].list_id.as_deref(), added[0].list_seq_no),
+            (Some("L1"), 2)
+        );
+        assert!(order_store
+            .add_order_list("", "L1", &[entry("C", "1")], None)
+            .is_err());
+        // A list with a known ClOrdID is refused as a whole
+        assert!(order_store
+            .add_order_list("", "L2", &[entry("C", "1"), entry("A", "2")], None)
+            .is_err());
+        assert!(order_store.get_order(&OrderKey::own("C")).is_none());
+        drop(order_store);
+
+        let order_store = OrderStore::new(path, 4096).unwrap();
+        let ids = |orders: Vec<Order>| -> Vec<String> {
+            orders.into_iter().map(|order| order.id).collect()
+        };
+        assert_eq!(ids(order_store.list_orders("", "L1")), vec!["A", "B"]);
+        assert!(order_store.list_orders("", "L2").is_empty());
+    }
+}
@@ -0,0 +1,466 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{error, info};
+
+use tracing::Span;
+
+use crate::buffer_pool::BufferPool;
+use crate::logging::log_message;
+use crate::throttle::{ThrottleAction, ThrottleConfig, TokenBucket};
+use crate::timer::on_timer_thread;
+use crate::transport::{split_messages, Transport};
+
+/// MsgTypes of session-level messages, which never wait for room in the queue nor count
+/// against the throttle.
+const ADMIN_MSG_TYPES: [&str; 7] = ["0", "1", "2", "3", "4", "5", "A"];
+
+/// MsgType of a BusinessMessageReject, never throttled so refusing a message above the rate
+/// is never silent.
+const BUSINESS_MESSAGE_REJECT: &str = "j";
+
+/// How long closing the session waits for queued messages (e.g. a Logout) to be written.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Default)]
+struct QueueState {
+    /// What was written at once, e.g. a batch, each kept whole.
+    writes: VecDeque<Vec<u8>>,
+    /// The writer is writing what it already took off the queue.
+    writing: bool,
+    closed: bool,
+}
+
+/// Messages waiting for the session's writer thread, bounded to `capacity` writes. Messages
+/// carry their MsgSeqNum once queued, so they keep their order on the wire. Application
+/// messages are held back before they take their number instead: `admit` waits for room and
+/// for the throttle, while admin messages go straight to the queue, behind nothing but what
+/// is already being written.
+pub struct OutboundQueue {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+    capacity: usize,
+    throttle: Option<(ThrottleAction, Mutex<TokenBucket>)>,
+}
+
+impl OutboundQueue {
+    pub fn new(capacity: usize, throttle: Option<ThrottleConfig>) -> Self {
+        Self {
+            state: Mutex::new(QueueState::default()),
+            changed: Condvar::new(),
+            capacity: capacity.max(1),
+            throttle: throttle.map(|config| (config.action, Mutex::new(TokenBucket::new(&config)))),
+        }
+    }
+
+    /// Lets application messages be sent. Waits while the queue is full, which slows the
+    /// sender down to the pace of the connection, and while a delaying throttle holds them
+    /// back. false when a rejecting throttle refuses them, all unless there is a token for
+    /// each. Timer tasks never wait, their messages go past the capacity and the delay.
+    pub fn admit(&self, application_messages: usize) -> bool {
+        if application_messages == 0 {
+            return true;
+        }
+        if !on_timer_thread() {
+            drop(self.room());
+        }
+        match &self.throttle {
+            Some((ThrottleAction::Reject, bucket)) => bucket
+                .lock()
+                .unwrap()
+                .try_take_many(application_messages, Instant::now())
+                .is_ok(),
+            Some((ThrottleAction::Delay, bucket)) => {
+                for _ in 0..application_messages {
+                    // Taken whether or not it is there yet, a timer task does not wait for it
+                    while let Err(wait) = bucket.lock().unwrap().try_take(Instant::now()) {
+                        if on_timer_thread() {
+                            break;
+                        }
+                        thread::sleep(wait);
+                    }
+                }
+                true
+            }
+            None => true,
+        }
+    }
+
+    /// Whether a rejecting throttle would refuse an application message sent now.
+    pub fn throttled(&self) -> bool {
+        match &self.throttle {
+            Some((ThrottleAction::Reject, bucket)) => {
+                !bucket.lock().unwrap().has_token(Instant::now())
+            }
+            _ => false,
+        }
+    }
+
+    /// Queues what is written at once, to be written and flushed whole.
+    pub fn push(&self, write: Vec<u8>) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(io::Error::new(ErrorKind::BrokenPipe, "Session is closed"));
+        }
+        state.writes.push_back(write);
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    fn room(&self) -> MutexGuard<'_, QueueState> {
+        let mut state = self.state.lock().unwrap();
+        while !state.closed && state.writes.len() >= self.capacity {
+            state = self.changed.wait(state).unwrap();
+        }
+        state
+    }
+
+    /// Takes every write queued, waiting for one. Returns None once the queue is closed and
+    /// empty.
+    fn pop_all(&self) -> Option<Vec<Vec<u8>>> {
+        let mut state = self.state.lock().unwrap();
+        state.writing = false;
+        self.changed.notify_all();
+        loop {
+            if !state.writes.is_empty() {
+                state.writing = true;
+                self.changed.notify_all();
+                return Some(state.writes.drain(..).collect());
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    /// Refuses further messages and waits, at most DRAIN_TIMEOUT, for the queued ones to be
+    /// written. A timer task does not wait, what is not written by then is lost.
+    pub fn close(&self) {
+        let deadline = Instant::now() + DRAIN_TIMEOUT;
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        self.changed.notify_all();
+        if on_timer_thread() {
+            return;
+        }
+        while !state.writes.is_empty() || state.writing {
+            let now = Instant::now();
+            if now >= deadline {
+                error!(
+                    "Closing the session with {} write(s) not done",
+                    state.writes.len()
+                );
+                return;
+            }
+            state = self.changed.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+
+    /// Drops the queued messages once the connection can no longer be written to.
+    fn abandon(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.writing = false;
+        state.writes.clear();
+        self.changed.notify_all();
+    }
+
+    /// Writes waiting to be done.
+    pub fn depth(&self) -> usize {
+        self.state.lock().unwrap().writes.len()
+    }
+}
+
+/// The messages of what is about to be written which count against the capacity and the
+/// throttle, all but admin messages and BusinessMessageRejects.
+pub fn application_messages(buf: &[u8]) -> usize {
+    split_messages(buf)
+        .into_iter()
+        .filter(|message| {
+            let msg_type = message
+                .split(|&byte| byte == 0x01)
+                .find_map(|field| field.strip_prefix(b"35="))
+                .and_then(|msg_type| std::str::from_utf8(msg_type).ok())
+                .unwrap_or_default();
+            !ADMIN_MSG_TYPES.contains(&msg_type) && msg_type != BUSINESS_MESSAGE_REJECT
+        })
+        .count()
+}
+
+/// A session transport whose writes go through the outbound queue to a dedicated writer
+/// thread, so handlers never block on the socket. Reads go straight to the connection.
+/// Each write is queued in a buffer of the session's pool, given back once written; the
+/// writer writes out everything queued before it flushes.
+pub struct QueuedTransport {
+    connection: Box<dyn Transport>,
+    queue: Arc<OutboundQueue>,
+    buffers: Arc<BufferPool>,
+}
+
+impl QueuedTransport {
+    /// Starts the writer thread of the connection. The thread ends once the transport is
+    /// closed and the queue is drained.
+    pub fn start(
+        connection: Box<dyn Transport>,
+        capacity: usize,
+        throttle: Option<ThrottleConfig>,
+        buffers: Arc<BufferPool>,
+    ) -> io::Result<(Self, Arc<OutboundQueue>, JoinHandle<()>)> {
+        let queue = Arc::new(OutboundQueue::new(capacity, throttle));
+        let mut writer = connection.try_clone_transport()?;
+        let writer_queue = Arc::clone(&queue);
+        let writer_buffers = Arc::clone(&buffers);
+        // The writer logs in the span of the caller, the outbound side of the session
+        let span = Span::current();
+        let writer_handle = thread::spawn(move || {
+            let _entered = span.entered();
+            while let Some(writes) = writer_queue.pop_all() {
+                let written = writes
+                    .iter()
+                    .try_for_each(|write| writer.write_all(write))
+                    .and_then(|_| writer.flush());
+                if let Err(e) = written {
+                    error!(
+                        "Failed to write outbound message, closing the session: {}",
+                        e
+                    );
+                    writer_queue.abandon();
+                    if let Err(e) = writer.close() {
+                        error!("Failed to close the connection: {}", e);
+                    }
+                    break;
+                }
+                for write in writes {
+                    // Logged borrowed, copied only for a masked tag or a DATA field which is
+                    // not UTF-8
+                    for message in split_messages(&write) {
+                        log_message("sent out message", &String::from_utf8_lossy(message));
+                    }
+                    writer_buffers.give_back(write);
+                }
+            }
+            info!("Outbound writer stopped");
+        });
+        let transport = Self {
+            connection,
+            queue: Arc::clone(&queue),
+            buffers,
+        };
+        Ok((transport, queue, writer_handle))
+    }
+}
+
+impl Read for QueuedTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.connection.read(buf)
+    }
+}
+
+impl Write for QueuedTransport {
+    /// Queues the buffer whole, e.g. a batch, for the writer to write in one go.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pooled = self.buffers.take();
+        pooled.extend_from_slice(buf);
+        self.queue.push(pooled)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for QueuedTransport {
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(Self {
+            connection: self.connection.try_clone_transport()?,
+            queue: Arc::clone(&self.queue),
+            buffers: Arc::clone(&self.buffers),
+        }))
+    }
+
+    /// Writes out what is queued, then closes the connection.
+    fn close(&self) -> io::Result<()> {
+        self.queue.close();
+        self.connection.close()
+    }
+
+    fn peer(&self) -> String {
+        self.connection.peer()
+    }
+
+    fn outbound_queue(&self) -> Option<Arc<OutboundQueue>> {
+        Some(Arc::clone(&self.queue))
+    }
+
+    fn intercept_outbound<'a>(&self, message: &'a str) -> Option<Cow<'a, str>> {
+        self.connection.intercept_outbound(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timer::TimerScheduler;
+    use crate::transport::MemoryTransport;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_full_queue_holds_application_messages_only() {
+        let queue = Arc::new(OutboundQueue::new(1, None));
+        queue
+            .push(b"8=FIX.4.2\x0135=D\x0134=1\x01".to_vec())
+            .unwrap();
+        // Admin messages are queued past the capacity
+        assert!(queue.admit(0));
+        queue
+            .push(b"8=FIX.4.2\x0135=0\x0134=2\x01".to_vec())
+            .unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let blocked_queue = Arc::clone(&queue);
+        let blocked = thread::spawn(move || {
+            sender.send(blocked_queue.admit(1)).unwrap();
+        });
+        assert!(receiver.recv_timeout(Duration::from_millis(50)).is_err());
+
+        let writes = queue.pop_all().unwrap();
+        assert!(writes[0].starts_with(b"8=FIX.4.2\x0135=D\x0134=1"));
+        assert!(writes[1].starts_with(b"8=FIX.4.2\x0135=0\x0134=2"));
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)), Ok(true));
+        blocked.join().unwrap();
+        assert_eq!(queue.depth(), 0);
+    }
+
+    #[test]
+    fn test_timer_tasks_never_wait_for_room() {
+        let queue = Arc::new(OutboundQueue::new(1, None));
+        queue
+            .push(b"8=FIX.4.2\x0135=D\x0134=1\x01".to_vec())
+            .unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let timer_queue = Arc::clone(&queue);
+        TimerScheduler::start().schedule(Instant::now(), move || {
+            let admitted = timer_queue.admit(1);
+            let pushed = timer_queue.push(b"8=FIX.4.2\x0135=8\x0134=2\x01".to_vec());
+            sender.send(admitted && pushed.is_ok()).unwrap();
+            None
+        });
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(1)), Ok(true));
+        assert_eq!(queue.depth(), 2);
+    }
+
+    #[test]
+    fn test_throttle_rejects_application_messages_only() {
+        let queue = OutboundQueue::new(
+            10,
+            Some(ThrottleConfig {
+                messages_per_second: 1,
+                burst: 1,
+                action: ThrottleAction::Reject,
+            }),
+        );
+        assert!(!queue.throttled());
+        assert!(queue.admit(1));
+        assert!(queue.throttled());
+        assert!(!queue.admit(1));
+        assert!(queue.admit(0));
+    }
+
+    #[test]
+    fn test_throttle_takes_a_token_per_message_of_a_batch() {
+        let queue = OutboundQueue::new(
+            10,
+            Some(ThrottleConfig {
+                messages_per_second: 1,
+                burst: 2,
+                action: ThrottleAction::Reject,
+            }),
+        );
+        let order = "8=FIX.4.2\x019=5\x0135=D\x0110=000\x01";
+        let heartbeat = "8=FIX.4.2\x019=5\x0135=0\x0110=000\x01";
+        let reject = "8=FIX.4.2\x019=5\x0135=j\x0110=000\x01";
+
+        let batch = format!("{}{}{}", order, order, order);
+        assert!(!queue.admit(application_messages(batch.as_bytes())));
+        // The heartbeat of a batch costs nothing
+        let batch = format!("{}{}{}", order, heartbeat, order);
+        assert!(queue.admit(application_messages(batch.as_bytes())));
+        assert!(!queue.admit(application_messages(order.as_bytes())));
+        // Nor does refusing a business message
+        assert_eq!(application_messages(reject.as_bytes()), 0);
+    }
+
+    #[test]
+    fn test_throttle_delays_each_message_of_a_batch() {
+        let queue = OutboundQueue::new(
+            10,
+            Some(ThrottleConfig {
+                messages_per_second: 20,
+                burst: 1,
+                action: ThrottleAction::Delay,
+            }),
+        );
+        let start = Instant::now();
+        // The first message uses the burst, the other two wait 50ms each
+        assert!(queue.admit(3));
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_admin_messages_pass_delayed_application_messages() {
+        let (local, mut remote) = MemoryTransport::pair();
+        let throttle = ThrottleConfig {
+            messages_per_second: 5,
+            burst: 1,
+            action: ThrottleAction::Delay,
+        };
+        let (mut transport, queue, writer) =
+            QueuedTransport::start(Box::new(local), 10, Some(throttle), Arc::default()).unwrap();
+        assert!(queue.admit(1));
+        let delayed_queue = Arc::clone(&queue);
+        let delayed = thread::spawn(move || delayed_queue.admit(1));
+
+        // The heartbeat goes out while the order waits for the throttle
+        let heartbeat = b"8=FIX.4.2\x0135=0\x01";
+        let start = Instant::now();
+        transport.write_all(heartbeat).unwrap();
+        let mut buf = [0; 64];
+        let length = remote.read(&mut buf).unwrap();
+        assert_eq!(&buf[..length], heartbeat);
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert!(delayed.join().unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        transport.close().unwrap();
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn test_queued_transport_writes_in_order_and_drains_on_close() {
+        let (local, mut remote) = MemoryTransport::pair();
+        let buffers = Arc::new(BufferPool::new());
+        let (mut transport, queue, writer) =
+            QueuedTransport::start(Box::new(local), 10, None, Arc::clone(&buffers)).unwrap();
+        let order = "8=FIX.4.2\x019=5\x0135=D\x0110=000\x01";
+        transport.write_all(order.repeat(2).as_bytes()).unwrap();
+        transport.write_all(b"8=FIX.4.2\x0135=5\x01").unwrap();
+        transport.close().unwrap();
+        writer.join().unwrap();
+
+        let mut received = Vec::new();
+        remote.read_to_end(&mut received).unwrap();
+        assert_eq!(received, format!("{}8=FIX.4.2\x0135=5\x01", order.repeat(2)).as_bytes());
+        // A batch is queued whole, and every buffer taken is back in the pool once written
+        let stats = buffers.stats();
+        assert_eq!(stats.taken, 2);
+        assert_eq!(stats.idle as u64, stats.allocated);
+        assert!(transport.write_all(b"8=FIX.4.2\x0135=0\x01").is_err());
+        assert_eq!(queue.depth(), 0);
+    }
+}
@@ -8,29 +8,29 @@ use quick_xml::{events::Event, Error as XmlError, Reader};
 
 #[derive(Debug)]
 pub enum FixError {
-    XmlError(XmlError),
-    IoError(IOError),
-    ParseError(String),
+    Xml(XmlError),
+    Io(IOError),
+    Parse(String),
 }
 
 impl From<io::Error> for FixError {
     fn from(error: io::Error) -> Self {
-        FixError::IoError(error)
+        FixError::Io(error)
     }
 }
 
 impl From<quick_xml::Error> for FixError {
     fn from(error: quick_xml::Error) -> Self {
-        FixError::XmlError(error)
+        FixError::Xml(error)
     }
 }
 
 impl Clone for FixError {
     fn clone(&self) -> Self {
         match self {
-            FixError::XmlError(e) => FixError::XmlError(e.clone()),
-            FixError::IoError(e) => FixError::IoError(io::Error::new(e.kind(), e.to_string())),
-            FixError::ParseError(e) => FixError::ParseError(e.clone()),
+            FixError::Xml(e) => FixError::Xml(e.clone()),
+            FixError::Io(e) => FixError::Io(io::Error::new(e.kind(), e.to_string())),
+            FixError::Parse(e) => FixError::Parse(e.clone()),
         }
     }
 }
@@ -42,6 +42,9 @@ pub struct FixMsgTag {
     pub(crate) field: Option<HashMap<String, String>>,
 }
 
+/// Messages and their required fields, keyed by message name or by MsgType.
+type MsgTagMap = HashMap<String, FixMsgTag>;
+
 const FIX_MESSAGE_TAG: &[u8] = b"message";
 const HEADER_TAG: &[u8] = b"header";
 const TRAILER_TAG: &[u8] = b"trailer";
@@ -51,12 +54,12 @@ pub fn parse_fix_payload_xml(
     xml_path: &str,
     msgtype_name_map: &HashMap<String, String>,
     fix_tagname_number_map: &HashMap<String, FixTag>,
-) -> Result<(HashMap<String, FixMsgTag>, HashMap<String, FixMsgTag>), FixError> {
-    if !fs::metadata(xml_path).is_ok() {
+) -> Result<(MsgTagMap, MsgTagMap), FixError> {
+    if fs::metadata(xml_path).is_err() {
         error!("XML Payload definition file not found. - {}", xml_path);
         return Ok((HashMap::new(), HashMap::new()));
     }
-    let file = File::open(xml_path).map_err(FixError::IoError)?;
+    let file = File::open(xml_path).map_err(FixError::Io)?;
     let file = BufReader::new(file);
 
     let mut reader = Reader::from_reader(file);
@@ -73,16 +76,14 @@ pub fn parse_fix_payload_xml(
 
     loop {
         match reader.read_event_into(&mut buf) {
-            Ok(Event::Empty(e)) => {
-                if e.name() == quick_xml::name::QName(FIELD_TAG) {
-                    let (field_name, required) = parse_field(&e)?;
-                    if required == "Y" {
-                        current_fieldname_map.insert(field_name.clone(), required.clone());
-                        if let Some(tags_info) = fix_tagname_number_map.get(&field_name) {
-                            current_fieldtag_map.insert(tags_info.number.clone(), required.clone());
-                        } else {
-                            current_fieldtag_map.insert(field_name.clone(), required.clone());
-                        }
+            Ok(Event::Empty(e)) if e.name() == quick_xml::name::QName(FIELD_TAG) => {
+                let (field_name, required) = parse_field(&e)?;
+                if required == "Y" {
+                    current_fieldname_map.insert(field_name.clone(), required.clone());
+                    if let Some(tags_info) = fix_tagname_number_map.get(&field_name) {
+                        current_fieldtag_map.insert(tags_info.number.clone(), required.clone());
+                    } else {
+                        current_fieldtag_map.insert(field_name.clone(), required.clone());
                     }
                 }
             }
@@ -126,22 +127,22 @@ pub fn parse_fix_payload_xml(
                 }
                 _ => {}
             },
-            Ok(Event::End(ref e)) => {
-                if [FIX_MESSAGE_TAG, HEADER_TAG, TRAILER_TAG].contains(&e.name().as_ref()) {
-                    if let Some(tag) = fixname_map.get_mut(&current_msg_name) {
-                        tag.field = Some(current_fieldname_map.clone());
-                    }
-                    if let Some(tag) = fixnumber_map.get_mut(&current_msg_type) {
-                        tag.field = Some(current_fieldtag_map.clone());
-                    }
-                    current_msg_name.clear();
-                    current_fieldname_map.clear();
-                    current_msg_type.clear();
-                    current_fieldtag_map.clear();
+            Ok(Event::End(ref e))
+                if [FIX_MESSAGE_TAG, HEADER_TAG, TRAILER_TAG].contains(&e.name().as_ref()) =>
+            {
+                if let Some(tag) = fixname_map.get_mut(&current_msg_name) {
+                    tag.field = Some(current_fieldname_map.clone());
+                }
+                if let Some(tag) = fixnumber_map.get_mut(&current_msg_type) {
+                    tag.field = Some(current_fieldtag_map.clone());
                 }
+                current_msg_name.clear();
+                current_fieldname_map.clear();
+                current_msg_type.clear();
+                current_fieldtag_map.clear();
             }
             Ok(Event::Eof) => break,
-            Err(e) => return Err(FixError::XmlError(e)),
+            Err(e) => return Err(FixError::Xml(e)),
             _ => {}
         }
         buf.clear();
@@ -157,7 +158,7 @@ fn parse_message(
     let mut msgcat = None;
 
     for attr in event.attributes() {
-        let attr = attr.map_err(|e| FixError::XmlError(XmlError::from(e)))?;
+        let attr = attr.map_err(|e| FixError::Xml(XmlError::from(e)))?;
         match attr.key {
             quick_xml::name::QName(b"name") => msgname = Some(attr.unescape_value()?.into_owned()),
             quick_xml::name::QName(b"msgtype") => {
@@ -170,9 +171,7 @@ fn parse_message(
     if let (Some(msg_name), Some(msg_type), Some(msg_cat)) = (msgname, msgtype, msgcat) {
         Ok((msg_name, msg_type, msg_cat))
     } else {
-        Err(FixError::ParseError(
-            "Incomplete message attributes".to_string(),
-        ))
+        Err(FixError::Parse("Incomplete message attributes".to_string()))
     }
 }
 
@@ -181,7 +180,7 @@ fn parse_field(event: &quick_xml::events::BytesStart) -> Result<(String, String)
     let mut required = None;
 
     for attr in event.attributes() {
-        let attr = attr.map_err(|e| FixError::XmlError(XmlError::from(e)))?;
+        let attr = attr.map_err(|e| FixError::Xml(XmlError::from(e)))?;
         match attr.key {
             quick_xml::name::QName(b"name") => {
                 field_name = Some(attr.unescape_value()?.into_owned())
@@ -195,9 +194,7 @@ fn parse_field(event: &quick_xml::events::BytesStart) -> Result<(String, String)
     if let (Some(field_name), Some(required)) = (field_name, required) {
         Ok((field_name, required))
     } else {
-        Err(FixError::ParseError(
-            "Incomplete field attributes".to_string(),
-        ))
+        Err(FixError::Parse("Incomplete field attributes".to_string()))
     }
 }
 
@@ -252,10 +249,10 @@ mod tests {
         let result = parse_message(&event);
         assert!(result.is_err());
 
-        if let FixError::ParseError(err) = result.unwrap_err() {
+        if let FixError::Parse(err) = result.unwrap_err() {
             assert_eq!(err, "Incomplete message attributes".to_string());
         } else {
-            panic!("Expected FixError::ParseError");
+            panic!("Expected FixError::Parse");
         }
     }
 
@@ -280,10 +277,10 @@ mod tests {
         let result = parse_field(&event);
         assert!(result.is_err());
 
-        if let FixError::ParseError(err) = result.unwrap_err() {
+        if let FixError::Parse(err) = result.unwrap_err() {
             assert_eq!(err, "Incomplete field attributes".to_string());
         } else {
-            panic!("Expected FixError::ParseError");
+            panic!("Expected FixError::Parse");
         }
     }
 
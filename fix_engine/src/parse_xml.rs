@@ -1,4 +1,4 @@
-use std::{fs, io};
+use std::{fmt, fs, io};
 // parse_xml.rs
 use std::collections::HashMap;
 use std::fs::File;
@@ -8,17 +8,30 @@ use log::{error, info};
 use prettytable::{format, Cell, Row, Table};
 use quick_xml::{events::Event, Error as XmlError, Reader};
 
+use crate::masking::{is_masked, mask_message};
+use crate::tag_value::fields;
+
 // Custom error type for FIX related errors
 #[derive(Debug)]
 pub enum FixError {
-    XmlError(XmlError),
-    IoError(IOError),
-    ParseError(String),
+    Xml(XmlError),
+    Io(IOError),
+    Parse(String),
+}
+
+impl fmt::Display for FixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FixError::Xml(e) => write!(f, "XML error: {}", e),
+            FixError::Io(e) => write!(f, "I/O error: {}", e),
+            FixError::Parse(e) => write!(f, "{}", e),
+        }
+    }
 }
 
 impl From<io::Error> for FixError {
     fn from(error: io::Error) -> Self {
-        FixError::IoError(error)
+        FixError::Io(error)
     }
 }
 
@@ -32,6 +45,8 @@ pub struct FixTag {
 }
 
 impl FixTag {
+    /// Tags are read from the data dictionary, so only tests build one by hand.
+    #[cfg(test)]
     pub fn new(
         number: String,
         name: String,
@@ -65,20 +80,18 @@ pub(crate) enum DataType {
 const FIX_FIELD_TAG: &[u8] = b"field";
 const ENUM_VALUE_TAG: &[u8] = b"value";
 
+// Tags by number and by name, then message names by MsgType and MsgTypes by name
+type FixDictionary = (
+    HashMap<u32, FixTag>,
+    HashMap<String, FixTag>,
+    HashMap<String, String>,
+    HashMap<String, String>,
+);
+
 // Parse FIX XML definitions
-pub fn parse_fix_xml(
-    xml_path: &str,
-) -> Result<
-    (
-        HashMap<u32, FixTag>,
-        HashMap<String, FixTag>,
-        HashMap<String, String>,
-        HashMap<String, String>,
-    ),
-    FixError,
-> {
+pub fn parse_fix_xml(xml_path: &str) -> Result<FixDictionary, FixError> {
     // Check if the file exists
-    if !fs::metadata(xml_path).is_ok() {
+    if fs::metadata(xml_path).is_err() {
         error!("XML definition file not found. - {}", xml_path);
         return Ok((
             HashMap::new(),
@@ -87,7 +100,7 @@ pub fn parse_fix_xml(
             HashMap::new(),
         ));
     }
-    let file = File::open(xml_path).map_err(FixError::IoError)?;
+    let file = File::open(xml_path).map_err(FixError::Io)?;
     let file = BufReader::new(file);
 
     let mut reader = Reader::from_reader(file);
@@ -109,9 +122,9 @@ pub fn parse_fix_xml(
             Ok(Event::Empty(e)) => match e.name() {
                 quick_xml::name::QName(FIX_FIELD_TAG) => {
                     let (field_number, field_name, data_type) = parse_field_number(&e)?;
-                    let parsed_number = field_number.parse::<u32>().map_err(|e| {
-                        FixError::ParseError(format!("Error parsing tag number: {}", e))
-                    })?;
+                    let parsed_number = field_number
+                        .parse::<u32>()
+                        .map_err(|e| FixError::Parse(format!("Error parsing tag number: {}", e)))?;
                     data_tag_map.insert(
                         parsed_number,
                         FixTag {
@@ -142,52 +155,48 @@ pub fn parse_fix_xml(
                 }
                 _ => {}
             },
-            Ok(Event::Start(e)) => {
-                if e.name() == quick_xml::name::QName(FIX_FIELD_TAG) {
-                    let (e_field_number, e_field_name, e_data_type) = parse_field_number(&e)?;
-                    let parsed_number = e_field_number.parse::<u32>().map_err(|e| {
-                        FixError::ParseError(format!("Error parsing tag number: {}", e))
-                    })?;
-                    data_tag_map.insert(
-                        parsed_number,
-                        FixTag {
-                            number: e_field_number.clone(),
-                            name: e_field_name.clone(),
-                            data_type: e_data_type.clone(),
-                            enum_values: None,
-                        },
-                    );
-                    data_name_map.insert(
-                        e_field_name.clone(),
-                        FixTag {
-                            number: e_field_number.clone(),
-                            name: e_field_name.clone(),
-                            data_type: e_data_type.clone(),
-                            enum_values: None,
-                        },
-                    );
-                    current_tag_number = e_field_number.clone();
-                    current_tag_name = e_field_name.clone();
-                }
+            Ok(Event::Start(e)) if e.name() == quick_xml::name::QName(FIX_FIELD_TAG) => {
+                let (e_field_number, e_field_name, e_data_type) = parse_field_number(&e)?;
+                let parsed_number = e_field_number
+                    .parse::<u32>()
+                    .map_err(|e| FixError::Parse(format!("Error parsing tag number: {}", e)))?;
+                data_tag_map.insert(
+                    parsed_number,
+                    FixTag {
+                        number: e_field_number.clone(),
+                        name: e_field_name.clone(),
+                        data_type: e_data_type.clone(),
+                        enum_values: None,
+                    },
+                );
+                data_name_map.insert(
+                    e_field_name.clone(),
+                    FixTag {
+                        number: e_field_number.clone(),
+                        name: e_field_name.clone(),
+                        data_type: e_data_type.clone(),
+                        enum_values: None,
+                    },
+                );
+                current_tag_number = e_field_number.clone();
+                current_tag_name = e_field_name.clone();
             }
-            Ok(Event::End(ref e)) => {
-                if e.name() == quick_xml::name::QName(FIX_FIELD_TAG) {
-                    let key_no: u32 = current_tag_number.parse().unwrap();
-                    if let Some(tag) = data_tag_map.get_mut(&key_no) {
-                        tag.enum_values = Some(current_enum_tag_map.clone());
-                    }
-                    let key_name: String = current_tag_name.to_string();
-                    if let Some(tag) = data_name_map.get_mut(&key_name) {
-                        tag.enum_values = Some(current_enum_name_map.clone());
-                    }
-                    current_tag_number = "0".to_string();
-                    current_tag_name = "_".to_string();
-                    current_enum_tag_map.clear();
-                    current_enum_name_map.clear();
+            Ok(Event::End(ref e)) if e.name() == quick_xml::name::QName(FIX_FIELD_TAG) => {
+                let key_no: u32 = current_tag_number.parse().unwrap();
+                if let Some(tag) = data_tag_map.get_mut(&key_no) {
+                    tag.enum_values = Some(current_enum_tag_map.clone());
                 }
+                let key_name: String = current_tag_name.to_string();
+                if let Some(tag) = data_name_map.get_mut(&key_name) {
+                    tag.enum_values = Some(current_enum_name_map.clone());
+                }
+                current_tag_number = "0".to_string();
+                current_tag_name = "_".to_string();
+                current_enum_tag_map.clear();
+                current_enum_name_map.clear();
             }
             Ok(Event::Eof) => break,
-            Err(e) => return Err(FixError::XmlError(e)),
+            Err(e) => return Err(FixError::Xml(e)),
             _ => {}
         }
         buf.clear();
@@ -207,44 +216,42 @@ fn parse_field_number(
     let mut field_number = None;
     let mut field_name = None;
     let mut data_type = None;
-    for attr in event.attributes() {
-        if let Ok(attr) = attr {
-            match attr.key {
-                quick_xml::name::QName(b"number") => {
-                    field_number = Some(String::from_utf8_lossy(&attr.value).into_owned())
-                }
-                quick_xml::name::QName(b"name") => {
-                    field_name = Some(String::from_utf8_lossy(&attr.value).into_owned())
-                }
-                quick_xml::name::QName(b"type") => {
-                    let type_str = std::str::from_utf8(&attr.value).map_err(|_| {
-                        FixError::ParseError("Error parsing UTF-8 string".to_string())
-                    })?;
-                    data_type = Some(match type_str {
-                        "STRING"
-                        | "MULTIPLEVALUESTRING"
-                        | "CURRENCY"
-                        | "EXCHANGE"
-                        | "UTCTIMESTAMP"
-                        | "LOCALMKTDATE"
-                        | "DATA"
-                        | "UTCDATE"
-                        | "UTCTIMEONLY" => DataType::String,
-                        "INT" | "PRICE" | "AMT" | "QTY" | "LENGTH" | "PRICEOFFSET"
-                        | "MONTHYEAR" | "DAYOFMONTH" => DataType::Int,
-                        "FLOAT" => DataType::Float,
-                        "CHAR" => DataType::Char,
-                        "BOOLEAN" => DataType::Bool,
-                        _ => {
-                            return Err(FixError::ParseError(format!(
-                                "Unknown data type: {}",
-                                type_str
-                            )));
-                        }
-                    });
-                }
-                _ => {}
+    for attr in event.attributes().flatten() {
+        match attr.key {
+            quick_xml::name::QName(b"number") => {
+                field_number = Some(String::from_utf8_lossy(&attr.value).into_owned())
+            }
+            quick_xml::name::QName(b"name") => {
+                field_name = Some(String::from_utf8_lossy(&attr.value).into_owned())
             }
+            quick_xml::name::QName(b"type") => {
+                let type_str = std::str::from_utf8(&attr.value).map_err(|_| {
+                    FixError::Parse("Error parsing UTF-8 string".to_string())
+                })?;
+                data_type = Some(match type_str {
+                    "STRING"
+                    | "MULTIPLEVALUESTRING"
+                    | "CURRENCY"
+                    | "EXCHANGE"
+                    | "UTCTIMESTAMP"
+                    | "LOCALMKTDATE"
+                    | "DATA"
+                    | "UTCDATE"
+                    | "UTCTIMEONLY"
+                    | "MONTHYEAR" => DataType::String,
+                    "INT" | "LENGTH" | "DAYOFMONTH" => DataType::Int,
+                    "FLOAT" | "PRICE" | "AMT" | "QTY" | "PRICEOFFSET" => DataType::Float,
+                    "CHAR" => DataType::Char,
+                    "BOOLEAN" => DataType::Bool,
+                    _ => {
+                        return Err(FixError::Parse(format!(
+                            "Unknown data type: {}",
+                            type_str
+                        )));
+                    }
+                });
+            }
+            _ => {}
         }
     }
     if let (Some(field_number), Some(field_name), Some(data_type)) =
@@ -252,9 +259,7 @@ fn parse_field_number(
     {
         Ok((field_number, field_name, data_type))
     } else {
-        Err(FixError::ParseError(
-            "Incomplete field attributes".to_string(),
-        ))
+        Err(FixError::Parse("Incomplete field attributes".to_string()))
     }
 }
 
@@ -270,19 +275,19 @@ fn parse_value_enum(event: &quick_xml::events::BytesStart) -> Result<(String, St
                 quick_xml::name::QName(b"enum") => {
                     // Ensure detection of invalid UTF-8
                     let enum_value = std::str::from_utf8(&attr.value)
-                        .map_err(|_| FixError::ParseError("Error parsing UTF-8 string".to_string()))?;
+                        .map_err(|_| FixError::Parse("Error parsing UTF-8 string".to_string()))?;
                     enum_data = Some(enum_value.to_owned());
                 }
                 quick_xml::name::QName(b"description") => {
                     // Ensure detection of invalid UTF-8
                     let desc_value = std::str::from_utf8(&attr.value)
-                        .map_err(|_| FixError::ParseError("Error parsing UTF-8 string".to_string()))?;
+                        .map_err(|_| FixError::Parse("Error parsing UTF-8 string".to_string()))?;
                     description = Some(desc_value.to_owned());
                 }
                 _ => {}
             },
             Err(e) => {
-                return Err(FixError::XmlError(e.into()));
+                return Err(FixError::Xml(e.into()));
             }
         }
     }
@@ -290,9 +295,7 @@ fn parse_value_enum(event: &quick_xml::events::BytesStart) -> Result<(String, St
     if let (Some(enum_data), Some(description)) = (enum_data, description) {
         Ok((enum_data, description))
     } else {
-        Err(FixError::ParseError(
-            "Incomplete enum attributes".to_string(),
-        ))
+        Err(FixError::Parse("Incomplete enum attributes".to_string()))
     }
 }
 
@@ -311,44 +314,46 @@ pub fn print_fix_message(
         Cell::new("Value"),
         Cell::new("Description"),
     ]));
-    let modified_message = message.replace('\x01', "|");
-    info!("{}", modified_message);
-    let fields: Vec<&str> = modified_message.split('|').collect();
-    for field in fields {
-        let parts: Vec<&str> = field.split('=').collect();
-        if parts.len() == 2 {
-            if let Ok(tag) = parts[0].parse::<u32>() {
-                if let Some(tag_definition) = tags_map.get(&tag) {
-                    let mut row = Row::empty();
-                    row.add_cell(Cell::new(&tag_definition.name));
-                    row.add_cell(Cell::new(&tag_definition.number));
-                    row.add_cell(Cell::new(parts[1]));
-                    if let Some(enum_values) = &tag_definition.enum_values {
-                        if let Some(enum_description) = enum_values.get(parts[1]) {
-                            row.add_cell(Cell::new(enum_description));
-                        } else {
-                            row.add_cell(Cell::new(""));
-                        }
-                    } else {
-                        row.add_cell(Cell::new(""));
-                    }
-                    table.add_row(row);
+    info!("{}", mask_message(message).replace('\x01', "|"));
+    for field in fields(message.as_bytes()) {
+        let (tag, value) = match field {
+            Ok((tag, value)) => (tag, String::from_utf8_lossy(value)),
+            Err(e) => {
+                let mut row = Row::empty();
+                row.add_cell(Cell::new("Invalid tag number"));
+                row.add_cell(Cell::new(""));
+                row.add_cell(Cell::new(&e));
+                row.add_cell(Cell::new(""));
+                table.add_row(row);
+                break;
+            }
+        };
+        if let Some(tag_definition) = tags_map.get(&tag) {
+            let mut row = Row::empty();
+            row.add_cell(Cell::new(&tag_definition.name));
+            row.add_cell(Cell::new(&tag_definition.number));
+            if is_masked(tag) {
+                row.add_cell(Cell::new("****"));
+            } else {
+                row.add_cell(Cell::new(&value));
+            }
+            if let Some(enum_values) = &tag_definition.enum_values {
+                if let Some(enum_description) = enum_values.get(value.as_ref()) {
+                    row.add_cell(Cell::new(enum_description));
                 } else {
-                    let mut row = Row::empty();
-                    row.add_cell(Cell::new("Unknown tag"));
-                    row.add_cell(Cell::new(parts[0]));
-                    row.add_cell(Cell::new(parts[1]));
                     row.add_cell(Cell::new(""));
-                    table.add_row(row);
                 }
             } else {
-                let mut row = Row::empty();
-                row.add_cell(Cell::new("Invalid tag number"));
-                row.add_cell(Cell::new(parts[0]));
-                row.add_cell(Cell::new(parts[1]));
                 row.add_cell(Cell::new(""));
-                table.add_row(row);
             }
+            table.add_row(row);
+        } else {
+            let mut row = Row::empty();
+            row.add_cell(Cell::new("Unknown tag"));
+            row.add_cell(Cell::new(&tag.to_string()));
+            row.add_cell(Cell::new(&value));
+            row.add_cell(Cell::new(""));
+            table.add_row(row);
         }
     }
 
@@ -361,7 +366,7 @@ pub fn print_fix_message(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use quick_xml::events::{BytesStart, BytesText, Event};
+    use quick_xml::events::Event;
     use quick_xml::Reader;
 
     #[test]
@@ -404,7 +409,7 @@ mod tests {
                 assert!(result.is_err());
                 let err = result.unwrap_err();
                 match err {
-                    FixError::ParseError(msg) => {
+                    FixError::Parse(msg) => {
                         assert_eq!(msg, "Incomplete enum attributes");
                     }
                     _ => panic!("Unexpected error type"),
@@ -431,7 +436,7 @@ mod tests {
                 assert!(result.is_err());
                 let err = result.unwrap_err();
                 match err {
-                    FixError::ParseError(msg) => assert_eq!(msg, "Error parsing UTF-8 string"),
+                    FixError::Parse(msg) => assert_eq!(msg, "Error parsing UTF-8 string"),
                     _ => panic!("Unexpected error type"),
                 }
             }
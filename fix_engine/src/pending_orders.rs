@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use indexmap::IndexMap;
+use log::{error, warn};
+
+use crate::session::{Session, SESSION_STATES};
+use crate::session_events::SESSION_HOOKS;
+use crate::timer::TIMERS;
+use crate::watchdog::SessionActivity;
+use crate::{ORDER_ACK_STATUS_REQUEST, ORDER_ACK_TIMEOUT_MS};
+
+lazy_static! {
+    /// Order requests of the initiator waiting for their ExecutionReport or OrderCancelReject.
+    pub static ref PENDING_ORDERS: PendingOrders = PendingOrders::default();
+}
+
+/// An order request sent and not answered yet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingRequest {
+    pub msg_type: String,
+    pub cl_ord_id: String,
+    pub symbol: Option<String>,
+    pub side: Option<String>,
+    pub sent_at: Instant,
+}
+
+impl PendingRequest {
+    pub fn is_status_request(&self) -> bool {
+        matches!(self.msg_type.as_str(), "H" | "ORDER_STATUS_REQUEST")
+    }
+
+    /// OrderStatusRequest asking the counterparty what became of the order.
+    pub fn status_request(&self) -> IndexMap<String, String> {
+        let mut msg_map = IndexMap::from([
+            ("MsgType".to_string(), "H".to_string()),
+            ("ClOrdID".to_string(), self.cl_ord_id.clone()),
+        ]);
+        for (field, value) in [("Symbol", &self.symbol), ("Side", &self.side)] {
+            if let Some(value) = value {
+                msg_map.insert(field.to_string(), value.clone());
+            }
+        }
+        msg_map
+    }
+}
+
+/// MsgType of an order request, as a value or the dictionary description.
+fn is_order_request(msg_type: &str) -> bool {
+    matches!(
+        msg_type,
+        "D" | "F"
+            | "G"
+            | "H"
+            | "NEW_ORDER_SINGLE"
+            | "ORDER_CANCEL_REQUEST"
+            | "ORDER_CANCEL_REPLACE_REQUEST"
+            | "ORDER_STATUS_REQUEST"
+    )
+}
+
+/// Outbound NewOrderSingle, cancel, cancel/replace and status requests by ClOrdID, until
+/// answered.
+#[derive(Default)]
+pub struct PendingOrders {
+    requests: Mutex<HashMap<String, PendingRequest>>,
+}
+
+impl PendingOrders {
+    /// Starts waiting for the answer to a message sent at `sent_at` if it is an order request.
+    pub fn track(&self, msg_map: &IndexMap<String, String>, sent_at: Instant) {
+        let (Some(msg_type), Some(cl_ord_id)) = (msg_map.get("MsgType"), msg_map.get("ClOrdID"))
+        else {
+            return;
+        };
+        if !is_order_request(msg_type) {
+            return;
+        }
+        let request = PendingRequest {
+            msg_type: msg_type.clone(),
+            cl_ord_id: cl_ord_id.clone(),
+            symbol: msg_map.get("Symbol").cloned(),
+            side: msg_map.get("Side").cloned(),
+            sent_at,
+        };
+        self.requests
+            .lock()
+            .unwrap()
+            .insert(cl_ord_id.clone(), request);
+    }
+
+    /// An ExecutionReport or OrderCancelReject answered the request. Returns false when no
+    /// request with this ClOrdID was waiting.
+    pub fn acknowledge(&self, cl_ord_id: &str) -> bool {
+        self.requests.lock().unwrap().remove(cl_ord_id).is_some()
+    }
+
+    /// Stops waiting for the requests sent before `sent_before` and returns them.
+    pub fn take_expired(&self, sent_before: Instant) -> Vec<PendingRequest> {
+        let mut requests = self.requests.lock().unwrap();
+        let expired: Vec<String> = requests
+            .values()
+            .filter(|request| request.sent_at < sent_before)
+            .map(|request| request.cl_ord_id.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|cl_ord_id| requests.remove(cl_ord_id))
+            .collect()
+    }
+}
+
+/// Checks the pending order requests of the session on the shared timer: those unanswered
+/// after `order_ack_timeout_ms` are reported to the session hooks and, with
+/// `order_ack_status_request`, followed by an OrderStatusRequest.
+pub fn start_ack_timer(session: Session, activity: Arc<SessionActivity>) {
+    let timeout_ms = ORDER_ACK_TIMEOUT_MS.load(Ordering::SeqCst);
+    if timeout_ms == 0 {
+        return;
+    }
+    let timeout = Duration::from_millis(timeout_ms);
+    let period = (timeout / 10).max(Duration::from_millis(10));
+    let session_state = SESSION_STATES.for_session(&session.session_id);
+    TIMERS.schedule(Instant::now() + period, move || {
+        if session_state.is_logout_sent() || activity.is_closed() {
+            return None;
+        }
+        let now = Instant::now();
+        let expired = match now.checked_sub(timeout) {
+            Some(sent_before) => PENDING_ORDERS.take_expired(sent_before),
+            None => Vec::new(),
+        };
+        for request in expired {
+            warn!(
+                "No answer to {} {} within {} ms",
+                request.msg_type, request.cl_ord_id, timeout_ms
+            );
+            SESSION_HOOKS.unacknowledged_order(
+                &session.session_id,
+                &request.cl_ord_id,
+                &request.msg_type,
+            );
+            // An unanswered status request is not asked again
+            if ORDER_ACK_STATUS_REQUEST.load(Ordering::SeqCst) && !request.is_status_request() {
+                if let Err(e) = session.send_batch(vec![request.status_request()]) {
+                    error!(
+                        "Failed to send OrderStatusRequest for {}: {}",
+                        request.cl_ord_id, e
+                    );
+                }
+            }
+        }
+        Some(now + period)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order_request(msg_type: &str, cl_ord_id: &str) -> IndexMap<String, String> {
+        [
+            ("MsgType", msg_type),
+            ("ClOrdID", cl_ord_id),
+            ("Symbol", "IBM"),
+            ("Side", "BUY"),
+        ]
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+    }
+
+    #[test]
+    fn test_pending_requests_expire() {
+        let pending = PendingOrders::default();
+        let start = Instant::now();
+        pending.track(&order_request("D", "1"), start);
+        pending.track(&order_request("F", "2"), start + Duration::from_millis(100));
+        pending.track(&order_request("0", "3"), start);
+
+        assert!(pending.take_expired(start).is_empty());
+        let expired = pending.take_expired(start + Duration::from_millis(50));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].cl_ord_id, "1");
+        assert!(!pending.acknowledge("1"));
+        assert!(!pending.acknowledge("3"));
+        assert!(pending.acknowledge("2"));
+        assert!(pending
+            .take_expired(start + Duration::from_secs(1))
+            .is_empty());
+
+        let status_request = expired[0].status_request();
+        assert_eq!(status_request["MsgType"], "H");
+        assert_eq!(status_request["Symbol"], "IBM");
+        assert!(!expired[0].is_status_request());
+    }
+}
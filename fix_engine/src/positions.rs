@@ -0,0 +1,241 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, RwLock};
+
+use prettytable::{row, Cell, Row, Table};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::execution_store::ExecutionRecord;
+use crate::matching::Side;
+use crate::order_events::{OrderEvent, OrderEventKind, OrderEventSink};
+
+const AVG_PX_DECIMALS: u32 = 8;
+
+/// Net position of an account in a symbol: positive long, negative short.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Position {
+    pub account: String,
+    pub symbol: String,
+    pub quantity: Decimal,
+    /// Average price of the open quantity, 0 when flat.
+    pub avg_px: Decimal,
+    /// Profit or loss of the quantity closed so far.
+    pub realized_pnl: Decimal,
+}
+
+impl Position {
+    /// Nets a fill into the position. Reducing it realizes the difference to the average
+    /// price; what goes past flat opens the other way at the fill price.
+    fn apply_fill(&mut self, side: Side, quantity: Decimal, price: Decimal) {
+        let signed = match side {
+            Side::Buy => quantity,
+            Side::Sell => -quantity,
+        };
+        if self.quantity.is_zero() || self.quantity.is_sign_positive() == signed.is_sign_positive()
+        {
+            let total = self.quantity.abs() + quantity;
+            self.avg_px = ((self.avg_px * self.quantity.abs() + price * quantity) / total)
+                .round_dp(AVG_PX_DECIMALS)
+                .normalize();
+            self.quantity += signed;
+            return;
+        }
+        let closed = quantity.min(self.quantity.abs());
+        let direction = if self.quantity.is_sign_positive() {
+            Decimal::ONE
+        } else {
+            -Decimal::ONE
+        };
+        self.realized_pnl += (price - self.avg_px) * closed * direction;
+        self.quantity += signed;
+        if self.quantity.is_zero() {
+            self.avg_px = Decimal::ZERO;
+        } else if quantity > closed {
+            self.avg_px = price;
+        }
+    }
+}
+
+/// Positions by account and symbol, netted from the fills published as order events and
+/// seeded from the ExecutionReports stored.
+#[derive(Default)]
+pub struct PositionBook {
+    positions: RwLock<BTreeMap<(String, String), Position>>,
+    /// ExecIDs of the fills netted, so a report received again is not netted twice.
+    exec_ids: RwLock<HashSet<String>>,
+}
+
+impl PositionBook {
+    /// Nets the stored report into the position of its account and symbol if it is a fill.
+    pub fn apply(&self, record: &ExecutionRecord) {
+        if matches!(record.exec_type.as_str(), "1" | "2" | "F") {
+            self.net(
+                Some(&record.exec_id),
+                &record.account,
+                &record.symbol,
+                &record.side,
+                record.last_qty.as_deref(),
+                record.last_px.as_deref(),
+            );
+        }
+    }
+
+    /// Nets the event into the position of its account and symbol if it is a fill.
+    pub fn apply_event(&self, event: &OrderEvent) {
+        if event.kind == OrderEventKind::Filled {
+            self.net(
+                event.exec_id.as_deref(),
+                &event.account,
+                &event.symbol,
+                &event.side,
+                event.last_qty.as_deref(),
+                event.last_px.as_deref(),
+            );
+        }
+    }
+
+    /// Nets a fill with a side, last quantity and last price, once per ExecID.
+    fn net(
+        &self,
+        exec_id: Option<&str>,
+        account: &str,
+        symbol: &str,
+        side: &str,
+        last_qty: Option<&str>,
+        last_px: Option<&str>,
+    ) {
+        let parse = |value: Option<&str>| value.and_then(|v| v.parse().ok());
+        let (Some(side), Some(quantity), Some(price)) =
+            (Side::parse(side), parse(last_qty), parse(last_px))
+        else {
+            return;
+        };
+        if quantity <= Decimal::ZERO {
+            return;
+        }
+        if let Some(exec_id) = exec_id {
+            if !self.exec_ids.write().unwrap().insert(exec_id.to_string()) {
+                return;
+            }
+        }
+        let key = (account.to_string(), symbol.to_string());
+        let mut positions = self.positions.write().unwrap();
+        let position = positions.entry(key).or_insert_with(|| Position {
+            account: account.to_string(),
+            symbol: symbol.to_string(),
+            ..Position::default()
+        });
+        position.apply_fill(side, quantity, price);
+    }
+
+    /// The positions of the account, or of every account, by account and symbol.
+    pub fn positions(&self, account: Option<&str>) -> Vec<Position> {
+        self.positions
+            .read()
+            .unwrap()
+            .values()
+            .filter(|position| account.is_none_or(|account| position.account == account))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Nets the fills published as order events into the positions.
+pub struct PositionSink(pub Arc<PositionBook>);
+
+impl OrderEventSink for PositionSink {
+    fn name(&self) -> &str {
+        "positions"
+    }
+
+    fn on_event(&self, event: &OrderEvent) -> Result<(), Box<dyn std::error::Error>> {
+        self.0.apply_event(event);
+        Ok(())
+    }
+}
+
+/// The positions as a table for the console.
+pub fn positions_table(positions: &[Position]) -> String {
+    let mut table = Table::new();
+    table.add_row(row![
+        "Account",
+        "Symbol",
+        "Quantity",
+        "AvgPx",
+        "RealizedPnL"
+    ]);
+    for position in positions {
+        table.add_row(Row::new(vec![
+            Cell::new(&position.account),
+            Cell::new(&position.symbol),
+            Cell::new(&position.quantity.to_string()),
+            Cell::new(&position.avg_px.to_string()),
+            Cell::new(&position.realized_pnl.to_string()),
+        ]));
+    }
+    table.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(value: &str) -> Decimal {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_fills_are_netted_into_positions() {
+        let mut position = Position::default();
+        position.apply_fill(Side::Buy, dec("100"), dec("10"));
+        position.apply_fill(Side::Buy, dec("100"), dec("12"));
+        assert_eq!(
+            (position.quantity, position.avg_px),
+            (dec("200"), dec("11"))
+        );
+
+        position.apply_fill(Side::Sell, dec("50"), dec("13"));
+        assert_eq!(
+            (position.quantity, position.avg_px),
+            (dec("150"), dec("11"))
+        );
+        assert_eq!(position.realized_pnl, dec("100"));
+
+        // Selling past flat opens a short at the fill price
+        position.apply_fill(Side::Sell, dec("200"), dec("9"));
+        assert_eq!((position.quantity, position.avg_px), (dec("-50"), dec("9")));
+        assert_eq!(position.realized_pnl, dec("-200"));
+
+        position.apply_fill(Side::Buy, dec("50"), dec("8"));
+        assert_eq!((position.quantity, position.avg_px), (dec("0"), dec("0")));
+        assert_eq!(position.realized_pnl, dec("-150"));
+    }
+
+    #[test]
+    fn test_fill_events_are_netted_once() {
+        let book = Arc::new(PositionBook::default());
+        let sink = PositionSink(Arc::clone(&book));
+        let mut fill = OrderEvent::from_order_message(
+            OrderEventKind::Filled,
+            &crate::test_support::msg_map(&[
+                ("ExecID", "E1"),
+                ("Account", "ACC1"),
+                ("Symbol", "IBM"),
+                ("Side", "BUY"),
+            ]),
+        );
+        fill.last_qty = Some("100".to_string());
+        fill.last_px = Some("50".to_string());
+        sink.on_event(&fill).unwrap();
+        // Received again, as a possible duplicate
+        sink.on_event(&fill).unwrap();
+        let mut canceled = fill.clone();
+        canceled.kind = OrderEventKind::Canceled;
+        canceled.exec_id = Some("E2".to_string());
+        sink.on_event(&canceled).unwrap();
+
+        let positions = book.positions(Some("ACC1"));
+        assert_eq!(positions.len(), 1);
+        assert_eq!((positions[0].quantity, positions[0].avg_px), (dec("100"), dec("50")));
+    }
+}
@@ -0,0 +1,279 @@
+use std::io::{self, Error, ErrorKind, Read, Write};
+use std::net::{IpAddr, TcpStream};
+use std::sync::RwLock;
+
+lazy_static! {
+    /// Proxy the initiator tunnels through to reach the venue, None to connect directly.
+    pub static ref PROXY: RwLock<Option<ProxyConfig>> = RwLock::new(None);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyKind {
+    Socks5,
+    HttpConnect,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProxyConfig {
+    pub kind: ProxyKind,
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl ProxyConfig {
+    /// Asks the proxy, over a stream connected to it, to open a tunnel to the target.
+    /// Once this returns the stream carries the FIX session.
+    pub fn open_tunnel(&self, stream: &mut TcpStream, host: &str, port: u16) -> io::Result<()> {
+        match self.kind {
+            ProxyKind::Socks5 => self.socks5_connect(stream, host, port),
+            ProxyKind::HttpConnect => self.http_connect(stream, host, port),
+        }
+    }
+
+    fn credentials(&self) -> Option<(&str, &str)> {
+        self.username
+            .as_deref()
+            .map(|username| (username, self.password.as_deref().unwrap_or("")))
+    }
+
+    /// SOCKS5 (RFC 1928) CONNECT, with username/password authentication (RFC 1929).
+    fn socks5_connect(&self, stream: &mut TcpStream, host: &str, port: u16) -> io::Result<()> {
+        const NO_AUTH: u8 = 0x00;
+        const USERNAME_PASSWORD: u8 = 0x02;
+
+        let credentials = self.credentials();
+        let method = if credentials.is_some() {
+            USERNAME_PASSWORD
+        } else {
+            NO_AUTH
+        };
+        stream.write_all(&[0x05, 0x01, method])?;
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply)?;
+        if reply != [0x05, method] {
+            return Err(proxy_error(format!(
+                "SOCKS5 proxy refused authentication method {}",
+                method
+            )));
+        }
+
+        if let Some((username, password)) = credentials {
+            let mut request = vec![0x01];
+            for value in [username, password] {
+                let length = u8::try_from(value.len())
+                    .map_err(|_| proxy_error("SOCKS5 credentials longer than 255 bytes"))?;
+                request.push(length);
+                request.extend_from_slice(value.as_bytes());
+            }
+            stream.write_all(&request)?;
+            stream.read_exact(&mut reply)?;
+            if reply[1] != 0x00 {
+                return Err(proxy_error("SOCKS5 proxy rejected the credentials"));
+            }
+        }
+
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let mut request = vec![0x05, 0x01, 0x00];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(address)) => {
+                request.push(0x01);
+                request.extend_from_slice(&address.octets());
+            }
+            Ok(IpAddr::V6(address)) => {
+                request.push(0x04);
+                request.extend_from_slice(&address.octets());
+            }
+            Err(_) => {
+                // Host names are resolved by the proxy
+                let length = u8::try_from(host.len())
+                    .map_err(|_| proxy_error("Host name longer than 255 bytes"))?;
+                request.push(0x03);
+                request.push(length);
+                request.extend_from_slice(host.as_bytes());
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request)?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply)?;
+        if reply[1] != 0x00 {
+            return Err(proxy_error(format!(
+                "SOCKS5 proxy failed to connect to {}:{}, reply code {}",
+                host, port, reply[1]
+            )));
+        }
+        // Skip the bound address and port the proxy reports
+        let address_length = match reply[3] {
+            0x01 => 4,
+            0x04 => 16,
+            0x03 => {
+                let mut length = [0u8; 1];
+                stream.read_exact(&mut length)?;
+                length[0] as usize
+            }
+            other => {
+                return Err(proxy_error(format!(
+                    "Unknown SOCKS5 address type {}",
+                    other
+                )))
+            }
+        };
+        let mut bound_address = vec![0u8; address_length + 2];
+        stream.read_exact(&mut bound_address)?;
+        Ok(())
+    }
+
+    fn http_connect(&self, stream: &mut TcpStream, host: &str, port: u16) -> io::Result<()> {
+        let authority = if host.contains(':') && !host.starts_with('[') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        };
+        let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+        if let Some((username, password)) = self.credentials() {
+            request.push_str(&format!(
+                "Proxy-Authorization: Basic {}\r\n",
+                base64(format!("{}:{}", username, password).as_bytes())
+            ));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        // Read byte by byte so nothing after the headers (the venue's first bytes) is consumed
+        let mut response = Vec::new();
+        let mut byte = [0u8; 1];
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() > 8192 {
+                return Err(proxy_error("HTTP proxy response headers too long"));
+            }
+            stream.read_exact(&mut byte)?;
+            response.push(byte[0]);
+        }
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        match status_line.split_whitespace().nth(1) {
+            Some("200") => Ok(()),
+            _ => Err(proxy_error(format!(
+                "HTTP proxy refused CONNECT {}: {}",
+                authority, status_line
+            ))),
+        }
+    }
+}
+
+fn proxy_error(message: impl Into<String>) -> Error {
+    Error::new(ErrorKind::ConnectionRefused, message.into())
+}
+
+fn base64(input: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let value = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[((value >> (18 - 6 * index)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn proxy(kind: ProxyKind, username: Option<&str>) -> ProxyConfig {
+        ProxyConfig {
+            kind,
+            host: "127.0.0.1".to_string(),
+            port: 0,
+            username: username.map(|username| username.to_string()),
+            password: username.map(|_| "secret".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_socks5_tunnel() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).unwrap();
+            assert_eq!(greeting, [0x05, 0x01, 0x02]);
+            stream.write_all(&[0x05, 0x02]).unwrap();
+            let mut auth = [0u8; 13];
+            stream.read_exact(&mut auth).unwrap();
+            assert_eq!(&auth, b"\x01\x04user\x06secret");
+            stream.write_all(&[0x01, 0x00]).unwrap();
+
+            let mut request = [0u8; 4 + 1 + 9 + 2];
+            stream.read_exact(&mut request).unwrap();
+            assert_eq!(&request[..5], &[0x05, 0x01, 0x00, 0x03, 9]);
+            assert_eq!(&request[5..14], b"venue.com");
+            assert_eq!(u16::from_be_bytes([request[14], request[15]]), 9999);
+            stream
+                .write_all(&[0x05, 0x00, 0x00, 0x01, 10, 0, 0, 1, 0x27, 0x0f])
+                .unwrap();
+            stream.write_all(b"8=FIX.4.2").unwrap();
+        });
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        proxy(ProxyKind::Socks5, Some("user"))
+            .open_tunnel(&mut stream, "venue.com", 9999)
+            .unwrap();
+        let mut fix = [0u8; 9];
+        stream.read_exact(&mut fix).unwrap();
+        assert_eq!(&fix, b"8=FIX.4.2");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_http_connect_tunnel() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            for status in [
+                "200 Connection established",
+                "407 Proxy Authentication Required",
+            ] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = Vec::new();
+                let mut byte = [0u8; 1];
+                while !request.ends_with(b"\r\n\r\n") {
+                    stream.read_exact(&mut byte).unwrap();
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                assert!(request.starts_with("CONNECT [::1]:9999 HTTP/1.1\r\n"));
+                assert!(request.contains("Proxy-Authorization: Basic dXNlcjpzZWNyZXQ=\r\n"));
+                let response = format!("HTTP/1.1 {}\r\n\r\n8=FIX.4.2", status);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        let proxy = proxy(ProxyKind::HttpConnect, Some("user"));
+        let mut stream = TcpStream::connect(address).unwrap();
+        proxy.open_tunnel(&mut stream, "::1", 9999).unwrap();
+        let mut fix = [0u8; 9];
+        stream.read_exact(&mut fix).unwrap();
+        assert_eq!(&fix, b"8=FIX.4.2");
+
+        let mut stream = TcpStream::connect(address).unwrap();
+        assert!(proxy.open_tunnel(&mut stream, "::1", 9999).is_err());
+        server.join().unwrap();
+    }
+}
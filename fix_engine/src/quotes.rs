@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use indexmap::IndexMap;
+use prettytable::{row, Cell, Row, Table};
+use rust_decimal::Decimal;
+
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H:%M:%S%.3f";
+
+lazy_static! {
+    /// Quotes sent to or received from the counterparties and not canceled or expired yet.
+    pub static ref QUOTES: QuoteBook = QuoteBook::default();
+    /// How the acceptor answers QuoteRequests, None when it does not quote.
+    pub static ref QUOTE_CONFIG: RwLock<Option<QuoteConfig>> = RwLock::new(None);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuoteConfig {
+    /// Distance between the bid and the offer, in percent of the reference price.
+    pub spread_pct: Decimal,
+    /// Time from a quote to its ValidUntilTime.
+    pub validity: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    /// CompID of the counterparty the quote was sent to or received from, QuoteIDs are only
+    /// unique within a session.
+    pub counterparty: String,
+    pub quote_id: String,
+    pub quote_req_id: Option<String>,
+    pub symbol: String,
+    pub bid_px: Option<Decimal>,
+    pub offer_px: Option<Decimal>,
+    pub bid_size: Option<Decimal>,
+    pub offer_size: Option<Decimal>,
+    /// ValidUntilTime; a quote without one stays until canceled.
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+impl Quote {
+    /// The quote answering a QuoteRequest: bid and offer `spread_pct` apart around the
+    /// reference price, rounded away from it to the tick size, for the requested quantity.
+    pub fn for_request(
+        config: &QuoteConfig,
+        counterparty: &str,
+        quote_id: &str,
+        msg_map: &IndexMap<String, String>,
+        reference_price: Decimal,
+        tick_size: Option<Decimal>,
+    ) -> Self {
+        let half_spread = reference_price * config.spread_pct / Decimal::from(200);
+        let to_tick = |price: Decimal, round_up: bool| match tick_size {
+            Some(tick) if !tick.is_zero() => {
+                let ticks = price / tick;
+                let ticks = if round_up {
+                    ticks.ceil()
+                } else {
+                    ticks.floor()
+                };
+                (ticks * tick).normalize()
+            }
+            _ => price.round_dp(8).normalize(),
+        };
+        let size = msg_map
+            .get("OrderQty")
+            .and_then(|quantity| quantity.parse().ok());
+        Quote {
+            counterparty: counterparty.to_string(),
+            quote_id: quote_id.to_string(),
+            quote_req_id: msg_map.get("QuoteReqID").cloned(),
+            symbol: msg_map.get("Symbol").cloned().unwrap_or_default(),
+            bid_px: Some(to_tick(reference_price - half_spread, false)),
+            offer_px: Some(to_tick(reference_price + half_spread, true)),
+            bid_size: size,
+            offer_size: size,
+            valid_until: chrono::Duration::from_std(config.validity)
+                .ok()
+                .map(|validity| Utc::now() + validity),
+        }
+    }
+
+    /// A Quote received from the counterparty; None without a QuoteID.
+    pub fn from_message(counterparty: &str, msg_map: &IndexMap<String, String>) -> Option<Self> {
+        let decimal = |field: &str| msg_map.get(field).and_then(|value| value.parse().ok());
+        Some(Quote {
+            counterparty: counterparty.to_string(),
+            quote_id: msg_map.get("QuoteID")?.clone(),
+            quote_req_id: msg_map.get("QuoteReqID").cloned(),
+            symbol: msg_map.get("Symbol").cloned().unwrap_or_default(),
+            bid_px: decimal("BidPx"),
+            offer_px: decimal("OfferPx"),
+            bid_size: decimal("BidSize"),
+            offer_size: decimal("OfferSize"),
+            valid_until: msg_map.get("ValidUntilTime").and_then(|value| {
+                NaiveDateTime::parse_from_str(value, "%Y%m%d-%H:%M:%S%.f")
+                    .ok()
+                    .map(|timestamp| timestamp.and_utc())
+            }),
+        })
+    }
+
+    /// Override fields of the Quote template.
+    pub fn fields(&self) -> HashMap<String, String> {
+        let mut fields = HashMap::from([
+            ("QuoteID".to_string(), self.quote_id.clone()),
+            ("Symbol".to_string(), self.symbol.clone()),
+        ]);
+        let optional = [
+            ("QuoteReqID", self.quote_req_id.clone()),
+            ("BidPx", self.bid_px.map(|px| px.to_string())),
+            ("OfferPx", self.offer_px.map(|px| px.to_string())),
+            ("BidSize", self.bid_size.map(|size| size.to_string())),
+            ("OfferSize", self.offer_size.map(|size| size.to_string())),
+            (
+                "ValidUntilTime",
+                self.valid_until
+                    .map(|valid_until| valid_until.format(TIMESTAMP_FORMAT).to_string()),
+            ),
+        ];
+        for (field, value) in optional {
+            if let Some(value) = value {
+                fields.insert(field.to_string(), value);
+            }
+        }
+        fields
+    }
+
+    /// Override fields of the QuoteCancel template withdrawing this quote.
+    pub fn cancel_fields(&self) -> HashMap<String, String> {
+        let mut fields = HashMap::from([
+            ("QuoteID".to_string(), self.quote_id.clone()),
+            ("Symbol".to_string(), self.symbol.clone()),
+        ]);
+        if let Some(quote_req_id) = &self.quote_req_id {
+            fields.insert("QuoteReqID".to_string(), quote_req_id.clone());
+        }
+        fields
+    }
+}
+
+/// Live quotes by counterparty and QuoteID.
+#[derive(Default)]
+pub struct QuoteBook {
+    quotes: Mutex<BTreeMap<(String, String), Quote>>,
+}
+
+impl QuoteBook {
+    /// Adds the quote, replacing one with the same QuoteID from the counterparty.
+    pub fn insert(&self, quote: Quote) {
+        let key = (quote.counterparty.clone(), quote.quote_id.clone());
+        self.quotes.lock().unwrap().insert(key, quote);
+    }
+
+    /// Removes the quotes a QuoteCancel from the counterparty withdraws: all of its quotes for
+    /// QuoteCancelType CANCEL_ALL_QUOTES, those of the Symbol for a cancel per security when
+    /// no QuoteID is given, otherwise the quote with the QuoteID.
+    pub fn cancel(&self, counterparty: &str, msg_map: &IndexMap<String, String>) -> Vec<Quote> {
+        let cancel_type = msg_map.get("QuoteCancelType").map(String::as_str);
+        let quote_id = msg_map
+            .get("QuoteID")
+            .filter(|quote_id| !quote_id.is_empty());
+        let symbol = msg_map.get("Symbol");
+        let withdrawn = |quote: &Quote| match (cancel_type, quote_id) {
+            (Some("4" | "CANCEL_ALL_QUOTES"), _) => true,
+            (_, Some(quote_id)) => &quote.quote_id == quote_id,
+            (_, None) => Some(&quote.symbol) == symbol,
+        };
+        let mut quotes = self.quotes.lock().unwrap();
+        let keys: Vec<(String, String)> = quotes
+            .iter()
+            .filter(|((owner, _), quote)| owner == counterparty && withdrawn(quote))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.iter().filter_map(|key| quotes.remove(key)).collect()
+    }
+
+    /// Removes the quote if its ValidUntilTime has passed, returning it.
+    pub fn expire(&self, counterparty: &str, quote_id: &str, now: DateTime<Utc>) -> Option<Quote> {
+        let mut quotes = self.quotes.lock().unwrap();
+        let key = (counterparty.to_string(), quote_id.to_string());
+        match quotes.get(&key)?.valid_until {
+            Some(valid_until) if valid_until <= now => quotes.remove(&key),
+            _ => None,
+        }
+    }
+
+    /// The live quotes, by counterparty and QuoteID.
+    pub fn active(&self) -> Vec<Quote> {
+        self.quotes.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// The quotes as a table for the console.
+pub fn quotes_table(quotes: &[Quote]) -> String {
+    let mut table = Table::new();
+    table.add_row(row![
+        "Counterparty",
+        "QuoteID",
+        "QuoteReqID",
+        "Symbol",
+        "BidSize",
+        "BidPx",
+        "OfferPx",
+        "OfferSize",
+        "ValidUntilTime"
+    ]);
+    let text = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    for quote in quotes {
+        table.add_row(Row::new(vec![
+            Cell::new(&quote.counterparty),
+            Cell::new(&quote.quote_id),
+            Cell::new(&text(quote.quote_req_id.clone())),
+            Cell::new(&quote.symbol),
+            Cell::new(&text(quote.bid_size.map(|size| size.to_string()))),
+            Cell::new(&text(quote.bid_px.map(|px| px.to_string()))),
+            Cell::new(&text(quote.offer_px.map(|px| px.to_string()))),
+            Cell::new(&text(quote.offer_size.map(|size| size.to_string()))),
+            Cell::new(&text(quote.valid_until.map(|valid_until| {
+                valid_until.format(TIMESTAMP_FORMAT).to_string()
+            }))),
+        ]));
+    }
+    table.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg_map;
+
+    #[test]
+    fn test_quote_lifecycle() {
+        let config = QuoteConfig {
+            spread_pct: Decimal::ONE,
+            validity: Duration::from_secs(30),
+        };
+        let request = msg_map(&[("QuoteReqID", "R1"), ("Symbol", "IBM"), ("OrderQty", "100")]);
+        let quote = Quote::for_request(
+            &config,
+            "CLIENT",
+            "Q00000001",
+            &request,
+            Decimal::from(150),
+            Some(Decimal::new(5, 2)),
+        );
+        // 0.75 either side of 150, rounded away to the 0.05 tick
+        assert_eq!(quote.bid_px, Some(Decimal::new(14925, 2)));
+        assert_eq!(quote.offer_px, Some(Decimal::new(15075, 2)));
+        assert_eq!(quote.bid_size, Some(Decimal::from(100)));
+
+        // What goes out is read back the same by the counterparty
+        let fields = quote.fields();
+        let received = Quote::from_message(
+            "CLIENT",
+            &fields
+                .iter()
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect(),
+        )
+        .unwrap();
+        assert_eq!(received.offer_px, quote.offer_px);
+        assert_eq!(
+            received.valid_until.map(|time| time.timestamp_millis()),
+            quote.valid_until.map(|time| time.timestamp_millis())
+        );
+
+        let book = QuoteBook::default();
+        book.insert(quote.clone());
+        book.insert(Quote {
+            quote_id: "Q00000002".to_string(),
+            symbol: "AAPL".to_string(),
+            ..quote.clone()
+        });
+        book.insert(Quote {
+            counterparty: "OTHER".to_string(),
+            ..quote.clone()
+        });
+        assert!(book.expire("CLIENT", "Q00000001", Utc::now()).is_none());
+        let later = Utc::now() + chrono::Duration::seconds(31);
+        assert_eq!(book.expire("CLIENT", "Q00000001", later), Some(quote));
+
+        let canceled = book.cancel("CLIENT", &msg_map(&[("Symbol", "AAPL")]));
+        assert_eq!(canceled.len(), 1);
+        let canceled = book.cancel("OTHER", &msg_map(&[("QuoteCancelType", "4")]));
+        assert_eq!(canceled[0].counterparty, "OTHER");
+        assert!(book.active().is_empty());
+    }
+}
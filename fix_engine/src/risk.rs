@@ -0,0 +1,464 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::Ordering;
+use std::sync::{Mutex, RwLock};
+use std::time::Instant;
+
+use indexmap::IndexMap;
+use log::{error, info, warn};
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::kill_switch::{cancel_account_orders, KILL_SWITCH};
+use crate::orderstore::{Order, OrderStore};
+use crate::symbol_master::SYMBOL_MASTER;
+use crate::throttle::{ThrottleConfig, TokenBucket};
+use crate::trading_session::{TradSesStatus, TRADING_SESSION};
+use crate::MAX_ACCOUNT_OPEN_QTY;
+
+lazy_static! {
+    pub static ref ACCOUNT_RISK: AccountRisk = AccountRisk::new();
+    /// Limits every NewOrderSingle is checked against before it is accepted, from `[risk]`.
+    pub static ref PRE_TRADE_LIMITS: RwLock<Option<PreTradeLimits>> = RwLock::new(None);
+    pub static ref ACCOUNT_THROTTLE: AccountThrottle = AccountThrottle::default();
+}
+
+/// Pre-trade limits of the acceptor. An order violating one is rejected, unlike a breach of
+/// the open quantity limit the account is not blocked.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PreTradeLimits {
+    pub max_order_qty: Option<Decimal>,
+    /// Largest OrderQty x price, the price of a market order being its reference price.
+    pub max_notional: Option<Decimal>,
+    /// Largest distance of a limit price from the symbol's reference price, in percent.
+    pub price_band_pct: Option<Decimal>,
+    pub reference_prices: HashMap<String, Decimal>,
+    pub max_open_orders: Option<usize>,
+}
+
+impl PreTradeLimits {
+    /// Checks a NewOrderSingle and returns why it is refused, for the Text of the reject.
+    pub fn check(
+        &self,
+        msg_map: &IndexMap<String, String>,
+        order_store: &OrderStore,
+    ) -> Result<(), String> {
+        let decimal = |field: &str| -> Result<Option<Decimal>, String> {
+            msg_map
+                .get(field)
+                .filter(|value| !value.is_empty())
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid {} {}", field, value))
+                })
+                .transpose()
+        };
+        let order_qty = decimal("OrderQty")?.unwrap_or_default();
+        let price = decimal("Price")?;
+        let symbol = msg_map.get("Symbol").map(String::as_str).unwrap_or("");
+        let reference_price = self.reference_prices.get(symbol).copied();
+
+        if let Some(max_order_qty) = self.max_order_qty {
+            if order_qty > max_order_qty {
+                return Err(format!(
+                    "OrderQty {} exceeds the maximum of {}",
+                    order_qty, max_order_qty
+                ));
+            }
+        }
+        if let (Some(max_notional), Some(price)) = (self.max_notional, price.or(reference_price)) {
+            let notional = order_qty * price;
+            if notional > max_notional {
+                return Err(format!(
+                    "Notional {} exceeds the maximum of {}",
+                    notional, max_notional
+                ));
+            }
+        }
+        if let (Some(band_pct), Some(price), Some(reference_price)) =
+            (self.price_band_pct, price, reference_price)
+        {
+            let band = reference_price * band_pct / Decimal::ONE_HUNDRED;
+            if (price - reference_price).abs() > band {
+                return Err(format!(
+                    "Price {} is outside {}% of the reference price {} of {}",
+                    price, band_pct, reference_price, symbol
+                ));
+            }
+        }
+        if let Some(max_open_orders) = self.max_open_orders {
+            let account = msg_map.get("Account").map(String::as_str).unwrap_or("");
+            let open_orders = order_store.open_orders_for_account(account).len();
+            if open_orders >= max_open_orders {
+                return Err(format!(
+                    "Account {} already has {} open orders, the maximum",
+                    account, open_orders
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Orders a second each account may send, with the orders accepted and throttled per account
+/// for the metrics. Nothing is throttled nor counted until configured.
+#[derive(Default)]
+pub struct AccountThrottle {
+    config: RwLock<Option<ThrottleConfig>>,
+    accounts: Mutex<HashMap<String, AccountFlow>>,
+}
+
+struct AccountFlow {
+    bucket: TokenBucket,
+    counters: ThrottleCounters,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThrottleCounters {
+    /// Orders within the rate.
+    pub accepted: u64,
+    /// Orders rejected for exceeding it.
+    pub throttled: u64,
+}
+
+impl AccountThrottle {
+    /// Sets the rate, None to stop throttling. The counters start over.
+    pub fn configure(&self, config: Option<ThrottleConfig>) {
+        *self.config.write().unwrap() = config;
+        self.accounts.lock().unwrap().clear();
+    }
+
+    /// Takes one order of the account off its rate, or returns why it is throttled.
+    pub fn check(&self, account: &str) -> Result<(), String> {
+        self.check_at(account, Instant::now())
+    }
+
+    fn check_at(&self, account: &str, now: Instant) -> Result<(), String> {
+        let Some(config) = *self.config.read().unwrap() else {
+            return Ok(());
+        };
+        let mut accounts = self.accounts.lock().unwrap();
+        let flow = accounts
+            .entry(account.to_string())
+            .or_insert_with(|| AccountFlow {
+                bucket: TokenBucket::new(&config),
+                counters: ThrottleCounters::default(),
+            });
+        match flow.bucket.try_take(now) {
+            Ok(()) => {
+                flow.counters.accepted += 1;
+                Ok(())
+            }
+            Err(_) => {
+                flow.counters.throttled += 1;
+                Err(format!(
+                    "Throttled: account {} exceeds {} orders per second",
+                    account, config.messages_per_second
+                ))
+            }
+        }
+    }
+
+    /// The counters of every account which sent an order, by account.
+    pub fn counters(&self) -> BTreeMap<String, ThrottleCounters> {
+        self.accounts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(account, flow)| (account.clone(), flow.counters))
+            .collect()
+    }
+}
+
+/// Checks a NewOrderSingle against the kill switch, the trading session status, the
+/// account's order rate, the symbol master and the configured pre-trade limits.
+pub fn check_pre_trade(
+    msg_map: &IndexMap<String, String>,
+    order_store: &OrderStore,
+) -> Result<(), RiskBreach> {
+    if let Some(reason) = KILL_SWITCH.engaged() {
+        return Err(RiskBreach {
+            reason: format!("Kill switch engaged: {}", reason),
+            canceled_orders: Vec::new(),
+        });
+    }
+    let (status, text) = TRADING_SESSION.status();
+    if status != TradSesStatus::Open {
+        return Err(RiskBreach {
+            reason: format!(
+                "Trading session {:?}{}",
+                status,
+                text.map(|text| format!(": {}", text)).unwrap_or_default()
+            ),
+            canceled_orders: Vec::new(),
+        });
+    }
+    let account = msg_map.get("Account").map(String::as_str).unwrap_or("");
+    let limits = PRE_TRADE_LIMITS.read().unwrap();
+    let symbol_master = SYMBOL_MASTER.read().unwrap();
+    let checked = ACCOUNT_THROTTLE
+        .check(account)
+        .and_then(|()| match symbol_master.as_ref() {
+            Some(symbol_master) => symbol_master.validate(msg_map),
+            None => Ok(()),
+        })
+        .and_then(|()| match limits.as_ref() {
+            Some(limits) => limits.check(msg_map, order_store),
+            None => Ok(()),
+        });
+    checked.map_err(|reason| {
+        warn!(
+            "Pre-trade check failed for order {:?}: {}",
+            msg_map.get("ClOrdID"),
+            reason
+        );
+        RiskBreach {
+            reason,
+            canceled_orders: Vec::new(),
+        }
+    })
+}
+
+/// A per-account limit breach: the account is blocked and its open orders were canceled.
+#[derive(Debug)]
+pub struct RiskBreach {
+    pub reason: String,
+    pub canceled_orders: Vec<Order>,
+}
+
+/// Accounts blocked after breaching a risk limit, with the reason of the breach.
+/// An account stays blocked until an operator clears it.
+pub struct AccountRisk {
+    blocked: RwLock<HashMap<String, String>>,
+}
+
+impl AccountRisk {
+    pub fn new() -> Self {
+        Self {
+            blocked: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the breach reason when the account is blocked.
+    pub fn blocked_reason(&self, account: &str) -> Option<String> {
+        self.blocked.read().unwrap().get(account).cloned()
+    }
+
+    /// Checks a new order against the account's open quantity limit (MAX_ACCOUNT_OPEN_QTY,
+    /// 0 disables it). A breach blocks the account and cancels all of its open orders.
+    pub fn check_new_order(
+        &self,
+        account: &str,
+        order_qty: Decimal,
+        order_store: &OrderStore,
+    ) -> Result<(), RiskBreach> {
+        if let Some(reason) = self.blocked_reason(account) {
+            return Err(RiskBreach {
+                reason: format!("Account {} is blocked: {}", account, reason),
+                canceled_orders: Vec::new(),
+            });
+        }
+
+        let max_open_qty = MAX_ACCOUNT_OPEN_QTY.load(Ordering::SeqCst);
+        let open_qty = order_store.open_quantity(account) + order_qty;
+        if max_open_qty == 0 || open_qty <= Decimal::from(max_open_qty) {
+            return Ok(());
+        }
+
+        let reason = format!("open quantity {} exceeds limit {}", open_qty, max_open_qty);
+        Err(self.breach(account, &reason, order_store))
+    }
+
+    /// Blocks the account and cancels all of its open orders, those of the other sessions and
+    /// of the matching engine as the kill switch does. The returned orders are those of
+    /// `order_store`, for the breaching session to report.
+    pub fn breach(&self, account: &str, reason: &str, order_store: &OrderStore) -> RiskBreach {
+        error!("Risk limit breached for account {}: {}", account, reason);
+        self.blocked
+            .write()
+            .unwrap()
+            .insert(account.to_string(), reason.to_string());
+
+        let text = format!("Account {} is blocked: {}", account, reason);
+        cancel_account_orders(account, &text, order_store);
+        let canceled_orders = match order_store.cancel_account_orders(account) {
+            Ok(orders) => orders,
+            Err(e) => {
+                error!("Failed to cancel orders of account {}: {}", account, e);
+                Vec::new()
+            }
+        };
+        info!(
+            "Canceled {} open orders of account {}",
+            canceled_orders.len(),
+            account
+        );
+
+        RiskBreach {
+            reason: text,
+            canceled_orders,
+        }
+    }
+
+    /// Unblocks the account. Returns false when it was not blocked.
+    pub fn clear(&self, account: &str) -> bool {
+        let cleared = self.blocked.write().unwrap().remove(account).is_some();
+        if cleared {
+            info!("Risk breach of account {} cleared", account);
+        }
+        cleared
+    }
+}
+
+impl Default for AccountRisk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderstore::OrderKey;
+    use crate::throttle::ThrottleAction;
+    use std::time::Duration;
+    use tempfile::NamedTempFile;
+
+    fn order(id: &str, account: &str, quantity: u64, ordstatus: &str) -> Order {
+        Order {
+            id: id.to_string(),
+            sender_comp_id: String::new(),
+            order_id: String::new(),
+            account: account.to_string(),
+            symbol: "IBM".to_string(),
+            side: "1".to_string(),
+            quantity: Decimal::from(quantity),
+            price: Some(Decimal::from(100)),
+            ordtype: "2".to_string(),
+            transacttime: "20240101-00:00:00".to_string(),
+            ordstatus: ordstatus.to_string(),
+            timeinforce: "DAY".to_string(),
+            cum_qty: Decimal::ZERO,
+            avg_px: Decimal::ZERO,
+            list_id: None,
+            list_seq_no: 0,
+            history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_breach_cancels_and_blocks_account() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store = OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+        order_store
+            .add_order(order("1", "ACC1", 100, "New"), None)
+            .unwrap();
+        order_store
+            .add_order(order("2", "ACC1", 50, "Canceled"), None)
+            .unwrap();
+        order_store
+            .add_order(order("3", "ACC2", 100, "New"), None)
+            .unwrap();
+
+        let risk = AccountRisk::new();
+        let breach = risk.breach("ACC1", "limit", &order_store);
+        assert_eq!(breach.canceled_orders.len(), 1);
+        assert_eq!(breach.canceled_orders[0].id, "1");
+        assert_eq!(order_store.get_order(&OrderKey::own("1")).unwrap().ordstatus, "Canceled");
+        assert_eq!(order_store.get_order(&OrderKey::own("3")).unwrap().ordstatus, "New");
+
+        let blocked = risk
+            .check_new_order("ACC1", Decimal::ONE, &order_store)
+            .unwrap_err();
+        assert!(blocked.canceled_orders.is_empty());
+        assert!(risk
+            .check_new_order("ACC2", Decimal::ONE, &order_store)
+            .is_ok());
+
+        assert!(risk.clear("ACC1"));
+        assert!(!risk.clear("ACC1"));
+        assert!(risk
+            .check_new_order("ACC1", Decimal::ONE, &order_store)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_account_throttle() {
+        let throttle = AccountThrottle::default();
+        let start = Instant::now();
+        assert!(throttle.check_at("ACC1", start).is_ok());
+        assert!(throttle.counters().is_empty());
+
+        throttle.configure(Some(ThrottleConfig {
+            messages_per_second: 2,
+            burst: 2,
+            action: ThrottleAction::Reject,
+        }));
+        assert!(throttle.check_at("ACC1", start).is_ok());
+        assert!(throttle.check_at("ACC1", start).is_ok());
+        assert!(throttle
+            .check_at("ACC1", start)
+            .unwrap_err()
+            .contains("2 orders per second"));
+        // Accounts have a rate of their own
+        assert!(throttle.check_at("ACC2", start).is_ok());
+        assert!(throttle
+            .check_at("ACC1", start + Duration::from_millis(500))
+            .is_ok());
+
+        let counters = throttle.counters();
+        assert_eq!(
+            counters["ACC1"],
+            ThrottleCounters {
+                accepted: 3,
+                throttled: 1
+            }
+        );
+        assert_eq!(counters["ACC2"].accepted, 1);
+    }
+
+    #[test]
+    fn test_pre_trade_limits() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let order_store = OrderStore::new(temp_file.path().to_str().unwrap(), 4096).unwrap();
+        order_store
+            .add_order(order("1", "ACC1", 100, "New"), None)
+            .unwrap();
+        let limits = PreTradeLimits {
+            max_order_qty: Some(Decimal::from(1000)),
+            max_notional: Some(Decimal::from(50_000)),
+            price_band_pct: Some(Decimal::from(10)),
+            reference_prices: HashMap::from([("IBM".to_string(), Decimal::from(100))]),
+            max_open_orders: Some(1),
+        };
+        let new_order = |fields: &[(&str, &str)]| -> IndexMap<String, String> {
+            let mut msg_map: IndexMap<String, String> =
+                [("Account", "ACC2"), ("Symbol", "IBM"), ("OrderQty", "100")]
+                    .iter()
+                    .map(|(field, value)| (field.to_string(), value.to_string()))
+                    .collect();
+            for (field, value) in fields {
+                msg_map.insert(field.to_string(), value.to_string());
+            }
+            msg_map
+        };
+        let check = |fields: &[(&str, &str)]| limits.check(&new_order(fields), &order_store);
+
+        assert!(check(&[("Price", "105")]).is_ok());
+        assert!(check(&[("OrderQty", "1001")])
+            .unwrap_err()
+            .starts_with("OrderQty 1001"));
+        // A market order is valued at the reference price
+        assert!(check(&[("OrderQty", "600")])
+            .unwrap_err()
+            .starts_with("Notional 60000"));
+        assert!(check(&[("Price", "89")])
+            .unwrap_err()
+            .contains("reference price 100"));
+        assert!(check(&[("Price", "89"), ("Symbol", "AAPL")]).is_ok());
+        assert!(check(&[("Account", "ACC1")])
+            .unwrap_err()
+            .contains("1 open orders"));
+        assert!(check(&[("Price", "abc")]).is_err());
+    }
+}
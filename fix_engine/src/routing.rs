@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use indexmap::IndexMap;
+
+use crate::bridge::{is_bridged, venue_holding};
+use crate::sequence::SessionId;
+
+lazy_static! {
+    /// Where the acceptor sends the business messages of its clients, None when it handles
+    /// them all itself.
+    pub static ref ROUTING_RULES: RwLock<Option<Arc<RoutingRules>>> = RwLock::new(None);
+}
+
+/// Where a business message goes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteTarget {
+    /// Handled by the engine: order store, matching engine or simulator.
+    Internal,
+    /// Forwarded to the bridge venue with this TargetCompID.
+    Venue(String),
+    /// Refused with a BusinessMessageReject.
+    Reject,
+}
+
+impl RouteTarget {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "internal" => RouteTarget::Internal,
+            "reject" => RouteTarget::Reject,
+            venue => RouteTarget::Venue(venue.to_string()),
+        }
+    }
+}
+
+/// Sends the messages whose fields all have one of the given values to the target. A field is
+/// named as in the dictionary, matching the value described (e.g. `Side=BUY`), or by tag
+/// number, matching the value on the wire, for custom tags.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutingRule {
+    conditions: Vec<(String, Vec<String>)>,
+    pub target: RouteTarget,
+}
+
+impl RoutingRule {
+    /// Parses `<field>=<value>[|<value>...][,<field>=...] -> <target>`.
+    pub fn parse(rule: &str) -> Result<Self, String> {
+        let (conditions, target) = rule
+            .split_once("->")
+            .ok_or_else(|| format!("Missing -> in routing rule {}", rule))?;
+        let conditions = conditions
+            .split(',')
+            .map(|condition| {
+                let (field, values) = condition
+                    .split_once('=')
+                    .ok_or_else(|| format!("Invalid condition {} in routing rule", condition))?;
+                let values = values.split('|').map(|value| value.trim().to_string());
+                Ok((field.trim().to_string(), values.collect()))
+            })
+            .collect::<Result<_, String>>()?;
+        let target = target.trim();
+        if target.is_empty() {
+            return Err(format!("Missing target in routing rule {}", rule));
+        }
+        Ok(RoutingRule {
+            conditions,
+            target: RouteTarget::parse(target),
+        })
+    }
+
+    pub fn matches(&self, msg_map: &IndexMap<String, String>, message: &str) -> bool {
+        self.conditions.iter().all(|(field, values)| {
+            let value = if field.parse::<u32>().is_ok() {
+                wire_value(message, field)
+            } else {
+                msg_map.get(field).map(String::as_str)
+            };
+            value.is_some_and(|value| values.iter().any(|allowed| allowed == value))
+        })
+    }
+}
+
+/// The value of the tag in the raw message.
+fn wire_value<'a>(message: &'a str, tag: &str) -> Option<&'a str> {
+    message
+        .split(['\x01', '|'])
+        .filter_map(|field| field.split_once('='))
+        .find(|(number, _)| *number == tag)
+        .map(|(_, value)| value)
+}
+
+/// The rules of the `[routing]` section, tried in order, and where the order requests
+/// matching none go.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutingRules {
+    rules: Vec<RoutingRule>,
+    default: RouteTarget,
+}
+
+impl RoutingRules {
+    pub fn new(rules: Vec<RoutingRule>, default: RouteTarget) -> Self {
+        Self { rules, default }
+    }
+
+    /// Parses the `rule.<n>` entries of the section in the order of n; `default` sets the
+    /// target of the order requests none matches, `default_target` otherwise.
+    pub fn parse(
+        section: &HashMap<String, String>,
+        default_target: RouteTarget,
+    ) -> Result<Self, String> {
+        let mut numbered = Vec::new();
+        for (key, rule) in section {
+            let Some(number) = key.strip_prefix("rule.") else {
+                continue;
+            };
+            let number: u32 = number
+                .parse()
+                .map_err(|_| format!("Invalid routing rule number {}", key))?;
+            numbered.push((number, RoutingRule::parse(rule)?));
+        }
+        numbered.sort_by_key(|(number, _)| *number);
+        let default = section
+            .get("default")
+            .map_or(default_target, |target| RouteTarget::parse(target));
+        Ok(Self::new(
+            numbered.into_iter().map(|(_, rule)| rule).collect(),
+            default,
+        ))
+    }
+
+    /// The venues the rules and the default send messages to.
+    pub fn venues(&self) -> Vec<&str> {
+        self.rules
+            .iter()
+            .map(|rule| &rule.target)
+            .chain([&self.default])
+            .filter_map(|target| match target {
+                RouteTarget::Venue(venue) => Some(venue.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The target of the first rule the message matches. An order request matching none goes
+    /// to the default, any other message is handled by the engine.
+    pub fn target(
+        &self,
+        msg_type: &str,
+        msg_map: &IndexMap<String, String>,
+        message: &str,
+    ) -> RouteTarget {
+        match self
+            .rules
+            .iter()
+            .find(|rule| rule.matches(msg_map, message))
+        {
+            Some(rule) => rule.target.clone(),
+            None if is_bridged(msg_type) => self.default.clone(),
+            None => RouteTarget::Internal,
+        }
+    }
+}
+
+/// Where the business message of the client goes: the venue an order was forwarded to for
+/// the requests naming it, otherwise as the routing rules decide.
+pub fn route(
+    client: &SessionId,
+    msg_type: &str,
+    msg_map: &IndexMap<String, String>,
+    message: &str,
+) -> RouteTarget {
+    let Some(rules) = ROUTING_RULES.read().unwrap().clone() else {
+        return RouteTarget::Internal;
+    };
+    let named_order = match msg_type {
+        "ORDER_STATUS_REQUEST" => msg_map.get("ClOrdID"),
+        _ => msg_map.get("OrigClOrdID"),
+    };
+    if let Some(bridge) = named_order.and_then(|cl_ord_id| venue_holding(client, cl_ord_id)) {
+        return RouteTarget::Venue(bridge.name().to_string());
+    }
+    rules.target(msg_type, msg_map, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg_map;
+
+    #[test]
+    fn test_messages_are_routed_by_the_first_matching_rule() {
+        let section = HashMap::from([
+            (
+                "rule.2".to_string(),
+                "MsgType=NEW_ORDER_SINGLE,Symbol=IBM|MSFT -> VENUE_A".to_string(),
+            ),
+            (
+                "rule.1".to_string(),
+                "Account=BLOCKED -> reject".to_string(),
+            ),
+            ("rule.3".to_string(), "5001=DARK -> VENUE_B".to_string()),
+            ("rule.4".to_string(), "SenderSubID=DESK2 -> VENUE_B".to_string()),
+        ]);
+        let rules =
+            RoutingRules::parse(&section, RouteTarget::Venue("VENUE_A".to_string())).unwrap();
+        assert_eq!(rules.venues(), vec!["VENUE_A", "VENUE_B", "VENUE_B", "VENUE_A"]);
+
+        let order = |symbol: &str, account: &str| {
+            msg_map(&[
+                ("MsgType", "NEW_ORDER_SINGLE"),
+                ("Symbol", symbol),
+                ("Account", account),
+            ])
+        };
+        let target = |msg_map: &IndexMap<String, String>, message: &str| {
+            rules.target(&msg_map["MsgType"], msg_map, message)
+        };
+        assert_eq!(
+            target(&order("MSFT", "A1"), ""),
+            RouteTarget::Venue("VENUE_A".to_string())
+        );
+        assert_eq!(target(&order("IBM", "BLOCKED"), ""), RouteTarget::Reject);
+        assert_eq!(
+            target(&order("AAPL", "A1"), "8=FIX.4.2|35=D|5001=DARK|10=000|"),
+            RouteTarget::Venue("VENUE_B".to_string())
+        );
+        assert_eq!(
+            target(&order("AAPL", "A1"), "8=FIX.4.2|35=D|10=000|"),
+            RouteTarget::Venue("VENUE_A".to_string())
+        );
+        let mut desk_order = order("AAPL", "A1");
+        desk_order.insert("SenderSubID".to_string(), "DESK2".to_string());
+        assert_eq!(
+            target(&desk_order, "8=FIX.4.2|35=D|50=DESK2|10=000|"),
+            RouteTarget::Venue("VENUE_B".to_string())
+        );
+        assert_eq!(
+            target(&msg_map(&[("MsgType", "QUOTE_REQUEST")]), ""),
+            RouteTarget::Internal
+        );
+
+        let section = HashMap::from([("rule.x".to_string(), "Symbol=IBM -> internal".to_string())]);
+        assert!(RoutingRules::parse(&section, RouteTarget::Internal).is_err());
+        assert!(RoutingRule::parse("Symbol=IBM").is_err());
+    }
+}
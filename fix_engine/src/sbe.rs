@@ -0,0 +1,742 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, RwLock};
+
+use log::debug;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use rust_decimal::Decimal;
+
+use crate::message_converter::append_fields;
+use crate::sequence::SessionId;
+use crate::transport::{split_messages, Transport};
+
+lazy_static! {
+    /// The SBE templates of the sessions, None unless `encoding=sbe`.
+    pub static ref SBE_SCHEMA: RwLock<Option<Arc<SbeSchema>>> = RwLock::new(None);
+}
+
+/// Simple Open Framing Header: the frame length (header included) and the encoding type,
+/// both big-endian.
+const FRAME_HEADER_LENGTH: usize = 6;
+/// Largest frame read. The templates hold fixed-length blocks only, so a longer frame is
+/// corrupt, and its length must not size the read buffer.
+const MAX_FRAME_LENGTH: usize = 64 * 1024;
+/// Encoding type of little-endian SBE 1.0 in the framing header.
+const SBE_ENCODING_TYPE: u16 = 0x5BE0;
+/// blockLength, templateId, schemaId and version, little-endian.
+const MESSAGE_HEADER_LENGTH: usize = 8;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Encoding of a field in the block of its template.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldType {
+    /// Fixed-length characters, padded with NULs.
+    Char(usize),
+    /// Integer of 1, 2, 4 or 8 bytes, its highest (unsigned) or lowest (signed) value null.
+    Int { size: usize, signed: bool },
+    /// int64 mantissa, followed by an int8 exponent unless the schema fixes it.
+    Decimal { exponent: Option<i8> },
+}
+
+impl FieldType {
+    fn primitive(name: &str, length: usize) -> Option<Self> {
+        let (size, signed) = match name {
+            "char" => return Some(FieldType::Char(length)),
+            "int8" => (1, true),
+            "int16" => (2, true),
+            "int32" => (4, true),
+            "int64" => (8, true),
+            "uint8" => (1, false),
+            "uint16" => (2, false),
+            "uint32" => (4, false),
+            "uint64" => (8, false),
+            _ => return None,
+        };
+        Some(FieldType::Int { size, signed })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            FieldType::Char(length) => *length,
+            FieldType::Int { size, .. } => *size,
+            FieldType::Decimal { exponent: Some(_) } => 8,
+            FieldType::Decimal { exponent: None } => 9,
+        }
+    }
+
+    /// The null value and the valid range of an integer type.
+    fn int_range(size: usize, signed: bool) -> (i128, std::ops::RangeInclusive<i128>) {
+        let bits = 8 * size as u32;
+        if signed {
+            let min = -(1i128 << (bits - 1));
+            (min, min + 1..=(1i128 << (bits - 1)) - 1)
+        } else {
+            let max = (1i128 << bits) - 1;
+            (max, 0..=max - 1)
+        }
+    }
+
+    fn encode(&self, value: &str, target: &mut [u8]) -> Result<(), String> {
+        match *self {
+            FieldType::Char(length) => {
+                if value.len() > length {
+                    return Err(format!("longer than {} characters", length));
+                }
+                target[..value.len()].copy_from_slice(value.as_bytes());
+            }
+            FieldType::Int { size, signed } => {
+                let (_, range) = Self::int_range(size, signed);
+                let number = value
+                    .parse::<i128>()
+                    .ok()
+                    .filter(|number| range.contains(number))
+                    .ok_or_else(|| {
+                        format!("not an {}int{}", if signed { "" } else { "u" }, 8 * size)
+                    })?;
+                target.copy_from_slice(&number.to_le_bytes()[..size]);
+            }
+            FieldType::Decimal { exponent } => {
+                let decimal = value
+                    .parse::<Decimal>()
+                    .map_err(|_| "not a decimal".to_string())?;
+                let (mantissa, exponent) = match exponent {
+                    Some(exponent) => {
+                        let mut scaled = decimal;
+                        scaled.rescale(exponent.unsigned_abs() as u32);
+                        if scaled != decimal {
+                            return Err(format!("more decimals than exponent {}", exponent));
+                        }
+                        (scaled.mantissa(), exponent)
+                    }
+                    None => (decimal.mantissa(), -(decimal.scale() as i8)),
+                };
+                let mantissa = i64::try_from(mantissa)
+                    .ok()
+                    .filter(|mantissa| *mantissa != i64::MIN)
+                    .ok_or_else(|| "out of range".to_string())?;
+                target[..8].copy_from_slice(&mantissa.to_le_bytes());
+                if self.size() == 9 {
+                    target[8] = exponent as u8;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the null value of the type.
+    fn encode_null(&self, target: &mut [u8]) {
+        match *self {
+            FieldType::Char(_) => target.fill(0),
+            FieldType::Int { size, signed } => {
+                let (null, _) = Self::int_range(size, signed);
+                target.copy_from_slice(&null.to_le_bytes()[..size]);
+            }
+            FieldType::Decimal { .. } => {
+                target[..8].copy_from_slice(&i64::MIN.to_le_bytes());
+                if self.size() == 9 {
+                    target[8] = 0;
+                }
+            }
+        }
+    }
+
+    /// The value held, None when null.
+    fn decode(&self, source: &[u8]) -> Option<String> {
+        match *self {
+            FieldType::Char(_) => {
+                let length = source
+                    .iter()
+                    .position(|byte| *byte == 0)
+                    .unwrap_or(source.len());
+                (length > 0).then(|| String::from_utf8_lossy(&source[..length]).to_string())
+            }
+            FieldType::Int { size, signed } => {
+                let negative = signed && source[size - 1] & 0x80 != 0;
+                let mut bytes = [if negative { 0xFF } else { 0 }; 16];
+                bytes[..size].copy_from_slice(source);
+                let number = i128::from_le_bytes(bytes);
+                let (null, _) = Self::int_range(size, signed);
+                (number != null).then(|| number.to_string())
+            }
+            FieldType::Decimal { exponent } => {
+                let mantissa = i64::from_le_bytes(source[..8].try_into().unwrap());
+                if mantissa == i64::MIN {
+                    return None;
+                }
+                let exponent = exponent.unwrap_or(source.get(8).copied().unwrap_or(0) as i8);
+                // Values no Decimal holds are dropped like nulls
+                let decimal = if exponent < 0 {
+                    Decimal::try_from_i128_with_scale(
+                        mantissa as i128,
+                        exponent.unsigned_abs() as u32,
+                    )
+                    .ok()?
+                } else {
+                    let power = 10i64.checked_pow(exponent as u32)?;
+                    Decimal::from(mantissa).checked_mul(Decimal::from(power))?
+                };
+                Some(decimal.normalize().to_string())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SbeField {
+    tag: u32,
+    offset: usize,
+    field_type: FieldType,
+}
+
+/// The fixed block of one FIX message type.
+#[derive(Debug, Clone)]
+struct SbeTemplate {
+    id: u16,
+    msg_type: String,
+    block_length: usize,
+    fields: Vec<SbeField>,
+}
+
+/// The message templates of an SBE XML schema (`sbe:messageSchema`), each `sbe:message` naming
+/// its MsgType in `semanticType` and each field its tag in `id`. Fields are `char` (with a
+/// `length`), integer primitives or decimal composites of a `mantissa` and an `exponent`;
+/// repeating groups and variable-length data are not supported.
+#[derive(Debug)]
+pub struct SbeSchema {
+    id: u16,
+    version: u16,
+    templates: Vec<SbeTemplate>,
+}
+
+impl SbeSchema {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let xml = fs::read_to_string(path)?;
+        Self::parse(&xml).map_err(|e| invalid(format!("SBE schema {}: {}", path, e)))
+    }
+
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        let mut reader = Reader::from_str(xml);
+        reader.trim_text(true);
+        let mut schema = SbeSchema {
+            id: 0,
+            version: 0,
+            templates: Vec::new(),
+        };
+        let mut types: HashMap<String, FieldType> = HashMap::new();
+        // The decimal composite being read: its name, and its exponent once known
+        let mut composite: Option<(String, Option<Option<i8>>)> = None;
+        let mut constant_exponent = false;
+        loop {
+            let event = reader.read_event().map_err(|e| e.to_string())?;
+            let element = match &event {
+                Event::Start(element) | Event::Empty(element) => element,
+                Event::Text(text) if constant_exponent => {
+                    let value = text.unescape().map_err(|e| e.to_string())?;
+                    let exponent = value
+                        .trim()
+                        .parse::<i8>()
+                        .ok()
+                        .filter(|exponent| *exponent <= 0)
+                        .ok_or_else(|| format!("Invalid constant exponent {}", value))?;
+                    if let Some((_, known)) = composite.as_mut() {
+                        *known = Some(Some(exponent));
+                    }
+                    constant_exponent = false;
+                    continue;
+                }
+                Event::End(element) if element.local_name().as_ref() == b"composite" => {
+                    if let Some((name, Some(exponent))) = composite.take() {
+                        types.insert(name, FieldType::Decimal { exponent });
+                    }
+                    continue;
+                }
+                Event::Eof => break,
+                _ => continue,
+            };
+            let mut attributes = HashMap::new();
+            for attribute in element.attributes() {
+                let attribute = attribute.map_err(|e| e.to_string())?;
+                let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).to_string();
+                let value = attribute.unescape_value().map_err(|e| e.to_string())?;
+                attributes.insert(key, value.to_string());
+            }
+            let attribute = |name: &str| {
+                attributes
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| format!("Missing {} of {:?}", name, attributes))
+            };
+            let number = |name: &str| {
+                attribute(name)?
+                    .parse::<u32>()
+                    .map_err(|_| format!("Invalid {} in {:?}", name, attributes))
+            };
+            match element.local_name().as_ref() {
+                b"messageSchema" => {
+                    if attributes
+                        .get("byteOrder")
+                        .is_some_and(|order| order != "littleEndian")
+                    {
+                        return Err("Only littleEndian schemas are supported".to_string());
+                    }
+                    schema.id = number("id")? as u16;
+                    schema.version = attributes
+                        .get("version")
+                        .map_or(Ok(0), |_| number("version"))?
+                        as u16;
+                }
+                b"composite" => {
+                    composite = Some((attribute("name")?, None));
+                }
+                b"type" => match composite.as_mut() {
+                    Some((_, exponent))
+                        if attributes.get("name").map(String::as_str) == Some("exponent") =>
+                    {
+                        if attributes.get("presence").map(String::as_str) == Some("constant") {
+                            constant_exponent = true;
+                        } else {
+                            *exponent = Some(None);
+                        }
+                    }
+                    Some(_) => {}
+                    None => {
+                        let length = attributes
+                            .get("length")
+                            .map_or(Ok(1), |_| number("length"))?;
+                        let primitive = attribute("primitiveType")?;
+                        let field_type = FieldType::primitive(&primitive, length as usize)
+                            .ok_or_else(|| format!("Unsupported primitiveType {}", primitive))?;
+                        types.insert(attribute("name")?, field_type);
+                    }
+                },
+                b"message" => {
+                    schema.templates.push(SbeTemplate {
+                        id: number("id")? as u16,
+                        msg_type: attribute("semanticType")?,
+                        block_length: 0,
+                        fields: Vec::new(),
+                    });
+                }
+                b"field" => {
+                    let template = schema
+                        .templates
+                        .last_mut()
+                        .ok_or_else(|| "field outside of a message".to_string())?;
+                    let type_name = attribute("type")?;
+                    let field_type = types
+                        .get(&type_name)
+                        .copied()
+                        .or_else(|| FieldType::primitive(&type_name, 1))
+                        .ok_or_else(|| format!("Unknown type {}", type_name))?;
+                    let offset = match attributes.get("offset") {
+                        Some(_) => number("offset")? as usize,
+                        None => template.block_length,
+                    };
+                    template.block_length = template.block_length.max(offset + field_type.size());
+                    template.fields.push(SbeField {
+                        tag: number("id")?,
+                        offset,
+                        field_type,
+                    });
+                }
+                b"group" | b"data" => {
+                    return Err(
+                        "Repeating groups and variable-length data are not supported".to_string(),
+                    );
+                }
+                _ => {}
+            }
+        }
+        if schema.templates.is_empty() {
+            return Err("No messages".to_string());
+        }
+        Ok(schema)
+    }
+
+    pub fn template_count(&self) -> usize {
+        self.templates.len()
+    }
+
+    /// The SOH delimited FIX message as a framed SBE message. Fields its template has no room
+    /// for are left out.
+    pub fn encode(&self, message: &[u8]) -> io::Result<Vec<u8>> {
+        let message = String::from_utf8_lossy(message);
+        let fields: Vec<(&str, &str)> = message
+            .split('\x01')
+            .filter_map(|field| field.split_once('='))
+            .collect();
+        let msg_type = fields
+            .iter()
+            .find(|(tag, _)| *tag == "35")
+            .map(|(_, value)| *value)
+            .ok_or_else(|| invalid("Missing MsgType".to_string()))?;
+        let template = self
+            .templates
+            .iter()
+            .find(|template| template.msg_type == msg_type)
+            .ok_or_else(|| invalid(format!("No SBE template for MsgType {}", msg_type)))?;
+
+        let mut block = vec![0; template.block_length];
+        for field in &template.fields {
+            field
+                .field_type
+                .encode_null(&mut block[field.offset..field.offset + field.field_type.size()]);
+        }
+        for (tag, value) in &fields {
+            if matches!(*tag, "8" | "9" | "10" | "35") {
+                continue;
+            }
+            let Some(field) = template
+                .fields
+                .iter()
+                .find(|field| tag.parse() == Ok(field.tag))
+            else {
+                debug!("Tag {} left out of SBE template {}", tag, template.id);
+                continue;
+            };
+            let target = &mut block[field.offset..field.offset + field.field_type.size()];
+            field
+                .field_type
+                .encode(value, target)
+                .map_err(|e| invalid(format!("Tag {} value {}: {}", tag, value, e)))?;
+        }
+
+        let length = FRAME_HEADER_LENGTH + MESSAGE_HEADER_LENGTH + block.len();
+        let mut frame = Vec::with_capacity(length);
+        frame.extend_from_slice(&(length as u32).to_be_bytes());
+        frame.extend_from_slice(&SBE_ENCODING_TYPE.to_be_bytes());
+        for value in [
+            template.block_length as u16,
+            template.id,
+            self.id,
+            self.version,
+        ] {
+            frame.extend_from_slice(&value.to_le_bytes());
+        }
+        frame.extend_from_slice(&block);
+        Ok(frame)
+    }
+
+    /// The SOH delimited FIX message of an SBE message, its framing header removed, with its
+    /// BodyLength and CheckSum.
+    pub fn decode(&self, message: &[u8], begin_string: &str) -> io::Result<String> {
+        if message.len() < MESSAGE_HEADER_LENGTH {
+            return Err(invalid("Truncated SBE message header".to_string()));
+        }
+        let header =
+            |index: usize| u16::from_le_bytes([message[2 * index], message[2 * index + 1]]);
+        let (block_length, template_id, schema_id) = (header(0) as usize, header(1), header(2));
+        if schema_id != self.id {
+            return Err(invalid(format!(
+                "SBE message of schema {}, expected {}",
+                schema_id, self.id
+            )));
+        }
+        let template = self
+            .templates
+            .iter()
+            .find(|template| template.id == template_id)
+            .ok_or_else(|| invalid(format!("Unknown SBE template {}", template_id)))?;
+        let block = &message[MESSAGE_HEADER_LENGTH..];
+        if block.len() < block_length || block_length < template.block_length {
+            return Err(invalid(format!(
+                "Truncated SBE message of template {}",
+                template_id
+            )));
+        }
+        let fields: Vec<(u32, String)> = template
+            .fields
+            .iter()
+            .filter_map(|field| {
+                let source = &block[field.offset..field.offset + field.field_type.size()];
+                field
+                    .field_type
+                    .decode(source)
+                    .map(|value| (field.tag, value))
+            })
+            .collect();
+        let header = format!("8={}|35={}|", begin_string, template.msg_type);
+        Ok(append_fields(&header, &fields).replace('|', "\x01"))
+    }
+}
+
+/// A transport exchanging SBE frames with the counterparty while the engine reads and writes
+/// tag=value messages, one decoded message per read like the read loop assumes.
+struct SbeTransport {
+    inner: Box<dyn Transport>,
+    schema: Arc<SbeSchema>,
+    begin_string: String,
+    /// What is left of the last message decoded, for reads into a smaller buffer.
+    pending: Vec<u8>,
+}
+
+impl SbeTransport {
+    /// The next frame without its framing header, None once the connection is closed.
+    fn read_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut header = [0; FRAME_HEADER_LENGTH];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.inner.read(&mut header[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                read => filled += read,
+            }
+        }
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let encoding_type = u16::from_be_bytes([header[4], header[5]]);
+        if encoding_type != SBE_ENCODING_TYPE
+            || !(FRAME_HEADER_LENGTH..=MAX_FRAME_LENGTH).contains(&length)
+        {
+            return Err(invalid(format!(
+                "Invalid SBE frame of encoding type {:#06x} and length {}",
+                encoding_type, length
+            )));
+        }
+        let mut message = vec![0; length - FRAME_HEADER_LENGTH];
+        self.inner.read_exact(&mut message)?;
+        Ok(Some(message))
+    }
+}
+
+impl Read for SbeTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            match self.read_frame()? {
+                Some(message) => {
+                    self.pending = self
+                        .schema
+                        .decode(&message, &self.begin_string)?
+                        .into_bytes();
+                }
+                None => return Ok(0),
+            }
+        }
+        let length = buf.len().min(self.pending.len());
+        buf[..length].copy_from_slice(&self.pending[..length]);
+        self.pending.drain(..length);
+        Ok(length)
+    }
+}
+
+impl Write for SbeTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut frames = Vec::new();
+        for message in split_messages(buf) {
+            frames.extend(self.schema.encode(message)?);
+        }
+        self.inner.write_all(&frames)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Transport for SbeTransport {
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(Self {
+            inner: self.inner.try_clone_transport()?,
+            schema: Arc::clone(&self.schema),
+            begin_string: self.begin_string.clone(),
+            pending: Vec::new(),
+        }))
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.inner.close()
+    }
+
+    fn peer(&self) -> String {
+        self.inner.peer()
+    }
+}
+
+/// Wraps the connection of a session so its messages travel SBE encoded, when `encoding=sbe`.
+pub fn with_sbe_codec(stream: Box<dyn Transport>, session_id: &SessionId) -> Box<dyn Transport> {
+    match SBE_SCHEMA.read().unwrap().as_ref() {
+        Some(schema) => Box::new(SbeTransport {
+            inner: stream,
+            schema: Arc::clone(schema),
+            begin_string: session_id.begin_string.clone(),
+            pending: Vec::new(),
+        }),
+        None => stream,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    const SCHEMA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<sbe:messageSchema xmlns:sbe="http://fixprotocol.io/2016/sbe" id="42" version="1"
+                   byteOrder="littleEndian">
+  <types>
+    <type name="CompID" primitiveType="char" length="8"/>
+    <composite name="Price">
+      <type name="mantissa" primitiveType="int64"/>
+      <type name="exponent" primitiveType="int8" presence="constant">-4</type>
+    </composite>
+    <composite name="Qty">
+      <type name="mantissa" primitiveType="int64"/>
+      <type name="exponent" primitiveType="int8"/>
+    </composite>
+  </types>
+  <sbe:message name="NewOrderSingle" id="14" semanticType="D">
+    <field name="SenderCompID" id="49" type="CompID"/>
+    <field name="MsgSeqNum" id="34" type="uint32"/>
+    <field name="ClOrdID" id="11" type="CompID"/>
+    <field name="Side" id="54" type="char"/>
+    <field name="OrderQty" id="38" type="Qty"/>
+    <field name="Price" id="44" type="Price"/>
+    <field name="StopPx" id="99" type="Price"/>
+  </sbe:message>
+</sbe:messageSchema>"#;
+
+    #[test]
+    fn test_sbe_transport_round_trip() {
+        let schema = Arc::new(SbeSchema::parse(SCHEMA).unwrap());
+        assert_eq!(schema.templates[0].block_length, 8 + 4 + 8 + 1 + 9 + 8 + 8);
+        let order = append_fields(
+            "8=FIX.4.2|35=D|",
+            &[
+                (49, "CLIENT".to_string()),
+                (34, "7".to_string()),
+                (11, "C1".to_string()),
+                (54, "1".to_string()),
+                (38, "100".to_string()),
+                (44, "187.5".to_string()),
+                (5001, "X".to_string()),
+            ],
+        )
+        .replace('|', "\x01");
+
+        let (local, remote) = MemoryTransport::pair();
+        let mut sender = SbeTransport {
+            inner: Box::new(local),
+            schema: Arc::clone(&schema),
+            begin_string: "FIX.4.2".to_string(),
+            pending: Vec::new(),
+        };
+        let mut receiver = SbeTransport {
+            inner: Box::new(remote),
+            schema: Arc::clone(&schema),
+            begin_string: "FIX.4.2".to_string(),
+            pending: Vec::new(),
+        };
+        // Two messages written at once travel as two frames
+        sender
+            .write_all(format!("{}{}", order, order).as_bytes())
+            .unwrap();
+
+        // The custom tag is left out and the null StopPx stays absent
+        let expected = append_fields(
+            "8=FIX.4.2|35=D|",
+            &[
+                (49, "CLIENT".to_string()),
+                (34, "7".to_string()),
+                (11, "C1".to_string()),
+                (54, "1".to_string()),
+                (38, "100".to_string()),
+                (44, "187.5".to_string()),
+            ],
+        )
+        .replace('|', "\x01");
+        let mut buf = [0; 1024];
+        let length = receiver.read(&mut buf).unwrap();
+        assert_eq!(String::from_utf8_lossy(&buf[..length]), expected);
+        let mut small = [0; 16];
+        assert_eq!(receiver.read(&mut small).unwrap(), 16);
+        assert_eq!(&small, &expected.as_bytes()[..16]);
+
+        let unknown = "8=FIX.4.2\x019=5\x0135=0\x0110=000\x01";
+        assert!(sender.write_all(unknown.as_bytes()).is_err());
+        let price = expected.replace("44=187.5", "44=187.50001");
+        assert!(schema.encode(price.as_bytes()).is_err());
+        let reference = SbeSchema::load("reference/FIX4_2_SBE.xml").unwrap();
+        assert_eq!(reference.template_count(), 13);
+        assert!(
+            SbeSchema::parse(&SCHEMA.replace("field name=\"Side\"", "data name=\"Side\"")).is_err()
+        );
+    }
+
+    fn sbe_pair() -> (MemoryTransport, SbeTransport) {
+        let (local, remote) = MemoryTransport::pair();
+        let receiver = SbeTransport {
+            inner: Box::new(remote),
+            schema: Arc::new(SbeSchema::parse(SCHEMA).unwrap()),
+            begin_string: "FIX.4.2".to_string(),
+            pending: Vec::new(),
+        };
+        (local, receiver)
+    }
+
+    fn frame_header(length: usize, encoding_type: u16) -> Vec<u8> {
+        let mut header = (length as u32).to_be_bytes().to_vec();
+        header.extend_from_slice(&encoding_type.to_be_bytes());
+        header
+    }
+
+    #[test]
+    fn test_oversized_frame_is_refused() {
+        let (mut sender, mut receiver) = sbe_pair();
+        sender
+            .write_all(&frame_header(u32::MAX as usize, SBE_ENCODING_TYPE))
+            .unwrap();
+        let error = receiver.read(&mut [0; 1024]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let (mut sender, mut receiver) = sbe_pair();
+        sender
+            .write_all(&frame_header(MAX_FRAME_LENGTH + 1, SBE_ENCODING_TYPE))
+            .unwrap();
+        assert!(receiver.read(&mut [0; 1024]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_frame_headers() {
+        // Shorter than its own header
+        let (mut sender, mut receiver) = sbe_pair();
+        sender
+            .write_all(&frame_header(FRAME_HEADER_LENGTH - 1, SBE_ENCODING_TYPE))
+            .unwrap();
+        assert!(receiver.read(&mut [0; 1024]).is_err());
+
+        // Not SBE
+        let (mut sender, mut receiver) = sbe_pair();
+        sender.write_all(&frame_header(64, 0x0001)).unwrap();
+        assert!(receiver.read(&mut [0; 1024]).is_err());
+
+        // No room for the message header
+        let (mut sender, mut receiver) = sbe_pair();
+        sender
+            .write_all(&frame_header(FRAME_HEADER_LENGTH, SBE_ENCODING_TYPE))
+            .unwrap();
+        assert!(receiver.read(&mut [0; 1024]).is_err());
+    }
+
+    #[test]
+    fn test_truncated_frame() {
+        let (mut sender, mut receiver) = sbe_pair();
+        let mut frame = frame_header(FRAME_HEADER_LENGTH + 20, SBE_ENCODING_TYPE);
+        frame.extend_from_slice(&[0; 10]);
+        sender.write_all(&frame).unwrap();
+        sender.close().unwrap();
+        assert_eq!(
+            receiver.read(&mut [0; 1024]).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        // Closed between frames
+        let (sender, mut receiver) = sbe_pair();
+        sender.close().unwrap();
+        assert_eq!(receiver.read(&mut [0; 1024]).unwrap(), 0);
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::{Arc, RwLock};
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, TimeZone, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use log::{error, info};
+
+use crate::gap_report::write_gap_report;
+use crate::message_journal::MESSAGE_JOURNALS;
+use crate::sequence::SequenceStores;
+
+lazy_static! {
+    pub static ref SESSION_SCHEDULE: RwLock<Option<SessionSchedule>> = RwLock::new(None);
+}
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// Trading hours of the session. Without start/end days the session runs every day
+/// between start_time and end_time; with them it runs once a week from
+/// start_day start_time to end_day end_time. An end before the start wraps around midnight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSchedule {
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub start_day: Option<Weekday>,
+    pub end_day: Option<Weekday>,
+    pub timezone: Tz,
+}
+
+impl SessionSchedule {
+    pub fn is_in_session(&self, now: DateTime<Utc>) -> bool {
+        let local = now.with_timezone(&self.timezone);
+        let (start, end, position) = match (self.start_day, self.end_day) {
+            (Some(start_day), Some(end_day)) => (
+                week_seconds(start_day, self.start_time),
+                week_seconds(end_day, self.end_time),
+                week_seconds(local.weekday(), local.time()),
+            ),
+            _ => (
+                day_seconds(self.start_time),
+                day_seconds(self.end_time),
+                day_seconds(local.time()),
+            ),
+        };
+
+        if start < end {
+            start <= position && position < end
+        } else if start > end {
+            position >= start || position < end
+        } else {
+            true
+        }
+    }
+}
+
+/// Time of day at which both sequence numbers roll back to 1 and the journal is archived.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyReset {
+    pub reset_time: NaiveTime,
+    pub timezone: Tz,
+}
+
+impl DailyReset {
+    /// Returns the most recent reset instant at or before `now`.
+    pub fn last_reset_at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = now.with_timezone(&self.timezone).date_naive();
+        let reset_on = |date: NaiveDate| {
+            // On a DST gap the reset happens at the first valid instant after it
+            let local = date.and_time(self.reset_time);
+            self.timezone
+                .from_local_datetime(&local)
+                .earliest()
+                .unwrap_or_else(|| self.timezone.from_utc_datetime(&local))
+                .with_timezone(&Utc)
+        };
+
+        let reset = reset_on(today);
+        if reset <= now {
+            reset
+        } else {
+            reset_on(today.pred_opt().unwrap())
+        }
+    }
+}
+
+/// Starts a thread which resets the sequence numbers of every session and archives the
+/// message journal whenever the daily reset time passes.
+pub fn start_daily_reset(daily_reset: DailyReset, seq_stores: Vec<Arc<SequenceStores>>) {
+    thread::spawn(move || {
+        let mut last_reset = daily_reset.last_reset_at(Utc::now());
+        loop {
+            sleep(Duration::from_secs(1));
+            let reset = daily_reset.last_reset_at(Utc::now());
+            if reset == last_reset {
+                continue;
+            }
+            last_reset = reset;
+
+            info!("Daily reset time reached, resetting sequence numbers to 1");
+            for stores in &seq_stores {
+                stores.reset_all();
+            }
+
+            // The archived journals are named after the trading date that just ended
+            let trading_date = reset.with_timezone(&daily_reset.timezone).date_naive();
+            let suffix = trading_date
+                .pred_opt()
+                .unwrap()
+                .format("%Y%m%d")
+                .to_string();
+            for journal in MESSAGE_JOURNALS.all() {
+                match write_gap_report(&journal, &suffix) {
+                    Ok(report_path) => info!("Wrote gap report to {}", report_path),
+                    Err(e) => error!("Failed to write gap report: {}", e),
+                }
+                match journal.archive(&suffix) {
+                    Ok(archive_path) => info!("Archived message journal to {}", archive_path),
+                    Err(e) => error!("Failed to archive message journal: {}", e),
+                }
+            }
+        }
+    });
+}
+
+fn day_seconds(time: NaiveTime) -> i64 {
+    time.num_seconds_from_midnight() as i64
+}
+
+fn week_seconds(day: Weekday, time: NaiveTime) -> i64 {
+    day.num_days_from_monday() as i64 * SECONDS_PER_DAY + day_seconds(time)
+}
+
+/// Blocks until the configured session is open. Returns true if the engine had to wait,
+/// i.e. a new session period is starting; always false when no schedule is configured.
+pub fn wait_for_session_open() -> bool {
+    let mut waited = false;
+    loop {
+        let in_session = match SESSION_SCHEDULE.read().unwrap().as_ref() {
+            Some(schedule) => schedule.is_in_session(Utc::now()),
+            None => true,
+        };
+        if in_session {
+            return waited;
+        }
+        if !waited {
+            info!("Outside of session hours, waiting for the session to open");
+            waited = true;
+        }
+        sleep(Duration::from_secs(1));
+    }
+}
+
+/// Returns true if a schedule is configured and the session is currently closed.
+pub fn is_session_closed() -> bool {
+    SESSION_SCHEDULE
+        .read()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|schedule| !schedule.is_in_session(Utc::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, "%H:%M:%S").unwrap()
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, min, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_daily_session() {
+        let schedule = SessionSchedule {
+            start_time: time("12:30:00"),
+            end_time: time("21:30:00"),
+            start_day: None,
+            end_day: None,
+            timezone: Tz::UTC,
+        };
+        assert!(!schedule.is_in_session(utc(2024, 5, 6, 12, 29)));
+        assert!(schedule.is_in_session(utc(2024, 5, 6, 12, 30)));
+        assert!(!schedule.is_in_session(utc(2024, 5, 6, 21, 30)));
+    }
+
+    #[test]
+    fn test_overnight_session_in_timezone() {
+        // 18:00-06:00 New York time is 22:00-10:00 UTC during daylight saving
+        let schedule = SessionSchedule {
+            start_time: time("18:00:00"),
+            end_time: time("06:00:00"),
+            start_day: None,
+            end_day: None,
+            timezone: "America/New_York".parse().unwrap(),
+        };
+        assert!(schedule.is_in_session(utc(2024, 5, 6, 23, 0)));
+        assert!(schedule.is_in_session(utc(2024, 5, 7, 9, 59)));
+        assert!(!schedule.is_in_session(utc(2024, 5, 7, 10, 0)));
+    }
+
+    #[test]
+    fn test_weekly_session() {
+        // Sunday 17:00 to Friday 17:00, 2024-05-05 is a Sunday
+        let schedule = SessionSchedule {
+            start_time: time("17:00:00"),
+            end_time: time("17:00:00"),
+            start_day: Some(Weekday::Sun),
+            end_day: Some(Weekday::Fri),
+            timezone: Tz::UTC,
+        };
+        assert!(!schedule.is_in_session(utc(2024, 5, 5, 16, 59)));
+        assert!(schedule.is_in_session(utc(2024, 5, 5, 17, 0)));
+        assert!(schedule.is_in_session(utc(2024, 5, 8, 3, 0)));
+        assert!(!schedule.is_in_session(utc(2024, 5, 10, 17, 0)));
+        assert!(!schedule.is_in_session(utc(2024, 5, 11, 12, 0)));
+    }
+
+    #[test]
+    fn test_last_reset_at() {
+        let daily_reset = DailyReset {
+            reset_time: time("00:00:00"),
+            timezone: Tz::UTC,
+        };
+        assert_eq!(
+            daily_reset.last_reset_at(utc(2024, 5, 6, 15, 0)),
+            utc(2024, 5, 6, 0, 0)
+        );
+
+        // 17:00 New York is 21:00 UTC during daylight saving
+        let daily_reset = DailyReset {
+            reset_time: time("17:00:00"),
+            timezone: "America/New_York".parse().unwrap(),
+        };
+        assert_eq!(
+            daily_reset.last_reset_at(utc(2024, 5, 6, 20, 59)),
+            utc(2024, 5, 5, 21, 0)
+        );
+        assert_eq!(
+            daily_reset.last_reset_at(utc(2024, 5, 6, 21, 0)),
+            utc(2024, 5, 6, 21, 0)
+        );
+    }
+}
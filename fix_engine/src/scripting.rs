@@ -0,0 +1,230 @@
+use std::cell::Cell;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use log::error;
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+
+use crate::interceptors::{InterceptedMessage, Interceptor, Verdict};
+
+/// Operations a script may run on one message, loops included.
+const MAX_OPERATIONS: u64 = 100_000;
+/// Nested function calls a script may make.
+const MAX_CALL_LEVELS: usize = 32;
+/// How long a script may run on one message, the session waiting for it.
+const MAX_RUN_TIME: Duration = Duration::from_millis(100);
+
+thread_local! {
+    /// When the script running on this thread started, for `on_progress` to stop it in time.
+    static RUN_STARTED: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// What becomes of a message its script fails on, e.g. by exceeding its limits.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ScriptFailure {
+    /// The message passes as it was.
+    #[default]
+    Pass,
+    /// The message is dropped.
+    Drop,
+}
+
+impl ScriptFailure {
+    /// `fail-open` or `fail-closed`, as given after the file of a `script` rule.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "fail-open" => Ok(ScriptFailure::Pass),
+            "fail-closed" => Ok(ScriptFailure::Drop),
+            _ => Err(format!("Unknown script option {}", value)),
+        }
+    }
+}
+
+/// The tag given to `get`, `set` or `remove`, an error for a negative or too large number.
+fn tag_number(tag: i64) -> Result<u32, Box<EvalAltResult>> {
+    u32::try_from(tag).map_err(|_| format!("Invalid tag {}", tag).into())
+}
+
+/// An interceptor running a Rhai script on each message, e.g. to map the internal account
+/// codes to those of the venue. The script sees the message as `message`, with
+/// `message.get(tag)` (`()` when absent), `message.set(tag, value)` and `message.remove(tag)`,
+/// and drops it by evaluating to `false`. A script failing, or running longer than its
+/// limits allow, leaves the message as it was or drops it as `on_failure` says.
+pub struct ScriptInterceptor {
+    path: String,
+    engine: Engine,
+    ast: AST,
+    on_failure: ScriptFailure,
+}
+
+impl ScriptInterceptor {
+    pub fn load(path: &str, on_failure: ScriptFailure) -> Result<Self, String> {
+        let script = fs::read_to_string(path).map_err(|e| format!("Script {}: {}", path, e))?;
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .on_progress(|_| {
+                let started = RUN_STARTED.with(Cell::get)?;
+                (started.elapsed() > MAX_RUN_TIME).then(|| "Script timed out".into())
+            });
+        engine
+            .register_type_with_name::<InterceptedMessage>("Message")
+            .register_fn(
+                "get",
+                |message: &mut InterceptedMessage,
+                 tag: i64|
+                 -> Result<Dynamic, Box<EvalAltResult>> {
+                    Ok(message
+                        .get(tag_number(tag)?)
+                        .map_or(Dynamic::UNIT, |value| value.into()))
+                },
+            )
+            .register_fn(
+                "set",
+                |message: &mut InterceptedMessage,
+                 tag: i64,
+                 value: &str|
+                 -> Result<(), Box<EvalAltResult>> {
+                    message.set(tag_number(tag)?, value);
+                    Ok(())
+                },
+            )
+            .register_fn(
+                "remove",
+                |message: &mut InterceptedMessage, tag: i64| -> Result<(), Box<EvalAltResult>> {
+                    message.remove(tag_number(tag)?);
+                    Ok(())
+                },
+            );
+        let ast = engine
+            .compile(&script)
+            .map_err(|e| format!("Script {}: {}", path, e))?;
+        Ok(Self {
+            path: path.to_string(),
+            engine,
+            ast,
+            on_failure,
+        })
+    }
+}
+
+impl Interceptor for ScriptInterceptor {
+    fn intercept(&self, message: &mut InterceptedMessage) -> Verdict {
+        let mut scope = Scope::new();
+        scope.push("message", message.clone());
+        RUN_STARTED.with(|started| started.set(Some(Instant::now())));
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut scope, &self.ast);
+        RUN_STARTED.with(|started| started.set(None));
+        match result {
+            Ok(result) => {
+                if let Some(changed) = scope.get_value::<InterceptedMessage>("message") {
+                    *message = changed;
+                }
+                match result.as_bool() {
+                    Ok(false) => Verdict::Drop,
+                    _ => Verdict::Pass,
+                }
+            }
+            Err(e) => {
+                error!("Script {} failed: {}", self.path, e);
+                match self.on_failure {
+                    ScriptFailure::Pass => Verdict::Pass,
+                    ScriptFailure::Drop => Verdict::Drop,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_script_interceptor() {
+        let mut script = NamedTempFile::new().unwrap();
+        write!(
+            script,
+            r#"
+            let accounts = #{{ "INT1": "VENUE-A1" }};
+            let account = message.get(1);
+            if account in accounts {{
+                message.set(1, accounts[account]);
+            }}
+            message.remove(5001);
+            message.get(38) != ()
+            "#
+        )
+        .unwrap();
+        let interceptor =
+            ScriptInterceptor::load(script.path().to_str().unwrap(), ScriptFailure::Pass).unwrap();
+
+        let mut order =
+            InterceptedMessage::parse(b"8=FIX.4.2\x0135=D\x011=INT1\x0138=100\x015001=X\x01")
+                .unwrap();
+        assert_eq!(interceptor.intercept(&mut order), Verdict::Pass);
+        assert_eq!(
+            order,
+            InterceptedMessage::parse(b"8=FIX.4.2\x0135=D\x011=VENUE-A1\x0138=100\x01").unwrap()
+        );
+
+        // Validation fails without an OrderQty
+        let mut order = InterceptedMessage::parse(b"8=FIX.4.2\x0135=D\x011=INT2\x01").unwrap();
+        assert_eq!(interceptor.intercept(&mut order), Verdict::Drop);
+        assert_eq!(order.get(1), Some("INT2"));
+
+        let accounts =
+            ScriptInterceptor::load("config/accounts.rhai", ScriptFailure::Pass).unwrap();
+        let mut order =
+            InterceptedMessage::parse(b"8=FIX.4.2\x0135=D\x011=INT2\x0138=100\x01").unwrap();
+        assert_eq!(accounts.intercept(&mut order), Verdict::Pass);
+        assert_eq!(order.get(1), Some("VENUE-A2"));
+        let mut heartbeat = InterceptedMessage::parse(b"8=FIX.4.2\x0135=0\x01").unwrap();
+        assert_eq!(accounts.intercept(&mut heartbeat), Verdict::Pass);
+        assert!(ScriptInterceptor::load("config/missing.rhai", ScriptFailure::Pass).is_err());
+    }
+
+    fn script(source: &str, on_failure: ScriptFailure) -> ScriptInterceptor {
+        let mut script = NamedTempFile::new().unwrap();
+        write!(script, "{}", source).unwrap();
+        ScriptInterceptor::load(script.path().to_str().unwrap(), on_failure).unwrap()
+    }
+
+    #[test]
+    fn test_script_limits_and_failure_policy() {
+        let order = || InterceptedMessage::parse(b"8=FIX.4.2\x0135=D\x011=INT1\x01").unwrap();
+
+        // A negative tag is refused rather than wrapped around to another tag
+        let negative_tag = r#"message.set(-4294967295, "X"); true"#;
+        let mut message = order();
+        assert_eq!(
+            script(negative_tag, ScriptFailure::Pass).intercept(&mut message),
+            Verdict::Pass
+        );
+        assert_eq!(message, order());
+        assert_eq!(
+            script(negative_tag, ScriptFailure::Drop).intercept(&mut order()),
+            Verdict::Drop
+        );
+
+        // A script which never ends is stopped
+        let endless = "loop { message.get(1); }";
+        assert_eq!(
+            script(endless, ScriptFailure::Drop).intercept(&mut order()),
+            Verdict::Drop
+        );
+        let recursive = "fn deeper(n) { deeper(n + 1) } deeper(0)";
+        assert_eq!(
+            script(recursive, ScriptFailure::Pass).intercept(&mut order()),
+            Verdict::Pass
+        );
+
+        assert_eq!(ScriptFailure::parse("fail-closed"), Ok(ScriptFailure::Drop));
+        assert!(ScriptFailure::parse("closed").is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+
+use crate::symbol_master::{SymbolInfo, SymbolMaster, TradingStatus};
+
+/// SecurityResponseType(323) of a SecurityDefinition.
+pub const SECURITY_RESPONSE_TYPE_ACCEPT_AS_IS: &str = "1";
+pub const SECURITY_RESPONSE_TYPE_LIST_OF_SECURITIES: &str = "4";
+pub const SECURITY_RESPONSE_TYPE_REJECT: &str = "5";
+pub const SECURITY_RESPONSE_TYPE_CANNOT_MATCH: &str = "6";
+
+/// SecurityRequestResult(560) of a SecurityList.
+pub const SECURITY_REQUEST_RESULT_VALID: &str = "0";
+pub const SECURITY_REQUEST_RESULT_INVALID: &str = "1";
+pub const SECURITY_REQUEST_RESULT_NO_INSTRUMENTS: &str = "2";
+
+/// The symbols a SecurityDefinitionRequest (35=c) or SecurityListRequest (35=x) asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecurityRequest {
+    /// The definition of one symbol.
+    Symbol(String),
+    /// The list of the symbols, or only the one given.
+    List(Option<String>),
+}
+
+impl SecurityRequest {
+    /// From a SecurityDefinitionRequest: the identity and specifications of its Symbol, or
+    /// the list of securities. Lists of security types are not supported.
+    pub fn from_definition_request(msg_map: &IndexMap<String, String>) -> Result<Self, String> {
+        let symbol = msg_map.get("Symbol").filter(|symbol| !symbol.is_empty());
+        let request_type = msg_map
+            .get("SecurityRequestType")
+            .map_or("", String::as_str);
+        match request_type {
+            "0"
+            | "1"
+            | "REQUEST_SECURITY_IDENTITY_AND_SPECIFICATIONS"
+            | "REQUEST_SECURITY_IDENTITY_FOR_SPECIFICATIONS" => symbol
+                .map(|symbol| SecurityRequest::Symbol(symbol.clone()))
+                .ok_or_else(|| "Missing Symbol".to_string()),
+            "3" | "REQUEST_LIST_SECURITIES" => Ok(SecurityRequest::List(symbol.cloned())),
+            other => Err(format!("Unsupported SecurityRequestType {}", other)),
+        }
+    }
+
+    /// From a SecurityListRequest: the securities of its Symbol or all of them.
+    pub fn from_list_request(msg_map: &IndexMap<String, String>) -> Result<Self, String> {
+        let symbol = msg_map.get("Symbol").filter(|symbol| !symbol.is_empty());
+        let request_type = msg_map
+            .get("SecurityListRequestType")
+            .map_or("", String::as_str);
+        match request_type {
+            "0" | "SYMBOL" => symbol
+                .map(|symbol| SecurityRequest::List(Some(symbol.clone())))
+                .ok_or_else(|| "Missing Symbol".to_string()),
+            "4" | "ALL_SECURITIES" => Ok(SecurityRequest::List(None)),
+            other => Err(format!("Unsupported SecurityListRequestType {}", other)),
+        }
+    }
+
+    /// The symbols of the master answering the request, by symbol.
+    pub fn select<'a>(&self, master: &'a SymbolMaster) -> Vec<&'a SymbolInfo> {
+        match self {
+            SecurityRequest::Symbol(symbol) | SecurityRequest::List(Some(symbol)) => {
+                master.get(symbol).into_iter().collect()
+            }
+            SecurityRequest::List(None) => master.symbols(),
+        }
+    }
+}
+
+/// The symbols in pages of at most `page_size`, one empty page when there are none.
+pub fn pages<'a>(symbols: &'a [&'a SymbolInfo], page_size: usize) -> Vec<&'a [&'a SymbolInfo]> {
+    if symbols.is_empty() {
+        return vec![&[]];
+    }
+    symbols.chunks(page_size.max(1)).collect()
+}
+
+/// Override fields of the SecurityDefinition template defining a symbol, or telling that
+/// none matches when `info` is None. Fields the dictionary lacks, TotalNumSecurities in
+/// FIX 4.4 or RoundLot in FIX 4.2, are left out when it is sent.
+pub fn security_definition_fields(
+    security_req_id: &str,
+    security_response_id: &str,
+    symbol: &str,
+    info: Option<&SymbolInfo>,
+) -> HashMap<String, String> {
+    let mut fields = HashMap::from([
+        ("SecurityReqID".to_string(), security_req_id.to_string()),
+        (
+            "SecurityResponseID".to_string(),
+            security_response_id.to_string(),
+        ),
+        ("Symbol".to_string(), symbol.to_string()),
+        (
+            "TotalNumSecurities".to_string(),
+            info.iter().count().to_string(),
+        ),
+    ]);
+    let Some(info) = info else {
+        fields.insert(
+            "SecurityResponseType".to_string(),
+            SECURITY_RESPONSE_TYPE_CANNOT_MATCH.to_string(),
+        );
+        fields.insert("Text".to_string(), format!("Unknown symbol {}", symbol));
+        return fields;
+    };
+    fields.insert(
+        "SecurityResponseType".to_string(),
+        SECURITY_RESPONSE_TYPE_ACCEPT_AS_IS.to_string(),
+    );
+    fields.insert("RoundLot".to_string(), info.lot_size.to_string());
+    if info.status == TradingStatus::Halted {
+        fields.insert("Text".to_string(), "Halted".to_string());
+    }
+    fields
+}
+
+/// Override fields of the SecurityDefinition or SecurityList refusing a request.
+pub fn security_request_rejected_fields(
+    security_req_id: &str,
+    security_response_id: &str,
+    security_list: bool,
+    text: &str,
+) -> HashMap<String, String> {
+    let mut fields = HashMap::from([
+        ("SecurityReqID".to_string(), security_req_id.to_string()),
+        (
+            "SecurityResponseID".to_string(),
+            security_response_id.to_string(),
+        ),
+        ("Text".to_string(), text.to_string()),
+    ]);
+    if security_list {
+        fields.insert(
+            "SecurityRequestResult".to_string(),
+            SECURITY_REQUEST_RESULT_INVALID.to_string(),
+        );
+    } else {
+        fields.insert(
+            "SecurityResponseType".to_string(),
+            SECURITY_RESPONSE_TYPE_REJECT.to_string(),
+        );
+        fields.insert("TotalNumSecurities".to_string(), "0".to_string());
+    }
+    fields
+}
+
+/// Override fields and NoRelatedSym group of a SecurityDefinition listing one page of the
+/// securities (FIX 4.2), each with its TotalNumSecurities.
+pub fn security_definition_page(
+    security_req_id: &str,
+    security_response_id: &str,
+    total: usize,
+    page: &[&SymbolInfo],
+) -> (HashMap<String, String>, Vec<(u32, String)>) {
+    let response_type = match total {
+        0 => SECURITY_RESPONSE_TYPE_CANNOT_MATCH,
+        _ => SECURITY_RESPONSE_TYPE_LIST_OF_SECURITIES,
+    };
+    let fields = HashMap::from([
+        ("SecurityReqID".to_string(), security_req_id.to_string()),
+        (
+            "SecurityResponseID".to_string(),
+            security_response_id.to_string(),
+        ),
+        (
+            "SecurityResponseType".to_string(),
+            response_type.to_string(),
+        ),
+        ("TotalNumSecurities".to_string(), total.to_string()),
+    ]);
+    let mut group = Vec::new();
+    if !page.is_empty() {
+        group.push((146, page.len().to_string()));
+        group.extend(page.iter().map(|info| (311, info.symbol.clone())));
+    }
+    (fields, group)
+}
+
+/// Override fields and NoRelatedSym group of a SecurityList carrying one page of the
+/// securities (FIX 4.4), LastFragment telling whether more follow.
+pub fn security_list_page(
+    security_req_id: &str,
+    security_response_id: &str,
+    total: usize,
+    page: &[&SymbolInfo],
+    last_fragment: bool,
+) -> (HashMap<String, String>, Vec<(u32, String)>) {
+    let result = match total {
+        0 => SECURITY_REQUEST_RESULT_NO_INSTRUMENTS,
+        _ => SECURITY_REQUEST_RESULT_VALID,
+    };
+    let fields = HashMap::from([
+        ("SecurityReqID".to_string(), security_req_id.to_string()),
+        (
+            "SecurityResponseID".to_string(),
+            security_response_id.to_string(),
+        ),
+        ("SecurityRequestResult".to_string(), result.to_string()),
+        ("TotNoRelatedSym".to_string(), total.to_string()),
+        (
+            "LastFragment".to_string(),
+            if last_fragment { "Y" } else { "N" }.to_string(),
+        ),
+    ]);
+    let mut group = Vec::new();
+    if !page.is_empty() {
+        group.push((146, page.len().to_string()));
+        for info in page {
+            group.push((55, info.symbol.clone()));
+            group.push((561, info.lot_size.to_string()));
+        }
+    }
+    (fields, group)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::msg_map;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_security_requests_are_answered_in_pages() {
+        let master = SymbolMaster::new(
+            ["MSFT", "AAPL", "IBM"]
+                .iter()
+                .map(|symbol| SymbolInfo {
+                    symbol: symbol.to_string(),
+                    tick_size: Decimal::new(1, 2),
+                    lot_size: Decimal::ONE,
+                    status: TradingStatus::Trading,
+                })
+                .collect(),
+        );
+        let all = SecurityRequest::from_list_request(&msg_map(&[(
+            "SecurityListRequestType",
+            "ALL_SECURITIES",
+        )]))
+        .unwrap();
+        let symbols = all.select(&master);
+        let paged = pages(&symbols, 2);
+        assert_eq!(paged.len(), 2);
+        assert_eq!(paged[0][0].symbol, "AAPL");
+        let (fields, group) = security_list_page("R1", "S00000001", 3, paged[1], true);
+        assert_eq!(fields["TotNoRelatedSym"], "3");
+        assert_eq!(fields["LastFragment"], "Y");
+        assert_eq!(
+            group,
+            vec![
+                (146, "1".to_string()),
+                (55, "MSFT".to_string()),
+                (561, "1".to_string())
+            ]
+        );
+
+        let unknown = SecurityRequest::from_definition_request(&msg_map(&[
+            ("SecurityRequestType", "REQUEST_LIST_SECURITIES"),
+            ("Symbol", "TSLA"),
+        ]))
+        .unwrap();
+        let symbols = unknown.select(&master);
+        assert_eq!(pages(&symbols, 2), vec![&[] as &[&SymbolInfo]]);
+        let (fields, group) = security_definition_page("R2", "S00000002", 0, &[]);
+        assert_eq!(
+            fields["SecurityResponseType"],
+            SECURITY_RESPONSE_TYPE_CANNOT_MATCH
+        );
+        assert!(group.is_empty());
+        assert!(SecurityRequest::from_definition_request(&msg_map(&[(
+            "SecurityRequestType",
+            "REQUEST_SECURITY_IDENTITY_AND_SPECIFICATIONS"
+        )]))
+        .is_err());
+    }
+}
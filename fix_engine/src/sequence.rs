@@ -1,46 +1,104 @@
 use fs2::FileExt;
+use indexmap::IndexMap;
+use log::{error, info};
 use serde::{Deserialize, Serialize};
-use std::fs::{File, OpenOptions};
-use std::io::Read;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+use crate::tag_value;
 
 #[derive(Serialize, Deserialize, Debug)]
 struct SequenceNumber {
     incoming: u64,
     outgoing: u64,
+    /// OrderIDs, ExecIDs and the other IDs issued by the session so far, never reset.
+    #[serde(default)]
+    order_ids: u64,
+    #[serde(default)]
+    exec_ids: u64,
+    #[serde(default)]
+    quote_ids: u64,
+    #[serde(default)]
+    alloc_report_ids: u64,
+    #[serde(default)]
+    security_response_ids: u64,
+    /// Changes not written to the file yet.
+    #[serde(skip)]
+    pending: u64,
+}
+
+/// When sequence number changes reach the file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlushPolicy {
+    /// Write after this many increments. 1 writes through, 0 leaves it to the interval.
+    pub every_n: u64,
+    /// Write pending increments in the background at this interval. Zero disables it.
+    pub interval: Duration,
+    /// fsync the file before it replaces the previous one.
+    pub fsync: bool,
+}
+
+impl Default for FlushPolicy {
+    fn default() -> Self {
+        FlushPolicy {
+            every_n: 1,
+            interval: Duration::ZERO,
+            fsync: true,
+        }
+    }
 }
 
+/// First token of the header line of a sequence file, followed by the format version
+/// and the checksum of the JSON body.
+const FILE_MAGIC: &str = "FIXSEQ";
+const FILE_VERSION: u32 = 1;
+
 pub struct SequenceNumberStore {
     file_path: String,
     sequence_numbers: Arc<Mutex<SequenceNumber>>,
+    flush_policy: FlushPolicy,
 }
 
 impl SequenceNumberStore {
-    pub fn new(file_path: &str) -> Self {
-        let sequence_numbers = if let Ok(mut file) = File::open(file_path) {
-            let mut content = String::new();
-            if file.read_to_string(&mut content).is_ok() {
-                serde_json::from_str(&content).unwrap_or_else(|_| SequenceNumber {
-                    incoming: 1,
-                    outgoing: 1,
-                })
-            } else {
-                SequenceNumber {
-                    incoming: 1,
-                    outgoing: 1,
-                }
-            }
-        } else {
-            SequenceNumber {
-                incoming: 1,
-                outgoing: 1,
-            }
+    /// Loads the sequence numbers from the file, starting at 1/1 when it does not exist yet.
+    /// A file which fails its checksum or cannot be parsed is an error rather than a reset.
+    pub fn new(file_path: &str) -> io::Result<Self> {
+        let sequence_numbers = match fs::read_to_string(file_path) {
+            Ok(content) => decode(&content).map_err(|e| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Corrupt sequence file {}: {}", file_path, e),
+                )
+            })?,
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
         };
 
-        SequenceNumberStore {
+        Ok(SequenceNumberStore {
             file_path: file_path.to_string(),
-            sequence_numbers: Arc::new(Mutex::new(sequence_numbers)),
-        }
+            sequence_numbers: Arc::new(Mutex::new(sequence_numbers.unwrap_or(SequenceNumber {
+                incoming: 1,
+                outgoing: 1,
+                order_ids: 0,
+                exec_ids: 0,
+                quote_ids: 0,
+                alloc_report_ids: 0,
+                security_response_ids: 0,
+                pending: 0,
+            }))),
+            flush_policy: FlushPolicy::default(),
+        })
+    }
+
+    pub fn with_flush_policy(mut self, flush_policy: FlushPolicy) -> Self {
+        self.flush_policy = flush_policy;
+        self
     }
 
     pub fn get_incoming(&self) -> u64 {
@@ -56,37 +114,422 @@ impl SequenceNumberStore {
     pub fn increment_incoming(&self) {
         let mut seq = self.sequence_numbers.lock().unwrap();
         seq.incoming += 1;
-        self.persist(&seq);
+        self.persist_batched(&mut seq);
     }
 
+    /// Senders take their number with `send_outgoing`, so only tests step it by hand.
+    #[cfg(test)]
     pub fn increment_outgoing(&self) {
         let mut seq = self.sequence_numbers.lock().unwrap();
         seq.outgoing += 1;
-        self.persist(&seq);
+        self.persist_batched(&mut seq);
     }
 
     pub fn set_incoming(&self, new_seq: u64) {
         let mut seq = self.sequence_numbers.lock().unwrap();
         seq.incoming = new_seq;
-        self.persist(&seq);
+        self.persist(&mut seq);
     }
 
     pub fn set_outgoing(&self, new_seq: u64) {
         let mut seq = self.sequence_numbers.lock().unwrap();
         seq.outgoing = new_seq;
-        self.persist(&seq);
+        self.persist(&mut seq);
+    }
+
+    /// Resets both sequence numbers to 1, e.g. for a Logon carrying ResetSeqNumFlag=Y.
+    pub fn reset(&self) {
+        let mut seq = self.sequence_numbers.lock().unwrap();
+        seq.incoming = 1;
+        seq.outgoing = 1;
+        self.persist(&mut seq);
+    }
+
+    /// Sends `count` messages numbered from the next outgoing sequence number, which stays
+    /// taken while `send` writes or enqueues them: a timer, the simulator or the console
+    /// sending meanwhile waits and gets the numbers after them. The numbers are consumed
+    /// once `send` succeeds; a message which never went out leaves its number to the next.
+    pub fn send_outgoing<T>(
+        &self,
+        count: u64,
+        send: impl FnOnce(u64) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mut seq = self.sequence_numbers.lock().unwrap();
+        let sent = send(seq.outgoing)?;
+        seq.outgoing += count;
+        self.persist_batched(&mut seq);
+        Ok(sent)
+    }
+
+    /// Assigns the next exchange OrderID of the session. The counter is written through and
+    /// survives sequence resets, so OrderIDs stay unique across restarts.
+    pub fn next_order_id(&self) -> String {
+        let mut seq = self.sequence_numbers.lock().unwrap();
+        seq.order_ids += 1;
+        let order_id = format!("O{:08}", seq.order_ids);
+        self.persist(&mut seq);
+        order_id
+    }
+
+    /// Assigns the next ExecID of the session, written through like the OrderIDs.
+    pub fn next_exec_id(&self) -> String {
+        let mut seq = self.sequence_numbers.lock().unwrap();
+        seq.exec_ids += 1;
+        let exec_id = format!("E{:08}", seq.exec_ids);
+        self.persist(&mut seq);
+        exec_id
+    }
+
+    /// Assigns the next QuoteID of the session, written through like the OrderIDs.
+    pub fn next_quote_id(&self) -> String {
+        let mut seq = self.sequence_numbers.lock().unwrap();
+        seq.quote_ids += 1;
+        let quote_id = format!("Q{:08}", seq.quote_ids);
+        self.persist(&mut seq);
+        quote_id
     }
 
-    fn persist(&self, seq: &SequenceNumber) {
-        let file = OpenOptions::new()
+    /// Assigns the next AllocReportID of the session, written through like the OrderIDs.
+    pub fn next_alloc_report_id(&self) -> String {
+        let mut seq = self.sequence_numbers.lock().unwrap();
+        seq.alloc_report_ids += 1;
+        let alloc_report_id = format!("A{:08}", seq.alloc_report_ids);
+        self.persist(&mut seq);
+        alloc_report_id
+    }
+
+    /// Assigns the next SecurityResponseID of the session, written through like the OrderIDs.
+    pub fn next_security_response_id(&self) -> String {
+        let mut seq = self.sequence_numbers.lock().unwrap();
+        seq.security_response_ids += 1;
+        let security_response_id = format!("S{:08}", seq.security_response_ids);
+        self.persist(&mut seq);
+        security_response_id
+    }
+
+    /// Writes pending increments to the file, e.g. before shutting down.
+    pub fn flush(&self) {
+        let mut seq = self.sequence_numbers.lock().unwrap();
+        if seq.pending > 0 {
+            self.persist(&mut seq);
+        }
+    }
+
+    /// Increments are written once the flush policy's count is reached; the rest are left
+    /// to the background flusher or an explicit flush.
+    fn persist_batched(&self, seq: &mut SequenceNumber) {
+        seq.pending += 1;
+        if self.flush_policy.every_n > 0 && seq.pending >= self.flush_policy.every_n {
+            self.persist(seq);
+        }
+    }
+
+    /// Writes the numbers to a temp file and renames it over the sequence file, so a crash
+    /// leaves either the previous or the new numbers on disk.
+    fn persist(&self, seq: &mut SequenceNumber) {
+        self.write_atomically(seq)
+            .unwrap_or_else(|e| panic!("Failed to persist {}: {}", self.file_path, e));
+        seq.pending = 0;
+    }
+
+    fn write_atomically(&self, seq: &SequenceNumber) -> io::Result<()> {
+        // The sequence file itself is replaced on every write, so processes lock a side file
+        let lock_file = OpenOptions::new()
             .write(true)
             .create(true)
-            .open(&self.file_path)
-            .unwrap();
-        file.lock_exclusive().unwrap();
-        let content = serde_json::to_string(seq).unwrap();
-        std::fs::write(&self.file_path, content).unwrap();
-        file.unlock().unwrap();
+            .open(format!("{}.lock", self.file_path))?;
+        lock_file.lock_exclusive()?;
+
+        let temp_path = format!("{}.tmp", self.file_path);
+        let mut temp_file = File::create(&temp_path)?;
+        temp_file.write_all(encode(seq).as_bytes())?;
+        if self.flush_policy.fsync {
+            temp_file.sync_all()?;
+        }
+        fs::rename(&temp_path, &self.file_path)?;
+
+        lock_file.unlock()
+    }
+}
+
+/// Encodes the numbers as a header line (magic, version, checksum) followed by the JSON body.
+fn encode(seq: &SequenceNumber) -> String {
+    let body = serde_json::to_string(seq).unwrap();
+    format!(
+        "{} {} {:016x}\n{}",
+        FILE_MAGIC,
+        FILE_VERSION,
+        checksum(&body),
+        body
+    )
+}
+
+/// Decodes a sequence file. Files written before the header was introduced hold the bare
+/// JSON body and are still accepted; an empty file holds no numbers yet.
+fn decode(content: &str) -> Result<Option<SequenceNumber>, String> {
+    if content.trim().is_empty() {
+        return Ok(None);
+    }
+
+    let body = match content.strip_prefix(FILE_MAGIC) {
+        Some(rest) => {
+            let (header, body) = rest.split_once('\n').ok_or("missing body")?;
+            let mut fields = header.split_whitespace();
+            let version = fields.next().ok_or("missing version")?;
+            if version != FILE_VERSION.to_string() {
+                return Err(format!("unsupported format version {}", version));
+            }
+            let expected = fields.next().ok_or("missing checksum")?;
+            if expected != format!("{:016x}", checksum(body)) {
+                return Err("checksum mismatch".to_string());
+            }
+            body
+        }
+        None => content,
+    };
+
+    serde_json::from_str(body)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+/// FNV-1a hash of the body, enough to detect torn or hand-edited files.
+fn checksum(body: &str) -> u64 {
+    body.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Identifies a FIX session by BeginString, our SenderCompID and the counterparty's CompID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SessionId {
+    pub begin_string: String,
+    pub sender_comp_id: String,
+    pub target_comp_id: String,
+}
+
+impl SessionId {
+    /// Builds the session id from the header template. The counterparty's CompID, when known
+    /// from its Logon, takes precedence over the template TargetCompID.
+    pub fn from_header(header: &IndexMap<String, String>, target_comp_id: Option<&str>) -> Self {
+        let get = |key: &str| header.get(key).cloned().unwrap_or_default();
+        SessionId {
+            begin_string: get("BeginString"),
+            sender_comp_id: get("SenderCompID"),
+            target_comp_id: target_comp_id
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| get("TargetCompID")),
+        }
+    }
+
+    /// The session a message of the counterparty arrived on, its CompIDs swapped.
+    pub fn from_received(msg_map: &IndexMap<String, String>) -> Self {
+        let get = |key: &str| msg_map.get(key).cloned().unwrap_or_default();
+        SessionId {
+            begin_string: get("BeginString"),
+            sender_comp_id: get("TargetCompID"),
+            target_comp_id: get("SenderCompID"),
+        }
+    }
+
+    /// The session of a raw message, sent by us when `sent` or else received. None unless it
+    /// has a BeginString, SenderCompID and TargetCompID.
+    pub fn of_message(message: &str, sent: bool) -> Option<Self> {
+        let (mut begin_string, mut sender_comp_id, mut target_comp_id) = (None, None, None);
+        for (tag, value) in tag_value::fields(message.as_bytes()).map_while(Result::ok) {
+            let value = String::from_utf8_lossy(value).into_owned();
+            match tag {
+                8 => begin_string = Some(value),
+                49 => sender_comp_id = Some(value),
+                56 => target_comp_id = Some(value),
+                _ => {}
+            }
+            if begin_string.is_some() && sender_comp_id.is_some() && target_comp_id.is_some() {
+                break;
+            }
+        }
+        let (sender_comp_id, target_comp_id) = if sent {
+            (sender_comp_id?, target_comp_id?)
+        } else {
+            (target_comp_id?, sender_comp_id?)
+        };
+        Some(SessionId {
+            begin_string: begin_string?,
+            sender_comp_id,
+            target_comp_id,
+        })
+    }
+
+    /// The file of the session named after `base_path`, e.g. `data/sequence_FIX.4.2_A_B.json`
+    /// for `data/sequence.json`.
+    pub fn file_path(&self, base_path: &str) -> String {
+        let suffix = self.file_stem();
+
+        let path = Path::new(base_path);
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let file_name = match path.extension() {
+            Some(extension) => format!("{}_{}.{}", stem, suffix, extension.to_string_lossy()),
+            None => format!("{}_{}", stem, suffix),
+        };
+        path.with_file_name(file_name).to_string_lossy().to_string()
+    }
+
+    /// `<BeginString>_<SenderCompID>_<TargetCompID>` with characters unsafe in file names
+    /// replaced, to name the files kept per session.
+    pub fn file_stem(&self) -> String {
+        let sanitize = |value: &str| -> String {
+            value
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect()
+        };
+        format!(
+            "{}_{}_{}",
+            sanitize(&self.begin_string),
+            sanitize(&self.sender_comp_id),
+            sanitize(&self.target_comp_id)
+        )
+    }
+}
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}->{}",
+            self.begin_string, self.sender_comp_id, self.target_comp_id
+        )
+    }
+}
+
+/// Sequence number stores of every session, each persisted to its own file named after the
+/// configured `sequence_store` path and the session id (e.g. `data/sequence_FIX.4.2_A_B.json`).
+pub struct SequenceStores {
+    base_path: String,
+    stores: Mutex<HashMap<SessionId, Arc<SequenceNumberStore>>>,
+    flush_policy: Mutex<FlushPolicy>,
+}
+
+impl SequenceStores {
+    pub fn new(base_path: &str) -> Self {
+        SequenceStores {
+            base_path: base_path.to_string(),
+            stores: Mutex::new(HashMap::new()),
+            flush_policy: Mutex::new(FlushPolicy::default()),
+        }
+    }
+
+    /// Sets the flush policy of the stores opened from now on and, when it has an interval,
+    /// starts a thread writing their pending increments in the background.
+    pub fn apply_flush_policy(self: &Arc<Self>, flush_policy: FlushPolicy) {
+        *self.flush_policy.lock().unwrap() = flush_policy;
+        if flush_policy.interval.is_zero() {
+            return;
+        }
+
+        let stores = Arc::clone(self);
+        thread::spawn(move || loop {
+            sleep(flush_policy.interval);
+            stores.flush_all();
+        });
+    }
+
+    /// Writes the pending increments of every session.
+    pub fn flush_all(&self) {
+        for store in self.all() {
+            store.flush();
+        }
+    }
+
+    /// Returns the store of the session, loading it from its file on first use.
+    pub fn for_session(&self, session_id: &SessionId) -> io::Result<Arc<SequenceNumberStore>> {
+        let mut stores = self.stores.lock().unwrap();
+        if let Some(store) = stores.get(session_id) {
+            return Ok(Arc::clone(store));
+        }
+
+        let file_path = session_id.file_path(&self.base_path);
+        // Numbers persisted by earlier versions in the shared file move to the first session
+        if !Path::new(&file_path).exists() && Path::new(&self.base_path).exists() {
+            match fs::rename(&self.base_path, &file_path) {
+                Ok(_) => info!("Moved {} to {}", self.base_path, file_path),
+                Err(e) => info!("Could not move {} to {}: {}", self.base_path, file_path, e),
+            }
+        }
+
+        info!("Sequence numbers of {} are stored in {}", session_id, file_path);
+        let flush_policy = *self.flush_policy.lock().unwrap();
+        let store =
+            Arc::new(SequenceNumberStore::new(&file_path)?.with_flush_policy(flush_policy));
+        stores.insert(session_id.clone(), Arc::clone(&store));
+        Ok(store)
+    }
+
+    /// Returns the stores of every session seen so far.
+    pub fn all(&self) -> Vec<Arc<SequenceNumberStore>> {
+        self.stores.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Resets the sequence numbers of every session to 1: those loaded, and those only
+    /// stored on disk so a session logging on after the reset starts from 1 too.
+    pub fn reset_all(&self) {
+        // Held so no session loads its numbers halfway through the reset
+        let stores = self.stores.lock().unwrap();
+        for store in stores.values() {
+            store.reset();
+        }
+        for file_path in self.stored_files() {
+            let file_path = file_path.to_string_lossy().to_string();
+            if stores.values().any(|store| store.file_path == file_path) {
+                continue;
+            }
+            match SequenceNumberStore::new(&file_path) {
+                Ok(store) => store.reset(),
+                Err(e) => error!("Failed to reset {}: {}", file_path, e),
+            }
+        }
+    }
+
+    /// The files of the sessions named after `base_path`, and the shared file of earlier
+    /// versions if still there.
+    fn stored_files(&self) -> Vec<PathBuf> {
+        let base_path = Path::new(&self.base_path);
+        let stem = base_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let prefix = format!("{}_", stem);
+        let extension = base_path.extension();
+        let directory = match base_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut files: Vec<PathBuf> = fs::read_dir(directory)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_file()
+                    && path.extension() == extension
+                    && path
+                        .file_name()
+                        .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+            })
+            .collect();
+        if base_path.is_file() {
+            files.push(base_path.to_path_buf());
+        }
+        files
     }
 }
 
@@ -98,7 +541,7 @@ mod tests {
     #[test]
     fn test_new_creates_default_sequence_numbers() {
         let temp_file = NamedTempFile::new().unwrap();
-        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap());
+        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
 
         assert_eq!(store.get_incoming(), 1);
         assert_eq!(store.get_outgoing(), 1);
@@ -110,7 +553,7 @@ mod tests {
         let existing_data = r#"{"incoming": 42, "outgoing": 100}"#;
         std::fs::write(temp_file.path(), existing_data).unwrap();
 
-        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap());
+        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
 
         assert_eq!(store.get_incoming(), 42);
         assert_eq!(store.get_outgoing(), 100);
@@ -119,7 +562,7 @@ mod tests {
     #[test]
     fn test_increment_incoming() {
         let temp_file = NamedTempFile::new().unwrap();
-        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap());
+        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
 
         store.increment_incoming();
         assert_eq!(store.get_incoming(), 2);
@@ -128,7 +571,7 @@ mod tests {
     #[test]
     fn test_increment_outgoing() {
         let temp_file = NamedTempFile::new().unwrap();
-        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap());
+        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
 
         store.increment_outgoing();
         assert_eq!(store.get_outgoing(), 2);
@@ -137,7 +580,7 @@ mod tests {
     #[test]
     fn test_set_incoming() {
         let temp_file = NamedTempFile::new().unwrap();
-        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap());
+        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
 
         store.set_incoming(10);
         assert_eq!(store.get_incoming(), 10);
@@ -146,22 +589,93 @@ mod tests {
     #[test]
     fn test_set_outgoing() {
         let temp_file = NamedTempFile::new().unwrap();
-        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap());
+        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
 
         store.set_outgoing(20);
         assert_eq!(store.get_outgoing(), 20);
     }
 
+    #[test]
+    fn test_reset() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
+
+        store.set_incoming(42);
+        store.set_outgoing(100);
+        store.reset();
+        assert_eq!(store.get_incoming(), 1);
+        assert_eq!(store.get_outgoing(), 1);
+
+        let reloaded_store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
+        assert_eq!(reloaded_store.get_incoming(), 1);
+        assert_eq!(reloaded_store.get_outgoing(), 1);
+    }
+
+    #[test]
+    fn test_send_outgoing_from_several_threads() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = Arc::new(
+            SequenceNumberStore::new(temp_file.path().to_str().unwrap())
+                .unwrap()
+                .with_flush_policy(FlushPolicy {
+                    every_n: 0,
+                    ..FlushPolicy::default()
+                }),
+        );
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let senders: Vec<_> = (0..4)
+            .map(|_| {
+                let (store, sent) = (Arc::clone(&store), Arc::clone(&sent));
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        store
+                            .send_outgoing(1, |msg_seq_num| {
+                                sent.lock().unwrap().push(msg_seq_num);
+                                Ok(())
+                            })
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        senders.into_iter().for_each(|sender| sender.join().unwrap());
+
+        // Each number went out once, in the order it was taken
+        assert_eq!(*sent.lock().unwrap(), (1..=200).collect::<Vec<u64>>());
+        assert_eq!(store.get_outgoing(), 201);
+
+        // A message which never went out leaves its number to the next
+        let failed = store.send_outgoing(1, |_| Err::<(), _>(Error::other("closed")));
+        assert!(failed.is_err());
+        assert_eq!(store.send_outgoing(3, Ok).unwrap(), 201);
+        assert_eq!(store.get_outgoing(), 204);
+    }
+
+    #[test]
+    fn test_ids_are_unique_across_restarts() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
+
+        assert_eq!(store.next_order_id(), "O00000001");
+        assert_eq!(store.next_exec_id(), "E00000001");
+        assert_eq!(store.next_exec_id(), "E00000002");
+        store.reset();
+
+        let reloaded_store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
+        assert_eq!(reloaded_store.next_order_id(), "O00000002");
+        assert_eq!(reloaded_store.next_exec_id(), "E00000003");
+    }
+
     #[test]
     fn test_persist_data() {
         let temp_file = NamedTempFile::new().unwrap();
-        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap());
+        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
 
         store.set_incoming(99);
         store.set_outgoing(88);
 
         // Reload the sequence number store to verify persisted data
-        let reloaded_store = SequenceNumberStore::new(temp_file.path().to_str().unwrap());
+        let reloaded_store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
         assert_eq!(reloaded_store.get_incoming(), 99);
         assert_eq!(reloaded_store.get_outgoing(), 88);
     }
@@ -172,11 +686,20 @@ mod tests {
         // Write invalid JSON to the file
         std::fs::write(temp_file.path(), "invalid_json").unwrap();
 
-        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap());
+        // Should refuse to start from 1/1 instead of silently resetting
+        assert!(SequenceNumberStore::new(temp_file.path().to_str().unwrap()).is_err());
+    }
 
-        // Should fall back to default sequence numbers
-        assert_eq!(store.get_incoming(), 1);
-        assert_eq!(store.get_outgoing(), 1);
+    #[test]
+    fn test_detects_checksum_mismatch() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
+        store.set_outgoing(20);
+
+        let content = std::fs::read_to_string(temp_file.path()).unwrap();
+        assert!(content.starts_with("FIXSEQ 1 "));
+        std::fs::write(temp_file.path(), content.replace("20", "21")).unwrap();
+        assert!(SequenceNumberStore::new(temp_file.path().to_str().unwrap()).is_err());
     }
 
     #[test]
@@ -184,7 +707,7 @@ mod tests {
         use std::thread;
 
         let temp_file = NamedTempFile::new().unwrap();
-        let store = Arc::new(SequenceNumberStore::new(temp_file.path().to_str().unwrap()));
+        let store = Arc::new(SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap());
 
         let store_clone1 = Arc::clone(&store);
         let handle1 = thread::spawn(move || {
@@ -206,4 +729,139 @@ mod tests {
         assert_eq!(store.get_incoming(), 51);
         assert_eq!(store.get_outgoing(), 51);
     }
-}
\ No newline at end of file
+
+    fn session_id(target_comp_id: &str) -> SessionId {
+        SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "FIX_Engine".to_string(),
+            target_comp_id: target_comp_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_sequence_stores_per_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().join("sequence.json");
+        let stores = SequenceStores::new(base_path.to_str().unwrap());
+
+        let xyz = stores.for_session(&session_id("XYZ")).unwrap();
+        let abc = stores.for_session(&session_id("ABC")).unwrap();
+        xyz.increment_outgoing();
+
+        assert_eq!(xyz.get_outgoing(), 2);
+        assert_eq!(abc.get_outgoing(), 1);
+        assert!(Arc::ptr_eq(&xyz, &stores.for_session(&session_id("XYZ")).unwrap()));
+        assert!(dir
+            .path()
+            .join("sequence_FIX.4.2_FIX_Engine_XYZ.json")
+            .exists());
+        assert_eq!(stores.all().len(), 2);
+    }
+
+    #[test]
+    fn test_sequence_stores_migrate_shared_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().join("sequence.json");
+        std::fs::write(&base_path, r#"{"incoming": 42, "outgoing": 100}"#).unwrap();
+        let stores = SequenceStores::new(base_path.to_str().unwrap());
+
+        let store = stores.for_session(&session_id("XYZ")).unwrap();
+        assert_eq!(store.get_incoming(), 42);
+        assert_eq!(store.get_outgoing(), 100);
+        assert!(!base_path.exists());
+    }
+
+    #[test]
+    fn test_session_id_from_header() {
+        let header: IndexMap<String, String> = [
+            ("BeginString", "FIX.4.2"),
+            ("SenderCompID", "FIX_Engine"),
+            ("TargetCompID", "XYZExchange"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        assert_eq!(
+            SessionId::from_header(&header, None).to_string(),
+            "FIX.4.2:FIX_Engine->XYZExchange"
+        );
+        assert_eq!(
+            SessionId::from_header(&header, Some("ABC")).target_comp_id,
+            "ABC"
+        );
+    }
+
+    #[test]
+    fn test_session_id_of_message() {
+        let message = "8=FIX.4.2\x019=40\x0135=D\x0149=FIX_Engine\x0156=XYZExchange\x0110=000\x01";
+        let ours = SessionId::of_message(message, true).unwrap();
+        assert_eq!(ours.to_string(), "FIX.4.2:FIX_Engine->XYZExchange");
+        assert_eq!(
+            SessionId::of_message(message, false).unwrap().to_string(),
+            "FIX.4.2:XYZExchange->FIX_Engine"
+        );
+        assert_eq!(SessionId::of_message("8=FIX.4.2|35=0|49=A|", true), None);
+
+        let received: IndexMap<String, String> = [
+            ("BeginString", "FIX.4.2"),
+            ("SenderCompID", "XYZExchange"),
+            ("TargetCompID", "FIX_Engine"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        assert_eq!(SessionId::from_received(&received), ours);
+    }
+
+    #[test]
+    fn test_batched_writes() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path().to_str().unwrap();
+        let store = SequenceNumberStore::new(path)
+            .unwrap()
+            .with_flush_policy(FlushPolicy {
+                every_n: 3,
+                interval: Duration::ZERO,
+                fsync: false,
+            });
+
+        store.increment_outgoing();
+        store.increment_outgoing();
+        assert_eq!(SequenceNumberStore::new(path).unwrap().get_outgoing(), 1);
+
+        store.increment_outgoing();
+        assert_eq!(SequenceNumberStore::new(path).unwrap().get_outgoing(), 4);
+
+        store.increment_incoming();
+        store.flush();
+        assert_eq!(SequenceNumberStore::new(path).unwrap().get_incoming(), 2);
+    }
+
+    #[test]
+    fn test_reset_all_resets_stores_not_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let base_path = dir.path().join("sequence.json");
+        let session = |target_comp_id: &str| SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "FIX_Engine".to_string(),
+            target_comp_id: target_comp_id.to_string(),
+        };
+        let stores = SequenceStores::new(base_path.to_str().unwrap());
+        let loaded = stores.for_session(&session("XYZ")).unwrap();
+        loaded.set_outgoing(12);
+        let stored_path = session("ABC").file_path(base_path.to_str().unwrap());
+        SequenceNumberStore::new(&stored_path)
+            .unwrap()
+            .set_incoming(34);
+        let unrelated_path = dir.path().join("orders.json");
+        fs::write(&unrelated_path, "{}").unwrap();
+
+        stores.reset_all();
+
+        assert_eq!(loaded.get_outgoing(), 1);
+        let stored = stores.for_session(&session("ABC")).unwrap();
+        assert_eq!((stored.get_incoming(), stored.get_outgoing()), (1, 1));
+        assert_eq!(fs::read_to_string(&unrelated_path).unwrap(), "{}");
+    }
+}
@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
+use log::info;
+
+use crate::dont_know_trade::SENT_ORDERS;
+use crate::engine_events::ENGINE_EVENTS;
+use crate::execution_store::{store_execution_report, ExecDirection};
+#[cfg(feature = "kafka")]
+use crate::kafka::publish_message;
+use crate::message_converter::{fixmap2fixmsg, restamp_seq_num};
+use crate::message_journal::journal_sent;
+use crate::outbound::application_messages;
+use crate::pending_orders::PENDING_ORDERS;
+use crate::sequence::{SequenceNumberStore, SessionId};
+use crate::transport::Transport;
+use crate::{AtomicDateTime, MessageMap, IS_INITIATOR, ORDER_ACK_TIMEOUT_MS};
+
+lazy_static! {
+    pub static ref SESSION_STATES: SessionStates = SessionStates::new();
+}
+
+/// The Logon and Logout handshakes of one session, when it last sent a message and how its
+/// counterparty behaves.
+#[derive(Default)]
+pub struct SessionState {
+    sent_logon: AtomicBool,
+    received_logon: AtomicBool,
+    /// A Logout went out, the session is ending.
+    sent_logout: AtomicBool,
+    /// The counterparty confirmed the Logout we sent.
+    received_logout: AtomicBool,
+    /// When the last message went out, or the session started.
+    last_sent_time: AtomicDateTime,
+    /// Messages in a row received with an inaccurate SendingTime.
+    latency_violations: AtomicU64,
+    /// Session Rejects in a row of our messages.
+    consecutive_rejects: AtomicU64,
+    /// No more order messages go out after too many session Rejects in a row.
+    order_flow_halted: AtomicBool,
+}
+
+impl SessionState {
+    pub fn message_sent(&self) {
+        self.last_sent_time.store(Utc::now(), Ordering::SeqCst);
+    }
+
+    pub fn last_sent_time(&self) -> DateTime<Utc> {
+        self.last_sent_time.load(Ordering::SeqCst)
+    }
+
+    /// Time since the last message went out, or since the session started.
+    pub fn sender_idle(&self) -> Duration {
+        self.last_sent_time.elapsed()
+    }
+
+    /// Records a message received with an inaccurate SendingTime, returns how many arrived in
+    /// a row.
+    pub fn latency_violation(&self) -> u64 {
+        self.latency_violations.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Ends the run of messages with an inaccurate SendingTime.
+    pub fn clear_latency_violations(&self) {
+        self.latency_violations.store(0, Ordering::SeqCst);
+    }
+
+    /// Records a session Reject of one of our messages, returns how many arrived in a row.
+    pub fn session_reject(&self) -> u64 {
+        self.consecutive_rejects.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Ends the run of session Rejects, the counterparty accepted a business message.
+    pub fn clear_session_rejects(&self) {
+        self.consecutive_rejects.store(0, Ordering::SeqCst);
+    }
+
+    pub fn halt_order_flow(&self) {
+        self.order_flow_halted.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_order_flow_halted(&self) -> bool {
+        self.order_flow_halted.load(Ordering::SeqCst)
+    }
+
+    /// Lets order messages go out again, e.g. once the session logs on again.
+    pub fn resume_order_flow(&self) {
+        self.clear_session_rejects();
+        self.order_flow_halted.store(false, Ordering::SeqCst);
+    }
+
+    pub fn logon_sent(&self) {
+        self.sent_logon.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_logon_sent(&self) -> bool {
+        self.sent_logon.load(Ordering::SeqCst)
+    }
+
+    pub fn logon_received(&self) {
+        self.received_logon.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_logon_received(&self) -> bool {
+        self.received_logon.load(Ordering::SeqCst)
+    }
+
+    /// Logon exchanged and no Logout sent since.
+    pub fn is_logged_on(&self) -> bool {
+        self.is_logon_sent() && self.is_logon_received() && !self.is_logout_sent()
+    }
+
+    /// Records a Logout sent, unconfirmed so far.
+    pub fn logout_sent(&self) {
+        self.received_logout.store(false, Ordering::SeqCst);
+        self.sent_logout.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_logout_sent(&self) -> bool {
+        self.sent_logout.load(Ordering::SeqCst)
+    }
+
+    pub fn logout_received(&self) {
+        self.received_logout.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_logout_received(&self) -> bool {
+        self.received_logout.load(Ordering::SeqCst)
+    }
+
+    /// Forgets the Logout of an earlier connection once the session logs on again.
+    pub fn clear_logout(&self) {
+        self.sent_logout.store(false, Ordering::SeqCst);
+        self.received_logout.store(false, Ordering::SeqCst);
+    }
+
+    /// Starts over for a new connection of the session.
+    pub fn reset(&self) {
+        self.sent_logon.store(false, Ordering::SeqCst);
+        self.received_logon.store(false, Ordering::SeqCst);
+        self.clear_logout();
+        self.message_sent();
+        self.clear_latency_violations();
+    }
+}
+
+/// The state of every session seen so far, keyed by SessionId.
+pub struct SessionStates {
+    states: Mutex<HashMap<SessionId, Arc<SessionState>>>,
+}
+
+impl SessionStates {
+    pub fn new() -> Self {
+        Self {
+            states: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn for_session(&self, session_id: &SessionId) -> Arc<SessionState> {
+        Arc::clone(
+            self.states
+                .lock()
+                .unwrap()
+                .entry(session_id.clone())
+                .or_default(),
+        )
+    }
+
+    /// Records the raw message sent by the session its header names.
+    pub fn message_sent(&self, message: &str) {
+        if let Some(session_id) = SessionId::of_message(message, true) {
+            self.for_session(&session_id).message_sent();
+        }
+    }
+}
+
+impl Default for SessionStates {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A connected FIX session: the stream plus the dictionaries and sequence numbers
+/// used to encode outgoing messages for it.
+pub struct Session {
+    pub session_id: SessionId,
+    pub stream: Arc<Mutex<Box<dyn Transport>>>,
+    pub all_msg_map_collection: Arc<MessageMap>,
+    pub seq_store: Arc<SequenceNumberStore>,
+}
+
+impl Session {
+    pub fn new(
+        session_id: SessionId,
+        stream: Arc<Mutex<Box<dyn Transport>>>,
+        all_msg_map_collection: Arc<MessageMap>,
+        seq_store: Arc<SequenceNumberStore>,
+    ) -> Self {
+        Self {
+            session_id,
+            stream,
+            all_msg_map_collection,
+            seq_store,
+        }
+    }
+
+    /// Encodes the messages (maps keyed by tag name, merged over the session header) with
+    /// consecutive MsgSeqNums, journals them and writes the whole batch with a single
+    /// write and flush. Returns the assigned sequence numbers, none for a message the
+    /// outbound interceptors drop, or an error when a rejecting throttle refuses the batch.
+    /// The initiator remembers the ClOrdIDs of its order requests and, with
+    /// `order_ack_timeout_ms`, tracks them until answered.
+    pub fn send_batch(&self, messages: Vec<IndexMap<String, String>>) -> io::Result<Vec<u64>> {
+        let is_initiator = IS_INITIATOR.load(Ordering::SeqCst);
+        let track_orders = is_initiator && ORDER_ACK_TIMEOUT_MS.load(Ordering::SeqCst) > 0;
+        let mut tracked_messages = Vec::new();
+        let mut intercepted_messages = Vec::with_capacity(messages.len());
+        let queue = {
+            let stream = self.stream.lock().unwrap();
+            // Encoded with the numbers they would take, restamped once they are taken
+            let next_seq_num = self.seq_store.get_outgoing();
+            for msg_map in messages {
+                let mut merged_msg_map = self.all_msg_map_collection.fix_header.clone();
+                merged_msg_map.extend(msg_map);
+                let fix_msg = fixmap2fixmsg(
+                    &merged_msg_map,
+                    &self.all_msg_map_collection.fix_tag_name_map,
+                    next_seq_num + intercepted_messages.len() as u64,
+                )
+                .replace("|", "\x01");
+                if let Some(fix_msg) = stream.intercept_outbound(&fix_msg) {
+                    intercepted_messages.push(fix_msg.into_owned());
+                    if is_initiator {
+                        tracked_messages.push(merged_msg_map);
+                    }
+                }
+            }
+            stream.outbound_queue()
+        };
+        if intercepted_messages.is_empty() {
+            return Ok(Vec::new());
+        }
+        let application_messages: usize = intercepted_messages
+            .iter()
+            .map(|fix_msg| application_messages(fix_msg.as_bytes()))
+            .sum();
+        if queue.is_some_and(|queue| !queue.admit(application_messages)) {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "Outbound message rate limit reached",
+            ));
+        }
+        let count = intercepted_messages.len() as u64;
+        // The numbers stay taken while the batch is written, and holding the stream lock keeps
+        // other senders from interleaving with it
+        let (first_seq_num, encoded_messages) = self.seq_store.send_outgoing(count, |first_seq_num| {
+            let mut stream = self.stream.lock().unwrap();
+            let encoded_messages: Vec<String> = intercepted_messages
+                .iter()
+                .enumerate()
+                .map(|(offset, fix_msg)| {
+                    restamp_seq_num(fix_msg, first_seq_num + offset as u64).into_owned()
+                })
+                .collect();
+            stream.write_all(encoded_messages.concat().as_bytes())?;
+            stream.flush()?;
+            Ok((first_seq_num, encoded_messages))
+        })?;
+
+        let next_seq_num = first_seq_num + count;
+        for fix_msg in &encoded_messages {
+            journal_sent(fix_msg);
+            store_execution_report(ExecDirection::Sent, fix_msg);
+            #[cfg(feature = "kafka")]
+            publish_message(ExecDirection::Sent, fix_msg);
+            ENGINE_EVENTS.message(ExecDirection::Sent, fix_msg);
+        }
+        SESSION_STATES.for_session(&self.session_id).message_sent();
+        let sent_at = Instant::now();
+        for msg_map in &tracked_messages {
+            SENT_ORDERS.record(msg_map);
+            if track_orders {
+                PENDING_ORDERS.track(msg_map, sent_at);
+            }
+        }
+        info!(
+            "sent out batch of {} messages, MsgSeqNum {} to {}",
+            encoded_messages.len(),
+            first_seq_num,
+            next_seq_num - 1
+        );
+
+        Ok((first_seq_num..next_seq_num).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use tempfile::NamedTempFile;
+
+    use crate::parse_xml::{DataType, FixTag};
+
+    fn fix_tag(number: &str, name: &str) -> (String, FixTag) {
+        (
+            name.to_string(),
+            FixTag::new(number.to_string(), name.to_string(), DataType::String, None),
+        )
+    }
+
+    fn setup_msg_map() -> Arc<MessageMap> {
+        let fix_header: IndexMap<String, String> = [
+            ("BeginString", "FIX.4.2"),
+            ("BodyLength", "0"),
+            ("MsgType", "0"),
+            ("MsgSeqNum", "0"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        Arc::new(MessageMap {
+            fix_header,
+            fix_tag_number_map: Default::default(),
+            admin_msg_list: Default::default(),
+            admin_msg: Default::default(),
+            app_msg: Default::default(),
+            fix_tag_name_map: HashMap::from([
+                fix_tag("8", "BeginString"),
+                fix_tag("9", "BodyLength"),
+                fix_tag("35", "MsgType"),
+                fix_tag("34", "MsgSeqNum"),
+                fix_tag("11", "ClOrdID"),
+            ]),
+            msgnumber_fields_map: Default::default(),
+            valid_msg_types: Default::default(),
+            required_fields: Default::default(),
+        })
+    }
+
+    #[test]
+    fn test_send_batch() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = String::new();
+            stream.read_to_string(&mut buffer).unwrap();
+            buffer
+        });
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let seq_store = Arc::new(SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap());
+        seq_store.set_outgoing(5);
+        let stream = TcpStream::connect(address).unwrap();
+        let session = Session::new(
+            SessionId::from_header(&setup_msg_map().fix_header, None),
+            Arc::new(Mutex::new(Box::new(stream))),
+            setup_msg_map(),
+            seq_store,
+        );
+
+        let orders = ["1001", "1002", "1003"]
+            .iter()
+            .map(|cl_ord_id| {
+                IndexMap::from([
+                    ("MsgType".to_string(), "D".to_string()),
+                    ("ClOrdID".to_string(), cl_ord_id.to_string()),
+                ])
+            })
+            .collect();
+        assert_eq!(session.send_batch(orders).unwrap(), vec![5, 6, 7]);
+        assert_eq!(session.seq_store.get_outgoing(), 8);
+        drop(session);
+
+        let received = server.join().unwrap();
+        let messages: Vec<&str> = received.split("8=FIX.4.2\x01").skip(1).collect();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].contains("\x0134=5\x0111=1001\x01"));
+        assert!(messages[2].contains("\x0134=7\x0111=1003\x01"));
+    }
+
+    #[test]
+    fn test_logout_state_per_session() {
+        let states = SessionStates::new();
+        let session = |target_comp_id: &str| SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "FIX_Engine".to_string(),
+            target_comp_id: target_comp_id.to_string(),
+        };
+        let xyz = states.for_session(&session("XYZ"));
+        let abc = states.for_session(&session("ABC"));
+
+        xyz.logout_sent();
+        assert!(states.for_session(&session("XYZ")).is_logout_sent());
+        assert!(!abc.is_logout_sent());
+
+        // A confirmation of ABC's Logout does not confirm XYZ's
+        abc.logout_sent();
+        abc.logout_received();
+        assert!(!xyz.is_logout_received());
+
+        xyz.logout_received();
+        xyz.clear_logout();
+        assert!(!xyz.is_logout_sent() && !xyz.is_logout_received());
+        assert!(abc.is_logout_sent() && abc.is_logout_received());
+    }
+
+    #[test]
+    fn test_last_sent_time_per_session() {
+        let states = SessionStates::new();
+        let xyz = states.for_session(&SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "FIX_Engine".to_string(),
+            target_comp_id: "XYZ".to_string(),
+        });
+        thread::sleep(Duration::from_millis(20));
+
+        // A message to ABC does not put off the heartbeat to XYZ
+        states.message_sent("8=FIX.4.2\x0135=0\x0149=FIX_Engine\x0156=ABC\x0134=2\x01");
+        assert!(xyz.sender_idle() >= Duration::from_millis(20));
+        states.message_sent("8=FIX.4.2\x0135=0\x0149=FIX_Engine\x0156=XYZ\x0134=2\x01");
+        assert!(xyz.sender_idle() < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_latency_violations_per_session() {
+        let states = SessionStates::new();
+        let session = |target_comp_id: &str| SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "FIX_Engine".to_string(),
+            target_comp_id: target_comp_id.to_string(),
+        };
+        let xyz = states.for_session(&session("XYZ"));
+        let abc = states.for_session(&session("ABC"));
+
+        assert_eq!(xyz.latency_violation(), 1);
+        assert_eq!(xyz.latency_violation(), 2);
+        // A late message from ABC does not bring XYZ closer to a Logout
+        assert_eq!(abc.latency_violation(), 1);
+        abc.clear_latency_violations();
+        assert_eq!(xyz.latency_violation(), 3);
+        xyz.reset();
+        assert_eq!(xyz.latency_violation(), 1);
+    }
+
+    #[test]
+    fn test_order_flow_halted_per_session() {
+        let states = SessionStates::new();
+        let session = |target_comp_id: &str| SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "FIX_Engine".to_string(),
+            target_comp_id: target_comp_id.to_string(),
+        };
+        let xyz = states.for_session(&session("XYZ"));
+        let abc = states.for_session(&session("ABC"));
+
+        assert_eq!(xyz.session_reject(), 1);
+        assert_eq!(xyz.session_reject(), 2);
+        assert_eq!(abc.session_reject(), 1);
+        xyz.halt_order_flow();
+        // Rejects on XYZ do not stop orders to ABC
+        assert!(xyz.is_order_flow_halted());
+        assert!(!abc.is_order_flow_halted());
+        abc.clear_session_rejects();
+        assert_eq!(abc.session_reject(), 1);
+        xyz.resume_order_flow();
+        assert!(!xyz.is_order_flow_halted());
+        assert_eq!(xyz.session_reject(), 1);
+    }
+}
@@ -0,0 +1,264 @@
+use std::sync::RwLock;
+
+use log::info;
+
+use crate::sequence::SessionId;
+
+lazy_static! {
+    pub static ref SESSION_HOOKS: SessionHookRegistry = SessionHookRegistry::new();
+}
+
+/// Callbacks on session lifecycle transitions, e.g. to enable order flow only once the
+/// session is logged on. Each callback is given the session it happened on and defaults to
+/// doing nothing.
+pub trait SessionHooks: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// The Logon handshake completed.
+    fn on_logon(&self, _session_id: &SessionId) {}
+
+    /// The counterparty sent a Logout, either initiating it or confirming ours.
+    fn on_logout(&self, _session_id: &SessionId, _text: Option<&str>) {}
+
+    /// The connection closed.
+    fn on_disconnect(&self, _session_id: &SessionId) {}
+
+    /// The counterparty requested a resend of our messages BeginSeqNo..EndSeqNo (0 = infinity).
+    fn on_resend(&self, _session_id: &SessionId, _begin_seq_no: u64, _end_seq_no: u64) {}
+
+    /// The counterparty moved its outgoing sequence number to NewSeqNo.
+    fn on_sequence_reset(&self, _session_id: &SessionId, _new_seq_no: u64, _gap_fill: bool) {}
+
+    /// No ExecutionReport or OrderCancelReject answered our order request (MsgType
+    /// `msg_type`) within `order_ack_timeout_ms`.
+    fn on_unacknowledged_order(&self, _session_id: &SessionId, _cl_ord_id: &str, _msg_type: &str) {}
+
+    /// An ExecutionReport arrived for an order we never sent.
+    fn on_unknown_execution(&self, _session_id: &SessionId, _cl_ord_id: &str, _exec_id: &str) {}
+
+    /// The counterparty published the TradSesStatus of a trading session, e.g. OPEN or
+    /// HALTED, as the dictionary describes it.
+    fn on_trading_session_status(
+        &self,
+        _session_id: &SessionId,
+        _trading_session_id: &str,
+        _status: &str,
+        _text: Option<&str>,
+    ) {
+    }
+}
+
+/// Calls every registered hook on each session transition.
+pub struct SessionHookRegistry {
+    hooks: RwLock<Vec<Box<dyn SessionHooks>>>,
+}
+
+impl SessionHookRegistry {
+    pub fn new() -> Self {
+        Self {
+            hooks: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn register(&self, hooks: Box<dyn SessionHooks>) {
+        info!("Registered session hooks: {}", hooks.name());
+        self.hooks.write().unwrap().push(hooks);
+    }
+
+    pub fn logon(&self, session_id: &SessionId) {
+        for hooks in self.hooks.read().unwrap().iter() {
+            hooks.on_logon(session_id);
+        }
+    }
+
+    pub fn logout(&self, session_id: &SessionId, text: Option<&str>) {
+        for hooks in self.hooks.read().unwrap().iter() {
+            hooks.on_logout(session_id, text);
+        }
+    }
+
+    pub fn disconnect(&self, session_id: &SessionId) {
+        for hooks in self.hooks.read().unwrap().iter() {
+            hooks.on_disconnect(session_id);
+        }
+    }
+
+    pub fn resend(&self, session_id: &SessionId, begin_seq_no: u64, end_seq_no: u64) {
+        for hooks in self.hooks.read().unwrap().iter() {
+            hooks.on_resend(session_id, begin_seq_no, end_seq_no);
+        }
+    }
+
+    pub fn sequence_reset(&self, session_id: &SessionId, new_seq_no: u64, gap_fill: bool) {
+        for hooks in self.hooks.read().unwrap().iter() {
+            hooks.on_sequence_reset(session_id, new_seq_no, gap_fill);
+        }
+    }
+
+    pub fn unacknowledged_order(&self, session_id: &SessionId, cl_ord_id: &str, msg_type: &str) {
+        for hooks in self.hooks.read().unwrap().iter() {
+            hooks.on_unacknowledged_order(session_id, cl_ord_id, msg_type);
+        }
+    }
+
+    pub fn unknown_execution(&self, session_id: &SessionId, cl_ord_id: &str, exec_id: &str) {
+        for hooks in self.hooks.read().unwrap().iter() {
+            hooks.on_unknown_execution(session_id, cl_ord_id, exec_id);
+        }
+    }
+
+    pub fn trading_session_status(
+        &self,
+        session_id: &SessionId,
+        trading_session_id: &str,
+        status: &str,
+        text: Option<&str>,
+    ) {
+        for hooks in self.hooks.read().unwrap().iter() {
+            hooks.on_trading_session_status(session_id, trading_session_id, status, text);
+        }
+    }
+}
+
+/// Logs every session transition to the application log.
+pub struct SessionLogHooks;
+
+impl SessionHooks for SessionLogHooks {
+    fn name(&self) -> &str {
+        "log"
+    }
+
+    fn on_logon(&self, session_id: &SessionId) {
+        info!("[SESSION] {} Logged on", session_id);
+    }
+
+    fn on_logout(&self, session_id: &SessionId, text: Option<&str>) {
+        info!(
+            "[SESSION] {} Logged out: {}",
+            session_id,
+            text.unwrap_or("-")
+        );
+    }
+
+    fn on_disconnect(&self, session_id: &SessionId) {
+        info!("[SESSION] {} Disconnected", session_id);
+    }
+
+    fn on_resend(&self, session_id: &SessionId, begin_seq_no: u64, end_seq_no: u64) {
+        info!(
+            "[SESSION] {} Resend requested: {}-{}",
+            session_id, begin_seq_no, end_seq_no
+        );
+    }
+
+    fn on_sequence_reset(&self, session_id: &SessionId, new_seq_no: u64, gap_fill: bool) {
+        info!(
+            "[SESSION] {} Sequence reset to {} (GapFill: {})",
+            session_id, new_seq_no, gap_fill
+        );
+    }
+
+    fn on_unacknowledged_order(&self, session_id: &SessionId, cl_ord_id: &str, msg_type: &str) {
+        info!(
+            "[SESSION] {} Order request unanswered: {} {}",
+            session_id, msg_type, cl_ord_id
+        );
+    }
+
+    fn on_unknown_execution(&self, session_id: &SessionId, cl_ord_id: &str, exec_id: &str) {
+        info!(
+            "[SESSION] {} ExecutionReport {} for unknown order {}",
+            session_id, exec_id, cl_ord_id
+        );
+    }
+
+    fn on_trading_session_status(
+        &self,
+        session_id: &SessionId,
+        trading_session_id: &str,
+        status: &str,
+        text: Option<&str>,
+    ) {
+        info!(
+            "[SESSION] {} Trading session {} {}: {}",
+            session_id,
+            trading_session_id,
+            status,
+            text.unwrap_or("-")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct RecordingHooks {
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl SessionHooks for RecordingHooks {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        fn on_logon(&self, session_id: &SessionId) {
+            let call = format!("logon {}", session_id);
+            self.calls.lock().unwrap().push(call);
+        }
+
+        fn on_sequence_reset(&self, _session_id: &SessionId, new_seq_no: u64, gap_fill: bool) {
+            let call = format!("sequence_reset {} {}", new_seq_no, gap_fill);
+            self.calls.lock().unwrap().push(call);
+        }
+
+        fn on_unknown_execution(&self, _session_id: &SessionId, cl_ord_id: &str, exec_id: &str) {
+            let call = format!("unknown_execution {} {}", cl_ord_id, exec_id);
+            self.calls.lock().unwrap().push(call);
+        }
+
+        fn on_trading_session_status(
+            &self,
+            _session_id: &SessionId,
+            id: &str,
+            status: &str,
+            _text: Option<&str>,
+        ) {
+            let call = format!("trading_session_status {} {}", id, status);
+            self.calls.lock().unwrap().push(call);
+        }
+    }
+
+    #[test]
+    fn test_registered_hooks_are_called() {
+        let registry = SessionHookRegistry::new();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        registry.register(Box::new(RecordingHooks {
+            calls: Arc::clone(&calls),
+        }));
+        registry.register(Box::new(SessionLogHooks));
+
+        let session_id = SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "BUYSIDE".to_string(),
+            target_comp_id: "SELLSIDE".to_string(),
+        };
+        registry.logon(&session_id);
+        registry.resend(&session_id, 1, 0);
+        registry.sequence_reset(&session_id, 10, true);
+        registry.trading_session_status(&session_id, "DAY", "HALTED", Some("Volatility"));
+        registry.unknown_execution(&session_id, "9", "E9");
+        registry.disconnect(&session_id);
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            vec![
+                "logon FIX.4.2:BUYSIDE->SELLSIDE".to_string(),
+                "sequence_reset 10 true".to_string(),
+                "trading_session_status DAY HALTED".to_string(),
+                "unknown_execution 9 E9".to_string()
+            ]
+        );
+    }
+}
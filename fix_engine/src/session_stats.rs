@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::sequence::SequenceNumberStore;
+
+/// Per-connection counters reported by the periodic task as one compact log line.
+pub struct SessionStats {
+    session: String,
+    messages_in: AtomicU64,
+    max_latency_micros: AtomicU64,
+    /// Outbound messages waiting for the writer thread, sampled before each report.
+    queue_depth: AtomicU64,
+    last_outgoing_seq: AtomicU64,
+}
+
+impl SessionStats {
+    pub fn new(session: &str, seq_store: &SequenceNumberStore) -> Self {
+        Self {
+            session: session.to_string(),
+            messages_in: AtomicU64::new(0),
+            max_latency_micros: AtomicU64::new(0),
+            queue_depth: AtomicU64::new(0),
+            last_outgoing_seq: AtomicU64::new(seq_store.get_outgoing()),
+        }
+    }
+
+    /// Counts one handled inbound message, however many reads it took, and the time it took
+    /// to handle it.
+    pub fn record_incoming(&self, latency: Duration) {
+        self.messages_in.fetch_add(1, Ordering::SeqCst);
+        self.max_latency_micros
+            .fetch_max(latency.as_micros() as u64, Ordering::SeqCst);
+    }
+
+    pub fn record_queue_depth(&self, depth: usize) {
+        self.queue_depth.store(depth as u64, Ordering::SeqCst);
+    }
+
+    /// Builds the report line for the interval since the previous call and resets the counters.
+    /// Outbound messages are counted from the outgoing sequence number, so every send path is covered.
+    pub fn take_report(&self, seq_store: &SequenceNumberStore) -> String {
+        let seq_in = seq_store.get_incoming();
+        let seq_out = seq_store.get_outgoing();
+        let last_outgoing_seq = self.last_outgoing_seq.swap(seq_out, Ordering::SeqCst);
+        let messages_out = if seq_out >= last_outgoing_seq {
+            seq_out - last_outgoing_seq
+        } else {
+            // The sequence numbers were reset during the interval
+            seq_out.saturating_sub(1)
+        };
+
+        format!(
+            "[QOS] {} in={} out={} max_latency_us={} queue_depth={} seq_in={} seq_out={}",
+            self.session,
+            self.messages_in.swap(0, Ordering::SeqCst),
+            messages_out,
+            self.max_latency_micros.swap(0, Ordering::SeqCst),
+            self.queue_depth.load(Ordering::SeqCst),
+            seq_in,
+            seq_out
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_take_report() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let seq_store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
+        let stats = SessionStats::new("127.0.0.1:9999", &seq_store);
+
+        stats.record_incoming(Duration::from_micros(120));
+        stats.record_incoming(Duration::from_micros(80));
+        seq_store.increment_incoming();
+        seq_store.increment_incoming();
+        seq_store.increment_outgoing();
+
+        assert_eq!(
+            stats.take_report(&seq_store),
+            "[QOS] 127.0.0.1:9999 in=2 out=1 max_latency_us=120 queue_depth=0 seq_in=3 seq_out=2"
+        );
+        // Counters start over for the next interval
+        assert_eq!(
+            stats.take_report(&seq_store),
+            "[QOS] 127.0.0.1:9999 in=0 out=0 max_latency_us=0 queue_depth=0 seq_in=3 seq_out=2"
+        );
+    }
+
+    #[test]
+    fn test_take_report_after_sequence_reset() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let seq_store = SequenceNumberStore::new(temp_file.path().to_str().unwrap()).unwrap();
+        seq_store.set_outgoing(50);
+        let stats = SessionStats::new("session", &seq_store);
+
+        seq_store.reset();
+        seq_store.increment_outgoing();
+        seq_store.increment_outgoing();
+
+        assert!(stats.take_report(&seq_store).contains(" out=2 "));
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::RwLock;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+
+lazy_static! {
+    /// Milliseconds added to the timestamps we send, negative values make the venue run behind.
+    pub static ref SIM_CLOCK_SKEW_MS: AtomicI64 = AtomicI64::new(0);
+    /// The precision of the timestamps we send, milliseconds when not configured.
+    pub static ref TIMESTAMP_PRECISION: RwLock<Option<TimestampPrecision>> = RwLock::new(None);
+}
+
+/// The fraction of a second carried by the SendingTime and TransactTime we send.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimestampPrecision {
+    Seconds,
+    Millis,
+    Micros,
+    Nanos,
+}
+
+impl TimestampPrecision {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "seconds" => Ok(TimestampPrecision::Seconds),
+            "millis" => Ok(TimestampPrecision::Millis),
+            "micros" => Ok(TimestampPrecision::Micros),
+            "nanos" => Ok(TimestampPrecision::Nanos),
+            _ => Err(format!("Unknown timestamp precision {}", value)),
+        }
+    }
+
+    fn format(self) -> &'static str {
+        match self {
+            TimestampPrecision::Seconds => "%Y%m%d-%H:%M:%S",
+            TimestampPrecision::Millis => "%Y%m%d-%H:%M:%S%.3f",
+            TimestampPrecision::Micros => "%Y%m%d-%H:%M:%S%.6f",
+            TimestampPrecision::Nanos => "%Y%m%d-%H:%M:%S%.9f",
+        }
+    }
+}
+
+fn precision() -> Option<TimestampPrecision> {
+    *TIMESTAMP_PRECISION.read().unwrap()
+}
+
+fn format_timestamp(timestamp: NaiveDateTime, precision: TimestampPrecision) -> String {
+    timestamp.format(precision.format()).to_string()
+}
+
+fn skew() -> Duration {
+    Duration::milliseconds(SIM_CLOCK_SKEW_MS.load(Ordering::SeqCst))
+}
+
+/// Current time of the simulated venue: the real clock shifted by the configured skew.
+pub fn venue_now() -> DateTime<Utc> {
+    Utc::now() + skew()
+}
+
+/// SendingTime of an outgoing message.
+pub fn venue_timestamp() -> String {
+    let precision = precision().unwrap_or(TimestampPrecision::Millis);
+    format_timestamp(venue_now().naive_utc(), precision)
+}
+
+/// Shifts an outgoing UTCTimestamp (e.g. TransactTime) by the skew, in the configured
+/// precision. Values which are not timestamps, and all values while neither a skew nor a
+/// precision is configured, are returned unchanged.
+pub fn skew_timestamp(value: &str) -> String {
+    shift_timestamp(value, skew(), precision())
+}
+
+fn shift_timestamp(value: &str, skew: Duration, precision: Option<TimestampPrecision>) -> String {
+    if skew.is_zero() && precision.is_none() {
+        return value.to_string();
+    }
+    match NaiveDateTime::parse_from_str(value, "%Y%m%d-%H:%M:%S%.f") {
+        Ok(timestamp) => format_timestamp(
+            timestamp + skew,
+            precision.unwrap_or(TimestampPrecision::Millis),
+        ),
+        Err(_) => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_skew_timestamp() {
+        assert_eq!(
+            shift_timestamp("20240101-00:00:00.000", Duration::zero(), None),
+            "20240101-00:00:00.000"
+        );
+
+        let skew = Duration::milliseconds(-1500);
+        assert_eq!(
+            shift_timestamp("20240101-00:00:00", skew, None),
+            "20231231-23:59:58.500"
+        );
+        assert_eq!(
+            shift_timestamp("20240101-00:00:00", skew, Some(TimestampPrecision::Seconds)),
+            "20231231-23:59:58"
+        );
+        assert_eq!(shift_timestamp("not a timestamp", skew, None), "not a timestamp");
+    }
+
+    #[test]
+    fn test_timestamp_precision() {
+        let timestamp =
+            NaiveDateTime::parse_from_str("20240101-00:00:01.123456789", "%Y%m%d-%H:%M:%S%.f")
+                .unwrap();
+        let formatted = |precision: &str| {
+            format_timestamp(timestamp, TimestampPrecision::parse(precision).unwrap())
+        };
+        assert_eq!(formatted("seconds"), "20240101-00:00:01");
+        assert_eq!(formatted("millis"), "20240101-00:00:01.123");
+        assert_eq!(formatted("micros"), "20240101-00:00:01.123456");
+        assert_eq!(formatted("nanos"), "20240101-00:00:01.123456789");
+        assert!(TimestampPrecision::parse("minutes").is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+use chrono::Utc;
+
+lazy_static! {
+    /// Randomness shared by the simulator components (fills, rejects, latencies, market data).
+    /// Seeded from `sim_seed` so runs can be reproduced.
+    pub static ref SIM_RNG: RwLock<Arc<SimRng>> = RwLock::new(Arc::new(SimRng::from_clock()));
+}
+
+const GOLDEN_GAMMA: u64 = 0x9e3779b97f4a7c15;
+
+/// Seedable SplitMix64 generator.
+pub struct SimRng {
+    seed: u64,
+    state: AtomicU64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// Seeds from the current time, for runs which do not need to be reproduced.
+    pub fn from_clock() -> Self {
+        Self::new(Utc::now().timestamp_nanos_opt().unwrap_or_default() as u64)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn next_u64(&self) -> u64 {
+        mix(self
+            .state
+            .fetch_add(GOLDEN_GAMMA, Ordering::SeqCst)
+            .wrapping_add(GOLDEN_GAMMA))
+    }
+
+    /// Uniform in [0, 1).
+    pub fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn mix(value: u64) -> u64 {
+    let mut z = value;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let first = SimRng::new(42);
+        let second = SimRng::new(42);
+        let draws: Vec<u64> = (0..5).map(|_| first.next_u64()).collect();
+        assert_eq!(draws, (0..5).map(|_| second.next_u64()).collect::<Vec<_>>());
+        assert_ne!(draws[0], SimRng::new(43).next_u64());
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use indexmap::IndexMap;
+use log::{error, info};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+
+use crate::message_handling::{order_execution_report, send_execution_report};
+use crate::order_events::{OrderEvent, ORDER_EVENTS};
+use crate::orderstore::{OrdStatus, Order, OrderStore};
+use crate::parse_xml::FixTag;
+use crate::risk::PRE_TRADE_LIMITS;
+use crate::sequence::SequenceNumberStore;
+use crate::sim_rng::SIM_RNG;
+use crate::timer::TIMERS;
+use crate::transport::Transport;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimulatorConfig {
+    /// Part of each order's quantity filled, 0.0 to 1.0; the rest stays open.
+    pub fill_ratio: f64,
+    /// Executions the filled quantity is split into.
+    pub fills: u32,
+    /// Delay before each execution, drawn uniformly from this range in milliseconds.
+    pub latency_ms: (u64, u64),
+    /// Chance of rejecting an order instead of acknowledging it, 0.0 to 1.0.
+    pub reject_probability: f64,
+}
+
+impl SimulatorConfig {
+    pub fn rejects(&self) -> bool {
+        SIM_RNG.read().unwrap().next_f64() < self.reject_probability
+    }
+
+    fn latency(&self) -> Duration {
+        let (min, max) = self.latency_ms;
+        let span = max.saturating_sub(min);
+        let offset = match span {
+            0 => 0,
+            span => SIM_RNG.read().unwrap().next_u64() % (span + 1),
+        };
+        Duration::from_millis(min + offset)
+    }
+
+    /// The quantities of the executions of an order, in whole units, the last ones taking
+    /// the remainder.
+    pub fn fill_quantities(&self, order_qty: Decimal) -> Vec<Decimal> {
+        let fill_ratio = Decimal::from_f64(self.fill_ratio.clamp(0.0, 1.0)).unwrap_or_default();
+        let filled = (order_qty * fill_ratio).round().to_u64().unwrap_or(0);
+        let fills = (self.fills.max(1) as u64).min(filled);
+        if fills == 0 {
+            return Vec::new();
+        }
+        (0..fills)
+            .map(|index| filled / fills + u64::from(index >= fills - filled % fills))
+            .map(Decimal::from)
+            .collect()
+    }
+}
+
+/// Everything the fill timer needs to report executions to the counterparty.
+pub struct FillContext {
+    pub stream: Box<dyn Transport>,
+    pub app_msg: HashMap<String, IndexMap<String, String>>,
+    pub fix_tag_name_map: HashMap<String, FixTag>,
+    pub seq_store: Arc<SequenceNumberStore>,
+    pub order_store: Arc<OrderStore>,
+}
+
+/// Sends the partial fills and fill of an acknowledged order from the shared timer thread,
+/// each after a simulated latency. Stops when the order is canceled or replaced meanwhile.
+pub fn start_fills(config: SimulatorConfig, order: Order, context: FillContext) {
+    let mut quantities = config.fill_quantities(order.quantity).into_iter();
+    if quantities.len() == 0 {
+        return;
+    }
+    // A market order is filled at the reference price of its symbol
+    let reference_price = || {
+        PRE_TRADE_LIMITS
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|limits| limits.reference_prices.get(&order.symbol).copied())
+    };
+    let Some(price) = order.price.or_else(reference_price) else {
+        info!("No price to fill order {} at, not simulating fills", order.id);
+        return;
+    };
+    let stream = Arc::new(Mutex::new(context.stream));
+    TIMERS.schedule(Instant::now() + config.latency(), move || {
+        let last_qty = quantities.next()?;
+        match context.order_store.get_order(&order.key()) {
+            Some(current) if current.is_open() && current.status() != OrdStatus::Replaced => {}
+            _ => {
+                info!(
+                    "Order {} is no longer open, simulated fills stopped",
+                    order.id
+                );
+                return None;
+            }
+        }
+        let filled = match context
+            .order_store
+            .fill_order(&order.key(), last_qty, price)
+        {
+            Ok(filled) => filled,
+            Err(e) => {
+                error!("Failed to fill order {}: {}", order.id, e);
+                return None;
+            }
+        };
+        let exec_type = if filled.leaves_qty().is_zero() {
+            "2"
+        } else {
+            "1"
+        };
+        let exec_id = context.seq_store.next_exec_id();
+        ORDER_EVENTS.publish(&OrderEvent::from_fill(&filled, &exec_id, last_qty, price));
+        let override_map =
+            order_execution_report(&filled, &exec_id, exec_type, last_qty, price);
+        if let Err(e) = send_execution_report(
+            &stream,
+            &context.app_msg,
+            &context.fix_tag_name_map,
+            &context.seq_store,
+            &override_map,
+        ) {
+            error!("Failed to send simulated fill: {}", e);
+            return None;
+        }
+        info!(
+            "Simulated {} of {} on order {}",
+            last_qty, order.quantity, order.id
+        );
+        (quantities.len() > 0).then(|| Instant::now() + config.latency())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill_quantities() {
+        let config = SimulatorConfig {
+            fill_ratio: 1.0,
+            fills: 3,
+            latency_ms: (10, 20),
+            reject_probability: 0.0,
+        };
+        let quantities = |order_qty: u64, config: &SimulatorConfig| -> Vec<u64> {
+            config
+                .fill_quantities(Decimal::from(order_qty))
+                .iter()
+                .filter_map(|quantity| quantity.to_u64())
+                .collect()
+        };
+        assert_eq!(quantities(100, &config), vec![33, 33, 34]);
+        assert_eq!(quantities(2, &config), vec![1, 1]);
+        let partial = SimulatorConfig {
+            fill_ratio: 0.5,
+            ..config
+        };
+        assert_eq!(quantities(100, &partial), vec![16, 17, 17]);
+        assert!(!config.rejects());
+        let latency = config.latency();
+        assert!(latency >= Duration::from_millis(10) && latency <= Duration::from_millis(20));
+    }
+}
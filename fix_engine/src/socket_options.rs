@@ -0,0 +1,101 @@
+use std::io;
+use std::net::{SocketAddr, TcpStream};
+use std::sync::RwLock;
+
+use log::error;
+use socket2::{Domain, SockRef, Socket, Type};
+
+lazy_static! {
+    pub static ref SOCKET_OPTIONS: RwLock<SocketOptions> = RwLock::new(SocketOptions::default());
+}
+
+/// Options applied to every session socket, connected or accepted. Unset options keep the
+/// operating system defaults.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SocketOptions {
+    /// Disables Nagle's algorithm so small messages are sent without delay.
+    pub tcp_nodelay: bool,
+    pub so_keepalive: bool,
+    pub recv_buffer_size: Option<usize>,
+    pub send_buffer_size: Option<usize>,
+    /// Initiator only: source address to connect from, for venues which whitelist our IP.
+    /// Port 0 lets the operating system pick the port.
+    pub local_address: Option<SocketAddr>,
+}
+
+impl SocketOptions {
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        if self.tcp_nodelay {
+            stream.set_nodelay(true)?;
+        }
+        let socket = SockRef::from(stream);
+        if self.so_keepalive {
+            socket.set_keepalive(true)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = self.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies the configured socket options, logging rather than failing the connection.
+pub fn apply_socket_options(stream: &TcpStream) {
+    if let Err(e) = SOCKET_OPTIONS.read().unwrap().apply(stream) {
+        error!("Failed to apply socket options: {}", e);
+    }
+}
+
+/// Connects to `remote` from `local`, binding the socket before connecting.
+pub fn connect_from(local: SocketAddr, remote: SocketAddr) -> io::Result<TcpStream> {
+    let socket = Socket::new(Domain::for_address(remote), Type::STREAM, None)?;
+    if local.port() != 0 {
+        // A fixed source port would otherwise be unusable until TIME_WAIT expires
+        socket.set_reuse_address(true)?;
+    }
+    socket.bind(&local.into())?;
+    socket.connect(&remote.into())?;
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_apply_socket_options() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let options = SocketOptions {
+            tcp_nodelay: true,
+            so_keepalive: true,
+            recv_buffer_size: Some(65536),
+            send_buffer_size: None,
+            local_address: None,
+        };
+
+        options.apply(&stream).unwrap();
+        assert!(stream.nodelay().unwrap());
+        let socket = SockRef::from(&stream);
+        assert!(socket.keepalive().unwrap());
+        assert!(socket.recv_buffer_size().unwrap() >= 65536);
+    }
+
+    #[test]
+    fn test_connect_from_local_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let local: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let stream = connect_from(local, listener.local_addr().unwrap()).unwrap();
+        let (_accepted, peer) = listener.accept().unwrap();
+        assert_eq!(stream.local_addr().unwrap(), peer);
+        assert_eq!(stream.peer_addr().unwrap(), listener.local_addr().unwrap());
+
+        // Nothing listens on port 1
+        let closed: SocketAddr = "127.0.0.1:1".parse().unwrap();
+        assert!(connect_from(local, closed).is_err());
+    }
+}
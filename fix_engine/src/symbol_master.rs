@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use indexmap::IndexMap;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+lazy_static! {
+    /// Reference data of the symbols the acceptor trades, None when orders are not validated.
+    pub static ref SYMBOL_MASTER: RwLock<Option<Arc<SymbolMaster>>> = RwLock::new(None);
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TradingStatus {
+    Trading,
+    Halted,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SymbolInfo {
+    pub symbol: String,
+    /// Prices are multiples of it.
+    pub tick_size: Decimal,
+    /// Quantities are multiples of it.
+    pub lot_size: Decimal,
+    pub status: TradingStatus,
+}
+
+/// The symbols orders may be placed in, loaded from a CSV file with the header
+/// `symbol,tick_size,lot_size,status` or a JSON array of the same fields.
+#[derive(Debug, Default)]
+pub struct SymbolMaster {
+    symbols: HashMap<String, SymbolInfo>,
+}
+
+impl SymbolMaster {
+    pub fn load(file_path: &str) -> io::Result<Self> {
+        let content = fs::read_to_string(file_path)?;
+        let invalid = |reason: String| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid symbol master {}: {}", file_path, reason),
+            )
+        };
+        let symbols: Vec<SymbolInfo> = match Path::new(file_path).extension() {
+            Some(extension) if extension == "json" => {
+                serde_json::from_str(&content).map_err(|e| invalid(e.to_string()))?
+            }
+            _ => Self::parse_csv(&content).map_err(invalid)?,
+        };
+        Ok(Self::new(symbols))
+    }
+
+    pub fn new(symbols: Vec<SymbolInfo>) -> Self {
+        Self {
+            symbols: symbols
+                .into_iter()
+                .map(|info| (info.symbol.clone(), info))
+                .collect(),
+        }
+    }
+
+    fn parse_csv(content: &str) -> Result<Vec<SymbolInfo>, String> {
+        let mut lines = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let header: Vec<&str> = match lines.next() {
+            Some((_, header)) => header.split(',').map(str::trim).collect(),
+            None => return Ok(Vec::new()),
+        };
+        let mut symbols = Vec::new();
+        for (number, line) in lines {
+            let record: HashMap<&str, &str> = header
+                .iter()
+                .copied()
+                .zip(line.split(',').map(str::trim))
+                .collect();
+            let field = |name: &str| {
+                record
+                    .get(name)
+                    .copied()
+                    .ok_or_else(|| format!("line {}: missing {}", number + 1, name))
+            };
+            let decimal = |name: &str| -> Result<Decimal, String> {
+                let value = field(name)?;
+                value
+                    .parse()
+                    .map_err(|_| format!("line {}: invalid {} {}", number + 1, name, value))
+            };
+            let status = match field("status")?.to_lowercase().as_str() {
+                "trading" => TradingStatus::Trading,
+                "halted" => TradingStatus::Halted,
+                other => return Err(format!("line {}: unknown status {}", number + 1, other)),
+            };
+            symbols.push(SymbolInfo {
+                symbol: field("symbol")?.to_string(),
+                tick_size: decimal("tick_size")?,
+                lot_size: decimal("lot_size")?,
+                status,
+            });
+        }
+        Ok(symbols)
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&SymbolInfo> {
+        self.symbols.get(symbol)
+    }
+
+    /// Every symbol, in alphabetical order.
+    pub fn symbols(&self) -> Vec<&SymbolInfo> {
+        let mut symbols: Vec<&SymbolInfo> = self.symbols.values().collect();
+        symbols.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        symbols
+    }
+
+    /// Checks the Symbol, Price and OrderQty of a new order, returning why it is refused.
+    pub fn validate(&self, msg_map: &IndexMap<String, String>) -> Result<(), String> {
+        let symbol = msg_map.get("Symbol").map(String::as_str).unwrap_or("");
+        let info = self
+            .symbols
+            .get(symbol)
+            .ok_or_else(|| format!("Unknown symbol {}", symbol))?;
+        if info.status == TradingStatus::Halted {
+            return Err(format!("Symbol {} is halted", symbol));
+        }
+        let multiple_of = |field: &str, size: Decimal| -> Result<(), String> {
+            let Some(value) = msg_map.get(field).filter(|value| !value.is_empty()) else {
+                return Ok(());
+            };
+            let value: Decimal = value
+                .parse()
+                .map_err(|_| format!("Invalid {} {}", field, value))?;
+            if !size.is_zero() && !(value % size).is_zero() {
+                return Err(format!(
+                    "{} {} is not a multiple of {} for {}",
+                    field, value, size, symbol
+                ));
+            }
+            Ok(())
+        };
+        multiple_of("Price", info.tick_size)?;
+        multiple_of("OrderQty", info.lot_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_orders_are_validated_against_the_symbol_master() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "symbol,tick_size,lot_size,status").unwrap();
+        writeln!(file, "IBM,0.01,100,trading").unwrap();
+        writeln!(file, "AAPL,0.05,1,halted").unwrap();
+        let master = SymbolMaster::load(file.path().to_str().unwrap()).unwrap();
+        assert_eq!(master.len(), 2);
+
+        let order = |symbol: &str, quantity: &str, price: &str| -> IndexMap<String, String> {
+            [("Symbol", symbol), ("OrderQty", quantity), ("Price", price)]
+                .iter()
+                .map(|(field, value)| (field.to_string(), value.to_string()))
+                .collect()
+        };
+        assert!(master.validate(&order("IBM", "200", "150.25")).is_ok());
+        assert!(master.validate(&order("IBM", "200", "")).is_ok());
+        assert!(master
+            .validate(&order("IBM", "200", "150.255"))
+            .unwrap_err()
+            .starts_with("Price 150.255"));
+        assert!(master
+            .validate(&order("IBM", "150", "150"))
+            .unwrap_err()
+            .starts_with("OrderQty 150"));
+        assert_eq!(
+            master.validate(&order("AAPL", "1", "187.5")),
+            Err("Symbol AAPL is halted".to_string())
+        );
+        assert_eq!(
+            master.validate(&order("MSFT", "1", "1")),
+            Err("Unknown symbol MSFT".to_string())
+        );
+
+        let json = r#"[{"symbol": "IBM", "tick_size": "0.01", "lot_size": "1",
+                        "status": "trading"}]"#;
+        let mut file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        write!(file, "{}", json).unwrap();
+        let master = SymbolMaster::load(file.path().to_str().unwrap()).unwrap();
+        assert!(master.validate(&order("IBM", "150", "150")).is_ok());
+    }
+}
@@ -0,0 +1,363 @@
+use std::borrow::Cow;
+use std::fmt::Write;
+
+const SOH: u8 = 0x01;
+
+/// Whether the byte ends a field, the engine writing messages with '|' for SOH to show them.
+pub fn is_delimiter(byte: u8) -> bool {
+    byte == SOH || byte == b'|'
+}
+
+/// The DATA fields with the Length field preceding them, which gives the size of their value.
+/// Their value may hold any byte, SOH included.
+pub const DATA_FIELDS: [(u32, u32); 14] = [
+    (89, 93),   // Signature, SignatureLength
+    (91, 90),   // SecureData, SecureDataLen
+    (96, 95),   // RawData, RawDataLength
+    (213, 212), // XmlData, XmlDataLength
+    (349, 348), // EncodedIssuer, EncodedIssuerLen
+    (351, 350), // EncodedSecurityDesc, EncodedSecurityDescLen
+    (353, 352), // EncodedListExecInst, EncodedListExecInstLen
+    (355, 354), // EncodedText, EncodedTextLen
+    (357, 356), // EncodedSubject, EncodedSubjectLen
+    (359, 358), // EncodedHeadline, EncodedHeadlineLen
+    (361, 360), // EncodedAllocText, EncodedAllocTextLen
+    (363, 362), // EncodedUnderlyingIssuer, EncodedUnderlyingIssuerLen
+    (365, 364), // EncodedUnderlyingSecurityDesc, EncodedUnderlyingSecurityDescLen
+    (446, 445), // EncodedListStatusText, EncodedListStatusTextLen
+];
+
+/// The CheckSum of the bytes, their sum modulo 256, summed in chunks with the `simd` feature.
+pub fn checksum(bytes: &[u8]) -> u8 {
+    if cfg!(feature = "simd") {
+        checksum_by_chunk(bytes)
+    } else {
+        checksum_by_byte(bytes)
+    }
+}
+
+fn checksum_by_byte(bytes: &[u8]) -> u8 {
+    (bytes
+        .iter()
+        .fold(0u32, |sum, &byte| sum.wrapping_add(byte as u32))
+        % 256) as u8
+}
+
+/// Adds each chunk to 32 byte lanes, which the compiler turns into vector additions, the lanes
+/// only summed at the end. Bytes wrap around as the sum only counts modulo 256.
+fn checksum_by_chunk(bytes: &[u8]) -> u8 {
+    let mut chunks = bytes.chunks_exact(32);
+    let lanes = chunks.by_ref().fold([0u8; 32], |mut lanes, chunk| {
+        for (lane, &byte) in lanes.iter_mut().zip(chunk) {
+            *lane = lane.wrapping_add(byte);
+        }
+        lanes
+    });
+    lanes
+        .iter()
+        .chain(chunks.remainder())
+        .fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// The tag number written with the digits, None for anything else or above u32::MAX. Parsed
+/// without branching on each digit with the `simd` feature.
+pub fn parse_tag(digits: &[u8]) -> Option<u32> {
+    if cfg!(feature = "simd") {
+        parse_tag_branchless(digits)
+    } else {
+        parse_tag_by_str(digits)
+    }
+}
+
+fn parse_tag_by_str(digits: &[u8]) -> Option<u32> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|tag| tag.parse::<u32>().ok())
+}
+
+/// Accumulates the digits in a u64, which ten of them cannot overflow, and whether any byte
+/// was not a digit, checked once at the end.
+fn parse_tag_branchless(digits: &[u8]) -> Option<u32> {
+    if digits.is_empty() || digits.len() > 10 {
+        return None;
+    }
+    let (tag, invalid) = digits.iter().fold((0u64, false), |(tag, invalid), &byte| {
+        let digit = byte.wrapping_sub(b'0');
+        (tag * 10 + digit as u64, invalid | (digit > 9))
+    });
+    if invalid {
+        return None;
+    }
+    u32::try_from(tag).ok()
+}
+
+/// The Length field of a DATA field.
+pub fn length_tag(data_tag: u32) -> Option<u32> {
+    DATA_FIELDS
+        .iter()
+        .find(|(tag, _)| *tag == data_tag)
+        .map(|(_, length_tag)| *length_tag)
+}
+
+/// Whether the tag is the Length field of a DATA field, which the encoder computes.
+pub fn is_length_tag(tag: &str) -> bool {
+    tag.parse::<u32>()
+        .is_ok_and(|tag| DATA_FIELDS.iter().any(|(_, length_tag)| *length_tag == tag))
+}
+
+/// The field as `tag=value`, a DATA field preceded by its Length field computed from the value,
+/// e.g. `95=7|96=token42`.
+pub fn encode_field(tag: &str, value: &str) -> String {
+    match tag.parse::<u32>().ok().and_then(length_tag) {
+        Some(length_tag) => format!("{}={}|{}={}", length_tag, value.len(), tag, value),
+        None => format!("{}={}", tag, value),
+    }
+}
+
+/// The fields of a raw message delimited by SOH or '|' as `(tag, value)`, borrowed from it,
+/// empty fields skipped. The value of a DATA field is as long as its Length field says,
+/// whatever bytes it holds.
+pub struct Fields<'a> {
+    message: &'a [u8],
+    position: usize,
+    /// The DATA field announced by the last Length field, and its length.
+    data_length: Option<(u32, usize)>,
+}
+
+pub fn fields(message: &[u8]) -> Fields<'_> {
+    Fields {
+        message,
+        position: 0,
+        data_length: None,
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u32, &'a [u8]), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self
+            .message
+            .get(self.position)
+            .is_some_and(|&byte| is_delimiter(byte))
+        {
+            self.position += 1;
+        }
+        let rest = &self.message[self.position..];
+        if rest.is_empty() {
+            return None;
+        }
+        let field = self.next_field(rest);
+        // A field which does not parse ends the iteration
+        self.position = match field {
+            Ok((_, consumed)) => self.position + consumed,
+            Err(_) => self.message.len(),
+        };
+        Some(field.map(|(field, _)| field))
+    }
+}
+
+impl<'a> Fields<'a> {
+    /// Where the next field starts in the message.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The field at the start of `rest` and the bytes it takes, its delimiter included.
+    fn next_field(&mut self, rest: &'a [u8]) -> Result<((u32, &'a [u8]), usize), String> {
+        let equals = rest
+            .iter()
+            .take_while(|&&byte| !is_delimiter(byte))
+            .position(|&byte| byte == b'=')
+            .ok_or_else(|| format!("Field without a tag at {}", self.position))?;
+        let tag = parse_tag(&rest[..equals])
+            .ok_or_else(|| format!("Invalid tag at {}", self.position))?;
+        let value_start = equals + 1;
+        let value_end = match self.data_length.take() {
+            Some((data_tag, length)) if data_tag == tag => {
+                let value_end = value_start + length;
+                if value_end > rest.len()
+                    || rest.get(value_end).is_some_and(|&byte| !is_delimiter(byte))
+                {
+                    return Err(format!("Tag {} is not {} bytes long", tag, length));
+                }
+                value_end
+            }
+            _ => rest[value_start..]
+                .iter()
+                .position(|&byte| is_delimiter(byte))
+                .map_or(rest.len(), |end| value_start + end),
+        };
+        let value = &rest[value_start..value_end];
+        if let Some((data_tag, _)) = DATA_FIELDS.iter().find(|(_, length)| *length == tag) {
+            let length = std::str::from_utf8(value)
+                .ok()
+                .and_then(|length| length.parse::<usize>().ok())
+                .ok_or_else(|| format!("Invalid length {} of tag {}", tag, data_tag))?;
+            self.data_length = Some((*data_tag, length));
+        }
+        Ok(((tag, value), (value_end + 1).min(rest.len())))
+    }
+}
+
+/// Whether the value of the field cannot be held as text delimited by SOH or '|'.
+fn is_binary(tag: u32, value: &[u8]) -> bool {
+    length_tag(tag).is_some()
+        && (value.iter().any(|&byte| is_delimiter(byte)) || std::str::from_utf8(value).is_err())
+}
+
+/// A raw message as text, borrowed unless a DATA field holds binary: its value is given in
+/// hexadecimal then, its Length field rewritten to the length of the hexadecimal so the text
+/// parses again. None when another field is not UTF-8.
+pub fn decode_message(message: &[u8]) -> Option<Cow<'_, str>> {
+    let binary = fields(message).any(|field| field.is_ok_and(|(tag, value)| is_binary(tag, value)));
+    if !binary {
+        return std::str::from_utf8(message).ok().map(Cow::Borrowed);
+    }
+    let fields = fields(message).collect::<Result<Vec<_>, _>>().ok()?;
+    let mut decoded = String::with_capacity(message.len() * 2);
+    for (index, &(tag, value)) in fields.iter().enumerate() {
+        write!(decoded, "{}=", tag).unwrap();
+        let data = fields
+            .get(index + 1)
+            .filter(|(data_tag, _)| length_tag(*data_tag) == Some(tag));
+        match data {
+            Some(&(data_tag, data)) if is_binary(data_tag, data) => {
+                write!(decoded, "{}", data.len() * 2).unwrap()
+            }
+            _ if is_binary(tag, value) => {
+                for byte in value {
+                    write!(decoded, "{:02X}", byte).unwrap();
+                }
+            }
+            _ => decoded.push_str(std::str::from_utf8(value).ok()?),
+        }
+        decoded.push('\x01');
+    }
+    Some(Cow::Owned(decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_fields_are_sliced_by_length() {
+        let message = b"8=FIX.4.2\x0135=A\x0195=5\x0196=a\x01|\xffb\x0198=0\x0110=123\x01";
+        let parsed: Vec<(u32, &[u8])> = fields(message).map(Result::unwrap).collect();
+        assert_eq!(
+            parsed,
+            vec![
+                (8, &b"FIX.4.2"[..]),
+                (35, b"A"),
+                (95, b"5"),
+                (96, b"a\x01|\xffb"),
+                (98, b"0"),
+                (10, b"123"),
+            ]
+        );
+        assert_eq!(
+            decode_message(message).unwrap(),
+            "8=FIX.4.2\x0135=A\x0195=10\x0196=61017CFF62\x0198=0\x0110=123\x01"
+        );
+        // The decoded text parses again, RawData as long as its hexadecimal
+        let decoded = decode_message(message).unwrap();
+        assert!(fields(decoded.as_bytes())
+            .any(|field| field == Ok((96, &b"61017CFF62"[..]))));
+
+        // Text is borrowed, RawData included
+        let text = "8=FIX.4.2\x0135=A\x0195=3\x0196=abc\x0110=123\x01";
+        assert!(
+            matches!(decode_message(text.as_bytes()), Some(Cow::Borrowed(borrowed))
+            if borrowed == text)
+        );
+        assert!(fields(b"95=9\x0196=abc\x01").any(|field| field.is_err()));
+        assert!(fields(b"35=A\x01x=1\x01").any(|field| field.is_err()));
+        assert_eq!(decode_message(b"35=A\x0158=\xff\x01"), None);
+    }
+
+    #[test]
+    fn test_simd_variants_match_the_per_byte_loops() {
+        // Every remainder of a chunk, past a lane wrapping around
+        let message: Vec<u8> = (0..3000u32).map(|index| (index * 7 + 250) as u8).collect();
+        for end in (0..40).chain([1023, 1024, 3000]) {
+            assert_eq!(
+                checksum_by_chunk(&message[..end]),
+                checksum_by_byte(&message[..end])
+            );
+        }
+        assert_eq!(checksum(b"8=FIX.4.2\x019=5\x0135=0\x01"), 161);
+
+        for digits in
+            "0,8,35,035,12345678,4294967295,4294967296,99999999999,,3a,3/,:,-1,35 ".split(',')
+        {
+            assert_eq!(
+                parse_tag_branchless(digits.as_bytes()),
+                parse_tag_by_str(digits.as_bytes()),
+                "{}",
+                digits
+            );
+        }
+        // A sign is no part of a tag
+        assert_eq!(parse_tag_branchless(b"+1"), None);
+    }
+
+    /// Times both implementations, e.g.
+    /// `cargo test --release bench_checksum_and_parse_tag -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_checksum_and_parse_tag() {
+        use std::hint::black_box;
+        use std::time::Instant;
+
+        const ROUNDS: u32 = 1_000_000;
+        let message = "8=FIX.4.2\x019=178\x0135=8\x0149=VENUE\x0156=FIX_Engine\x0134=12\x01\
+            52=20240501-12:00:00.000\x0137=O1\x0111=C1\x0117=E1\x01150=2\x0139=2\x0155=IBM\x01\
+            54=1\x0138=100\x0132=100\x0131=140.25\x01151=0\x0114=100\x016=140.25\x01";
+        let time = |name: &str, run: &dyn Fn() -> u64| {
+            let started = Instant::now();
+            let total = (0..ROUNDS).fold(0u64, |total, _| total.wrapping_add(black_box(run())));
+            println!(
+                "{}: {:?} a message ({})",
+                name,
+                started.elapsed() / ROUNDS,
+                total
+            );
+        };
+
+        let batch = message.repeat(20);
+        for (name, bytes) in [("message", message.as_bytes()), ("batch", batch.as_bytes())] {
+            time(&format!("{} checksum by byte", name), &|| {
+                checksum_by_byte(black_box(bytes)) as u64
+            });
+            time(&format!("{} checksum by chunk", name), &|| {
+                checksum_by_chunk(black_box(bytes)) as u64
+            });
+        }
+
+        let tags: Vec<&[u8]> = message
+            .split('\x01')
+            .filter_map(|field| field.split_once('='))
+            .map(|(tag, _)| tag.as_bytes())
+            .collect();
+        let tags = &tags;
+        let parse = |parse_tag: fn(&[u8]) -> Option<u32>| {
+            move || {
+                tags.iter()
+                    .map(|tag| parse_tag(black_box(tag)).unwrap() as u64)
+                    .sum()
+            }
+        };
+        time("tags by str", &parse(parse_tag_by_str));
+        time("tags branchless", &parse(parse_tag_branchless));
+    }
+
+    #[test]
+    fn test_encode_field() {
+        assert_eq!(encode_field("96", "token42"), "95=7|96=token42");
+        assert_eq!(encode_field("213", "<a/>"), "212=4|213=<a/>");
+        assert_eq!(encode_field("58", "text"), "58=text");
+        assert!(is_length_tag("95"));
+        assert!(!is_length_tag("96"));
+    }
+}
@@ -0,0 +1,298 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use indexmap::IndexMap;
+use log::{error, info, warn};
+use uuid::Uuid;
+
+use crate::config::{load_config, update_enrichment};
+use crate::enrichment::set_field;
+use crate::message_converter::read_json_file;
+use crate::sim_clock::venue_timestamp;
+use crate::MessageMap;
+
+/// How often the watched files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Predefined messages by name, swapped as a whole when their file is reloaded. A message
+/// being built keeps the templates it started with.
+#[derive(Default)]
+pub struct Templates {
+    templates: RwLock<Arc<HashMap<String, IndexMap<String, String>>>>,
+    /// The CompIDs and SubIDs of the session, set on every template whatever its file says.
+    session_fields: Vec<(String, String)>,
+}
+
+impl Templates {
+    pub fn new(
+        templates: HashMap<String, IndexMap<String, String>>,
+        session_fields: Vec<(String, String)>,
+    ) -> Self {
+        let reloadable = Self {
+            templates: RwLock::default(),
+            session_fields,
+        };
+        reloadable.replace(templates);
+        reloadable
+    }
+
+    pub fn current(&self) -> Arc<HashMap<String, IndexMap<String, String>>> {
+        Arc::clone(&self.templates.read().unwrap())
+    }
+
+    pub fn replace(&self, mut templates: HashMap<String, IndexMap<String, String>>) {
+        for template in templates.values_mut() {
+            for (name, value) in &self.session_fields {
+                set_field(template, name, value);
+            }
+        }
+        *self.templates.write().unwrap() = Arc::new(templates);
+    }
+}
+
+impl Clone for Templates {
+    fn clone(&self) -> Self {
+        Self {
+            templates: RwLock::new(self.current()),
+            session_fields: self.session_fields.clone(),
+        }
+    }
+}
+
+/// Expands the placeholders of a template value when the message is sent: `${uuid}` (a random
+/// UUID), `${now}` (the sending time), `${seq}` (its MsgSeqNum) and `${env:NAME}` (the
+/// environment variable NAME). An unknown placeholder is left as it is.
+pub fn expand_placeholders(value: &str, msg_seq_num: u64) -> Cow<'_, str> {
+    if !value.contains("${") {
+        return Cow::Borrowed(value);
+    }
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(length) = rest[start..].find('}') else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+        let placeholder = &rest[start..start + length + 1];
+        match &placeholder[2..length] {
+            "uuid" => expanded.push_str(&Uuid::new_v4().to_string()),
+            "now" => expanded.push_str(&venue_timestamp()),
+            "seq" => expanded.push_str(&msg_seq_num.to_string()),
+            name => match name.strip_prefix("env:").map(env::var) {
+                Some(Ok(variable)) => expanded.push_str(&variable),
+                _ => {
+                    warn!("Placeholder {} not expanded", placeholder);
+                    expanded.push_str(placeholder);
+                }
+            },
+        }
+        rest = &rest[start + length + 1..];
+    }
+    expanded.push_str(rest);
+    Cow::Owned(expanded)
+}
+
+/// A file watched for changes.
+struct Watched {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl Watched {
+    fn new(path: PathBuf) -> Self {
+        let modified = modified(&path);
+        Self { path, modified }
+    }
+
+    /// Whether the file changed since last checked.
+    fn changed(&mut self) -> bool {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        modified.is_some()
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Swaps in the admin and application templates of the file. A file which does not parse
+/// leaves the templates as they were.
+fn reload_templates(path: &Path, message_maps: &MessageMap) {
+    match read_json_file(path.to_str().unwrap_or_default()) {
+        Ok((_, admin_msg, app_msg)) => {
+            message_maps.admin_msg.replace(admin_msg);
+            message_maps.app_msg.replace(app_msg);
+            info!("Reloaded the predefined messages of {}", path.display());
+        }
+        Err(e) => error!("Predefined messages {} not reloaded: {}", path.display(), e),
+    }
+}
+
+/// Watches the predefined messages of the sessions and the configuration file, reloading the
+/// templates and the `[enrichment]` section when they change, without a restart.
+pub fn start_hot_reload(config_file: PathBuf, sessions: Vec<(PathBuf, Arc<MessageMap>)>) {
+    let mut config_file = Watched::new(config_file);
+    let mut sessions: Vec<(Watched, Arc<MessageMap>)> = sessions
+        .into_iter()
+        .map(|(path, message_maps)| (Watched::new(path), message_maps))
+        .collect();
+    info!(
+        ">>>>>> Reloading {} and the predefined messages on change",
+        config_file.path.display()
+    );
+    thread::spawn(move || loop {
+        thread::sleep(RELOAD_INTERVAL);
+        for (predefined_msg, message_maps) in sessions.iter_mut() {
+            if predefined_msg.changed() {
+                reload_templates(&predefined_msg.path, message_maps);
+            }
+        }
+        if config_file.changed() {
+            match load_config(&config_file.path)
+                .and_then(|config_map| update_enrichment(&config_map))
+            {
+                Ok(()) => info!("Reloaded the enrichment of {}", config_file.path.display()),
+                Err(e) => error!("Enrichment not reloaded: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_reload_templates() {
+        let mut file = NamedTempFile::new().unwrap();
+        let header = r#""header": {"BeginString": "FIX.4.2", "MsgType": "Logon"}"#;
+        write!(
+            file,
+            r#"{{{}, "admin": {{}}, "app": {{"News": {{"Headline": "A"}}}}}}"#,
+            header
+        )
+        .unwrap();
+        let path = file.path().to_path_buf();
+        let mut watched = Watched::new(path.clone());
+        assert!(!watched.changed());
+
+        let message_maps = MessageMap {
+            fix_header: Default::default(),
+            fix_tag_number_map: Default::default(),
+            admin_msg_list: Default::default(),
+            admin_msg: Default::default(),
+            app_msg: Templates::new(
+                HashMap::new(),
+                vec![("TargetCompID".to_string(), "VENUE".to_string())],
+            ),
+            fix_tag_name_map: Default::default(),
+            msgnumber_fields_map: Default::default(),
+            valid_msg_types: Default::default(),
+            required_fields: Default::default(),
+        };
+        let before = message_maps.app_msg.current();
+        reload_templates(&path, &message_maps);
+        let news = &message_maps.app_msg.current()["News"];
+        assert_eq!(news["Headline"], "A");
+        // The CompIDs of the session replace those of the file
+        assert_eq!(news["TargetCompID"], "VENUE");
+        assert!(before.is_empty());
+
+        // A broken file keeps the templates
+        fs::write(&path, "{").unwrap();
+        reload_templates(&path, &message_maps);
+        assert_eq!(message_maps.app_msg.current()["News"]["Headline"], "A");
+    }
+
+    #[test]
+    fn test_session_comp_ids_override_the_templates() {
+        let config_map = HashMap::from([(
+            "session".to_string(),
+            HashMap::from([
+                ("sender_comp_id".to_string(), "CLIENT7".to_string()),
+                ("target_comp_id".to_string(), "VENUE".to_string()),
+                ("sender_sub_id".to_string(), "DESK2".to_string()),
+            ]),
+        )]);
+        let template = |sender: &str| -> IndexMap<String, String> {
+            [
+                ("BeginString", "FIX.4.2"),
+                ("MsgType", "D"),
+                ("SenderCompID", sender),
+                ("TargetCompID", "TEMPLATE_TARGET"),
+                ("Symbol", "IBM"),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+        };
+        let templates = Templates::new(
+            HashMap::from([("NEW_ORDER_SINGLE".to_string(), template("TEMPLATE_SENDER"))]),
+            crate::config::get_session_identity(&config_map),
+        );
+        let fields = |templates: &Templates| -> Vec<(String, String)> {
+            templates.current()["NEW_ORDER_SINGLE"]
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect()
+        };
+        let expected: Vec<(String, String)> = [
+            ("BeginString", "FIX.4.2"),
+            ("MsgType", "D"),
+            ("SenderCompID", "CLIENT7"),
+            ("TargetCompID", "VENUE"),
+            // A SubID the template lacks ends the header
+            ("SenderSubID", "DESK2"),
+            ("Symbol", "IBM"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        assert_eq!(fields(&templates), expected);
+
+        // Reloaded templates are overridden too
+        templates.replace(HashMap::from([(
+            "NEW_ORDER_SINGLE".to_string(),
+            template("RELOADED_SENDER"),
+        )]));
+        assert_eq!(fields(&templates), expected);
+    }
+
+    #[test]
+    fn test_expand_placeholders() {
+        env::set_var("TEST_PLACEHOLDER_ACCOUNT", "ACC1");
+        assert_eq!(expand_placeholders("ACC", 7), "ACC");
+        assert_eq!(
+            expand_placeholders("${env:TEST_PLACEHOLDER_ACCOUNT}-${seq}", 7),
+            "ACC1-7"
+        );
+        assert_eq!(
+            expand_placeholders("${env:TEST_PLACEHOLDER_MISSING}${other}${", 7),
+            "${env:TEST_PLACEHOLDER_MISSING}${other}${"
+        );
+        let id = expand_placeholders("ORD-${uuid}", 7);
+        assert!(Uuid::parse_str(id.strip_prefix("ORD-").unwrap()).is_ok());
+        assert_ne!(
+            expand_placeholders("${uuid}", 7),
+            expand_placeholders("${uuid}", 7)
+        );
+        assert_eq!(
+            expand_placeholders("${now}", 7).len(),
+            venue_timestamp().len()
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use indexmap::IndexMap;
+
+use crate::MessageMap;
+
+/// A parsed message as the handlers get it, field names to values.
+pub fn msg_map(fields: &[(&str, &str)]) -> IndexMap<String, String> {
+    fields
+        .iter()
+        .map(|(field, value)| (field.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Empty templates and dictionary, for tests not encoding or naming fields.
+pub fn message_maps() -> Arc<MessageMap> {
+    Arc::new(MessageMap {
+        fix_header: Default::default(),
+        fix_tag_number_map: Default::default(),
+        admin_msg_list: Default::default(),
+        admin_msg: Default::default(),
+        app_msg: Default::default(),
+        fix_tag_name_map: Default::default(),
+        msgnumber_fields_map: Default::default(),
+        valid_msg_types: Default::default(),
+        required_fields: Default::default(),
+    })
+}
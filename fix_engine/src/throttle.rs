@@ -0,0 +1,116 @@
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    /// Cap on the application messages a session sends per second, None for no cap.
+    pub static ref THROTTLE: RwLock<Option<ThrottleConfig>> = RwLock::new(None);
+}
+
+/// What happens to an application message sent above the rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleAction {
+    /// Held back, before it takes its MsgSeqNum, until the rate allows it.
+    Delay,
+    /// Refused and not sent, a business message received meanwhile is answered with a
+    /// BusinessMessageReject.
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThrottleConfig {
+    pub messages_per_second: u32,
+    /// Messages which may be sent at once after a quiet period.
+    pub burst: u32,
+    pub action: ThrottleAction,
+}
+
+/// Token bucket refilled at `rate` tokens a second, holding at most `burst` tokens.
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(config: &ThrottleConfig) -> Self {
+        let burst = config.burst.max(1) as f64;
+        Self {
+            rate: config.messages_per_second.max(1) as f64,
+            burst,
+            tokens: burst,
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Takes a token, or returns how long until one is available.
+    pub fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.try_take_many(1, now)
+    }
+
+    /// Whether a token is available, without taking it.
+    pub fn has_token(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= 1.0
+    }
+
+    /// Takes `count` tokens, or none and returns how long until they are available. More
+    /// tokens than the burst are never available at once.
+    pub fn try_take_many(&mut self, count: usize, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+        let count = count as f64;
+        if self.tokens >= count {
+            self.tokens -= count;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((count - self.tokens) / self.rate))
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.refilled = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(&ThrottleConfig {
+            messages_per_second: 10,
+            burst: 2,
+            action: ThrottleAction::Delay,
+        });
+        let start = Instant::now();
+        assert!(bucket.try_take(start).is_ok());
+        assert!(bucket.try_take(start).is_ok());
+        let wait = bucket.try_take(start).unwrap_err();
+        assert!(wait > Duration::from_millis(99) && wait <= Duration::from_millis(100));
+
+        // One token comes back every 100ms, never more than the burst
+        assert!(bucket.try_take(start + Duration::from_millis(100)).is_ok());
+        assert!(bucket.try_take(start + Duration::from_millis(100)).is_err());
+        assert!(bucket.try_take(start + Duration::from_secs(10)).is_ok());
+        assert!(bucket.try_take(start + Duration::from_secs(10)).is_ok());
+        assert!(bucket.try_take(start + Duration::from_secs(10)).is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_takes_all_or_nothing() {
+        let mut bucket = TokenBucket::new(&ThrottleConfig {
+            messages_per_second: 10,
+            burst: 3,
+            action: ThrottleAction::Reject,
+        });
+        let start = Instant::now();
+        assert!(bucket.try_take_many(4, start).is_err());
+        assert!(bucket.try_take_many(2, start).is_ok());
+        assert!(bucket.try_take_many(2, start).is_err());
+        assert!(bucket.try_take(start).is_ok());
+    }
+}
@@ -0,0 +1,121 @@
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+
+lazy_static! {
+    pub static ref TIMERS: TimerScheduler = TimerScheduler::start();
+}
+
+thread_local! {
+    static ON_TIMER_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether the caller is a timer task. Tasks must never block, every session's timers wait
+/// for them.
+pub fn on_timer_thread() -> bool {
+    ON_TIMER_THREAD.with(Cell::get)
+}
+
+/// A scheduled callback. It returns its next deadline, or None once it is done.
+type TimerTask = Box<dyn FnMut() -> Option<Instant> + Send>;
+
+#[derive(Default)]
+struct TimerQueue {
+    deadlines: BinaryHeap<Reverse<(Instant, u64)>>,
+    tasks: HashMap<u64, TimerTask>,
+    next_id: u64,
+}
+
+/// Runs the timers of every session (heartbeats, QoS reports, ...) on one thread which
+/// sleeps until the earliest deadline, instead of one polling thread per connection.
+pub struct TimerScheduler {
+    queue: Arc<(Mutex<TimerQueue>, Condvar)>,
+}
+
+impl TimerScheduler {
+    pub fn start() -> Self {
+        let queue = Arc::new((Mutex::new(TimerQueue::default()), Condvar::new()));
+        let queue_clone = Arc::clone(&queue);
+        thread::spawn(move || {
+            ON_TIMER_THREAD.with(|on_timer_thread| on_timer_thread.set(true));
+            run_timers(&queue_clone)
+        });
+        Self { queue }
+    }
+
+    /// Runs the task at the deadline, then again at each deadline it returns.
+    pub fn schedule<F>(&self, deadline: Instant, task: F)
+    where
+        F: FnMut() -> Option<Instant> + Send + 'static,
+    {
+        let (lock, condvar) = &*self.queue;
+        let mut queue = lock.lock().unwrap();
+        let id = queue.next_id;
+        queue.next_id += 1;
+        queue.tasks.insert(id, Box::new(task));
+        queue.deadlines.push(Reverse((deadline, id)));
+        condvar.notify_one();
+    }
+}
+
+fn run_timers(queue: &(Mutex<TimerQueue>, Condvar)) {
+    let (lock, condvar) = queue;
+    let mut timers = lock.lock().unwrap();
+    loop {
+        let Some(&Reverse((deadline, id))) = timers.deadlines.peek() else {
+            timers = condvar.wait(timers).unwrap();
+            continue;
+        };
+        let now = Instant::now();
+        if deadline > now {
+            timers = condvar.wait_timeout(timers, deadline - now).unwrap().0;
+            continue;
+        }
+
+        timers.deadlines.pop();
+        let Some(mut task) = timers.tasks.remove(&id) else {
+            continue;
+        };
+        // New timers can still be scheduled while the task runs
+        drop(timers);
+        let next_deadline = task();
+        timers = lock.lock().unwrap();
+        if let Some(next_deadline) = next_deadline {
+            timers.tasks.insert(id, task);
+            timers.deadlines.push(Reverse((next_deadline, id)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_tasks_fire_in_deadline_order() {
+        let timers = TimerScheduler::start();
+        let (sender, receiver) = mpsc::channel();
+        let start = Instant::now();
+
+        let slow_sender = sender.clone();
+        timers.schedule(start + Duration::from_millis(40), move || {
+            slow_sender.send("slow").unwrap();
+            None
+        });
+        let mut remaining = 2;
+        timers.schedule(start + Duration::from_millis(10), move || {
+            sender.send("fast").unwrap();
+            remaining -= 1;
+            (remaining > 0).then(|| Instant::now() + Duration::from_millis(10))
+        });
+
+        let fired: Vec<&str> = receiver.iter().take(3).collect();
+        assert_eq!(fired, vec!["fast", "fast", "slow"]);
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
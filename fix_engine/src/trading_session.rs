@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::RwLock;
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+use log::{error, info};
+use serde::Serialize;
+
+use crate::admin_http::{AdminSession, ADMIN_SESSIONS};
+use crate::message_converter::msgtype2fixmsg;
+use crate::message_handling::send_sequenced;
+use crate::schedule::{is_session_closed, SESSION_SCHEDULE};
+
+lazy_static! {
+    /// State of the trading session the acceptor publishes to its counterparties.
+    pub static ref TRADING_SESSION: TradingSession = TradingSession::default();
+}
+
+/// TradingSessionID(336) of the one trading session the acceptor runs.
+pub const TRADING_SESSION_ID: &str = "DAY";
+
+/// TradSesStatus(340).
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TradSesStatus {
+    Halted,
+    Open,
+    Closed,
+    PreOpen,
+    PreClose,
+}
+
+impl TradSesStatus {
+    /// TradSesStatus as the dictionary description, in any case, or the raw value.
+    pub fn parse(status: &str) -> Option<TradSesStatus> {
+        match status.to_uppercase().as_str() {
+            "HALTED" | "1" => Some(TradSesStatus::Halted),
+            "OPEN" | "2" => Some(TradSesStatus::Open),
+            "CLOSED" | "3" => Some(TradSesStatus::Closed),
+            "PRE_OPEN" | "4" => Some(TradSesStatus::PreOpen),
+            "PRE_CLOSE" | "5" => Some(TradSesStatus::PreClose),
+            _ => None,
+        }
+    }
+
+    pub fn value(self) -> &'static str {
+        match self {
+            TradSesStatus::Halted => "1",
+            TradSesStatus::Open => "2",
+            TradSesStatus::Closed => "3",
+            TradSesStatus::PreOpen => "4",
+            TradSesStatus::PreClose => "5",
+        }
+    }
+}
+
+/// TradSesStatus of the trading session and the Text it was published with. New orders are
+/// only accepted while it is open.
+pub struct TradingSession {
+    state: RwLock<(TradSesStatus, Option<String>)>,
+}
+
+impl Default for TradingSession {
+    fn default() -> Self {
+        Self {
+            state: RwLock::new((TradSesStatus::Open, None)),
+        }
+    }
+}
+
+impl TradingSession {
+    pub fn status(&self) -> (TradSesStatus, Option<String>) {
+        self.state.read().unwrap().clone()
+    }
+
+    fn set(&self, status: TradSesStatus, text: Option<&str>) {
+        *self.state.write().unwrap() = (status, text.map(str::to_string));
+    }
+}
+
+/// Override fields of the TradingSessionStatus template.
+pub fn trading_session_status_fields(
+    status: TradSesStatus,
+    text: Option<&str>,
+) -> HashMap<String, String> {
+    let mut fields = HashMap::from([
+        (
+            "TradingSessionID".to_string(),
+            TRADING_SESSION_ID.to_string(),
+        ),
+        ("TradSesStatus".to_string(), status.value().to_string()),
+    ]);
+    if let Some(text) = text {
+        fields.insert("Text".to_string(), text.to_string());
+    }
+    fields
+}
+
+fn send_trading_session_status(
+    session: &AdminSession,
+    status: TradSesStatus,
+    text: Option<&str>,
+) -> io::Result<()> {
+    let message = msgtype2fixmsg(
+        "Trading_Session_Status".to_string(),
+        &session.message_maps.app_msg.current(),
+        &session.message_maps.fix_tag_name_map,
+        Some(&trading_session_status_fields(status, text)),
+        session.seq_store.get_outgoing(),
+    );
+    send_sequenced(&session.stream, &session.seq_store, message.replace("|", "\x01"))
+}
+
+/// Moves the trading session to the status and sends a TradingSessionStatus to every
+/// running session. Returns the number of sessions it was sent to.
+pub fn publish_trading_session_status(status: TradSesStatus, text: Option<&str>) -> usize {
+    TRADING_SESSION.set(status, text);
+    info!(
+        "Trading session {} {:?}: {}",
+        TRADING_SESSION_ID,
+        status,
+        text.unwrap_or("-")
+    );
+    let mut sent = 0;
+    for session in ADMIN_SESSIONS.all() {
+        match send_trading_session_status(&session, status, text) {
+            Ok(()) => sent += 1,
+            Err(e) => error!(
+                "Failed to send the TradingSessionStatus to {}: {}",
+                session.session_id, e
+            ),
+        }
+    }
+    sent
+}
+
+/// Starts a thread which publishes the trading session open or closed as the session
+/// schedule opens and closes it. Does nothing when no session schedule is configured.
+pub fn start_trading_session_publisher() {
+    if SESSION_SCHEDULE.read().unwrap().is_none() {
+        return;
+    }
+    let status = |closed: bool| {
+        if closed {
+            TradSesStatus::Closed
+        } else {
+            TradSesStatus::Open
+        }
+    };
+    let mut closed = is_session_closed();
+    TRADING_SESSION.set(status(closed), None);
+    thread::spawn(move || loop {
+        sleep(Duration::from_secs(1));
+        if is_session_closed() == closed {
+            continue;
+        }
+        closed = !closed;
+        publish_trading_session_status(status(closed), None);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trading_session_status() {
+        for status in [
+            TradSesStatus::Halted,
+            TradSesStatus::Open,
+            TradSesStatus::Closed,
+            TradSesStatus::PreOpen,
+            TradSesStatus::PreClose,
+        ] {
+            assert_eq!(TradSesStatus::parse(status.value()), Some(status));
+        }
+        assert_eq!(
+            TradSesStatus::parse("pre_open"),
+            Some(TradSesStatus::PreOpen)
+        );
+        assert_eq!(TradSesStatus::parse("HALT"), None);
+
+        let trading_session = TradingSession::default();
+        assert_eq!(trading_session.status(), (TradSesStatus::Open, None));
+        trading_session.set(TradSesStatus::Halted, Some("Volatility"));
+        assert_eq!(
+            trading_session.status(),
+            (TradSesStatus::Halted, Some("Volatility".to_string()))
+        );
+        let fields = trading_session_status_fields(TradSesStatus::Halted, Some("Volatility"));
+        assert_eq!(fields["TradSesStatus"], "1");
+        assert_eq!(fields["TradingSessionID"], TRADING_SESSION_ID);
+    }
+}
@@ -0,0 +1,233 @@
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::Arc;
+
+use crate::buffer_pool::BUFFER_SIZE;
+use crate::outbound::OutboundQueue;
+use crate::tag_value::{fields, is_delimiter};
+
+/// Largest message read, what grows beyond it without becoming a message is dropped.
+pub const MAX_MESSAGE_LEN: usize = 64 * BUFFER_SIZE;
+
+/// A bidirectional byte stream carrying a FIX session. TCP is the only production transport,
+/// TLS or Unix domain sockets plug in by implementing it and tests use in-memory pipes.
+pub trait Transport: Read + Write + Send {
+    /// Another handle on the same connection, for the threads reading, writing and timing it.
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>>;
+
+    /// Closes both directions, which also ends a blocked read on any other handle.
+    fn close(&self) -> io::Result<()>;
+
+    /// The remote end, for logging.
+    fn peer(&self) -> String;
+
+    /// The queue of the writer thread the messages go through, None when they are written
+    /// straight to the connection. Senders have it admit their application messages before
+    /// they take their MsgSeqNum, without holding the transport, so admin messages are not
+    /// held up behind them and a refused message leaves no gap.
+    fn outbound_queue(&self) -> Option<Arc<OutboundQueue>> {
+        None
+    }
+
+    /// The message as the interceptors of the session let it out, None when one drops it.
+    /// Senders ask before the message takes its MsgSeqNum, so a dropped one leaves no gap.
+    fn intercept_outbound<'a>(&self, message: &'a str) -> Option<Cow<'a, str>> {
+        Some(Cow::Borrowed(message))
+    }
+}
+
+impl Transport for TcpStream {
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+
+    fn peer(&self) -> String {
+        self.peer_addr()
+            .map(|address| address.to_string())
+            .unwrap_or_else(|_| "unknown".to_string())
+    }
+}
+
+/// Splits what the engine writes at once, one or more whole messages, at each CheckSum. The
+/// value of a DATA field is skipped by its Length, whatever SOH or CheckSum it holds. What
+/// follows the last CheckSum, or a field which does not parse, is left as the last message.
+pub fn split_messages(buf: &[u8]) -> Vec<&[u8]> {
+    let mut messages = Vec::new();
+    let mut start = 0;
+    let mut fields = fields(buf);
+    while let Some(Ok((tag, _))) = fields.next() {
+        if tag == 10 {
+            messages.push(&buf[start..fields.position()]);
+            start = fields.position();
+        }
+    }
+    if start < buf.len() {
+        messages.push(&buf[start..]);
+    }
+    messages
+}
+
+/// The length of the first message of what has been read, up to and including the delimiter
+/// of its CheckSum. A message which does not parse, or has no CheckSum, ends where the next
+/// one begins. None while the message has not all arrived.
+pub fn message_length(buf: &[u8]) -> Option<usize> {
+    let mut fields = fields(buf);
+    while let Some(Ok((tag, _))) = fields.next() {
+        if tag == 10 && is_delimiter(buf[fields.position() - 1]) {
+            return Some(fields.position());
+        }
+    }
+    (1..buf.len())
+        .find(|&start| is_delimiter(buf[start - 1]) && buf[start..].starts_with(b"8=FIX"))
+}
+
+#[cfg(test)]
+pub use memory::MemoryTransport;
+
+#[cfg(test)]
+mod memory {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Condvar, Mutex};
+
+    #[derive(Default)]
+    struct Pipe {
+        buffer: Mutex<(VecDeque<u8>, bool)>,
+        readable: Condvar,
+    }
+
+    impl Pipe {
+        fn close(&self) {
+            self.buffer.lock().unwrap().1 = true;
+            self.readable.notify_all();
+        }
+    }
+
+    /// One end of an in-memory duplex pipe.
+    #[derive(Clone)]
+    pub struct MemoryTransport {
+        incoming: Arc<Pipe>,
+        outgoing: Arc<Pipe>,
+    }
+
+    impl MemoryTransport {
+        /// Both ends of a connected pipe.
+        pub fn pair() -> (Self, Self) {
+            let (first, second) = (Arc::new(Pipe::default()), Arc::new(Pipe::default()));
+            (
+                Self {
+                    incoming: Arc::clone(&first),
+                    outgoing: Arc::clone(&second),
+                },
+                Self {
+                    incoming: second,
+                    outgoing: first,
+                },
+            )
+        }
+    }
+
+    impl Read for MemoryTransport {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut buffer = self.incoming.buffer.lock().unwrap();
+            while buffer.0.is_empty() && !buffer.1 {
+                buffer = self.incoming.readable.wait(buffer).unwrap();
+            }
+            let length = buf.len().min(buffer.0.len());
+            for (target, byte) in buf.iter_mut().zip(buffer.0.drain(..length)) {
+                *target = byte;
+            }
+            Ok(length)
+        }
+    }
+
+    impl Write for MemoryTransport {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut buffer = self.outgoing.buffer.lock().unwrap();
+            if buffer.1 {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+            buffer.0.extend(buf);
+            self.outgoing.readable.notify_all();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for MemoryTransport {
+        fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+            Ok(Box::new(self.clone()))
+        }
+
+        fn close(&self) -> io::Result<()> {
+            self.incoming.close();
+            self.outgoing.close();
+            Ok(())
+        }
+
+        fn peer(&self) -> String {
+            "memory".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_handling::send_message;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_memory_transport() {
+        let (local, mut remote) = MemoryTransport::pair();
+        let stream: Arc<Mutex<Box<dyn Transport>>> = Arc::new(Mutex::new(Box::new(local)));
+        send_message(&stream, "8=FIX.4.2\x0135=0\x01".to_string()).unwrap();
+
+        let mut buffer = [0u8; 64];
+        let length = remote.read(&mut buffer).unwrap();
+        assert_eq!(&buffer[..length], b"8=FIX.4.2\x0135=0\x01");
+
+        // Closing one handle ends the reads of every other
+        let reader = remote.try_clone_transport().unwrap();
+        stream.lock().unwrap().close().unwrap();
+        assert_eq!(remote.read(&mut buffer).unwrap(), 0);
+        assert_eq!(reader.peer(), "memory");
+    }
+
+    #[test]
+    fn test_split_messages_skips_data_fields() {
+        let logon = b"8=FIX.4.2\x0135=A\x0195=8\x0196=a\x0110=1\x01b\x0110=001\x01";
+        let heartbeat = b"8=FIX.4.2\x0135=0\x0110=002\x01";
+        let buf = [&logon[..], heartbeat, b"8=FIX"].concat();
+        assert_eq!(
+            split_messages(&buf),
+            vec![&logon[..], &heartbeat[..], &b"8=FIX"[..]]
+        );
+    }
+
+    #[test]
+    fn test_message_length() {
+        let logon = b"8=FIX.4.2\x0135=A\x0195=8\x0196=a\x0110=1\x01b\x0110=001\x01";
+        let heartbeat = b"8=FIX.4.2\x0135=0\x0110=002\x01";
+        let buf = [&logon[..], heartbeat].concat();
+        assert_eq!(message_length(&buf), Some(logon.len()));
+        assert_eq!(message_length(heartbeat), Some(heartbeat.len()));
+
+        // Not all arrived yet
+        assert_eq!(message_length(&heartbeat[..heartbeat.len() - 1]), None);
+        assert_eq!(message_length(&logon[..20]), None);
+
+        // A message without a CheckSum, or garbled, ends at the next BeginString
+        let garbled = b"8=FIX.4.2\x0135=0\x01x\x01";
+        assert_eq!(message_length(&[&garbled[..], heartbeat].concat()), Some(garbled.len()));
+        assert_eq!(message_length(garbled), None);
+    }
+}
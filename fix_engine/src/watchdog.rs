@@ -0,0 +1,202 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::{error, info};
+
+use crate::sequence::SequenceNumberStore;
+use crate::transport::Transport;
+use crate::{AtomicDateTime, WATCHDOG_TIMEOUT};
+
+/// Last-activity timestamps of the reader and heartbeat threads of one session.
+/// The writer is considered active whenever the outgoing sequence number moves.
+pub struct SessionActivity {
+    started: Instant,
+    /// When the last message was read, or the session started.
+    last_received_time: AtomicDateTime,
+    last_tick_millis: AtomicU64,
+    /// A TestRequest went out since the last message was read.
+    test_request_pending: AtomicBool,
+    closed: AtomicBool,
+}
+
+impl SessionActivity {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            last_received_time: AtomicDateTime::new(Utc::now()),
+            last_tick_millis: AtomicU64::new(0),
+            test_request_pending: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn touch_reader(&self) {
+        self.last_received_time.store(Utc::now(), Ordering::SeqCst);
+        self.test_request_pending.store(false, Ordering::SeqCst);
+    }
+
+    pub fn last_received_time(&self) -> DateTime<Utc> {
+        self.last_received_time.load(Ordering::SeqCst)
+    }
+
+    /// Records a TestRequest sent for want of messages, until the next one is read.
+    pub fn test_request_sent(&self) {
+        self.test_request_pending.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_test_request_pending(&self) -> bool {
+        self.test_request_pending.load(Ordering::SeqCst)
+    }
+
+    pub fn touch_heartbeat(&self) {
+        self.last_tick_millis
+            .store(self.elapsed_millis(), Ordering::SeqCst);
+    }
+
+    /// Marks the session as ended so the watchdog stops monitoring it.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    /// Time since the last message was read, or since the session started.
+    pub fn reader_idle(&self) -> Duration {
+        self.last_received_time.elapsed()
+    }
+
+    /// Time since the last heartbeat tick. The timer thread may store a tick after the clock
+    /// is read here, so the tick can be ahead of it.
+    fn heartbeat_idle(&self) -> Duration {
+        let last_tick_millis = self.last_tick_millis.load(Ordering::SeqCst);
+        Duration::from_millis(self.elapsed_millis().saturating_sub(last_tick_millis))
+    }
+
+    fn elapsed_millis(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+}
+
+impl Default for SessionActivity {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the name of the first component idle for longer than the timeout, if any.
+fn stalled_component(
+    activity: &SessionActivity,
+    writer_idle: Duration,
+    timeout: Duration,
+) -> Option<&'static str> {
+    if activity.reader_idle() > timeout {
+        Some("reader")
+    } else if writer_idle > timeout {
+        Some("writer")
+    } else if activity.heartbeat_idle() > timeout {
+        Some("heartbeat")
+    } else {
+        None
+    }
+}
+
+/// Starts a thread which tears the session down, logging what every component was doing,
+/// once the reader, writer or heartbeat makes no progress for WATCHDOG_TIMEOUT seconds.
+/// Does nothing when the timeout is 0.
+pub fn start_watchdog(
+    stream: Box<dyn Transport>,
+    activity: Arc<SessionActivity>,
+    seq_store: Arc<SequenceNumberStore>,
+) {
+    let timeout = Duration::from_secs(WATCHDOG_TIMEOUT.load(Ordering::SeqCst));
+    if timeout.is_zero() {
+        return;
+    }
+
+    thread::spawn(move || {
+        let mut last_outgoing_seq = seq_store.get_outgoing();
+        let mut last_write = Instant::now();
+        while !activity.is_closed() {
+            sleep(Duration::from_secs(1));
+
+            let outgoing_seq = seq_store.get_outgoing();
+            if outgoing_seq != last_outgoing_seq {
+                last_outgoing_seq = outgoing_seq;
+                last_write = Instant::now();
+            }
+
+            if let Some(component) = stalled_component(&activity, last_write.elapsed(), timeout) {
+                error!(
+                    "Watchdog: {} made no progress for {} seconds, tearing down the session \
+                     (reader idle {}s, writer idle {}s, heartbeat idle {}s, seq in {}, seq out {})",
+                    component,
+                    timeout.as_secs(),
+                    activity.reader_idle().as_secs(),
+                    last_write.elapsed().as_secs(),
+                    activity.heartbeat_idle().as_secs(),
+                    seq_store.get_incoming(),
+                    outgoing_seq
+                );
+                if let Err(e) = stream.close() {
+                    error!("Watchdog failed to close the connection: {}", e);
+                }
+                return;
+            }
+        }
+        info!("Watchdog stopped, session closed");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stalled_component() {
+        let activity = SessionActivity::new();
+        let timeout = Duration::from_millis(50);
+        assert_eq!(stalled_component(&activity, Duration::ZERO, timeout), None);
+
+        sleep(Duration::from_millis(60));
+        activity.touch_reader();
+        assert_eq!(
+            stalled_component(&activity, Duration::ZERO, timeout),
+            Some("heartbeat")
+        );
+
+        activity.touch_heartbeat();
+        assert_eq!(
+            stalled_component(&activity, Duration::from_millis(60), timeout),
+            Some("writer")
+        );
+        assert_eq!(stalled_component(&activity, Duration::ZERO, timeout), None);
+    }
+
+    #[test]
+    fn test_test_request_pending_until_a_message_is_read() {
+        let activity = SessionActivity::new();
+        let started = activity.last_received_time();
+        activity.test_request_sent();
+        assert!(activity.is_test_request_pending());
+
+        sleep(Duration::from_millis(5));
+        activity.touch_reader();
+        assert!(!activity.is_test_request_pending());
+        assert!(activity.last_received_time() > started);
+        assert!(activity.reader_idle() < Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_heartbeat_tick_ahead_of_the_clock() {
+        let activity = SessionActivity::new();
+        activity
+            .last_tick_millis
+            .store(activity.elapsed_millis() + 1_000, Ordering::SeqCst);
+        assert_eq!(activity.heartbeat_idle(), Duration::ZERO);
+    }
+}
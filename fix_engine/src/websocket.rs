@@ -0,0 +1,265 @@
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use log::{error, info};
+use serde_json::json;
+use tungstenite::Message;
+
+use crate::engine_events::{EngineEvent, SessionState, ENGINE_EVENTS};
+use crate::execution_store::ExecDirection;
+use crate::message_validator::FixMessage;
+use crate::sequence::SessionId;
+use crate::session_events::{SessionHooks, SESSION_HOOKS};
+use crate::MessageMap;
+
+/// Fans the messages, order and session events out as JSON to every connected WebSocket
+/// client, each written by its own thread so a slow client never blocks the session.
+pub struct EventStream {
+    clients: Mutex<Vec<Sender<String>>>,
+    /// Names the fields of the messages streamed.
+    message_maps: Arc<MessageMap>,
+}
+
+impl EventStream {
+    pub fn new(message_maps: Arc<MessageMap>) -> Self {
+        Self {
+            clients: Mutex::new(Vec::new()),
+            message_maps,
+        }
+    }
+
+    /// The events from now on, until the receiver is dropped.
+    pub fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        self.clients.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn is_watched(&self) -> bool {
+        !self.clients.lock().unwrap().is_empty()
+    }
+
+    /// Sends the event to every client, forgetting those disconnected.
+    pub fn broadcast(&self, event: String) {
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|client| client.send(event.clone()).is_ok());
+    }
+
+    /// Streams an event of the engine, if anybody watches: the messages with their fields
+    /// named by the dictionary, the order events as `{"type": "order", "event": {...}}` and
+    /// the session transitions as `{"type": "session", "session": "FIX.4.2:A->B", "event":
+    /// "logon", ...}`.
+    pub fn stream(&self, event: EngineEvent) {
+        if !self.is_watched() {
+            return;
+        }
+        match event {
+            EngineEvent::MessageReceived(message) => {
+                self.message(ExecDirection::Received, &message)
+            }
+            EngineEvent::MessageSent(message) => self.message(ExecDirection::Sent, &message),
+            EngineEvent::OrderUpdated(event) => {
+                self.broadcast(json!({"type": "order", "event": event}).to_string())
+            }
+            EngineEvent::SessionStateChanged(session_id, state) => self.session_event(
+                &session_id,
+                match state {
+                    SessionState::LoggedOn => json!({"event": "logon"}),
+                    SessionState::LoggedOut { text } => json!({"event": "logout", "text": text}),
+                    SessionState::Disconnected => json!({"event": "disconnect"}),
+                },
+            ),
+        }
+    }
+
+    fn message(&self, direction: ExecDirection, message: &FixMessage) {
+        let fields = message.to_json(&self.message_maps.fix_tag_number_map);
+        self.broadcast(message_event(direction, &fields));
+    }
+
+    fn session_event(&self, session_id: &SessionId, event: serde_json::Value) {
+        let mut event = event;
+        event["type"] = "session".into();
+        event["session"] = session_id.to_string().into();
+        self.broadcast(event.to_string());
+    }
+}
+
+/// `{"type": "message", "direction": "sent"|"received", "fields": {...}}`, the fields as
+/// `FixMessage::to_json` gives them.
+pub fn message_event(direction: ExecDirection, fields: &str) -> String {
+    let direction = match direction {
+        ExecDirection::Sent => "sent",
+        ExecDirection::Received => "received",
+    };
+    format!(
+        r#"{{"type":"message","direction":"{}","fields":{}}}"#,
+        direction, fields
+    )
+}
+
+/// Streams the session events the engine events leave out, e.g.
+/// `{"type": "session", "event": "resend", ...}`.
+pub struct WebSocketHooks(Arc<EventStream>);
+
+impl SessionHooks for WebSocketHooks {
+    fn name(&self) -> &str {
+        "websocket"
+    }
+
+    fn on_resend(&self, session_id: &SessionId, begin_seq_no: u64, end_seq_no: u64) {
+        self.0.session_event(
+            session_id,
+            json!({
+                "event": "resend",
+                "begin_seq_no": begin_seq_no,
+                "end_seq_no": end_seq_no,
+            }),
+        );
+    }
+
+    fn on_sequence_reset(&self, session_id: &SessionId, new_seq_no: u64, gap_fill: bool) {
+        self.0.session_event(
+            session_id,
+            json!({
+                "event": "sequence_reset",
+                "new_seq_no": new_seq_no,
+                "gap_fill": gap_fill,
+            }),
+        );
+    }
+
+    fn on_unacknowledged_order(&self, session_id: &SessionId, cl_ord_id: &str, msg_type: &str) {
+        self.0.session_event(
+            session_id,
+            json!({
+                "event": "unacknowledged_order",
+                "cl_ord_id": cl_ord_id,
+                "msg_type": msg_type,
+            }),
+        );
+    }
+
+    fn on_unknown_execution(&self, session_id: &SessionId, cl_ord_id: &str, exec_id: &str) {
+        self.0.session_event(
+            session_id,
+            json!({
+                "event": "unknown_execution",
+                "cl_ord_id": cl_ord_id,
+                "exec_id": exec_id,
+            }),
+        );
+    }
+
+    fn on_trading_session_status(
+        &self,
+        session_id: &SessionId,
+        trading_session_id: &str,
+        status: &str,
+        text: Option<&str>,
+    ) {
+        self.0.session_event(
+            session_id,
+            json!({
+                "event": "trading_session_status",
+                "trading_session_id": trading_session_id,
+                "status": status,
+                "text": text,
+            }),
+        );
+    }
+}
+
+/// Serves the event stream to WebSocket clients on the address, e.g. a web UI watching the
+/// engine live.
+pub fn start_websocket_server(
+    address: SocketAddr,
+    message_maps: Arc<MessageMap>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    info!(
+        "WebSocket event stream listening on {}",
+        listener.local_addr()?
+    );
+    let events = Arc::new(EventStream::new(message_maps));
+    SESSION_HOOKS.register(Box::new(WebSocketHooks(Arc::clone(&events))));
+    let engine_events = ENGINE_EVENTS.subscribe();
+    let streamed = Arc::clone(&events);
+    thread::spawn(move || {
+        for event in engine_events {
+            streamed.stream(event);
+        }
+    });
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let receiver = events.subscribe();
+                    thread::spawn(move || stream_events(stream, receiver));
+                }
+                Err(e) => error!("WebSocket connection failed: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Writes the events to the client until it disconnects.
+fn stream_events(stream: TcpStream, receiver: Receiver<String>) {
+    let peer = stream.peer_addr().ok();
+    let mut websocket = match tungstenite::accept(stream) {
+        Ok(websocket) => websocket,
+        Err(e) => {
+            error!("WebSocket handshake with {:?} failed: {}", peer, e);
+            return;
+        }
+    };
+    info!("WebSocket client {:?} watching", peer);
+    for event in receiver {
+        if let Err(e) = websocket.send(Message::Text(event)) {
+            info!("WebSocket client {:?} gone: {}", peer, e);
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::message_maps;
+
+    #[test]
+    fn test_events_are_streamed_to_every_client_as_json() {
+        let events = Arc::new(EventStream::new(message_maps()));
+        assert!(!events.is_watched());
+        let first = events.subscribe();
+        let second = events.subscribe();
+        drop(second);
+
+        // Tags missing from the dictionary keep their number
+        let message = FixMessage::parse("8=FIX.4.2|35=D|11=1|").unwrap();
+        events.stream(EngineEvent::MessageSent(message));
+        let session_id = SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "BUYSIDE".to_string(),
+            target_comp_id: "SELLSIDE".to_string(),
+        };
+        WebSocketHooks(Arc::clone(&events)).on_sequence_reset(&session_id, 7, true);
+
+        assert_eq!(
+            first.recv().unwrap(),
+            r#"{"type":"message","direction":"sent","fields":{"8":"FIX.4.2","35":"D","11":"1"}}"#
+        );
+        let reset: serde_json::Value = serde_json::from_str(&first.recv().unwrap()).unwrap();
+        assert_eq!(reset["type"], "session");
+        assert_eq!(reset["session"], "FIX.4.2:BUYSIDE->SELLSIDE");
+        assert_eq!(reset["event"], "sequence_reset");
+        assert_eq!(reset["new_seq_no"], 7);
+        assert_eq!(events.clients.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,235 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use chrono::{NaiveDate, Utc};
+use log::error;
+
+use crate::logging::Direction;
+use crate::masking::mask_bytes;
+use crate::sequence::SessionId;
+use crate::transport::{message_length, Transport, MAX_MESSAGE_LEN};
+
+lazy_static! {
+    /// Directory of the per-session wire logs, None when they are not written.
+    pub static ref WIRE_LOG_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+}
+
+/// Append-only log of the raw messages of one session, separate from the application log:
+/// `<timestamp> <IN|OUT> <raw message>` per line. A new file,
+/// `<directory>/<session>.<YYYYMMDD>.log`, is started every UTC day.
+pub struct WireLog {
+    directory: PathBuf,
+    session: String,
+    file: Mutex<Option<(NaiveDate, File)>>,
+}
+
+impl WireLog {
+    pub fn new(directory: &Path, session_id: &SessionId) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            session: session_id.file_stem(),
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn file_path(&self, date: NaiveDate) -> PathBuf {
+        self.directory
+            .join(format!("{}.{}.log", self.session, date.format("%Y%m%d")))
+    }
+
+    pub fn record(&self, direction: Direction, message: &[u8]) -> io::Result<()> {
+        let now = Utc::now();
+        let today = now.date_naive();
+        let mut file = self.file.lock().unwrap();
+        if !matches!(&*file, Some((date, _)) if *date == today) {
+            fs::create_dir_all(&self.directory)?;
+            let opened = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(self.file_path(today))?;
+            *file = Some((today, opened));
+        }
+        let (_, file) = file.as_mut().unwrap();
+        let direction = match direction {
+            Direction::Inbound => "IN",
+            Direction::Outbound => "OUT",
+        };
+        let mut line = format!("{} {} ", now.format("%Y%m%d-%H:%M:%S%.6f"), direction).into_bytes();
+        let message = mask_bytes(message);
+        line.extend_from_slice(message.strip_suffix(b"\n").unwrap_or(&message));
+        line.push(b'\n');
+        file.write_all(&line)
+    }
+
+    fn record_or_log(&self, direction: Direction, message: &[u8]) {
+        if let Err(e) = self.record(direction, message) {
+            error!("Failed to write the wire log: {}", e);
+        }
+    }
+
+    /// Adds the bytes to what has been seen of the messages not recorded yet, and records
+    /// each message they complete, one line a message however the bytes were read or written.
+    fn record_framed(&self, direction: Direction, unframed: &mut Vec<u8>, bytes: &[u8]) {
+        unframed.extend_from_slice(bytes);
+        loop {
+            let length = match message_length(unframed) {
+                Some(length) => length,
+                // Bytes which never become a message are recorded as they are
+                None if unframed.len() > MAX_MESSAGE_LEN => unframed.len(),
+                None => break,
+            };
+            self.record_or_log(direction, &unframed[..length]);
+            unframed.drain(..length);
+        }
+    }
+}
+
+/// Wraps the connection of a session so every message read from and written to it is
+/// recorded in the session's wire log, when a wire log directory is configured.
+pub fn with_wire_log(stream: Box<dyn Transport>, session_id: &SessionId) -> Box<dyn Transport> {
+    match WIRE_LOG_DIR.read().unwrap().as_deref() {
+        Some(directory) => Box::new(WireLoggedTransport {
+            inner: stream,
+            log: Arc::new(WireLog::new(directory, session_id)),
+            read: Vec::new(),
+            written: Vec::new(),
+        }),
+        None => stream,
+    }
+}
+
+/// A transport recording its traffic, framed into messages the way the read loop frames
+/// them: a read may carry several messages or part of one, a write a whole batch.
+struct WireLoggedTransport {
+    inner: Box<dyn Transport>,
+    log: Arc<WireLog>,
+    /// What has been read of the messages not recorded yet.
+    read: Vec<u8>,
+    /// What has been written of the messages not recorded yet.
+    written: Vec<u8>,
+}
+
+impl Read for WireLoggedTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let length = self.inner.read(buf)?;
+        self.log
+            .record_framed(Direction::Inbound, &mut self.read, &buf[..length]);
+        Ok(length)
+    }
+}
+
+impl Write for WireLoggedTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let length = self.inner.write(buf)?;
+        self.log
+            .record_framed(Direction::Outbound, &mut self.written, &buf[..length]);
+        Ok(length)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Transport for WireLoggedTransport {
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(Self {
+            inner: self.inner.try_clone_transport()?,
+            log: Arc::clone(&self.log),
+            read: Vec::new(),
+            written: Vec::new(),
+        }))
+    }
+
+    fn close(&self) -> io::Result<()> {
+        self.inner.close()
+    }
+
+    fn peer(&self) -> String {
+        self.inner.peer()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::MemoryTransport;
+
+    #[test]
+    fn test_wire_logged_transport() {
+        let dir = tempfile::tempdir().unwrap();
+        let session_id = SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "FIX_Engine".to_string(),
+            target_comp_id: "XYZ/Exchange".to_string(),
+        };
+        let log = Arc::new(WireLog::new(dir.path(), &session_id));
+        let (local, mut remote) = MemoryTransport::pair();
+        let mut transport = WireLoggedTransport {
+            inner: Box::new(local),
+            log: Arc::clone(&log),
+            read: Vec::new(),
+            written: Vec::new(),
+        };
+        let logon = "8=FIX.4.2\x019=10\x0135=A\x0134=1\x0110=000\x01";
+        let heartbeat = "8=FIX.4.2\x019=10\x0135=0\x0134=2\x0110=000\x01";
+
+        // A batch is recorded a message a line, and so are messages read at once or in parts
+        transport
+            .write_all(format!("{}{}", logon, heartbeat).as_bytes())
+            .unwrap();
+        let (start, end) = heartbeat.split_at(12);
+        remote
+            .write_all(format!("{}{}", logon, start).as_bytes())
+            .unwrap();
+        let mut buf = [0u8; 64];
+        assert_eq!(transport.read(&mut buf).unwrap(), logon.len() + start.len());
+        remote.write_all(end.as_bytes()).unwrap();
+        assert_eq!(transport.read(&mut buf).unwrap(), end.len());
+
+        let path = log.file_path(Utc::now().date_naive());
+        assert!(path
+            .to_string_lossy()
+            .contains("FIX.4.2_FIX_Engine_XYZ_Exchange."));
+        let content = fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].ends_with(&format!(" OUT {}", logon)));
+        assert!(lines[1].ends_with(&format!(" OUT {}", heartbeat)));
+        assert!(lines[2].ends_with(&format!(" IN {}", logon)));
+        assert!(lines[3].ends_with(&format!(" IN {}", heartbeat)));
+    }
+
+    #[test]
+    fn test_masks_fields_split_across_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let session_id = SessionId {
+            begin_string: "FIX.4.2".to_string(),
+            sender_comp_id: "FIX_Engine".to_string(),
+            target_comp_id: "XYZ".to_string(),
+        };
+        let log = Arc::new(WireLog::new(dir.path(), &session_id));
+        let (local, mut remote) = MemoryTransport::pair();
+        let mut transport = WireLoggedTransport {
+            inner: Box::new(local),
+            log: Arc::clone(&log),
+            read: Vec::new(),
+            written: Vec::new(),
+        };
+        let logon = "8=FIX.4.2\x019=21\x0135=A\x01554=secret\x0196=ab\x0110=000\x01";
+
+        // Password and RawData arrive in parts, each part read on its own
+        let mut buf = [0u8; 64];
+        for part in [&logon[..26], &logon[26..35], &logon[35..]] {
+            remote.write_all(part.as_bytes()).unwrap();
+            assert_eq!(transport.read(&mut buf).unwrap(), part.len());
+        }
+
+        let content = fs::read_to_string(log.file_path(Utc::now().date_naive())).unwrap();
+        assert_eq!(content.lines().count(), 1);
+        assert!(content.contains("\x01554=****\x0196=****\x01"));
+        assert!(!content.contains("cret"));
+    }
+}